@@ -1,16 +1,26 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
 
 pub use config::DiagnosticConfig;
 
 use config::{
-    ExcludeRegex, Mode, ProjectOptions, PythonVersion, Settings, TypeCheckerFlags,
-    UntypedFunctionReturnMode,
+    ExcludeRegex, FollowImports, Mode, ProjectOptions, PythonVersion, ReportKind, Settings,
+    TypeCheckerFlags, UntypedFunctionReturnMode,
 };
 use vfs::{AbsPath, SimpleLocalFS, VfsHandler};
 
 use clap::Parser;
 
-#[derive(Parser, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    JsonLines,
+    Sarif,
+    Github,
+}
+
+#[derive(Parser, Clone, Default, Debug)]
 pub struct Cli {
     // Additional options that are not present in zmypy
     /// Choosing a mode sets the basic preset of flags. The default mode is typed, which is not
@@ -24,6 +34,72 @@ pub struct Cli {
     #[arg(long)]
     pub untyped_function_return_mode: Option<UntypedFunctionReturnMode>,
 
+    /// Do not report the diagnostics that are recorded in this baseline file
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<String>,
+
+    /// Record all current diagnostics in a baseline file instead of reporting them
+    #[arg(long, value_name = "PATH")]
+    pub write_baseline: Option<PathBuf>,
+
+    /// How diagnostics are printed. json prints a single document and json-lines a diagnostic per
+    /// line, both follow a stable schema. sarif prints a SARIF 2.1.0 log and github prints
+    /// workflow commands for annotations. Defaults to github in GitHub Actions and to text
+    /// otherwise.
+    #[arg(long, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+
+    /// Stop printing errors after N errors. The summary still counts all errors
+    #[arg(long, value_name = "N")]
+    error_limit: Option<usize>,
+
+    /// Exit with a failure if the percentage of expressions with precise (non-Any) types is below
+    /// the given percentage
+    #[arg(long, value_name = "PERCENT")]
+    pub fail_under_coverage: Option<f64>,
+
+    /// Only exit with a failure if there are more than N errors. Together with a baseline, this
+    /// allows a number of new errors.
+    #[arg(long, value_name = "N")]
+    pub max_new_errors: Option<usize>,
+
+    /// Write the parse, name binding and check durations of every module to the given file
+    #[arg(long, value_name = "FILE")]
+    pub timing_stats: Option<PathBuf>,
+
+    /// Print a summary of the durations, cache hit rates and memory usage to stderr
+    #[arg(long)]
+    pub verbose_timing: bool,
+
+    /// The number of threads that check files in parallel. Every thread loads typeshed and the
+    /// imported modules again, which needs more memory. Defaults to 1
+    #[arg(long, short = 'j', value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// The path of the file that is read from stdin when `-` is passed as a file, which is checked
+    /// in the context of the project without being written to disk
+    #[arg(long, value_name = "PATH")]
+    pub stdin_filename: Option<String>,
+
+    /// Keep running and recheck the files that are affected by changes. After the first check,
+    /// only added and resolved diagnostics are printed.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Clear the screen before every check in watch mode
+    #[arg(long, requires = "watch")]
+    pub clear_screen: bool,
+
+    /// Do not fall back to the user-level Mypy configs ($XDG_CONFIG_HOME/mypy/config,
+    /// ~/.config/mypy/config and ~/.mypy.ini) if the project has no config
+    #[arg(long)]
+    pub no_user_config: bool,
+
+    /// Set a config option like `--set disallow_untyped_defs=true`, may be repeated. These
+    /// options are applied after the config file and all other flags and therefore win.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub set: Vec<(String, String)>,
+
     #[command(flatten)]
     pub mypy_options: MypyCli,
 }
@@ -33,6 +109,20 @@ impl Cli {
         Self {
             mode: Some(Mode::Mypy),
             untyped_function_return_mode: None,
+            baseline: None,
+            write_baseline: None,
+            output: None,
+            error_limit: None,
+            fail_under_coverage: None,
+            max_new_errors: None,
+            timing_stats: None,
+            verbose_timing: false,
+            jobs: None,
+            stdin_filename: None,
+            watch: false,
+            clear_screen: false,
+            no_user_config: false,
+            set: vec![],
             mypy_options,
         }
     }
@@ -83,11 +173,18 @@ pub struct MypyCli {
     /// Typecheck modules without stubs or py.typed marker
     #[arg(long)]
     follow_untyped_imports: bool,
+    /// How to treat imports of modules that are not checked
+    #[arg(long)]
+    follow_imports: Option<FollowImports>,
+    /// Apply --follow-imports to stub files as well
+    #[arg(long)]
+    follow_imports_for_stubs: bool,
 
     // Platform configuration
-    /// Type check code assuming it will be running on Python x.y
+    /// Type check code assuming it will be running on Python x.y (may be repeated to check
+    /// against multiple versions)
     #[arg(long)]
-    python_version: Option<PythonVersion>,
+    python_version: Vec<PythonVersion>,
     /// Specifies the path for a python executable (for example a virtual env)
     #[arg(long)]
     python_executable: Option<String>,
@@ -238,6 +335,9 @@ pub struct MypyCli {
     extra_checks: bool,
     #[arg(long)]
     no_extra_checks: bool,
+    /// Report importing or using deprecated features as notes instead of errors
+    #[arg(long)]
+    report_deprecated_as_note: bool,
     /// Strict mode; enables the following flags: --warn-unused-configs, --disallow-any-generics,
     /// --disallow-subclassing-any, --disallow-untyped-calls, --disallow-untyped-defs,
     /// --disallow-incomplete-defs, --check-untyped-defs, --disallow-untyped-decorators,
@@ -268,6 +368,9 @@ pub struct MypyCli {
     show_error_codes: bool,
     #[arg(long)]
     hide_error_codes: bool,
+    /// Show links to error code documentation
+    #[arg(long)]
+    show_error_code_links: bool,
     // --show-absolute-path Show absolute paths to files (inverse: --hide-absolute-path)
     /// Use visually nicer output in error messages: Use soft word wrap, show source code snippets,
     /// and show error location markers (inverse: --no-pretty)
@@ -280,6 +383,39 @@ pub struct MypyCli {
     error_summary: bool,
     #[arg(long)]
     no_error_summary: bool,
+
+    // Incremental mode:
+    /// Cache the results of modules and reuse them for unchanged modules in the next run
+    #[arg(long)]
+    incremental: bool,
+    /// Store the results of modules in the given directory if --incremental is used, defaults to
+    /// `.zuban_cache` in the project directory. `/dev/null` disables the cache
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<String>,
+
+    // Report generation:
+    /// Write a JUnit XML test result document with the type checking results to the given file
+    #[arg(long, value_name = "PATH")]
+    junit_xml: Option<String>,
+    /// Write an HTML report with the type precision of every line to the given directory
+    #[arg(long, value_name = "DIR")]
+    html_report: Option<String>,
+    /// Write a text file with a type precision summary of every module to the given directory
+    #[arg(long, value_name = "DIR")]
+    txt_report: Option<String>,
+    /// Write a text file with per-module line precision statistics to the given directory
+    #[arg(long, value_name = "DIR")]
+    lineprecision_report: Option<String>,
+    /// Write a text file with the number of expressions of type Any per module to the given
+    /// directory
+    #[arg(long, value_name = "DIR")]
+    any_exprs_report: Option<String>,
+
+    // Advanced options:
+    /// When encountering SOURCE_FILE, read and type check the contents of SHADOW_FILE instead.
+    /// May be specified more than once
+    #[arg(long, num_args = 2, value_names = ["SOURCE_FILE", "SHADOW_FILE"])]
+    pub shadow_file: Vec<String>,
     #[arg(long, hide = true)]
     explicit_package_bases: bool,
     #[arg(long, hide = true)]
@@ -300,22 +436,37 @@ pub fn apply_flags(
     project_options: &mut ProjectOptions,
     diagnostic_config: &mut DiagnosticConfig,
     current_dir: Arc<AbsPath>,
-    cli: Cli,
+    mut cli: Cli,
     project_dir: Arc<AbsPath>,
     config_path: Option<&AbsPath>,
-) {
+) -> anyhow::Result<()> {
+    let set = std::mem::take(&mut cli.set);
     apply_flags_detailed(
         vfs_handler,
         &mut project_options.settings,
         &mut project_options.flags,
         diagnostic_config,
-        current_dir,
+        current_dir.clone(),
         cli,
         project_dir,
         config_path,
+    );
+    project_options.apply_cli_settings(
+        vfs_handler,
+        &current_dir,
+        diagnostic_config,
+        set.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
     )
 }
 
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let Some((key, value)) = s.split_once('=') else {
+        return Err(format!("Expected KEY=VALUE, found {s:?}"));
+    };
+    Ok((key.trim().replace('-', "_"), value.trim().to_string()))
+}
+
 pub fn apply_flags_detailed(
     vfs_handler: &SimpleLocalFS,
     settings: &mut Settings,
@@ -332,6 +483,20 @@ pub fn apply_flags_detailed(
     if let Some(untyped_function_return_mode) = cli.untyped_function_return_mode {
         settings.untyped_function_return_mode = untyped_function_return_mode
     }
+    if let Some(baseline) = &cli.baseline {
+        settings.baseline =
+            Some(vfs_handler.normalize_rc_path(vfs_handler.absolute_path(&current_dir, baseline)));
+    }
+    if cli.write_baseline.is_some() {
+        // The new baseline should contain all diagnostics.
+        settings.baseline = None;
+    }
+    if let Some(error_limit) = cli.error_limit {
+        diagnostic_config.error_limit = Some(error_limit);
+    }
+    if let Some(jobs) = cli.jobs {
+        settings.jobs = Some(jobs);
+    }
 
     apply_mypy_flags(
         vfs_handler,
@@ -384,6 +549,12 @@ fn apply_mypy_flags(
     if cli.follow_untyped_imports {
         flags.follow_untyped_imports = true;
     }
+    if let Some(follow_imports) = cli.follow_imports {
+        flags.follow_imports = follow_imports;
+    }
+    if cli.follow_imports_for_stubs {
+        flags.follow_imports_for_stubs = true;
+    }
     apply!(flags, disallow_untyped_defs, allow_untyped_defs);
     apply!(flags, disallow_untyped_calls, allow_untyped_calls);
     apply!(flags, disallow_untyped_decorators, allow_untyped_decorators);
@@ -401,6 +572,9 @@ fn apply_mypy_flags(
     apply!(flags, warn_no_return, no_warn_no_return);
     apply!(flags, no_implicit_reexport, implicit_reexport);
     apply!(flags, extra_checks, no_extra_checks);
+    if cli.report_deprecated_as_note {
+        flags.report_deprecated_as_note = true;
+    }
     apply!(
         flags,
         allow_incomplete_generics,
@@ -412,6 +586,9 @@ fn apply_mypy_flags(
     apply!(diagnostic_config, show_error_end, hide_error_end);
     apply!(diagnostic_config, show_error_codes, hide_error_codes);
     apply!(diagnostic_config, pretty, no_pretty);
+    if cli.show_error_code_links {
+        diagnostic_config.show_error_code_links = true;
+    }
     apply!(diagnostic_config, error_summary, no_error_summary);
     apply!(settings, exclude_gitignore, no_exclude_gitignore);
     apply!(settings, explicit_package_bases, no_explicit_package_bases);
@@ -427,8 +604,41 @@ fn apply_mypy_flags(
     if cli.platform.is_some() {
         settings.platform = cli.platform;
     }
-    if let Some(python_version) = cli.python_version {
-        settings.python_version = Some(python_version);
+    match cli.python_version.as_slice() {
+        [] => (),
+        [python_version] => {
+            settings.python_version = Some(*python_version);
+            settings.python_versions.clear();
+        }
+        _ => settings.python_versions = cli.python_version,
+    }
+    if cli.incremental {
+        settings.incremental = true;
+    }
+    match cli.cache_dir.as_deref() {
+        Some("/dev/null") => settings.incremental = false,
+        Some(cache_dir) => {
+            settings.cache_dir = Some(
+                vfs_handler.normalize_rc_path(vfs_handler.absolute_path(&current_dir, cache_dir)),
+            )
+        }
+        None => (),
+    }
+    if let Some(junit_xml) = &cli.junit_xml {
+        settings.junit_xml =
+            Some(vfs_handler.normalize_rc_path(vfs_handler.absolute_path(&current_dir, junit_xml)));
+    }
+    settings.collect_suppressed_diagnostics = cli.output == Some(OutputFormat::Sarif);
+    for (kind, dir) in [
+        (ReportKind::Html, &cli.html_report),
+        (ReportKind::Txt, &cli.txt_report),
+        (ReportKind::LinePrecision, &cli.lineprecision_report),
+        (ReportKind::AnyExprs, &cli.any_exprs_report),
+    ] {
+        if let Some(dir) = dir {
+            let dir = vfs_handler.normalize_rc_path(vfs_handler.absolute_path(&current_dir, dir));
+            settings.set_report_dir(kind, dir)
+        }
     }
     if let Some(p) = cli.python_executable {
         settings
@@ -442,6 +652,7 @@ fn apply_mypy_flags(
         settings
             .set_files_or_directories_to_check(vfs_handler, &current_dir, config_path, cli.files)
             .expect("Need a valid glob path as a files argument");
+        settings.files_passed_on_command_line = true;
     }
     tracing::info!(
         "Checking the following files: {:?}",
@@ -455,6 +666,7 @@ fn apply_mypy_flags(
     if cli.ignore_excludes_from_config {
         // This is for testing, so we can test all files
         flags.excludes.clear();
+        flags.exclude_patterns = Default::default();
     }
     for r in cli.exclude {
         flags
@@ -1,16 +1,20 @@
+mod pyright;
 mod searcher;
 mod venv;
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, num::NonZeroUsize, sync::Arc};
 
 use anyhow::{anyhow, bail};
 use clap::ValueEnum as _;
 use ini::{Ini, ParseOption};
 use regex::Regex;
 use toml_edit::{DocumentMut, Item, Table, Value};
-use vfs::{AbsPath, Directory, GlobAbsPath, LocalFS, NormalizedPath, VfsHandler};
+use vfs::{
+    AbsPath, Directory, GitignorePatterns, GlobAbsPath, LocalFS, NormalizedPath, VfsHandler,
+    WatcherKind,
+};
 
-pub use searcher::{find_cli_config, find_workspace_config};
+pub use searcher::{find_cli_config, find_workspace_config, user_config_paths};
 
 type ConfigResult = anyhow::Result<()>;
 
@@ -21,12 +25,40 @@ const OPTIONS_STARTING_WITH_ALLOW: [&str; 4] = [
     "allow_empty_bodies",
 ];
 
+/// Profiles that can be used with `profile = "..."` in `[tool.zuban]`, they expand to a set of
+/// flags. Additional profiles can be defined in `[tool.zuban.profiles.<name>]`.
+const BUILTIN_PROFILES: [&str; 3] = ["strict", "standard", "lenient"];
+
+#[derive(Debug, Clone)]
 pub struct DiagnosticConfig {
     pub show_error_codes: bool,
     pub show_error_end: bool,
     pub show_column_numbers: bool,
     pub pretty: bool,
     pub error_summary: bool,
+    // Adds a note with a link to the documentation of error codes.
+    pub show_error_code_links: bool,
+    // Errors after this many errors are not printed, but still counted in the summary.
+    pub error_limit: Option<usize>,
+    // The width that `--pretty` wraps messages to, usually the width of the terminal.
+    pub terminal_width: usize,
+}
+
+impl DiagnosticConfig {
+    pub fn with_overrides(&self, overrides: &DiagnosticConfigOverrides) -> Self {
+        Self {
+            show_error_codes: overrides.show_error_codes.unwrap_or(self.show_error_codes),
+            show_error_end: overrides.show_error_end.unwrap_or(self.show_error_end),
+            show_column_numbers: overrides
+                .show_column_numbers
+                .unwrap_or(self.show_column_numbers),
+            pretty: overrides.pretty.unwrap_or(self.pretty),
+            error_summary: self.error_summary,
+            show_error_code_links: self.show_error_code_links,
+            error_limit: self.error_limit,
+            terminal_width: self.terminal_width,
+        }
+    }
 }
 
 impl Default for DiagnosticConfig {
@@ -37,10 +69,45 @@ impl Default for DiagnosticConfig {
             show_column_numbers: false,
             pretty: false,
             error_summary: true,
+            show_error_code_links: false,
+            error_limit: None,
+            terminal_width: 80,
         }
     }
 }
 
+/// The parts of the [`DiagnosticConfig`] that can be changed for a single file with inline
+/// configuration comments like `# zuban: show-error-codes`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticConfigOverrides {
+    pub show_error_codes: Option<bool>,
+    pub show_error_end: Option<bool>,
+    pub show_column_numbers: Option<bool>,
+    pub pretty: Option<bool>,
+}
+
+impl DiagnosticConfigOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns `None` if the option is not about formatting diagnostics.
+    pub fn set(&mut self, name: &str, value: IniOrTomlValue) -> Option<ConfigResult> {
+        let (target, invert) = match name {
+            "show_error_codes" => (&mut self.show_error_codes, false),
+            "hide_error_codes" => (&mut self.show_error_codes, true),
+            "show_error_end" => (&mut self.show_error_end, false),
+            "hide_error_end" => (&mut self.show_error_end, true),
+            "show_column_numbers" => (&mut self.show_column_numbers, false),
+            "hide_column_numbers" => (&mut self.show_column_numbers, true),
+            "pretty" => (&mut self.pretty, false),
+            "no_pretty" => (&mut self.pretty, true),
+            _ => return None,
+        };
+        Some(value.as_bool(invert).map(|value| *target = Some(value)))
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct ProjectOptions {
     pub settings: Settings,
@@ -54,6 +121,21 @@ pub enum Mode {
     Default,
 }
 
+/// How imported modules that are not part of the checked files are handled, like Mypy's
+/// --follow-imports.
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum FollowImports {
+    /// Follow imports and analyze the imported modules.
+    #[default]
+    Normal,
+    /// Like normal, but diagnostics of modules that are not checked are never reported.
+    Silent,
+    /// Imports of modules that are not checked are treated as Any.
+    Skip,
+    /// Like skip, but the import is reported as an error.
+    Error,
+}
+
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, clap::ValueEnum)]
 pub enum UntypedFunctionReturnMode {
     Any,
@@ -61,10 +143,33 @@ pub enum UntypedFunctionReturnMode {
     Advanced,
 }
 
+/// The severity of diagnostics with a specific error code, see `[tool.zuban.severity]`.
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum ErrorCodeSeverity {
+    Error,
+    Warning,
+    Note,
+    Hint,
+    /// The diagnostics are not reported at all.
+    Off,
+}
+
+/// The coverage reports of Mypy, like `--html-report DIR`.
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+pub enum ReportKind {
+    Html,
+    Txt,
+    LinePrecision,
+    AnyExprs,
+}
+
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub struct Settings {
     pub platform: Option<String>,
     pub python_version: Option<PythonVersion>,
+    // If multiple versions are given, `zuban check` checks the project once per version, see
+    // `python_versions` and a repeated `--python-version`.
+    pub python_versions: Vec<PythonVersion>,
     pub environment: Option<Arc<NormalizedPath>>,
     pub mypy_path: Vec<Arc<NormalizedPath>>,
     pub prepended_site_packages: Vec<Arc<NormalizedPath>>,
@@ -76,7 +181,43 @@ pub struct Settings {
     pub explicit_package_bases: bool, // From Mypy's --explicit-package-bases
     // These are absolute paths.
     pub files_or_directories_to_check: Vec<GlobAbsPath>,
+    // Whether the files to check were passed on the command line instead of the config, which
+    // means that only a part of the project is checked.
+    pub files_passed_on_command_line: bool,
     pub typeshed_path: Option<Arc<NormalizedPath>>,
+    // The names of the enabled plugins, e.g. `numpy.typing.mypy_plugin`.
+    pub plugins: Vec<String>,
+    // Plugins that Zuban does not support are an error in Zuban configs, but only a warning in
+    // Mypy configs, because not every Mypy plugin is supported.
+    pub plugins_from_zuban: bool,
+    // From the django-stubs config, e.g. `myproject.settings`.
+    pub django_settings_module: Option<String>,
+    // A JSON file with diagnostics that are not reported, see `zuban check --write-baseline`.
+    pub baseline: Option<Arc<NormalizedPath>>,
+    // A JUnit XML report with the results of `zuban check` is written to this file.
+    pub junit_xml: Option<Arc<NormalizedPath>>,
+    // Diagnostics suppressed by `# type: ignore` comments are only collected for the SARIF
+    // output, which lists them as suppressed results.
+    pub collect_suppressed_diagnostics: bool,
+    // Directories that the coverage reports like `--html-report` are written to.
+    pub report_dirs: Vec<(ReportKind, Arc<NormalizedPath>)>,
+    // The number of threads that check files, see `zuban check --jobs`. Files are checked on a
+    // single thread if it's not set.
+    pub jobs: Option<NonZeroUsize>,
+    // The results of unchanged modules are reused from the cache directory of a previous check,
+    // see `--incremental` and `--cache-dir`. Disabled by default.
+    pub incremental: bool,
+    pub cache_dir: Option<Arc<NormalizedPath>>,
+    // The memory in megabytes that the analysis results of a long-running server may use, before
+    // the results of the least recently used files are evicted.
+    pub memory_limit_mb: Option<usize>,
+    // How a long-running server watches the files for changes, e.g. with Watchman.
+    pub file_watcher: WatcherKind,
+    // How long to wait for more file changes after a change, before they are processed together.
+    pub file_watcher_debounce_ms: Option<u64>,
+    // Options of Mypy configs that are ignored, because they are not known. Unknown options are
+    // an error in Zuban configs.
+    pub unknown_options: Vec<UnknownOption>,
 }
 
 impl Default for Settings {
@@ -84,6 +225,7 @@ impl Default for Settings {
         Self {
             platform: None,
             python_version: None,
+            python_versions: vec![],
             environment: None,
             typeshed_path: std::env::var("ZUBAN_TYPESHED")
                 .ok()
@@ -95,12 +237,52 @@ impl Default for Settings {
             exclude_gitignore: true,
             explicit_package_bases: false,
             files_or_directories_to_check: vec![],
+            files_passed_on_command_line: false,
             prepended_site_packages: vec![],
+            plugins: vec![],
+            plugins_from_zuban: false,
+            django_settings_module: None,
+            baseline: None,
+            junit_xml: None,
+            collect_suppressed_diagnostics: false,
+            report_dirs: vec![],
+            jobs: None,
+            incremental: false,
+            cache_dir: None,
+            memory_limit_mb: None,
+            file_watcher: WatcherKind::Auto,
+            file_watcher_debounce_ms: None,
+            unknown_options: vec![],
         }
     }
 }
 
 impl Settings {
+    fn add_unknown_option(&mut self, unknown: UnknownOption, from_zuban: bool) -> ConfigResult {
+        if from_zuban {
+            bail!("{}", unknown.message());
+        }
+        tracing::warn!(
+            "Ignored an option of {:?}: {}",
+            unknown.config_path,
+            unknown.message()
+        );
+        self.unknown_options.push(unknown);
+        Ok(())
+    }
+
+    /// Sets the section of the unknown options that were added after the first `previous_len`.
+    fn set_unknown_options_section(&mut self, previous_len: usize, section: &str) {
+        for unknown in &mut self.unknown_options[previous_len..] {
+            unknown.section = Some(section.to_string());
+        }
+    }
+
+    pub fn set_report_dir(&mut self, kind: ReportKind, dir: Arc<NormalizedPath>) {
+        self.report_dirs.retain(|(k, _)| *k != kind);
+        self.report_dirs.push((kind, dir))
+    }
+
     pub fn computed_platform(&self) -> &str {
         self.platform.as_deref().unwrap_or(if cfg!(windows) {
             "win32"
@@ -155,15 +337,16 @@ impl Settings {
         config_file_path: Option<&AbsPath>,
         items: impl IntoIterator<Item = String>,
     ) -> anyhow::Result<()> {
+        let items: Vec<String> = items.into_iter().collect();
+        if let Some(first) = items.first()
+            && first.starts_with('!')
+        {
+            // Negated patterns only exclude files that were matched by the patterns before them.
+            bail!("Expected the first files pattern not to be negated, got \"{first}\"")
+        }
         self.files_or_directories_to_check = items
             .into_iter()
-            .map(|s| {
-                GlobAbsPath::new(
-                    handler,
-                    project_dir,
-                    &replace_env_vars(config_file_path, &s),
-                )
-            })
+            .map(|s| GlobAbsPath::new(handler, project_dir, &expand_path(config_file_path, &s)))
             .collect::<anyhow::Result<Vec<_>>>()?;
         Ok(())
     }
@@ -191,26 +374,71 @@ fn to_normalized_path(
     config_file_path: Option<&AbsPath>,
     s: &str,
 ) -> Arc<NormalizedPath> {
-    // Replace only $MYPY_CONFIG_FILE_DIR for now.
-    handler.normalize_rc_path(if s.contains('$') {
-        handler.absolute_path(project_dir, &replace_env_vars(config_file_path, s))
-    } else {
-        handler.absolute_path(project_dir, s)
-    })
+    handler.normalize_rc_path(handler.absolute_path(project_dir, &expand_path(config_file_path, s)))
+}
+
+/// Expands paths like Mypy, see `expand_path_with`.
+fn expand_path<'x>(config_file_path: Option<&AbsPath>, s: &'x str) -> Cow<'x, str> {
+    let config_file_dir = config_file_path
+        .and_then(|path| path.as_ref().parent())
+        .and_then(|dir| dir.to_str());
+    expand_path_with(config_file_dir, s, |name| std::env::var(name).ok())
 }
 
-fn replace_env_vars<'x>(config_file_path: Option<&AbsPath>, s: &'x str) -> Cow<'x, str> {
-    // Replace only $MYPY_CONFIG_FILE_DIR for now.
-    if s.contains('$')
-        && let Some(config_file_path) = config_file_path
-        && let Some(mypy_config_file_dir) = config_file_path.as_ref().parent()
+/// Expands a leading `~` and environment variables like `$NAME` or `${NAME}` like Python's
+/// `os.path.expanduser` and `os.path.expandvars`, which Mypy uses for paths in configs. Unknown
+/// variables are left unchanged. `$MYPY_CONFIG_FILE_DIR` is the directory of the config file.
+fn expand_path_with<'x>(
+    config_file_dir: Option<&str>,
+    s: &'x str,
+    lookup_env_var: impl Fn(&str) -> Option<String>,
+) -> Cow<'x, str> {
+    if !s.contains('$') && !s.starts_with('~') {
+        return Cow::Borrowed(s);
+    }
+    let lookup = |name: &str| match name {
+        "MYPY_CONFIG_FILE_DIR" => config_file_dir
+            .map(String::from)
+            .or_else(|| lookup_env_var(name)),
+        _ => lookup_env_var(name),
+    };
+    let mut expanded = String::new();
+    let mut rest = s;
+    if let Some(after) = s.strip_prefix('~')
+        && (after.is_empty() || after.starts_with(['/', '\\']))
+        && let Some(home) = lookup("HOME").or_else(|| lookup("USERPROFILE"))
     {
-        return Cow::Owned(s.replace(
-            "$MYPY_CONFIG_FILE_DIR",
-            mypy_config_file_dir.to_str().unwrap(),
-        ));
+        expanded.push_str(&home);
+        rest = after;
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        match Some(name).filter(|name| !name.is_empty()).and_then(lookup) {
+            Some(value) => {
+                expanded.push_str(&value);
+                rest = remaining;
+            }
+            None => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
     }
-    Cow::Borrowed(s)
+    expanded.push_str(rest);
+    Cow::Owned(expanded)
 }
 
 impl ProjectOptions {
@@ -257,6 +485,7 @@ impl ProjectOptions {
             let Some(name) = name else { continue };
             if name == "mypy" {
                 had_relevant_section = true;
+                let previous_len = result.settings.unknown_options.len();
                 for (key, value) in section.iter() {
                     apply_from_base_config(
                         vfs,
@@ -270,6 +499,17 @@ impl ProjectOptions {
                         false,
                     )?;
                 }
+                result
+                    .settings
+                    .set_unknown_options_section(previous_len, name);
+            } else if name == "mypy.plugins.django-stubs" {
+                for (key, value) in section.iter() {
+                    apply_django_stubs_config(
+                        &mut result.settings,
+                        key,
+                        IniOrTomlValue::Ini(value),
+                    )?
+                }
             } else if let Some(rest) = name.strip_prefix("mypy-") {
                 had_relevant_section = true;
                 for rest in rest.split(',') {
@@ -279,11 +519,13 @@ impl ProjectOptions {
                             .iter()
                             .map(|(x, y)| (x.into(), OverrideIniOrTomlValue::Ini(y.into())))
                             .collect(),
+                        from_zuban: false,
                     })
                 }
             }
         }
         order_overrides_for_priority(&mut result.overrides);
+        result.validate_overrides(config_file_path)?;
         Ok(had_relevant_section.then_some(result))
     }
 
@@ -325,12 +567,43 @@ impl ProjectOptions {
                 config,
                 true,
             )?;
+            result.apply_pyproject_django_stubs_table(&document)?;
             Some(result)
         } else {
             result
         })
     }
 
+    /// A `zuban.toml` has the same options as `[tool.zuban]`, but at the top level.
+    pub fn from_zuban_toml(
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        config_file_path: &AbsPath,
+        code: &str,
+        diagnostic_config: &mut DiagnosticConfig,
+        mut mode: Option<Mode>,
+    ) -> anyhow::Result<Self> {
+        let document: DocumentMut = code.parse()?;
+        if let Some(item) = document.get("mode")
+            && let Some(value) = item.as_value()
+        {
+            mode = Some(
+                Mode::from_str(IniOrTomlValue::Toml(value).as_str()?, false)
+                    .map_err(|err| map_clap_error("mode", err))?,
+            );
+        }
+        let mut result = Self::default_for_mode(mode.unwrap_or(Mode::Default));
+        result.apply_pyproject_table(
+            vfs,
+            project_dir,
+            config_file_path,
+            diagnostic_config,
+            document.as_item(),
+            true,
+        )?;
+        Ok(result)
+    }
+
     pub fn apply_pyproject_toml_mypy_part(
         vfs: &dyn VfsHandler,
         project_dir: &AbsPath,
@@ -351,12 +624,86 @@ impl ProjectOptions {
                 config,
                 false,
             )?;
+            result.apply_pyproject_django_stubs_table(document)?;
             Ok(Some(result))
         } else {
             Ok(None)
         }
     }
 
+    /// Applies options that were configured in an editor, like `strict = true`. They overwrite
+    /// the options of config files.
+    pub fn apply_editor_settings<'x>(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        settings: impl IntoIterator<Item = (&'x str, &'x str)>,
+    ) -> anyhow::Result<()> {
+        self.apply_key_values(
+            vfs,
+            project_dir,
+            &mut DiagnosticConfig::default(),
+            settings,
+            "editor setting",
+        )
+    }
+
+    /// Applies the options of `--set key=value`. They overwrite the options of config files and
+    /// of all other flags.
+    pub fn apply_cli_settings<'x>(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        current_dir: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        settings: impl IntoIterator<Item = (&'x str, &'x str)>,
+    ) -> anyhow::Result<()> {
+        self.apply_key_values(vfs, current_dir, diagnostic_config, settings, "--set")
+    }
+
+    fn apply_key_values<'x>(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        settings: impl IntoIterator<Item = (&'x str, &'x str)>,
+        origin: &str,
+    ) -> anyhow::Result<()> {
+        for (key, value) in settings {
+            apply_from_base_config(
+                vfs,
+                project_dir,
+                None,
+                &mut self.settings,
+                &mut self.flags,
+                diagnostic_config,
+                key,
+                IniOrTomlValue::Ini(value),
+                true,
+            )
+            .map_err(|err| anyhow!("Invalid {origin} {key}: {err}"))?;
+        }
+        Ok(())
+    }
+
+    fn apply_pyproject_django_stubs_table(&mut self, document: &DocumentMut) -> ConfigResult {
+        let Some(config) = document
+            .get("tool")
+            .and_then(|item| item.get("django-stubs"))
+        else {
+            return Ok(());
+        };
+        let Item::Table(table) = config else {
+            bail!("Expected tool.django-stubs to be a table in pyproject.toml");
+        };
+        for (key, item) in table.iter() {
+            let Some(value) = item.as_value() else {
+                bail!("Expected tool.django-stubs to be a simple table in pyproject.toml");
+            };
+            apply_django_stubs_config(&mut self.settings, key, IniOrTomlValue::Toml(value))?
+        }
+        Ok(())
+    }
+
     fn apply_pyproject_table(
         &mut self,
         vfs: &dyn VfsHandler,
@@ -365,6 +712,28 @@ impl ProjectOptions {
         diagnostic_config: &mut DiagnosticConfig,
         config: &Item,
         from_zuban: bool,
+    ) -> anyhow::Result<()> {
+        self.apply_toml_table(
+            vfs,
+            project_dir,
+            config_file_path,
+            diagnostic_config,
+            config,
+            from_zuban,
+            &mut vec![],
+        )
+    }
+
+    #[expect(clippy::too_many_arguments)]
+    fn apply_toml_table(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        config_file_path: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        config: &Item,
+        from_zuban: bool,
+        extended_by: &mut Vec<Arc<NormalizedPath>>,
     ) -> anyhow::Result<()> {
         let Item::Table(table) = config else {
             bail!(
@@ -376,8 +745,34 @@ impl ProjectOptions {
             );
         };
 
+        if from_zuban {
+            self.apply_extended_config(
+                vfs,
+                project_dir,
+                config_file_path,
+                diagnostic_config,
+                table,
+                extended_by,
+            )?;
+            self.apply_pyproject_profile(
+                vfs,
+                project_dir,
+                config_file_path,
+                diagnostic_config,
+                table,
+            )?;
+        }
+
+        let previous_len = self.settings.unknown_options.len();
         for (key, item) in table.iter() {
             match item {
+                // Profiles are applied before all other options, so that they can be overwritten.
+                Item::Value(_) if from_zuban && key == "profile" => (),
+                Item::Value(_) if from_zuban && key == "extends" => (),
+                Item::Table(_) if from_zuban && key == "profiles" => (),
+                Item::Table(severities) if from_zuban && key == "severity" => {
+                    self.flags.apply_error_code_severities(severities)?
+                }
                 Item::Value(value) => {
                     apply_from_base_config(
                         vfs,
@@ -408,7 +803,11 @@ impl ProjectOptions {
                                     }
                                 }
                             }
-                            self.overrides.push(OverrideConfig { module, config })
+                            self.overrides.push(OverrideConfig {
+                                module,
+                                config,
+                                from_zuban,
+                            })
                         }
                     }
                 }
@@ -423,7 +822,202 @@ impl ProjectOptions {
                 }
             }
         }
+        // Unknown options are only allowed in tool.mypy.
+        self.settings
+            .set_unknown_options_section(previous_len, "tool.mypy");
         order_overrides_for_priority(&mut self.overrides);
+        self.validate_overrides(config_file_path)
+    }
+
+    /// Overrides are only applied when a matching file is checked, so we make sure here that
+    /// they are valid.
+    fn validate_overrides(&mut self, config_file_path: &AbsPath) -> ConfigResult {
+        for override_ in &self.overrides {
+            let mut flags = self.flags.clone();
+            for (key, value) in &override_.config {
+                let result = match apply_from_config_part(&mut flags, key, value.as_value(), false)
+                {
+                    Err(err) if err.is::<UnknownOption>() => {
+                        let mut unknown = err.downcast::<UnknownOption>().unwrap();
+                        unknown.config_path = Some(Arc::from(config_file_path));
+                        unknown.section = Some(match value {
+                            OverrideIniOrTomlValue::Ini(_) => format!("mypy-{}", override_.module),
+                            OverrideIniOrTomlValue::Toml(_) => "tool.mypy.overrides".to_string(),
+                        });
+                        self.settings
+                            .add_unknown_option(unknown, override_.from_zuban)
+                    }
+                    result => result,
+                };
+                result.map_err(|err| {
+                    anyhow!("Invalid override for module {}: {err}", override_.module)
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProjectOptions {
+    fn apply_pyproject_profile(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        config_file_path: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        table: &Table,
+    ) -> ConfigResult {
+        let profiles = match table.get("profiles") {
+            Some(Item::Table(profiles)) => {
+                if let Some(name) = profiles
+                    .iter()
+                    .map(|(name, _)| name)
+                    .find(|name| BUILTIN_PROFILES.contains(name))
+                {
+                    bail!(
+                        "The builtin profile {name:?} cannot be redefined in tool.zuban.profiles"
+                    );
+                }
+                Some(profiles)
+            }
+            Some(_) => bail!("Expected tool.zuban.profiles to be a table in pyproject.toml"),
+            None => None,
+        };
+        let Some(item) = table.get("profile") else {
+            return Ok(());
+        };
+        let Some(value) = item.as_value() else {
+            bail!("Expected tool.zuban.profile to be a string in pyproject.toml");
+        };
+        self.apply_profile(
+            vfs,
+            project_dir,
+            config_file_path,
+            diagnostic_config,
+            profiles,
+            IniOrTomlValue::Toml(value).as_str()?,
+            &mut vec![],
+        )
+    }
+
+    /// A config with `extends = "../base.toml"` is based on another config, which is applied
+    /// first. The base is either a pyproject.toml with a `[tool.zuban]` table or has the options
+    /// at the top level like a zuban.toml. Relative paths to it are relative to the config.
+    fn apply_extended_config(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        config_file_path: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        table: &Table,
+        extended_by: &mut Vec<Arc<NormalizedPath>>,
+    ) -> ConfigResult {
+        let current = vfs.normalize_rc_path(Arc::from(config_file_path));
+        if extended_by.contains(&current) {
+            bail!("The config {config_file_path} extends itself");
+        }
+        extended_by.push(current);
+        let Some(item) = table.get("extends") else {
+            return Ok(());
+        };
+        let Some(value) = item.as_value() else {
+            bail!("Expected extends to be a string in {config_file_path}");
+        };
+        let config_dir = vfs
+            .parent_of_absolute_path(config_file_path)
+            .unwrap_or(config_file_path);
+        let base_path = to_normalized_path(
+            vfs,
+            config_dir,
+            Some(config_file_path),
+            IniOrTomlValue::Toml(value).as_str()?,
+        );
+        let code = std::fs::read_to_string(base_path.as_ref())
+            .map_err(|err| anyhow!("Issue while reading {base_path}: {err}"))?;
+        let document: DocumentMut = code.parse()?;
+        let base = match document.get("tool") {
+            Some(tool) => tool
+                .get("zuban")
+                .ok_or_else(|| anyhow!("Expected a [tool.zuban] table in {base_path}"))?,
+            None => document.as_item(),
+        };
+        tracing::info!("{config_file_path} extends {base_path}");
+        self.apply_toml_table(
+            vfs,
+            project_dir,
+            &base_path,
+            diagnostic_config,
+            base,
+            true,
+            extended_by,
+        )
+        .map_err(|err| anyhow!("Issue in {base_path}: {err}"))
+    }
+
+    #[expect(clippy::too_many_arguments)]
+    fn apply_profile(
+        &mut self,
+        vfs: &dyn VfsHandler,
+        project_dir: &AbsPath,
+        config_file_path: &AbsPath,
+        diagnostic_config: &mut DiagnosticConfig,
+        profiles: Option<&Table>,
+        name: &str,
+        seen: &mut Vec<String>,
+    ) -> ConfigResult {
+        match name {
+            "strict" => self.flags.enable_all_strict_flags(),
+            "standard" => (),
+            "lenient" => self.flags.enable_lenient_flags(),
+            _ => {
+                let Some(profile) = profiles.and_then(|profiles| profiles.get(name)) else {
+                    bail!(
+                        "Unknown profile {name:?}, expected one of {} or a profile defined in tool.zuban.profiles",
+                        BUILTIN_PROFILES.join(", ")
+                    );
+                };
+                let Item::Table(profile) = profile else {
+                    bail!("Expected tool.zuban.profiles.{name} to be a table in pyproject.toml");
+                };
+                if seen.iter().any(|n| n == name) {
+                    bail!("The profile {name:?} is based on itself");
+                }
+                seen.push(name.to_string());
+                // A profile can be based on another profile.
+                if let Some(base) = profile.get("profile").and_then(|item| item.as_value()) {
+                    self.apply_profile(
+                        vfs,
+                        project_dir,
+                        config_file_path,
+                        diagnostic_config,
+                        profiles,
+                        IniOrTomlValue::Toml(base).as_str()?,
+                        seen,
+                    )?;
+                }
+                for (key, item) in profile.iter() {
+                    if key == "profile" {
+                        continue;
+                    }
+                    let Some(value) = item.as_value() else {
+                        bail!(
+                            "Expected tool.zuban.profiles.{name} to be a simple table in pyproject.toml"
+                        );
+                    };
+                    apply_from_base_config(
+                        vfs,
+                        project_dir,
+                        Some(config_file_path),
+                        &mut self.settings,
+                        &mut self.flags,
+                        diagnostic_config,
+                        key,
+                        IniOrTomlValue::Toml(value),
+                        true,
+                    )?;
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -451,6 +1045,8 @@ pub struct TypeCheckerFlags {
     pub check_untyped_defs: bool,
     pub ignore_missing_imports: bool,
     pub follow_untyped_imports: bool,
+    pub follow_imports: FollowImports,
+    pub follow_imports_for_stubs: bool,
 
     pub disallow_untyped_defs: bool,
     pub disallow_untyped_calls: bool,
@@ -476,12 +1072,17 @@ pub struct TypeCheckerFlags {
 
     pub enabled_error_codes: Vec<String>,
     pub disabled_error_codes: Vec<String>,
+    // From `[tool.zuban.severity]`, later entries win.
+    pub error_code_severities: Vec<(String, ErrorCodeSeverity)>,
     pub always_true_symbols: Vec<String>,
     pub always_false_symbols: Vec<String>,
     pub excludes: Vec<ExcludeRegex>,
+    // From `exclude_patterns`, which uses the syntax of a .gitignore.
+    pub exclude_patterns: GitignorePatterns,
 
     pub extra_checks: bool,
     pub case_sensitive: bool,
+    pub report_deprecated_as_note: bool,
 
     // Non-mypy settings
     pub use_joins: bool,
@@ -501,6 +1102,8 @@ impl Default for TypeCheckerFlags {
             check_untyped_defs: true,
             ignore_missing_imports: false,
             follow_untyped_imports: true,
+            follow_imports: FollowImports::Normal,
+            follow_imports_for_stubs: false,
             disallow_untyped_defs: false,
             disallow_untyped_calls: false,
             disallow_untyped_decorators: false,
@@ -523,12 +1126,15 @@ impl Default for TypeCheckerFlags {
             disable_bytearray_promotion: false,
             disable_memoryview_promotion: false,
             excludes: vec![],
+            exclude_patterns: Default::default(),
             always_true_symbols: vec![],
             always_false_symbols: vec![],
             enabled_error_codes: vec![],
             disabled_error_codes: vec![],
+            error_code_severities: vec![],
             extra_checks: false,
             case_sensitive: true,
+            report_deprecated_as_note: false,
             use_joins: false,
             disallow_deprecated: false,
             allow_incomplete_generics: false,
@@ -539,6 +1145,27 @@ impl Default for TypeCheckerFlags {
 }
 
 impl TypeCheckerFlags {
+    pub fn error_code_severity(&self, code: &str) -> Option<ErrorCodeSeverity> {
+        self.error_code_severities
+            .iter()
+            .rev()
+            .find_map(|(c, severity)| (c == code).then_some(*severity))
+    }
+
+    fn apply_error_code_severities(&mut self, table: &Table) -> ConfigResult {
+        for (code, item) in table.iter() {
+            let Some(value) = item.as_value() else {
+                bail!("Expected tool.zuban.severity.{code} to be a string in pyproject.toml");
+            };
+            let severity =
+                ErrorCodeSeverity::from_str(IniOrTomlValue::Toml(value).as_str()?, false)
+                    .map_err(|err| map_clap_error(&format!("tool.zuban.severity.{code}"), err))?;
+            self.error_code_severities
+                .push((code.to_string(), severity));
+        }
+        Ok(())
+    }
+
     pub fn enable_all_strict_flags(&mut self) {
         // Use for --strict
         // self.warn_unused_configs = true;
@@ -560,6 +1187,17 @@ impl TypeCheckerFlags {
         self.untyped_strict_optional = true;
     }
 
+    /// Used for the "lenient" profile, e.g. for legacy codebases that are mostly untyped.
+    pub fn enable_lenient_flags(&mut self) {
+        self.check_untyped_defs = false;
+        self.ignore_missing_imports = true;
+        self.allow_untyped_globals = true;
+        self.allow_redefinition = true;
+        self.warn_unreachable = false;
+        self.warn_no_return = false;
+        self.strict_equality = false;
+    }
+
     pub fn enable_strict_bytes(&mut self) {
         self.disable_bytearray_promotion = true;
         self.disable_memoryview_promotion = true;
@@ -604,6 +1242,12 @@ impl PythonVersion {
     }
 }
 
+impl std::fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
 impl std::str::FromStr for PythonVersion {
     type Err = anyhow::Error;
 
@@ -652,20 +1296,20 @@ impl std::hash::Hash for ExcludeRegex {
 }
 
 // These are the overrides with the precedence order as described in https://mypy.readthedocs.io/en/stable/config_file.html#config-file-format
-#[derive(PartialOrd, Ord, PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Copy, Clone, Debug, Hash)]
 enum OverrideKind {
     WellStructured, // e.g. foo.bar.*
     Unstructured,   // e.g. foo.*.baz
     ModuleName,     // e.g. foo.bar (has the highest priority
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 enum OverridePathPart {
     Part(Box<str>),
     Wildcard,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct OverridePath {
     path: Vec<OverridePathPart>,
     kind: OverrideKind,
@@ -699,6 +1343,21 @@ impl From<&str> for OverridePath {
     }
 }
 
+impl std::fmt::Display for OverridePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, part) in self.path.iter().enumerate() {
+            if i != 0 {
+                f.write_str(".")?;
+            }
+            match part {
+                OverridePathPart::Part(part) => f.write_str(part)?,
+                OverridePathPart::Wildcard => f.write_str("*")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl OverridePath {
     pub fn matches_file_path(&self, name: &str, parent_dir: Option<&Directory>) -> bool {
         fn matches_file_path<'x>(
@@ -756,25 +1415,52 @@ enum OverrideIniOrTomlValue {
     Ini(Box<str>),
 }
 
-#[derive(Clone, Debug)]
-pub struct OverrideConfig {
-    pub module: OverridePath, // Path like foo.bar or foo.bar.*
-    // Key/Value mappings
-    config: Vec<(Box<str>, OverrideIniOrTomlValue)>,
+impl std::hash::Hash for OverrideIniOrTomlValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        // TOML values don't implement Hash, but their representation identifies them.
+        match self {
+            Self::Toml(v) => v.to_string().hash(state),
+            Self::Ini(v) => v.hash(state),
+        }
+    }
 }
 
-impl OverrideConfig {
-    pub fn apply_to_flags(&self, flags: &mut TypeCheckerFlags) -> ConfigResult {
-        for (key, value) in self.config.iter() {
-            apply_from_config_part(
-                flags,
-                key,
-                match value {
-                    OverrideIniOrTomlValue::Toml(v) => IniOrTomlValue::Toml(v),
-                    OverrideIniOrTomlValue::Ini(v) => IniOrTomlValue::Ini(v),
-                },
-                false,
-            )?;
+impl std::cmp::PartialEq for OverrideIniOrTomlValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Toml(v1), Self::Toml(v2)) => v1.to_string() == v2.to_string(),
+            (Self::Ini(v1), Self::Ini(v2)) => v1 == v2,
+            _ => false,
+        }
+    }
+}
+
+impl OverrideIniOrTomlValue {
+    fn as_value(&self) -> IniOrTomlValue<'_> {
+        match self {
+            Self::Toml(v) => IniOrTomlValue::Toml(v),
+            Self::Ini(v) => IniOrTomlValue::Ini(v),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct OverrideConfig {
+    pub module: OverridePath, // Path like foo.bar or foo.bar.*
+    // Key/Value mappings
+    config: Vec<(Box<str>, OverrideIniOrTomlValue)>,
+    // Overrides in [[tool.zuban.overrides]] are stricter about unknown options.
+    from_zuban: bool,
+}
+
+impl OverrideConfig {
+    pub fn apply_to_flags(&self, flags: &mut TypeCheckerFlags) -> ConfigResult {
+        for (key, value) in self.config.iter() {
+            match apply_from_config_part(flags, key, value.as_value(), self.from_zuban) {
+                // Already reported when the config was loaded.
+                Err(err) if err.is::<UnknownOption>() => (),
+                result => result?,
+            }
         }
         Ok(())
     }
@@ -798,6 +1484,186 @@ fn pyproject_toml_override_module_names(table: &Table) -> anyhow::Result<Vec<Ove
     }
 }
 
+/// An option of a config file that is not known, e.g. because of a typo.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct UnknownOption {
+    pub name: String,
+    pub value: String,
+    pub config_path: Option<Arc<AbsPath>>,
+    /// The section of the config file the option is in, e.g. `mypy-foo.*` or `tool.mypy`.
+    pub section: Option<String>,
+}
+
+impl UnknownOption {
+    /// The known option with the most similar name, e.g. `disallow_untyped_defs` for
+    /// `disallow_untyed_defs`.
+    pub fn suggestion(&self) -> Option<String> {
+        let name = self.name.replace('-', "_");
+        KNOWN_OPTIONS
+            .iter()
+            .chain(IGNORED_OPTIONS)
+            .flat_map(|option| [option.to_string(), format!("no_{option}")])
+            .map(|option| (edit_distance(&name, &option), option))
+            .filter(|(distance, _)| *distance <= name.len() / 5 + 1)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, option)| option)
+    }
+
+    pub fn message(&self) -> String {
+        if let Some((_, reason)) = UNSUPPORTED_OPTIONS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+        {
+            return format!("{self} ({reason})");
+        }
+        match self.suggestion() {
+            Some(suggestion) => format!("{self} (did you mean {suggestion:?}?)"),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for UnknownOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unrecognized option: {} = {}", self.name, self.value)
+    }
+}
+
+impl std::error::Error for UnknownOption {}
+
+/// Whether an option is one of [`KNOWN_OPTIONS`] or [`IGNORED_OPTIONS`], possibly in its
+/// inverted form. All other options are reported as unknown.
+fn is_known_option(name: &str) -> bool {
+    let (_, inverted) = maybe_invert(name);
+    [name, &inverted]
+        .iter()
+        .any(|name| KNOWN_OPTIONS.contains(name) || IGNORED_OPTIONS.contains(name))
+}
+
+/// The options of config files, without the inverted forms like `no_strict_optional`.
+const KNOWN_OPTIONS: &[&str] = &[
+    "show_error_codes",
+    "show_column_numbers",
+    "show_error_end",
+    "pretty",
+    "show_error_code_links",
+    "error_summary",
+    "show_error_context",
+    "show_traceback",
+    "enable_incomplete_feature",
+    "warn_redundant_casts",
+    "warn_unused_configs",
+    "warn_unused_ignores",
+    "exclude_gitignore",
+    "respect_gitignore",
+    "explicit_package_bases",
+    "incremental",
+    "cache_dir",
+    "plugins",
+    "exclude",
+    "exclude_patterns",
+    "files",
+    "mypy_path",
+    "python_executable",
+    "python_version",
+    "python_versions",
+    "platform",
+    "memory_limit_mb",
+    "file_watcher",
+    "file_watcher_debounce_ms",
+    "junit_xml",
+    "html_report",
+    "txt_report",
+    "lineprecision_report",
+    "any_exprs_report",
+    "mode",
+    "profile",
+    "extends",
+    "baseline",
+    "untyped_function_return_mode",
+    "strict",
+    "always_true",
+    "always_false",
+    "enable_error_code",
+    "disable_error_code",
+    "strict_optional",
+    "strict_equality",
+    "strict_equality_for_none",
+    "strict_concatenate",
+    "strict_bytes",
+    "implicit_optional",
+    "check_untyped_defs",
+    "ignore_missing_imports",
+    "follow_untyped_imports",
+    "follow_imports",
+    "follow_imports_for_stubs",
+    "disallow_untyped_defs",
+    "disallow_untyped_calls",
+    "disallow_untyped_decorators",
+    "disallow_any_generics",
+    "disallow_any_decorated",
+    "disallow_any_explicit",
+    "disallow_any_unimported",
+    "disallow_any_expr",
+    "disallow_subclassing_any",
+    "disallow_incomplete_defs",
+    "allow_untyped_globals",
+    "allow_empty_bodies",
+    "allow_redefinition",
+    "allow_redefinition_new",
+    "warn_unreachable",
+    "warn_return_any",
+    "warn_no_return",
+    "local_partial_types",
+    "implicit_reexport",
+    "disable_bytearray_promotion",
+    "disable_memoryview_promotion",
+    "extra_checks",
+    "report_deprecated_as_note",
+    "ignore_errors",
+    "untyped_strict_optional",
+];
+
+/// Options of Mypy that are accepted, but don't change anything in Zuban, e.g. because they
+/// only concern Mypy's cache or its output.
+const IGNORED_OPTIONS: &[&str] = &[
+    "sqlite_cache",
+    "cache_fine_grained",
+    "force_uppercase_builtins",
+    "force_union_syntax",
+    "verbosity",
+    "color_output",
+    "namespace_packages",
+    "warn_incomplete_stub",
+    "show_absolute_path",
+    "install_types",
+    "non_interactive",
+    "untyped_calls_exclude",
+    "scripts_are_modules",
+    "no_site_packages",
+];
+
+/// Options of Mypy configs that Zuban cannot support, with the reason that is reported to users.
+const UNSUPPORTED_OPTIONS: &[(&str, &str)] = &[(
+    "plugins_path",
+    "Zuban only supports its builtin plugins and does not load plugin files",
+)];
+
+/// The Levenshtein distance of two names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum IniOrTomlValue<'config> {
     Toml(&'config Value),
@@ -808,7 +1674,7 @@ pub enum IniOrTomlValue<'config> {
 impl IniOrTomlValue<'_> {
     fn as_repr(&self) -> Cow<'_, str> {
         match self {
-            Self::Toml(v) => Cow::from(v.to_string()),
+            Self::Toml(v) => Cow::from(v.to_string().trim().to_string()),
             Self::Ini(v) => Cow::Borrowed(v),
             Self::InlineConfigNoValue => Cow::Borrowed("True"),
         }
@@ -881,6 +1747,22 @@ pub fn set_flag(
     value: IniOrTomlValue,
     add_error_if_unrecognized_option: bool,
 ) -> ConfigResult {
+    if !is_known_option(name) {
+        let unknown = UnknownOption {
+            name: name.into(),
+            value: value.as_repr().into(),
+            config_path: None,
+            section: None,
+        };
+        if add_error_if_unrecognized_option {
+            bail!("{unknown}");
+        }
+        return Err(unknown.into());
+    }
+    if IGNORED_OPTIONS.contains(&name) {
+        tracing::info!("Ignored the option {name}, which has no effect in Zuban");
+        return Ok(());
+    }
     let (invert, option_name) = maybe_invert(name);
     let add_list_of_str = |target: &mut Vec<String>| {
         if invert {
@@ -920,31 +1802,40 @@ pub fn set_flag(
         }
         "always_true" => add_list_of_str(&mut flags.always_true_symbols),
         "always_false" => add_list_of_str(&mut flags.always_false_symbols),
-        "enable_error_code" => add_list_of_str(&mut flags.enabled_error_codes),
-        "disable_error_code" => add_list_of_str(&mut flags.disabled_error_codes),
+        // Like in Mypy the last setting wins, so per-module overrides and inline configuration
+        // can disable codes that are enabled globally and the other way around.
+        "enable_error_code" => {
+            let previous_len = flags.enabled_error_codes.len();
+            add_list_of_str(&mut flags.enabled_error_codes)?;
+            let added = &flags.enabled_error_codes[previous_len..];
+            flags
+                .disabled_error_codes
+                .retain(|code| !added.contains(code));
+            Ok(())
+        }
+        "disable_error_code" => {
+            let previous_len = flags.disabled_error_codes.len();
+            add_list_of_str(&mut flags.disabled_error_codes)?;
+            let added = &flags.disabled_error_codes[previous_len..];
+            flags
+                .enabled_error_codes
+                .retain(|code| !added.contains(code));
+            Ok(())
+        }
         "strict" => bail!(concat!(
             r#"Setting "strict" not supported in inline configuration: "#,
             r#"specify it in a configuration file instead, or set individual "#,
             r#"inline flags (see "mypy -h" for the list of flags enabled in strict mode)"#
         )),
-        _ => set_bool_init_flags(
-            flags,
-            name,
-            &option_name,
-            value,
-            invert,
-            add_error_if_unrecognized_option,
-        ),
+        _ => set_bool_init_flags(flags, &option_name, value, invert),
     }
 }
 
 fn set_bool_init_flags(
     flags: &mut TypeCheckerFlags,
-    original_name: &str,
     name: &str,
     value: IniOrTomlValue,
     invert: bool,
-    add_error_if_unrecognized_option: bool,
 ) -> ConfigResult {
     match name {
         "strict_optional" => flags.strict_optional = value.as_bool(invert)?,
@@ -988,32 +1879,25 @@ fn set_bool_init_flags(
         | "strict_equality_for_none" => {
             tracing::warn!("Ignored config value {name}, please contact support if you need them");
         }
-        "sqlite_cache" | "incremental" => (), // This doesn't matter
-        // Probably doesn't matter
-        "force_uppercase_builtins" | "force_union_syntax" | "verbosity" | "color_output" => (),
-
         "extra_checks" => flags.extra_checks = value.as_bool(invert)?,
-        // These are currently ignored
-        "follow_imports" | "follow_imports_for_stubs" => (),
-        // Will always be irrelevant
-        "cache_fine_grained" => (),
+        "report_deprecated_as_note" => flags.report_deprecated_as_note = value.as_bool(invert)?,
+        "follow_imports" => {
+            flags.follow_imports = FollowImports::from_str(value.as_str()?, false)
+                .map_err(|err| map_clap_error("follow_imports", err))?
+        }
+        "follow_imports_for_stubs" => flags.follow_imports_for_stubs = value.as_bool(invert)?,
         "ignore_errors" => {
             flags.ignore_errors = value.as_bool(invert)?;
         }
-        "python_version" => bail!("python_version not supported in inline configuration"),
+        "python_version" | "python_versions" => {
+            bail!("{name} not supported in inline configuration")
+        }
 
         // Our own
         "untyped_strict_optional" => flags.untyped_strict_optional = value.as_bool(invert)?,
-        _ => {
-            if add_error_if_unrecognized_option {
-                bail!("Unrecognized option: {original_name} = {}", value.as_repr());
-            } else {
-                tracing::warn!(
-                    "Unsupported option given in Mypy config: {original_name} = {}, contact support if you need it",
-                    value.as_repr()
-                );
-            }
-        }
+        // The other known options are only valid in the global section of a config file, e.g.
+        // `cache_dir`.
+        _ => bail!("{name} not supported per module or in inline configuration"),
     }
     Ok(())
 }
@@ -1023,7 +1907,45 @@ fn split_and_trim<'a>(s: &'a str, pattern: &'a [char]) -> impl Iterator<Item = &
     if let Some(new_s) = s.strip_suffix(pattern) {
         s = new_s
     }
-    s.split(pattern).map(|s| s.trim())
+    // Separators within braces are part of a glob alternation like `src/{a,b}.py`.
+    let mut depth = 0usize;
+    s.split(move |c| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => return depth == 0 && pattern.contains(&c),
+        }
+        false
+    })
+    .map(|s| s.trim())
+}
+
+/// Splits a plugin like `numpy.typing.mypy_plugin:plugin` into its location (a module name or a
+/// path to a Python file) and the optional entry point.
+pub fn split_plugin_entry_point(plugin: &str) -> (&str, Option<&str>) {
+    match plugin.rsplit_once(':') {
+        // Windows paths like C:\plugin.py contain a colon as well.
+        Some((location, entry_point)) if !entry_point.contains(['/', '\\']) => {
+            (location, Some(entry_point))
+        }
+        _ => (plugin, None),
+    }
+}
+
+fn check_plugin_syntax(plugin: &str) -> ConfigResult {
+    let is_identifier = |s: &str| {
+        s.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    let (location, entry_point) = split_plugin_entry_point(plugin);
+    if !entry_point.is_none_or(is_identifier)
+        || !(location.ends_with(".py") || location.split('.').all(is_identifier))
+    {
+        bail!(
+            "Invalid plugin {plugin:?}, expected a module name like numpy.typing.mypy_plugin or a path to a Python file"
+        );
+    }
+    Ok(())
 }
 
 fn map_clap_error(arg: &str, err: String) -> anyhow::Error {
@@ -1055,25 +1977,52 @@ fn apply_from_base_config(
         "pretty" => {
             diagnostic_config.pretty = value.as_bool(false)?;
         }
-        "exclude_gitignore" => {
+        "show_error_code_links" => {
+            diagnostic_config.show_error_code_links = value.as_bool(false)?;
+        }
+        "exclude_gitignore" | "respect_gitignore" => {
             settings.exclude_gitignore = value.as_bool(false)?;
         }
         "explicit_package_bases" => {
             settings.explicit_package_bases = value.as_bool(false)?;
         }
+        "error_summary" => {
+            diagnostic_config.error_summary = value.as_bool(false)?;
+        }
         "no_error_summary" => {
             diagnostic_config.error_summary = value.as_bool(true)?;
         }
         "show_error_context"
         | "show_traceback"
-        | "plugins"
         | "enable_incomplete_feature"
-        | "show_error_code_links"
-        | "cache_dir"
         | "warn_redundant_casts"
         | "warn_unused_configs" => {
             tracing::warn!("TODO ignored config value {key}");
         }
+        "incremental" => settings.incremental = value.as_bool(false)?,
+        "cache_dir" => {
+            let cache_dir = value.as_str()?;
+            // Mypy's way of disabling the cache.
+            if cache_dir == "/dev/null" {
+                settings.incremental = false;
+            } else {
+                settings.cache_dir = Some(to_normalized_path(
+                    vfs,
+                    project_dir,
+                    config_file_path,
+                    cache_dir,
+                ));
+            }
+        }
+        "plugins" => {
+            for plugin in value.as_str_list(key, &[','])? {
+                check_plugin_syntax(&plugin)?;
+                settings.plugins.push(plugin)
+            }
+            settings.plugins_from_zuban |= from_zuban;
+        }
+        // Like in Mypy, excludes are regexes and not paths, so they are not expanded.
+        "exclude" => add_excludes(&mut flags.excludes, value)?,
         "files" => settings.set_files_or_directories_to_check(
             vfs,
             project_dir,
@@ -1097,18 +2046,116 @@ fn apply_from_base_config(
             })
         }
         "platform" => settings.platform = Some(value.as_str()?.to_string()),
+        "memory_limit_mb" => {
+            settings.memory_limit_mb =
+                Some(if let IniOrTomlValue::Toml(Value::Integer(i)) = &value {
+                    (*i.value()).try_into()?
+                } else {
+                    value.as_str()?.parse()?
+                })
+        }
+        "file_watcher_debounce_ms" => {
+            settings.file_watcher_debounce_ms =
+                Some(if let IniOrTomlValue::Toml(Value::Integer(i)) = &value {
+                    (*i.value()).try_into()?
+                } else {
+                    value.as_str()?.parse()?
+                })
+        }
+        "file_watcher" => {
+            settings.file_watcher = value
+                .as_str()?
+                .parse()
+                .map_err(|err: String| anyhow!(err))?
+        }
+        "junit_xml" => {
+            settings.junit_xml = Some(to_normalized_path(
+                vfs,
+                project_dir,
+                config_file_path,
+                value.as_str()?,
+            ))
+        }
+        "html_report" | "txt_report" | "lineprecision_report" | "any_exprs_report" => {
+            let kind = match key {
+                "html_report" => ReportKind::Html,
+                "txt_report" => ReportKind::Txt,
+                "lineprecision_report" => ReportKind::LinePrecision,
+                _ => ReportKind::AnyExprs,
+            };
+            let dir = to_normalized_path(vfs, project_dir, config_file_path, value.as_str()?);
+            settings.set_report_dir(kind, dir)
+        }
         // Our own
+        "python_versions" => {
+            let versions: Vec<PythonVersion> = value
+                .as_str_list(key, &[','])?
+                .iter()
+                .map(|v| v.parse())
+                .collect::<anyhow::Result<_>>()?;
+            match versions.as_slice() {
+                // Like a single --python-version
+                [version] => {
+                    settings.python_version = Some(*version);
+                    settings.python_versions.clear();
+                }
+                _ => settings.python_versions = versions,
+            }
+        }
         "mode" => (), // Already checked earlier
+        "profile" | "extends" => bail!("{key} is only supported in tool.zuban and zuban.toml"),
+        "exclude_patterns" => {
+            for pattern in value.as_str_list(key, &[',', '\n'])? {
+                flags
+                    .exclude_patterns
+                    .add(&pattern)
+                    .map_err(|err| anyhow!("Invalid exclude pattern {pattern:?}: {err}"))?
+            }
+        }
+        "baseline" => {
+            settings.baseline = Some(to_normalized_path(
+                vfs,
+                project_dir,
+                config_file_path,
+                value.as_str()?,
+            ))
+        }
         "untyped_function_return_mode" => {
             settings.untyped_function_return_mode =
                 UntypedFunctionReturnMode::from_str(value.as_str()?, false)
                     .map_err(|err| map_clap_error("untyped_function_return_mode", err))?;
         }
-        _ => return apply_from_config_part(flags, key, value, from_zuban),
+        _ => {
+            return match apply_from_config_part(flags, key, value, false) {
+                Err(err) if err.is::<UnknownOption>() => {
+                    let mut unknown = err.downcast::<UnknownOption>().unwrap();
+                    unknown.config_path = config_file_path.map(Arc::from);
+                    settings.add_unknown_option(unknown, from_zuban)
+                }
+                result => result,
+            };
+        }
     };
     Ok(())
 }
 
+/// The options of the django-stubs Mypy plugin.
+fn apply_django_stubs_config(
+    settings: &mut Settings,
+    key: &str,
+    value: IniOrTomlValue,
+) -> ConfigResult {
+    match key {
+        "django_settings_module" => {
+            // The documentation of django-stubs quotes the module in ini files as well.
+            let module = value.as_str()?.trim_matches(|c| c == '"' || c == '\'');
+            settings.django_settings_module = Some(module.to_string())
+        }
+        _ => tracing::warn!("TODO ignored django-stubs config value {key}"),
+    }
+    Ok(())
+}
+
 fn apply_from_config_part(
     flags: &mut TypeCheckerFlags,
     key: &str,
@@ -1332,6 +2379,101 @@ mod tests {
         assert_eq!(err.to_string(), "Expected str, got false");
     }
 
+    #[test]
+    fn test_python_versions() {
+        let code = "[tool.zuban]\npython_versions = ['3.9', '3.12']";
+        let opts = project_options_valid(code, false);
+        assert_eq!(
+            opts.settings.python_versions,
+            [PythonVersion::new(3, 9), PythonVersion::new(3, 12)]
+        );
+
+        let code = "[mypy]\npython_versions = 3.10, 3.13";
+        let opts = project_options_valid(code, true);
+        assert_eq!(
+            opts.settings.python_versions,
+            [PythonVersion::new(3, 10), PythonVersion::new(3, 13)]
+        );
+
+        // A single version is the version to check against.
+        let code = "[tool.zuban]\npython_versions = ['3.11']";
+        let opts = project_options_valid(code, false);
+        assert!(opts.settings.python_versions.is_empty());
+        assert_eq!(
+            opts.settings.python_version,
+            Some(PythonVersion::new(3, 11))
+        );
+
+        let code = "[tool.zuban]\npython_versions = ['3']";
+        let err = project_options_err(code, false);
+        assert_eq!(
+            err.to_string(),
+            "Expected a dot separated python version like 3.13"
+        );
+    }
+
+    #[test]
+    fn test_error_code_severities() {
+        let code = "[tool.zuban.severity]\npossibly-undefined = 'warning'\nmisc = 'off'";
+        let opts = project_options_valid(code, false);
+        assert_eq!(
+            opts.flags.error_code_severity("possibly-undefined"),
+            Some(ErrorCodeSeverity::Warning)
+        );
+        assert_eq!(
+            opts.flags.error_code_severity("misc"),
+            Some(ErrorCodeSeverity::Off)
+        );
+        assert_eq!(opts.flags.error_code_severity("operator"), None);
+
+        let err = project_options_err("[tool.zuban.severity]\nmisc = 'fatal'", false);
+        assert!(
+            err.to_string()
+                .starts_with("Error while parsing tool.zuban.severity.misc: ")
+        );
+    }
+
+    #[test]
+    fn test_plugins() {
+        let code = "[mypy]\nplugins = pydantic.mypy, numpy.typing.mypy_plugin:plugin";
+        let opts = project_options_valid(code, true);
+        assert_eq!(
+            opts.settings.plugins,
+            ["pydantic.mypy", "numpy.typing.mypy_plugin:plugin"]
+        );
+        assert!(!opts.settings.plugins_from_zuban);
+
+        let code = "[tool.zuban]\nplugins = ['pydantic.mypy']";
+        let opts = project_options_valid(code, false);
+        assert!(opts.settings.plugins_from_zuban);
+
+        // Plugin files are never loaded, plugins_path is only a compatibility warning.
+        let code = "[tool.mypy]\nplugins = ['plugin.py']\nplugins_path = ['plugins']";
+        let opts = project_options_valid(code, false);
+        assert_eq!(
+            opts.settings.unknown_options[0].message(),
+            "Unrecognized option: plugins_path = ['plugins'] (Zuban only supports its \
+             builtin plugins and does not load plugin files)"
+        );
+
+        let err = |code| project_options_err(code, false).to_string();
+        assert_eq!(
+            err("[tool.mypy]\nplugins = ['pydantic..mypy']"),
+            "Invalid plugin \"pydantic..mypy\", expected a module name like \
+             numpy.typing.mypy_plugin or a path to a Python file"
+        );
+        assert_eq!(
+            err("[tool.zuban]\nplugins = ['foo:1entry']"),
+            "Invalid plugin \"foo:1entry\", expected a module name like \
+             numpy.typing.mypy_plugin or a path to a Python file"
+        );
+        assert_eq!(
+            err("[tool.zuban]\nplugins_path = ['plugins']"),
+            "Unrecognized option: plugins_path = ['plugins'] (Zuban only supports its \
+             builtin plugins and does not load plugin files)"
+        );
+    }
+
     #[test]
     fn test_platform_valid() {
         let code = "[mypy]\nplatform = foo";
@@ -1345,4 +2487,439 @@ mod tests {
         let err = project_options_err(code, false);
         assert_eq!(err.to_string(), "Expected str, got false");
     }
+
+    #[test]
+    fn test_memory_limit() {
+        let opts = project_options_valid("[tool.zuban]\nmemory_limit_mb = 2048", false);
+        assert_eq!(opts.settings.memory_limit_mb, Some(2048));
+        let opts = project_options_valid("[mypy]\nmemory_limit_mb = 512", true);
+        assert_eq!(opts.settings.memory_limit_mb, Some(512));
+        let err = project_options_err("[tool.zuban]\nmemory_limit_mb = -1", false);
+        assert_eq!(
+            err.to_string(),
+            "out of range integral type conversion attempted"
+        );
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let opts = project_options_valid("[tool.zuban]\nfile_watcher = \"watchman\"", false);
+        assert_eq!(opts.settings.file_watcher, WatcherKind::Watchman);
+        let opts = project_options_valid("[mypy]\nfile_watcher = notify", true);
+        assert_eq!(opts.settings.file_watcher, WatcherKind::Notify);
+        let err = project_options_err("[tool.zuban]\nfile_watcher = \"inotify\"", false);
+        assert_eq!(
+            err.to_string(),
+            r#"Expected "auto", "notify" or "watchman", got "inotify""#
+        );
+        let opts = project_options_valid("[tool.zuban]\nfile_watcher_debounce_ms = 200", false);
+        assert_eq!(opts.settings.file_watcher_debounce_ms, Some(200));
+    }
+
+    #[test]
+    fn test_files_patterns() {
+        let opts = project_options_valid("[mypy]\nfiles = src/{a,b}.py, !src/generated", true);
+        let negated: Vec<_> = opts
+            .settings
+            .files_or_directories_to_check
+            .iter()
+            .map(|glob| glob.is_negated())
+            .collect();
+        assert_eq!(negated, [false, true]);
+        let err = project_options_err("[tool.mypy]\nfiles = [\"!src/generated\"]", false);
+        assert_eq!(
+            err.to_string(),
+            r#"Expected the first files pattern not to be negated, got "!src/generated""#
+        );
+    }
+
+    #[test]
+    fn test_unknown_options() {
+        let code = "\
+            [mypy]\n\
+            disallow_untyed_defs = True\n\
+            no_warn_unreachabel = True\n\
+            something_else = 1\n\
+            [mypy-foo.*]\n\
+            ignore_erors = True
+        ";
+        let opts = project_options_valid(code, true);
+        let unknown: Vec<_> = opts
+            .settings
+            .unknown_options
+            .iter()
+            .map(|unknown| unknown.message())
+            .collect();
+        assert_eq!(
+            unknown,
+            [
+                "Unrecognized option: disallow_untyed_defs = True \
+                 (did you mean \"disallow_untyped_defs\"?)",
+                "Unrecognized option: no_warn_unreachabel = True \
+                 (did you mean \"no_warn_unreachable\"?)",
+                "Unrecognized option: something_else = 1",
+                "Unrecognized option: ignore_erors = True (did you mean \"ignore_errors\"?)",
+            ]
+        );
+        let sections: Vec<_> = opts
+            .settings
+            .unknown_options
+            .iter()
+            .map(|unknown| unknown.section.as_deref())
+            .collect();
+        assert_eq!(
+            sections,
+            [Some("mypy"), Some("mypy"), Some("mypy"), Some("mypy-foo.*")]
+        );
+
+        let code = "\
+            [tool.mypy]\n\
+            strict_optinal = true\n\
+            [[tool.mypy.overrides]]\n\
+            module = 'foo'\n\
+            ignore_erors = true
+        ";
+        let opts = project_options_valid(code, false);
+        let sections: Vec<_> = opts
+            .settings
+            .unknown_options
+            .iter()
+            .map(|unknown| unknown.section.as_deref())
+            .collect();
+        assert_eq!(sections, [Some("tool.mypy"), Some("tool.mypy.overrides")]);
+
+        // Zuban configs do not allow unknown options.
+        let err = project_options_err("[tool.zuban]\ncheck_untyped_def = true", false);
+        assert_eq!(
+            err.to_string(),
+            "Unrecognized option: check_untyped_def = true \
+             (did you mean \"check_untyped_defs\"?)"
+        );
+    }
+
+    #[test]
+    fn test_ignored_options() {
+        let code = "\
+            [mypy]\n\
+            namespace_packages = True\n\
+            warn_incomplete_stub = True\n\
+            show_absolute_path = True\n\
+            install_types = True\n\
+            non_interactive = True\n\
+            untyped_calls_exclude = foo\n\
+            scripts_are_modules = True\n\
+            no_site_packages = True\n\
+            color_output = False\n\
+            [mypy-foo]\n\
+            warn_incomplete_stub = True
+        ";
+        let opts = project_options_valid(code, true);
+        assert_eq!(opts.settings.unknown_options, []);
+
+        // Ignored options are accepted in Zuban configs and in inline configuration as well.
+        project_options_valid("[tool.zuban]\nnamespace_packages = true", false);
+        let mut flags = TypeCheckerFlags::default();
+        set_flag(
+            &mut flags,
+            "namespace_packages",
+            IniOrTomlValue::Ini("True"),
+            true,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_known_options_are_recognized() {
+        let mut diagnostic_config = DiagnosticConfig::default();
+        let local_fs = LocalFS::without_watcher();
+        let project_dir = local_fs.unchecked_abs_path("/foo");
+        for option in KNOWN_OPTIONS.iter().chain(IGNORED_OPTIONS) {
+            let mut settings = Settings::default();
+            let result = apply_from_base_config(
+                &local_fs,
+                &project_dir,
+                None,
+                &mut settings,
+                &mut TypeCheckerFlags::default(),
+                &mut diagnostic_config,
+                option,
+                IniOrTomlValue::Ini("1"),
+                false,
+            );
+            // Invalid values are fine, as long as the option is known.
+            assert_eq!(settings.unknown_options, [], "{option}: {result:?}");
+        }
+    }
+
+    #[test]
+    fn test_exclude_patterns() {
+        let code = "\
+            [tool.zuban]\n\
+            exclude_patterns = ['build/', '**/generated/*.py', '!build/keep.py', '/out']\n\
+            respect_gitignore = false
+        ";
+        let opts = project_options_valid(code, false);
+        assert!(!opts.settings.exclude_gitignore);
+        let excluded = |path, is_dir| opts.flags.exclude_patterns.is_path_ignored(path, is_dir);
+        assert!(excluded("build", true));
+        assert!(excluded("pkg/build", true));
+        assert!(!excluded("build", false));
+        assert!(!excluded("build/keep.py", false));
+        assert!(excluded("a/b/generated/foo.py", false));
+        assert!(!excluded("a/b/generated/foo.pyi", false));
+        assert!(excluded("out", false));
+        assert!(!excluded("pkg/out", false));
+
+        let opts = project_options_valid("[mypy]\nexclude_patterns = build/, dist/", true);
+        assert_eq!(opts.flags.exclude_patterns.patterns(), ["build/", "dist/"]);
+
+        let err = project_options_err("[tool.zuban]\nexclude_patterns = 'a{b'", false);
+        assert!(
+            err.to_string().starts_with("Invalid exclude pattern"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/user".to_string()),
+            "VENV" => Some("/venv".to_string()),
+            _ => None,
+        };
+        let expand = |s| expand_path_with(Some("/project"), s, lookup).into_owned();
+        assert_eq!(expand("src"), "src");
+        assert_eq!(expand("~/stubs"), "/home/user/stubs");
+        assert_eq!(expand("~user/stubs"), "~user/stubs");
+        assert_eq!(expand("$MYPY_CONFIG_FILE_DIR/stubs"), "/project/stubs");
+        assert_eq!(expand("${VENV}/lib:$VENV"), "/venv/lib:/venv");
+        assert_eq!(
+            expand("$UNKNOWN/${UNKNOWN}/$/${VENV"),
+            "$UNKNOWN/${UNKNOWN}/$/${VENV"
+        );
+        assert_eq!(expand(r"^build/.*\.py$"), r"^build/.*\.py$");
+
+        let opts = project_options_valid(
+            "[mypy]\nexclude = $MYPY_CONFIG_FILE_DIR/build/\ncache_dir = $MYPY_CONFIG_FILE_DIR/c",
+            true,
+        );
+        assert_eq!(
+            opts.flags.excludes[0].regex_str,
+            "$MYPY_CONFIG_FILE_DIR/build/"
+        );
+        assert_eq!(
+            opts.settings
+                .cache_dir
+                .as_deref()
+                .map(|p| p.as_ref().to_str().unwrap()),
+            Some("/c")
+        );
+    }
+
+    #[test]
+    fn test_django_settings_module() {
+        let code = "[mypy]\n[mypy.plugins.django-stubs]\ndjango_settings_module = \"app.settings\"";
+        let opts = project_options_valid(code, true);
+        assert_eq!(
+            opts.settings.django_settings_module.unwrap(),
+            "app.settings"
+        );
+
+        let code = "[tool.mypy]\n[tool.django-stubs]\ndjango_settings_module = 'app.settings'";
+        let opts = project_options_valid(code, false);
+        assert_eq!(
+            opts.settings.django_settings_module.unwrap(),
+            "app.settings"
+        );
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let code = "[tool.zuban]\n[[tool.zuban.overrides]]\nmodule = ['foo.*']\nfoo = true";
+        let err = project_options_err(code, false);
+        assert_eq!(
+            err.to_string(),
+            "Invalid override for module foo.*: Unrecognized option: foo = true"
+        );
+        // Mypy ignores unknown options in overrides
+        let code = "[tool.mypy]\n[[tool.mypy.overrides]]\nmodule = ['foo.*']\nfoo = true";
+        assert_eq!(project_options_valid(code, false).overrides.len(), 1);
+
+        let code = "[mypy]\n[mypy-foo.bar]\ndisallow_any_generics = what";
+        let err = project_options_err(code, true);
+        assert_eq!(
+            err.to_string(),
+            "Invalid override for module foo.bar: Expected bool, got \"what\""
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let code = "[tool.zuban]\nwarn_return_any = false\nprofile = 'strict'";
+        let opts = project_options_valid(code, false);
+        assert!(opts.flags.disallow_untyped_defs);
+        assert!(!opts.flags.warn_return_any);
+
+        let code = "[tool.zuban]\nprofile = 'lenient'";
+        let opts = project_options_valid(code, false);
+        assert!(opts.flags.ignore_missing_imports);
+        assert!(!opts.flags.check_untyped_defs);
+
+        let code = "\
+            [tool.zuban]\n\
+            profile = 'team'\n\
+            [tool.zuban.profiles.team]\n\
+            profile = 'strict'\n\
+            disallow_any_generics = false\n\
+        ";
+        let opts = project_options_valid(code, false);
+        assert!(opts.flags.disallow_untyped_defs);
+        assert!(!opts.flags.disallow_any_generics);
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        let err = project_options_err("[tool.zuban]\nprofile = 'foo'", false);
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile \"foo\", expected one of strict, standard, lenient or a profile defined in tool.zuban.profiles"
+        );
+        let code = "[tool.zuban]\nprofile = 'a'\n[tool.zuban.profiles.a]\nprofile = 'a'";
+        let err = project_options_err(code, false);
+        assert_eq!(err.to_string(), "The profile \"a\" is based on itself");
+        let code = "[tool.zuban.profiles.strict]\nstrict = false";
+        let err = project_options_err(code, false);
+        assert_eq!(
+            err.to_string(),
+            "The builtin profile \"strict\" cannot be redefined in tool.zuban.profiles"
+        );
+    }
+
+    #[test]
+    fn test_editor_settings() {
+        let local_fs = LocalFS::without_watcher();
+        let project_dir = local_fs.unchecked_abs_path("/foo");
+        let mut opts = project_options_valid("[mypy]\nwarn_unreachable = True", true);
+        opts.apply_editor_settings(
+            &local_fs,
+            &project_dir,
+            [("strict", "true"), ("python_version", "3.12")],
+        )
+        .unwrap();
+        assert!(opts.flags.warn_unreachable);
+        assert!(opts.flags.disallow_untyped_defs);
+        assert_eq!(opts.settings.python_version.unwrap().minor, 12);
+
+        let err = opts
+            .apply_editor_settings(&local_fs, &project_dir, [("strict", "maybe")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Invalid editor setting strict: Expected bool, got "maybe""#
+        );
+        let err = opts
+            .apply_editor_settings(&local_fs, &project_dir, [("foo", "1")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid editor setting foo: Unrecognized option: foo = 1"
+        );
+    }
+
+    fn pyright_options(config: &serde_json::Value) -> anyhow::Result<ProjectOptions> {
+        let local_fs = LocalFS::without_watcher();
+        let project_dir = local_fs.unchecked_abs_path("/foo");
+        let config_path = local_fs.unchecked_abs_path("/foo/pyrightconfig.json");
+        pyright::pyright_project_options(&local_fs, &project_dir, &config_path, config, None)
+    }
+
+    #[test]
+    fn test_pyright_config() {
+        let code = r#"{
+            // Comments and trailing commas are allowed
+            "exclude": ["**/generated", "build/*.py",],
+            "strict": ["src/pkg/core", "tests/conftest.py"],
+            /* A block comment with "quotes" */
+            "pythonVersion": "3.11",
+            "pythonPlatform": "Windows",
+            "typeCheckingMode": "basic",
+            "reportMissingImports": "warning",
+            "reportUnnecessaryTypeIgnoreComment": true,
+            "reportRedeclaration": "none",
+            "reportSomethingNew": "error",
+            "url": "http://example.com",
+        }"#;
+        let config = pyright::parse_pyright_json(code).unwrap();
+        assert_eq!(config["url"], "http://example.com");
+        let opts = pyright_options(&config).unwrap();
+        assert_eq!(
+            opts.settings.python_version,
+            Some(PythonVersion::new(3, 11))
+        );
+        assert_eq!(opts.settings.platform.as_deref(), Some("win32"));
+        assert!(!opts.flags.warn_return_any);
+
+        let excluded = |path| opts.flags.excludes.iter().any(|e| e.regex.is_match(path));
+        assert!(excluded("generated"));
+        assert!(excluded("a/b/generated/foo.py"));
+        assert!(excluded("build/setup.py"));
+        assert!(!excluded("build/nested/setup.py"));
+        assert!(!excluded("generated_foo.py"));
+
+        let overrides: Vec<_> = opts
+            .overrides
+            .iter()
+            .map(|o| o.module.to_string())
+            .collect();
+        assert_eq!(overrides, ["pkg.core.*", "tests.conftest"]);
+        let mut flags = opts.flags.clone();
+        opts.overrides[0].apply_to_flags(&mut flags).unwrap();
+        assert!(flags.disallow_untyped_defs);
+
+        assert_eq!(
+            opts.flags.error_code_severities,
+            [
+                ("import-not-found".to_string(), ErrorCodeSeverity::Warning),
+                ("no-redef".to_string(), ErrorCodeSeverity::Off),
+                ("unused-ignore".to_string(), ErrorCodeSeverity::Error),
+            ]
+        );
+        assert!(
+            opts.flags
+                .enabled_error_codes
+                .contains(&"unused-ignore".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pyright_config_invalid() {
+        let err = |code| {
+            let config = pyright::parse_pyright_json(code).unwrap();
+            pyright_options(&config).unwrap_err().to_string()
+        };
+        assert_eq!(
+            err(r#"{"typeCheckingMode": "loose"}"#),
+            "Expected typeCheckingMode to be one of off, basic, standard or strict, found \"loose\""
+        );
+        assert_eq!(
+            err(r#"{"reportMissingImports": "fatal"}"#),
+            "Unknown severity fatal for reportMissingImports"
+        );
+        assert_eq!(
+            err(r#"{"include": "src"}"#),
+            "Expected include to be a list of strings, found \"src\""
+        );
+    }
+
+    #[test]
+    fn test_pyright_pyproject_toml() {
+        let document: DocumentMut =
+            "[tool.pyright]\ntypeCheckingMode = 'strict'\nexclude = ['out']"
+                .parse()
+                .unwrap();
+        let config = pyright::toml_to_json(&document["tool"]["pyright"]);
+        let opts = pyright_options(&config).unwrap();
+        assert!(opts.flags.disallow_untyped_defs);
+        assert_eq!(opts.flags.excludes[0].regex_str, "^out(/|$)");
+    }
 }
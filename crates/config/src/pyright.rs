@@ -0,0 +1,323 @@
+//! Support for the configuration of Pyright in `pyrightconfig.json` or `[tool.pyright]`, so
+//! projects that use Pyright can be checked without any configuration changes.
+//!
+//! Only the options that have an equivalent in Zuban are mapped, all others are ignored:
+//!
+//! - `include` and `exclude` are the checked files and the excluded globs.
+//! - `strict` and `ignore` are paths that are checked strictly, respectively not reported. They
+//!   are mapped to per-module overrides.
+//! - `typeCheckingMode` maps `strict` to the strict flags and `basic` to the lenient profile.
+//!   `off` does not report any type errors.
+//! - `pythonVersion`, `pythonPlatform`, `extraPaths`, `stubPath`, `typeshedPath`, `venvPath` and
+//!   `venv` configure the environment.
+//! - `reportXxx` rules set the severity of the corresponding Mypy error codes.
+
+use std::str::FromStr as _;
+
+use anyhow::bail;
+use serde_json::Value as JsonValue;
+use toml_edit::{Item, Value as TomlValue};
+use vfs::{AbsPath, VfsHandler};
+
+use crate::{
+    ErrorCodeSeverity, ExcludeRegex, Mode, OverrideConfig, OverrideIniOrTomlValue, ProjectOptions,
+    PythonVersion, order_overrides_for_priority, to_normalized_path,
+};
+
+pub(crate) const PYRIGHT_CONFIG_NAME: &str = "pyrightconfig.json";
+
+/// Pyright rules and the Mypy error codes that report the same issues.
+const RULES_TO_ERROR_CODES: [(&str, &str); 30] = [
+    ("reportAbstractUsage", "abstract"),
+    ("reportArgumentType", "arg-type"),
+    ("reportAssignmentType", "assignment"),
+    ("reportAttributeAccessIssue", "attr-defined"),
+    ("reportCallIssue", "call-arg"),
+    ("reportDeprecated", "deprecated"),
+    ("reportGeneralTypeIssues", "misc"),
+    ("reportImplicitOverride", "explicit-override"),
+    ("reportIncompatibleMethodOverride", "override"),
+    ("reportIndexIssue", "index"),
+    ("reportInvalidTypeForm", "valid-type"),
+    ("reportMatchNotExhaustive", "exhaustive-match"),
+    ("reportMissingImports", "import-not-found"),
+    ("reportMissingTypeStubs", "import-untyped"),
+    ("reportNoOverloadImplementation", "no-overload-impl"),
+    ("reportOperatorIssue", "operator"),
+    ("reportOptionalMemberAccess", "union-attr"),
+    ("reportPossiblyUnbound", "possibly-undefined"),
+    ("reportRedeclaration", "no-redef"),
+    ("reportReturnType", "return-value"),
+    ("reportTypedDictNotRequiredAccess", "typeddict-item"),
+    ("reportUndefinedVariable", "name-defined"),
+    ("reportUnhashable", "unhashable"),
+    ("reportUnnecessaryCast", "redundant-cast"),
+    ("reportUnnecessaryComparison", "comparison-overlap"),
+    ("reportUnnecessaryTypeIgnoreComment", "unused-ignore"),
+    ("reportUnreachable", "unreachable"),
+    ("reportUntypedFunctionDecorator", "untyped-decorator"),
+    ("reportUnusedCoroutine", "unused-coroutine"),
+    ("reportUnusedExpression", "unused-expr"),
+];
+
+/// Pyright configs are JSON with comments and trailing commas.
+pub(crate) fn parse_pyright_json(code: &str) -> anyhow::Result<JsonValue> {
+    let mut json = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            json.push(c);
+            match c {
+                '\\' => json.extend(chars.next()),
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                json.push(c)
+            }
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (']' | '}', _) => {
+                let without_whitespace = json.trim_end().len();
+                if json[..without_whitespace].ends_with(',') {
+                    json.truncate(without_whitespace - 1);
+                }
+                json.push(c)
+            }
+            _ => json.push(c),
+        }
+    }
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// `[tool.pyright]` is mapped to the same structure as `pyrightconfig.json`.
+pub(crate) fn toml_to_json(item: &Item) -> JsonValue {
+    fn value_to_json(value: &TomlValue) -> JsonValue {
+        match value {
+            TomlValue::String(s) => JsonValue::String(s.value().clone()),
+            TomlValue::Integer(i) => (*i.value()).into(),
+            TomlValue::Float(f) => (*f.value()).into(),
+            TomlValue::Boolean(b) => (*b.value()).into(),
+            TomlValue::Datetime(d) => JsonValue::String(d.value().to_string()),
+            TomlValue::Array(array) => array.iter().map(value_to_json).collect(),
+            TomlValue::InlineTable(table) => table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        }
+    }
+    match item {
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => table
+            .iter()
+            .map(|(key, item)| (key.to_string(), toml_to_json(item)))
+            .collect(),
+        Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| toml_to_json(&Item::Table(table.clone())))
+            .collect(),
+        Item::None => JsonValue::Null,
+    }
+}
+
+pub(crate) fn pyright_project_options(
+    vfs: &dyn VfsHandler,
+    project_dir: &AbsPath,
+    config_file_path: &AbsPath,
+    config: &JsonValue,
+    mode: Option<Mode>,
+) -> anyhow::Result<ProjectOptions> {
+    let Some(config) = config.as_object() else {
+        bail!("Expected the Pyright config in {config_file_path} to be an object");
+    };
+    let mut options = ProjectOptions::default_for_mode(mode.unwrap_or(Mode::Default));
+    let settings = &mut options.settings;
+    let flags = &mut options.flags;
+    let path = |s: &str| to_normalized_path(vfs, project_dir, Some(config_file_path), s);
+    for (key, value) in config {
+        match key.as_str() {
+            "include" => settings.set_files_or_directories_to_check(
+                vfs,
+                project_dir,
+                Some(config_file_path),
+                str_list(key, value)?,
+            )?,
+            "exclude" => {
+                for glob in str_list(key, value)? {
+                    let regex_str = glob_to_regex(&glob);
+                    flags.excludes.push(ExcludeRegex::new(regex_str)?)
+                }
+            }
+            "strict" | "ignore" => {
+                let flag = match key.as_str() {
+                    "strict" => "strict",
+                    _ => "ignore_errors",
+                };
+                for p in str_list(key, value)? {
+                    options.overrides.push(OverrideConfig {
+                        module: path_to_module_pattern(&p).as_str().into(),
+                        config: vec![(flag.into(), OverrideIniOrTomlValue::Toml(true.into()))],
+                        from_zuban: true,
+                    })
+                }
+            }
+            "typeCheckingMode" => match value.as_str() {
+                Some("off") => flags.ignore_errors = true,
+                Some("basic") => flags.enable_lenient_flags(),
+                Some("standard") => (),
+                Some("strict") => flags.enable_all_strict_flags(),
+                _ => bail!(
+                    "Expected typeCheckingMode to be one of off, basic, standard or strict, \
+                     found {value}"
+                ),
+            },
+            "pythonVersion" => {
+                settings.python_version = Some(PythonVersion::from_str(str_value(key, value)?)?)
+            }
+            "pythonPlatform" => {
+                settings.platform = match str_value(key, value)? {
+                    "Linux" => Some("linux".into()),
+                    "Windows" => Some("win32".into()),
+                    "Darwin" => Some("darwin".into()),
+                    // Pyright checks for all platforms, which Zuban doesn't support.
+                    "All" => None,
+                    platform => bail!("Unknown pythonPlatform {platform}"),
+                }
+            }
+            "extraPaths" => {
+                for p in str_list(key, value)? {
+                    settings.mypy_path.push(path(&p))
+                }
+            }
+            "stubPath" => settings.mypy_path.push(path(str_value(key, value)?)),
+            "typeshedPath" => settings.typeshed_path = Some(path(str_value(key, value)?)),
+            // venv is combined with venvPath below.
+            "venvPath" | "venv" => (),
+            _ if key.starts_with("report") => {
+                let Some(code) = RULES_TO_ERROR_CODES
+                    .iter()
+                    .find_map(|(rule, code)| (rule == key).then_some(*code))
+                else {
+                    tracing::info!("Ignored the Pyright rule {key}, it has no Mypy equivalent");
+                    continue;
+                };
+                let severity = match value {
+                    JsonValue::Bool(true) => ErrorCodeSeverity::Error,
+                    JsonValue::Bool(false) => ErrorCodeSeverity::Off,
+                    JsonValue::String(s) => match s.as_str() {
+                        "error" => ErrorCodeSeverity::Error,
+                        "warning" => ErrorCodeSeverity::Warning,
+                        "information" => ErrorCodeSeverity::Note,
+                        "none" => ErrorCodeSeverity::Off,
+                        _ => bail!("Unknown severity {s} for {key}"),
+                    },
+                    _ => bail!("Expected {key} to be a boolean or a string, found {value}"),
+                };
+                if severity != ErrorCodeSeverity::Off {
+                    // Some of the codes like unused-ignore are disabled by default.
+                    flags.disabled_error_codes.retain(|c| c != code);
+                    flags.enabled_error_codes.push(code.to_string());
+                }
+                flags
+                    .error_code_severities
+                    .push((code.to_string(), severity))
+            }
+            _ => tracing::info!("Ignored the Pyright option {key}"),
+        }
+    }
+    if let Some(venv) = config.get("venv") {
+        let venv = str_value("venv", venv)?;
+        let venv_path = match config.get("venvPath") {
+            Some(venv_path) => format!("{}/{venv}", str_value("venvPath", venv_path)?),
+            None => venv.to_string(),
+        };
+        settings.environment = Some(path(&venv_path));
+    }
+    order_overrides_for_priority(&mut options.overrides);
+    options.validate_overrides(config_file_path)?;
+    Ok(options)
+}
+
+fn str_value<'x>(key: &str, value: &'x JsonValue) -> anyhow::Result<&'x str> {
+    match value.as_str() {
+        Some(s) => Ok(s),
+        None => bail!("Expected {key} to be a string, found {value}"),
+    }
+}
+
+fn str_list(key: &str, value: &JsonValue) -> anyhow::Result<Vec<String>> {
+    let Some(list) = value.as_array() else {
+        bail!("Expected {key} to be a list of strings, found {value}");
+    };
+    list.iter()
+        .map(|entry| str_value(key, entry).map(String::from))
+        .collect()
+}
+
+/// Pyright excludes are globs relative to the project directory, which also exclude everything
+/// within matched directories.
+fn glob_to_regex(glob: &str) -> String {
+    let glob = glob
+        .strip_prefix("./")
+        .unwrap_or(glob)
+        .trim_end_matches('/');
+    let mut regex = "^".to_string();
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex += "(.*/)?";
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            regex += ".*";
+            rest = after;
+            continue;
+        }
+        match c {
+            '*' => regex += "[^/]*",
+            '?' => regex += "[^/]",
+            _ => regex += &regex::escape(&c.to_string()),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    regex += "(/|$)";
+    regex
+}
+
+/// Pyright uses paths for `strict` and `ignore`, while overrides use module names. A `src`
+/// directory is assumed to be the root of the modules.
+fn path_to_module_pattern(path: &str) -> String {
+    let path = path
+        .strip_prefix("./")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    let path = path.strip_prefix("src/").unwrap_or(path);
+    let (path, is_file) = match path
+        .strip_suffix(".pyi")
+        .or_else(|| path.strip_suffix(".py"))
+    {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+    let path = path.strip_suffix("/__init__").unwrap_or(path);
+    let module = path.replace('/', ".");
+    match is_file {
+        true => module,
+        false => format!("{module}.*"),
+    }
+}
@@ -1,10 +1,19 @@
-use std::{io::Read, path::Path, sync::Arc};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{DiagnosticConfig, Mode, ProjectOptions};
+use crate::{
+    DiagnosticConfig, Mode, ProjectOptions, PythonVersion,
+    pyright::{PYRIGHT_CONFIG_NAME, parse_pyright_json, pyright_project_options, toml_to_json},
+};
 use toml_edit::DocumentMut;
 use vfs::{AbsPath, VfsHandler};
 
 const PYPROJECT_TOML_NAME: &str = "pyproject.toml";
+/// Zuban's own configs take precedence over all Mypy configs.
+const ZUBAN_CONFIG_NAMES: [&str; 2] = ["zuban.toml", ".zuban.toml"];
 const CONFIG_NAMES: [&str; 4] = [
     // Mypy prioritizes mypy.ini. But since we allow [tool.zuban] entries as well it makes sense
     // to check that first. I doubt many people have both mypy.ini and pyproject.toml configs for
@@ -13,9 +22,6 @@ const CONFIG_NAMES: [&str; 4] = [
     "mypy.ini",
     ".mypy.ini",
     "setup.cfg",
-    // TODO this is currently not implemented
-    //"~/.config/mypy/config",
-    //"~/.mypy.ini",
 ];
 
 pub struct FoundConfig {
@@ -23,31 +29,55 @@ pub struct FoundConfig {
     pub diagnostic_config: DiagnosticConfig,
     pub config_path: Option<Arc<AbsPath>>,
     pub most_probable_base: Arc<AbsPath>,
+    /// How options were derived from files other than the config, e.g. the Python version from
+    /// `requires-python`. They should be shown to the user.
+    pub notes: Vec<String>,
 }
 
+/// The user-level Mypy configs in the order of precedence. They are only used by the CLI and only
+/// if no project config is found.
+pub fn user_config_paths(lookup_env_var: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(config_home) = lookup_env_var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        paths.push(Path::new(&config_home).join("mypy").join("config"))
+    }
+    if let Some(home) = lookup_env_var("HOME")
+        .or_else(|| lookup_env_var("USERPROFILE"))
+        .filter(|dir| !dir.is_empty())
+    {
+        let home = Path::new(&home);
+        paths.push(home.join(".config").join("mypy").join("config"));
+        paths.push(home.join(".mypy.ini"));
+    }
+    paths
+}
+
+/// Finds the config of a language server workspace. The `pyproject.toml` that decides the Python
+/// version is looked up like in [`find_cli_config`].
 pub fn find_workspace_config(
     vfs: &dyn VfsHandler,
     workspace_dir: Arc<AbsPath>,
     on_check_path: impl FnMut(&AbsPath),
-) -> anyhow::Result<ProjectOptions> {
-    let config = find_mypy_config_file_in_dir(vfs, workspace_dir, None, on_check_path)?;
-
-    Ok(match config {
-        Some(config) => config.project_options,
-        None => {
+) -> anyhow::Result<FoundConfig> {
+    let mut found = find_mypy_config_file_in_dir(vfs, workspace_dir.clone(), None, on_check_path)?
+        .unwrap_or_else(|| {
             tracing::info!("No relevant config found");
-            ProjectOptions::default_for_mode(Mode::Default)
-        }
-    })
+            default_config(None, None, workspace_dir)
+        });
+    apply_requires_python(vfs, &mut found, None);
+    Ok(found)
 }
 
 pub fn find_cli_config(
     vfs: &dyn VfsHandler,
     current_dir: Arc<AbsPath>,
     config_file: Option<&Path>,
+    user_config_paths: &[PathBuf],
     mode: Option<Mode>,
 ) -> anyhow::Result<FoundConfig> {
-    if let Some(config_file) = config_file.as_ref() {
+    // An explicit pyproject.toml is used even if it has a different name.
+    let mut explicit_pyproject_toml = None;
+    let mut found = if let Some(config_file) = config_file.as_ref() {
         let Some(config_path) = config_file.as_os_str().to_str() else {
             anyhow::bail!("Expected a valid UTF-8 encoded config path")
         };
@@ -56,29 +86,60 @@ pub fn find_cli_config(
             .map_err(|err| anyhow::anyhow!("Issue while reading {config_path}: {err}"))?;
 
         let most_probable_base = Arc::from(vfs.parent_of_absolute_path(&config_path).unwrap());
+        if config_path.ends_with(PYPROJECT_TOML_NAME) {
+            explicit_pyproject_toml = s.parse().ok().map(|toml| (config_path.clone(), toml));
+        }
         let result = initialize_config(vfs, &current_dir, config_path, s, mode)?;
-        let project_options = result.0.unwrap_or_else(ProjectOptions::mypy_default);
-        Ok(FoundConfig {
-            project_options,
+        FoundConfig {
+            project_options: result.0.unwrap_or_else(ProjectOptions::mypy_default),
             diagnostic_config: result.1,
             config_path: Some(result.2),
             most_probable_base,
-        })
+            notes: vec![],
+        }
     } else {
-        let mut current = current_dir.clone();
-        loop {
-            if let Some(found) = find_mypy_config_file_in_dir(vfs, current.clone(), mode, |_| ())? {
-                return Ok(found);
-            }
-            if let Some(outer) = vfs.parent_of_absolute_path(&current) {
-                current = Arc::from(outer);
-            } else {
-                break;
-            }
+        find_cli_config_in_parents(vfs, current_dir, user_config_paths, mode)?
+    };
+    apply_requires_python(vfs, &mut found, explicit_pyproject_toml);
+    Ok(found)
+}
+
+fn find_cli_config_in_parents(
+    vfs: &dyn VfsHandler,
+    current_dir: Arc<AbsPath>,
+    user_config_paths: &[PathBuf],
+    mode: Option<Mode>,
+) -> anyhow::Result<FoundConfig> {
+    let mut current = current_dir.clone();
+    loop {
+        if let Some(found) = find_mypy_config_file_in_dir(vfs, current.clone(), mode, |_| ())? {
+            return Ok(found);
+        }
+        if let Some(outer) = vfs.parent_of_absolute_path(&current) {
+            current = Arc::from(outer);
+        } else {
+            break;
         }
-        tracing::info!("No relevant config found");
-        Ok(default_config(mode, None, current_dir))
     }
+    for path in user_config_paths {
+        let (Some(path), Ok(content)) = (path.to_str(), std::fs::read_to_string(path)) else {
+            continue;
+        };
+        let config_path = vfs.absolute_path(&current_dir, path);
+        tracing::info!("User config found: {config_path}");
+        // Unlike the configs of a project, a user config is used even without a [mypy]
+        // section and relative paths are relative to the current directory.
+        let result = initialize_config(vfs, &current_dir, config_path, content, mode)?;
+        return Ok(FoundConfig {
+            project_options: result.0.unwrap_or_else(ProjectOptions::mypy_default),
+            diagnostic_config: result.1,
+            config_path: Some(result.2),
+            most_probable_base: current_dir,
+            notes: vec![],
+        });
+    }
+    tracing::info!("No relevant config found");
+    Ok(default_config(mode, None, current_dir))
 }
 
 fn initialize_config(
@@ -90,7 +151,29 @@ fn initialize_config(
 ) -> anyhow::Result<(Option<ProjectOptions>, DiagnosticConfig, Arc<AbsPath>)> {
     let _p = tracing::info_span!("config_finder").entered();
     let mut diagnostic_config = DiagnosticConfig::default();
-    let options = if config_path.ends_with(".toml") {
+    let options = if config_path.ends_with(".json") {
+        let config = parse_pyright_json(&content)
+            .map_err(|err| anyhow::anyhow!("Issue while parsing {config_path}: {err}"))?;
+        Some(pyright_project_options(
+            vfs,
+            in_dir,
+            &config_path,
+            &config,
+            mode,
+        )?)
+    } else if AsRef::<Path>::as_ref(&*config_path)
+        .file_name()
+        .is_some_and(|name| ZUBAN_CONFIG_NAMES.iter().any(|n| name == *n))
+    {
+        Some(ProjectOptions::from_zuban_toml(
+            vfs,
+            in_dir,
+            &config_path,
+            &content,
+            &mut diagnostic_config,
+            mode,
+        )?)
+    } else if config_path.ends_with(".toml") {
         ProjectOptions::from_pyproject_toml_only(
             vfs,
             in_dir,
@@ -111,6 +194,22 @@ fn find_mypy_config_file_in_dir(
     mode: Option<Mode>,
     mut on_check_path: impl FnMut(&AbsPath),
 ) -> anyhow::Result<Option<FoundConfig>> {
+    for config_name in ZUBAN_CONFIG_NAMES {
+        let path = vfs.join(&dir, config_name);
+        on_check_path(&path);
+        if let Ok(content) = std::fs::read_to_string(path.as_ref()) {
+            let config_path = vfs.absolute_path(&dir, config_name);
+            tracing::info!("Zuban config found: {config_path}");
+            let result = initialize_config(vfs, &dir, config_path, content, mode)?;
+            return Ok(Some(FoundConfig {
+                project_options: result.0.unwrap(),
+                diagnostic_config: result.1,
+                config_path: Some(result.2),
+                most_probable_base: dir,
+                notes: vec![],
+            }));
+        }
+    }
     let mut end_result = None;
     let mut pyproject_toml: Option<DocumentMut> = None;
     for config_name in CONFIG_NAMES.iter() {
@@ -140,6 +239,7 @@ fn find_mypy_config_file_in_dir(
                         diagnostic_config,
                         config_path: Some(config_path),
                         most_probable_base: dir.clone(),
+                        notes: vec![],
                     });
                     break;
                 }
@@ -157,13 +257,48 @@ fn find_mypy_config_file_in_dir(
                         diagnostic_config: result.1,
                         config_path: Some(result.2),
                         most_probable_base: dir.clone(),
+                        notes: vec![],
                     });
                     break;
                 }
             };
         }
     }
-    if let Some(pyproject_toml) = pyproject_toml
+    if end_result.is_none() {
+        // Pyright configs are only used if there is no Mypy config, so that Pyright users can use
+        // Zuban without changing their config.
+        let path = vfs.join(&dir, PYRIGHT_CONFIG_NAME);
+        on_check_path(&path);
+        if let Ok(content) = std::fs::read_to_string(path.as_ref()) {
+            let config_path = vfs.absolute_path(&dir, PYRIGHT_CONFIG_NAME);
+            tracing::info!("Pyright config found: {config_path}");
+            let result = initialize_config(vfs, &dir, config_path, content, mode)?;
+            end_result = Some(FoundConfig {
+                project_options: result.0.unwrap(),
+                diagnostic_config: result.1,
+                config_path: Some(result.2),
+                most_probable_base: dir.clone(),
+                notes: vec![],
+            });
+        } else if let Some(config) = pyproject_toml
+            .as_ref()
+            .and_then(|toml| toml.get("tool"))
+            .and_then(|item| item.get("pyright"))
+        {
+            let config_path = vfs.absolute_path(&dir, PYPROJECT_TOML_NAME);
+            tracing::info!("[tool.pyright] found in {config_path}");
+            let project_options =
+                pyright_project_options(vfs, &dir, &config_path, &toml_to_json(config), mode)?;
+            end_result = Some(FoundConfig {
+                project_options,
+                diagnostic_config: DiagnosticConfig::default(),
+                config_path: Some(config_path),
+                most_probable_base: dir.clone(),
+                notes: vec![],
+            });
+        }
+    }
+    if let Some(pyproject_toml) = &pyproject_toml
         && let Some(config) = pyproject_toml
             .get("tool")
             .and_then(|item| item.get("zuban"))
@@ -188,6 +323,81 @@ fn find_mypy_config_file_in_dir(
     Ok(end_result)
 }
 
+fn read_pyproject_toml(vfs: &dyn VfsHandler, dir: &AbsPath) -> Option<(Arc<AbsPath>, DocumentMut)> {
+    let path = vfs.join(dir, PYPROJECT_TOML_NAME);
+    let content = std::fs::read_to_string(path.as_ref()).ok()?;
+    let toml = content
+        .parse()
+        .inspect_err(|err| tracing::warn!("Issue while parsing {path}: {err}"))
+        .ok()?;
+    Some((path, toml))
+}
+
+/// The `pyproject.toml` in the directory or otherwise the closest one in its parents, which is
+/// also used if it does not configure a type checker.
+fn closest_pyproject_toml(
+    vfs: &dyn VfsHandler,
+    dir: Arc<AbsPath>,
+) -> Option<(Arc<AbsPath>, DocumentMut)> {
+    let mut current = dir;
+    loop {
+        if let Some(found) = read_pyproject_toml(vfs, &current) {
+            return Some(found);
+        }
+        current = Arc::from(vfs.parent_of_absolute_path(&current)?);
+    }
+}
+
+/// Without an explicit Python version, the minimum version of `[project] requires-python` is
+/// checked, since newer features would break for users of that version. The `pyproject.toml` is
+/// the closest one to the directory of the config, so the CLI and the language server agree.
+fn apply_requires_python(
+    vfs: &dyn VfsHandler,
+    found: &mut FoundConfig,
+    pyproject_toml: Option<(Arc<AbsPath>, DocumentMut)>,
+) {
+    let settings = &mut found.project_options.settings;
+    if settings.python_version.is_some() || !settings.python_versions.is_empty() {
+        return;
+    }
+    let Some((path, pyproject_toml)) =
+        pyproject_toml.or_else(|| closest_pyproject_toml(vfs, found.most_probable_base.clone()))
+    else {
+        return;
+    };
+    let Some(specifier) = pyproject_toml
+        .get("project")
+        .and_then(|project| project.get("requires-python"))
+        .and_then(|item| item.as_str())
+    else {
+        return;
+    };
+    if let Some(version) = minimum_python_version(specifier) {
+        let note =
+            format!("Using Python {version}, because of requires-python = {specifier:?} in {path}");
+        tracing::info!("{note}");
+        found.notes.push(note);
+        settings.python_version = Some(version);
+    }
+}
+
+/// The minimum version of a specifier like `>=3.9,<4`. Upper bounds and exclusions are ignored.
+fn minimum_python_version(specifier: &str) -> Option<PythonVersion> {
+    specifier
+        .split(',')
+        .filter_map(|clause| {
+            let clause = clause.trim();
+            let version = [">=", "~=", "==", ">"]
+                .iter()
+                .find_map(|operator| clause.strip_prefix(operator))?;
+            let mut parts = version.trim().split('.');
+            let major = parts.next()?.parse().ok()?;
+            let minor = parts.next()?.parse().ok()?;
+            Some(PythonVersion::new(major, minor))
+        })
+        .reduce(|a, b| if b > a { b } else { a })
+}
+
 fn default_config(
     mode: Option<Mode>,
     config_path: Option<Arc<AbsPath>>,
@@ -198,5 +408,6 @@ fn default_config(
         diagnostic_config: DiagnosticConfig::default(),
         config_path,
         most_probable_base: dir,
+        notes: vec![],
     }
 }
@@ -799,6 +799,11 @@ macro_rules! create_grammar {
                 self.internal_tree.nodes.len()
             }
 
+            /// The memory of the nodes, without the code.
+            pub fn nodes_memory_size(&self) -> usize {
+                self.internal_tree.nodes.len() * std::mem::size_of::<$crate::InternalNode>()
+            }
+
             pub fn nodes(&self) -> impl Iterator<Item=$Node<'_>> {
                 self.internal_tree.nodes.iter().enumerate().map(
                     |(index, internal_node)| self.node(index as $crate::NodeIndex, internal_node)
@@ -1,6 +1,9 @@
-use parsa_python::CodeIndex;
+use parsa_python::{CodeIndex, NonterminalType::*, PyNodeType::Nonterminal};
 
-use crate::{Tree, TypeIgnoreComment};
+use crate::{
+    Annotation, Assignment, Expression, FunctionDef, MatchStmt, ReturnStmt, StarExpressions, Tree,
+    TypeIgnoreComment,
+};
 
 pub struct TypeIgnoreInsertion<'tree> {
     pub insertion_index: CodeIndex,
@@ -22,6 +25,16 @@ impl TypeIgnoreInsertion<'_> {
     }
 }
 
+/// Where `case` blocks can be appended to a match statement.
+pub struct MatchCasesInsertion<'tree> {
+    pub insertion_index: CodeIndex,
+    /// Whether the match statement is at the end of a file without a trailing newline.
+    pub needs_newline: bool,
+    pub case_indentation: &'tree str,
+    pub body_indentation: String,
+    pub subject: &'tree str,
+}
+
 impl Tree {
     /// Checks where we can insert `type: ignore[<code>]` and `zuban: ignore[<code>]`
     pub fn insertion_point_for_type_ignore(
@@ -65,4 +78,154 @@ impl Tree {
             }
         }
     }
+
+    /// The annotation of the parameter default or the annotated assignment at `position`.
+    pub fn annotation_of_default_or_assignment(
+        &self,
+        position: CodeIndex,
+    ) -> Option<Expression<'_>> {
+        let node = self.0.leaf_by_position(position).parent_until(&[
+            Nonterminal(param_with_default),
+            Nonterminal(param_maybe_default),
+            Nonterminal(assignment),
+            Nonterminal(lambda),
+            Nonterminal(function_def),
+            Nonterminal(class_def),
+        ])?;
+        if node.is_type(Nonterminal(assignment)) {
+            Some(Assignment::new(node).maybe_annotation()?.expression())
+        } else if node.is_type(Nonterminal(param_with_default))
+            || node.is_type(Nonterminal(param_maybe_default))
+        {
+            let annot = node.nth_child(1);
+            annot
+                .is_type(Nonterminal(annotation))
+                .then(|| Annotation::new(annot).expression())
+        } else {
+            None
+        }
+    }
+
+    /// The return annotation of the function that contains `position`.
+    pub fn return_annotation_of_function_at(&self, position: CodeIndex) -> Option<Expression<'_>> {
+        let node = self.0.leaf_by_position(position).parent_until(&[
+            Nonterminal(function_def),
+            Nonterminal(lambda),
+            Nonterminal(class_def),
+        ])?;
+        if !node.is_type(Nonterminal(function_def)) {
+            return None;
+        }
+        Some(FunctionDef::new(node).return_annotation()?.expression())
+    }
+
+    /// The value of the return statement at `position` and the function it belongs to.
+    pub fn return_value_at(
+        &self,
+        position: CodeIndex,
+    ) -> Option<(FunctionDef<'_>, StarExpressions<'_>)> {
+        let node = self.0.leaf_by_position(position).parent_until(&[
+            Nonterminal(return_stmt),
+            Nonterminal(lambda),
+            Nonterminal(function_def),
+            Nonterminal(class_def),
+        ])?;
+        if !node.is_type(Nonterminal(return_stmt)) {
+            return None;
+        }
+        let func = node.parent_until(&[Nonterminal(function_def)])?;
+        Some((
+            FunctionDef::new(func),
+            ReturnStmt::new(node).star_expressions()?,
+        ))
+    }
+
+    /// Where `await` can be added to the expression between `start` and `end`, which is in front
+    /// of its primary, e.g. `-await f()`. Returns nothing if the expression needs brackets.
+    pub fn await_insertion_point(&self, start: CodeIndex, end: CodeIndex) -> Option<CodeIndex> {
+        let mut node = self.0.leaf_by_position(start);
+        while let Some(parent) = node.parent()
+            && parent.start() >= start
+            && parent.end() <= end
+        {
+            node = parent
+        }
+        loop {
+            if node.is_leaf()
+                || node.is_type(Nonterminal(primary))
+                || node.is_type(Nonterminal(atom))
+            {
+                return Some(node.start());
+            }
+            if node.is_type(Nonterminal(factor)) {
+                // The operand of a unary operator like `-`
+                node = node.nth_child(1);
+            } else if node.iter_children().count() == 1 {
+                node = node.nth_child(0);
+            } else {
+                return None;
+            }
+        }
+    }
+
+    /// The insertion point for new `case` blocks of the match statement with the subject at
+    /// `position`. New cases are added after the last case.
+    pub fn insertion_point_for_match_cases(
+        &self,
+        position: CodeIndex,
+    ) -> Option<MatchCasesInsertion<'_>> {
+        let node = self
+            .0
+            .leaf_by_position(position)
+            .parent_until(&[Nonterminal(match_stmt)])?;
+        let match_stmt_ = MatchStmt::new(node);
+        let (subject, case_blocks) = match_stmt_.unpack();
+        let mut case_blocks = case_blocks.peekable();
+        let first_case = *case_blocks.peek()?;
+        let last_case = case_blocks.last()?;
+        let case_indentation = self.indentation_at(first_case.node.start())?;
+        let (_, _, first_block) = first_case.unpack();
+        // For cases like `case 1: pass` we simply indent with four spaces.
+        let body_indentation = first_block
+            .node
+            .iter_children()
+            .find(|n| n.is_type(Nonterminal(stmt)))
+            .and_then(|stmt_node| self.indentation_at(stmt_node.start()))
+            .map(|indentation| indentation.to_string())
+            .unwrap_or_else(|| format!("{case_indentation}    "));
+        let insertion_index = last_case.node.end();
+        Some(MatchCasesInsertion {
+            insertion_index,
+            needs_newline: !self.code()[..insertion_index as usize].ends_with(['\n', '\r']),
+            case_indentation,
+            body_indentation,
+            subject: subject.as_code(),
+        })
+    }
+
+    /// The whitespace before `position`, if there is only whitespace in front of it on its line.
+    fn indentation_at(&self, position: CodeIndex) -> Option<&str> {
+        let before = &self.code()[..position as usize];
+        let line_start = before.rfind(['\n', '\r']).map(|i| i + 1).unwrap_or(0);
+        let indentation = &before[line_start..];
+        indentation
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+            .then_some(indentation)
+    }
+
+    /// `await` is only allowed directly in async functions.
+    pub fn is_in_async_function(&self, position: CodeIndex) -> bool {
+        self.0
+            .leaf_by_position(position)
+            .parent_until(&[
+                Nonterminal(function_def),
+                Nonterminal(lambda),
+                Nonterminal(class_def),
+            ])
+            .is_some_and(|node| {
+                node.is_type(Nonterminal(function_def))
+                    && FunctionDef::new(node).parent().is_async()
+            })
+    }
 }
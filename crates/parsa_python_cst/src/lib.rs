@@ -3,6 +3,7 @@ mod code_actions;
 mod completion;
 mod match_stmt;
 mod ranges;
+mod refactoring;
 mod signatures;
 mod strings;
 
@@ -27,6 +28,7 @@ use parsa_python::{
     PyTree, SearchIterator, SiblingIterator, TerminalType, parse,
 };
 pub use ranges::Range;
+pub use refactoring::{ExpressionExtraction, ExtractableExpression, StatementsExtraction};
 pub use signatures::{SignatureArg, SignatureArgsIterator, SignatureBase};
 pub use strings::PythonString;
 
@@ -48,6 +50,10 @@ impl Tree {
         self.0.length()
     }
 
+    pub fn nodes_memory_size(&self) -> usize {
+        self.0.nodes_memory_size()
+    }
+
     pub fn code(&self) -> &str {
         self.0.as_code()
     }
@@ -56,6 +62,15 @@ impl Tree {
         File::new(self.0.root_node())
     }
 
+    /// Functions that are not nested in other functions, i.e. module level functions and methods,
+    /// in the order of the code.
+    pub fn non_nested_function_defs(&self) -> impl Iterator<Item = FunctionDef<'_>> {
+        self.0
+            .root_node()
+            .search(&[Nonterminal(function_def)], true)
+            .map(FunctionDef::new)
+    }
+
     pub fn maybe_star_expressions(&self) -> Option<StarExpressions<'_>> {
         let mut node = self.0.root_node();
         for (nonterminal, expected_node_count) in [
@@ -160,13 +175,16 @@ impl Tree {
         &self.code()[0..start as usize]
     }
 
-    pub fn mypy_inline_config_directives(&self) -> impl Iterator<Item = (CodeIndex, &str)> {
-        const PREFIX: &str = "# mypy: ";
+    /// Directives like `# mypy: allow-untyped-defs` or the Zuban equivalent
+    /// `# zuban: allow-untyped-defs`.
+    pub fn inline_config_directives(&self) -> impl Iterator<Item = (CodeIndex, &str)> {
+        const PREFIXES: [&str; 2] = ["# mypy: ", "# zuban: "];
         let mut code_index_start = 0;
         self.code().split('\n').filter_map(move |line| {
-            let result = line
-                .strip_prefix(PREFIX)
-                .map(|rest| (code_index_start + PREFIX.len() as CodeIndex, rest));
+            let result = PREFIXES.iter().find_map(|prefix| {
+                line.strip_prefix(prefix)
+                    .map(|rest| (code_index_start + prefix.len() as CodeIndex, rest))
+            });
             code_index_start += line.len() as CodeIndex + 1;
             result
         })
@@ -444,6 +462,25 @@ impl Tree {
                 }
             })
     }
+
+    /// All expressions that are evaluated at runtime. Annotations, type param bounds and defaults
+    /// and the values of `type` statements are not part of it.
+    pub fn runtime_expressions(&self) -> impl Iterator<Item = Expression<'_>> {
+        self.0
+            .nodes()
+            .filter(|n| {
+                n.is_type(Nonterminal(expression))
+                    && n.parent_until(&[
+                        Nonterminal(annotation),
+                        Nonterminal(star_annotation),
+                        Nonterminal(return_annotation),
+                        Nonterminal(type_params),
+                        Nonterminal(type_alias),
+                    ])
+                    .is_none()
+            })
+            .map(Expression::new)
+    }
 }
 
 pub enum TypeIgnoreComment<'db> {
@@ -1286,6 +1323,10 @@ impl<'db> DictStarred<'db> {
 }
 
 impl<'db> Expression<'db> {
+    pub fn parent_scope(&self) -> Scope<'db> {
+        scope_for_node(self.node)
+    }
+
     pub fn unpack(self) -> ExpressionContent<'db> {
         let node = self.node.nth_child(0);
         if node.is_type(Nonterminal(lambda)) {
@@ -2539,6 +2580,25 @@ impl<'db> FunctionDef<'db> {
         self.body().iter_stmt_likes().next()?.node.maybe_string()
     }
 
+    /// Whether the body might change something that is visible outside of the function, i.e. it
+    /// assigns attributes (e.g. `self.x = 1`), yields or uses `global` or `nonlocal`.
+    pub fn body_might_change_outer_scopes(&self) -> bool {
+        self.body()
+            .node
+            .search(
+                &[
+                    Nonterminal(t_primary),
+                    Nonterminal(yield_expr),
+                    Nonterminal(global_stmt),
+                    Nonterminal(nonlocal_stmt),
+                ],
+                false,
+            )
+            .any(|n| {
+                !n.is_type(Nonterminal(t_primary)) || n.nth_child(2).is_type(Nonterminal(name_def))
+            })
+    }
+
     pub fn trivial_body_state(&self) -> TrivialBodyState<'_> {
         // In Mypy this is handled in "is_trivial_body"
         let mut stmts = self.body().iter_stmt_likes();
@@ -0,0 +1,453 @@
+use parsa_python::{
+    CodeIndex, NodeIndex,
+    NonterminalType::*,
+    PyNode,
+    PyNodeType::{self, Nonterminal, Terminal},
+    TerminalType,
+};
+
+use crate::{ClassDef, Expression, ExpressionPart, Name, Scope, Tree, completion::scope_for_node};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExtractableExpression<'db> {
+    Expression(Expression<'db>),
+    Part(ExpressionPart<'db>),
+}
+
+impl<'db> ExtractableExpression<'db> {
+    pub fn start(&self) -> CodeIndex {
+        match self {
+            Self::Expression(expr) => expr.start(),
+            Self::Part(part) => part.start(),
+        }
+    }
+
+    pub fn end(&self) -> CodeIndex {
+        match self {
+            Self::Expression(expr) => expr.end(),
+            Self::Part(part) => part.end(),
+        }
+    }
+
+    pub fn as_code(&self) -> &'db str {
+        match self {
+            Self::Expression(expr) => expr.as_code(),
+            Self::Part(part) => part.as_code(),
+        }
+    }
+}
+
+pub struct ExpressionExtraction<'db> {
+    pub expression: ExtractableExpression<'db>,
+    pub scope: Scope<'db>,
+    /// The new variable is inserted before the statement that contains the expression.
+    pub insertion_index: CodeIndex,
+    pub indentation: &'db str,
+}
+
+pub struct StatementsExtraction<'db> {
+    /// The range of the full lines of the statements
+    pub start: CodeIndex,
+    pub end: CodeIndex,
+    pub indentation: &'db str,
+    pub scope: Scope<'db>,
+    pub contains_await: bool,
+    /// The start of the top level statement that contains the statements, which is where a new
+    /// function can be defined.
+    pub top_level_start: CodeIndex,
+    /// Names that are read before they are assigned in the statements and that therefore need
+    /// to be passed to a new function.
+    pub parameters: Vec<Name<'db>>,
+    /// The first reads after the statements of names that are assigned in the statements.
+    pub return_values: Vec<Name<'db>>,
+}
+
+// Inside these, a new variable cannot simply be defined before the statement.
+const NO_EXTRACTION_PARENTS: &[PyNodeType] = &[
+    Nonterminal(lambda),
+    Nonterminal(comprehension),
+    Nonterminal(dict_comprehension),
+    Nonterminal(block),
+    Nonterminal(simple_stmt),
+    Nonterminal(if_stmt),
+    Nonterminal(for_stmt),
+    Nonterminal(with_stmt),
+    Nonterminal(while_stmt),
+    Nonterminal(try_stmt),
+    Nonterminal(match_stmt),
+    Nonterminal(function_def),
+    Nonterminal(class_def),
+    Nonterminal(decorated),
+];
+
+const NESTED_SCOPES: &[PyNodeType] = &[
+    Nonterminal(function_def),
+    Nonterminal(class_def),
+    Nonterminal(lambda),
+];
+
+impl Tree {
+    /// Finds the expression that exactly covers the range (ignoring whitespace) and checks that
+    /// it can be assigned to a variable before the statement it's in.
+    pub fn expression_for_extraction(
+        &self,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> Option<ExpressionExtraction<'_>> {
+        let (start, end) = self.trim_range(start, end)?;
+        let mut node = self.0.leaf_by_position(start);
+        if node.start() != start {
+            return None;
+        }
+        let extracted = loop {
+            node = node.parent()?;
+            if node.start() != start || node.end() > end {
+                return None;
+            }
+            if node.end() == end {
+                if node.is_type(Nonterminal(expression)) {
+                    break ExtractableExpression::Expression(Expression::new(node));
+                }
+                if let Some(part) = ExpressionPart::maybe_new(node) {
+                    break ExtractableExpression::Part(part);
+                }
+            }
+        };
+        let parent = node.parent_until(NO_EXTRACTION_PARENTS)?;
+        let stmt_node = if parent.is_type(Nonterminal(simple_stmt)) {
+            // A simple statement on the same line as its compound statement like `if x: y` is
+            // not supported.
+            let stmt_node = parent.parent().unwrap().parent().unwrap();
+            if !stmt_node.is_type(Nonterminal(stmt)) {
+                return None;
+            }
+            stmt_node
+        } else {
+            // Only the first part of a compound statement is always executed before its body.
+            let first_part_index =
+                if parent.is_type(Nonterminal(if_stmt)) || parent.is_type(Nonterminal(with_stmt)) {
+                    1
+                } else if parent.is_type(Nonterminal(for_stmt)) {
+                    3
+                } else {
+                    return None;
+                };
+            let first_part = parent.nth_child(first_part_index);
+            if first_part.start() > node.start() || node.end() > first_part.end() {
+                return None;
+            }
+            parent.parent_until(&[Nonterminal(stmt)]).unwrap()
+        };
+        let insertion_index = stmt_node.start();
+        Some(ExpressionExtraction {
+            expression: extracted,
+            scope: scope_for_node(node),
+            insertion_index,
+            indentation: self.indentation_of_line(insertion_index),
+        })
+    }
+
+    /// Finds the statements of the same block that are covered by the range. Statements that
+    /// change the control flow of the surrounding code like `return` or `break` are not allowed.
+    pub fn statements_for_extraction(
+        &self,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> Option<StatementsExtraction<'_>> {
+        let (start, end) = self.trim_range(start, end)?;
+        let line_start = self.line_start(start);
+        if !self.code()[line_start as usize..start as usize]
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+        {
+            return None;
+        }
+        let mut first = None;
+        let mut node = self.0.leaf_by_position(start);
+        while let Some(parent) = node.parent() {
+            if parent.start() != start || end_without_whitespace(parent) > end {
+                break;
+            }
+            if parent.is_type(Nonterminal(stmt)) {
+                first = Some(parent);
+            }
+            node = parent;
+        }
+        let first = first?;
+        let mut last = first;
+        while end_without_whitespace(last) < end {
+            last = last.next_sibling()?;
+            if !last.is_type(Nonterminal(stmt)) || end_without_whitespace(last) > end {
+                return None;
+            }
+        }
+        let scope = scope_for_node(first);
+        let scope_node = match scope {
+            Scope::Module => self.0.root_node(),
+            Scope::Function(func) => func.node,
+            Scope::Class(_) | Scope::Lambda(_) => return None,
+        };
+        let mut contains_await = false;
+        let mut current = first;
+        loop {
+            if !is_extractable_stmt(current, &mut contains_await) {
+                return None;
+            }
+            if current.index == last.index {
+                break;
+            }
+            current = current.next_sibling().unwrap();
+        }
+        let end = last.end();
+        let (params, return_values) = self.names_flowing_through(scope, scope_node, start, end);
+        let mut top_level = first;
+        while let Some(parent) = top_level.parent()
+            && !parent.is_type(Nonterminal(file))
+        {
+            top_level = parent;
+        }
+        Some(StatementsExtraction {
+            start: line_start,
+            end,
+            indentation: self.indentation_of_line(start),
+            scope,
+            contains_await,
+            top_level_start: self.line_start(top_level.start()),
+            parameters: params,
+            return_values,
+        })
+    }
+
+    /// Returns the names that are read in the range before they are assigned there and the names
+    /// that are assigned in the range and read after it. For the latter the first read after the
+    /// range is returned.
+    ///
+    /// The new function is defined on the module level, so names of the scope and of enclosing
+    /// functions need to be passed. Nested functions of the scope might read the names assigned in
+    /// the range at any time, so their reads count as reads after the range.
+    fn names_flowing_through<'db>(
+        &'db self,
+        scope: Scope,
+        scope_node: PyNode<'db>,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> (Vec<Name<'db>>, Vec<Name<'db>>) {
+        let mut locals: Vec<&str> = vec![];
+        let mut assigned: Vec<&str> = vec![];
+        let mut read_before_assignment: Vec<Name> = vec![];
+        let mut read_after: Vec<Name> = vec![];
+        // The assignments of the nested scopes, because their reads are only relevant if the
+        // name is not one of their own.
+        let mut nested_assignments: Vec<(NodeIndex, &str)> = vec![];
+        for node in scope_node.search(&[Terminal(TerminalType::Name)], false) {
+            let name = Name::new(node);
+            let parent = node.parent().unwrap();
+            let (is_read, is_assigned) = if parent.is_type(Nonterminal(atom)) {
+                (true, false)
+            } else if parent.is_type(Nonterminal(name_def)) {
+                let definition_scope = definition_scope(parent);
+                if definition_scope.index != scope_node.index {
+                    nested_assignments.push((definition_scope.index, name.as_code()));
+                }
+                (
+                    is_augmented_assignment_target(parent),
+                    definition_scope.index == scope_node.index,
+                )
+            } else {
+                continue;
+            };
+            let code = name.as_code();
+            if name.start() < start || name.end() > end {
+                if is_assigned {
+                    locals.push(code)
+                }
+                let is_read_later = name.start() >= end || nested_scope(node, scope_node).is_some();
+                if is_read && is_read_later && !read_after.iter().any(|n| n.as_code() == code) {
+                    read_after.push(name)
+                }
+            } else {
+                if is_read
+                    && !assigned.contains(&code)
+                    && !read_before_assignment.iter().any(|n| n.as_code() == code)
+                {
+                    read_before_assignment.push(name)
+                }
+                if is_assigned && !assigned.contains(&code) {
+                    assigned.push(code)
+                }
+            }
+        }
+        read_after.retain(|name| {
+            nested_scope(name.node, scope_node)
+                .is_none_or(|nested| !nested_assignments.contains(&(nested.index, name.as_code())))
+        });
+        let enclosing = enclosing_function_names(scope_node);
+        let is_function = matches!(scope, Scope::Function(_));
+        let params = read_before_assignment
+            .into_iter()
+            .filter(|n| {
+                let code = n.as_code();
+                assigned.contains(&code)
+                    || is_function && (locals.contains(&code) || enclosing.contains(&code))
+            })
+            .collect();
+        let return_values = assigned
+            .into_iter()
+            .filter_map(|code| read_after.iter().find(|n| n.as_code() == code).copied())
+            .collect();
+        (params, return_values)
+    }
+
+    /// The position after the last statement of a class body, where new members can be added,
+    /// and the indentation of the body. Returns `None` for bodies on the same line as the class,
+    /// like `class C: pass`.
+    pub fn class_member_insertion_point(&self, class: ClassDef) -> Option<(CodeIndex, &str)> {
+        let mut stmts = class
+            .block()
+            .node
+            .iter_children()
+            .filter(|n| n.is_type(Nonterminal(stmt)));
+        let first = stmts.next()?;
+        let last = stmts.last().unwrap_or(first);
+        Some((last.end(), self.indentation_of_line(first.start())))
+    }
+
+    fn trim_range(&self, start: CodeIndex, end: CodeIndex) -> Option<(CodeIndex, CodeIndex)> {
+        let code = self.code().get(start as usize..end as usize)?;
+        let trimmed_start = code.trim_start();
+        if trimmed_start.is_empty() {
+            return None;
+        }
+        let start = start + (code.len() - trimmed_start.len()) as CodeIndex;
+        let end = start + trimmed_start.trim_end().len() as CodeIndex;
+        Some((start, end))
+    }
+
+    fn line_start(&self, position: CodeIndex) -> CodeIndex {
+        self.code()[..position as usize]
+            .rfind('\n')
+            .map_or(0, |i| i as CodeIndex + 1)
+    }
+
+    fn indentation_of_line(&self, position: CodeIndex) -> &str {
+        let line = &self.code()[self.line_start(position) as usize..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+}
+
+fn end_without_whitespace(node: PyNode) -> CodeIndex {
+    node.start() + node.as_code().trim_end().len() as CodeIndex
+}
+
+/// The scope a name is defined in. The name of a function or class belongs to the scope around it.
+fn definition_scope(name_def_node: PyNode) -> PyNode {
+    let parent = name_def_node.parent().unwrap();
+    let node =
+        if parent.is_type(Nonterminal(function_def)) || parent.is_type(Nonterminal(class_def)) {
+            parent
+        } else {
+            name_def_node
+        };
+    node.parent_until(&[
+        Nonterminal(file),
+        Nonterminal(function_def),
+        Nonterminal(class_def),
+        Nonterminal(lambda),
+    ])
+    .unwrap()
+}
+
+/// The scope directly within the given scope that contains the node, if the node is not directly
+/// part of the given scope.
+fn nested_scope<'db>(node: PyNode<'db>, scope_node: PyNode<'db>) -> Option<PyNode<'db>> {
+    let mut nested = None;
+    let mut current = node;
+    while let Some(parent) = current.parent_until(NESTED_SCOPES) {
+        if parent.index == scope_node.index {
+            return nested;
+        }
+        nested = Some(parent);
+        current = parent;
+    }
+    // The module is not one of the nested scopes, but encloses all of them.
+    if scope_node.is_type(Nonterminal(file)) {
+        return nested;
+    }
+    None
+}
+
+/// The names that are defined in the functions around a scope. Class bodies are skipped, because
+/// their names are not visible in nested scopes.
+fn enclosing_function_names<'db>(scope_node: PyNode<'db>) -> Vec<&'db str> {
+    let mut names = vec![];
+    let mut current = scope_node;
+    while let Some(parent) = current.parent_until(NESTED_SCOPES) {
+        if !parent.is_type(Nonterminal(class_def)) {
+            names.extend(
+                parent
+                    .search(&[Nonterminal(name_def)], false)
+                    .filter(|n| definition_scope(*n).index == parent.index)
+                    .map(|n| n.as_code()),
+            );
+        }
+        current = parent;
+    }
+    names
+}
+
+fn is_augmented_assignment_target(name_def_node: PyNode) -> bool {
+    let target = name_def_node.parent().unwrap();
+    target.is_type(Nonterminal(single_target))
+        && target
+            .next_sibling()
+            .is_some_and(|n| n.is_type(Nonterminal(augassign)))
+}
+
+fn is_extractable_stmt(stmt_node: PyNode, contains_await: &mut bool) -> bool {
+    const FLOW_CONTROL: &[PyNodeType] = &[
+        Nonterminal(return_stmt),
+        Nonterminal(yield_expr),
+        Nonterminal(global_stmt),
+        Nonterminal(nonlocal_stmt),
+        Nonterminal(function_def),
+        Nonterminal(class_def),
+        Nonterminal(lambda),
+    ];
+    if stmt_node
+        .search(FLOW_CONTROL, true)
+        .any(|n| !NESTED_SCOPES.contains(&n.type_()))
+    {
+        return false;
+    }
+    // Breaks and continues are fine if the loop is also extracted.
+    const LOOP_CONTROL: &[PyNodeType] = &[
+        Nonterminal(break_stmt),
+        Nonterminal(continue_stmt),
+        Nonterminal(for_stmt),
+        Nonterminal(while_stmt),
+        Nonterminal(function_def),
+        Nonterminal(class_def),
+        Nonterminal(lambda),
+    ];
+    if stmt_node
+        .search(LOOP_CONTROL, true)
+        .any(|n| n.is_type(Nonterminal(break_stmt)) || n.is_type(Nonterminal(continue_stmt)))
+    {
+        return false;
+    }
+    const AWAIT: &[PyNodeType] = &[
+        Nonterminal(await_primary),
+        Nonterminal(async_stmt),
+        Nonterminal(function_def),
+        Nonterminal(class_def),
+        Nonterminal(lambda),
+    ];
+    if stmt_node.search(AWAIT, true).any(|n| {
+        n.is_type(Nonterminal(await_primary))
+            || n.is_type(Nonterminal(async_stmt))
+                && !n.nth_child(1).is_type(Nonterminal(function_def))
+    }) {
+        *contains_await = true;
+    }
+    true
+}
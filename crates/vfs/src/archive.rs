@@ -0,0 +1,191 @@
+//! Python imports modules from zip archives on the sys path, e.g. from wheels, eggs or from the
+//! zipped standard library of embedded distributions. Like in Python the paths within an archive
+//! are written as if the archive was a directory, e.g. `/lib/python312.zip/json/__init__.py`.
+
+use std::{
+    fs::File,
+    io::Read as _,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+use utils::FastHashMap;
+
+use crate::{FileMetadata, FileType};
+
+const ARCHIVE_EXTENSIONS: [&str; 3] = ["zip", "whl", "egg"];
+
+/// Splits a path into the path of the archive and the path within the archive, which uses `/` as
+/// a separator and is empty for the archive itself.
+pub(crate) fn split_archive_path(path: &Path) -> Option<(&Path, String)> {
+    for archive in path.ancestors() {
+        let is_archive = archive
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if is_archive && archive.is_file() {
+            let inner = path.strip_prefix(archive).ok()?;
+            let inner: Vec<_> = inner
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect::<Option<_>>()?;
+            return Some((archive, inner.join("/")));
+        }
+    }
+    None
+}
+
+struct Archive {
+    modified: Option<SystemTime>,
+    len: u64,
+    // The directories with their entries and whether the entries are directories.
+    dirs: FastHashMap<String, Vec<(Box<str>, bool)>>,
+    // Kept open, so reading entries does not parse the central directory again.
+    zip: Mutex<zip::ZipArchive<File>>,
+}
+
+impl Archive {
+    fn new(path: &Path) -> Result<Self, String> {
+        let metadata = std::fs::metadata(path).map_err(|err| err.to_string())?;
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let archive = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+        let mut dirs: FastHashMap<String, Vec<(Box<str>, bool)>> = FastHashMap::default();
+        dirs.insert(String::new(), vec![]);
+        for name in archive.file_names() {
+            let (name, is_dir) = match name.strip_suffix('/') {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            // Adds the entry and all the directories that contain it.
+            let mut child = (name, is_dir);
+            loop {
+                let (dir, child_name) = child.0.rsplit_once('/').unwrap_or(("", child.0));
+                let entries = dirs.entry(dir.to_owned()).or_default();
+                let is_new = !entries.iter().any(|(n, _)| **n == *child_name);
+                if is_new {
+                    entries.push((child_name.into(), child.1));
+                }
+                if !is_new || dir.is_empty() {
+                    break;
+                }
+                child = (dir, true);
+            }
+            if is_dir {
+                dirs.entry(name.to_owned()).or_default();
+            }
+        }
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            dirs,
+            zip: Mutex::new(archive),
+        })
+    }
+
+    fn is_up_to_date(&self, path: &Path) -> bool {
+        std::fs::metadata(path)
+            .is_ok_and(|m| m.modified().ok() == self.modified && m.len() == self.len)
+    }
+
+    fn entry_is_dir(&self, inner: &str) -> Option<bool> {
+        let (dir, name) = inner.rsplit_once('/').unwrap_or(("", inner));
+        self.dirs
+            .get(dir)?
+            .iter()
+            .find(|(n, _)| **n == *name)
+            .map(|(_, is_dir)| *is_dir)
+    }
+
+    fn with_entry<T>(
+        &self,
+        inner: &str,
+        f: impl FnOnce(zip::read::ZipFile<File>) -> std::io::Result<T>,
+    ) -> Result<T, String> {
+        let mut zip = self.zip.lock().unwrap();
+        let entry = zip.by_name(inner).map_err(|err| err.to_string())?;
+        f(entry).map_err(|err| err.to_string())
+    }
+}
+
+/// The listings of the archives that were read, which are read again once the archive changes.
+#[derive(Default)]
+pub(crate) struct Archives {
+    cache: RwLock<FastHashMap<PathBuf, Arc<Archive>>>,
+}
+
+impl Archives {
+    fn archive(&self, path: &Path) -> Option<Arc<Archive>> {
+        if let Some(archive) = self.cache.read().unwrap().get(path)
+            && archive.is_up_to_date(path)
+        {
+            return Some(archive.clone());
+        }
+        match Archive::new(path) {
+            Ok(archive) => {
+                let archive = Arc::new(archive);
+                self.cache
+                    .write()
+                    .unwrap()
+                    .insert(path.to_owned(), archive.clone());
+                Some(archive)
+            }
+            Err(err) => {
+                tracing::warn!("Tried to read the archive {path:?} but failed: {err}");
+                self.cache.write().unwrap().remove(path);
+                None
+            }
+        }
+    }
+
+    /// Returns the names of the entries of a directory and whether they are directories.
+    pub(crate) fn list_dir(&self, archive: &Path, dir: &str) -> Vec<(Box<str>, bool)> {
+        self.archive(archive)
+            .and_then(|archive| archive.dirs.get(dir).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the entry is a directory or `None` if it does not exist.
+    pub(crate) fn entry_is_dir(&self, archive: &Path, inner: &str) -> Option<bool> {
+        self.archive(archive)?.entry_is_dir(inner)
+    }
+
+    pub(crate) fn entry_metadata(&self, archive_path: &Path, inner: &str) -> Option<FileMetadata> {
+        let archive = self.archive(archive_path)?;
+        let modified = archive.modified;
+        if archive.entry_is_dir(inner)? {
+            return Some(FileMetadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified,
+            });
+        }
+        let len = archive
+            .with_entry(inner, |entry| Ok(entry.size()))
+            .inspect_err(|err| {
+                tracing::warn!(
+                    "Tried to read the size of {inner} in {archive_path:?} but failed: \
+                     {err}"
+                )
+            })
+            .ok()?;
+        Some(FileMetadata {
+            file_type: FileType::File,
+            len,
+            modified,
+        })
+    }
+
+    pub(crate) fn read_file(&self, archive_path: &Path, inner: &str) -> Option<String> {
+        self.archive(archive_path)?
+            .with_entry(inner, |mut entry| {
+                let mut code = String::new();
+                entry.read_to_string(&mut code)?;
+                Ok(code)
+            })
+            .inspect_err(|err| {
+                tracing::warn!("Tried to read {inner} in {archive_path:?} but failed: {err}")
+            })
+            .ok()
+    }
+}
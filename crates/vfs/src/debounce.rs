@@ -0,0 +1,254 @@
+//! Git operations and builds change lots of files at once. The events of such a burst are
+//! collected and merged, so that every path is only invalidated once.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Receiver;
+use notify::{
+    Event, EventKind,
+    event::{CreateKind, Flag, ModifyKind, RemoveKind, RenameMode},
+};
+use utils::FastHashMap;
+
+use crate::NotifyEvent;
+
+/// If this many entries of a directory change at once, the directory is read again instead of
+/// invalidating every entry.
+const RESCAN_THRESHOLD: usize = 100;
+/// Files that keep changing should still be checked at some point.
+const MAX_DEBOUNCE_FACTOR: u32 = 20;
+
+/// Receives the events that follow the given event within the debounce time and merges them.
+///
+/// Every path appears only once in the result, e.g. a file that was created and removed again
+/// only leads to a removal. Events for paths within created or removed directories and events of directories
+/// with lots of changes are replaced by a single event for the directory, which is flagged with
+/// [`Flag::Rescan`]. If an error is received, only the error is returned, because the events
+/// are not reliable anymore.
+pub fn receive_coalesced_events(
+    receiver: &Receiver<NotifyEvent>,
+    first: NotifyEvent,
+    debounce: Duration,
+) -> Vec<NotifyEvent> {
+    let deadline = Instant::now() + debounce * MAX_DEBOUNCE_FACTOR;
+    let mut events = vec![first];
+    loop {
+        let next = if debounce.is_zero() {
+            receiver.try_recv().ok()
+        } else if Instant::now() < deadline {
+            receiver.recv_timeout(debounce).ok()
+        } else {
+            None
+        };
+        let Some(event) = next else { break };
+        events.push(event)
+    }
+    coalesce_events(events, RESCAN_THRESHOLD)
+}
+
+#[derive(Clone, Copy)]
+struct PathChange {
+    existed_before: bool,
+    exists_now: bool,
+    rescan: bool,
+}
+
+impl PathChange {
+    fn kind(self) -> EventKind {
+        // Removals are not dropped even if the path was created in the meantime. A path that is
+        // replaced (e.g. by a rename) might report the removal of the old file after the
+        // creation of the new one. The invalidation checks what actually exists.
+        match (self.existed_before, self.exists_now) {
+            (false, true) => EventKind::Create(CreateKind::Any),
+            (true, true) => EventKind::Modify(ModifyKind::Any),
+            (_, false) => EventKind::Remove(RemoveKind::Any),
+        }
+    }
+}
+
+fn coalesce_events(events: Vec<NotifyEvent>, rescan_threshold: usize) -> Vec<NotifyEvent> {
+    // The order of the paths is kept, since the order of the events might matter.
+    let mut order: Vec<PathBuf> = vec![];
+    let mut changes: FastHashMap<PathBuf, PathChange> = FastHashMap::default();
+    let mut other_events = vec![];
+    for event in events {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => return vec![Err(err)],
+        };
+        if event.need_rescan()
+            || !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            )
+        {
+            other_events.push(Ok(event));
+            continue;
+        }
+        let paths_count = event.paths.len();
+        for (i, path) in event.paths.into_iter().enumerate() {
+            // Whether the path exists after this event, `None` if that is not known.
+            let exists = match event.kind {
+                EventKind::Create(_) => Some(true),
+                EventKind::Remove(_) => Some(false),
+                EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Some(false),
+                EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some(true),
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths_count == 2 => {
+                    Some(i == 1)
+                }
+                _ => None,
+            };
+            match changes.get_mut(&path) {
+                Some(change) => change.exists_now = exists.unwrap_or(true),
+                None => {
+                    changes.insert(
+                        path.clone(),
+                        PathChange {
+                            // Unknown changes are treated like modifications.
+                            existed_before: exists != Some(true),
+                            exists_now: exists.unwrap_or(true),
+                            rescan: false,
+                        },
+                    );
+                    order.push(path);
+                }
+            }
+        }
+    }
+
+    // Invalidating a created or removed directory invalidates everything within it.
+    order.retain(|path| {
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(change) = changes.get_mut(ancestor)
+                && !matches!(change.kind(), EventKind::Modify(_))
+            {
+                change.rescan = true;
+                return false;
+            }
+        }
+        true
+    });
+
+    let mut changes_per_dir: FastHashMap<&Path, usize> = FastHashMap::default();
+    for path in &order {
+        if let Some(parent) = path.parent() {
+            *changes_per_dir.entry(parent).or_default() += 1;
+        }
+    }
+    let mut rescanned_dirs: Vec<PathBuf> = changes_per_dir
+        .into_iter()
+        .filter(|(_, count)| *count >= rescan_threshold)
+        .map(|(dir, _)| dir.to_owned())
+        .collect();
+    rescanned_dirs.sort();
+
+    let mut result = vec![];
+    for dir in &rescanned_dirs {
+        result.push(Ok(Event::new(EventKind::Modify(ModifyKind::Any))
+            .add_path(dir.clone())
+            .set_flag(Flag::Rescan)));
+    }
+    for path in order {
+        if rescanned_dirs
+            .iter()
+            .any(|dir| *dir == path || path.parent() == Some(dir))
+        {
+            continue;
+        }
+        let change = changes[&path];
+        let event = Event::new(change.kind()).add_path(path);
+        result.push(Ok(if change.rescan {
+            event.set_flag(Flag::Rescan)
+        } else {
+            event
+        }));
+    }
+    result.extend(other_events);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, path: &str) -> NotifyEvent {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    fn create(path: &str) -> NotifyEvent {
+        event(EventKind::Create(CreateKind::File), path)
+    }
+
+    fn modify(path: &str) -> NotifyEvent {
+        event(EventKind::Modify(ModifyKind::Any), path)
+    }
+
+    fn remove(path: &str) -> NotifyEvent {
+        event(EventKind::Remove(RemoveKind::File), path)
+    }
+
+    fn coalesced(events: Vec<NotifyEvent>, rescan_threshold: usize) -> Vec<String> {
+        coalesce_events(events, rescan_threshold)
+            .into_iter()
+            .map(|event| match event {
+                Ok(event) => {
+                    let rescan = if event.need_rescan() { " rescan" } else { "" };
+                    format!("{:?} {}{rescan}", event.kind, event.paths[0].display())
+                }
+                Err(err) => err.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_coalesce_events() {
+        let events = vec![
+            modify("/p/a.py"),
+            create("/p/tmp.py"),
+            modify("/p/a.py"),
+            remove("/p/b.py"),
+            modify("/p/tmp.py"),
+            remove("/p/tmp.py"),
+            create("/p/b.py"),
+            create("/p/c.py"),
+        ];
+        assert_eq!(
+            coalesced(events, 100),
+            [
+                "Modify(Any) /p/a.py",
+                "Remove(Any) /p/tmp.py",
+                "Modify(Any) /p/b.py",
+                "Create(Any) /p/c.py"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_events_in_directories() {
+        let events = vec![
+            create("/p/pkg"),
+            create("/p/pkg/a.py"),
+            create("/p/pkg/sub/b.py"),
+            modify("/p/x/1.py"),
+            modify("/p/x/2.py"),
+            modify("/q/y.py"),
+        ];
+        assert_eq!(
+            coalesced(events, 2),
+            [
+                "Modify(Any) /p/x rescan",
+                "Create(Any) /p/pkg rescan",
+                "Modify(Any) /q/y.py"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_events_with_error() {
+        let events = vec![modify("/p/a.py"), Err(notify::Error::generic("failed"))];
+        assert_eq!(coalesced(events, 100), ["failed"]);
+    }
+}
@@ -1,35 +1,156 @@
+use std::path::Path;
+
 use crate::{AbsPath, VfsHandler};
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub struct GlobAbsPath {
-    pattern: glob::Pattern,
+    original: Box<str>,
+    // A pattern like `foo/{a,b}.py` is split into one pattern per alternative.
+    patterns: Vec<glob::Pattern>,
+    negated: bool,
+    case_sensitive: bool,
 }
 
 impl GlobAbsPath {
     pub fn new(vfs: &dyn VfsHandler, current_dir: &AbsPath, path: &str) -> anyhow::Result<Self> {
-        let p = vfs.normalize_rc_path(vfs.absolute_path(current_dir, path));
-        let pattern = glob::Pattern::new(&p)?;
-        Ok(Self { pattern })
+        let (negated, path) = match path.strip_prefix('!') {
+            Some(path) => (true, path),
+            None => (false, path),
+        };
+        let to_pattern = |path: &str| vfs.normalize_rc_path(vfs.absolute_path(current_dir, path));
+        let patterns = expand_braces(path)
+            .iter()
+            .map(|p| Ok(glob::Pattern::new(&to_pattern(p))?))
+            .collect::<anyhow::Result<_>>()?;
+        let original: &str = &to_pattern(path);
+        // The directory before the first wildcard decides about the case-sensitivity.
+        let literal_prefix = &original[..original
+            .find(['?', '[', '*', '{'])
+            .unwrap_or(original.len())];
+        let case_sensitive = vfs.is_case_sensitive(&vfs.unchecked_abs_path(literal_prefix));
+        Ok(Self {
+            original: original.into(),
+            patterns,
+            negated,
+            case_sensitive,
+        })
     }
 
-    pub fn matches(&self, vfs: &dyn VfsHandler, path: &AbsPath) -> bool {
+    pub fn matches(&self, path: &AbsPath) -> bool {
         // TODO this does not correctly use VFS's separator
-        self.pattern.matches_with(
-            path,
-            glob::MatchOptions {
-                case_sensitive: vfs.is_case_sensitive(),
-                require_literal_separator: true,
-                ..Default::default()
-            },
-        )
+        self.patterns.iter().any(|pattern| {
+            pattern.matches_with(
+                path,
+                glob::MatchOptions {
+                    case_sensitive: self.case_sensitive,
+                    require_literal_separator: true,
+                    ..Default::default()
+                },
+            )
+        })
+    }
+
+    /// Negated patterns start with `!` and exclude paths that were matched by the patterns before
+    /// them.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Whether the path or one of its parent directories is matched by the patterns. Like in a
+    /// `.gitignore` the last pattern that matches decides.
+    pub fn matches_path_or_parent(
+        globs: &[GlobAbsPath],
+        vfs: &dyn VfsHandler,
+        path: &AbsPath,
+    ) -> bool {
+        let ancestors: Vec<_> = Path::new(&**path)
+            .ancestors()
+            .filter_map(|p| Some(vfs.unchecked_abs_path(p.to_str()?)))
+            .collect();
+        globs
+            .iter()
+            .rev()
+            .find(|glob| ancestors.iter().any(|p| glob.matches(p)))
+            .is_some_and(|glob| !glob.negated)
     }
 
     pub fn maybe_simple_path(&self) -> Option<&str> {
-        let original = self.pattern.as_str();
-        (!original.contains(['?', '[', ']', '*'])).then_some(original)
+        let [pattern] = self.patterns.as_slice() else {
+            return None;
+        };
+        let original = pattern.as_str();
+        (!self.negated && !original.contains(['?', '[', ']', '*'])).then_some(original)
     }
 
     pub fn as_str(&self) -> &str {
-        self.pattern.as_str()
+        &self.original
+    }
+}
+
+/// Expands `{a,b}` alternatives, which may be nested, into separate patterns. Braces without a
+/// comma and braces within character classes are kept as they are.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let bytes = pattern.as_bytes();
+    let mut in_class = false;
+    for (start, &b) in bytes.iter().enumerate() {
+        match b {
+            b'[' => in_class = true,
+            b']' => in_class = false,
+            b'{' if !in_class => {
+                let mut depth = 0;
+                let mut commas = vec![];
+                for (i, &b) in bytes.iter().enumerate().skip(start) {
+                    match b {
+                        b'{' => depth += 1,
+                        b',' if depth == 1 => commas.push(i),
+                        b'}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                if commas.is_empty() {
+                                    break;
+                                }
+                                let before = &pattern[..start];
+                                let after = &pattern[i + 1..];
+                                let mut result = vec![];
+                                let bounds = std::iter::once(start)
+                                    .chain(commas.iter().copied())
+                                    .zip(commas.iter().copied().chain(std::iter::once(i)));
+                                for (from, to) in bounds {
+                                    let alternative = &pattern[from + 1..to];
+                                    // The alternatives and the rest might contain braces as well.
+                                    for expanded in expand_braces(&format!("{alternative}{after}"))
+                                    {
+                                        result.push(format!("{before}{expanded}"));
+                                    }
+                                }
+                                return result;
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    vec![pattern.to_owned()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("foo/*.py"), ["foo/*.py"]);
+        assert_eq!(expand_braces("{a,b}/x.py"), ["a/x.py", "b/x.py"]);
+        assert_eq!(
+            expand_braces("{a,b{c,d}}/x.{py,pyi}"),
+            [
+                "a/x.py", "a/x.pyi", "bc/x.py", "bc/x.pyi", "bd/x.py", "bd/x.pyi"
+            ]
+        );
+        assert_eq!(expand_braces("a{,_test}.py"), ["a.py", "a_test.py"]);
+        assert_eq!(expand_braces("{a}/[{,]}.py"), ["{a}/[{,]}.py"]);
     }
 }
@@ -1,11 +1,16 @@
 // Some parts are copied from rust-analyzer
 
+mod archive;
+mod debounce;
 mod glob_abs_path;
 mod local_fs;
+mod memory_fs;
+mod metadata;
 mod normalized_path;
 mod path;
 mod tree;
 mod vfs;
+mod watchman;
 mod workspaces;
 
 use std::{
@@ -16,18 +21,32 @@ use std::{
 
 use crossbeam_channel::Receiver;
 
+pub use debounce::receive_coalesced_events;
 pub use glob_abs_path::GlobAbsPath;
-pub use local_fs::{LocalFS, SimpleLocalFS};
+pub use local_fs::{LocalFS, SimpleLocalFS, WatcherKind};
+pub use memory_fs::MemoryFS;
+pub use metadata::{FileMetadata, FileType};
 pub use normalized_path::NormalizedPath;
 pub use path::AbsPath;
 pub use tree::{
-    DirOrFile, Directory, DirectoryEntry, Entries, FileEntry, FileIndex, GitignoreFile, Parent,
+    DirOrFile, Directory, DirectoryEntry, Entries, FileEntry, FileIndex, GitignoreFile,
+    GitignorePatterns, Parent,
 };
 pub use vfs::{InvalidationResult, PathWithScheme, Vfs, VfsFile, VfsPanicRecovery};
 pub use workspaces::{Workspace, WorkspaceKind, Workspaces};
 
 pub type NotifyEvent = notify::Result<notify::Event>;
 
+/// The usual case-sensitivity of the file systems of the current platform. Most file systems
+/// on macOS and Windows are case-insensitive.
+pub fn default_case_sensitivity() -> bool {
+    !cfg!(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "windows"
+    ))
+}
+
 /// Interface for reading and watching files.                                  
 pub trait VfsHandler: Sync + Send {
     /// Load the content of the given file, returning [`None`] if it does not  
@@ -50,6 +69,10 @@ pub trait VfsHandler: Sync + Send {
         replace_name: &str,
     ) -> Option<DirectoryEntry>;
 
+    /// Returns [`None`] if the path does not exist. Unlike the other methods this does not watch
+    /// the path.
+    fn read_metadata(&self, path: &AbsPath) -> Option<FileMetadata>;
+
     fn separator(&self) -> char {
         std::path::MAIN_SEPARATOR
     }
@@ -112,8 +135,16 @@ pub trait VfsHandler: Sync + Send {
         self.unchecked_abs_path(Path::new(&**path).join(name).to_str().unwrap())
     }
 
-    fn is_case_sensitive(&self) -> bool {
-        cfg!(target_os = "windows")
+    /// Whether paths in the directory of the given path are case-sensitive. Callers should cache
+    /// the result, because implementations may need to access the file system.
+    fn is_case_sensitive(&self, _path: &AbsPath) -> bool {
+        default_case_sensitivity()
+    }
+
+    /// Whether the path is an archive like a wheel, whose modules are importable if it is on the
+    /// sys path.
+    fn is_archive(&self, _path: &AbsPath) -> bool {
+        false
     }
 
     fn is_unnecessary_invalidation(
@@ -1,5 +1,6 @@
 use std::{
     path::{Path, PathBuf},
+    str::FromStr,
     sync::{Arc, RwLock},
 };
 
@@ -8,33 +9,76 @@ use notify::{RecommendedWatcher, RecursiveMode, Watcher, recommended_watcher};
 use utils::FastHashSet;
 
 use crate::{
-    AbsPath, Directory, DirectoryEntry, Entries, FileEntry, GitignoreFile, NormalizedPath,
-    NotifyEvent, Parent, PathWithScheme, VfsHandler, Workspace, tree::DirEntries,
+    AbsPath, Directory, DirectoryEntry, Entries, FileEntry, FileMetadata, FileType, GitignoreFile,
+    NormalizedPath, NotifyEvent, Parent, PathWithScheme, VfsHandler, Workspace, WorkspaceKind,
+    archive::{Archives, split_archive_path},
+    tree::{DirEntries, SymlinkedDirectory},
+    watchman::{self, Watchman},
 };
 
 const GLOBALLY_IGNORED_FOLDERS: [&str; 3] = ["site-packages", "node_modules", "__pycache__"];
 
 pub type SimpleLocalFS = LocalFS<Box<dyn Fn(PathWithScheme) + Sync + Send>>;
 
+/// How the files are watched for changes, configured with the `file_watcher` option.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WatcherKind {
+    /// Uses Watchman if the project is configured for it, notify otherwise.
+    #[default]
+    Auto,
+    Notify,
+    Watchman,
+}
+
+impl FromStr for WatcherKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => Self::Auto,
+            "notify" => Self::Notify,
+            "watchman" => Self::Watchman,
+            _ => {
+                return Err(format!(
+                    "Expected \"auto\", \"notify\" or \"watchman\", got \"{s}\""
+                ));
+            }
+        })
+    }
+}
+
+enum FileWatcher {
+    Notify(RwLock<RecommendedWatcher>),
+    Watchman(Watchman),
+}
+
 pub struct LocalFS<T: Fn(PathWithScheme) + Sync + Send> {
-    watcher: Option<(RwLock<RecommendedWatcher>, Receiver<NotifyEvent>)>,
+    watcher: Option<(FileWatcher, Receiver<NotifyEvent>)>,
     already_watched_dirs: RwLock<FastHashSet<PathBuf>>,
     on_invalidated_in_memory_file: Option<T>,
+    archives: Archives,
 }
 
 impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
     fn read_and_watch_file(&self, path: &PathWithScheme) -> Option<String> {
         tracing::debug!("Read from FS: {}", path.as_uri());
-        if **path.scheme != *"file" {
-            tracing::error!(
-                "Tried to read from FS for the scheme: {}, scheme file was expected",
-                &path.scheme
+        if !path.is_file_scheme() {
+            // Documents of other schemes only exist as long as the client has them opened.
+            tracing::debug!(
+                "Did not read {}, because it's not on the file system",
+                path.as_uri()
             );
             return None;
         }
         let path = &path.path;
         // Need to watch first, because otherwise the file might be read deleted and then watched.
         let p = path.as_ref().as_ref();
+        if let Some((archive, inner)) = split_archive_path(p)
+            && !inner.is_empty()
+        {
+            self.watch(archive);
+            return self.archives.read_file(archive, &inner);
+        }
         self.watch(p);
         let result = std::fs::read_to_string(p);
         if let Err(error) = &result {
@@ -57,6 +101,23 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
             }
             _ => tracing::error!("{from} error (base: {path}): {e}"),
         };
+        if let Some((archive, inner)) = split_archive_path(Path::new(path)) {
+            self.watch(archive);
+            let entries = self.archives.list_dir(archive, &inner);
+            return Entries::from_vec(
+                entries
+                    .into_iter()
+                    .filter_map(|(name, is_dir)| {
+                        let resolved = if is_dir {
+                            ResolvedFileType::Directory
+                        } else {
+                            ResolvedFileType::File
+                        };
+                        resolved.into_dir_entry(workspaces, self, parent.clone(), name)
+                    })
+                    .collect(),
+            );
+        }
         self.watch(path);
         let iterator = match std::fs::read_dir(path) {
             Ok(iterator) => iterator,
@@ -79,11 +140,9 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
                     };
                     match dir_entry.file_type() {
                         Ok(file_type) => {
-                            let new = if file_type.is_dir() {
-                                ResolvedFileType::Directory
-                            } else if file_type.is_symlink() {
+                            let new = if file_type.is_symlink() {
                                 let p = dir_entry.path();
-                                match self.follow_and_watch_symlink(&p) {
+                                match self.follow_and_watch_symlink(workspaces, &p) {
                                     Ok(resolved) => resolved,
                                     Err(err) => {
                                         tracing::warn!("Follow symlink error, path={p:?}: {err}");
@@ -91,8 +150,17 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
                                     }
                                 }
                             } else {
-                                debug_assert!(file_type.is_file());
-                                ResolvedFileType::File
+                                match FileType::from(file_type) {
+                                    FileType::File => ResolvedFileType::File,
+                                    FileType::Directory => ResolvedFileType::Directory,
+                                    FileType::Other => {
+                                        tracing::debug!(
+                                            "Listdir ignored {:?}, because it's not a regular file",
+                                            dir_entry.path()
+                                        );
+                                        continue;
+                                    }
+                                }
                             };
                             if name == ".gitignore" && matches!(new, ResolvedFileType::File) {
                                 if let Some(code) = self.read_and_watch_file(
@@ -134,6 +202,16 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
         parent: Parent,
         replace_name: &str,
     ) -> Option<DirectoryEntry> {
+        if let Some((archive, inner)) = split_archive_path(Path::new(path))
+            && !inner.is_empty()
+        {
+            let resolved = if self.archives.entry_is_dir(archive, &inner)? {
+                ResolvedFileType::Directory
+            } else {
+                ResolvedFileType::File
+            };
+            return resolved.into_dir_entry(workspaces, self, parent, replace_name);
+        }
         let metadata = match std::fs::symlink_metadata(path) {
             Ok(metadata) => metadata,
             Err(err) => {
@@ -141,11 +219,8 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
                 return None;
             }
         };
-        let resolved = if metadata.is_dir() {
-            self.watch(path);
-            ResolvedFileType::Directory
-        } else if metadata.is_symlink() {
-            match self.follow_and_watch_symlink(path) {
+        let resolved = if metadata.is_symlink() {
+            match self.follow_and_watch_symlink(workspaces, path) {
                 Ok(resolved) => resolved,
                 Err(err) => {
                     tracing::info!("read_entry follow symlink error, path={path}: {err}");
@@ -153,13 +228,35 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
                 }
             }
         } else {
-            debug_assert!(metadata.is_file());
+            let resolved = match FileType::from(metadata.file_type()) {
+                FileType::File => ResolvedFileType::File,
+                FileType::Directory => ResolvedFileType::Directory,
+                FileType::Other => {
+                    tracing::debug!("Ignored {path:?}, because it's not a regular file");
+                    return None;
+                }
+            };
             self.watch(path);
-            ResolvedFileType::File
+            resolved
         };
         resolved.into_dir_entry(workspaces, self, parent, replace_name)
     }
 
+    fn read_metadata(&self, path: &AbsPath) -> Option<FileMetadata> {
+        if let Some((archive, inner)) = split_archive_path(path.as_ref())
+            && !inner.is_empty()
+        {
+            return self.archives.entry_metadata(archive, &inner);
+        }
+        match std::fs::metadata(path.as_ref() as &Path) {
+            Ok(metadata) => Some(metadata.into()),
+            Err(err) => {
+                tracing::debug!("Reading metadata failed for path={path:?}: {err}");
+                None
+            }
+        }
+    }
+
     fn notify_receiver(&self) -> Option<&Receiver<NotifyEvent>> {
         self.watcher.as_ref().map(|(_, r)| r)
     }
@@ -170,6 +267,35 @@ impl<T: Fn(PathWithScheme) + Sync + Send> VfsHandler for LocalFS<T> {
         }
     }
 
+    fn is_case_sensitive(&self, path: &AbsPath) -> bool {
+        // Checks whether the same path with a different case points to the same entry. The path
+        // itself might not exist yet and is therefore checked with its parents.
+        for p in Path::new(&**path).ancestors() {
+            let Some(name) = p.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let swapped: String = name
+                .chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect();
+            if swapped == name || std::fs::symlink_metadata(p).is_err() {
+                continue;
+            }
+            let case_sensitive =
+                !same_file::is_same_file(p, p.with_file_name(swapped)).unwrap_or(false);
+            tracing::debug!("Probed {p:?} to be case-sensitive={case_sensitive}");
+            return case_sensitive;
+        }
+        crate::default_case_sensitivity()
+    }
+
+    fn is_archive(&self, path: &AbsPath) -> bool {
+        split_archive_path(path.as_ref()).is_some_and(|(_, inner)| inner.is_empty())
+    }
+
     fn is_unnecessary_invalidation(
         &self,
         path: &AbsPath,
@@ -202,6 +328,7 @@ impl SimpleLocalFS {
             watcher: None,
             already_watched_dirs: Default::default(),
             on_invalidated_in_memory_file: None,
+            archives: Default::default(),
         }
     }
 }
@@ -219,90 +346,129 @@ impl<T: Fn(PathWithScheme) + Sync + Send> LocalFS<T> {
             }
         }));
         Self {
-            watcher: watcher.map(|w| (RwLock::new(w), watcher_receiver)),
+            watcher: watcher.map(|w| (FileWatcher::Notify(RwLock::new(w)), watcher_receiver)),
             already_watched_dirs: Default::default(),
             on_invalidated_in_memory_file: Some(on_invalidated_memory_file),
+            archives: Default::default(),
+        }
+    }
+
+    /// Like [`Self::with_watcher`], but the files are watched by the given kind of watcher. If
+    /// Watchman is not available, notify is used instead.
+    pub fn with_watcher_kind(
+        kind: WatcherKind,
+        root: &Path,
+        on_invalidated_memory_file: T,
+    ) -> Self {
+        let use_watchman = match kind {
+            WatcherKind::Auto => watchman::is_configured_for(root),
+            WatcherKind::Notify => false,
+            WatcherKind::Watchman => true,
+        };
+        if use_watchman {
+            let (sender, receiver) = unbounded();
+            match Watchman::new(sender) {
+                Ok(watchman) => {
+                    tracing::info!("Using Watchman to watch {root:?}");
+                    return Self {
+                        watcher: Some((FileWatcher::Watchman(watchman), receiver)),
+                        already_watched_dirs: Default::default(),
+                        on_invalidated_in_memory_file: Some(on_invalidated_memory_file),
+                        archives: Default::default(),
+                    };
+                }
+                Err(err) => {
+                    tracing::warn!("Watchman is not used, falling back to notify: {err}")
+                }
+            }
         }
+        Self::with_watcher(on_invalidated_memory_file)
     }
 
     pub fn watch<P: AsRef<Path>>(&self, path: P) {
         let path = path.as_ref();
-        if let Some((watcher, _)) = &self.watcher {
-            if cfg!(any(
-                target_os = "macos",
-                target_os = "windows",
-                target_os = "ios"
-            )) {
-                // On windows adding the watch n times will cause n events. We therefore used to
-                // remove the watch before adding it again. This was generally problematic, because
-                // it might be modified during that time and we might lose an event.
-                // However now that we only watch a path if we do not already watch it it shouldn't
-                // be an issue anymore.
-
-                // Linux does not support recursive files.
-                match std::fs::canonicalize(path) {
-                    Ok(canonicalized) => {
-                        let mut already_watched = self.already_watched_dirs.write().unwrap();
-                        // General information:
-                        // MacOS - FSEventStreamCreate: https://developer.apple.com/documentation/coreservices/1443980-fseventstreamcreate
-                        // Windows - ReadDirectoryChangesW: https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-readdirectorychangesw
-                        if let Some(_found) = already_watched
-                            .iter()
-                            .find(|watched| canonicalized.starts_with(watched))
-                        {
-                            // TODO While I'm not sure, I think MacOS simply works with paths, so
-                            // watching should be fine. On Windows this feels different, because we
-                            // are watching a directory handle. This complicates things, because we
-                            // always provide a path (can be a file too) to notify. So if the
-                            // parent directory of a file is removed (can be the parent of a
-                            // symlink target as well) and recreated we might run into issues.
-                            // So there might be edge cases where we lose watches for now.
-                            // As one possible workaround we try to rewatch the same file again.
-                            //
-                            // This might however still not work in cases where the file tree is
-                            // removed a level lower and then recreated later. In essence we would
-                            // need to watch ALL ancestors (however this is probably also an issue
-                            // on Linux and is only fine on MacOS).
-                            if cfg!(target_os = "windows") {
-                                /* TODO for now we disabled this
-                                if &canonicalized == found {
-                                    let _ = watcher.borrow_mut().unwatch(&canonicalized);
-                                    log_notify_error(
-                                        watcher
-                                            .borrow_mut()
-                                            .watch(&canonicalized, RecursiveMode::Recursive),
-                                    );
-                                    tracing::debug!("Removed and re-added recursive watch for {canonicalized:?}");
-                                    already_watched.insert(canonicalized);
-                                }
-                                */
+        match &self.watcher {
+            Some((FileWatcher::Watchman(watchman), _)) => watchman.watch(path),
+            Some((FileWatcher::Notify(watcher), _)) => self.watch_with_notify(watcher, path),
+            None => (),
+        }
+    }
+
+    fn watch_with_notify(&self, watcher: &RwLock<RecommendedWatcher>, path: &Path) {
+        if cfg!(any(
+            target_os = "macos",
+            target_os = "windows",
+            target_os = "ios"
+        )) {
+            // On windows adding the watch n times will cause n events. We therefore used to
+            // remove the watch before adding it again. This was generally problematic, because
+            // it might be modified during that time and we might lose an event.
+            // However now that we only watch a path if we do not already watch it it shouldn't
+            // be an issue anymore.
+
+            // Linux does not support recursive files.
+            match std::fs::canonicalize(path) {
+                Ok(canonicalized) => {
+                    let mut already_watched = self.already_watched_dirs.write().unwrap();
+                    // General information:
+                    // MacOS - FSEventStreamCreate: https://developer.apple.com/documentation/coreservices/1443980-fseventstreamcreate
+                    // Windows - ReadDirectoryChangesW: https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-readdirectorychangesw
+                    if let Some(_found) = already_watched
+                        .iter()
+                        .find(|watched| canonicalized.starts_with(watched))
+                    {
+                        // TODO While I'm not sure, I think MacOS simply works with paths, so
+                        // watching should be fine. On Windows this feels different, because we
+                        // are watching a directory handle. This complicates things, because we
+                        // always provide a path (can be a file too) to notify. So if the
+                        // parent directory of a file is removed (can be the parent of a
+                        // symlink target as well) and recreated we might run into issues.
+                        // So there might be edge cases where we lose watches for now.
+                        // As one possible workaround we try to rewatch the same file again.
+                        //
+                        // This might however still not work in cases where the file tree is
+                        // removed a level lower and then recreated later. In essence we would
+                        // need to watch ALL ancestors (however this is probably also an issue
+                        // on Linux and is only fine on MacOS).
+                        if cfg!(target_os = "windows") {
+                            /* TODO for now we disabled this
+                            if &canonicalized == found {
+                                let _ = watcher.borrow_mut().unwatch(&canonicalized);
+                                log_notify_error(
+                                    watcher
+                                        .borrow_mut()
+                                        .watch(&canonicalized, RecursiveMode::Recursive),
+                                );
+                                tracing::debug!("Removed and re-added recursive watch for {canonicalized:?}");
+                                already_watched.insert(canonicalized);
                             }
-                        } else {
-                            log_notify_error(
-                                watcher
-                                    .write()
-                                    .unwrap()
-                                    .watch(&canonicalized, RecursiveMode::Recursive),
-                            );
-                            tracing::debug!("Added recursive watch for {canonicalized:?}");
-                            already_watched.insert(canonicalized);
+                            */
                         }
-                    }
-                    Err(err) => {
-                        tracing::error!(
-                            "Failed to canonicalize path {path:?} and did therefore not watch, because: {err}"
+                    } else {
+                        log_notify_error(
+                            watcher
+                                .write()
+                                .unwrap()
+                                .watch(&canonicalized, RecursiveMode::Recursive),
                         );
+                        tracing::debug!("Added recursive watch for {canonicalized:?}");
+                        already_watched.insert(canonicalized);
                     }
                 }
-            } else {
-                log_notify_error(
-                    watcher
-                        .write()
-                        .unwrap()
-                        .watch(path, RecursiveMode::NonRecursive),
-                );
-                tracing::debug!("Added watch for {path:?}");
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to canonicalize path {path:?} and did therefore not watch, because: {err}"
+                    );
+                }
             }
+        } else {
+            log_notify_error(
+                watcher
+                    .write()
+                    .unwrap()
+                    .watch(path, RecursiveMode::NonRecursive),
+            );
+            tracing::debug!("Added watch for {path:?}");
         }
     }
 
@@ -316,41 +482,97 @@ impl<T: Fn(PathWithScheme) + Sync + Send> LocalFS<T> {
 
     fn follow_and_watch_symlink<P: AsRef<Path>>(
         &self,
+        workspaces: &[Arc<Workspace>],
         path: P,
     ) -> Result<ResolvedFileType, String> {
-        self.follow_symlink_internal(path.as_ref(), path.as_ref())
+        let path = path.as_ref();
+        let mut links = vec![];
+        let resolved = follow_symlink(path, path, &mut links)?;
+        if matches!(resolved, ResolvedFileType::Directory)
+            && let Some(target) = std::fs::canonicalize(path)
+                .ok()
+                .and_then(|target| self.path_in_workspaces(workspaces, &target))
+        {
+            // The target is watched once its entries are read. Watching the links as well would
+            // report the changes of the target for the paths of the links.
+            return Ok(ResolvedFileType::SymlinkedDirectory(target));
+        }
+        for link in links {
+            self.watch(link)
+        }
+        Ok(resolved)
     }
 
-    fn follow_symlink_internal(
+    /// Returns the path of a canonicalized path within the workspaces, which might differ if
+    /// the root of a workspace is not canonicalized.
+    fn path_in_workspaces(
         &self,
-        origin_path: &Path,
-        path: &Path,
-    ) -> Result<ResolvedFileType, String> {
-        self.watch(path);
-        let target_path = resolve_link(path)?;
-        let metadata = std::fs::symlink_metadata(&target_path).map_err(|e| format!("{e}"))?;
-        let file_type = metadata.file_type();
-        if file_type.is_dir() {
-            // We use same_file here for comparing the entries, because just comparing the paths
-            // can be hard, because they might be symlinks to e.g. `.`. Maybe cannonicalized paths
-            // could work, but the walkdir crate works like that, so we are probably on the safe
-            // side, because lots of well-known projects are using it.
-            let target_handle =
-                same_file::Handle::from_path(&target_path).map_err(|e| format!("{e}"))?;
-            for p in origin_path.ancestors() {
-                if let Ok(ancestor_handle) = same_file::Handle::from_path(p)
-                    && target_handle == ancestor_handle
-                {
-                    return Err(format!("Detected cycle in {target_path:?}"));
-                }
+        workspaces: &[Arc<Workspace>],
+        canonicalized: &Path,
+    ) -> Option<PathWithScheme> {
+        workspaces.iter().find_map(|workspace| {
+            if **workspace.scheme != *"file" || workspace.kind == WorkspaceKind::Fallback {
+                return None;
+            }
+            let root_path: &Path = workspace.root_path().as_ref();
+            let rest = canonicalized
+                .strip_prefix(std::fs::canonicalize(root_path).ok()?)
+                .ok()?;
+            let path = if rest.as_os_str().is_empty() {
+                workspace.root_path.clone()
+            } else {
+                self.unchecked_normalized_path(
+                    self.unchecked_abs_path(root_path.join(rest).to_str()?),
+                )
+            };
+            Some(PathWithScheme {
+                path,
+                scheme: workspace.scheme.clone(),
+            })
+        })
+    }
+}
+
+// Like the kernel we give up after a certain number of links, which avoids endless loops of
+// links that point to each other.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+fn follow_symlink(
+    origin_path: &Path,
+    path: &Path,
+    links: &mut Vec<PathBuf>,
+) -> Result<ResolvedFileType, String> {
+    if links.len() >= MAX_SYMLINK_HOPS {
+        return Err(format!(
+            "Too many levels of symbolic links in {origin_path:?}"
+        ));
+    }
+    links.push(path.to_owned());
+    let target_path = resolve_link(path)?;
+    let metadata = std::fs::symlink_metadata(&target_path).map_err(|e| format!("{e}"))?;
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        // We use same_file here for comparing the entries, because just comparing the paths
+        // can be hard, because they might be symlinks to e.g. `.`. Maybe cannonicalized paths
+        // could work, but the walkdir crate works like that, so we are probably on the safe
+        // side, because lots of well-known projects are using it.
+        let target_handle =
+            same_file::Handle::from_path(&target_path).map_err(|e| format!("{e}"))?;
+        // The link itself resolves to the target and is therefore skipped.
+        for p in origin_path.ancestors().skip(1) {
+            if let Ok(ancestor_handle) = same_file::Handle::from_path(p)
+                && target_handle == ancestor_handle
+            {
+                return Err(format!("Detected cycle in {target_path:?}"));
             }
-            Ok(ResolvedFileType::Directory)
-        } else if file_type.is_symlink() {
-            self.follow_symlink_internal(origin_path, &target_path)
-        } else {
-            debug_assert!(file_type.is_file());
-            Ok(ResolvedFileType::File)
         }
+        Ok(ResolvedFileType::Directory)
+    } else if file_type.is_symlink() {
+        follow_symlink(origin_path, &target_path, links)
+    } else if file_type.is_file() {
+        Ok(ResolvedFileType::File)
+    } else {
+        Err(format!("{target_path:?} is not a regular file"))
     }
 }
 
@@ -366,13 +588,15 @@ fn resolve_link(path: &Path) -> Result<PathBuf, String> {
     }
 }
 
-enum ResolvedFileType {
+pub(crate) enum ResolvedFileType {
     File,
     Directory,
+    // A symlink to a directory within the workspaces
+    SymlinkedDirectory(PathWithScheme),
 }
 
 impl ResolvedFileType {
-    fn into_dir_entry<N: Into<Box<str>> + AsRef<str>>(
+    pub(crate) fn into_dir_entry<N: Into<Box<str>> + AsRef<str>>(
         self,
         workspaces: &[Arc<Workspace>],
         vfs: &dyn VfsHandler,
@@ -405,6 +629,18 @@ impl ResolvedFileType {
                 }
                 DirectoryEntry::Directory(dir)
             }
+            ResolvedFileType::SymlinkedDirectory(target) => {
+                let dir = Directory::new(parent, name.into());
+                let entries = match workspaces.iter().find(|workspace| {
+                    workspace.root_path == target.path && workspace.scheme == target.scheme
+                }) {
+                    Some(workspace) => DirEntries::NestedWorkspace(Arc::downgrade(workspace)),
+                    None => DirEntries::Symlink(SymlinkedDirectory::new(target)),
+                };
+                let result = dir.entries.set(entries);
+                debug_assert!(result.is_ok());
+                DirectoryEntry::Directory(dir)
+            }
         })
     }
 }
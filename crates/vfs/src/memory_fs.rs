@@ -0,0 +1,364 @@
+//! A [`VfsHandler`] that keeps all files in memory, e.g. for programs that embed the type checker
+//! without giving it access to the file system and for tests. With a watcher, changes are reported
+//! through the notify receiver, like the changes of a watched file system.
+
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use notify::{
+    Event, EventKind,
+    event::{CreateKind, DataChange, ModifyKind, RemoveKind},
+};
+
+use crate::{
+    AbsPath, DirectoryEntry, Entries, FileMetadata, FileType, GitignoreFile, NotifyEvent, Parent,
+    PathWithScheme, SimpleLocalFS, VfsHandler, Workspace, local_fs::ResolvedFileType,
+};
+
+#[derive(Debug, Clone)]
+enum MemoryEntry {
+    File(Arc<str>),
+    Directory,
+}
+
+/// Clones share the same files, so a clone can be kept to change files after the original was
+/// passed to a project.
+#[derive(Clone)]
+pub struct MemoryFS {
+    entries: Arc<RwLock<BTreeMap<Box<str>, MemoryEntry>>>,
+    // Directories that are read from the file system instead, e.g. typeshed
+    disk_dirs: Vec<PathBuf>,
+    local_fs: Arc<SimpleLocalFS>,
+    watcher: Option<(Sender<NotifyEvent>, Receiver<NotifyEvent>)>,
+    case_sensitive: bool,
+}
+
+impl Default for MemoryFS {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryFS {
+    pub fn new() -> Self {
+        Self {
+            entries: Default::default(),
+            disk_dirs: vec![],
+            local_fs: Arc::new(SimpleLocalFS::without_watcher()),
+            watcher: None,
+            case_sensitive: true,
+        }
+    }
+
+    /// Reports all changes through the notify receiver. Without a watcher, projects do not see
+    /// changes of files they already read.
+    pub fn with_watcher(mut self) -> Self {
+        self.watcher = Some(unbounded());
+        self
+    }
+
+    /// Behaves like the case-insensitive file systems of macOS and Windows when probed. Paths
+    /// are still looked up with their exact case.
+    pub fn with_case_insensitive(mut self) -> Self {
+        self.case_sensitive = false;
+        self
+    }
+
+    /// Files within the directory are read from the file system. This is mostly useful for
+    /// typeshed, which is needed for every project. These files are not watched.
+    pub fn with_disk_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_dirs.push(dir.into());
+        self
+    }
+
+    fn key(&self, path: &str) -> Box<str> {
+        let path = self.normalize_unchecked_abs_path(path);
+        let path: &str = &path;
+        path.into()
+    }
+
+    fn is_on_disk(&self, path: &str) -> bool {
+        self.disk_dirs
+            .iter()
+            .any(|dir| Path::new(path).starts_with(dir))
+    }
+
+    /// Creates or overwrites a file. Missing parent directories are created as well.
+    pub fn write_file(&self, path: &str, code: &str) {
+        let key = self.key(path);
+        let mut entries = self.entries.write().unwrap();
+        let created_dir = self.create_parent_dirs(&mut entries, &key);
+        let previous = entries.insert(key.clone(), MemoryEntry::File(code.into()));
+        drop(entries);
+        let event = match (created_dir, previous) {
+            // Invalidating the new directory invalidates everything within it.
+            (Some(dir), _) => Event::new(EventKind::Create(CreateKind::Folder)).add_path(dir),
+            (None, Some(MemoryEntry::File(_))) => {
+                Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+                    .add_path(PathBuf::from(&*key))
+            }
+            (None, _) => {
+                Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from(&*key))
+            }
+        };
+        self.send(event)
+    }
+
+    /// Creates a directory and its missing parents.
+    pub fn create_dir_all(&self, path: &str) {
+        let key = self.key(path);
+        let mut entries = self.entries.write().unwrap();
+        if matches!(entries.get(&key), Some(MemoryEntry::Directory)) {
+            return;
+        }
+        let created_dir = self.create_parent_dirs(&mut entries, &key);
+        entries.insert(key.clone(), MemoryEntry::Directory);
+        drop(entries);
+        let dir = created_dir.unwrap_or_else(|| PathBuf::from(&*key));
+        self.send(Event::new(EventKind::Create(CreateKind::Folder)).add_path(dir))
+    }
+
+    /// Removes a file or a directory with everything within it. Returns false if the path does
+    /// not exist.
+    pub fn remove(&self, path: &str) -> bool {
+        let key = self.key(path);
+        let mut entries = self.entries.write().unwrap();
+        let Some(removed) = entries.remove(&key) else {
+            return false;
+        };
+        let kind = match removed {
+            MemoryEntry::File(_) => RemoveKind::File,
+            MemoryEntry::Directory => {
+                let prefix = self.dir_prefix(&key);
+                entries.retain(|path, _| !path.starts_with(&*prefix));
+                RemoveKind::Folder
+            }
+        };
+        drop(entries);
+        self.send(Event::new(EventKind::Remove(kind)).add_path(PathBuf::from(&*key)));
+        true
+    }
+
+    /// Returns the topmost directory that was created.
+    fn create_parent_dirs(
+        &self,
+        entries: &mut BTreeMap<Box<str>, MemoryEntry>,
+        path: &str,
+    ) -> Option<PathBuf> {
+        let mut created = None;
+        let path = self.unchecked_abs_path(path);
+        let mut current = self.parent_of_absolute_path(&path);
+        while let Some(dir) = current {
+            current = self.parent_of_absolute_path(dir);
+            if current.is_none() {
+                // The root always exists.
+                break;
+            }
+            let dir_str: &str = dir;
+            match entries.get(dir_str) {
+                Some(MemoryEntry::Directory) => break,
+                Some(MemoryEntry::File(_)) => {
+                    tracing::warn!("Replaced the file {dir_str} with a directory");
+                }
+                None => (),
+            }
+            entries.insert(dir_str.into(), MemoryEntry::Directory);
+            created = Some(PathBuf::from(dir_str));
+        }
+        created
+    }
+
+    fn dir_prefix(&self, dir: &str) -> String {
+        let mut prefix = dir.to_string();
+        if self.strip_separator_suffix(dir).is_none() {
+            prefix.push(self.separator());
+        }
+        prefix
+    }
+
+    fn send(&self, event: Event) {
+        if let Some((sender, _)) = &self.watcher {
+            // The receiver is owned by self and therefore still exists.
+            sender.send(Ok(event)).unwrap()
+        }
+    }
+
+    fn entry(&self, path: &str) -> Option<MemoryEntry> {
+        self.entries.read().unwrap().get(path).cloned()
+    }
+}
+
+impl VfsHandler for MemoryFS {
+    fn read_and_watch_file(&self, path: &PathWithScheme) -> Option<String> {
+        if !path.is_file_scheme() {
+            return None;
+        }
+        if self.is_on_disk(path.path()) {
+            return self.local_fs.read_and_watch_file(path);
+        }
+        match self.entry(path.path())? {
+            MemoryEntry::File(code) => Some(code.to_string()),
+            MemoryEntry::Directory => None,
+        }
+    }
+
+    fn read_metadata(&self, path: &AbsPath) -> Option<FileMetadata> {
+        if self.is_on_disk(path) {
+            return self.local_fs.read_metadata(path);
+        }
+        Some(match self.entry(path)? {
+            MemoryEntry::File(code) => FileMetadata {
+                file_type: FileType::File,
+                len: code.len() as u64,
+                modified: None,
+            },
+            MemoryEntry::Directory => FileMetadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified: None,
+            },
+        })
+    }
+
+    fn notify_receiver(&self) -> Option<&Receiver<NotifyEvent>> {
+        self.watcher.as_ref().map(|(_, receiver)| receiver)
+    }
+
+    fn on_invalidated_in_memory_file(&self, _path: PathWithScheme) {}
+
+    fn is_case_sensitive(&self, _path: &AbsPath) -> bool {
+        self.case_sensitive
+    }
+
+    fn read_and_watch_dir(
+        &self,
+        workspaces: &[Arc<Workspace>],
+        path: &str,
+        parent: Parent,
+    ) -> Entries {
+        if self.is_on_disk(path) {
+            return self.local_fs.read_and_watch_dir(workspaces, path, parent);
+        }
+        let prefix = self.dir_prefix(path);
+        let entries = self.entries.read().unwrap();
+        let mut result = vec![];
+        for (child_path, entry) in
+            entries.range::<str, _>((Bound::Excluded(&*prefix), Bound::Unbounded))
+        {
+            let Some(name) = child_path.strip_prefix(&prefix) else {
+                break;
+            };
+            if name.contains(self.separator()) {
+                // Within a sub directory
+                continue;
+            }
+            if let Some(entry) =
+                to_dir_entry(workspaces, self, parent.clone(), child_path, name, entry)
+            {
+                result.push(entry)
+            }
+        }
+        Entries::from_vec(result)
+    }
+
+    fn read_and_watch_entry(
+        &self,
+        workspaces: &[Arc<Workspace>],
+        path: &str,
+        parent: Parent,
+        replace_name: &str,
+    ) -> Option<DirectoryEntry> {
+        if self.is_on_disk(path) {
+            return self
+                .local_fs
+                .read_and_watch_entry(workspaces, path, parent, replace_name);
+        }
+        let entry = self.entry(path)?;
+        to_dir_entry(workspaces, self, parent, path, replace_name, &entry)
+    }
+}
+
+fn to_dir_entry(
+    workspaces: &[Arc<Workspace>],
+    vfs: &dyn VfsHandler,
+    parent: Parent,
+    path: &str,
+    name: &str,
+    entry: &MemoryEntry,
+) -> Option<DirectoryEntry> {
+    match entry {
+        MemoryEntry::File(code) if name == ".gitignore" => Some(DirectoryEntry::Gitignore(
+            GitignoreFile::new(parent, path, code),
+        )),
+        MemoryEntry::File(_) => {
+            ResolvedFileType::File.into_dir_entry(workspaces, vfs, parent, name)
+        }
+        MemoryEntry::Directory => {
+            ResolvedFileType::Directory.into_dir_entry(workspaces, vfs, parent, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(fs: &MemoryFS) -> Vec<String> {
+        fs.notify_receiver()
+            .unwrap()
+            .try_iter()
+            .map(|event| {
+                let event = event.unwrap();
+                format!("{:?} {}", event.kind, event.paths[0].display())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_fs_events() {
+        let fs = MemoryFS::new().with_watcher();
+        fs.write_file("/p/pkg/a.py", "");
+        fs.write_file("/p/pkg/b.py", "");
+        fs.write_file("/p/pkg/a.py", "x = 1");
+        assert_eq!(fs.remove("/p/pkg/b.py"), true);
+        assert_eq!(fs.remove("/p/pkg/b.py"), false);
+        fs.create_dir_all("/p/other/sub");
+        assert_eq!(fs.remove("/p/other"), true);
+        assert_eq!(
+            events(&fs),
+            [
+                "Create(Folder) /p",
+                "Create(File) /p/pkg/b.py",
+                "Modify(Data(Content)) /p/pkg/a.py",
+                "Remove(File) /p/pkg/b.py",
+                "Create(Folder) /p/other",
+                "Remove(Folder) /p/other",
+            ]
+        );
+        let code = fs.read_and_watch_file(&PathWithScheme::with_file_scheme(
+            fs.normalize_unchecked_abs_path("/p/pkg/a.py"),
+        ));
+        assert_eq!(code.as_deref(), Some("x = 1"));
+        let metadata = fs
+            .read_metadata(&fs.unchecked_abs_path("/p/pkg/a.py"))
+            .unwrap();
+        assert_eq!(metadata.file_type, FileType::File);
+        assert_eq!(metadata.len, 5);
+        let metadata = fs.read_metadata(&fs.unchecked_abs_path("/p/pkg")).unwrap();
+        assert_eq!(metadata.file_type, FileType::Directory);
+        assert!(
+            fs.read_metadata(&fs.unchecked_abs_path("/p/pkg/b.py"))
+                .is_none()
+        );
+        assert!(fs.entry("/p/other/sub").is_none());
+
+        let fs = MemoryFS::new();
+        fs.write_file("/p/a.py", "");
+        assert!(fs.notify_receiver().is_none());
+    }
+}
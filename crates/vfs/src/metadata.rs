@@ -0,0 +1,41 @@
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    /// Sockets, devices, pipes and similar entries, which are not Python files.
+    Other,
+}
+
+impl From<std::fs::FileType> for FileType {
+    fn from(file_type: std::fs::FileType) -> Self {
+        if file_type.is_dir() {
+            Self::Directory
+        } else if file_type.is_file() {
+            Self::File
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The metadata of a file, symlinks are followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub file_type: FileType,
+    /// The size in bytes
+    pub len: u64,
+    /// Not available on all platforms and for files that only exist in memory.
+    pub modified: Option<SystemTime>,
+}
+
+impl From<std::fs::Metadata> for FileMetadata {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        Self {
+            file_type: metadata.file_type().into(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
@@ -80,6 +80,11 @@ impl Parent {
         }
     }
 
+    /// Whether the names of the entries are case-sensitive, which is probed for every workspace.
+    pub fn is_case_sensitive(&self) -> bool {
+        self.workspace().case_sensitive
+    }
+
     pub fn with_entries<T, X>(&self, vfs: &Vfs<X>, callback: impl FnOnce(&Entries) -> T) -> T {
         match self {
             Self::Directory(dir) => callback(Directory::entries(vfs, &dir.upgrade().unwrap())),
@@ -187,7 +192,11 @@ impl DirectoryEntry {
     }
 
     fn walk_internal<X>(&self, vfs: &Vfs<X>, callable: &mut impl FnMut(&Entries, &Self) -> bool) {
-        if let DirectoryEntry::Directory(dir) = self {
+        if let DirectoryEntry::Directory(dir) = self
+            // The target is walked with its own path, which also avoids walking cycles of
+            // symlinks.
+            && !dir.is_symlink_into_workspace()
+        {
             let entries = Directory::entries(vfs, dir);
             for entry in entries.borrow().iter() {
                 if callable(entries, entry) {
@@ -218,6 +227,47 @@ pub struct Directory {
 pub(crate) enum DirEntries {
     Entries(Entries),
     NestedWorkspace(Weak<Workspace>),
+    Symlink(SymlinkedDirectory),
+}
+
+/// A symlinked directory whose target is part of a workspace. The entries are those of the target
+/// directory, so a file that is reachable through multiple paths is only added once.
+#[derive(Debug, Clone)]
+pub(crate) struct SymlinkedDirectory {
+    target: PathWithScheme,
+    // Used if the target is not part of the tree anymore
+    fallback: OnceLock<Entries>,
+}
+
+impl SymlinkedDirectory {
+    pub(crate) fn new(target: PathWithScheme) -> Self {
+        Self {
+            target,
+            fallback: Default::default(),
+        }
+    }
+
+    fn entries<'x>(
+        &'x self,
+        vfs: &dyn VfsHandler,
+        workspaces: &Workspaces,
+        dir: &Arc<Directory>,
+    ) -> &'x Entries {
+        if let Some(DirOrFile::Dir(target)) = workspaces.search_path(vfs, &self.target) {
+            let reference =
+                Directory::entries_with_workspaces(vfs, workspaces, &target) as *const Entries;
+            // Like for nested workspaces the target is owned by the tree, which is only modified
+            // while there are no references into it.
+            return unsafe { &*reference };
+        }
+        self.fallback.get_or_init(|| {
+            vfs.read_and_watch_dir(
+                &workspaces.items.read().unwrap(),
+                &dir.absolute_path(vfs).path,
+                Parent::Directory(Arc::downgrade(dir)),
+            )
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -268,20 +318,27 @@ impl Directory {
         workspaces: &Workspaces,
         dir: &'x Arc<Directory>,
     ) -> &'x Entries {
-        dir.entries
-            .get_or_init(|| {
-                DirEntries::Entries(vfs.read_and_watch_dir(
-                    &*workspaces.items.read().unwrap(),
-                    &dir.absolute_path(vfs).path,
-                    Parent::Directory(Arc::downgrade(dir)),
-                ))
-            })
-            .expect()
+        let entries = dir.entries.get_or_init(|| {
+            DirEntries::Entries(vfs.read_and_watch_dir(
+                &*workspaces.items.read().unwrap(),
+                &dir.absolute_path(vfs).path,
+                Parent::Directory(Arc::downgrade(dir)),
+            ))
+        });
+        match entries {
+            DirEntries::Symlink(symlink) => symlink.entries(vfs, workspaces, dir),
+            _ => entries.expect(),
+        }
     }
 
     pub fn is_nested_workspace(&self) -> bool {
         matches!(self.entries.get(), Some(DirEntries::NestedWorkspace(_)))
     }
+
+    /// Whether this is a symlink to a directory that is part of a workspace.
+    pub fn is_symlink_into_workspace(&self) -> bool {
+        matches!(self.entries.get(), Some(DirEntries::Symlink(_)))
+    }
 }
 
 impl Entries {
@@ -426,8 +483,8 @@ impl Entries {
                 {
                     match dir_entries {
                         DirEntries::Entries(entries) => entries.unload_file(vfs, rest),
-                        // The other workspace is responsible for unloading
-                        DirEntries::NestedWorkspace(_) => (),
+                        // The other workspace or the target is responsible for unloading
+                        DirEntries::NestedWorkspace(_) | DirEntries::Symlink(_) => (),
                     }
                 }
             } else if matches!(*entry, DirectoryEntry::File(_)) {
@@ -477,8 +534,9 @@ impl Entries {
                     {
                         match dir_entries {
                             DirEntries::Entries(entries) => entries.delete_directory(vfs, rest),
-                            // The other workspace is responsible for the deletion
-                            DirEntries::NestedWorkspace(_) => Ok(()),
+                            // The other workspace or the target is responsible for the
+                            // deletion
+                            DirEntries::NestedWorkspace(_) | DirEntries::Symlink(_) => Ok(()),
                         }
                     } else {
                         drop(inner);
@@ -521,6 +579,9 @@ impl DirEntries {
                 let reference = &strong.entries as *const Entries;
                 unsafe { &*reference }
             }
+            DirEntries::Symlink(_) => {
+                unreachable!("Symlinked directories are resolved with the workspaces")
+            }
         }
     }
 }
@@ -669,6 +730,56 @@ impl GitignoreFile {
     }
 }
 
+/// Patterns with the syntax of a .gitignore that are not read from a file, e.g. the
+/// `exclude_patterns` of a config. They match paths relative to a workspace.
+#[derive(Debug, Clone)]
+pub struct GitignorePatterns {
+    patterns: Vec<String>,
+    gitignore: Gitignore,
+}
+
+impl GitignorePatterns {
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Later patterns win over earlier ones, e.g. `!build/keep.py` after `build/`.
+    pub fn add(&mut self, pattern: &str) -> Result<(), ignore::Error> {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in self.patterns.iter().map(|p| p.as_str()).chain([pattern]) {
+            builder.add_line(None, pattern)?;
+        }
+        self.gitignore = builder.build()?;
+        self.patterns.push(pattern.into());
+        Ok(())
+    }
+
+    pub fn is_path_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        !self.patterns.is_empty() && self.gitignore.matched(rel_path, is_dir).is_ignore()
+    }
+}
+
+impl Default for GitignorePatterns {
+    fn default() -> Self {
+        Self {
+            patterns: vec![],
+            gitignore: Gitignore::empty(),
+        }
+    }
+}
+
+impl PartialEq for GitignorePatterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+impl Eq for GitignorePatterns {}
+impl std::hash::Hash for GitignorePatterns {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.patterns.hash(state)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
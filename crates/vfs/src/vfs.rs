@@ -1,8 +1,13 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
     ops::BitOrAssign,
     pin::Pin,
-    sync::{Arc, OnceLock},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 use tracing::Level;
@@ -43,7 +48,8 @@ pub struct VfsPanicRecovery<T> {
 }
 
 pub struct Vfs<F> {
-    pub handler: Box<dyn VfsHandler>,
+    // Shared, so that other databases (e.g. the ones of parallel checks) can read the same files.
+    pub handler: Arc<dyn VfsHandler>,
     pub workspaces: Workspaces,
     pub files: InsertOnlyVec<FileState<F>>,
     in_memory_files: HashMap<PathWithScheme, InMemoryKind>,
@@ -55,7 +61,7 @@ enum InMemoryKind {
 }
 
 impl<F: VfsFile> Vfs<F> {
-    pub fn new(handler: Box<dyn VfsHandler>) -> Self {
+    pub fn new(handler: Arc<dyn VfsHandler>) -> Self {
         Self {
             handler,
             workspaces: Default::default(),
@@ -141,7 +147,7 @@ impl<F: VfsFile> Vfs<F> {
         }
 
         Self {
-            handler,
+            handler: handler.into(),
             workspaces,
             files: files.into(),
             in_memory_files: Default::default(),
@@ -180,7 +186,6 @@ impl<F: VfsFile> Vfs<F> {
 
     pub fn load_panic_recovery(
         &mut self,
-        case_sensitive: bool,
         recovery: VfsPanicRecovery<F::Artifacts>,
         new_file: impl Fn(FileIndex, &FileEntry, F::Artifacts) -> F,
     ) {
@@ -194,7 +199,6 @@ impl<F: VfsFile> Vfs<F> {
             );
             let ensured = self.workspaces.ensure_file(
                 &*self.handler,
-                case_sensitive,
                 &recoverable_file.path,
                 // TODO this should not be an empty string, since it could include gitignore, but
                 // that's currently not supported anyway so passing an empty string here is fine.
@@ -223,12 +227,27 @@ impl<F: VfsFile> Vfs<F> {
 
     pub fn add_workspace(&self, root_path: Arc<NormalizedPath>, kind: WorkspaceKind) -> bool {
         self.workspaces
-            .add(&*self.handler, file_scheme(), root_path, kind)
+            .add(&*self.handler, file_scheme(), root_path, kind, None)
     }
 
-    pub fn search_path(&self, case_sensitive: bool, path: &PathWithScheme) -> Option<DirOrFile> {
-        self.workspaces
-            .search_path(&*self.handler, case_sensitive, path)
+    /// Adds the directory of an editable install to the site-packages, but only the given
+    /// top-level packages and modules in it.
+    pub fn add_editable_install(
+        &self,
+        root_path: Arc<NormalizedPath>,
+        packages: Box<[Box<str>]>,
+    ) -> bool {
+        self.workspaces.add(
+            &*self.handler,
+            file_scheme(),
+            root_path,
+            WorkspaceKind::SitePackages,
+            Some(packages),
+        )
+    }
+
+    pub fn search_path(&self, path: &PathWithScheme) -> Option<DirOrFile> {
+        self.workspaces.search_path(&*self.handler, path)
     }
 
     fn invalidate_files(
@@ -395,17 +414,23 @@ impl<F: VfsFile> Vfs<F> {
         }
     }
 
+    pub fn in_memory_files(&self) -> impl Iterator<Item = (&PathWithScheme, FileIndex)> {
+        self.in_memory_files
+            .iter()
+            .filter_map(|(path, kind)| match kind {
+                InMemoryKind::File(file_index) => Some((path, *file_index)),
+                InMemoryKind::Gitignore(_) => None,
+            })
+    }
+
     pub fn store_in_memory_file(
         &mut self,
-        case_sensitive: bool,
         path: PathWithScheme,
         code: Box<str>,
         new_file: impl FnOnce(FileIndex, &FileEntry, Box<str>) -> F,
     ) -> (Option<FileIndex>, InvalidationResult) {
         tracing::info!("Loading in memory file: {}", &path.path);
-        let ensured = self
-            .workspaces
-            .ensure_file(&*self.handler, case_sensitive, &path, &code);
+        let ensured = self.workspaces.ensure_file(&*self.handler, &path, &code);
 
         let file_entry = match ensured.kind {
             AddedKind::FileEntry(file_entry) => file_entry,
@@ -482,15 +507,46 @@ impl<F: VfsFile> Vfs<F> {
 
     fn invalidate_and_unload_in_memory_file(
         &mut self,
-        case_sensitive: bool,
         file_index: FileIndex,
     ) -> InvalidationResult {
         let file_state = &self.files[file_index.0 as usize];
         self.workspaces
-            .unload_file(&*self.handler, case_sensitive, &file_state.path);
+            .unload_file(&*self.handler, &file_state.path);
+        self.invalidate_and_unload_file(file_index)
+    }
+
+    /// Unloads a file to free its memory. The files that depend on it are invalidated and the
+    /// file is loaded again from the file system once it's needed.
+    pub fn evict_file(&mut self, file_index: FileIndex) -> InvalidationResult {
+        debug_assert!(
+            !self
+                .in_memory_files
+                .contains_key(&self.file_state(file_index).path)
+        );
         self.invalidate_and_unload_file(file_index)
     }
 
+    /// The files that are invalidated if the file changes, i.e. the files that depend on it
+    /// directly or indirectly. Returns `None` if a change invalidates the whole database.
+    pub fn dependent_files(&self, file_index: FileIndex) -> Option<Vec<FileIndex>> {
+        let mut dependents = vec![];
+        let mut stack = vec![file_index];
+        while let Some(current) = stack.pop() {
+            let InvalidationDetail::Some(invalidations) =
+                self.file_state(current).file_entry.invalidations.iter()
+            else {
+                return None;
+            };
+            for &dependent in &invalidations {
+                if dependent != file_index && !dependents.contains(&dependent) {
+                    dependents.push(dependent);
+                    stack.push(dependent);
+                }
+            }
+        }
+        Some(dependents)
+    }
+
     fn invalidate_and_unload_file(&mut self, file_index: FileIndex) -> InvalidationResult {
         let file_state = &mut self.files[file_index.0 as usize];
         file_state.unload();
@@ -500,7 +556,6 @@ impl<F: VfsFile> Vfs<F> {
 
     pub fn close_in_memory_file(
         &mut self,
-        case_sensitive: bool,
         path: &PathWithScheme,
         to_file: impl FnOnce(&FileState<F>, FileIndex, Box<str>) -> F,
     ) -> Result<InvalidationResult, &'static str> {
@@ -537,7 +592,7 @@ impl<F: VfsFile> Vfs<F> {
             } else {
                 match removed {
                     InMemoryKind::File(file_index) => {
-                        self.invalidate_and_unload_in_memory_file(case_sensitive, file_index)
+                        self.invalidate_and_unload_in_memory_file(file_index)
                     }
                     InMemoryKind::Gitignore(gitignore) => {
                         gitignore.parent.with_entries(self, |entries| {
@@ -552,7 +607,6 @@ impl<F: VfsFile> Vfs<F> {
 
     pub fn delete_in_memory_files_directory(
         &mut self,
-        case_sensitive: bool,
         dir_path: &PathWithScheme,
         to_file: impl Fn(&FileState<F>, FileIndex, Box<str>) -> F,
     ) -> Result<InvalidationResult, String> {
@@ -572,16 +626,23 @@ impl<F: VfsFile> Vfs<F> {
             .collect();
         let mut invalidation_result = InvalidationResult::InvalidatedFiles;
         for path in in_mem_paths {
-            invalidation_result |= self
-                .close_in_memory_file(case_sensitive, &path, &to_file)
-                .unwrap();
+            invalidation_result |= self.close_in_memory_file(&path, &to_file).unwrap();
         }
-        self.workspaces
-            .delete_directory(&*self.handler, case_sensitive, dir_path)?;
+        self.workspaces.delete_directory(&*self.handler, dir_path)?;
         Ok(invalidation_result)
     }
 
-    pub fn invalidate_path(&mut self, case_sensitive: bool, path: &AbsPath) -> InvalidationResult {
+    pub fn invalidate_path(&mut self, path: &AbsPath) -> InvalidationResult {
+        self.invalidate_path_internal(path, false)
+    }
+
+    /// Like [`Self::invalidate_path`], but directories are always read again, even if they still
+    /// exist. This is used if lots of entries of a directory changed at once.
+    pub fn rescan_path(&mut self, path: &AbsPath) -> InvalidationResult {
+        self.invalidate_path_internal(path, true)
+    }
+
+    fn invalidate_path_internal(&mut self, path: &AbsPath, rescan: bool) -> InvalidationResult {
         let _span = tracing::debug_span!("invalidate_path").entered();
         let in_mem_path = PathWithScheme {
             path: self.handler.normalize_path(path).into_owned(),
@@ -592,6 +653,9 @@ impl<F: VfsFile> Vfs<F> {
             tracing::debug!("Ignored invalidation, because the file is in-memory");
             return InvalidationResult::InvalidatedFiles;
         }
+        if let Some(result) = self.invalidate_archive_workspace(path) {
+            return result;
+        }
         let mut invalidates_db = false;
         let mut all_unloads = FastHashSet::default();
         let mut all_invalidations = FastHashSet::<FileIndex>::default();
@@ -600,7 +664,7 @@ impl<F: VfsFile> Vfs<F> {
         #[allow(unused_variables)]
         if let Some((workspace, parent, replace_name)) = self
             .workspaces
-            .search_potential_parent_for_invalidation(&*self.handler, case_sensitive, path)
+            .search_potential_parent_for_invalidation(&*self.handler, path)
         {
             #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
             {
@@ -665,9 +729,10 @@ impl<F: VfsFile> Vfs<F> {
                 });
             };
             parent.with_entries(self, |in_dir| {
-                if self
-                    .handler
-                    .is_unnecessary_invalidation(path, in_dir.search(replace_name).as_deref())
+                if !rescan
+                    && self
+                        .handler
+                        .is_unnecessary_invalidation(path, in_dir.search(replace_name).as_deref())
                 {
                     tracing::debug!(
                         "Ignored invalidation for {path}, because it is an unnecessary invalidation"
@@ -737,9 +802,7 @@ impl<F: VfsFile> Vfs<F> {
         // After unloading in memory files (especially it they are within unloaded directories need
         // to be ensured).
         for (path, file_index) in ensure_unloaded_in_memory_paths {
-            let ensured = self
-                .workspaces
-                .ensure_file(&*self.handler, case_sensitive, &path, "");
+            let ensured = self.workspaces.ensure_file(&*self.handler, &path, "");
             debug_assert!(ensured.invalidations.is_empty());
             match ensured.kind {
                 AddedKind::FileEntry(file_entry) => {
@@ -756,15 +819,55 @@ impl<F: VfsFile> Vfs<F> {
         InvalidationResult::InvalidatedFiles
     }
 
+    /// An archive on the sys path is the root of a workspace. If it changes, all of its entries
+    /// are read again.
+    fn invalidate_archive_workspace(&mut self, path: &AbsPath) -> Option<InvalidationResult> {
+        let workspace = self
+            .workspaces
+            .items
+            .read()
+            .unwrap()
+            .iter()
+            .find(|workspace| ***workspace.root_path == **path)
+            .cloned()
+            .filter(|_| self.handler.is_archive(path))?;
+        tracing::info!("Read the archive {path} again, because it changed");
+        let mut unloads = vec![];
+        workspace.entries.walk_entries(self, &mut |_, entry| {
+            if let DirectoryEntry::File(file) = entry
+                && let Some(file_index) = file.get_file_index()
+            {
+                unloads.push(file_index)
+            }
+            true
+        });
+        let new_entries = self.handler.read_and_watch_dir(
+            &self.workspaces.items.read().unwrap(),
+            &workspace.root_path,
+            Parent::Workspace(Arc::downgrade(&workspace)),
+        );
+        *workspace.entries.borrow_mut() = std::mem::take(&mut new_entries.borrow_mut());
+        for file_index in unloads {
+            self.files[file_index.0 as usize].unload();
+        }
+        // Modules of the archive might be imported anywhere.
+        Some(InvalidationResult::InvalidatedDb)
+    }
+
     fn matches_current_dir_entry(&self, old: &DirectoryEntry, new: &DirectoryEntry) -> bool {
         match (old, new) {
             (DirectoryEntry::File(old), DirectoryEntry::File(_)) => {
                 if let Some(file_index) = old.get_file_index() {
                     let file_state = self.file_state(file_index);
-                    if let Some(old_code) = file_state.code()
+                    // Files that were unloaded (e.g. evicted) only know the hash of their code.
+                    let old_hash = file_state.content_hash.get();
+                    if (file_state.code().is_some() || old_hash.is_some())
                         && let Some(new_code) = self.handler.read_and_watch_file(&file_state.path)
                     {
-                        return old_code == new_code;
+                        return match file_state.code() {
+                            Some(old_code) => old_code == new_code,
+                            None => old_hash == Some(content_hash(&new_code)),
+                        };
                     }
                 }
                 false
@@ -774,6 +877,20 @@ impl<F: VfsFile> Vfs<F> {
         }
     }
 
+    /// Creates the file again from its current code. This is necessary if something that is not
+    /// part of the code changes how it is interpreted, e.g. when a notebook cell is moved.
+    pub fn reload_file(
+        &mut self,
+        file_index: FileIndex,
+        to_file: impl FnOnce(&FileState<F>, FileIndex, Box<str>) -> F,
+    ) -> InvalidationResult {
+        let Some(code) = self.file_state(file_index).code() else {
+            return InvalidationResult::InvalidatedFiles;
+        };
+        let code = code.into();
+        self.update_file(file_index, code, to_file)
+    }
+
     fn update_file(
         &mut self,
         file_index: FileIndex,
@@ -878,6 +995,31 @@ pub struct FileState<F> {
     path: PathWithScheme,
     file_entry: Arc<FileEntry>,
     file: OnceLock<F>,
+    // The hash of the last loaded code, which is kept when the file is unloaded. This avoids
+    // invalidating the dependents if a file is written without changes, e.g. by `touch`.
+    content_hash: ContentHash,
+}
+
+#[derive(Debug, Default)]
+struct ContentHash(AtomicU64);
+
+impl ContentHash {
+    fn get(&self) -> Option<u64> {
+        // 0 is used for unknown hashes.
+        Some(self.0.load(Ordering::Relaxed)).filter(|&hash| hash != 0)
+    }
+}
+
+impl Clone for ContentHash {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+fn content_hash(code: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+    hasher.finish().max(1)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -912,7 +1054,7 @@ impl PathWithScheme {
         }
     }
 
-    fn is_subfile(&self) -> bool {
+    pub(crate) fn is_subfile(&self) -> bool {
         // Setting an empty scheme is currently used for subfiles
         self.scheme.is_empty()
     }
@@ -921,8 +1063,27 @@ impl PathWithScheme {
         &self.path
     }
 
+    pub fn is_file_scheme(&self) -> bool {
+        **self.scheme == *"file"
+    }
+
+    /// The name of the file in the fallback workspace. Files of other schemes are named by their
+    /// URI, because they might have the same path as a file on disk.
+    pub(crate) fn fallback_name(&self) -> Cow<'_, str> {
+        if self.is_file_scheme() || self.is_subfile() {
+            Cow::Borrowed(&self.path)
+        } else {
+            Cow::Owned(self.as_uri())
+        }
+    }
+
     pub fn as_uri(&self) -> String {
-        if cfg!(windows) && **self.scheme == *"file" {
+        if !self.is_file_scheme() && !self.is_subfile() {
+            // The path of other schemes is everything after the colon of the URI, which is still
+            // percent-encoded.
+            return format!("{}:{}", self.scheme, self.path);
+        }
+        if cfg!(windows) && self.is_file_scheme() {
             let replaced = self
                 .path
                 .strip_prefix(r"\\?")
@@ -944,10 +1105,12 @@ impl<F: VfsFile> FileState<F> {
         if invalidates_db {
             file_entry.invalidations.set_invalidates_db();
         }
+        let content_hash = ContentHash(AtomicU64::new(content_hash(file.code())));
         Self {
             file_entry,
             path,
             file: OnceLock::from(file),
+            content_hash,
         }
     }
 
@@ -963,11 +1126,16 @@ impl<F: VfsFile> FileState<F> {
             file_entry,
             path,
             file: OnceLock::new(),
+            content_hash: ContentHash::default(),
         }
     }
 
     fn update(&self, file: F) {
-        let _maybe_err = self.file.set(file);
+        let hash = content_hash(file.code());
+        let maybe_err = self.file.set(file);
+        if maybe_err.is_ok() {
+            self.content_hash.0.store(hash, Ordering::Relaxed)
+        }
         /*
          * TODO in the future we want to ensure this again, but for now it's known that this is an
          * issue
@@ -0,0 +1,314 @@
+//! Watchman (https://facebook.github.io/watchman/) watches large repositories more efficiently than
+//! the recursive watchers of notify and does not run into inotify limits. We talk to it through
+//! its command line client, which starts the Watchman server if it is not running yet.
+
+use std::{
+    io::{BufReader, Write as _},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crossbeam_channel::Sender;
+use notify::{
+    Event, EventKind,
+    event::{CreateKind, ModifyKind, RemoveKind},
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use utils::FastHashSet;
+
+use crate::NotifyEvent;
+
+const SUBSCRIPTION_NAME: &str = "zuban";
+
+/// Whether Watchman should be used if no watcher was configured. This is the case if the project
+/// has a `.watchmanconfig` or if Watchman is running and already watches the project, e.g.
+/// because another tool uses it.
+pub(crate) fn is_configured_for(root: &Path) -> bool {
+    if root
+        .ancestors()
+        .any(|dir| dir.join(".watchmanconfig").is_file())
+    {
+        return is_available();
+    }
+    #[derive(Deserialize)]
+    struct WatchList {
+        roots: Vec<PathBuf>,
+    }
+    run_command::<WatchList>(&["--no-spawn"], serde_json::json!(["watch-list"]))
+        .is_ok_and(|list| list.roots.iter().any(|watched| root.starts_with(watched)))
+}
+
+fn is_available() -> bool {
+    Command::new("watchman")
+        .arg("version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+pub(crate) struct Watchman {
+    sender: Sender<NotifyEvent>,
+    subscriptions: RwLock<Subscriptions>,
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    active: Vec<Subscription>,
+    // Directories that Watchman refused to watch, e.g. because of its `root_files` restrictions.
+    failed: FastHashSet<PathBuf>,
+}
+
+impl Subscriptions {
+    fn covers(&self, path: &Path) -> bool {
+        self.active.iter().any(|s| path.starts_with(&s.root))
+            || self.failed.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+impl Watchman {
+    pub(crate) fn new(sender: Sender<NotifyEvent>) -> Result<Self, String> {
+        if !is_available() {
+            return Err("the watchman executable was not found".to_string());
+        }
+        Ok(Self {
+            sender,
+            subscriptions: Default::default(),
+        })
+    }
+
+    pub(crate) fn watch(&self, path: &Path) {
+        if self.subscriptions.read().unwrap().covers(path) {
+            return;
+        }
+        // Watchman only watches directories, nonexistent paths are watched through the first
+        // ancestor that exists.
+        let Some(dir) = path.ancestors().find(|p| p.is_dir()) else {
+            return;
+        };
+        let mut subscriptions = self.subscriptions.write().unwrap();
+        if subscriptions.covers(path) {
+            return;
+        }
+        match self.subscribe(dir) {
+            Ok(subscription) => {
+                tracing::debug!("Added Watchman subscription for {:?}", subscription.root);
+                // The new root might contain roots that were subscribed before.
+                subscriptions
+                    .active
+                    .retain(|s| !s.root.starts_with(&subscription.root));
+                subscriptions.active.push(subscription)
+            }
+            Err(err) => {
+                tracing::warn!("Failed to watch {dir:?} with Watchman: {err}");
+                subscriptions.failed.insert(dir.to_owned());
+            }
+        }
+    }
+
+    fn subscribe(&self, dir: &Path) -> Result<Subscription, String> {
+        #[derive(Deserialize)]
+        struct WatchProject {
+            watch: PathBuf,
+        }
+        #[derive(Deserialize)]
+        struct Clock {
+            clock: String,
+        }
+        let root =
+            run_command::<WatchProject>(&[], serde_json::json!(["watch-project", dir]))?.watch;
+        // The clock is queried before the caller reads the files. Subscribing since that clock
+        // reports everything that changes in the meantime, even if the subscription is only
+        // active later.
+        let clock = run_command::<Clock>(&[], serde_json::json!(["clock", &root]))?.clock;
+        let command = serde_json::json!([
+            "subscribe",
+            &root,
+            SUBSCRIPTION_NAME,
+            {
+                "since": clock,
+                "fields": ["name", "exists", "new"],
+            }
+        ]);
+        let mut child = Command::new("watchman")
+            .args(["--no-pretty", "--persistent", "--json-command"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| err.to_string())?;
+        let mut stdin = child.stdin.take().unwrap();
+        writeln!(stdin, "{command}").map_err(|err| err.to_string())?;
+        let stdout = child.stdout.take().unwrap();
+
+        let ended_on_purpose = Arc::new(AtomicBool::new(false));
+        let sender = self.sender.clone();
+        let subscription_root = root.clone();
+        let ended = ended_on_purpose.clone();
+        std::thread::Builder::new()
+            .name("watchman".into())
+            .spawn(move || {
+                let responses = serde_json::Deserializer::from_reader(BufReader::new(stdout))
+                    .into_iter::<SubscriptionResponse>();
+                for response in responses {
+                    let events = match response {
+                        Ok(response) => response.into_events(&subscription_root),
+                        Err(err) => vec![Err(notify::Error::generic(&format!(
+                            "Invalid Watchman response: {err}"
+                        )))],
+                    };
+                    for event in events {
+                        if sender.send(event).is_err() {
+                            // The receiver was dropped.
+                            return;
+                        }
+                    }
+                }
+                if !ended.load(Ordering::SeqCst) {
+                    // Changes might have been missed, so the caller needs to start from scratch.
+                    let _ = sender.send(Err(notify::Error::generic(
+                        "The Watchman subscription ended unexpectedly",
+                    )));
+                }
+            })
+            .map_err(|err| err.to_string())?;
+        Ok(Subscription {
+            root,
+            child,
+            ended_on_purpose,
+        })
+    }
+}
+
+struct Subscription {
+    root: PathBuf,
+    child: Child,
+    ended_on_purpose: Arc<AtomicBool>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.ended_on_purpose.store(true, Ordering::SeqCst);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn run_command<T: DeserializeOwned>(
+    args: &[&str],
+    command: serde_json::Value,
+) -> Result<T, String> {
+    let mut child = Command::new("watchman")
+        .args(args)
+        .args(["--no-pretty", "--json-command"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "{command}").map_err(|err| err.to_string())?;
+    drop(stdin);
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    let response: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|err| err.to_string())?;
+    if let Some(error) = response.get("error") {
+        return Err(error.to_string());
+    }
+    serde_json::from_value(response).map_err(|err| err.to_string())
+}
+
+/// The responses of a subscription, the first one confirms the subscription and does not contain
+/// files.
+#[derive(Deserialize)]
+struct SubscriptionResponse {
+    error: Option<String>,
+    #[serde(default)]
+    is_fresh_instance: bool,
+    #[serde(default)]
+    files: Vec<ChangedFile>,
+}
+
+#[derive(Deserialize)]
+struct ChangedFile {
+    name: String,
+    exists: bool,
+    #[serde(default)]
+    new: bool,
+}
+
+impl SubscriptionResponse {
+    fn into_events(self, root: &Path) -> Vec<NotifyEvent> {
+        if let Some(error) = self.error {
+            return vec![Err(notify::Error::generic(&error))];
+        }
+        if self.is_fresh_instance {
+            // Watchman was restarted and does not know what changed since our clock.
+            return vec![Err(notify::Error::generic(
+                "Watchman lost track of the changes",
+            ))];
+        }
+        self.files
+            .into_iter()
+            .map(|file| {
+                let kind = if !file.exists {
+                    EventKind::Remove(RemoveKind::Any)
+                } else if file.new {
+                    EventKind::Create(CreateKind::Any)
+                } else {
+                    EventKind::Modify(ModifyKind::Any)
+                };
+                Ok(Event::new(kind).add_path(root.join(file.name)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(response: &str) -> Vec<String> {
+        let response: SubscriptionResponse = serde_json::from_str(response).unwrap();
+        response
+            .into_events(Path::new("/root"))
+            .into_iter()
+            .map(|event| match event {
+                Ok(event) => format!("{:?} {:?}", event.kind, event.paths),
+                Err(err) => err.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_subscription_events() {
+        assert!(events(r#"{"version": "2024.01.22.00", "subscribe": "zuban"}"#).is_empty());
+        assert_eq!(
+            events(
+                r#"{"subscription": "zuban", "root": "/root", "is_fresh_instance": false,
+                    "files": [
+                        {"name": "a.py", "exists": true, "new": true},
+                        {"name": "pkg/b.py", "exists": true, "new": false},
+                        {"name": "c.py", "exists": false, "new": false}
+                    ]}"#
+            ),
+            [
+                r#"Create(Any) ["/root/a.py"]"#,
+                r#"Modify(Any) ["/root/pkg/b.py"]"#,
+                r#"Remove(Any) ["/root/c.py"]"#,
+            ]
+        );
+        assert_eq!(
+            events(r#"{"subscription": "zuban", "is_fresh_instance": true, "files": []}"#),
+            ["Watchman lost track of the changes"]
+        );
+        assert_eq!(
+            events(r#"{"error": "unable to resolve root"}"#),
+            ["unable to resolve root"]
+        );
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     vfs::Scheme,
 };
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum WorkspaceKind {
     TypeChecking,
     // Used as a fallback if files outside of workspaces are added to Workspaces
@@ -35,13 +35,14 @@ impl Workspaces {
         scheme: Scheme,
         root: Arc<NormalizedPath>,
         kind: WorkspaceKind,
+        packages: Option<Box<[Box<str>]>>,
     ) -> bool {
         let mut items = self.items.write().unwrap();
         if items.iter().any(|item| item.root_path == root) {
             // The path is already in there
             return false;
         }
-        let workspace = Workspace::new(vfs, &*items, scheme, root, kind);
+        let workspace = Workspace::new(vfs, &*items, scheme, root, kind, packages);
         items.push(workspace);
         true
     }
@@ -58,7 +59,7 @@ impl Workspaces {
             // The path is already in there
             return;
         }
-        items.insert(0, Workspace::new(vfs, items, scheme, root, kind))
+        items.insert(0, Workspace::new(vfs, items, scheme, root, kind, None))
     }
 
     fn inner_items_mut(&mut self) -> &mut Vec<Arc<Workspace>> {
@@ -68,12 +69,11 @@ impl Workspaces {
     fn strip_short_path_in_workspace<'path>(
         &self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &'path PathWithScheme,
     ) -> Option<(Arc<Workspace>, &'path str)> {
         let mut shortest: Option<(Arc<Workspace>, &'path str)> = None;
         for workspace in self.items.read().unwrap().iter() {
-            if let Some(p) = workspace.strip_path_prefix(vfs, case_sensitive, path) {
+            if let Some(p) = workspace.strip_path_prefix(vfs, path) {
                 if shortest
                     .as_ref()
                     .is_none_or(|(_, shortest)| shortest.len() > p.len())
@@ -104,15 +104,14 @@ impl Workspaces {
     pub(crate) fn search_path(
         &self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &PathWithScheme,
     ) -> Option<DirOrFile> {
-        self.strip_short_path_in_workspace(vfs, case_sensitive, path)
+        self.strip_short_path_in_workspace(vfs, path)
             .and_then(|(workspace, rest)| workspace.entries.search_path(vfs, self, rest))
             .or_else(|| {
                 self.iter().find_map(|workspace| {
                     if workspace.kind == WorkspaceKind::Fallback
-                        && let Some(entry) = workspace.entries.search(&path.path)
+                        && let Some(entry) = workspace.entries.search(&path.fallback_name())
                     {
                         let DirectoryEntry::File(f) = &*entry else {
                             unreachable!("Why would this ever be {entry:?} as a fallback?");
@@ -127,7 +126,6 @@ impl Workspaces {
     pub(crate) fn search_potential_parent_for_invalidation<'path>(
         &self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &'path AbsPath,
     ) -> Option<(Arc<Workspace>, Parent, &'path str)> {
         self.items.read().unwrap().iter().find_map(|workspace| {
@@ -136,11 +134,17 @@ impl Workspaces {
                 return None;
             }
             #[allow(unused_mut)]
-            let mut rest = strip_path_prefix(vfs, case_sensitive, path, workspace.root_path());
+            let mut rest =
+                strip_path_prefix(vfs, workspace.case_sensitive, path, workspace.root_path());
             #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
             {
                 rest = rest.or_else(|| {
-                    strip_path_prefix(vfs, case_sensitive, path, &workspace.canonicalized_path)
+                    strip_path_prefix(
+                        vfs,
+                        workspace.case_sensitive,
+                        path,
+                        &workspace.canonicalized_path,
+                    )
                 });
             }
             let mut rest = rest?;
@@ -154,7 +158,11 @@ impl Workspaces {
                     // paths are reachable.
                     rest = new_rest;
                     let entries = if let Some(dir) = current_dir.as_ref() {
-                        dir.entries.get()?.expect()
+                        match dir.entries.get()? {
+                            // Changes of the target are reported for the target's path.
+                            DirEntries::Symlink(_) => return None,
+                            entries => entries.expect(),
+                        }
                     } else {
                         &workspace.entries
                     };
@@ -168,6 +176,9 @@ impl Workspaces {
                         _ => return None,
                     }
                 } else {
+                    if current_dir.is_none() && !workspace.exposes(name) {
+                        return None;
+                    }
                     // Return dir
                     return Some((
                         workspace.clone(),
@@ -185,13 +196,10 @@ impl Workspaces {
     pub(crate) fn ensure_file(
         &mut self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &PathWithScheme,
         code: &str,
     ) -> AddedFile {
-        if let Some((workspace, rest)) =
-            self.strip_short_path_in_workspace(vfs, case_sensitive, path)
-        {
+        if let Some((workspace, rest)) = self.strip_short_path_in_workspace(vfs, path) {
             return ensure_dirs_and_file(
                 Parent::Workspace(Arc::downgrade(&workspace)),
                 &workspace.entries,
@@ -206,7 +214,7 @@ impl Workspaces {
                 return workspace.entries.ensure_file(
                     vfs,
                     Parent::Workspace(Arc::downgrade(workspace)),
-                    &path.path,
+                    &path.fallback_name(),
                     code,
                 );
             }
@@ -214,16 +222,17 @@ impl Workspaces {
         unreachable!("Expected to be able to place the file {path:?}")
     }
 
-    pub(crate) fn unload_file(
-        &mut self,
-        vfs: &dyn VfsHandler,
-        case_sensitive: bool,
-        path: &PathWithScheme,
-    ) {
+    pub(crate) fn unload_file(&mut self, vfs: &dyn VfsHandler, path: &PathWithScheme) {
         // TODO for now we always unload, fix that.
         for workspace in self.inner_items_mut() {
-            if let Some(p) = workspace.strip_path_prefix(vfs, case_sensitive, path) {
+            if let Some(p) = workspace.strip_path_prefix(vfs, path) {
                 workspace.entries.unload_file(vfs, p);
+            } else if workspace.kind == WorkspaceKind::Fallback
+                && !path.is_file_scheme()
+                && !path.is_subfile()
+            {
+                // Files of other schemes only exist while they are opened in the client.
+                workspace.entries.remove_name(&path.fallback_name());
             }
         }
     }
@@ -231,11 +240,10 @@ impl Workspaces {
     pub(crate) fn delete_directory(
         &mut self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &PathWithScheme,
     ) -> Result<(), String> {
         for workspace in self.inner_items_mut() {
-            if let Some(p) = workspace.strip_path_prefix(vfs, case_sensitive, path) {
+            if let Some(p) = workspace.strip_path_prefix(vfs, path) {
                 return workspace.entries.delete_directory(vfs, p);
             }
         }
@@ -324,6 +332,12 @@ pub struct Workspace {
     pub(crate) scheme: Scheme,
     pub entries: Entries,
     pub kind: WorkspaceKind,
+    // Probed for every workspace, because the file systems of macOS and Windows are usually
+    // case-insensitive, but can also be case-sensitive (and vice versa on Linux).
+    pub(crate) case_sensitive: bool,
+    // The directory of an editable install only contains the packages its finder maps, not the
+    // other entries next to them (e.g. tests or other projects).
+    packages: Option<Box<[Box<str>]>>,
 }
 
 impl Workspace {
@@ -333,8 +347,12 @@ impl Workspace {
         scheme: Scheme,
         root_path: Arc<NormalizedPath>,
         kind: WorkspaceKind,
+        packages: Option<Box<[Box<str>]>>,
     ) -> Arc<Self> {
         tracing::debug!("Add workspace {root_path}");
+        let case_sensitive = kind == WorkspaceKind::Fallback
+            || **scheme != *"file"
+            || vfs.is_case_sensitive(root_path.as_ref());
         let workspace;
         #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
         {
@@ -373,6 +391,8 @@ impl Workspace {
                 canonicalized_path: vfs
                     .unchecked_normalized_path(vfs.unchecked_abs_path(&canonicalized_path)),
                 kind,
+                case_sensitive,
+                packages,
             });
         };
         #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
@@ -382,17 +402,26 @@ impl Workspace {
                 scheme,
                 root_path,
                 kind,
+                case_sensitive,
+                packages,
             })
         }
         if kind == WorkspaceKind::Fallback {
             return workspace;
         }
+        // The workspace itself is needed to find the targets of symlinks within it.
+        let mut with_new = workspaces.to_vec();
+        with_new.push(workspace.clone());
         let new_entries = vfs.read_and_watch_dir(
-            workspaces,
+            &with_new,
             &workspace.root_path,
             Parent::Workspace(Arc::downgrade(&workspace)),
         );
         *workspace.entries.borrow_mut() = std::mem::take(&mut new_entries.borrow_mut());
+        workspace
+            .entries
+            .borrow_mut()
+            .retain(|entry| workspace.exposes(entry.name()));
 
         // Workspaces are added as nested workspaces already for workspaces that are contained
         // within this one. But this workspace could be contained by another one, check for that
@@ -426,16 +455,27 @@ impl Workspace {
         matches!(self.kind, WorkspaceKind::SitePackages)
     }
 
+    /// Whether an entry at the top level of the workspace is part of it, which is only not the
+    /// case for entries next to the packages of an editable install.
+    fn exposes(&self, name: &str) -> bool {
+        let module = name
+            .strip_suffix(".py")
+            .or_else(|| name.strip_suffix(".pyi"))
+            .unwrap_or(name);
+        self.packages
+            .as_ref()
+            .is_none_or(|packages| packages.iter().any(|package| **package == *module))
+    }
+
     fn strip_path_prefix<'x>(
         &self,
         vfs: &dyn VfsHandler,
-        case_sensitive: bool,
         path: &'x PathWithScheme,
     ) -> Option<&'x str> {
         if path.scheme != self.scheme {
             return None;
         }
-        strip_path_prefix(vfs, case_sensitive, &path.path, self.root_path())
+        strip_path_prefix(vfs, self.case_sensitive, &path.path, self.root_path())
     }
 
     pub fn root_path_starts_with(&self, path: &NormalizedPath) -> bool {
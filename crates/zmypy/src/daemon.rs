@@ -0,0 +1,547 @@
+//! A long-lived process that keeps a project in memory, so repeated checks only need to check the
+//! files that changed in between, like `dmypy`.
+//!
+//! - `zuban daemon start [ARGS]...` starts a daemon in the background for the current directory,
+//!   `zuban daemon run [ARGS]...` runs it in the foreground.
+//! - `zuban daemon status` and `zuban daemon stop` talk to a running daemon.
+//! - `zuban check [ARGS]...` sends its arguments to the daemon of the current directory if there is
+//!   one. The daemon recreates its project if the arguments differ from the previous check.
+//!
+//! On Unix the daemon listens on a socket, elsewhere on a loopback TCP port. The address is written
+//! together with a random token to a status file in the runtime directory of the user (the cache
+//! directory if there is none), which is only accessible by the user. Every request is a single
+//! line of JSON and is answered by a single line of JSON.
+//!
+//! Checks use the environment variables of the client that change the results (e.g.
+//! `VIRTUAL_ENV`), which are therefore sent with every check request.
+
+use std::{
+    collections::HashMap,
+    hash::{Hash as _, Hasher as _},
+    io::{BufRead as _, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use clap::Parser as _;
+use cli_args::Cli;
+use fnv::FnvHasher;
+use serde_json::{Value, json};
+use vfs::NormalizedPath;
+
+use crate::{CWD_ERROR, watch::WatchSession};
+
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Checking a big project from scratch (e.g. after the options changed) can take a while. If the
+/// daemon does not answer in time, the project is checked without it.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(600);
+/// The environment variables of a client that are used instead of the ones of the daemon.
+const FORWARDED_ENV_VARS: [&str; 7] = [
+    "VIRTUAL_ENV",
+    "CONDA_PREFIX",
+    "MYPYPATH",
+    "PYTHONPATH",
+    "COLUMNS",
+    "MYPY_FORCE_TERMINAL_WIDTH",
+    "GITHUB_ACTIONS",
+];
+
+#[cfg(not(unix))]
+use std::net::{TcpListener as Listener, TcpStream as Stream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener as Listener, UnixStream as Stream};
+
+/// The directory that contains the status files and sockets of the daemons of a user.
+fn daemons_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("zuban")
+        .join("daemons")
+}
+
+/// Every project directory has its own daemon. The name is hashed, because project directories
+/// might be nested deeply, while the paths of sockets are limited to ~100 bytes.
+fn daemon_name(project_dir: &Path) -> String {
+    let mut hasher = FnvHasher::default();
+    project_dir.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn status_path(project_dir: &Path) -> PathBuf {
+    daemons_dir().join(format!("{}.json", daemon_name(project_dir)))
+}
+
+/// The sync files of [`WatchSession::invalidate_pending_changes`] are written to this directory,
+/// so users never see them in their project.
+fn sync_dir(project_dir: &Path) -> PathBuf {
+    daemons_dir().join(format!("{}.sync", daemon_name(project_dir)))
+}
+
+fn create_daemons_dir() -> anyhow::Result<PathBuf> {
+    let dir = daemons_dir();
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt as _;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .with_context(|| format!("Could not create the daemon directory {dir:?}"))?;
+    Ok(dir)
+}
+
+struct Status {
+    pid: u32,
+    /// The path of the socket or the TCP address.
+    address: String,
+    token: String,
+}
+
+impl Status {
+    fn read(project_dir: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(status_path(project_dir)).ok()?;
+        let value: Value = serde_json::from_str(&content).ok()?;
+        Some(Self {
+            pid: value["pid"].as_u64()? as u32,
+            address: value["address"].as_str()?.to_string(),
+            token: value["token"].as_str()?.to_string(),
+        })
+    }
+
+    fn write(&self, project_dir: &Path) -> anyhow::Result<()> {
+        let content = json!({"pid": self.pid, "address": self.address, "token": self.token});
+        let path = status_path(project_dir);
+        // Writing to a temporary file first makes sure that clients never read a partial file.
+        let tmp = path.with_extension(format!("json.{}", self.pid));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            // The token is what prevents other users from sending requests.
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(content.to_string().as_bytes()))
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .context("Could not write the daemon status file")
+    }
+
+    fn connect(&self) -> std::io::Result<Stream> {
+        #[cfg(unix)]
+        {
+            // Connecting to a socket does not block.
+            Stream::connect(&self.address)
+        }
+        #[cfg(not(unix))]
+        {
+            let address = self
+                .address
+                .parse()
+                .map_err(|_| std::io::Error::other("Invalid daemon address"))?;
+            Stream::connect_timeout(&address, REQUEST_TIMEOUT)
+        }
+    }
+
+    fn request(&self, mut request: Value, timeout: Duration) -> anyhow::Result<Value> {
+        request["token"] = self.token.clone().into();
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        writeln!(stream, "{request}")?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+fn current_dir() -> String {
+    let current_dir = std::env::current_dir().expect("Expected a valid working directory");
+    current_dir.into_os_string().into_string().expect(CWD_ERROR)
+}
+
+/// A daemon is running if its status file exists and its process is alive. A daemon that does not
+/// answer in time is usually busy checking and is still considered running. Status files of
+/// daemons that were killed are removed.
+fn running_daemon(project_dir: &Path) -> Option<Status> {
+    let status = Status::read(project_dir)?;
+    match status.request(json!({"command": "status"}), REQUEST_TIMEOUT) {
+        Ok(response) if response["pid"].as_u64() == Some(status.pid as u64) => Some(status),
+        Ok(_) => {
+            tracing::warn!(
+                "The daemon at {} answered with a different pid",
+                status.address
+            );
+            None
+        }
+        Err(err) if is_dead(&status, &err) => {
+            tracing::info!("Removing the status file of a daemon that is not running anymore");
+            std::fs::remove_file(status_path(project_dir)).ok();
+            None
+        }
+        Err(err) => {
+            tracing::info!("The daemon (pid {}) did not answer: {err}", status.pid);
+            Some(status)
+        }
+    }
+}
+
+/// Whether a request failed, because the daemon does not exist anymore.
+fn is_dead(status: &Status, err: &anyhow::Error) -> bool {
+    let refused = err.downcast_ref::<std::io::Error>().is_some_and(|err| {
+        matches!(
+            err.kind(),
+            std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotFound
+        )
+    });
+    refused || !process_exists(status.pid)
+}
+
+/// Processes can only be looked up on Linux, so elsewhere they are assumed to exist.
+fn process_exists(pid: u32) -> bool {
+    !cfg!(target_os = "linux") || Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// The values of [`FORWARDED_ENV_VARS`] in this process.
+fn forwarded_env_vars() -> HashMap<String, Option<String>> {
+    FORWARDED_ENV_VARS
+        .iter()
+        .map(|name| (name.to_string(), std::env::var(name).ok()))
+        .collect()
+}
+
+/// Checks the project with the daemon of the current directory. Returns `None` if there is no
+/// daemon or if the daemon cannot check the project with these arguments.
+pub fn check_with_daemon(args: Vec<String>) -> Option<ExitCode> {
+    let status = running_daemon(Path::new(&current_dir()))?;
+    let color = colored::control::SHOULD_COLORIZE.should_colorize();
+    let request = json!({
+        "command": "check",
+        "args": args,
+        "color": color,
+        "env": forwarded_env_vars(),
+    });
+    let response = match status.request(request, CHECK_TIMEOUT) {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::warn!("Checking with the daemon failed: {err}");
+            return None;
+        }
+    };
+    if response["fallback"].as_bool() == Some(true) {
+        return None;
+    }
+    print!("{}", response["stdout"].as_str().unwrap_or_default());
+    eprint!("{}", response["stderr"].as_str().unwrap_or_default());
+    Some(ExitCode::from(
+        response["exit_code"].as_u64().unwrap_or(2) as u8
+    ))
+}
+
+pub fn daemon_start(args: Vec<String>) -> ExitCode {
+    let dir = current_dir();
+    if let Some(status) = running_daemon(Path::new(&dir)) {
+        eprintln!("A daemon is already running (pid {})", status.pid);
+        return ExitCode::from(2);
+    }
+    let result = std::env::current_exe()
+        .and_then(|exe| {
+            let mut command = Command::new(exe);
+            command
+                .args(["daemon", "run", "--"])
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            #[cfg(unix)]
+            {
+                // The daemon should not be stopped when the terminal that started it is closed.
+                use std::os::unix::process::CommandExt as _;
+                command.process_group(0);
+            }
+            command.spawn()
+        })
+        .context("Could not start the daemon");
+    let mut child = match result {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("{err:#}");
+            return ExitCode::from(2);
+        }
+    };
+    let start = Instant::now();
+    while start.elapsed() < START_TIMEOUT {
+        if let Ok(Some(exit_status)) = child.try_wait() {
+            eprintln!("The daemon exited unexpectedly ({exit_status})");
+            return ExitCode::from(2);
+        }
+        if let Some(status) = running_daemon(Path::new(&dir)) {
+            println!("Daemon started (pid {})", status.pid);
+            return ExitCode::SUCCESS;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    eprintln!("The daemon did not start in time");
+    ExitCode::from(2)
+}
+
+pub fn daemon_status() -> ExitCode {
+    let Some(status) = running_daemon(Path::new(&current_dir())) else {
+        println!("No daemon running");
+        return ExitCode::from(2);
+    };
+    match status.request(json!({"command": "status"}), REQUEST_TIMEOUT) {
+        Ok(response) => {
+            println!(
+                "Daemon running (pid {}) for {}s, {} checks",
+                status.pid,
+                response["uptime_seconds"].as_u64().unwrap_or_default(),
+                response["checks"].as_u64().unwrap_or_default(),
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Could not get the status of the daemon: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+pub fn daemon_stop() -> ExitCode {
+    let Some(status) = running_daemon(Path::new(&current_dir())) else {
+        println!("No daemon running");
+        return ExitCode::from(2);
+    };
+    match status.request(json!({"command": "stop"}), REQUEST_TIMEOUT) {
+        Ok(_) => {
+            println!("Daemon stopped");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Could not stop the daemon: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs the daemon in the foreground until it is stopped.
+pub fn daemon_run(args: Vec<String>) -> ExitCode {
+    match serve(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+pub(crate) struct Daemon {
+    current_dir: String,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    args: Vec<String>,
+    env: HashMap<String, Option<String>>,
+    session: WatchSession,
+    started: Instant,
+    checks: usize,
+}
+
+fn bind(project_dir: &Path) -> anyhow::Result<(Listener, String)> {
+    let dir = create_daemons_dir()?;
+    #[cfg(unix)]
+    {
+        let socket = dir.join(format!("{}.sock", daemon_name(project_dir)));
+        // The socket of a daemon that was killed is still there. Running daemons were already
+        // ruled out by the caller.
+        std::fs::remove_file(&socket).ok();
+        let address = socket
+            .to_str()
+            .with_context(|| format!("Expected valid unicode in {socket:?}"))?
+            .to_string();
+        let listener = Listener::bind(&socket)
+            .with_context(|| format!("Could not listen on the socket {socket:?}"))?;
+        Ok((listener, address))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (project_dir, dir);
+        let listener = Listener::bind((std::net::Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?.to_string();
+        Ok((listener, address))
+    }
+}
+
+fn serve(args: Vec<String>) -> anyhow::Result<()> {
+    let project_dir = PathBuf::from(current_dir());
+    if let Some(status) = running_daemon(&project_dir) {
+        anyhow::bail!("A daemon is already running (pid {})", status.pid)
+    }
+    let mut daemon = Daemon::new(args, current_dir(), None)?;
+    let (listener, address) = bind(&project_dir)?;
+    let status = Status {
+        pid: std::process::id(),
+        address,
+        token: random_token()?,
+    };
+    status.write(&project_dir)?;
+    tracing::info!("Daemon listening on {}", status.address);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!("Daemon connection failed: {err}");
+                continue;
+            }
+        };
+        // A client that does not send a request should not block the daemon.
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok();
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT)).ok();
+        let mut line = String::new();
+        if let Err(err) = BufReader::new(&stream).read_line(&mut line) {
+            tracing::warn!("Reading a daemon request failed: {err}");
+            continue;
+        }
+        let request: Value = serde_json::from_str(&line).unwrap_or_default();
+        if request["token"].as_str() != Some(&status.token) {
+            tracing::warn!("Ignored a daemon request with an invalid token");
+            continue;
+        }
+        let (response, stop) = daemon.handle_request(&request);
+        if let Err(err) = writeln!(stream, "{response}") {
+            tracing::warn!("Writing a daemon response failed: {err}");
+        }
+        if stop {
+            break;
+        }
+    }
+    std::fs::remove_file(status_path(&project_dir)).ok();
+    #[cfg(unix)]
+    std::fs::remove_file(&status.address).ok();
+    Ok(())
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(sync_dir(Path::new(&self.current_dir))).ok();
+    }
+}
+
+impl Daemon {
+    pub(crate) fn new(
+        args: Vec<String>,
+        current_dir: String,
+        typeshed_path: Option<Arc<NormalizedPath>>,
+    ) -> anyhow::Result<Self> {
+        // The daemon was started by a client, whose environment it inherited.
+        let env = forwarded_env_vars();
+        Ok(Self {
+            session: new_session(&args, &env, &current_dir, typeshed_path.clone())?,
+            current_dir,
+            typeshed_path,
+            args,
+            env,
+            started: Instant::now(),
+            checks: 0,
+        })
+    }
+
+    /// Returns the response and whether the daemon should stop.
+    pub(crate) fn handle_request(&mut self, request: &Value) -> (Value, bool) {
+        match request["command"].as_str() {
+            Some("status") => (
+                json!({
+                    "pid": std::process::id(),
+                    "uptime_seconds": self.started.elapsed().as_secs(),
+                    "checks": self.checks,
+                }),
+                false,
+            ),
+            Some("stop") => (json!({"stopped": true}), true),
+            Some("check") => (self.check(request), false),
+            _ => (json!({"error": "Unknown command"}), false),
+        }
+    }
+
+    fn check(&mut self, request: &Value) -> Value {
+        let args: Vec<String> = request["args"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|arg| Some(arg.as_str()?.to_string()))
+            .collect();
+        let env = match request["env"].as_object() {
+            Some(env) => FORWARDED_ENV_VARS
+                .iter()
+                .map(|name| {
+                    let value = env.get(*name).and_then(|value| value.as_str());
+                    (name.to_string(), value.map(|value| value.to_string()))
+                })
+                .collect(),
+            None => self.env.clone(),
+        };
+        if args != self.args || env != self.env {
+            tracing::info!("Recreating the project, because the arguments or environment changed");
+            match new_session(&args, &env, &self.current_dir, self.typeshed_path.clone()) {
+                Ok(session) => {
+                    self.session = session;
+                    self.args = args;
+                    self.env = env;
+                }
+                Err(err) => {
+                    return json!({"stdout": "", "stderr": format!("{err}\n"), "exit_code": 2});
+                }
+            }
+        }
+        colored::control::set_override(request["color"].as_bool() == Some(true));
+        self.session.invalidate_pending_changes();
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        let exit_code = match self.session.check_all(&mut stdout, &mut stderr) {
+            None => return json!({"fallback": true}),
+            Some(Ok(has_errors)) => has_errors as u8,
+            Some(Err(err)) => {
+                writeln!(stderr, "{err}").unwrap();
+                2
+            }
+        };
+        self.checks += 1;
+        json!({
+            "stdout": String::from_utf8_lossy(&stdout),
+            "stderr": String::from_utf8_lossy(&stderr),
+            "exit_code": exit_code,
+        })
+    }
+}
+
+fn new_session(
+    args: &[String],
+    env: &HashMap<String, Option<String>>,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+) -> anyhow::Result<WatchSession> {
+    let mut cli = Cli::try_parse_from(std::iter::once("check").chain(args.iter().map(|a| &**a)))?;
+    // Like in watch mode, the project is kept alive and changed files are invalidated.
+    cli.watch = true;
+    create_daemons_dir()?;
+    WatchSession::new(
+        cli,
+        current_dir.to_string(),
+        typeshed_path,
+        env.clone(),
+        Some(sync_dir(Path::new(current_dir))),
+    )
+}
+
+fn random_token() -> anyhow::Result<String> {
+    let mut bytes = [0; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|err| anyhow::anyhow!("Could not create a token: {err}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
@@ -1,15 +1,28 @@
+mod daemon;
+mod output;
+mod reports;
+mod timing;
+mod watch;
+
+use std::collections::{HashMap, HashSet};
 use std::env::VarError;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Instant;
 
-use cli_args::Cli;
-use colored::Colorize as _;
+use cli_args::{Cli, OutputFormat};
+use colored::{ColoredString, Colorize as _};
 pub use config::DiagnosticConfig;
-pub use zuban_python::Diagnostics;
+pub use daemon::{daemon_run, daemon_start, daemon_status, daemon_stop};
+pub use zuban_python::{AnnotateOptions, Diagnostics, StubgenOptions};
+
+use config::{ProjectOptions, PythonVersion, ReportKind, find_cli_config, user_config_paths};
+use vfs::{NormalizedPath, PathWithScheme, SimpleLocalFS, VfsHandler};
+use zuban_python::{Project, RunCause, Severity, TypeCoverage};
 
-use config::find_cli_config;
-use vfs::{NormalizedPath, SimpleLocalFS, VfsHandler};
-use zuban_python::{Project, RunCause};
+const CWD_ERROR: &str = "Expected valid unicode in working directory";
 
 pub fn run(cli: Cli) -> ExitCode {
     /*
@@ -21,36 +34,712 @@ pub fn run(cli: Cli) -> ExitCode {
     */
 
     let current_dir = std::env::current_dir().expect("Expected a valid working directory");
-    const CWD_ERROR: &str = "Expected valid unicode in working directory";
     let current_dir = current_dir.into_os_string().into_string().expect(CWD_ERROR);
+    if cli.watch {
+        return match watch::run_watch(cli, current_dir) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::from(2)
+            }
+        };
+    }
+    if cli.write_baseline.is_some() {
+        return match write_baseline(cli, &current_dir, None) {
+            Ok(summary) => {
+                println!("{summary}");
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::from(2)
+            }
+        };
+    }
     with_exit_code(cli, current_dir, None)
 }
 
+/// Like [`run`], but checks with the daemon of the current directory if one is running. The
+/// arguments are the ones that `cli` was parsed from.
+pub fn run_with_daemon(cli: Cli, args: Vec<String>) -> ExitCode {
+    // Content from stdin or shadow files is not sent to the daemon.
+    if !cli.watch
+        && cli.write_baseline.is_none()
+        && cli.stdin_filename.is_none()
+        && cli.mypy_options.shadow_file.is_empty()
+        && let Some(exit_code) = daemon::check_with_daemon(args)
+    {
+        return exit_code;
+    }
+    run(cli)
+}
+
 fn with_exit_code(
-    cli: Cli,
+    mut cli: Cli,
     current_dir: String,
     typeshed_path: Option<Arc<NormalizedPath>>,
 ) -> ExitCode {
-    with_diagnostics_from_cli(cli, &current_dir, typeshed_path, |diagnostics, config| {
-        let stdout = std::io::stdout();
+    tracing::info!("Zuban version {}", env!("CARGO_PKG_VERSION"));
+    tracing::info!("Checking in {current_dir}");
+    let start = Instant::now();
+    let in_memory_files = match take_in_memory_files(&mut cli, &current_dir, || {
+        std::io::read_to_string(std::io::stdin())
+    }) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(2);
+        }
+    };
+    let chosen_format = cli.output;
+    let timing_stats = cli.timing_stats.clone();
+    let verbose_timing = cli.verbose_timing;
+    let fail_under_coverage = cli.fail_under_coverage;
+    let max_new_errors = cli.max_new_errors;
+    let (local_fs, options, config) =
+        match options_from_cli(cli, &current_dir, typeshed_path, |name| std::env::var(name)) {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::from(2);
+            }
+        };
+    let stdout = &mut std::io::stdout().lock();
+    let result = if options.settings.python_versions.len() > 1 {
+        if chosen_format.is_some_and(|f| f != OutputFormat::Text)
+            || options.settings.junit_xml.is_some()
+            || !options.settings.report_dirs.is_empty()
+            || timing_stats.is_some()
+            || verbose_timing
+            || fail_under_coverage.is_some()
+            || max_new_errors.is_some()
+        {
+            eprintln!(
+                "Only the text output without reports, timings and thresholds is supported when \
+                 checking multiple Python versions"
+            );
+            return ExitCode::from(2);
+        }
+        write_diagnostics_for_versions(
+            stdout,
+            options,
+            &config,
+            &current_dir,
+            &in_memory_files,
+            true,
+        )
+    } else {
+        let output = CheckOutput {
+            format: chosen_format.unwrap_or_else(|| default_output_format(|n| std::env::var(n))),
+            add_colors: true,
+            junit_xml: options.settings.junit_xml.clone(),
+            report_dirs: options.settings.report_dirs.clone(),
+            timing_stats,
+            verbose_timing,
+            fail_under_coverage,
+            max_new_errors,
+        };
+        let mut project = Project::new(Box::new(local_fs), options, RunCause::LanguageServer);
+        store_in_memory_files(&mut project, &current_dir, &in_memory_files);
+        check_project(
+            &mut project,
+            stdout,
+            &mut std::io::stderr(),
+            &config,
+            &current_dir,
+            &output,
+            start,
+        )
+    };
+    match result {
+        Ok(has_errors) => ExitCode::from(has_errors as u8),
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Returns the files whose content is not read from disk, but from stdin (`--stdin-filename` with
+/// `-` as a file) or from another file (`--shadow-file`). `-` is replaced by the stdin filename.
+fn take_in_memory_files(
+    cli: &mut Cli,
+    current_dir: &str,
+    read_stdin: impl FnOnce() -> std::io::Result<String>,
+) -> anyhow::Result<Vec<(String, Box<str>)>> {
+    let mut files = vec![];
+    let reads_stdin = cli.mypy_options.files.iter().any(|file| file == "-");
+    match cli.stdin_filename.take() {
+        Some(stdin_filename) => {
+            if !reads_stdin {
+                anyhow::bail!("--stdin-filename requires - to be passed as a file");
+            }
+            let code =
+                read_stdin().map_err(|err| anyhow::anyhow!("Could not read from stdin: {err}"))?;
+            for file in &mut cli.mypy_options.files {
+                if file == "-" {
+                    *file = stdin_filename.clone();
+                }
+            }
+            files.push((stdin_filename, code.into()));
+        }
+        None if reads_stdin => {
+            anyhow::bail!("Reading from stdin (-) requires --stdin-filename");
+        }
+        None => (),
+    }
+    for pair in std::mem::take(&mut cli.mypy_options.shadow_file).chunks(2) {
+        let [source, shadow] = pair else {
+            unreachable!("clap always parses two values")
+        };
+        let code = std::fs::read_to_string(Path::new(current_dir).join(shadow))
+            .map_err(|err| anyhow::anyhow!("Could not read the shadow file {shadow}: {err}"))?;
+        files.push((source.clone(), code.into()));
+    }
+    Ok(files)
+}
+
+fn store_in_memory_files(
+    project: &mut Project,
+    current_dir: &str,
+    in_memory_files: &[(String, Box<str>)],
+) {
+    for (path, code) in in_memory_files {
+        let vfs = project.vfs_handler();
+        let path = vfs.absolute_path(&vfs.unchecked_abs_path(current_dir), path);
+        let path = PathWithScheme::with_file_scheme(vfs.unchecked_normalized_path(path));
+        project.store_in_memory_file(path, code.clone());
+    }
+}
+
+/// How the result of checking a project is written and which thresholds decide whether the check
+/// failed.
+#[derive(Clone)]
+struct CheckOutput {
+    format: OutputFormat,
+    /// Colors are only added to the text output if the terminal supports them.
+    add_colors: bool,
+    junit_xml: Option<Arc<NormalizedPath>>,
+    report_dirs: Vec<(ReportKind, Arc<NormalizedPath>)>,
+    timing_stats: Option<PathBuf>,
+    verbose_timing: bool,
+    fail_under_coverage: Option<f64>,
+    max_new_errors: Option<usize>,
+}
+
+/// Writes the diagnostics of a project and the requested reports. Returns whether the check
+/// failed, which is the case if there were errors or a threshold was not met.
+fn check_project(
+    project: &mut Project,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+    config: &DiagnosticConfig,
+    current_dir: &str,
+    output: &CheckOutput,
+    start: Instant,
+) -> anyhow::Result<bool> {
+    let error_count = {
+        let diagnostics = project.diagnostics()?;
+        write_diagnostics(
+            stdout,
+            &diagnostics,
+            config,
+            current_dir,
+            output.format,
+            output.add_colors,
+        )?;
+        if let Some(path) = &output.junit_xml {
+            let report = output::junit_xml(&diagnostics, config, current_dir, start.elapsed());
+            std::fs::write(path.as_ref().as_ref(), report).map_err(|err| {
+                anyhow::anyhow!("Could not write the JUnit XML report to {path}: {err}")
+            })?;
+        }
+        diagnostics.error_count()
+    };
+    let mut failed = error_count > 0;
+    if let Some(max_new_errors) = output.max_new_errors {
+        failed = error_count > max_new_errors;
+        if failed {
+            writeln!(
+                stderr,
+                "Found {error_count} errors, which is more than --max-new-errors {max_new_errors}"
+            )?;
+        }
+    }
+    if let Some(minimum) = output.fail_under_coverage {
+        let mut total = TypeCoverage::default();
+        for (_, coverage) in project.type_coverage()? {
+            total += coverage;
+        }
+        let percentage = total.percentage();
+        if percentage < minimum {
+            writeln!(
+                stderr,
+                "Type coverage {percentage:.2}% is below --fail-under-coverage {minimum}%"
+            )?;
+            failed = true;
+        }
+    }
+    if !output.report_dirs.is_empty() {
+        reports::write_reports(&project.line_precision()?, &output.report_dirs)?;
+    }
+    if output.timing_stats.is_some() || output.verbose_timing {
+        let stats = project.timing_stats();
+        if let Some(path) = &output.timing_stats {
+            timing::write_timing_stats(&stats, path, current_dir)?;
+        }
+        if output.verbose_timing {
+            write!(
+                stderr,
+                "{}",
+                timing::timing_summary(&stats, start.elapsed())
+            )?;
+        }
+    }
+    Ok(failed)
+}
+
+/// GitHub Actions set `GITHUB_ACTIONS`, where workflow commands show the diagnostics as
+/// annotations.
+fn default_output_format(
+    lookup_env_var: impl Fn(&str) -> Result<String, VarError>,
+) -> OutputFormat {
+    match lookup_env_var("GITHUB_ACTIONS") {
+        Ok(value) if value == "true" => OutputFormat::Github,
+        _ => OutputFormat::Text,
+    }
+}
+
+fn write_diagnostics(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    config: &DiagnosticConfig,
+    current_dir: &str,
+    format: OutputFormat,
+    add_colors: bool,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            write_text_diagnostics(writer, diagnostics, config, current_dir, add_colors)
+        }
+        OutputFormat::Json => output::write_json(writer, diagnostics, current_dir),
+        OutputFormat::JsonLines => output::write_json_lines(writer, diagnostics, current_dir),
+        OutputFormat::Sarif => output::write_sarif(writer, diagnostics, current_dir),
+        OutputFormat::Github => output::write_github(writer, diagnostics, current_dir),
+    }
+}
+
+fn write_text_diagnostics(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    config: &DiagnosticConfig,
+    current_dir: &str,
+    add_colors: bool,
+) -> anyhow::Result<()> {
+    // Like in Mypy, the documentation of an error code is only linked once.
+    let without_links = DiagnosticConfig {
+        show_error_code_links: false,
+        ..config.clone()
+    };
+    let mut linked_codes = HashSet::new();
+    let mut errors = 0;
+    for diagnostic in diagnostics.issues.iter() {
+        if diagnostic.severity() == Severity::Error {
+            errors += 1;
+        }
+        // The notes of the last shown error are still shown.
+        if config.error_limit.is_some_and(|limit| errors > limit) {
+            continue;
+        }
+        let config = match linked_codes.insert(diagnostic.mypy_error_code()) {
+            true => config,
+            false => &without_links,
+        };
+        diagnostic.write_colored(writer, config, current_dir, add_colors)?
+    }
+    if let Some(limit) = config.error_limit
+        && errors > limit
+    {
+        let hidden = errors - limit;
+        let s = if hidden == 1 { "" } else { "s" };
+        writeln!(
+            writer,
+            "{hidden} more error{s} not shown (--error-limit {limit})"
+        )?;
+    }
+    for entry in &diagnostics.stale_baseline_entries {
+        let message = stale_baseline_entry_message(diagnostics, entry, current_dir);
+        writeln!(writer, "{message}")?;
+    }
+    if config.error_summary {
+        let summary = colored_summary(
+            diagnostics.summary(),
+            diagnostics.error_count() > 0,
+            add_colors,
+        );
+        writeln!(writer, "{summary}")?;
+    }
+    Ok(())
+}
+
+/// The summary is red if there are errors and green otherwise.
+fn colored_summary(summary: String, has_errors: bool, add_colors: bool) -> ColoredString {
+    let summary = match has_errors {
+        true => summary.red().bold(),
+        false => summary.green().bold(),
+    };
+    paint(summary, add_colors)
+}
+
+fn paint(colored: ColoredString, add_colors: bool) -> ColoredString {
+    match add_colors {
+        true => colored,
+        false => colored.clear(),
+    }
+}
+
+/// A diagnostic of a check against multiple Python versions together with the versions it was
+/// reported for.
+struct VersionedDiagnostic {
+    position: (String, usize, usize),
+    plain: String,
+    /// The number of identical diagnostics before this one that were reported for the same
+    /// version, which pairs up repeated diagnostics of different versions.
+    occurrence: usize,
+    colored: Vec<u8>,
+    is_error: bool,
+    versions: Vec<PythonVersion>,
+}
+
+/// Checks the project once per Python version and merges the diagnostics. Diagnostics that are
+/// reported for all versions are printed like in a normal run, the others are tagged with the
+/// versions they are reported for. Returns whether there were any errors.
+fn write_diagnostics_for_versions(
+    writer: &mut impl Write,
+    mut options: ProjectOptions,
+    config: &DiagnosticConfig,
+    current_dir: &str,
+    in_memory_files: &[(String, Box<str>)],
+    add_colors: bool,
+) -> anyhow::Result<bool> {
+    let versions = std::mem::take(&mut options.settings.python_versions);
+    options.settings.python_version = versions.first().copied();
+    let mut project = Project::new(
+        Box::new(SimpleLocalFS::without_watcher()),
+        options,
+        RunCause::LanguageServer,
+    );
+    store_in_memory_files(&mut project, current_dir, in_memory_files);
+    let mut all: Vec<VersionedDiagnostic> = vec![];
+    let mut stale_baseline_messages: Vec<String> = vec![];
+    let mut summaries = vec![];
+    for (i, &version) in versions.iter().enumerate() {
+        tracing::info!("Checking against Python {version}");
+        if i > 0 {
+            // The parsed files are reused, but the Python version changes how typeshed and all
+            // version checks are inferred.
+            project.set_python_version(version);
+        }
+        let diagnostics = project.diagnostics()?;
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
         for diagnostic in diagnostics.issues.iter() {
+            let plain = diagnostic.as_string(config, Some(current_dir));
+            let occurrence = occurrences.entry(plain.clone()).or_default();
+            let mut colored = vec![];
+            diagnostic.write_colored(&mut colored, config, current_dir, add_colors)?;
+            let start = diagnostic.start_position();
+            all.push(VersionedDiagnostic {
+                position: (
+                    diagnostic.original_file_path().to_string(),
+                    start.line_zero_based(),
+                    start.code_points_column(),
+                ),
+                plain,
+                occurrence: *occurrence,
+                colored,
+                is_error: diagnostic.severity() == Severity::Error,
+                versions: vec![version],
+            });
+            *occurrence += 1;
+        }
+        for entry in &diagnostics.stale_baseline_entries {
+            stale_baseline_messages.push(stale_baseline_entry_message(
+                &diagnostics,
+                entry,
+                current_dir,
+            ));
+        }
+        summaries.push((
+            format!("Python {version}: {}", diagnostics.summary()),
+            diagnostics.error_count() > 0,
+        ));
+    }
+
+    // The sort is stable, so the versions of identical diagnostics stay in order when they are
+    // merged.
+    let key = |d: &VersionedDiagnostic| (d.position.clone(), d.plain.clone(), d.occurrence);
+    all.sort_by_cached_key(key);
+    all.dedup_by(|later, earlier| {
+        let is_same = later.position == earlier.position
+            && later.plain == earlier.plain
+            && later.occurrence == earlier.occurrence;
+        if is_same {
+            earlier.versions.append(&mut later.versions);
+        }
+        is_same
+    });
+    stale_baseline_messages.sort();
+    stale_baseline_messages.dedup();
+    for diagnostic in &all {
+        if diagnostic.versions.len() == versions.len() {
+            writer.write_all(&diagnostic.colored)?;
+            continue;
+        }
+        let tag = format!(
+            "  (Python {})",
             diagnostic
-                .write_colored(&mut stdout.lock(), config, &current_dir)
-                .unwrap()
+                .versions
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        // The tag belongs to the first line, notes and pretty output follow it.
+        let first_line_end = diagnostic
+            .colored
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(diagnostic.colored.len());
+        writer.write_all(&diagnostic.colored[..first_line_end])?;
+        write!(writer, "{}", paint(tag.cyan(), add_colors))?;
+        writer.write_all(&diagnostic.colored[first_line_end..])?;
+    }
+    for message in &stale_baseline_messages {
+        writeln!(writer, "{message}")?;
+    }
+    if config.error_summary {
+        for (summary, has_errors) in summaries {
+            writeln!(
+                writer,
+                "{}",
+                colored_summary(summary, has_errors, add_colors)
+            )?;
         }
-        if config.error_summary {
-            if diagnostics.error_count() > 0 {
-                println!("{}", diagnostics.summary().red().bold());
-            } else {
-                println!("{}", diagnostics.summary().green().bold());
-            }
+    }
+    Ok(all.iter().any(|d| d.is_error))
+}
+
+fn stale_baseline_entry_message(
+    diagnostics: &Diagnostics,
+    entry: &zuban_python::BaselineEntry,
+    current_dir: &str,
+) -> String {
+    format!(
+        "{}: note: Baseline entry does not match an error anymore: {}  [{}]",
+        output::baseline_entry_path(diagnostics, entry, current_dir),
+        entry.message,
+        entry.code
+    )
+}
+
+/// Writes all diagnostics to the baseline file of --write-baseline and returns a summary.
+fn write_baseline(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+) -> anyhow::Result<String> {
+    let baseline_path = Path::new(current_dir).join(cli.write_baseline.as_ref().unwrap());
+    let (mut project, _) =
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name))?;
+    ensure_single_python_version(&project, "--write-baseline")?;
+    let abs_baseline_path = project
+        .vfs_handler()
+        .unchecked_abs_path(baseline_path.to_str().expect(CWD_ERROR));
+    let baseline = project.diagnostics()?.to_baseline(&abs_baseline_path);
+    std::fs::write(&baseline_path, baseline.to_json()).map_err(|err| {
+        anyhow::anyhow!(
+            "Could not write baseline to {}: {err}",
+            baseline_path.display()
+        )
+    })?;
+    Ok(format!(
+        "Wrote {} diagnostics to the baseline {}",
+        baseline.entry_count(),
+        baseline_path.display()
+    ))
+}
+
+/// Prints the type coverage of all files that would be checked, either as a table or as JSON.
+pub fn run_coverage(cli: Cli, json: bool) -> ExitCode {
+    let current_dir = std::env::current_dir().expect("Expected a valid working directory");
+    let current_dir = current_dir.into_os_string().into_string().expect(CWD_ERROR);
+    match coverage_report(cli, &current_dir, None, json) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
         }
-        ExitCode::from((diagnostics.error_count() > 0) as u8)
-    })
-    .unwrap_or_else(|err| {
-        eprintln!("{err}");
-        ExitCode::from(2)
-    })
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn coverage_report(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    json: bool,
+) -> anyhow::Result<String> {
+    let (mut project, _) =
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name))?;
+    ensure_single_python_version(&project, "coverage")?;
+    let coverage = project.type_coverage()?;
+    let vfs_handler = project.vfs_handler();
+    let mut files: Vec<_> = coverage
+        .into_iter()
+        .map(|(path, coverage)| {
+            let path = vfs_handler
+                .path_relative_to(path.path(), Path::new(current_dir))
+                .unwrap_or_else(|| path.path().to_string());
+            (path, coverage)
+        })
+        .collect();
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut total = TypeCoverage::default();
+    for (_, coverage) in &files {
+        total += *coverage;
+    }
+    if json {
+        let to_json = |coverage: &TypeCoverage| {
+            serde_json::json!({
+                "expressions": coverage.expressions,
+                "precise": coverage.precise,
+                "any": coverage.any,
+                "percentage": coverage.percentage(),
+            })
+        };
+        let files: Vec<_> = files
+            .iter()
+            .map(|(path, coverage)| {
+                let mut value = to_json(coverage);
+                value["path"] = path.as_str().into();
+                value
+            })
+            .collect();
+        let report = serde_json::json!({"files": files, "total": to_json(&total)});
+        return Ok(format!("{}\n", serde_json::to_string_pretty(&report)?));
+    }
+    let path_width = files
+        .iter()
+        .map(|(path, _)| path.len())
+        .chain(["File".len(), "Total".len()])
+        .max()
+        .unwrap();
+    let line = |name: &str, [expressions, precise, any, percentage]: [&str; 4]| {
+        format!("{name:<path_width$}  {expressions:>11}  {precise:>7}  {any:>7}  {percentage:>8}\n")
+    };
+    let coverage_line = |name: &str, coverage: &TypeCoverage| {
+        line(
+            name,
+            [
+                &coverage.expressions.to_string(),
+                &coverage.precise.to_string(),
+                &coverage.any.to_string(),
+                &format!("{:.2}%", coverage.percentage()),
+            ],
+        )
+    };
+    let mut report = line("File", ["Expressions", "Precise", "Any", "Coverage"]);
+    let separator = "-".repeat(report.trim_end().len());
+    report += &separator;
+    report.push('\n');
+    for (path, coverage) in &files {
+        report += &coverage_line(path, coverage);
+    }
+    report += &separator;
+    report.push('\n');
+    report += &coverage_line("Total", &total);
+    Ok(report)
+}
+
+/// Adds the annotations of inlay hints to all files that would be checked.
+pub fn run_annotate(cli: Cli, options: AnnotateOptions) -> ExitCode {
+    let current_dir = std::env::current_dir().expect("Expected a valid working directory");
+    let current_dir = current_dir.into_os_string().into_string().expect(CWD_ERROR);
+    match write_annotations(cli, &current_dir, None, options) {
+        Ok(summary) => {
+            println!("{summary}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Rewrites the files in place and returns a summary.
+fn write_annotations(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    options: AnnotateOptions,
+) -> anyhow::Result<String> {
+    let (mut project, _) =
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name))?;
+    ensure_single_python_version(&project, "annotate")?;
+    let changed = project.annotate(options)?;
+    for (path, new_code) in &changed {
+        let path = path.path();
+        std::fs::write(path.as_ref(), new_code)
+            .map_err(|err| anyhow::anyhow!("Could not write {path}: {err}"))?;
+    }
+    let plural = if changed.len() == 1 { "" } else { "s" };
+    Ok(format!("Annotated {} file{plural}", changed.len()))
+}
+
+/// Writes stubs for all files that would be checked to `output_dir`.
+pub fn run_stubgen(cli: Cli, output_dir: &Path, options: StubgenOptions) -> ExitCode {
+    let current_dir = std::env::current_dir().expect("Expected a valid working directory");
+    let current_dir = current_dir.into_os_string().into_string().expect(CWD_ERROR);
+    match write_stubs(cli, &current_dir, None, output_dir, options) {
+        Ok(summary) => {
+            println!("{summary}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Writes the stubs and returns a summary.
+fn write_stubs(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    output_dir: &Path,
+    options: StubgenOptions,
+) -> anyhow::Result<String> {
+    let output_dir = Path::new(current_dir).join(output_dir);
+    let (mut project, _) =
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name))?;
+    ensure_single_python_version(&project, "stubgen")?;
+    let stubs = project.generate_stubs(options)?;
+    for stub in &stubs {
+        let path = output_dir.join(&stub.path);
+        std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, &stub.code))
+            .map_err(|err| anyhow::anyhow!("Could not write stub {}: {err}", path.display()))?;
+    }
+    let plural = if stubs.len() == 1 { "" } else { "s" };
+    Ok(format!(
+        "Generated {} stub{plural} in {}",
+        stubs.len(),
+        output_dir.display()
+    ))
 }
 
 pub fn with_diagnostics_from_cli<T>(
@@ -62,28 +751,69 @@ pub fn with_diagnostics_from_cli<T>(
     tracing::info!("Zuban version {}", env!("CARGO_PKG_VERSION"));
     tracing::info!("Checking in {current_dir}");
     let (mut project, diagnostic_config) =
-        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name));
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name))?;
     let diagnostics = project.diagnostics();
     Ok(callback(diagnostics?, &diagnostic_config))
 }
 
+/// Only checks support `python_versions` with multiple versions, everything else would silently
+/// use the first one.
+fn ensure_single_python_version(project: &Project, command: &str) -> anyhow::Result<()> {
+    if project.settings().python_versions.len() > 1 {
+        anyhow::bail!(
+            "{command} does not support multiple Python versions, choose one with \
+             --python-version"
+        )
+    }
+    Ok(())
+}
+
 fn project_from_cli(
     cli: Cli,
     current_dir: &str,
     typeshed_path: Option<Arc<NormalizedPath>>,
     lookup_env_var: impl Fn(&str) -> Result<String, VarError>,
-) -> (Project, DiagnosticConfig) {
+) -> anyhow::Result<(Project, DiagnosticConfig)> {
+    let (local_fs, options, diagnostic_config) =
+        options_from_cli(cli, current_dir, typeshed_path, lookup_env_var)?;
+    Ok((
+        Project::new(Box::new(local_fs), options, RunCause::LanguageServer),
+        diagnostic_config,
+    ))
+}
+
+fn options_from_cli(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    lookup_env_var: impl Fn(&str) -> Result<String, VarError>,
+) -> anyhow::Result<(SimpleLocalFS, ProjectOptions, DiagnosticConfig)> {
+    let watch = cli.watch;
     let local_fs = SimpleLocalFS::without_watcher();
     let current_dir = local_fs.unchecked_abs_path(current_dir);
+    let user_config_paths = match cli.no_user_config {
+        true => vec![],
+        false => user_config_paths(|name| lookup_env_var(name).ok()),
+    };
     let mut found = find_cli_config(
         &local_fs,
         current_dir.clone(),
         cli.mypy_options.config_file.as_deref(),
+        &user_config_paths,
         // Set the default to not mypy compatible, at least for now
         cli.mode(),
     )
     .unwrap_or_else(|err| panic!("Problem parsing Mypy config: {err}"));
     let mut options = found.project_options;
+    for note in &found.notes {
+        eprintln!("{note}");
+    }
+    for unknown in &options.settings.unknown_options {
+        match &unknown.config_path {
+            Some(path) => eprintln!("{path}: {}", unknown.message()),
+            None => eprintln!("{}", unknown.message()),
+        }
+    }
     if let Some(typeshed_path) = typeshed_path {
         options.settings.typeshed_path = Some(typeshed_path);
     }
@@ -91,9 +821,13 @@ fn project_from_cli(
     options.settings.try_to_apply_environment_variables(
         &local_fs,
         &found.most_probable_base,
-        lookup_env_var,
+        &lookup_env_var,
     );
+    found.diagnostic_config.terminal_width = terminal_width(&lookup_env_var);
 
+    // The coverage needs the inferred types of all modules, which are not cached.
+    let needs_all_types = cli.fail_under_coverage.is_some();
+    let project_dir = found.most_probable_base.clone();
     cli_args::apply_flags(
         &local_fs,
         &mut options,
@@ -102,108 +836,1849 @@ fn project_from_cli(
         cli,
         found.most_probable_base,
         found.config_path.as_deref(),
-    );
+    )?;
+    let settings = &mut options.settings;
+    if needs_all_types || !settings.report_dirs.is_empty() {
+        settings.incremental = false;
+    }
+    if settings.incremental && settings.cache_dir.is_none() {
+        settings.cache_dir =
+            Some(local_fs.normalize_rc_path(local_fs.join(&project_dir, ".zuban_cache")));
+    }
 
-    (
-        Project::new(Box::new(local_fs), options, RunCause::LanguageServer),
-        found.diagnostic_config,
-    )
+    // The config decides how files are watched.
+    let local_fs = match watch {
+        true => SimpleLocalFS::with_watcher_kind(
+            settings.file_watcher,
+            Path::new(&**project_dir),
+            Box::new(|_| ()),
+        ),
+        false => local_fs,
+    };
+    Ok((local_fs, options, found.diagnostic_config))
+}
+
+/// The width of the terminal that `--pretty` wraps messages to. Like in Mypy it can be set with
+/// `MYPY_FORCE_TERMINAL_WIDTH`.
+fn terminal_width(lookup_env_var: impl Fn(&str) -> Result<String, VarError>) -> usize {
+    ["MYPY_FORCE_TERMINAL_WIDTH", "COLUMNS"]
+        .into_iter()
+        .find_map(|name| lookup_env_var(name).ok()?.parse().ok())
+        .filter(|&width| width > 0)
+        .unwrap_or(80)
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::time::Duration;
 
     use clap::Parser as _;
 
-    use super::*;
+    use super::*;
+
+    fn diagnostics_with_env_lookup(
+        cli: Cli,
+        directory: &str,
+        lookup_env_var: impl Fn(&str) -> Result<String, VarError>,
+    ) -> anyhow::Result<Vec<String>> {
+        let (mut project, diagnostic_config) = project_from_cli(
+            cli,
+            directory,
+            Some(test_utils::typeshed_path()),
+            lookup_env_var,
+        )?;
+        let diagnostics = project.diagnostics();
+        let mut diagnostics = diagnostics?
+            .issues
+            .iter()
+            .map(|d| {
+                let mut s = d.as_string(&diagnostic_config, Some(directory));
+                if cfg!(windows) {
+                    s = s.replace('\\', "/")
+                }
+                s
+            })
+            .collect::<Vec<_>>();
+        diagnostics.sort();
+        Ok(diagnostics)
+    }
+
+    fn diagnostics(cli: Cli, directory: &str) -> Vec<String> {
+        diagnostics_with_env_lookup(cli, directory, |_| Err(VarError::NotPresent)).unwrap()
+    }
+
+    fn expect_diagnostics_error(cli: Cli, directory: &str) -> String {
+        diagnostics_with_env_lookup(cli, directory, |_| Err(VarError::NotPresent))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.mypy]
+            strict = true
+
+            [file foo.py]
+            1()
+            def foo(x) -> int: return 1
+
+            [file README]
+            Test that readme's are not type checked
+            "#,
+            false,
+        );
+        let d = || diagnostics(Cli::parse_from(vec![""]), test_dir.path());
+
+        const NOT_CALLABLE: &str = "foo.py:1: error: \"int\" not callable  [operator]";
+        assert_eq!(
+            d(),
+            vec![
+                NOT_CALLABLE.to_string(),
+                "foo.py:2: error: Function is missing a type annotation for one or more arguments  [no-untyped-def]"
+                    .to_string()
+            ]
+        );
+
+        test_dir.remove_file("pyproject.toml");
+
+        assert_eq!(d(), vec![NOT_CALLABLE.to_string()]);
+    }
+
+    #[test]
+    fn test_type_coverage() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            from typing import Any
+            def f(x: int, y: Any) -> None:
+                x + 1
+                y.foo
+                if isinstance(x, str):
+                    x.upper()
+
+            [file bar/baz.py]
+            x = [1]
+            "#,
+            false,
+        );
+        let report = |json| {
+            coverage_report(
+                Cli::parse_from([""]),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                json,
+            )
+            .unwrap()
+        };
+        let report_text = report(false);
+        let report_text = if cfg!(windows) {
+            report_text.replace('\\', "/")
+        } else {
+            report_text
+        };
+        assert_eq!(
+            report_text,
+            "\
+File        Expressions  Precise      Any  Coverage
+---------------------------------------------------
+bar/baz.py            2        2        0   100.00%
+foo.py                5        4        1    80.00%
+---------------------------------------------------
+Total                 7        6        1    85.71%
+"
+        );
+        let json: serde_json::Value = serde_json::from_str(&report(true)).unwrap();
+        assert_eq!(
+            json["total"],
+            serde_json::json!({"expressions": 7, "precise": 6, "any": 1, "percentage": 600.0 / 7.0})
+        );
+        assert_eq!(json["files"][1]["path"], "foo.py");
+        assert_eq!(json["files"][1]["any"], 1);
+    }
+
+    #[test]
+    fn test_stubgen() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pkg/__init__.py]
+            from typing import TypeVar, overload
+
+            T = TypeVar("T")
+            x = 1
+            _private = 2
+
+            @overload
+            def f(x: int) -> int: ...
+            @overload
+            def f(x: str) -> str: ...
+            def f(x):
+                return x
+
+            class A:
+                def __init__(self, x: int, y=""):
+                    self.x = x
+
+                def method(self, *, flag=True):
+                    return [1]
+
+            [file pkg/mod.py]
+            import sys
+            from typing import TYPE_CHECKING
+
+            if TYPE_CHECKING:
+                from collections.abc import Sequence
+
+            if sys.version_info >= (3, 11):
+                def h(x: Sequence[int]) -> int: return 1
+            else:
+                def h(x): return 1
+
+            try:
+                import json
+            except ImportError:
+                json = None
+
+            def g(): pass
+
+            if __name__ == "__main__":
+                g()
+            "#,
+            false,
+        );
+        let stubs = |options| {
+            let summary = write_stubs(
+                Cli::parse_from(["", "pkg"]),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                Path::new("out"),
+                options,
+            )
+            .unwrap();
+            assert!(summary.starts_with("Generated 2 stubs in "));
+            let read = |path| std::fs::read_to_string(Path::new(test_dir.path()).join(path));
+            (
+                read("out/pkg/__init__.pyi").unwrap(),
+                read("out/pkg/mod.pyi").unwrap(),
+            )
+        };
+        let (init, module) = stubs(StubgenOptions::default());
+        assert_eq!(
+            init,
+            "\
+from _typeshed import Incomplete
+
+from typing import TypeVar, overload
+
+T = TypeVar(\"T\")
+x: Incomplete
+
+@overload
+def f(x: int) -> int: ...
+@overload
+def f(x: str) -> str: ...
+
+class A:
+    x: Incomplete
+
+    def __init__(self, x: int, y=...) -> None: ...
+    def method(self, *, flag=...): ...
+"
+        );
+        assert_eq!(
+            module,
+            "\
+import sys
+from typing import TYPE_CHECKING
+
+if TYPE_CHECKING:
+    from collections.abc import Sequence
+
+if sys.version_info >= (3, 11):
+    def h(x: Sequence[int]) -> int: ...
+else:
+    def h(x): ...
+
+import json
+
+def g(): ...
+"
+        );
+
+        let (init, _) = stubs(StubgenOptions {
+            include_private: true,
+            infer_types: true,
+        });
+        assert_eq!(
+            init,
+            "\
+from typing import TypeVar, overload
+
+T = TypeVar(\"T\")
+x: int
+_private: int
+
+@overload
+def f(x: int) -> int: ...
+@overload
+def f(x: str) -> str: ...
+
+class A:
+    x: int
+
+    def __init__(self, x: int, y: str = ...) -> None: ...
+    def method(self, *, flag: bool = ...) -> list[int]: ...
+"
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            def f():
+                return 1
+
+            def g():
+                from pathlib import Path
+                x = [f()]
+                return Path(".")
+
+            [file bar.py]
+            def h() -> int:
+                return 1
+            "#,
+            false,
+        );
+        let annotate = |options| {
+            write_annotations(
+                Cli::parse_from([""]),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                options,
+            )
+            .unwrap()
+        };
+        let read = || std::fs::read_to_string(Path::new(test_dir.path()).join("foo.py")).unwrap();
+        let original = read();
+
+        let summary = annotate(AnnotateOptions {
+            only_obvious: true,
+            add_imports: false,
+        });
+        assert_eq!(summary, "Annotated 1 file");
+        assert!(read().contains("def f() -> int:\n"));
+        assert!(read().contains("    x = [f()]\n"));
+
+        test_dir.write_file("foo.py", &original);
+        annotate(AnnotateOptions::default());
+        assert!(read().contains("    x: list[int] = [f()]\n"));
+        assert!(read().contains("def g():\n"));
+
+        test_dir.write_file("foo.py", &original);
+        annotate(AnnotateOptions {
+            only_obvious: false,
+            add_imports: true,
+        });
+        assert!(read().starts_with("from pathlib import Path\n\ndef f() -> int:\n"));
+        assert!(read().contains("def g() -> Path:\n"));
+    }
+
+    #[test]
+    fn test_error_code_links() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            1()
+            x: int = ""
+            2()
+            "#,
+            false,
+        );
+        let (mut project, config) = project_from_cli(
+            Cli::parse_from(["", "--show-error-code-links", "foo.py"]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        let diagnostics = project.diagnostics().unwrap();
+        let mut output = vec![];
+        write_diagnostics(
+            &mut output,
+            &diagnostics,
+            &config,
+            test_dir.path(),
+            OutputFormat::Text,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+            foo.py:1: error: \"int\" not callable  [operator]\n\
+            foo.py:1: note: See https://mypy.readthedocs.io/en/stable/_refs.html#code-operator for more info\n\
+            foo.py:2: error: Incompatible types in assignment (expression has type \"str\", variable has type \"int\")  [assignment]\n\
+            foo.py:3: error: \"int\" not callable  [operator]\n\
+            Found 3 errors in 1 file (checked 1 source file)\n"
+        );
+    }
+
+    #[test]
+    fn test_error_code_severity() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.zuban.severity]
+            assignment = "warning"
+
+            [file foo.py]
+            x: int = ""
+            "#,
+            false,
+        );
+        let (mut project, _) = project_from_cli(
+            Cli::parse_from([""]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        let diagnostics = project.diagnostics().unwrap();
+        assert_eq!(diagnostics.issues.len(), 1);
+        assert_eq!(diagnostics.issues[0].severity(), Severity::Warning);
+        assert_eq!(diagnostics.error_count(), 0);
+        assert_eq!(
+            diagnostics.summary(),
+            "Success: no issues found in 1 source file"
+        );
+    }
+
+    #[test]
+    fn test_multiple_python_versions() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.zuban]
+            python_versions = ["3.9", "3.12"]
+
+            [file foo.py]
+            import sys
+            if sys.version_info >= (3, 11):
+                x: int = ""
+            1()
+            from typing import Self
+            "#,
+            false,
+        );
+        let check = |args: &[&str]| {
+            let (_, options, config) = options_from_cli(
+                Cli::parse_from(args),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let mut output = vec![];
+            let has_errors = write_diagnostics_for_versions(
+                &mut output,
+                options,
+                &config,
+                test_dir.path(),
+                &[],
+                false,
+            )
+            .unwrap();
+            assert!(has_errors);
+            String::from_utf8(output).unwrap().replace('\\', "/")
+        };
+        let expected = "\
+            foo.py:3: error: Incompatible types in assignment (expression has type \"str\", variable has type \"int\")  [assignment]  (Python 3.12)\n\
+            foo.py:4: error: \"int\" not callable  [operator]\n\
+            foo.py:5: error: Module \"typing\" has no attribute \"Self\"  [attr-defined]  (Python 3.9)\n\
+            Python 3.9: Found 2 errors in 1 file (checked 1 source file)\n\
+            Python 3.12: Found 2 errors in 1 file (checked 1 source file)\n";
+        assert_eq!(check(&[""]), expected);
+        assert_eq!(
+            check(&["", "--python-version", "3.9", "--python-version", "3.12"]),
+            expected
+        );
+
+        // A single version on the command line overwrites the versions of the config.
+        let (_, options, _) = options_from_cli(
+            Cli::parse_from(["", "--python-version", "3.10"]),
+            test_dir.path(),
+            None,
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        assert!(options.settings.python_versions.is_empty());
+        assert_eq!(
+            options.settings.python_version,
+            Some(PythonVersion::new(3, 10))
+        );
+
+        // Other commands would silently use only one of the versions.
+        let err = coverage_report(
+            Cli::parse_from([""]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "coverage does not support multiple Python versions, choose one with --python-version"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_plugins() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.zuban]
+            plugins = ["pydantic.mypy", "custom_plugin.py:plugin"]
+
+            [file custom_plugin.py]
+
+            [file foo.py]
+            1()
+            "#,
+            false,
+        );
+        assert_eq!(
+            expect_diagnostics_error(Cli::parse_from([""]), test_dir.path()),
+            "Cannot load the plugin \"custom_plugin.py:plugin\", the supported plugins are \
+             mypy_django_plugin.main, numpy.typing.mypy_plugin, pydantic.mypy, pydantic.v1.mypy"
+        );
+
+        // Mypy configs may use plugins that are not supported, plugins_path is only a warning.
+        test_dir.write_file(
+            "pyproject.toml",
+            "[tool.mypy]\nplugins = ['custom_plugin.py']\nplugins_path = ['plugins']",
+        );
+        assert_eq!(
+            diagnostics(Cli::parse_from([""]), test_dir.path()),
+            ["foo.py:1: error: \"int\" not callable  [operator]"]
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            def f() -> None: ...
+            def f() -> None: ...
+            1()
+            "#,
+            false,
+        );
+        let output = |format| {
+            let (mut project, config) = project_from_cli(
+                Cli::parse_from([""]),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let diagnostics = project.diagnostics().unwrap();
+            let mut output = vec![];
+            write_diagnostics(
+                &mut output,
+                &diagnostics,
+                &config,
+                test_dir.path(),
+                format,
+                false,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let not_callable = serde_json::json!({
+            "path": "foo.py",
+            "range": {"start": {"line": 3, "column": 1}, "end": {"line": 3, "column": 4}},
+            "severity": "error",
+            "code": "operator",
+            "message": "\"int\" not callable",
+            "related": [],
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&output(OutputFormat::Json)).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(
+            json["summary"],
+            serde_json::json!({"checked_files": 1, "files_with_errors": 1, "errors": 2})
+        );
+        assert_eq!(json["diagnostics"][1], not_callable);
+        let redefinition = &json["diagnostics"][0];
+        assert_eq!(redefinition["code"], "no-redef");
+        assert_eq!(
+            redefinition["related"],
+            serde_json::json!([{
+                "path": "foo.py",
+                "range": {"start": {"line": 1, "column": 5}, "end": {"line": 1, "column": 6}},
+                "message": "\"f\" first defined here",
+            }])
+        );
+
+        let lines = output(OutputFormat::JsonLines);
+        let lines: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&lines[0], redefinition);
+        assert_eq!(lines[1], not_callable);
+    }
+
+    #[test]
+    fn test_sarif_output() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            1()
+            2()  # type: ignore[operator]
+            "#,
+            false,
+        );
+        let project = |args: &[&str]| {
+            project_from_cli(
+                Cli::parse_from(args),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap()
+        };
+        // Suppressed diagnostics are only collected for the SARIF output.
+        let (mut text_project, _) = project(&[""]);
+        assert!(text_project.diagnostics().unwrap().suppressed.is_empty());
+
+        let (mut project, config) = project(&["", "--output", "sarif"]);
+        let diagnostics = project.diagnostics().unwrap();
+        let mut output = vec![];
+        write_diagnostics(
+            &mut output,
+            &diagnostics,
+            &config,
+            test_dir.path(),
+            OutputFormat::Sarif,
+            false,
+        )
+        .unwrap();
+        let sarif: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "zuban");
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            serde_json::json!([{
+                "id": "operator",
+                "name": "operator",
+                "shortDescription": {"text": "Diagnostics with the error code operator"},
+                "helpUri": "https://mypy.readthedocs.io/en/stable/_refs.html#code-operator",
+                "defaultConfiguration": {"level": "error"},
+            }])
+        );
+        let location = |line| {
+            serde_json::json!([{"physicalLocation": {
+                "artifactLocation": {"uri": "foo.py", "uriBaseId": "%SRCROOT%"},
+                "region": {"startLine": line, "startColumn": 1, "endLine": line, "endColumn": 4},
+            }}])
+        };
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["ruleId"], "operator");
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["message"]["text"], "\"int\" not callable");
+        assert_eq!(results[0]["locations"], location(1));
+        assert!(results[0].get("suppressions").is_none());
+        assert_eq!(results[1]["ruleId"], "operator");
+        assert_eq!(results[1]["locations"], location(2));
+        assert_eq!(
+            results[1]["suppressions"],
+            serde_json::json!([{"kind": "inSource"}])
+        );
+    }
+
+    #[test]
+    fn test_github_output() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            1()
+            reveal_type(1)
+            2()
+            "#,
+            false,
+        );
+        let (mut project, config) = project_from_cli(
+            Cli::parse_from([""]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        let diagnostics = project.diagnostics().unwrap();
+        let mut output = vec![];
+        write_diagnostics(
+            &mut output,
+            &diagnostics,
+            &config,
+            test_dir.path(),
+            OutputFormat::Github,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "::error file=foo.py,line=1,col=1,endLine=1,endColumn=4::[operator] \"int\" not callable\n\
+             ::notice file=foo.py,line=2,col=13,endLine=2,endColumn=14::Revealed type is \"Literal[1]?\"\n\
+             ::error file=foo.py,line=3,col=1,endLine=3,endColumn=4::[operator] \"int\" not callable\n\
+             Found 2 errors in 1 file (checked 1 source file)\n"
+        );
+
+        let github_actions = |value: &str| {
+            let value = value.to_string();
+            default_output_format(move |name| {
+                assert_eq!(name, "GITHUB_ACTIONS");
+                Ok(value.clone())
+            })
+        };
+        assert_eq!(github_actions("true"), OutputFormat::Github);
+        assert_eq!(github_actions("false"), OutputFormat::Text);
+        assert_eq!(
+            default_output_format(|_| Err(VarError::NotPresent)),
+            OutputFormat::Text
+        );
+
+        // The paths of baseline entries are relative to the baseline file.
+        std::fs::create_dir(Path::new(test_dir.path()).join("ci")).unwrap();
+        std::fs::write(
+            Path::new(test_dir.path()).join("ci/baseline.json"),
+            r#"{"version": 1, "entries": [
+                {"path": "../foo.py", "code": "misc", "message": "Old", "count": 1}
+            ]}"#,
+        )
+        .unwrap();
+        let (mut project, config) = project_from_cli(
+            Cli::parse_from(["", "--baseline", "ci/baseline.json"]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        let diagnostics = project.diagnostics().unwrap();
+        let mut output = vec![];
+        write_diagnostics(
+            &mut output,
+            &diagnostics,
+            &config,
+            test_dir.path(),
+            OutputFormat::Github,
+            false,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains(
+                "::notice file=foo.py::Baseline entry does not match an error anymore: Old  [misc]\n"
+            ),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            1()
+            x: int = ""
+
+            [file bar.py]
+            reveal_type(1)
+
+            [file baz.py]
+            "#,
+            false,
+        );
+        let junit_xml = |args: &[&str]| {
+            let (mut project, config) = project_from_cli(
+                Cli::parse_from(args),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let diagnostics = project.diagnostics().unwrap();
+            output::junit_xml(&diagnostics, &config, test_dir.path(), Duration::ZERO)
+        };
+        assert_eq!(
+            junit_xml(&["", "foo.py", "bar.py"]),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuite errors="0" failures="1" name="zuban" skips="0" tests="2" time="0.000">
+  <testcase classname="zuban" file="foo.py" line="1" name="foo.py" time="0">
+    <failure message="zuban produced errors">foo.py:1: error: &quot;int&quot; not callable  [operator]
+foo.py:2: error: Incompatible types in assignment (expression has type &quot;str&quot;, variable has type &quot;int&quot;)  [assignment]</failure>
+  </testcase>
+  <testcase classname="zuban" file="bar.py" line="1" name="bar.py" time="0">
+    <system-out>bar.py:1: note: Revealed type is &quot;Literal[1]?&quot;</system-out>
+  </testcase>
+</testsuite>
+"#
+        );
+        assert_eq!(
+            junit_xml(&["", "baz.py"]),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuite errors="0" failures="0" name="zuban" skips="0" tests="1" time="0.000">
+  <testcase classname="zuban" file="zuban" line="1" name="zuban" time="0.000">
+  </testcase>
+</testsuite>
+"#
+        );
+    }
+
+    #[test]
+    fn test_precision_reports() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            from typing import Any
+
+            def f(x: Any) -> list[Any]:
+                return [x]
+
+            a = 1
+            if int():
+                raise ValueError
+                b = f(a)
+
+            [file bar/__init__.py]
+            from typing import Any
+            x: list[Any] = []
+            "#,
+            false,
+        );
+        let (mut project, _) = project_from_cli(
+            Cli::parse_from(["", "foo.py", "bar"]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        let modules = project.line_precision().unwrap();
+        assert_eq!(
+            reports::line_precision_report(&modules),
+            "\
+Name  Lines  Precise  Imprecise  Any  Empty  Unanalyzed
+-------------------------------------------------------
+bar       2        0          1    0      1           0
+foo      10        3          0    1      5           1
+"
+        );
+        assert_eq!(
+            reports::any_exprs_report(&modules),
+            "\
+Name    Anys   Exprs   Coverage
+-------------------------------
+bar        1       1      0.00%
+foo        2       5     60.00%
+-------------------------------
+Total      3       6     50.00%
+"
+        );
+    }
+
+    #[test]
+    fn test_timing_stats() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            import bar
+            x: int = bar.y
+
+            [file bar.py]
+            y = 1
+            "#,
+            false,
+        );
+        let (mut project, _) = project_from_cli(
+            Cli::parse_from(["", "foo.py"]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+            |_| Err(VarError::NotPresent),
+        )
+        .unwrap();
+        project.diagnostics().unwrap();
+        let stats = project.timing_stats();
+        let file = |module| stats.files.iter().find(|f| f.module == module).unwrap();
+        assert!(file("foo").check.is_some());
+        // bar is only inferred while checking foo.
+        assert!(file("bar").check.is_none());
+        assert!(file("builtins").check.is_none());
+        assert!(stats.memory.code > 0);
+        assert!(stats.memory.syntax_trees > 0);
+
+        let table = timing::timing_stats_table(&stats, test_dir.path());
+        let mut lines = table.lines();
+        assert_eq!(
+            lines.next(),
+            Some("module\tparse_ms\tbind_ms\tcheck_ms\ttotal_ms\tpath")
+        );
+        let foo: Vec<_> = lines
+            .find(|line| line.starts_with("foo\t"))
+            .unwrap()
+            .split('\t')
+            .collect();
+        assert_eq!(foo.len(), 6);
+        assert_ne!(foo[3], "-");
+        assert_eq!(foo[5], "foo.py");
+    }
+
+    #[test]
+    fn test_watch() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            x: int = ""
+
+            [file bar.py]
+            y: int = 1
+            "#,
+            false,
+        );
+        let mut session = watch::WatchSession::new(
+            Cli::parse_from(["", "--watch"]),
+            test_dir.path().to_string(),
+            Some(test_utils::typeshed_path()),
+            Default::default(),
+            None,
+        )
+        .unwrap()
+        .without_colors();
+        let mut check = |changes: &[(&str, &str)]| {
+            for (name, code) in changes {
+                let path = Path::new(test_dir.path()).join(name);
+                std::fs::write(&path, code).unwrap();
+                session.invalidate_path(&path);
+            }
+            let mut output = vec![];
+            session.check(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            check(&[]),
+            "foo.py:1: error: Incompatible types in assignment (expression has type \"str\", \
+             variable has type \"int\")  [assignment]\n\
+             Found 1 error in 1 file (checked 2 source files)\n\
+             Watching for file changes...\n"
+        );
+        assert_eq!(
+            check(&[("foo.py", "x: int = 1\n"), ("bar.py", "y: int = \"\"\n")]),
+            "- foo.py:1: error: Incompatible types in assignment (expression has type \"str\", \
+             variable has type \"int\")  [assignment]\n\
+             + bar.py:1: error: Incompatible types in assignment (expression has type \"str\", \
+             variable has type \"int\")  [assignment]\n\
+             Found 1 error in 1 file (checked 2 source files)\n\
+             Watching for file changes...\n"
+        );
+        assert_eq!(
+            check(&[("foo.py", "x: int = 2\n")]),
+            "No changes in diagnostics\n\
+             Found 1 error in 1 file (checked 2 source files)\n\
+             Watching for file changes...\n"
+        );
+
+        let check_with = |args: &[&str]| {
+            let mut session = watch::WatchSession::new(
+                Cli::parse_from(args),
+                test_dir.path().to_string(),
+                Some(test_utils::typeshed_path()),
+                Default::default(),
+                None,
+            )
+            .unwrap();
+            session.check(&mut vec![]).map_err(|err| err.to_string())
+        };
+        let unsupported_output = Err(
+            "--watch only supports the text output without reports, timings and thresholds".into(),
+        );
+        assert_eq!(
+            check_with(&["", "--watch", "--output", "json"]),
+            unsupported_output
+        );
+        assert_eq!(
+            check_with(&["", "--watch", "--output", "sarif"]),
+            unsupported_output
+        );
+        assert_eq!(
+            check_with(&["", "--watch", "--junit-xml", "junit.xml"]),
+            unsupported_output
+        );
+        assert_eq!(
+            check_with(&["", "--watch", "--txt-report", "report"]),
+            unsupported_output
+        );
+        assert_eq!(
+            check_with(&[
+                "",
+                "--watch",
+                "--python-version",
+                "3.11",
+                "--python-version",
+                "3.12"
+            ]),
+            Err(
+                "--watch does not support multiple Python versions, choose one with \
+                 --python-version"
+                    .into()
+            )
+        );
+        assert_eq!(check_with(&["", "--watch", "--output", "text"]), Ok(()));
+    }
+
+    #[test]
+    fn test_watch_rescan() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file main.py]
+            from pkg.a import x
+            from pkg.b import y
+            reveal_type(x)
+
+            [file pkg/__init__.py]
+
+            [file pkg/a.py]
+            x = 1
+
+            [file pkg/b.py]
+            y = 1
+            "#,
+            false,
+        );
+        let mut session = watch::WatchSession::new(
+            Cli::parse_from(["", "--watch"]),
+            test_dir.path().to_string(),
+            Some(test_utils::typeshed_path()),
+            Default::default(),
+            None,
+        )
+        .unwrap()
+        .without_colors();
+        let mut output = vec![];
+        session.check(&mut output).unwrap();
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .contains("\"builtins.int\"")
+        );
+
+        // Lots of changes in a directory lead to a rescan of the directory.
+        let pkg = Path::new(test_dir.path()).join("pkg");
+        std::fs::write(pkg.join("a.py"), "x = ''\n").unwrap();
+        std::fs::remove_file(pkg.join("b.py")).unwrap();
+        session.rescan_path(&pkg);
+        let mut output = vec![];
+        session.check(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("+ main.py:3: note: Revealed type is \"builtins.str\""));
+        assert!(output.contains("named \"pkg.b\"  [import-not-found]"));
+    }
+
+    #[test]
+    fn test_daemon_requests() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            x: int = ""
+
+            [file bar.py]
+            y: int = 1
+            "#,
+            false,
+        );
+        let mut daemon = daemon::Daemon::new(
+            vec![],
+            test_dir.path().to_string(),
+            Some(test_utils::typeshed_path()),
+        )
+        .unwrap();
+        let mut request = |request: serde_json::Value| daemon.handle_request(&request);
+        let check = |args: &[&str]| serde_json::json!({"command": "check", "args": args});
+        let (response, stop) = request(check(&["foo.py"]));
+        assert!(!stop);
+        assert_eq!(response["exit_code"], 1);
+        assert_eq!(
+            response["stdout"],
+            "foo.py:1: error: Incompatible types in assignment (expression has type \"str\", \
+             variable has type \"int\")  [assignment]\n\
+             Found 1 error in 1 file (checked 1 source file)\n"
+        );
+        // Changes right before a request are seen by the check.
+        std::fs::write(Path::new(test_dir.path()).join("foo.py"), "x: int = 1\n").unwrap();
+        let (response, _) = request(check(&["foo.py"]));
+        assert_eq!(response["exit_code"], 0);
+        let (response, _) = request(check(&["bar.py"]));
+        assert_eq!(response["exit_code"], 0);
+        assert_eq!(
+            response["stdout"],
+            "Success: no issues found in 1 source file\n"
+        );
+
+        let (response, _) = request(check(&["--no-such-flag"]));
+        assert_eq!(response["exit_code"], 2);
+
+        // The environment of the client is used for the check.
+        let lib = Path::new(test_dir.path()).join("lib");
+        std::fs::create_dir(&lib).unwrap();
+        std::fs::write(lib.join("extra.py"), "").unwrap();
+        std::fs::write(Path::new(test_dir.path()).join("baz.py"), "import extra\n").unwrap();
+        let check_with_mypy_path = |mypy_path: Option<&str>| {
+            serde_json::json!({
+                "command": "check",
+                "args": ["baz.py"],
+                "env": {"MYPYPATH": mypy_path},
+            })
+        };
+        let (response, _) = request(check_with_mypy_path(None));
+        assert_eq!(response["exit_code"], 1);
+        let (response, _) = request(check_with_mypy_path(lib.to_str()));
+        assert_eq!(response["exit_code"], 0, "{response}");
+
+        let (response, _) = request(serde_json::json!({"command": "status"}));
+        assert_eq!(response["checks"], 5);
+        assert_eq!(response["pid"], std::process::id());
+        let (_, stop) = request(serde_json::json!({"command": "stop"}));
+        assert!(stop);
+    }
+
+    #[test]
+    fn test_in_memory_files() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file lib.py]
+            def f() -> int: ...
+
+            [file main.py]
+            import lib
+            x: int = lib.f()
+
+            [file shadow.py]
+            def f() -> str: ...
+            "#,
+            false,
+        );
+        let diagnostics = |args: &[&str], stdin: &str| {
+            let mut cli = Cli::parse_from(args);
+            let files = take_in_memory_files(&mut cli, test_dir.path(), || Ok(stdin.to_string()))?;
+            let (mut project, config) = project_from_cli(
+                cli,
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            store_in_memory_files(&mut project, test_dir.path(), &files);
+            let diagnostics = project.diagnostics()?;
+            anyhow::Ok(
+                diagnostics
+                    .issues
+                    .iter()
+                    .map(|d| d.as_string(&config, Some(test_dir.path())))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let code = "import lib\ny: str = lib.f()\n";
+        assert_eq!(
+            diagnostics(&["", "--stdin-filename", "new.py", "-"], code).unwrap(),
+            [
+                "new.py:2: error: Incompatible types in assignment (expression has type \"int\", \
+              variable has type \"str\")  [assignment]"
+            ]
+        );
+        // The content of main.py on disk is replaced.
+        assert_eq!(
+            diagnostics(&["", "--stdin-filename", "main.py", "-"], code)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            diagnostics(&["", "main.py", "--shadow-file", "lib.py", "shadow.py"], "").unwrap(),
+            [
+                "main.py:2: error: Incompatible types in assignment (expression has type \"str\", \
+              variable has type \"int\")  [assignment]"
+            ]
+        );
+        assert_eq!(
+            diagnostics(&["", "-"], "").unwrap_err().to_string(),
+            "Reading from stdin (-) requires --stdin-filename"
+        );
+        assert_eq!(
+            diagnostics(&["", "--stdin-filename", "new.py", "main.py"], "")
+                .unwrap_err()
+                .to_string(),
+            "--stdin-filename requires - to be passed as a file"
+        );
+    }
+
+    #[test]
+    fn test_error_limit_and_thresholds() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file foo.py]
+            from typing import Any
+            def f(x: Any) -> Any: ...
+            a: int = f(1)
+            b: int = ""
+            c: int = ""
+            d: int = ""
+            "#,
+            false,
+        );
+        let check = |args: &[&str]| {
+            let cli = Cli::parse_from(args);
+            let output = CheckOutput {
+                format: OutputFormat::Text,
+                add_colors: false,
+                junit_xml: None,
+                report_dirs: vec![],
+                timing_stats: None,
+                verbose_timing: false,
+                fail_under_coverage: cli.fail_under_coverage,
+                max_new_errors: cli.max_new_errors,
+            };
+            let (mut project, config) = project_from_cli(
+                cli,
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let mut stdout = vec![];
+            let mut stderr = vec![];
+            let failed = check_project(
+                &mut project,
+                &mut stdout,
+                &mut stderr,
+                &config,
+                test_dir.path(),
+                &output,
+                Instant::now(),
+            )
+            .unwrap();
+            let stdout = String::from_utf8(stdout).unwrap();
+            (failed, stdout, String::from_utf8(stderr).unwrap())
+        };
+        let (failed, stdout, _) = check(&["", "foo.py", "--error-limit", "1"]);
+        assert!(failed);
+        assert_eq!(
+            stdout,
+            "foo.py:4: error: Incompatible types in assignment (expression has type \"str\", \
+             variable has type \"int\")  [assignment]\n\
+             2 more errors not shown (--error-limit 1)\n\
+             Found 3 errors in 1 file (checked 1 source file)\n"
+        );
+
+        assert!(!check(&["", "foo.py", "--max-new-errors", "3"]).0);
+        let (failed, _, stderr) = check(&["", "foo.py", "--max-new-errors", "2"]);
+        assert!(failed);
+        assert_eq!(
+            stderr,
+            "Found 3 errors, which is more than --max-new-errors 2\n"
+        );
+
+        let (failed, _, stderr) = check(&[
+            "",
+            "foo.py",
+            "--max-new-errors",
+            "3",
+            "--fail-under-coverage",
+            "95",
+        ]);
+        assert!(failed);
+        assert!(
+            stderr.contains("is below --fail-under-coverage 95%"),
+            "{stderr}"
+        );
+        let args = [
+            "",
+            "foo.py",
+            "--max-new-errors",
+            "3",
+            "--fail-under-coverage",
+            "5",
+        ];
+        assert!(!check(&args).0);
+    }
+
+    #[test]
+    fn test_parallel_checking() {
+        logging_config::setup_logging_for_tests();
+        let mut fixture = String::new();
+        for i in 0..120 {
+            fixture += &format!("[file pkg/m{i}.py]\n");
+            if i > 0 {
+                fixture += &format!("from pkg.m{} import f{}\n", i - 1, i - 1);
+                fixture += &format!("x: str = f{}()\n", i - 1);
+            }
+            fixture += &format!("def f{i}() -> int:\n    return {i}\n\n");
+        }
+        let test_dir = test_utils::write_files_from_fixture(&fixture, false);
+        let check = |jobs: &str| {
+            let (mut project, config) = project_from_cli(
+                Cli::parse_from(["", "--jobs", jobs]),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let diagnostics = project.diagnostics().unwrap();
+            let issues: Vec<_> = diagnostics
+                .issues
+                .iter()
+                .map(|d| d.as_string(&config, Some(test_dir.path())))
+                .collect();
+            let summary = diagnostics.summary();
+            drop(diagnostics);
+            let timing_stats = project.timing_stats();
+            let checked = timing_stats
+                .files
+                .iter()
+                .filter(|f| f.module.starts_with("pkg.") && f.check.is_some())
+                .count();
+            (issues, summary, checked)
+        };
+        let (issues, summary, checked) = check("1");
+        assert_eq!(issues.len(), 119);
+        assert_eq!(
+            summary,
+            "Found 119 errors in 119 files (checked 120 source files)"
+        );
+        assert_eq!(checked, 120);
+        assert_eq!(check("2"), (issues, summary, checked));
+    }
+
+    #[test]
+    fn test_incremental_cache() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file a.py]
+            def f() -> int:
+                return 1
+
+            [file b.py]
+            from a import f
+            x: str = f()
+
+            [file c.py]
+            y = "" # type: int
+            "#,
+            false,
+        );
+        let check = |args: &[&str]| {
+            let (mut project, config) = project_from_cli(
+                Cli::parse_from(std::iter::once("").chain(args.iter().copied())),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let diagnostics = project.diagnostics().unwrap();
+            let mut issues: Vec<_> = diagnostics
+                .issues
+                .iter()
+                .map(|d| d.as_string(&config, Some(test_dir.path())))
+                .collect();
+            issues.sort();
+            drop(diagnostics);
+            let mut checked: Vec<_> = project
+                .timing_stats()
+                .files
+                .into_iter()
+                .filter(|f| f.check.is_some() && f.module.len() == 1)
+                .map(|f| f.module)
+                .collect();
+            checked.sort();
+            (issues, checked)
+        };
+        let cache_dir = Path::new(test_dir.path()).join(".zuban_cache");
+        // The cache is only used with --incremental.
+        let (issues, checked) = check(&[]);
+        assert_eq!(checked, ["a", "b", "c"]);
+        assert!(!cache_dir.exists());
+
+        assert_eq!(check(&["--incremental"]), (issues.clone(), checked));
+        assert_eq!(
+            issues,
+            [
+                "b.py:2: error: Incompatible types in assignment (expression has type \"int\", \
+                 variable has type \"str\")  [assignment]",
+                "c.py:1: error: Incompatible types in assignment (expression has type \"str\", \
+                 variable has type \"int\")  [assignment]",
+            ]
+        );
+        assert!(cache_dir.join(".gitignore").exists());
+
+        // Nothing changed, so everything is restored from the cache.
+        assert_eq!(check(&["--incremental"]), (issues.clone(), vec![]));
+
+        // Changing a module invalidates the modules that import it.
+        std::fs::write(
+            Path::new(test_dir.path()).join("a.py"),
+            "def f() -> str:\n    return ''\n",
+        )
+        .unwrap();
+        let (new_issues, checked) = check(&["--incremental"]);
+        assert_eq!(new_issues, issues[1..]);
+        assert_eq!(checked, ["a", "b"]);
+
+        // A new stub changes how `b` imports `a`, even though no known module changed.
+        std::fs::write(
+            Path::new(test_dir.path()).join("a.pyi"),
+            "def f() -> int: ...\n",
+        )
+        .unwrap();
+        let (new_issues, checked) = check(&["--incremental"]);
+        assert_eq!(new_issues, issues);
+        assert!(checked.contains(&"b".to_string()), "{checked:?}");
+    }
+
+    #[test]
+    fn test_terminal_width() {
+        let lookup = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, value)| value.to_string())
+                    .ok_or(VarError::NotPresent)
+            }
+        };
+        assert_eq!(terminal_width(lookup(&[])), 80);
+        assert_eq!(terminal_width(lookup(&[("COLUMNS", "120")])), 120);
+        assert_eq!(terminal_width(lookup(&[("COLUMNS", "0")])), 80);
+        assert_eq!(
+            terminal_width(lookup(&[
+                ("COLUMNS", "120"),
+                ("MYPY_FORCE_TERMINAL_WIDTH", "60")
+            ])),
+            60
+        );
+    }
+
+    #[test]
+    fn test_baseline() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.zuban]
+            baseline = "baseline.json"
+
+            [file foo.py]
+            1()
+            1()
+            def f() -> int: return ""
+            "#,
+            false,
+        );
+        let summary = write_baseline(
+            Cli::parse_from(["", "--write-baseline", "baseline.json"]),
+            test_dir.path(),
+            Some(test_utils::typeshed_path()),
+        )
+        .unwrap();
+        assert!(summary.starts_with("Wrote 3 diagnostics to the baseline"));
+        let stale_with_args = |args: &[&str]| {
+            let (mut project, _) = project_from_cli(
+                Cli::parse_from(args),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+                |_| Err(VarError::NotPresent),
+            )
+            .unwrap();
+            let diagnostics = project.diagnostics().unwrap();
+            diagnostics
+                .stale_baseline_entries
+                .iter()
+                .map(|entry| stale_baseline_entry_message(&diagnostics, entry, test_dir.path()))
+                .collect::<Vec<_>>()
+        };
+        let stale = || stale_with_args(&[""]);
+        let d = || diagnostics(Cli::parse_from([""]), test_dir.path());
+        let empty: [&str; _] = [];
+        assert_eq!(d(), empty);
+
+        // Line shifts do not matter, but new errors are reported.
+        test_dir.write_file("foo.py", "x = 1\n1()\n1()\n1()\ndef f() -> int: return b''");
+        assert_eq!(
+            d(),
+            [
+                "foo.py:4: error: \"int\" not callable  [operator]",
+                "foo.py:5: error: Incompatible return value type (got \"bytes\", expected \"int\")  [return-value]",
+            ]
+        );
+        assert_eq!(
+            stale(),
+            [
+                "foo.py: note: Baseline entry does not match an error anymore: Incompatible \
+              return value type (got \"str\", expected \"int\")  [return-value]"
+            ]
+        );
+
+        // Entries of files that are not checked (anymore) are stale as well.
+        test_dir.write_file(
+            "other.json",
+            r#"{"version": 1, "entries": [
+                {"path": "deleted.py", "code": "misc", "message": "Old", "count": 1}
+            ]}"#,
+        );
+        assert_eq!(
+            stale_with_args(&["", "--baseline", "other.json"]),
+            ["deleted.py: note: Baseline entry does not match an error anymore: Old  [misc]"]
+        );
+        // A partial check does not know whether the entries of other files are stale.
+        test_dir.write_file("bar.py", "");
+        assert_eq!(
+            stale_with_args(&["", "--baseline", "other.json", "bar.py"]),
+            empty
+        );
+
+        // An explicit baseline replaces the configured one.
+        test_dir.write_file("other.json", r#"{"version": 1, "entries": []}"#);
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(["", "--baseline", "other.json"]),
+                test_dir.path()
+            )
+            .len(),
+            4
+        );
+        test_dir.write_file("other.json", "{}");
+        let err = expect_diagnostics_error(
+            Cli::parse_from(["", "--baseline", "other.json"]),
+            test_dir.path(),
+        );
+        assert!(err.starts_with("Invalid baseline file"), "{err}");
+    }
+
+    #[test]
+    fn test_pyproject_should_be_ignored_if_no_relevant_entry() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+
+            [file setup.cfg]
+            [mypy]
+            exclude = "foo.py"
+
+            [file foo.py]
+            1()
+            def foo(x: str) -> int: return 1
+            [file bar.py]
+            def bar(x) -> None: ...
+            "#,
+            false,
+        );
+        let d = || diagnostics(Cli::parse_from(vec![""]), test_dir.path());
+
+        const NOT_CALLABLE: &str = "foo.py:1: error: \"int\" not callable  [operator]";
+
+        let empty: [&str; _] = [];
+        assert_eq!(d(), empty);
+
+        test_dir.write_file("pyproject.toml", "[tool.mypy]");
+
+        assert_eq!(d(), [NOT_CALLABLE]);
+
+        test_dir.write_file("pyproject.toml", "[tool.zuban]");
+        assert_eq!(d(), empty);
+
+        // The Zuban config can overwrite mypy settings.
+        test_dir.write_file(
+            "pyproject.toml",
+            "[tool.zuban]\ndisallow_untyped_defs = true",
+        );
+        const MISSING_ANNOTATION: &str = "bar.py:1: error: Function is missing a type \
+                                          annotation for one or more arguments  [no-untyped-def]";
+        assert_eq!(d(), [MISSING_ANNOTATION]);
+
+        // Using --config-file should disable all other config files and only use the one
+        // specified.
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(vec!["", "--config-file", "pyproject.toml"]),
+                test_dir.path()
+            ),
+            [MISSING_ANNOTATION, NOT_CALLABLE]
+        );
+
+        // If both the zuban config and the mypy section are available in pyproject.toml, that file
+        // overwrites everything else.
+        test_dir.write_file(
+            "pyproject.toml",
+            "[tool.zuban]\ndisallow_untyped_defs = true\n[tool.mypy]",
+        );
+        assert_eq!(d(), [MISSING_ANNOTATION, NOT_CALLABLE]);
+
+        // mypy.ini doesn't change that.
+        test_dir.write_file("mypy.ini", "[mypy]\nexclude = \"foo.py\"");
+        assert_eq!(d(), [MISSING_ANNOTATION, NOT_CALLABLE]);
+    }
+
+    #[test]
+    fn test_config_extends() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [tool.zuban]
+            extends = "configs/base.toml"
+            disallow_untyped_defs = false
+
+            [file configs/base.toml]
+            extends = "../shared/pyproject.toml"
+            disallow_untyped_defs = true
+            [[overrides]]
+            module = "foo"
+            ignore_errors = true
+
+            [file shared/pyproject.toml]
+            [tool.zuban]
+            disallow_untyped_calls = true
+
+            [file foo.py]
+            1()
+            [file bar.py]
+            def bar(x): ...
+            def baz() -> None: bar(1)
+            "#,
+            false,
+        );
+        let d = || diagnostics(Cli::parse_from(vec![""]), test_dir.path());
+
+        // The options of the extending config win.
+        assert_eq!(
+            d(),
+            [
+                "bar.py:2: error: Call to untyped function \"bar\" in typed context  [no-untyped-call]"
+            ]
+        );
+
+        test_dir.write_file(
+            "shared/pyproject.toml",
+            "[tool.zuban]\nextends = '../configs/base.toml'",
+        );
+        let local_fs = SimpleLocalFS::without_watcher();
+        let current_dir = local_fs.unchecked_abs_path(test_dir.path());
+        let Err(err) = find_cli_config(&local_fs, current_dir, None, &[], None) else {
+            panic!("Expected a cycle error")
+        };
+        assert!(
+            err.to_string().ends_with("base.toml extends itself"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_requires_python() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file pyproject.toml]
+            [project]
+            requires-python = ">=3.9, <4, !=3.10.*"
+            [tool.mypy]
+
+            [file m.py]
+            import sys
+            if sys.version_info >= (3, 11):
+                1()
+            "#,
+            false,
+        );
+        let d = || diagnostics(Cli::parse_from([""]), test_dir.path());
+
+        let empty: [&str; _] = [];
+        assert_eq!(d(), empty);
+
+        const NOT_CALLABLE: &str = "m.py:3: error: \"int\" not callable  [operator]";
+        // An explicit version wins.
+        test_dir.write_file(
+            "pyproject.toml",
+            "[project]\nrequires-python = '>=3.9'\n[tool.mypy]\npython_version = '3.12'",
+        );
+        assert_eq!(d(), [NOT_CALLABLE]);
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(["", "--python-version", "3.11"]),
+                test_dir.path()
+            ),
+            [NOT_CALLABLE]
+        );
+
+        test_dir.write_file(
+            "pyproject.toml",
+            "[project]\nrequires-python = '>3.10.2'\n[tool.mypy]",
+        );
+        assert_eq!(d(), empty);
+        test_dir.write_file(
+            "pyproject.toml",
+            "[project]\nrequires-python = '~=3.11'\n[tool.mypy]",
+        );
+        assert_eq!(d(), [NOT_CALLABLE]);
 
-    fn diagnostics_with_env_lookup(
-        cli: Cli,
-        directory: &str,
-        lookup_env_var: impl Fn(&str) -> Result<String, VarError>,
-    ) -> anyhow::Result<Vec<String>> {
-        let (mut project, diagnostic_config) = project_from_cli(
-            cli,
-            directory,
-            Some(test_utils::typeshed_path()),
-            lookup_env_var,
+        // The pyproject.toml does not need to configure mypy.
+        test_dir.write_file("pyproject.toml", "[project]\nrequires-python = '>=3.9'");
+        assert_eq!(d(), empty);
+        test_dir.write_file("mypy.ini", "[mypy]");
+        assert_eq!(d(), empty);
+        test_dir.write_file("other.ini", "[mypy]");
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(["", "--config-file", "other.ini"]),
+                test_dir.path()
+            ),
+            empty
+        );
+        test_dir.write_file("other.ini", "[mypy]\npython_version = 3.11");
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(["", "--config-file", "other.ini"]),
+                test_dir.path()
+            ),
+            [NOT_CALLABLE]
         );
-        let diagnostics = project.diagnostics();
-        let mut diagnostics = diagnostics?
-            .issues
-            .iter()
-            .map(|d| {
-                let mut s = d.as_string(&diagnostic_config, Some(directory));
-                if cfg!(windows) {
-                    s = s.replace('\\', "/")
-                }
-                s
-            })
-            .collect::<Vec<_>>();
-        diagnostics.sort();
-        Ok(diagnostics)
-    }
 
-    fn diagnostics(cli: Cli, directory: &str) -> Vec<String> {
-        diagnostics_with_env_lookup(cli, directory, |_| Err(VarError::NotPresent)).unwrap()
-    }
+        // The pyproject.toml next to an explicit config is used, not the one of the current
+        // directory.
+        test_dir.write_file("configs/other.ini", "[mypy]");
+        test_dir.write_file(
+            "configs/pyproject.toml",
+            "[project]\nrequires-python = '>=3.11'",
+        );
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(["", "--config-file", "configs/other.ini"]),
+                test_dir.path()
+            ),
+            [NOT_CALLABLE]
+        );
 
-    fn expect_diagnostics_error(cli: Cli, directory: &str) -> String {
-        diagnostics_with_env_lookup(cli, directory, |_| Err(VarError::NotPresent))
-            .unwrap_err()
-            .to_string()
+        // The chosen version is reported.
+        let local_fs = SimpleLocalFS::without_watcher();
+        let current_dir = local_fs.unchecked_abs_path(test_dir.path());
+        let found = find_cli_config(&local_fs, current_dir, None, &[], None).unwrap();
+        assert_eq!(found.notes.len(), 1);
+        assert!(
+            found.notes[0]
+                .starts_with(r#"Using Python 3.9, because of requires-python = ">=3.9" in "#),
+            "{:?}",
+            found.notes
+        );
     }
 
     #[test]
-    fn test_diagnostics() {
+    fn test_set_option() {
         logging_config::setup_logging_for_tests();
         let test_dir = test_utils::write_files_from_fixture(
             r#"
-            [file pyproject.toml]
-            [tool.mypy]
-            strict = true
+            [file mypy.ini]
+            [mypy]
+            disallow_untyped_defs = True
 
-            [file foo.py]
+            [file m.py]
+            def f(x): ...
             1()
-            def foo(x) -> int: return 1
-
-            [file README]
-            Test that readme's are not type checked
             "#,
             false,
         );
-        let d = || diagnostics(Cli::parse_from(vec![""]), test_dir.path());
+        let d =
+            |args: &[&str]| diagnostics(Cli::parse_from([""].iter().chain(args)), test_dir.path());
+        const NOT_CALLABLE: &str = "m.py:2: error: \"int\" not callable  [operator]";
+        const MISSING_ANNOTATION: &str = "m.py:1: error: Function is missing a type annotation  \
+                                          [no-untyped-def]";
 
-        const NOT_CALLABLE: &str = "foo.py:1: error: \"int\" not callable  [operator]";
+        let empty: [&str; _] = [];
+        assert_eq!(d(&[]), [MISSING_ANNOTATION, NOT_CALLABLE]);
+        assert_eq!(d(&["--set", "disallow_untyped_defs=false"]), [NOT_CALLABLE]);
         assert_eq!(
-            d(),
-            vec![
-                NOT_CALLABLE.to_string(),
-                "foo.py:2: error: Function is missing a type annotation for one or more arguments  [no-untyped-def]"
-                    .to_string()
+            d(&[
+                "--set",
+                "disallow-untyped-defs = no",
+                "--set",
+                "disable_error_code=operator"
+            ]),
+            empty
+        );
+        // --set wins over the other flags.
+        assert_eq!(
+            d(&[
+                "--allow-untyped-defs",
+                "--set",
+                "disallow_untyped_defs=true"
+            ]),
+            [MISSING_ANNOTATION, NOT_CALLABLE]
+        );
+        assert_eq!(
+            d(&["--set", "show_column_numbers=true"]),
+            [
+                "m.py:1:1: error: Function is missing a type annotation  [no-untyped-def]",
+                "m.py:2:1: error: \"int\" not callable  [operator]",
             ]
         );
-
-        test_dir.remove_file("pyproject.toml");
-
-        assert_eq!(d(), vec![NOT_CALLABLE.to_string()]);
+        assert!(Cli::try_parse_from(["", "--set", "strict"]).is_err());
+        assert_eq!(
+            expect_diagnostics_error(
+                Cli::parse_from(["", "--set", "disallow_untyped_defs=maybe"]),
+                test_dir.path()
+            ),
+            "Invalid --set disallow_untyped_defs: Expected bool, got \"maybe\""
+        );
     }
 
     #[test]
-    fn test_pyproject_should_be_ignored_if_no_relevant_entry() {
+    fn test_zuban_toml() {
         logging_config::setup_logging_for_tests();
         let test_dir = test_utils::write_files_from_fixture(
             r#"
             [file pyproject.toml]
+            [tool.mypy]
+            disallow_untyped_defs = true
 
-            [file setup.cfg]
-            [mypy]
+            [file zuban.toml]
             exclude = "foo.py"
 
             [file foo.py]
             1()
-            def foo(x: str) -> int: return 1
             [file bar.py]
             def bar(x) -> None: ...
             "#,
@@ -212,28 +2687,24 @@ mod tests {
         let d = || diagnostics(Cli::parse_from(vec![""]), test_dir.path());
 
         const NOT_CALLABLE: &str = "foo.py:1: error: \"int\" not callable  [operator]";
+        const MISSING_ANNOTATION: &str = "bar.py:1: error: Function is missing a type \
+                                          annotation for one or more arguments  [no-untyped-def]";
 
+        // zuban.toml takes precedence over the Mypy config in pyproject.toml
         let empty: [&str; _] = [];
         assert_eq!(d(), empty);
 
-        test_dir.write_file("pyproject.toml", "[tool.mypy]");
-
-        assert_eq!(d(), [NOT_CALLABLE]);
-
-        test_dir.write_file("pyproject.toml", "[tool.zuban]");
-        assert_eq!(d(), empty);
-
-        // The Zuban config can overwrite mypy settings.
         test_dir.write_file(
-            "pyproject.toml",
-            "[tool.zuban]\ndisallow_untyped_defs = true",
+            "zuban.toml",
+            "disallow_untyped_defs = true\n\
+             [[overrides]]\nmodule = \"foo\"\nignore_errors = true",
         );
-        const MISSING_ANNOTATION: &str = "bar.py:1: error: Function is missing a type \
-                                          annotation for one or more arguments  [no-untyped-def]";
         assert_eq!(d(), [MISSING_ANNOTATION]);
 
-        // Using --config-file should disable all other config files and only use the one
-        // specified.
+        test_dir.remove_file("zuban.toml");
+        test_dir.write_file(".zuban.toml", "mode = \"mypy\"");
+        assert_eq!(d(), [NOT_CALLABLE]);
+
         assert_eq!(
             diagnostics(
                 Cli::parse_from(vec!["", "--config-file", "pyproject.toml"]),
@@ -241,18 +2712,13 @@ mod tests {
             ),
             [MISSING_ANNOTATION, NOT_CALLABLE]
         );
-
-        // If both the zuban config and the mypy section are available in pyproject.toml, that file
-        // overwrites everything else.
-        test_dir.write_file(
-            "pyproject.toml",
-            "[tool.zuban]\ndisallow_untyped_defs = true\n[tool.mypy]",
+        assert_eq!(
+            diagnostics(
+                Cli::parse_from(vec!["", "--config-file", ".zuban.toml"]),
+                test_dir.path()
+            ),
+            [NOT_CALLABLE]
         );
-        assert_eq!(d(), [MISSING_ANNOTATION, NOT_CALLABLE]);
-
-        // mypy.ini doesn't change that.
-        test_dir.write_file("mypy.ini", "[mypy]\nexclude = \"foo.py\"");
-        assert_eq!(d(), [MISSING_ANNOTATION, NOT_CALLABLE]);
     }
 
     #[test]
@@ -397,8 +2863,23 @@ mod tests {
         assert_eq!(d(&["", "does-not-exist/../foo"]), [err1, err2]);
         // Same file twice
         assert_eq!(d(&["", "foo", "foo/mod2.py"]), [err1, err2]);
+        // Brace alternation
+        assert_eq!(d(&["", "{foo/bar,.}/mod{1,3}.py"]), [err1, err3]);
+        assert_eq!(d(&["", "foo/{bar/,}*.py"]), [err1, err2]);
+        // Negated patterns exclude what the patterns before them matched
+        assert_eq!(d(&["", "foo", "!foo/bar"]), [err2]);
+        assert_eq!(d(&["", "**/*.py", "!foo/**/mod[1-2].py"]), [err3]);
+        assert_eq!(d(&["", "foo", "!foo/bar", "foo/bar/mod1.py"]), [err1, err2]);
+        // Globs match depending on the case-sensitivity of the file system.
+        let local_fs = SimpleLocalFS::without_watcher();
+        if local_fs.is_case_sensitive(&local_fs.unchecked_abs_path(test_dir.path())) {
+            expect_not_found(&["", "FOO/*.py"]);
+        } else {
+            assert_eq!(d(&["", "FOO/*.py"]), [err2]);
+        }
 
         expect_not_found(&["", "foo", "undefined-path"]);
+        expect_not_found(&["", "foo/bar", "!foo/bar"]);
         if cfg!(windows) {
             assert_eq!(
                 expect_not_found(&["", "/foo/zuban/undefined-path"]),
@@ -444,7 +2925,8 @@ mod tests {
             cli,
             current_dir,
             None,
-        );
+        )
+        .unwrap();
         let files: Vec<&str> = project_options
             .settings
             .files_or_directories_to_check
@@ -626,6 +3108,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_user_config() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file home/.mypy.ini]
+            [mypy]
+            disallow_untyped_defs = True
+
+            [file xdg/mypy/config]
+            [mypy]
+            disallow_incomplete_defs = True
+
+            [file project/m.py]
+            def f(x): pass
+            def g(x: int, y) -> None: pass
+            "#,
+            false,
+        );
+        let home = format!("{}/home", test_dir.path());
+        let xdg = format!("{}/xdg", test_dir.path());
+        let project = format!("{}/project", test_dir.path());
+        let d = |args: &[&str], xdg_config_home: Option<&str>| {
+            diagnostics_with_env_lookup(Cli::parse_from(args), &project, |name| match name {
+                "HOME" => Ok(home.clone()),
+                "XDG_CONFIG_HOME" => xdg_config_home
+                    .map(String::from)
+                    .ok_or(VarError::NotPresent),
+                _ => Err(VarError::NotPresent),
+            })
+            .unwrap()
+        };
+        let untyped = [
+            "m.py:1: error: Function is missing a type annotation  [no-untyped-def]",
+            "m.py:2: error: Function is missing a type annotation for one or more arguments  [no-untyped-def]",
+        ];
+        assert_eq!(d(&[""], None), untyped);
+        // $XDG_CONFIG_HOME/mypy/config is preferred over ~/.mypy.ini
+        assert_eq!(d(&[""], Some(&xdg)), [untyped[1]]);
+        assert!(d(&["", "--no-user-config"], None).is_empty());
+
+        // A project config always wins
+        std::fs::write(format!("{project}/mypy.ini"), "[mypy]\n").unwrap();
+        assert!(d(&[""], None).is_empty());
+    }
+
     #[test]
     fn test_editable_source() {
         logging_config::setup_logging_for_tests();
@@ -721,6 +3249,154 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_editable_install_finders() {
+        logging_config::setup_logging_for_tests();
+        let site_packages = if cfg!(windows) {
+            "venv/Lib/site-packages"
+        } else {
+            "venv/lib/python3.12/site-packages"
+        };
+        let test_dir = test_utils::write_files_from_fixture(
+            &format!(
+                r#"
+                [file venv/bin/python]
+
+                [file venv/pyvenv.cfg]
+                include-system-site-packages = false
+                version = 3.12.3
+
+                [file {site_packages}/__editable__.foo-1.0.pth]
+                import __editable___foo_1_0_finder; __editable___foo_1_0_finder.install()
+
+                [file {site_packages}/_editable_impl_bar.pth]
+                import _editable_impl_bar
+
+                [file projects/foo/src/foo/__init__.py]
+                x = 1
+                [file projects/foo/src/foo/py.typed]
+                [file projects/foo/src/conftest.py]
+                [file projects/foo/src/other.pth]
+                ../extra
+                [file projects/foo/extra/extra_mod.py]
+
+                [file projects/bar/bar/__init__.py]
+                y = ""
+                [file projects/bar/bar/py.typed]
+
+                [file m.py]
+                from foo import x
+                from bar import y
+                reveal_type(x)
+                reveal_type(y)
+                import conftest
+                import extra_mod
+                "#
+            ),
+            false,
+        );
+        let project = |rel: &str| {
+            std::path::Path::new(test_dir.path())
+                .join(rel)
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+        test_dir.write_file(
+            &format!("{site_packages}/__editable___foo_1_0_finder.py"),
+            &format!(
+                "MAPPING: dict[str, str] = {{'foo': {:?}}}\nNAMESPACES: dict[str, list[str]] = {{}}\n",
+                project("projects/foo/src/foo")
+            ),
+        );
+        test_dir.write_file(
+            &format!("{site_packages}/_editable_impl_bar.py"),
+            &format!(
+                "from editables.redirector import RedirectingFinder as F\nF.install()\n\
+                 F.map_module('bar', {:?})\n",
+                project("projects/bar/bar/__init__.py")
+            ),
+        );
+        let d = |cli_args: &[&str]| diagnostics(Cli::parse_from(cli_args), test_dir.path());
+
+        assert_eq!(
+            d(&["", "--python-executable", "venv/bin/python", "m.py"]),
+            [
+                "m.py:3: note: Revealed type is \"builtins.int\"",
+                "m.py:4: note: Revealed type is \"builtins.str\"",
+                "m.py:5: error: Cannot find implementation or library stub for module named \"conftest\"  [import-not-found]",
+                "m.py:6: error: Cannot find implementation or library stub for module named \"extra_mod\"  [import-not-found]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zip_archive_imports() {
+        use std::io::Write as _;
+
+        logging_config::setup_logging_for_tests();
+        let site_packages = if cfg!(windows) {
+            "venv/Lib/site-packages"
+        } else {
+            "venv/lib/python3.12/site-packages"
+        };
+        let test_dir = test_utils::write_files_from_fixture(
+            &format!(
+                r#"
+                [file venv/bin/python]
+
+                [file venv/pyvenv.cfg]
+                include-system-site-packages = false
+                version = 3.12.3
+
+                [file {site_packages}/archives.pth]
+                ../../../../deps/pkg-1.0-py3-none-any.whl
+                ../../../../deps/other.zip
+
+                [file m.py]
+                from pkg import x
+                from pkg.sub import y
+                import single
+                reveal_type(x)
+                reveal_type(y)
+                reveal_type(single.z)
+                "#
+            ),
+            false,
+        );
+        let write_archive = |rel_path: &str, files: &[(&str, &str)]| {
+            let path = std::path::Path::new(test_dir.path()).join(rel_path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+            for (name, content) in files {
+                zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(content.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        };
+        write_archive(
+            "deps/pkg-1.0-py3-none-any.whl",
+            &[
+                ("pkg/__init__.py", "x = 1\n"),
+                ("pkg/py.typed", ""),
+                ("pkg/sub.py", "y = ''\n"),
+                ("pkg-1.0.dist-info/METADATA", "Name: pkg\n"),
+            ],
+        );
+        write_archive("deps/other.zip", &[("single.pyi", "z: bytes\n")]);
+        let d = |cli_args: &[&str]| diagnostics(Cli::parse_from(cli_args), test_dir.path());
+
+        assert_eq!(
+            d(&["", "--python-executable", "venv/bin/python", "m.py"]),
+            [
+                "m.py:4: note: Revealed type is \"builtins.int\"",
+                "m.py:5: note: Revealed type is \"builtins.str\"",
+                "m.py:6: note: Revealed type is \"builtins.bytes\"",
+            ]
+        );
+    }
+
     #[test]
     fn test_pythonpath() {
         logging_config::setup_logging_for_tests();
@@ -806,6 +3482,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_exclude_patterns() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file mypy.ini]
+            [mypy]
+            exclude_patterns =
+                build/*
+                !build/keep.py
+
+            [file m.py]
+            1()
+            [file n.py]
+            1()
+            [file build/lib.py]
+            1()
+            [file build/keep.py]
+            1()
+            [file .gitignore]
+            n.py
+            "#,
+            false,
+        );
+        let d = || diagnostics(Cli::parse_from([""]), test_dir.path());
+
+        // In Mypy mode the .gitignore is only used when enabled.
+        assert_eq!(
+            d(),
+            [
+                "build/keep.py:1: error: \"int\" not callable  [operator]",
+                "m.py:1: error: \"int\" not callable  [operator]",
+                "n.py:1: error: \"int\" not callable  [operator]",
+            ]
+        );
+        test_dir.write_file(
+            "mypy.ini",
+            "[mypy]\nexclude_patterns = build/\nrespect_gitignore = true",
+        );
+        assert_eq!(d(), ["m.py:1: error: \"int\" not callable  [operator]"]);
+    }
+
     #[test]
     fn test_read_file_only_once() {
         logging_config::setup_logging_for_tests();
@@ -0,0 +1,417 @@
+//! Machine-readable diagnostics for `--output`.
+//!
+//! ## JSON
+//!
+//! The schema of `--output json` and `--output json-lines` is stable: Fields might be added, but
+//! are never removed or changed. `json` prints a single document:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "diagnostics": [<diagnostic>, ...],
+//!   "stale_baseline_entries": [{"path": "foo.py", "code": "misc", "message": "...", "count": 1}],
+//!   "summary": {"checked_files": 2, "files_with_errors": 1, "errors": 1}
+//! }
+//! ```
+//!
+//! `json-lines` prints one diagnostic per line. A diagnostic looks like this:
+//!
+//! ```json
+//! {
+//!   "path": "foo.py",
+//!   "range": {"start": {"line": 1, "column": 1}, "end": {"line": 1, "column": 4}},
+//!   "severity": "error",
+//!   "code": "operator",
+//!   "message": "\"int\" not callable",
+//!   "related": [{"path": "bar.py", "range": <range>, "message": "..."}]
+//! }
+//! ```
+//!
+//! Paths are relative to the current directory if possible. Lines and columns start at 1 and
+//! columns count code points. The end of a range is exclusive. The severity is one of `error`,
+//! `warning`, `note` and `hint`. The message can consist of multiple lines, the additional lines
+//! are notes.
+//!
+//! ## SARIF
+//!
+//! `--output sarif` prints a [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/)
+//! log, which can for example be uploaded to GitHub Code Scanning. Every error code is a rule and
+//! diagnostics that are suppressed by `# type: ignore` comments are reported with an `inSource`
+//! suppression.
+//!
+//! ## GitHub
+//!
+//! `--output github` prints [workflow commands][commands] like
+//! `::error file=foo.py,line=1,col=1,endLine=1,endColumn=4::[operator] message`, which GitHub
+//! Actions show as annotations. Notes are printed as `::notice`.
+//!
+//! [commands]: https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions
+//!
+//! ## JUnit
+//!
+//! `--junit-xml PATH` writes a JUnit XML report in addition to the normal output. Every file with
+//! diagnostics is a test case that fails if the file has errors.
+
+use std::{io::Write, path::Path, time::Duration};
+
+use config::DiagnosticConfig;
+
+use serde_json::{Value, json};
+use vfs::{AbsPath, SimpleLocalFS, VfsHandler as _};
+use zuban_python::{
+    BaselineEntry, Diagnostic, Diagnostics, PositionInfos, Severity, error_code_documentation_url,
+};
+
+const VERSION: usize = 1;
+
+pub(crate) fn write_json(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    current_dir: &str,
+) -> anyhow::Result<()> {
+    let document = json!({
+        "version": VERSION,
+        "diagnostics": diagnostics
+            .issues
+            .iter()
+            .map(|d| diagnostic_to_json(d, current_dir))
+            .collect::<Vec<_>>(),
+        "stale_baseline_entries": diagnostics.stale_baseline_entries,
+        "summary": {
+            "checked_files": diagnostics.checked_files,
+            "files_with_errors": diagnostics.files_with_errors,
+            "errors": diagnostics.error_count(),
+        },
+    });
+    serde_json::to_writer_pretty(&mut *writer, &document)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+pub(crate) fn write_json_lines(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    current_dir: &str,
+) -> anyhow::Result<()> {
+    for diagnostic in diagnostics.issues.iter() {
+        serde_json::to_writer(&mut *writer, &diagnostic_to_json(diagnostic, current_dir))?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn diagnostic_to_json(diagnostic: &Diagnostic, current_dir: &str) -> Value {
+    let related: Vec<_> = diagnostic
+        .related_information()
+        .into_iter()
+        .map(|info| {
+            json!({
+                "path": display_path(info.name.file_path(), current_dir),
+                "range": range_to_json(info.name.name_range()),
+                "message": info.message,
+            })
+        })
+        .collect();
+    json!({
+        "path": display_path(diagnostic.original_file_path(), current_dir),
+        "range": range_to_json((diagnostic.start_position(), diagnostic.end_position())),
+        "severity": match diagnostic.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "note",
+            Severity::Hint => "hint",
+        },
+        "code": diagnostic.mypy_error_code(),
+        "message": diagnostic.message(),
+        "related": related,
+    })
+}
+
+pub(crate) fn display_path(path: &AbsPath, current_dir: &str) -> String {
+    SimpleLocalFS::without_watcher()
+        .path_relative_to(path, Path::new(current_dir))
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// The path of the file of a stale baseline entry, which is relative to the baseline file.
+pub(crate) fn baseline_entry_path(
+    diagnostics: &Diagnostics,
+    entry: &BaselineEntry,
+    current_dir: &str,
+) -> String {
+    let Some(path) = diagnostics
+        .baseline_dir
+        .as_ref()
+        .and_then(|dir| dir.join(&entry.path).into_os_string().into_string().ok())
+    else {
+        return entry.path.clone();
+    };
+    // The entries of files outside of the directory of the baseline file start with `..`.
+    let fs = SimpleLocalFS::without_watcher();
+    display_path(
+        &fs.normalize_rc_path(fs.unchecked_abs_path(&path)),
+        current_dir,
+    )
+}
+
+fn range_to_json((start, end): (PositionInfos, PositionInfos)) -> Value {
+    let position = |p: PositionInfos| {
+        let column = p.code_points_column() + 1;
+        json!({"line": p.line_one_based(), "column": column})
+    };
+    json!({"start": position(start), "end": position(end)})
+}
+
+pub(crate) fn write_sarif(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    current_dir: &str,
+) -> anyhow::Result<()> {
+    let mut rules: Vec<&str> = vec![];
+    let mut result = |diagnostic: &Diagnostic, suppressed: bool| {
+        let code = diagnostic.mypy_error_code();
+        let rule_index = match rules.iter().position(|rule| *rule == code) {
+            Some(index) => index,
+            None => {
+                rules.push(code);
+                rules.len() - 1
+            }
+        };
+        let related: Vec<_> = diagnostic
+            .related_information()
+            .into_iter()
+            .map(|info| {
+                json!({
+                    "physicalLocation": sarif_physical_location(
+                        info.name.file_path(),
+                        info.name.name_range(),
+                        current_dir,
+                    ),
+                    "message": {"text": info.message},
+                })
+            })
+            .collect();
+        let mut result = json!({
+            "ruleId": code,
+            "ruleIndex": rule_index,
+            "level": sarif_level(diagnostic.severity()),
+            "message": {"text": diagnostic.message()},
+            "locations": [{
+                "physicalLocation": sarif_physical_location(
+                    diagnostic.original_file_path(),
+                    (diagnostic.start_position(), diagnostic.end_position()),
+                    current_dir,
+                ),
+            }],
+            "relatedLocations": related,
+        });
+        if suppressed {
+            result["suppressions"] = json!([{"kind": "inSource"}]);
+        }
+        result
+    };
+    let mut results: Vec<_> = diagnostics
+        .issues
+        .iter()
+        .map(|d| result(d, false))
+        .collect();
+    results.extend(diagnostics.suppressed.iter().map(|d| result(d, true)));
+    let rules: Vec<_> = rules
+        .into_iter()
+        .map(|code| {
+            if code == "note" {
+                return json!({
+                    "id": code,
+                    "name": code,
+                    "shortDescription": {"text": "Notes that are not part of an error"},
+                    "defaultConfiguration": {"level": "note"},
+                });
+            }
+            let mut rule = json!({
+                "id": code,
+                "name": code,
+                "shortDescription": {"text": format!("Diagnostics with the error code {code}")},
+                "defaultConfiguration": {"level": "error"},
+            });
+            if let Some(url) = error_code_documentation_url(code) {
+                rule["helpUri"] = url.into();
+            }
+            rule
+        })
+        .collect();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "zuban",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://zubanls.com",
+                    "rules": rules,
+                },
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": {"uri": file_uri(&format!("{current_dir}/"))},
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_writer_pretty(&mut *writer, &log)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Information | Severity::Hint => "note",
+    }
+}
+
+fn sarif_physical_location(
+    path: &AbsPath,
+    (start, end): (PositionInfos, PositionInfos),
+    current_dir: &str,
+) -> Value {
+    let relative = display_path(path, current_dir);
+    let artifact_location = if Path::new(&relative).is_relative() && !relative.starts_with("..") {
+        json!({"uri": relative.replace('\\', "/"), "uriBaseId": "%SRCROOT%"})
+    } else {
+        json!({"uri": file_uri(&path.to_string())})
+    };
+    json!({
+        "artifactLocation": artifact_location,
+        "region": {
+            "startLine": start.line_one_based(),
+            "startColumn": start.code_points_column() + 1,
+            "endLine": end.line_one_based(),
+            "endColumn": end.code_points_column() + 1,
+        },
+    })
+}
+
+fn file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    match path.starts_with('/') {
+        true => format!("file://{path}"),
+        false => format!("file:///{path}"),
+    }
+}
+
+pub(crate) fn junit_xml(
+    diagnostics: &Diagnostics,
+    config: &DiagnosticConfig,
+    current_dir: &str,
+    time: Duration,
+) -> String {
+    let mut files: Vec<(String, Vec<&Diagnostic>)> = vec![];
+    for diagnostic in &diagnostics.issues {
+        let path = display_path(diagnostic.original_file_path(), current_dir);
+        match files.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, file_diagnostics)) => file_diagnostics.push(diagnostic),
+            None => files.push((path, vec![diagnostic])),
+        }
+    }
+    let mut failures = 0;
+    let mut test_cases = String::new();
+    for (path, file_diagnostics) in &files {
+        let path = xml_escape(path);
+        let messages = file_diagnostics
+            .iter()
+            .map(|d| xml_escape(&d.as_string(config, Some(current_dir))))
+            .collect::<Vec<_>>()
+            .join("\n");
+        test_cases += &format!(
+            "  <testcase classname=\"zuban\" file=\"{path}\" line=\"1\" name=\"{path}\" \
+             time=\"0\">\n"
+        );
+        if file_diagnostics
+            .iter()
+            .any(|d| d.severity() == Severity::Error)
+        {
+            failures += 1;
+            test_cases +=
+                &format!("    <failure message=\"zuban produced errors\">{messages}</failure>\n");
+        } else {
+            test_cases += &format!("    <system-out>{messages}</system-out>\n");
+        }
+        test_cases += "  </testcase>\n";
+    }
+    let time = time.as_secs_f64();
+    if files.is_empty() {
+        test_cases = format!(
+            "  <testcase classname=\"zuban\" file=\"zuban\" line=\"1\" name=\"zuban\" \
+             time=\"{time:.3}\">\n  </testcase>\n"
+        );
+    }
+    let tests = files.len().max(1);
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <testsuite errors=\"0\" failures=\"{failures}\" name=\"zuban\" skips=\"0\" \
+         tests=\"{tests}\" time=\"{time:.3}\">\n\
+         {test_cases}\
+         </testsuite>\n"
+    )
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(crate) fn write_github(
+    writer: &mut impl Write,
+    diagnostics: &Diagnostics,
+    current_dir: &str,
+) -> anyhow::Result<()> {
+    // See https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts
+    let escape_data = |s: &str| {
+        s.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    };
+    let escape_property = |s: &str| escape_data(s).replace(':', "%3A").replace(',', "%2C");
+    for diagnostic in &diagnostics.issues {
+        let command = match diagnostic.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information | Severity::Hint => "notice",
+        };
+        let path = display_path(diagnostic.original_file_path(), current_dir).replace('\\', "/");
+        let start = diagnostic.start_position();
+        let end = diagnostic.end_position();
+        let code = match diagnostic.mypy_error_code() {
+            "note" => String::new(),
+            code => format!("[{code}] "),
+        };
+        writeln!(
+            writer,
+            "::{command} file={},line={},col={},endLine={},endColumn={}::{code}{}",
+            escape_property(&path),
+            start.line_one_based(),
+            start.code_points_column() + 1,
+            end.line_one_based(),
+            end.code_points_column() + 1,
+            escape_data(&diagnostic.message()),
+        )?;
+    }
+    for entry in &diagnostics.stale_baseline_entries {
+        writeln!(
+            writer,
+            "::notice file={}::{}",
+            escape_property(
+                &baseline_entry_path(diagnostics, entry, current_dir).replace('\\', "/")
+            ),
+            escape_data(&format!(
+                "Baseline entry does not match an error anymore: {}  [{}]",
+                entry.message, entry.code
+            )),
+        )?;
+    }
+    writeln!(writer, "{}", diagnostics.summary())?;
+    Ok(())
+}
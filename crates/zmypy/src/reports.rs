@@ -0,0 +1,276 @@
+//! The coverage reports of Mypy, which show how precisely lines and expressions are typed.
+//!
+//! - `--lineprecision-report DIR` writes `lineprecision.txt`, the number of precise, imprecise
+//!   (the type contains `Any`), `Any`, empty and unanalyzed (unreachable) lines per module.
+//! - `--any-exprs-report DIR` writes `any-exprs.txt`, the number of expressions that contain
+//!   `Any` per module.
+//! - `--txt-report DIR` writes `index.txt`, the share of imprecise lines per module.
+//! - `--html-report DIR` writes `index.html` and a page for every module with its lines colored by
+//!   their precision.
+
+use std::{ops::AddAssign, path::Path, sync::Arc};
+
+use anyhow::Context as _;
+use config::ReportKind;
+use vfs::NormalizedPath;
+use zuban_python::{LinePrecision, ModulePrecision, TypeCoverage};
+
+use crate::output::xml_escape;
+
+pub(crate) fn write_reports(
+    modules: &[ModulePrecision],
+    report_dirs: &[(ReportKind, Arc<NormalizedPath>)],
+) -> anyhow::Result<()> {
+    for (kind, dir) in report_dirs {
+        let dir: &Path = dir.as_ref().as_ref();
+        let files = match kind {
+            ReportKind::Html => html_report(modules),
+            ReportKind::Txt => vec![("index.txt".to_string(), txt_report(modules))],
+            ReportKind::LinePrecision => vec![(
+                "lineprecision.txt".to_string(),
+                line_precision_report(modules),
+            )],
+            ReportKind::AnyExprs => vec![("any-exprs.txt".to_string(), any_exprs_report(modules))],
+        };
+        std::fs::create_dir_all(dir)
+            .and_then(|()| {
+                files
+                    .into_iter()
+                    .try_for_each(|(name, content)| std::fs::write(dir.join(name), content))
+            })
+            .with_context(|| format!("Could not write the report to {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Default, Clone, Copy)]
+struct LineCounts {
+    precise: usize,
+    imprecise: usize,
+    any: usize,
+    empty: usize,
+    unanalyzed: usize,
+}
+
+impl LineCounts {
+    fn new(module: &ModulePrecision) -> Self {
+        let mut counts = Self::default();
+        for line in &module.lines {
+            match line {
+                LinePrecision::Precise => counts.precise += 1,
+                LinePrecision::Imprecise => counts.imprecise += 1,
+                LinePrecision::Any => counts.any += 1,
+                LinePrecision::Empty => counts.empty += 1,
+                LinePrecision::Unanalyzed => counts.unanalyzed += 1,
+            }
+        }
+        counts
+    }
+
+    fn total(&self) -> usize {
+        self.precise + self.imprecise + self.any + self.empty + self.unanalyzed
+    }
+
+    /// Like Mypy, empty lines are counted as well.
+    fn imprecision(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => (self.imprecise + self.any) as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+impl AddAssign<&LineCounts> for LineCounts {
+    fn add_assign(&mut self, other: &Self) {
+        self.precise += other.precise;
+        self.imprecise += other.imprecise;
+        self.any += other.any;
+        self.empty += other.empty;
+        self.unanalyzed += other.unanalyzed;
+    }
+}
+
+pub(crate) fn line_precision_report(modules: &[ModulePrecision]) -> String {
+    let width = modules
+        .iter()
+        .map(|m| m.module.len())
+        .chain(["Name".len()])
+        .max()
+        .unwrap();
+    let mut report = format!(
+        "{:width$}  Lines  Precise  Imprecise  Any  Empty  Unanalyzed\n",
+        "Name"
+    );
+    report += &"-".repeat(width + 51);
+    report.push('\n');
+    for module in modules {
+        let c = LineCounts::new(module);
+        report += &format!(
+            "{:width$}  {:5}  {:7}  {:9}  {:3}  {:5}  {:10}\n",
+            module.module,
+            c.total(),
+            c.precise,
+            c.imprecise,
+            c.any,
+            c.empty,
+            c.unanalyzed,
+        );
+    }
+    report
+}
+
+pub(crate) fn any_exprs_report(modules: &[ModulePrecision]) -> String {
+    let row = |name: &str, coverage: &TypeCoverage| {
+        [
+            name.to_string(),
+            coverage.any.to_string(),
+            coverage.expressions.to_string(),
+            format!("{:.2}%", coverage.percentage()),
+        ]
+    };
+    let mut total = TypeCoverage::default();
+    let mut rows = vec![];
+    for module in modules {
+        total += module.coverage;
+        rows.push(row(&module.module, &module.coverage));
+    }
+    let total = row("Total", &total);
+    let header = ["Name", "Anys", "Exprs", "Coverage"].map(String::from);
+    let widths: [usize; 4] = std::array::from_fn(|i| {
+        [&header, &total]
+            .into_iter()
+            .chain(&rows)
+            .map(|row| row[i].len())
+            .max()
+            .unwrap()
+    });
+    let format_row = |row: &[String; 4]| {
+        let [w0, w1, w2, w3] = widths;
+        format!(
+            "{:<w0$}   {:>w1$}   {:>w2$}   {:>w3$}\n",
+            row[0], row[1], row[2], row[3]
+        )
+    };
+    let mut report = format_row(&header);
+    let separator = format!("{}\n", "-".repeat(report.len() - 1));
+    report += &separator;
+    for row in &rows {
+        report += &format_row(row);
+    }
+    report += &separator;
+    report += &format_row(&total);
+    report
+}
+
+fn txt_report(modules: &[ModulePrecision]) -> String {
+    let mut rows: Vec<_> = modules
+        .iter()
+        .map(|module| (module.module.as_str(), LineCounts::new(module)))
+        .collect();
+    let mut total = LineCounts::default();
+    for (_, counts) in &rows {
+        total += counts;
+    }
+    rows.push(("Total", total));
+    let cells: Vec<_> = rows
+        .iter()
+        .map(|(name, counts)| {
+            [
+                name.to_string(),
+                format!("{:.2}% imprecise", counts.imprecision()),
+                format!("{} LOC", counts.total()),
+            ]
+        })
+        .collect();
+    let header = ["Module", "Imprecision", "Lines"].map(String::from);
+    let widths: [usize; 3] = std::array::from_fn(|i| {
+        cells
+            .iter()
+            .chain([&header])
+            .map(|row| row[i].len())
+            .max()
+            .unwrap()
+    });
+    let separator = format!("+{}+\n", widths.map(|w| "-".repeat(w + 2)).join("+"));
+    let format_row = |row: &[String; 3]| {
+        let [w0, w1, w2] = widths;
+        format!("| {:<w0$} | {:>w1$} | {:>w2$} |\n", row[0], row[1], row[2])
+    };
+    let mut report = "Mypy Type Check Coverage Summary\n\
+                      ================================\n\n\
+                      Script: index\n\n"
+        .to_string();
+    report += &separator;
+    report += &format_row(&header);
+    report += &separator;
+    let (total, modules) = cells.split_last().unwrap();
+    for row in modules {
+        report += &format_row(row);
+    }
+    report += &separator;
+    report += &format_row(total);
+    report += &separator;
+    report
+}
+
+const HTML_STYLE: &str = "\
+table { border-collapse: collapse; font-family: monospace; }
+td, th { padding: 0 0.5em; text-align: left; }
+td.lineno { color: #888; text-align: right; }
+pre { margin: 0; }
+.line-precise { background-color: #cfc; }
+.line-imprecise { background-color: #ffa; }
+.line-any { background-color: #faa; }
+.line-unanalyzed { background-color: #ddd; }
+";
+
+fn html_page(title: &str, body: &str) -> String {
+    let title = xml_escape(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n"
+    )
+}
+
+fn html_report(modules: &[ModulePrecision]) -> Vec<(String, String)> {
+    let mut rows = String::new();
+    let mut files = vec![];
+    for module in modules {
+        let counts = LineCounts::new(module);
+        let name = xml_escape(&module.module);
+        rows += &format!(
+            "<tr><td><a href=\"{name}.html\">{name}</a></td><td>{:.2}% imprecise</td>\
+             <td>{} LOC</td></tr>\n",
+            counts.imprecision(),
+            counts.total(),
+        );
+        files.push((format!("{}.html", module.module), html_module_page(module)));
+    }
+    let index = format!(
+        "<table>\n<tr><th>Module</th><th>Imprecision</th><th>Lines</th></tr>\n{rows}</table>\n"
+    );
+    files.push((
+        "index.html".to_string(),
+        html_page("Mypy Type Check Coverage Summary", &index),
+    ));
+    files
+}
+
+fn html_module_page(module: &ModulePrecision) -> String {
+    let mut rows = String::new();
+    for (i, (code, precision)) in module.code.lines().zip(&module.lines).enumerate() {
+        let class = match precision {
+            LinePrecision::Empty => "line-empty",
+            LinePrecision::Unanalyzed => "line-unanalyzed",
+            LinePrecision::Precise => "line-precise",
+            LinePrecision::Imprecise => "line-imprecise",
+            LinePrecision::Any => "line-any",
+        };
+        rows += &format!(
+            "<tr class=\"{class}\"><td class=\"lineno\">{}</td><td><pre>{}</pre></td></tr>\n",
+            i + 1,
+            xml_escape(code),
+        );
+    }
+    html_page(&module.module, &format!("<table>\n{rows}</table>\n"))
+}
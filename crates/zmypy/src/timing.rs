@@ -0,0 +1,107 @@
+//! The output of `--timing-stats FILE` and `--verbose-timing`.
+//!
+//! - `--timing-stats FILE` writes a table with the parse, name binding and check durations in
+//!   milliseconds of every loaded module, the slowest first.
+//! - `--verbose-timing` prints the total durations, the slowest modules, the hit rate of the
+//!   protocol cache and the approximate memory of the loaded files to stderr.
+
+use std::{fmt::Write as _, path::Path, time::Duration};
+
+use anyhow::Context as _;
+use zuban_python::TimingStats;
+
+use crate::output::display_path;
+
+const SLOWEST_MODULES_IN_SUMMARY: usize = 10;
+
+pub(crate) fn write_timing_stats(
+    stats: &TimingStats,
+    path: &Path,
+    current_dir: &str,
+) -> anyhow::Result<()> {
+    std::fs::write(path, timing_stats_table(stats, current_dir))
+        .with_context(|| format!("Could not write the timing stats to {}", path.display()))
+}
+
+pub(crate) fn timing_stats_table(stats: &TimingStats, current_dir: &str) -> String {
+    let mut table = "module\tparse_ms\tbind_ms\tcheck_ms\ttotal_ms\tpath\n".to_string();
+    for file in &stats.files {
+        let check = match file.check {
+            Some(check) => millis(check),
+            None => "-".to_string(),
+        };
+        writeln!(
+            table,
+            "{}\t{}\t{}\t{check}\t{}\t{}",
+            file.module,
+            millis(file.parse),
+            millis(file.name_binding),
+            millis(file.total()),
+            display_path(file.path.path(), current_dir),
+        )
+        .unwrap();
+    }
+    table
+}
+
+pub(crate) fn timing_summary(stats: &TimingStats, total: Duration) -> String {
+    let sum = |f: fn(&zuban_python::FileTimingStats) -> Duration| -> Duration {
+        stats.files.iter().map(f).sum()
+    };
+    let checked = stats.files.iter().filter(|f| f.check.is_some()).count();
+    let mut summary = String::new();
+    writeln!(
+        summary,
+        "Timing: {} ms in total, {} modules loaded, {checked} checked",
+        millis(total),
+        stats.files.len(),
+    )
+    .unwrap();
+    writeln!(summary, "  parse: {} ms", millis(sum(|f| f.parse))).unwrap();
+    writeln!(
+        summary,
+        "  name binding: {} ms",
+        millis(sum(|f| f.name_binding))
+    )
+    .unwrap();
+    writeln!(
+        summary,
+        "  check: {} ms",
+        millis(sum(|f| f.check.unwrap_or_default()))
+    )
+    .unwrap();
+    writeln!(summary, "Slowest modules:").unwrap();
+    for file in stats.files.iter().take(SLOWEST_MODULES_IN_SUMMARY) {
+        writeln!(summary, "  {}: {} ms", file.module, millis(file.total())).unwrap();
+    }
+    match stats.protocol_cache_hit_rate() {
+        Some(rate) => writeln!(
+            summary,
+            "Protocol cache: {rate:.1}% hits ({} of {})",
+            stats.protocol_cache_hits,
+            stats.protocol_cache_hits + stats.protocol_cache_misses,
+        ),
+        None => writeln!(summary, "Protocol cache: unused"),
+    }
+    .unwrap();
+    let memory = &stats.memory;
+    writeln!(summary, "Memory (approximate):").unwrap();
+    for (name, bytes) in [
+        ("code", memory.code),
+        ("syntax trees", memory.syntax_trees),
+        ("inference points", memory.inference_points),
+        ("complex points", memory.complex_points),
+    ] {
+        writeln!(
+            summary,
+            "  {name}: {:.1} MiB",
+            bytes as f64 / (1024. * 1024.)
+        )
+        .unwrap();
+    }
+    summary
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.)
+}
@@ -0,0 +1,447 @@
+//! `zuban check --watch` keeps the project alive and rechecks it whenever a file changes.
+//!
+//! The first check prints all diagnostics like a normal run. After that only the diagnostics that
+//! were added (`+`) or resolved (`-`) since the previous check are printed, followed by the
+//! summary. Since the database is reused, only the files that were invalidated by a change are
+//! checked again.
+
+use std::{
+    collections::HashMap,
+    env::VarError,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cli_args::{Cli, OutputFormat};
+use colored::Colorize as _;
+use notify::EventKind;
+use vfs::{NormalizedPath, NotifyEvent};
+use zuban_python::{Project, RunCause};
+
+use crate::{
+    CheckOutput, DiagnosticConfig, check_project, colored_summary, default_output_format,
+    ensure_single_python_version, options_from_cli, paint,
+};
+
+/// Editors often write a file in several steps (e.g. truncate and write), which should only lead
+/// to a single recheck. Can be changed with the `file_watcher_debounce_ms` option.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Events usually arrive within milliseconds, but the sync directory might not be watchable.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Changes to these files change the options of the whole project, which is therefore recreated.
+const CONFIG_FILE_NAMES: [&str; 7] = [
+    "zuban.toml",
+    ".zuban.toml",
+    "pyproject.toml",
+    "mypy.ini",
+    ".mypy.ini",
+    "setup.cfg",
+    "pyrightconfig.json",
+];
+
+pub(crate) struct WatchSession {
+    cli: Cli,
+    current_dir: String,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    /// Environment variables that replace the ones of the process, e.g. the ones of the client
+    /// of a daemon. `None` means that the variable is not set for the client.
+    env_overrides: HashMap<String, Option<String>>,
+    /// A directory outside of the project that is watched together with the project, see
+    /// [`WatchSession::invalidate_pending_changes`].
+    sync_dir: Option<PathBuf>,
+    loaded: LoadedProject,
+    /// The diagnostics of the previous check, `None` before the first check.
+    previous: Option<Vec<String>>,
+    syncs: usize,
+    add_colors: bool,
+}
+
+impl WatchSession {
+    pub(crate) fn new(
+        cli: Cli,
+        current_dir: String,
+        typeshed_path: Option<Arc<NormalizedPath>>,
+        env_overrides: HashMap<String, Option<String>>,
+        sync_dir: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let loaded = LoadedProject::new(
+            &cli,
+            &current_dir,
+            typeshed_path.clone(),
+            &env_overrides,
+            sync_dir.as_deref(),
+            true,
+        )?;
+        Ok(Self {
+            cli,
+            current_dir,
+            typeshed_path,
+            env_overrides,
+            sync_dir,
+            loaded,
+            previous: None,
+            syncs: 0,
+            add_colors: true,
+        })
+    }
+
+    /// Writes all output without colors.
+    #[cfg(test)]
+    pub(crate) fn without_colors(mut self) -> Self {
+        self.add_colors = false;
+        self.loaded.output.add_colors = false;
+        self
+    }
+
+    /// Checks the project and prints the diagnostics, respectively the changes to the diagnostics
+    /// of the previous check.
+    pub(crate) fn check(&mut self, writer: &mut impl Write) -> anyhow::Result<()> {
+        self.ensure_supported_options()?;
+        let diagnostics = self.loaded.project.diagnostics()?;
+        let current: Vec<String> = diagnostics
+            .issues
+            .iter()
+            .map(|d| d.as_string(&self.loaded.config, Some(&self.current_dir)))
+            .collect();
+        if self.cli.clear_screen {
+            // Clears the screen and moves the cursor to the top left corner.
+            write!(writer, "\x1b[2J\x1b[H")?;
+        }
+        match &self.previous {
+            None => {
+                for line in &current {
+                    writeln!(writer, "{line}")?;
+                }
+            }
+            Some(previous) => {
+                let (added, resolved) = diff(previous, &current);
+                for line in &resolved {
+                    writeln!(
+                        writer,
+                        "{}",
+                        paint(format!("- {line}").green(), self.add_colors)
+                    )?;
+                }
+                for line in &added {
+                    writeln!(
+                        writer,
+                        "{}",
+                        paint(format!("+ {line}").red(), self.add_colors)
+                    )?;
+                }
+                if added.is_empty() && resolved.is_empty() {
+                    writeln!(writer, "No changes in diagnostics")?;
+                }
+            }
+        }
+        let summary = colored_summary(
+            diagnostics.summary(),
+            diagnostics.error_count() > 0,
+            self.add_colors,
+        );
+        writeln!(writer, "{summary}")?;
+        writeln!(writer, "Watching for file changes...")?;
+        writer.flush()?;
+        self.previous = Some(current);
+        self.loaded.project.enforce_memory_limit();
+        Ok(())
+    }
+
+    /// Rechecks only print the changes of the text output, so other outputs and reports are an
+    /// error instead of being ignored. A changed config might enable them, which is why this is
+    /// checked before every check.
+    fn ensure_supported_options(&self) -> anyhow::Result<()> {
+        let output = &self.loaded.output;
+        if self
+            .cli
+            .output
+            .is_some_and(|format| format != OutputFormat::Text)
+            || output.junit_xml.is_some()
+            || !output.report_dirs.is_empty()
+            || output.timing_stats.is_some()
+            || output.verbose_timing
+            || output.fail_under_coverage.is_some()
+            || output.max_new_errors.is_some()
+        {
+            anyhow::bail!(
+                "--watch only supports the text output without reports, timings and thresholds"
+            )
+        }
+        ensure_single_python_version(&self.loaded.project, "--watch")
+    }
+
+    /// Blocks until files change and invalidates them. Returns false if files can no longer be
+    /// watched.
+    pub(crate) fn wait_for_changes(&mut self) -> bool {
+        loop {
+            let Some(receiver) = self.loaded.project.vfs_handler().notify_receiver() else {
+                return false;
+            };
+            let Ok(event) = receiver.recv() else {
+                return false;
+            };
+            let debounce = (self.loaded.project.settings().file_watcher_debounce_ms)
+                .map_or(DEBOUNCE, Duration::from_millis);
+            let events = vfs::receive_coalesced_events(receiver, event, debounce);
+            let mut changed = false;
+            for event in events {
+                changed |= self.on_notify_event(event);
+            }
+            if changed {
+                return true;
+            }
+        }
+    }
+
+    fn on_notify_event(&mut self, event: NotifyEvent) -> bool {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                tracing::error!("Recreating the project, because of a notify event error: {err}");
+                self.recreate_project();
+                return true;
+            }
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return false;
+        }
+        let mut changed = false;
+        for path in &event.paths {
+            if event.need_rescan() {
+                self.rescan_path(path);
+                changed = true;
+            } else {
+                self.invalidate_path(path);
+                changed |= affects_diagnostics(path);
+            }
+        }
+        changed
+    }
+
+    /// Directories with lots of changes are read again as a whole.
+    pub(crate) fn rescan_path(&mut self, path: &Path) {
+        if CONFIG_FILE_NAMES
+            .iter()
+            .any(|name| path.join(name).is_file())
+        {
+            // A config file might be one of the changed entries.
+            tracing::info!("Recreating the project, because {path:?} changed");
+            self.recreate_project();
+            return;
+        }
+        if let Some(path) = path.to_str() {
+            let path = self.loaded.project.vfs_handler().unchecked_abs_path(path);
+            self.loaded.project.rescan_path(&path)
+        }
+    }
+
+    pub(crate) fn invalidate_path(&mut self, path: &Path) {
+        if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| CONFIG_FILE_NAMES.contains(&name))
+        {
+            tracing::info!("Recreating the project, because {path:?} changed");
+            self.recreate_project();
+            return;
+        }
+        if let Some(path) = path.to_str() {
+            let path = self.loaded.project.vfs_handler().unchecked_abs_path(path);
+            self.loaded.project.invalidate_path(&path)
+        }
+    }
+
+    fn recreate_project(&mut self) {
+        match LoadedProject::new(
+            &self.cli,
+            &self.current_dir,
+            self.typeshed_path.clone(),
+            &self.env_overrides,
+            self.sync_dir.as_deref(),
+            self.add_colors,
+        ) {
+            Ok(loaded) => self.loaded = loaded,
+            // The options of the command line were valid before, so a config change made them
+            // invalid. The previous project is kept until the config is fixed.
+            Err(err) => tracing::error!("Could not recreate the project: {err}"),
+        }
+    }
+
+    /// Invalidates the files that changed before this call.
+    ///
+    /// Events of file changes arrive with a delay, so a change right before this call might not
+    /// have been received yet. A sync file is therefore written to the sync directory, which is
+    /// watched by the same watcher as the project, and the events are processed until the event
+    /// of the sync file arrives. Events are received in the order of the changes, so all changes
+    /// before the call have been processed then.
+    pub(crate) fn invalidate_pending_changes(&mut self) {
+        // Every sync needs its own file, because the removal of the previous one might not have
+        // been received yet.
+        self.syncs += 1;
+        let sync_file_name = format!("{}.sync", self.syncs);
+        let sync_file = self.sync_dir.as_ref().map(|dir| dir.join(&sync_file_name));
+        let synced = sync_file
+            .as_ref()
+            .is_some_and(|file| std::fs::write(file, "").is_ok());
+        if !synced {
+            tracing::warn!("Could not write {sync_file:?}, pending changes might be missed");
+        }
+        let deadline = Instant::now() + SYNC_TIMEOUT;
+        while let Some(receiver) = self.loaded.project.vfs_handler().notify_receiver() {
+            let event = match synced {
+                true => receiver.recv_deadline(deadline).ok(),
+                false => receiver.try_recv().ok(),
+            };
+            let Some(event) = event else {
+                if synced {
+                    tracing::warn!("The event of {sync_file:?} did not arrive in time");
+                }
+                break;
+            };
+            let is_sync_event = event.as_ref().is_ok_and(|event| {
+                let name = Some(std::ffi::OsStr::new(&sync_file_name));
+                event.paths.iter().any(|path| path.file_name() == name)
+            });
+            self.on_notify_event(event);
+            if is_sync_event {
+                break;
+            }
+        }
+        if let Some(sync_file) = sync_file.filter(|_| synced) {
+            std::fs::remove_file(sync_file).ok();
+        }
+    }
+
+    /// Checks the project like a normal run, which writes all diagnostics and reports. Returns
+    /// `None` if multiple Python versions are checked, which is not supported.
+    pub(crate) fn check_all(
+        &mut self,
+        stdout: &mut impl Write,
+        stderr: &mut impl Write,
+    ) -> Option<anyhow::Result<bool>> {
+        if self.loaded.checks_multiple_python_versions {
+            return None;
+        }
+        let result = check_project(
+            &mut self.loaded.project,
+            stdout,
+            stderr,
+            &self.loaded.config,
+            &self.current_dir,
+            &self.loaded.output,
+            Instant::now(),
+        );
+        self.loaded.project.enforce_memory_limit();
+        Some(result)
+    }
+}
+
+struct LoadedProject {
+    project: Project,
+    config: DiagnosticConfig,
+    output: CheckOutput,
+    checks_multiple_python_versions: bool,
+}
+
+impl LoadedProject {
+    fn new(
+        cli: &Cli,
+        current_dir: &str,
+        typeshed_path: Option<Arc<NormalizedPath>>,
+        env_overrides: &HashMap<String, Option<String>>,
+        sync_dir: Option<&Path>,
+        add_colors: bool,
+    ) -> anyhow::Result<Self> {
+        let lookup_env_var = |name: &str| match env_overrides.get(name) {
+            Some(value) => value.clone().ok_or(VarError::NotPresent),
+            None => std::env::var(name),
+        };
+        let (local_fs, options, config) =
+            options_from_cli(cli.clone(), current_dir, typeshed_path, lookup_env_var)?;
+        if let Some(sync_dir) = sync_dir {
+            match std::fs::create_dir_all(sync_dir) {
+                Ok(()) => local_fs.watch(sync_dir),
+                Err(err) => tracing::warn!("Could not create {sync_dir:?}: {err}"),
+            }
+        }
+        let output = CheckOutput {
+            format: cli
+                .output
+                .unwrap_or_else(|| default_output_format(lookup_env_var)),
+            add_colors,
+            junit_xml: options.settings.junit_xml.clone(),
+            report_dirs: options.settings.report_dirs.clone(),
+            timing_stats: cli.timing_stats.clone(),
+            verbose_timing: cli.verbose_timing,
+            fail_under_coverage: cli.fail_under_coverage,
+            max_new_errors: cli.max_new_errors,
+        };
+        let checks_multiple_python_versions = options.settings.python_versions.len() > 1;
+        Ok(Self {
+            project: Project::new(Box::new(local_fs), options, RunCause::LanguageServer),
+            config,
+            output,
+            checks_multiple_python_versions,
+        })
+    }
+}
+
+/// Changes to other files like logs or build artifacts are still invalidated, but do not lead to a
+/// recheck. Paths without an extension are usually directories, which might contain new modules.
+fn affects_diagnostics(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => {
+            matches!(extension, "py" | "pyi")
+                || path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| CONFIG_FILE_NAMES.contains(&name))
+        }
+        None => true,
+    }
+}
+
+/// Returns the added and the resolved diagnostics. Diagnostics are compared as a multiset,
+/// because the same diagnostic might be reported multiple times on a line.
+fn diff<'a>(previous: &'a [String], current: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in previous {
+        *counts.entry(line).or_default() -= 1;
+    }
+    let mut added = vec![];
+    for line in current {
+        let count = counts.entry(line).or_default();
+        *count += 1;
+        if *count > 0 {
+            added.push(line.as_str())
+        }
+    }
+    let mut resolved = vec![];
+    for line in previous.iter().rev() {
+        let count = counts.get_mut(line.as_str()).unwrap();
+        if *count < 0 {
+            *count += 1;
+            resolved.push(line.as_str())
+        }
+    }
+    resolved.reverse();
+    (added, resolved)
+}
+
+pub(crate) fn run_watch(cli: Cli, current_dir: String) -> anyhow::Result<()> {
+    let mut session = WatchSession::new(cli, current_dir, None, HashMap::new(), None)?;
+    let stdout = &mut std::io::stdout();
+    loop {
+        session.check(stdout)?;
+        if !session.wait_for_changes() {
+            anyhow::bail!("Could not watch the files of the project");
+        }
+    }
+}
@@ -17,8 +17,73 @@ enum Commands {
     Check(#[command(flatten)] cli_args::Cli),
     /// Type checks files like you would do when calling `mypy`
     Mypy(#[command(flatten)] cli_args::MypyCli),
+    /// Reports the percentage of expressions with precise (non-Any) types for every file that
+    /// would be checked
+    Coverage {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        zmypy_config: cli_args::Cli,
+    },
+    /// Writes the types that are shown as inlay hints (variable and return types) into the files
+    /// that would be checked
+    Annotate {
+        /// Only add annotations that are None or builtin scalars like int and str
+        #[arg(long)]
+        only_obvious: bool,
+        /// Add imports for types that are not accessible yet instead of skipping them
+        #[arg(long)]
+        add_imports: bool,
+        #[command(flatten)]
+        zmypy_config: cli_args::Cli,
+    },
+    /// Generates stubs (.pyi files) for every file that would be checked
+    Stubgen {
+        /// The directory the stubs are written to
+        #[arg(short, long, default_value = "out")]
+        output_dir: std::path::PathBuf,
+        /// Also include names that start with an underscore
+        #[arg(long)]
+        include_private: bool,
+        /// Annotate unannotated code with the inferred types
+        #[arg(long)]
+        infer_types: bool,
+        #[command(flatten)]
+        zmypy_config: cli_args::Cli,
+    },
+    /// Keeps the project in memory between checks. `zuban check` uses the daemon of the current
+    /// directory while it is running.
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
     /// Starts an LSP server
-    Server {},
+    Server {
+        /// Listen on tcp://[HOST:]PORT or ws://[HOST:]PORT (WebSocket) instead of using stdio.
+        /// Clients are served one after another. The host defaults to 127.0.0.1, there is no
+        /// authentication, so other hosts should only be used in trusted networks.
+        #[arg(long)]
+        listen: Option<zubanls::ListenAddress>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Starts a daemon in the background with the arguments of `zuban check`
+    Start {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Runs a daemon in the foreground with the arguments of `zuban check`
+    Run {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Shows whether a daemon is running
+    Status,
+    /// Stops the running daemon
+    Stop,
 }
 
 fn main() -> ExitCode {
@@ -30,8 +95,60 @@ fn main() -> ExitCode {
     };
     match Cli::parse().command {
         Commands::Mypy(mypy_options) => run_check(cli_args::Cli::new_mypy_compatible(mypy_options)),
-        Commands::Check(zmypy_config) => run_check(zmypy_config),
-        Commands::Server {} => match run_server() {
+        Commands::Check(zmypy_config) => {
+            if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
+                panic!("{err}")
+            };
+            // The arguments after `zuban check` are sent to a running daemon.
+            zmypy::run_with_daemon(zmypy_config, std::env::args().skip(2).collect())
+        }
+        Commands::Daemon { command } => {
+            if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
+                panic!("{err}")
+            };
+            match command {
+                DaemonCommand::Start { args } => zmypy::daemon_start(args),
+                DaemonCommand::Run { args } => zmypy::daemon_run(args),
+                DaemonCommand::Status => zmypy::daemon_status(),
+                DaemonCommand::Stop => zmypy::daemon_stop(),
+            }
+        }
+        Commands::Coverage { json, zmypy_config } => {
+            if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
+                panic!("{err}")
+            };
+            zmypy::run_coverage(zmypy_config, json)
+        }
+        Commands::Annotate {
+            only_obvious,
+            add_imports,
+            zmypy_config,
+        } => {
+            if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
+                panic!("{err}")
+            };
+            let options = zmypy::AnnotateOptions {
+                only_obvious,
+                add_imports,
+            };
+            zmypy::run_annotate(zmypy_config, options)
+        }
+        Commands::Stubgen {
+            output_dir,
+            include_private,
+            infer_types,
+            zmypy_config,
+        } => {
+            if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
+                panic!("{err}")
+            };
+            let options = zmypy::StubgenOptions {
+                include_private,
+                infer_types,
+            };
+            zmypy::run_stubgen(zmypy_config, &output_dir, options)
+        }
+        Commands::Server { listen } => match run_server(listen) {
             Ok(()) => ExitCode::from(0),
             Err(err) => {
                 eprintln!("{err}");
@@ -41,14 +158,17 @@ fn main() -> ExitCode {
     }
 }
 
-fn run_server() -> anyhow::Result<()> {
+fn run_server(listen: Option<zubanls::ListenAddress>) -> anyhow::Result<()> {
     logging_config::setup_logging(None)?;
 
     // Logging to stderr.
     tracing::info!("Starting the Zuban Language Server");
 
     event_loop_thread(move || {
-        zubanls::run_server()?;
+        match listen {
+            Some(address) => zubanls::run_server_listening(&address)?,
+            None => zubanls::run_server()?,
+        }
         Ok(())
     })?;
 
@@ -0,0 +1,398 @@
+//! A stable API to embed the Zuban type checker in other Rust tools, e.g. formatters, refactoring
+//! engines and build systems.
+//!
+//! Unlike `zuban_python`, this crate does not expose any internals of the type checker, so tools
+//! don't break when they change. The crate is not published yet and is used as a git dependency.
+//! New fields and variants might be added at any time, which is why all of them are
+//! `#[non_exhaustive]`.
+//!
+//! ```no_run
+//! use zuban_api::{Position, Project};
+//!
+//! let mut project = Project::load("pyproject.toml".as_ref(), None)?;
+//! for diagnostic in project.check()? {
+//!     println!("{}: {}", diagnostic.path.display(), diagnostic.message);
+//! }
+//! project.set_overlay("foo.py".as_ref(), "x = [1]")?;
+//! let ty = project.document("foo.py".as_ref())?.type_at(Position::new(0, 0))?;
+//! assert_eq!(ty.as_deref(), Some("builtins.list[builtins.int]"));
+//! # anyhow::Ok(())
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use vfs::{NormalizedPath, PathWithScheme, SimpleLocalFS, VfsHandler as _};
+use zuban_python::{InputPosition, RunCause};
+
+/// A position in a file. Lines and columns start at 0, columns are counted in code points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+
+    fn to_input_position(self) -> InputPosition {
+        InputPosition::CodePoints {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Hint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub start: Position,
+    pub end: Position,
+    pub severity: Severity,
+    /// The Mypy error code, e.g. `arg-type`, or `note` for notes without a code
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(diagnostic: &zuban_python::Diagnostic) -> Self {
+        let position = |infos: zuban_python::PositionInfos| {
+            Position::new(infos.line_zero_based(), infos.code_points_column())
+        };
+        Self {
+            path: PathBuf::from(&**diagnostic.original_file_path()),
+            start: position(diagnostic.start_position()),
+            end: position(diagnostic.end_position()),
+            severity: match diagnostic.severity() {
+                zuban_python::Severity::Error => Severity::Error,
+                zuban_python::Severity::Warning => Severity::Warning,
+                zuban_python::Severity::Information => Severity::Note,
+                zuban_python::Severity::Hint => Severity::Hint,
+            },
+            code: diagnostic.mypy_error_code(),
+            message: diagnostic.message(),
+        }
+    }
+}
+
+/// The signature of a call, there are multiple for overloaded functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Signature {
+    /// e.g. `(a: int, b: str) -> list[int]`
+    pub label: String,
+    /// The index of the parameter the position is on
+    pub current_param: Option<usize>,
+}
+
+/// A type checked project. Files on disk are read lazily and are not watched, changes have to be
+/// reported with [`Project::invalidate`] or be set as overlays.
+pub struct Project {
+    project: zuban_python::Project,
+}
+
+impl Project {
+    /// Loads the project of a Mypy or Zuban config file like `pyproject.toml` or `mypy.ini`. If a
+    /// directory is passed, the config is searched in it and its parents like the CLI does.
+    ///
+    /// Typeshed is searched next to the executable or in `$ZUBAN_TYPESHED`, unless a path is
+    /// given.
+    pub fn load(config: &Path, typeshed_path: Option<&Path>) -> anyhow::Result<Self> {
+        let local_fs = SimpleLocalFS::without_watcher();
+        let path = local_fs.normalized_path_from_current_dir(to_str(config)?);
+        let (current_dir, config_file) = if Path::new(&***path).is_dir() {
+            (local_fs.unchecked_abs_path(&path), None)
+        } else {
+            let Some(parent) = local_fs.parent_of_absolute_path(&path) else {
+                anyhow::bail!("Expected a config file or a directory, got {config:?}")
+            };
+            (parent.into(), Some(Path::new(&***path)))
+        };
+        let found = config::find_cli_config(&local_fs, current_dir, config_file, &[], None)?;
+        let mut options = found.project_options;
+        let settings = &mut options.settings;
+        settings
+            .mypy_path
+            .push(local_fs.normalize_rc_path(found.most_probable_base.clone()));
+        if let Some(typeshed_path) = typeshed_path {
+            settings.typeshed_path =
+                Some(local_fs.normalized_path_from_current_dir(to_str(typeshed_path)?));
+        }
+        settings.try_to_apply_environment_variables(&local_fs, &found.most_probable_base, |name| {
+            std::env::var(name)
+        });
+        Ok(Self {
+            project: zuban_python::Project::new(
+                Box::new(local_fs),
+                options,
+                RunCause::LanguageServer,
+            ),
+        })
+    }
+
+    /// Type checks the project and returns the diagnostics of all checked files.
+    pub fn check(&mut self) -> anyhow::Result<Vec<Diagnostic>> {
+        let diagnostics = self.project.diagnostics()?;
+        Ok(diagnostics.issues.iter().map(Diagnostic::new).collect())
+    }
+
+    /// Uses the code instead of the content of the file on disk, like an unsaved file in an
+    /// editor. The file does not need to exist.
+    pub fn set_overlay(&mut self, path: &Path, code: &str) -> anyhow::Result<()> {
+        let path = self.file_path(path)?;
+        self.project.store_in_memory_file(path, code.into());
+        Ok(())
+    }
+
+    /// Uses the content of the file on disk again.
+    pub fn remove_overlay(&mut self, path: &Path) -> anyhow::Result<()> {
+        let path = self.file_path(path)?;
+        self.project
+            .close_in_memory_file(&path)
+            .map_err(|err| anyhow::anyhow!("{err}: {}", path.as_uri()))
+    }
+
+    /// Rereads a file or directory that was changed on disk.
+    pub fn invalidate(&mut self, path: &Path) -> anyhow::Result<()> {
+        let path = self.absolute_path(path)?;
+        self.project.invalidate_path(&path);
+        Ok(())
+    }
+
+    pub fn document(&mut self, path: &Path) -> anyhow::Result<Document<'_>> {
+        let file_path = self.file_path(path)?;
+        match self.project.document(&file_path) {
+            Some(document) => Ok(Document { document }),
+            None => anyhow::bail!("Could not load {path:?}"),
+        }
+    }
+
+    fn file_path(&self, path: &Path) -> anyhow::Result<PathWithScheme> {
+        Ok(PathWithScheme::with_file_scheme(self.absolute_path(path)?))
+    }
+
+    fn absolute_path(&self, path: &Path) -> anyhow::Result<Arc<NormalizedPath>> {
+        let vfs = self.project.vfs_handler();
+        let current_dir = std::env::current_dir()?;
+        let current_dir = vfs.unchecked_abs_path(to_str(&current_dir)?);
+        Ok(vfs.normalize_rc_path(vfs.absolute_path(&current_dir, to_str(path)?)))
+    }
+}
+
+fn to_str(path: &Path) -> anyhow::Result<&str> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => anyhow::bail!("Expected a valid UTF-8 encoded path, got {path:?}"),
+    }
+}
+
+/// A file of a [`Project`], which is type checked lazily when it is queried.
+pub struct Document<'project> {
+    document: zuban_python::Document<'project>,
+}
+
+impl Document<'_> {
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(&***self.document.path().path())
+    }
+
+    pub fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.document
+            .diagnostics()
+            .iter()
+            .map(Diagnostic::new)
+            .collect()
+    }
+
+    /// The type of the expression at the position formatted like `reveal_type` does, e.g.
+    /// `builtins.list[builtins.int]`.
+    pub fn type_at(&self, position: Position) -> anyhow::Result<Option<String>> {
+        self.document.inferred_type(position.to_input_position())
+    }
+
+    /// The hover of an editor as markdown, i.e. the declaration and the docstring.
+    pub fn hover(&self, position: Position) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .document
+            .documentation(position.to_input_position(), false)?
+            .map(|result| result.documentation))
+    }
+
+    /// The signatures of the call the position is in.
+    pub fn signatures(&self, position: Position) -> anyhow::Result<Vec<Signature>> {
+        let Some(signatures) = self
+            .document
+            .call_signatures(position.to_input_position())?
+        else {
+            return Ok(vec![]);
+        };
+        Ok(signatures
+            .into_iterator()
+            .map(|signature| Signature {
+                label: signature.label.into(),
+                current_param: signature.current_param,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::TestDir;
+
+    use super::*;
+
+    const FOO: &str = "def f(a: int, b: str) -> list[int]:\n    \"\"\"f doc\"\"\"\n    return [a]\n\
+                       x = f(1, '')\ny: str = x\n";
+    const ASSIGNMENT_ERROR: &str = r#"Incompatible types in assignment (expression has type "list[int]", variable has type "str")"#;
+
+    fn load(dir: &TestDir) -> Project {
+        dir.write_file("mypy.ini", "[mypy]\n");
+        let typeshed_path = test_utils::typeshed_path();
+        Project::load(
+            &Path::new(dir.path()).join("mypy.ini"),
+            Some(Path::new(&***typeshed_path)),
+        )
+        .unwrap()
+    }
+
+    /// The messages of the diagnostics, sorted to not depend on the order files are checked in.
+    fn messages(diagnostics: Vec<Diagnostic>) -> Vec<String> {
+        let mut messages: Vec<_> = diagnostics.into_iter().map(|d| d.message).collect();
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = TestDir::new(false);
+        dir.write_file("foo.py", FOO);
+        let mut project = load(&dir);
+        let foo = Path::new(dir.path()).join("foo.py");
+
+        let diagnostics = project.check().unwrap();
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("{diagnostics:?}")
+        };
+        assert_eq!(diagnostic.path, foo);
+        assert_eq!(diagnostic.start, Position::new(4, 9));
+        assert_eq!(diagnostic.end, Position::new(4, 10));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.code, "assignment");
+        assert_eq!(diagnostic.message, ASSIGNMENT_ERROR);
+    }
+
+    #[test]
+    fn test_document_queries() {
+        let dir = TestDir::new(false);
+        dir.write_file("foo.py", FOO);
+        let mut project = load(&dir);
+        let foo = Path::new(dir.path()).join("foo.py");
+
+        let mut document = project.document(&foo).unwrap();
+        assert_eq!(document.path(), foo);
+        assert_eq!(messages(document.diagnostics()), [ASSIGNMENT_ERROR]);
+
+        // Types
+        assert_eq!(
+            document.type_at(Position::new(3, 0)).unwrap().as_deref(),
+            Some("builtins.list[builtins.int]")
+        );
+        assert_eq!(
+            document.type_at(Position::new(4, 0)).unwrap().as_deref(),
+            Some("builtins.str")
+        );
+
+        // Hovers
+        assert_eq!(
+            document.hover(Position::new(3, 4)).unwrap().as_deref(),
+            Some("```python\n(function) def f(a: int, b: str) -> list[int]\n```\n---\nf doc")
+        );
+        assert_eq!(
+            document.hover(Position::new(4, 0)).unwrap().as_deref(),
+            Some("```python\n(variable) y: str\n```")
+        );
+
+        // Signatures
+        let signatures = document.signatures(Position::new(3, 6)).unwrap();
+        assert_eq!(
+            signatures,
+            [Signature {
+                label: "(a: int, b: str) -> list[int]".to_string(),
+                current_param: Some(0),
+            }]
+        );
+        let signatures = document.signatures(Position::new(3, 9)).unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].current_param, Some(1));
+        assert!(document.signatures(Position::new(4, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_overlays() {
+        let dir = TestDir::new(false);
+        dir.write_file("foo.py", FOO);
+        dir.write_file("bar.py", "from foo import y\nz: int = y\n");
+        let mut project = load(&dir);
+        let foo = Path::new(dir.path()).join("foo.py");
+        let bar = Path::new(dir.path()).join("bar.py");
+        const BAR_ERROR: &str = r#"Incompatible types in assignment (expression has type "str", variable has type "int")"#;
+        assert_eq!(
+            messages(project.check().unwrap()),
+            [ASSIGNMENT_ERROR, BAR_ERROR]
+        );
+
+        // Dependent files are checked again with the overlay.
+        project.set_overlay(&foo, "y = 1").unwrap();
+        assert_eq!(messages(project.check().unwrap()), Vec::<String>::new());
+        assert_eq!(
+            project
+                .document(&foo)
+                .unwrap()
+                .type_at(Position::new(0, 0))
+                .unwrap()
+                .as_deref(),
+            Some("builtins.int")
+        );
+
+        // Overlays of files that don't exist on disk
+        let new = Path::new(dir.path()).join("new.py");
+        project.set_overlay(&new, "from bar import z\nz()").unwrap();
+        assert_eq!(
+            messages(project.document(&new).unwrap().diagnostics()),
+            [r#""int" not callable"#]
+        );
+        project.remove_overlay(&new).unwrap();
+
+        project.remove_overlay(&foo).unwrap();
+        assert_eq!(
+            messages(project.check().unwrap()),
+            [ASSIGNMENT_ERROR, BAR_ERROR]
+        );
+        assert!(project.remove_overlay(&bar).is_err());
+
+        // Changes on disk are only seen after invalidating the file.
+        dir.write_file("foo.py", "y = ''");
+        assert_eq!(
+            messages(project.check().unwrap()),
+            [ASSIGNMENT_ERROR, BAR_ERROR]
+        );
+        project.invalidate(&foo).unwrap();
+        assert_eq!(messages(project.check().unwrap()), [BAR_ERROR]);
+    }
+}
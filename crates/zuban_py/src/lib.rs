@@ -0,0 +1,253 @@
+//! Python bindings that expose the inference of Zuban, e.g. for code generators, documentation
+//! builders and runtime validators. The Python package is built with maturin from
+//! `deploy/pypi/zuban-api`.
+
+use std::path::Path;
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+use zuban_api::{Position, Severity};
+
+fn runtime_error(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{err:#}"))
+}
+
+fn value_error(err: anyhow::Error) -> PyErr {
+    PyValueError::new_err(format!("{err:#}"))
+}
+
+/// A type checked project. Files are read once and not watched, load the project again to pick up
+/// changes.
+#[pyclass(unsendable, module = "zuban_api")]
+struct Project {
+    project: zuban_api::Project,
+}
+
+#[pymethods]
+impl Project {
+    /// Loads the project of a Mypy or Zuban config file like `pyproject.toml` or `mypy.ini`. If a
+    /// directory is passed, the config is searched in it and its parents like the CLI does.
+    #[staticmethod]
+    #[pyo3(signature = (config, typeshed_path=None))]
+    fn load(config: &str, typeshed_path: Option<&str>) -> PyResult<Self> {
+        let project = zuban_api::Project::load(Path::new(config), typeshed_path.map(Path::new))
+            .map_err(value_error)?;
+        Ok(Self { project })
+    }
+
+    /// Type checks the project and returns the errors and notes of all checked files.
+    fn check(&mut self) -> PyResult<Vec<Diagnostic>> {
+        let diagnostics = self.project.check().map_err(runtime_error)?;
+        Ok(diagnostics.into_iter().map(Diagnostic::new).collect())
+    }
+
+    /// The type of the expression at the position formatted like `reveal_type` does, e.g.
+    /// `builtins.list[builtins.int]`. Lines start at 1 and columns at 0.
+    fn type_at(&mut self, path: &str, line: usize, column: usize) -> PyResult<Option<String>> {
+        let position = position(line, column)?;
+        let document = self
+            .project
+            .document(Path::new(path))
+            .map_err(value_error)?;
+        document.type_at(position).map_err(runtime_error)
+    }
+
+    /// The signatures of the call the position is in, there might be multiple for overloads.
+    fn infer_signature(
+        &mut self,
+        path: &str,
+        line: usize,
+        column: usize,
+    ) -> PyResult<Vec<Signature>> {
+        let position = position(line, column)?;
+        let document = self
+            .project
+            .document(Path::new(path))
+            .map_err(value_error)?;
+        let signatures = document.signatures(position).map_err(runtime_error)?;
+        Ok(signatures.into_iter().map(Signature::new).collect())
+    }
+}
+
+fn position(line: usize, column: usize) -> PyResult<Position> {
+    let Some(line) = line.checked_sub(1) else {
+        return Err(PyValueError::new_err("Lines start at 1"));
+    };
+    Ok(Position::new(line, column))
+}
+
+#[pyclass(frozen, get_all, module = "zuban_api")]
+struct Diagnostic {
+    path: String,
+    /// Starts at 1
+    line: usize,
+    /// Starts at 0 and counts code points
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    /// One of "error", "warning", "note" and "hint"
+    severity: &'static str,
+    /// The Mypy error code, e.g. "arg-type"
+    code: &'static str,
+    message: String,
+}
+
+impl Diagnostic {
+    fn new(diagnostic: zuban_api::Diagnostic) -> Self {
+        Self {
+            path: diagnostic.path.to_string_lossy().into_owned(),
+            line: diagnostic.start.line + 1,
+            column: diagnostic.start.column,
+            end_line: diagnostic.end.line + 1,
+            end_column: diagnostic.end.column,
+            severity: match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+                Severity::Hint => "hint",
+                _ => "unknown",
+            },
+            code: diagnostic.code,
+            message: diagnostic.message,
+        }
+    }
+}
+
+#[pymethods]
+impl Diagnostic {
+    fn __repr__(&self) -> String {
+        format!(
+            "<Diagnostic {}:{}:{}: {}: {}  [{}]>",
+            self.path,
+            self.line,
+            self.column + 1,
+            self.severity,
+            self.message,
+            self.code
+        )
+    }
+}
+
+#[pyclass(frozen, get_all, module = "zuban_api")]
+struct Signature {
+    label: String,
+    /// The index of the parameter the position is on, if any
+    current_param: Option<usize>,
+}
+
+impl Signature {
+    fn new(signature: zuban_api::Signature) -> Self {
+        Self {
+            label: signature.label,
+            current_param: signature.current_param,
+        }
+    }
+}
+
+#[pymethods]
+impl Signature {
+    fn __repr__(&self) -> String {
+        format!("<Signature {}>", self.label)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "_native")]
+fn zuban_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Project>()?;
+    m.add_class::<Diagnostic>()?;
+    m.add_class::<Signature>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::TestDir;
+
+    use super::*;
+
+    fn load(dir: &TestDir) -> Project {
+        dir.write_file("mypy.ini", "[mypy]\n");
+        let typeshed_path = test_utils::typeshed_path();
+        Project::load(&format!("{}/mypy.ini", dir.path()), Some(&***typeshed_path)).unwrap()
+    }
+
+    #[test]
+    fn test_conversions() {
+        let dir = TestDir::new(false);
+        dir.write_file(
+            "foo.py",
+            "def f(a: int, b: str) -> int: ...\nx: str = f(1, '')\nreveal_type(x)\n",
+        );
+        let mut project = load(&dir);
+        let foo = format!("{}/foo.py", dir.path());
+
+        let diagnostics = project.check().unwrap();
+        let [error, note] = diagnostics.as_slice() else {
+            panic!(
+                "{:?}",
+                diagnostics.iter().map(|d| d.__repr__()).collect::<Vec<_>>()
+            )
+        };
+        // Lines start at 1, columns at 0.
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!((error.end_line, error.end_column), (2, 17));
+        assert_eq!(error.severity, "error");
+        assert_eq!(error.code, "assignment");
+        assert_eq!(
+            error.__repr__(),
+            format!(
+                "<Diagnostic {foo}:2:10: error: Incompatible types in assignment (expression has \
+                 type \"int\", variable has type \"str\")  [assignment]>"
+            )
+        );
+        assert_eq!(note.path, foo);
+        assert_eq!(note.line, 3);
+        assert_eq!(note.severity, "note");
+        assert_eq!(note.message, r#"Revealed type is "builtins.str""#);
+
+        assert_eq!(
+            project.type_at(&foo, 2, 0).unwrap().as_deref(),
+            Some("builtins.str")
+        );
+        let signatures = project.infer_signature(&foo, 2, 14).unwrap();
+        let [signature] = signatures.as_slice() else {
+            panic!(
+                "{:?}",
+                signatures.iter().map(|s| s.__repr__()).collect::<Vec<_>>()
+            )
+        };
+        assert_eq!(signature.label, "(a: int, b: str) -> int");
+        assert_eq!(signature.current_param, Some(1));
+        assert_eq!(signature.__repr__(), "<Signature (a: int, b: str) -> int>");
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = TestDir::new(false);
+        let mut project = load(&dir);
+        let foo = format!("{}/foo.py", dir.path());
+
+        let message = |err: PyErr| {
+            Python::initialize();
+            Python::attach(|py| {
+                assert!(err.is_instance_of::<PyValueError>(py), "{err}");
+                err.value(py).to_string()
+            })
+        };
+        let Err(err) = Project::load(&format!("{}/missing.ini", dir.path()), None) else {
+            panic!("Expected an error for a missing config")
+        };
+        assert!(message(err).starts_with("Issue while reading "));
+        let Err(err) = project.type_at(&foo, 0, 0) else {
+            panic!("Expected an error for line 0")
+        };
+        assert_eq!(message(err), "Lines start at 1");
+        let Err(err) = project.infer_signature(&foo, 1, 0) else {
+            panic!("Expected an error for a missing file")
+        };
+        assert!(message(err).starts_with("Could not load "));
+    }
+}
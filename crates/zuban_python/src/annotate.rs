@@ -0,0 +1,156 @@
+//! Writes the types of inlay hints into the source code, which is a batch version of accepting
+//! all inlay hints in an editor.
+
+use parsa_python_cst::CodeIndex;
+
+use crate::{
+    database::{Database, ParentScope},
+    file::PythonFile,
+    inlay_hints::{InlayHintOptions, inlay_hint},
+    quick_fixes::{writable_annotation, writable_annotation_with_additional_names},
+    type_::Type,
+};
+
+/// Options for [`crate::Project::annotate`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AnnotateOptions {
+    /// Only add annotations that are `None` or builtin scalars like `int` and `str`.
+    pub only_obvious: bool,
+    /// Adds imports for classes that are not accessible in a file yet. Otherwise such annotations
+    /// are skipped.
+    pub add_imports: bool,
+}
+
+/// Returns the new code of the file or nothing if no annotations were added.
+pub(crate) fn annotate_file(
+    db: &Database,
+    file: &PythonFile,
+    options: AnnotateOptions,
+) -> Option<String> {
+    let code = file.tree.code();
+    let mut insertions = vec![];
+    let mut imports: Vec<String> = vec![];
+    for potential in file.tree.potential_inlay_hints(0, code.len() as u32) {
+        let Some(hint) = inlay_hint(db, file, potential, InlayHintOptions::default()) else {
+            continue;
+        };
+        let annotation = match writable_annotation(db, file, &hint.type_) {
+            Some(annotation) => annotation,
+            None if options.add_imports => {
+                let Some((annotation, new_imports)) =
+                    annotation_with_imports(db, file, &hint.type_)
+                else {
+                    continue;
+                };
+                for import in new_imports {
+                    if !imports.contains(&import) {
+                        imports.push(import)
+                    }
+                }
+                annotation
+            }
+            None => continue,
+        };
+        if options.only_obvious && !is_obvious(&annotation) {
+            continue;
+        }
+        insertions.push((
+            hint.position.byte_position,
+            format!("{}{annotation}", hint.label_prefix()),
+        ));
+    }
+    if insertions.is_empty() {
+        return None;
+    }
+    let mut new_code = code.to_string();
+    // Inserting from the end keeps the positions of earlier insertions valid.
+    for (position, insertion) in insertions.into_iter().rev() {
+        new_code.insert_str(position, &insertion);
+    }
+    if !imports.is_empty() {
+        imports.sort();
+        let (position, import_code) = import_insertion(file, &imports);
+        new_code.insert_str(position as usize, &import_code);
+    }
+    Some(new_code)
+}
+
+/// Where the imports are added and their code. They are added after the imports at the start
+/// of the file.
+pub(crate) fn import_insertion(file: &PythonFile, imports: &[String]) -> (CodeIndex, String) {
+    let position = file.tree.initial_imports_end_code_index();
+    let mut import_code = String::new();
+    if position > 0 && !file.tree.code()[..position as usize].ends_with('\n') {
+        import_code.push('\n');
+    }
+    for import in imports {
+        import_code += import;
+        import_code.push('\n');
+    }
+    if position == 0 {
+        import_code.push('\n');
+    }
+    (position, import_code)
+}
+
+fn is_obvious(annotation: &str) -> bool {
+    matches!(
+        annotation,
+        "None" | "bool" | "int" | "float" | "complex" | "str" | "bytes"
+    )
+}
+
+/// The annotation together with the imports that make it writable. Only classes defined in the
+/// module scope of a module that exists at runtime and `Any` can be imported.
+pub(crate) fn annotation_with_imports(
+    db: &Database,
+    file: &PythonFile,
+    t: &Type,
+) -> Option<(String, Vec<String>)> {
+    let mut imports: Vec<(String, String)> = vec![];
+    let mut is_importable = true;
+    t.find_in_type(db, &mut |t| {
+        let class = match t {
+            Type::Any(_) => {
+                let name = "Any";
+                if file.lookup_symbol(name).is_none() && !imports.iter().any(|(_, n)| *n == name) {
+                    imports.push(("typing".to_string(), name.to_string()));
+                }
+                return false;
+            }
+            Type::Class(c) => c.class(db),
+            Type::Dataclass(d) => d.class(db),
+            Type::Enum(e) => e.class(db),
+            _ => return false,
+        };
+        let name = class.name();
+        if file.lookup_symbol(name).is_some()
+            || db.python_state.builtins().lookup_symbol(name).is_some()
+        {
+            return false;
+        }
+        let module = class.node_ref.file.qualified_name(db);
+        if !matches!(class.class_storage.parent_scope, ParentScope::Module)
+            || module == "_typeshed"
+            || class.node_ref.file.file_index == file.file_index
+        {
+            is_importable = false;
+            return true;
+        }
+        if !imports.iter().any(|(_, n)| *n == name) {
+            imports.push((module, name.to_string()));
+        }
+        false
+    });
+    if !is_importable {
+        return None;
+    }
+    imports.sort();
+    let names: Vec<_> = imports.iter().map(|(_, name)| name.as_str()).collect();
+    let annotation = writable_annotation_with_additional_names(db, file, t, &names)?;
+    let imports = imports
+        .into_iter()
+        .map(|(module, name)| format!("from {module} import {name}"))
+        .collect();
+    Some((annotation, imports))
+}
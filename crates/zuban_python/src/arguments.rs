@@ -7,7 +7,7 @@ use parsa_python_cst::{
 
 use crate::{
     InferenceState,
-    database::{Database, PointsBackup},
+    database::{Database, PointLink, PointsBackup},
     debug,
     diagnostics::IssueKind,
     file::PythonFile,
@@ -565,18 +565,20 @@ impl<'db> Arg<'db, '_> {
         i_s: &InferenceState,
         got: &str,
         expected: &str,
+        classes: Option<(PointLink, PointLink)>,
         error_text: &dyn Fn(&str) -> Option<Box<str>>,
     ) -> bool {
         self.add_issue(
             i_s,
-            IssueKind::ArgumentTypeIssue(
-                format!(
+            IssueKind::ArgumentTypeIssue {
+                message: format!(
                     "Argument {}{} has incompatible type {got}; expected \"{expected}\"",
                     self.human_readable_index(i_s.db),
                     error_text(" to ").as_deref().unwrap_or(""),
                 )
                 .into(),
-            ),
+                classes,
+            },
         )
     }
 
@@ -917,13 +919,14 @@ impl<'db: 'a, 'a> Iterator for ArgIteratorBase<'db, 'a> {
                             } else {
                                 node_ref.add_issue(
                                     i_s,
-                                    IssueKind::ArgumentTypeIssue(
-                                        format!(
+                                    IssueKind::ArgumentTypeIssue {
+                                        message: format!(
                                             "Argument after ** must be a mapping, not \"{}\"",
                                             type_.format_short(i_s.db),
                                         )
                                         .into(),
-                                    ),
+                                        classes: None,
+                                    },
                                 );
                                 Type::ERROR
                             };
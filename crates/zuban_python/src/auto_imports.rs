@@ -6,7 +6,8 @@ use std::{
 
 use config::ProjectOptions;
 use parsa_python_cst::{
-    CodeIndex, DottedImportName, DottedImportNameContent, Name, NameImportParent, Scope,
+    CodeIndex, DottedAsName, DottedImportName, DottedImportNameContent, ImportFrom, Name,
+    NameImportParent, Scope,
 };
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -273,6 +274,7 @@ pub(crate) fn create_import_code_action<'db>(
                     start_of_change: pos,
                     end_of_change: pos,
                     replacement: insertion.addition,
+                    additional_changes: vec![],
                 };
             }
         }
@@ -312,6 +314,7 @@ pub(crate) fn create_import_code_action<'db>(
         start_of_change: pos,
         end_of_change: pos,
         replacement,
+        additional_changes: vec![],
     }
 }
 
@@ -362,8 +365,8 @@ fn position_for_import<'db>(
     }
 }
 
-#[derive(PartialOrd, PartialEq)]
-enum ImportKind {
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
+pub(crate) enum ImportKind {
     StdLib,
     ThirdParty,
     Project,
@@ -377,26 +380,44 @@ fn file_to_kind(db: &Database, file: &PythonFile) -> ImportKind {
     }
 }
 
+fn import_result_to_kind(db: &Database, imp_result: Option<ImportResult>) -> Option<ImportKind> {
+    match imp_result? {
+        ImportResult::File(file_index) => Some(file_to_kind(db, db.loaded_python_file(file_index))),
+        ImportResult::PyTypedMissing => Some(ImportKind::ThirdParty),
+        ImportResult::Namespace(_) => None,
+    }
+}
+
+pub(crate) fn import_from_kind(
+    db: &Database,
+    from_file: &PythonFile,
+    import_from: ImportFrom,
+) -> Option<ImportKind> {
+    import_result_to_kind(
+        db,
+        from_file.import_from_first_part_without_loading_file(db, import_from),
+    )
+}
+
+pub(crate) fn dotted_as_name_kind(
+    db: &Database,
+    from_file: &PythonFile,
+    dotted: DottedAsName,
+) -> Option<ImportKind> {
+    import_result_to_kind(db, from_file.cache_dotted_as_name_import(db, dotted))
+}
+
 impl FileImport {
     fn kind_for_auto_imports(&self, db: &Database, from_file: &PythonFile) -> Option<ImportKind> {
-        let check = |imp_result: Option<ImportResult>| match imp_result? {
-            ImportResult::File(file_index) => {
-                Some(file_to_kind(db, db.loaded_python_file(file_index)))
-            }
-            ImportResult::PyTypedMissing => Some(ImportKind::ThirdParty),
-            ImportResult::Namespace(_) => None,
-        };
         let node_ref = NodeRef::new(from_file, self.node_index);
         if let Some(import_from) = node_ref.maybe_import_from() {
-            check(from_file.import_from_first_part_without_loading_file(db, import_from))
+            import_from_kind(db, from_file, import_from)
         } else {
             // We just use the first file that can be loaded, because this is a heuristic anyway.
-            for dotted in node_ref.expect_import_name().iter_dotted_as_names() {
-                if let Some(result) = check(from_file.cache_dotted_as_name_import(db, dotted)) {
-                    return Some(result);
-                }
-            }
-            None
+            node_ref
+                .expect_import_name()
+                .iter_dotted_as_names()
+                .find_map(|dotted| dotted_as_name_kind(db, from_file, dotted))
         }
     }
 }
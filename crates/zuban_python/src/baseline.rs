@@ -0,0 +1,181 @@
+//! A baseline records the existing diagnostics of a project to make it possible to adopt Zuban in
+//! legacy codebases. Diagnostics in the baseline are not reported, only new ones are.
+//!
+//! Diagnostics are identified by their file, error code and normalized message, but not by their
+//! position, so the baseline stays valid if code is moved around.
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utils::{FastHashMap, FastHashSet};
+use vfs::{AbsPath, NormalizedPath, PathWithScheme, VfsHandler};
+
+use crate::diagnostics::Diagnostic;
+
+const VERSION: usize = 1;
+
+lazy_static::lazy_static! {
+    static ref LINE_NUMBERS: Regex = Regex::new(r"\bline \d+").unwrap();
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    version: usize,
+    entries: Vec<BaselineEntry>,
+}
+
+/// Identifies the diagnostics of an entry: the path, code and message.
+type EntryKey = (String, String, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// Relative to the directory of the baseline file with `/` as a separator.
+    pub path: String,
+    pub code: String,
+    pub message: String,
+    /// How often the diagnostic appears in the file.
+    pub count: usize,
+}
+
+impl Baseline {
+    pub(crate) fn load(
+        handler: &dyn VfsHandler,
+        path: &std::sync::Arc<NormalizedPath>,
+    ) -> anyhow::Result<Self> {
+        let Some(code) =
+            handler.read_and_watch_file(&PathWithScheme::with_file_scheme(path.clone()))
+        else {
+            anyhow::bail!("Baseline file {path} does not exist");
+        };
+        let baseline: Self =
+            serde_json::from_str(&code).with_context(|| format!("Invalid baseline file {path}"))?;
+        if baseline.version != VERSION {
+            anyhow::bail!(
+                "Baseline file {path} has version {}, but only version {VERSION} is supported",
+                baseline.version
+            );
+        }
+        Ok(baseline)
+    }
+
+    pub(crate) fn from_diagnostics<'x>(
+        baseline_dir: &Path,
+        diagnostics: impl IntoIterator<Item = &'x Diagnostic<'x>>,
+    ) -> Self {
+        let mut counts: FastHashMap<EntryKey, usize> = FastHashMap::default();
+        for diagnostic in diagnostics {
+            *counts
+                .entry(BaselineEntry::new(baseline_dir, diagnostic).into_key())
+                .or_default() += 1;
+        }
+        Self {
+            version: VERSION,
+            entries: entries_from_counts(counts),
+        }
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.iter().map(|e| e.count).sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap() + "\n"
+    }
+
+    /// Removes all diagnostics that are part of the baseline and returns the entries that did not
+    /// match a diagnostic anymore. If `only_path` is given, only the entries of that file are
+    /// considered.
+    pub(crate) fn filter(
+        &self,
+        baseline_dir: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+        only_path: Option<&str>,
+    ) -> Vec<BaselineEntry> {
+        let mut remaining: FastHashMap<EntryKey, usize> = FastHashMap::default();
+        for entry in &self.entries {
+            if only_path.is_none_or(|path| entry.path == path) {
+                *remaining.entry(entry.clone().into_key()).or_default() += entry.count;
+            }
+        }
+        if remaining.is_empty() {
+            return vec![];
+        }
+        diagnostics.retain(|diagnostic| {
+            let key = BaselineEntry::new(baseline_dir, diagnostic).into_key();
+            match remaining.get_mut(&key) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            }
+        });
+        entries_from_counts(remaining)
+    }
+
+    /// The entries of files that were not checked, e.g. because they were deleted, renamed or
+    /// excluded. They can never match a diagnostic and are therefore stale.
+    pub(crate) fn entries_of_unchecked_files<'a>(
+        &'a self,
+        checked_paths: &'a FastHashSet<String>,
+    ) -> impl Iterator<Item = BaselineEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(|e| !checked_paths.contains(&e.path))
+            .cloned()
+    }
+}
+
+impl BaselineEntry {
+    fn new(baseline_dir: &Path, diagnostic: &Diagnostic) -> Self {
+        let message = diagnostic.message();
+        let first_line = message.lines().next().unwrap_or_default();
+        Self {
+            path: relative_path(
+                diagnostic.db.vfs.handler.as_ref(),
+                baseline_dir,
+                diagnostic.original_file_path(),
+            ),
+            code: diagnostic.mypy_error_code().to_string(),
+            message: LINE_NUMBERS.replace_all(first_line, "line N").into_owned(),
+            count: 1,
+        }
+    }
+
+    fn into_key(self) -> EntryKey {
+        (self.path, self.code, self.message)
+    }
+}
+
+/// The entries with a count above zero, sorted like in the baseline file.
+fn entries_from_counts(counts: FastHashMap<EntryKey, usize>) -> Vec<BaselineEntry> {
+    let mut entries: Vec<_> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|((path, code, message), count)| BaselineEntry {
+            path,
+            code,
+            message,
+            count,
+        })
+        .collect();
+    entries.sort_by(|a, b| (&a.path, &a.code, &a.message).cmp(&(&b.path, &b.code, &b.message)));
+    entries
+}
+
+/// A path in the format of [`BaselineEntry::path`].
+pub(crate) fn relative_path(
+    handler: &dyn VfsHandler,
+    baseline_dir: &Path,
+    path: &AbsPath,
+) -> String {
+    let relative = handler
+        .path_relative_to(path, baseline_dir)
+        .unwrap_or_else(|| path.to_string());
+    match handler.separator() {
+        '/' => relative,
+        separator => relative.replace(separator, "/"),
+    }
+}
@@ -0,0 +1,208 @@
+//! Most edits in the language server change a single function body. If the return type of the
+//! function is annotated and the body does not change anything outside of the function (e.g. an
+//! attribute with `self.x = 1`), the edit cannot change the diagnostics of other files or of the
+//! other functions of the file. Only the module level and the edited function are then checked
+//! again, while the diagnostics of the other functions and of the dependent files are kept.
+//!
+//! The changed range is found by comparing the old and the new code. The new code needs to have
+//! the same functions at the same (shifted) positions, which rules out edits that change the
+//! structure of the rest of the file, e.g. an unclosed bracket.
+
+use parsa_python_cst::{CodeIndex, FunctionDef, TrivialBodyState};
+use vfs::FileIndex;
+
+use crate::{
+    cache::IssueSnapshot,
+    database::Database,
+    file::{File as _, PythonFile},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FunctionRange {
+    // Includes the decorators
+    start: CodeIndex,
+    body_start: CodeIndex,
+    end: CodeIndex,
+}
+
+impl FunctionRange {
+    fn new(func: FunctionDef) -> Self {
+        Self {
+            start: func
+                .maybe_decorated()
+                .map_or(func.start(), |decorated| decorated.start()),
+            body_start: func.body().start(),
+            end: func.end(),
+        }
+    }
+}
+
+pub(crate) struct BodyEdit {
+    change_start: CodeIndex,
+    delta: i64,
+    // The functions of the old code that are not nested in other functions
+    functions: Vec<FunctionRange>,
+    edited: usize,
+    // The issues and unreachable ranges of the other functions, already at their new positions
+    edited_file: Option<(IssueSnapshot, Vec<(CodeIndex, CodeIndex)>)>,
+    dependents: Vec<(FileIndex, IssueSnapshot)>,
+}
+
+impl BodyEdit {
+    /// Returns `None` if the edit is not limited to a body that is invisible outside of its
+    /// function.
+    pub(crate) fn new(db: &Database, file: &PythonFile, new_code: &str) -> Option<Self> {
+        let old_code = file.tree.code();
+        if old_code == new_code
+            || file.super_file.is_some()
+            // Otherwise function bodies might complete partial types of the module, e.g. `[]`.
+            || !file.flags(db).local_partial_types
+            || !file.issues.is_complete()
+        {
+            return None;
+        }
+        let (change_start, change_end) = changed_range(old_code, new_code);
+        let functions: Vec<_> = file.tree.non_nested_function_defs().collect();
+        let edited = functions.iter().position(|func| {
+            let body = func.body();
+            body.start() < change_start && change_end <= body.end()
+        })?;
+        if !has_invisible_body(functions[edited]) {
+            return None;
+        }
+        let delta = new_code.len() as i64 - old_code.len() as i64;
+        let functions: Vec<_> = functions.into_iter().map(FunctionRange::new).collect();
+        let is_reused = |position: CodeIndex| {
+            functions
+                .iter()
+                .enumerate()
+                .any(|(i, func)| i != edited && func.start <= position && position < func.end)
+        };
+        let edited_file = IssueSnapshot::new(db, file, is_reused).map(|mut issues| {
+            issues.shift(change_start, delta);
+            let unreachable_ranges = file
+                .unmerged_unreachable_ranges()
+                .into_iter()
+                .filter(|&(start, _)| is_reused(start))
+                .map(|(start, end)| {
+                    (
+                        shift(start, change_start, delta),
+                        shift(end, change_start, delta),
+                    )
+                })
+                .collect();
+            (issues, unreachable_ranges)
+        });
+        let dependents = db
+            .vfs
+            .dependent_files(file.file_index)?
+            .into_iter()
+            .filter_map(|file_index| {
+                let dependent = db.vfs.file(file_index)?;
+                if !dependent.issues.is_complete() {
+                    return None;
+                }
+                Some((file_index, IssueSnapshot::new(db, dependent, |_| true)?))
+            })
+            .collect();
+        Some(Self {
+            change_start,
+            delta,
+            functions,
+            edited,
+            edited_file,
+            dependents,
+        })
+    }
+
+    /// Keeps the diagnostics after the file was replaced with the new code. Does nothing if the
+    /// new code has different functions.
+    pub(crate) fn apply(self, db: &mut Database, file_index: FileIndex) {
+        let Some(file) = db.vfs.file(file_index) else {
+            return;
+        };
+        let new_functions: Vec<_> = file.tree.non_nested_function_defs().collect();
+        if new_functions.len() != self.functions.len()
+            || !has_invisible_body(new_functions[self.edited])
+        {
+            return;
+        }
+        let to_new = |position| shift(position, self.change_start, self.delta);
+        let shifted_functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|func| FunctionRange {
+                start: to_new(func.start),
+                body_start: to_new(func.body_start),
+                end: to_new(func.end),
+            })
+            .collect();
+        if new_functions
+            .into_iter()
+            .map(FunctionRange::new)
+            .ne(shifted_functions.iter().copied())
+        {
+            return;
+        }
+        tracing::info!(
+            "Only a function body of {} changed, keeping the other diagnostics",
+            file.file_path(db)
+        );
+        for (dependent_index, snapshot) in self.dependents {
+            if let Some(dependent) = db.vfs.file(dependent_index)
+                && !dependent.issues.is_complete()
+            {
+                let (issues, suppressed) = snapshot.into_issues();
+                dependent.issues.restore_from_cache(issues, suppressed);
+            }
+        }
+        if let Some((snapshot, unreachable_ranges)) = self.edited_file {
+            let reused_ranges = shifted_functions
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != self.edited)
+                .map(|(_, func)| (func.start, func.end))
+                .collect();
+            let file = db.vfs.file_mut(file_index).unwrap();
+            let (issues, suppressed) = snapshot.into_issues();
+            file.issues
+                .reuse_issues_in_ranges(reused_ranges, issues, suppressed);
+            for (start, end) in unreachable_ranges {
+                file.add_unreachable_range(start, end)
+            }
+        }
+    }
+}
+
+/// Changes of such a body are invisible outside of the function: The return type is not
+/// inferred and the function does not become abstract like with an empty body (e.g. `...`).
+fn has_invisible_body(func: FunctionDef) -> bool {
+    func.return_annotation().is_some()
+        && matches!(func.trivial_body_state(), TrivialBodyState::Known(false))
+        && !func.body_might_change_outer_scopes()
+}
+
+/// Returns the start and the end of the changed range in the old code.
+fn changed_range(old: &str, new: &str) -> (CodeIndex, CodeIndex) {
+    let prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(old.len().min(new.len()) - prefix)
+        .take_while(|(x, y)| x == y)
+        .count();
+    (prefix as CodeIndex, (old.len() - suffix) as CodeIndex)
+}
+
+fn shift(position: CodeIndex, change_start: CodeIndex, delta: i64) -> CodeIndex {
+    if position >= change_start {
+        (position as i64 + delta) as CodeIndex
+    } else {
+        position
+    }
+}
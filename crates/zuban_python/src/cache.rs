@@ -0,0 +1,518 @@
+//! The incremental cache of `zuban check --incremental`, which stores the diagnostics of every
+//! checked module in a JSON file in the cache directory (`.zuban_cache` by default).
+//!
+//! An entry is only reused if its key matches. The key contains the Zuban version, the options
+//! and the content of the module and of all the modules it imports transitively. Imports are
+//! hashed as the paths they resolve to, so a module that shadows another one or a moved module
+//! invalidates the modules that import it. A change to a module therefore invalidates the module
+//! itself and all modules that depend on it. Modules outside of the project (site-packages and the
+//! standard library) are not parsed, their size and modification time is hashed instead. Typeshed
+//! is part of the Zuban version, unless a custom typeshed is used, which is hashed as a whole.
+//!
+//! Diagnostics that depend on files that might be added later (e.g. a missing import) are never
+//! cached, since a new file does not change the content of any known module. Neither are
+//! diagnostics with related information (e.g. the original definition of a redefinition), which
+//! refers to the analyzed code.
+
+use std::{
+    collections::{HashMap, hash_map::Entry as HashMapEntry},
+    hash::{Hash, Hasher as _},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use config::Settings;
+use fnv::FnvHasher;
+use parsa_python_cst::CodeIndex;
+use serde::{Deserialize, Serialize};
+use vfs::{FileIndex, WorkspaceKind};
+
+use crate::{
+    database::Database,
+    diagnostics::{Diagnostic, DiagnosticTag, Issue, IssueKind, error_code_index},
+    file::PythonFile,
+    imports::ImportResult,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Error codes of diagnostics that might go away without any known module changing.
+const UNCACHEABLE_ERROR_CODES: [&str; 3] = ["import", "import-not-found", "import-untyped"];
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    key: u64,
+    issues: Vec<CachedIssue>,
+    suppressed: Vec<CachedIssue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedIssue {
+    start: u32,
+    end: u32,
+    message: String,
+    notes: Vec<String>,
+    code: Option<String>,
+    is_note: bool,
+    tag: Option<DiagnosticTag>,
+}
+
+/// FNV is used, because the hashes of the standard library may change between Rust versions.
+fn stable_hash(value: impl Hash) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub(crate) struct IncrementalCache {
+    dir: PathBuf,
+    options_key: u64,
+}
+
+/// A module in the import graph of the checked files.
+#[derive(Default)]
+struct Node {
+    /// The hash of the module itself, without the modules it imports.
+    hash: u64,
+    imports: Vec<usize>,
+}
+
+#[derive(Default)]
+struct ImportGraph {
+    nodes: Vec<Node>,
+    positions: HashMap<FileIndex, usize>,
+    to_visit: Vec<FileIndex>,
+}
+
+impl ImportGraph {
+    /// The position of the node of a file, which is added if it is not part of the graph yet.
+    fn position(&mut self, file_index: FileIndex) -> usize {
+        match self.positions.entry(file_index) {
+            HashMapEntry::Occupied(entry) => *entry.get(),
+            HashMapEntry::Vacant(entry) => {
+                self.nodes.push(Node::default());
+                self.to_visit.push(file_index);
+                *entry.insert(self.nodes.len() - 1)
+            }
+        }
+    }
+
+    fn add_module(&mut self, db: &Database, file_index: FileIndex) {
+        let position = self.position(file_index);
+        let path = db.file_path(file_index);
+        let mut hasher = FnvHasher::default();
+        path.hash(&mut hasher);
+        let mut imports = vec![];
+        match db.vfs.file_entry(file_index).parent.workspace().kind {
+            WorkspaceKind::TypeChecking | WorkspaceKind::Fallback => {
+                match db.ensure_file_for_file_index(file_index) {
+                    Ok(file) => {
+                        file.tree.code().hash(&mut hasher);
+                        for imp in &file.all_imports {
+                            let mut results = 0;
+                            file.find_potential_import_for_import_node_index(
+                                db,
+                                imp.node_index,
+                                |result| {
+                                    results += 1;
+                                    match result {
+                                        ImportResult::File(imported) => {
+                                            db.file_path(imported).hash(&mut hasher);
+                                            imports.push(self.position(imported));
+                                        }
+                                        ImportResult::Namespace(namespace) => {
+                                            for dir in namespace.directories.iter() {
+                                                dir.absolute_path(&*db.vfs.handler)
+                                                    .hash(&mut hasher);
+                                            }
+                                        }
+                                        ImportResult::PyTypedMissing => {
+                                            "py.typed missing".hash(&mut hasher)
+                                        }
+                                    }
+                                },
+                            );
+                            results.hash(&mut hasher);
+                        }
+                    }
+                    Err(err) => err.hash(&mut hasher),
+                }
+            }
+            // Typeshed is part of the options key.
+            WorkspaceKind::Typeshed => (),
+            WorkspaceKind::SitePackages | WorkspaceKind::PythonStdLib => {
+                let metadata = db.vfs.handler.read_metadata(path);
+                metadata.map(|m| (m.len, m.modified)).hash(&mut hasher);
+            }
+        }
+        imports.sort_unstable();
+        imports.dedup();
+        self.nodes[position] = Node {
+            hash: hasher.finish(),
+            imports,
+        };
+    }
+}
+
+impl IncrementalCache {
+    /// Returns `None` if the cache is disabled or cannot be used for this project.
+    pub(crate) fn new(db: &Database, files: &[&PythonFile]) -> Option<Self> {
+        let settings = &db.project.settings;
+        let cache_dir = settings.cache_dir.as_ref()?;
+        if !settings.incremental
+            // Projects that watch files keep the results in memory.
+            || db.vfs.handler.notify_receiver().is_some()
+            // Plugins might depend on anything, e.g. the settings of a Django project.
+            || !settings.plugins.is_empty()
+            || settings.django_settings_module.is_some()
+        {
+            return None;
+        }
+        let mut hasher = FnvHasher::default();
+        VERSION.hash(&mut hasher);
+        // These options only change the output, not the diagnostics.
+        Settings {
+            jobs: None,
+            junit_xml: None,
+            baseline: None,
+            cache_dir: None,
+            unknown_options: vec![],
+            ..settings.clone()
+        }
+        .hash(&mut hasher);
+        db.project.flags.hash(&mut hasher);
+        db.project.overrides.hash(&mut hasher);
+        db.project.sys_path.hash(&mut hasher);
+        if let Some(typeshed_path) = &settings.typeshed_path {
+            hash_directory(&mut hasher, (***typeshed_path).as_ref(), true);
+        }
+        // Installing or upgrading a package changes the versions in the names of the
+        // `.dist-info` directories. The modules of a package are only hashed themselves, not the
+        // modules they import.
+        for (kind, path) in &db.project.sys_path {
+            if *kind == WorkspaceKind::SitePackages {
+                hash_directory(&mut hasher, (***path).as_ref(), false);
+            }
+        }
+        // Fixtures are found in conftest.py files without importing them.
+        for file in files {
+            if file.name(db) == "conftest" {
+                db.file_path(file.file_index).hash(&mut hasher);
+                file.tree.code().hash(&mut hasher);
+            }
+        }
+        Some(Self {
+            dir: AsRef::<Path>::as_ref(&**cache_dir).join(format!("zuban-{VERSION}")),
+            options_key: hasher.finish(),
+        })
+    }
+
+    /// The keys of the files, which change if a module or one of its dependencies changes.
+    ///
+    /// The import graph is built once for all files. Modules that import each other form a
+    /// strongly connected component and share the same key.
+    pub(crate) fn keys(&self, db: &Database, files: &[&PythonFile]) -> Vec<u64> {
+        let mut graph = ImportGraph::default();
+        let roots: Vec<_> = files
+            .iter()
+            .map(|file| graph.position(file.file_index))
+            .collect();
+        while let Some(file_index) = graph.to_visit.pop() {
+            graph.add_module(db, file_index);
+        }
+        let transitive = transitive_hashes(&graph.nodes);
+        roots
+            .into_iter()
+            .map(|position| stable_hash((self.options_key, transitive[position])))
+            .collect()
+    }
+
+    fn entry_path(&self, db: &Database, file: &PythonFile) -> PathBuf {
+        let hash = stable_hash(db.file_path(file.file_index));
+        self.dir.join(format!("{hash:016x}.json"))
+    }
+
+    /// Adds the cached diagnostics to the file if the entry is still valid. Returns false if the
+    /// file needs to be checked.
+    pub(crate) fn restore(&self, db: &Database, file: &PythonFile, key: u64) -> bool {
+        let Ok(content) = std::fs::read_to_string(self.entry_path(db, file)) else {
+            return false;
+        };
+        let entry: Entry = match serde_json::from_str(&content) {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!("Ignored an invalid cache entry: {err}");
+                return false;
+            }
+        };
+        if entry.key != key || *entry.path != ***db.file_path(file.file_index) {
+            return false;
+        }
+        file.issues
+            .restore_from_cache(to_issues(entry.issues), to_issues(entry.suppressed));
+        true
+    }
+
+    /// Writes the diagnostics of a checked file. Failures are only logged, because the cache is
+    /// just an optimization.
+    pub(crate) fn store(&self, db: &Database, file: &PythonFile, key: u64) {
+        let mut issues = vec![];
+        let mut suppressed = vec![];
+        if !collect_issues(db, file, 0, true, &|_| true, &mut issues, &mut suppressed) {
+            return;
+        }
+        if issues.iter().any(|issue| {
+            issue
+                .code
+                .as_deref()
+                .is_some_and(|code| UNCACHEABLE_ERROR_CODES.contains(&code))
+        }) {
+            return;
+        }
+        let entry = Entry {
+            path: db.file_path(file.file_index).to_string(),
+            key,
+            issues,
+            suppressed,
+        };
+        let write = || -> std::io::Result<()> {
+            if !self.dir.exists() {
+                std::fs::create_dir_all(&self.dir)?;
+                if let Some(parent) = self.dir.parent() {
+                    // The cache should never be committed.
+                    std::fs::write(parent.join(".gitignore"), "*\n")?;
+                }
+            }
+            std::fs::write(self.entry_path(db, file), serde_json::to_string(&entry)?)
+        };
+        if let Err(err) = write() {
+            tracing::warn!("Failed to write to the cache in {:?}: {err}", self.dir);
+        }
+    }
+}
+
+/// Hashes the names, sizes and modification times of the entries of a directory. Failures are
+/// hashed as well, because they are likely to change the results of the check, too.
+fn hash_directory(hasher: &mut FnvHasher, dir: &Path, recursive: bool) {
+    let mut entries: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(err) => {
+            err.kind().hash(hasher);
+            return;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        entry.file_name().hash(hasher);
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if recursive {
+                hash_directory(hasher, &entry.path(), true);
+            }
+        } else {
+            metadata.len().hash(hasher);
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .hash(hasher);
+        }
+    }
+}
+
+/// The hashes of the nodes including all the nodes they reach, computed with Tarjan's algorithm
+/// for strongly connected components. Components are finished in reverse topological order,
+/// therefore the components a component imports are always finished before it.
+fn transitive_hashes(nodes: &[Node]) -> Vec<u64> {
+    let mut index: Vec<Option<usize>> = vec![None; nodes.len()];
+    let mut lowlink = vec![0; nodes.len()];
+    let mut on_stack = vec![false; nodes.len()];
+    let mut component_hash: Vec<Option<u64>> = vec![None; nodes.len()];
+    let mut stack = vec![];
+    let mut next_index = 0;
+    for root in 0..nodes.len() {
+        if index[root].is_some() {
+            continue;
+        }
+        // Every frame is a node and the position of the next import to visit.
+        let mut frames = vec![(root, 0)];
+        while let Some(&(v, nth_import)) = frames.last() {
+            if nth_import == 0 {
+                index[v] = Some(next_index);
+                lowlink[v] = next_index;
+                next_index += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if let Some(&w) = nodes[v].imports.get(nth_import) {
+                frames.last_mut().unwrap().1 += 1;
+                match index[w] {
+                    None => frames.push((w, 0)),
+                    Some(w_index) if on_stack[w] => lowlink[v] = lowlink[v].min(w_index),
+                    Some(_) => (),
+                }
+                continue;
+            }
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                lowlink[parent] = lowlink[parent].min(lowlink[v]);
+            }
+            if Some(lowlink[v]) != index[v] {
+                continue;
+            }
+            let mut members = vec![];
+            loop {
+                let w = stack.pop().unwrap();
+                on_stack[w] = false;
+                members.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            let mut own: Vec<u64> = members.iter().map(|&m| nodes[m].hash).collect();
+            own.sort_unstable();
+            // The members of this component don't have a hash yet.
+            let mut imported: Vec<u64> = members
+                .iter()
+                .flat_map(|&m| &nodes[m].imports)
+                .filter_map(|&w| component_hash[w])
+                .collect();
+            imported.sort_unstable();
+            imported.dedup();
+            let hash = stable_hash((own, imported));
+            for m in members {
+                component_hash[m] = Some(hash);
+            }
+        }
+    }
+    component_hash.into_iter().map(Option::unwrap).collect()
+}
+
+/// The diagnostics of a file without references to the analyzed code, which allows keeping them
+/// when the code changes, see `body_edit.rs`.
+pub(crate) struct IssueSnapshot {
+    issues: Vec<CachedIssue>,
+    suppressed: Vec<CachedIssue>,
+}
+
+impl IssueSnapshot {
+    /// Only contains the issues that start at a position for which `keep` returns true. Returns
+    /// `None` if the issues cannot be kept, see `collect_issues`.
+    pub(crate) fn new(
+        db: &Database,
+        file: &PythonFile,
+        keep: impl Fn(CodeIndex) -> bool,
+    ) -> Option<Self> {
+        let mut issues = vec![];
+        let mut suppressed = vec![];
+        collect_issues(db, file, 0, true, &keep, &mut issues, &mut suppressed)
+            .then_some(Self { issues, suppressed })
+    }
+
+    /// Moves the issues that start at or after `from` by `delta`.
+    pub(crate) fn shift(&mut self, from: CodeIndex, delta: i64) {
+        let shift = |position: &mut u32| *position = (*position as i64 + delta) as u32;
+        for issue in self.issues.iter_mut().chain(self.suppressed.iter_mut()) {
+            if issue.start >= from {
+                shift(&mut issue.start);
+                shift(&mut issue.end);
+            }
+        }
+    }
+
+    /// Returns the issues and the suppressed issues.
+    pub(crate) fn into_issues(self) -> (Vec<Issue>, Vec<Issue>) {
+        (to_issues(self.issues), to_issues(self.suppressed))
+    }
+}
+
+fn to_issues(cached: Vec<CachedIssue>) -> Vec<Issue> {
+    cached
+        .into_iter()
+        .map(|c| {
+            Issue::from_start_stop(
+                c.start,
+                c.end,
+                IssueKind::Cached {
+                    message: c.message.into(),
+                    notes: c.notes.into_iter().map(Into::into).collect(),
+                    code: c.code.as_deref().and_then(error_code_index),
+                    is_note: c.is_note,
+                    tag: c.tag,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Collects the issues of a file and of its sub files (e.g. type comments) with positions in the
+/// original file. Returns false if the issues cannot be cached, because a sub file is not part of
+/// the original file or an issue links to other definitions.
+fn collect_issues(
+    db: &Database,
+    file: &PythonFile,
+    offset: u32,
+    is_original: bool,
+    keep: &dyn Fn(CodeIndex) -> bool,
+    issues: &mut Vec<CachedIssue>,
+    suppressed: &mut Vec<CachedIssue>,
+) -> bool {
+    let to_cached = |issue: &Issue| {
+        let diagnostic = Diagnostic::new(db, file, issue);
+        let mut notes = vec![];
+        let message = diagnostic.message_with_notes(&mut notes);
+        CachedIssue {
+            start: offset + issue.start_position,
+            end: offset + issue.end_position,
+            message,
+            notes,
+            code: issue.kind.mypy_error_code().map(String::from),
+            is_note: diagnostic.is_note(),
+            tag: diagnostic.tag(),
+        }
+    };
+    // The issues of the name binder are found again when the file is parsed.
+    let file_issues: Vec<_> = unsafe { file.issues.iter() }
+        .filter(|issue| !is_original || !issue.is_from_name_binder())
+        .filter(|issue| keep(offset + issue.start_position))
+        .collect();
+    if file_issues.iter().any(|issue| {
+        !Diagnostic::new(db, file, issue)
+            .related_information()
+            .is_empty()
+    }) {
+        return false;
+    }
+    issues.extend(file_issues.into_iter().map(to_cached));
+    suppressed.extend(
+        unsafe { file.issues.iter_suppressed() }
+            .filter(|issue| !is_original || !issue.is_from_name_binder())
+            .filter(|issue| keep(offset + issue.start_position))
+            .map(to_cached),
+    );
+    for file_index in file.sub_files.in_same_file() {
+        let sub_file = db.loaded_python_file(file_index);
+        let Some(sub_offset) = sub_file.super_file.and_then(|super_file| super_file.offset) else {
+            return false;
+        };
+        // Reused issues already contain the issues of their sub files.
+        if file.issues.is_reused(sub_offset) {
+            continue;
+        }
+        if !collect_issues(
+            db,
+            sub_file,
+            offset + sub_offset,
+            false,
+            keep,
+            issues,
+            suppressed,
+        ) {
+            return false;
+        }
+    }
+    true
+}
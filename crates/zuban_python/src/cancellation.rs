@@ -0,0 +1,38 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Allows aborting long running IDE requests (e.g. completions) from a different thread. The
+/// checks are cooperative and only happen at points where no partially calculated state is left
+/// behind, e.g. before the diagnostics of a file are calculated.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            false => Ok(()),
+            true => Err(Cancelled),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The request was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
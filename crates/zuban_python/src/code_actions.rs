@@ -1,12 +1,14 @@
 use parsa_python_cst::{CodeIndex, NameParent};
 
 use crate::{
-    Document, InputPosition, PositionInfos,
+    Document, InputPosition, PositionInfos, TextChange,
     auto_imports::{ImportFinder, create_import_code_action},
     database::Specific,
     debug,
     file::File as _,
+    implement_members::implement_abstract_members,
     node_ref::NodeRef,
+    quick_fixes::quick_fixes,
 };
 
 impl<'project> Document<'project> {
@@ -39,6 +41,11 @@ impl<'project> Document<'project> {
             if name.start() > until.byte {
                 break;
             }
+            if let Some(class) = name.name_def().and_then(|n| n.maybe_name_of_class())
+                && let Some(action) = implement_abstract_members(db, file, class)
+            {
+                actions.push(action)
+            }
             let node_ref = NodeRef::new(file, name.index());
             if node_ref.point().maybe_calculated_and_specific() == Some(Specific::AnyDueToError)
                 && matches!(name.parent(), NameParent::Atom { .. })
@@ -58,10 +65,16 @@ impl<'project> Document<'project> {
         for diag in file.diagnostics(db) {
             let issue_start = diag.start_position().byte_position as CodeIndex;
             let issue_end = diag.end_position().byte_position as CodeIndex;
-            if !diag.is_note()
-                && intersects(&check_range, &(issue_start..issue_end))
-                && let Some(insertion) = file.tree.insertion_point_for_type_ignore(issue_start)
-            {
+            if diag.is_note() || !intersects(&check_range, &(issue_start..issue_end)) {
+                continue;
+            }
+            for fix in quick_fixes(db, file, diag.issue) {
+                // Implementing members can for example be offered on the class and on an issue.
+                if !actions.iter().any(|action| action.title == fix.title) {
+                    actions.push(fix)
+                }
+            }
+            if let Some(insertion) = file.tree.insertion_point_for_type_ignore(issue_start) {
                 let error_code = diag.mypy_error_code();
                 if error_code == "syntax" {
                     // Syntax errors cannot be ignored
@@ -75,6 +88,7 @@ impl<'project> Document<'project> {
                             start_of_change: pos,
                             end_of_change: pos,
                             replacement,
+                            additional_changes: vec![],
                         })
                     }
                 };
@@ -99,4 +113,7 @@ pub struct CodeAction<'db> {
     pub start_of_change: PositionInfos<'db>,
     pub end_of_change: PositionInfos<'db>,
     pub replacement: String,
+    /// Changes elsewhere in the file that are needed as well, e.g. new imports. They do not
+    /// overlap with the main change.
+    pub additional_changes: Vec<TextChange<'db>>,
 }
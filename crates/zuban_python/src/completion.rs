@@ -56,13 +56,12 @@ impl<'db> PositionalDocument<'db, CompletionInfo<'db>> {
     ) -> anyhow::Result<Self> {
         let cursor_position = file.line_column_to_byte(pos)?;
         let (scope, node, rest) = file.tree.completion_node(cursor_position.byte);
-        let result = file.ensure_calculated_diagnostics(db);
+        file.ensure_calculated_diagnostics_unless_cancelled(db)?;
         debug!(
             "Complete on position {}->{pos:?} on leaf {node:?} with rest {:?}",
             file.file_path(db),
             rest.as_code()
         );
-        debug_assert!(result.is_ok());
         Ok(Self {
             db,
             file,
@@ -115,6 +114,8 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> Option<T>, T> CompletionResol
             slf.should_start_with_lowercase = Some(slf.infos.node.rest.as_code().to_lowercase());
         }
         slf.fill_items();
+        // Completions of a cancelled request are incomplete and should not be used.
+        db.cancellation.check()?;
         slf.items.sort_by_key(|item| item.0);
         Ok(slf.items.into_iter().map(|(_, item)| item).collect())
     }
@@ -148,6 +149,9 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> Option<T>, T> CompletionResol
                     None => (),
                 }
                 for scope in reachable_scopes {
+                    if db.cancellation.is_cancelled() {
+                        return;
+                    }
                     match scope {
                         Scope::Module => self.add_global_module_completions(file),
                         Scope::Class(cls) => {
@@ -6,22 +6,27 @@ use std::{
     sync::{Arc, Mutex, OnceLock, RwLock, Weak},
 };
 
-use config::{FinalizedTypeCheckerFlags, OverrideConfig, Settings};
+use ::utils::FastHashSet;
+use config::{FinalizedTypeCheckerFlags, OverrideConfig, PythonVersion, Settings};
 use parsa_python_cst::{NodeIndex, Tree};
 use rayon::prelude::*;
 use vfs::{
     AbsPath, DirOrFile, Directory, DirectoryEntry, Entries, FileEntry, FileIndex,
-    InvalidationResult, LocalFS, NormalizedPath, PathWithScheme, Vfs, VfsFile as _, VfsHandler,
-    Workspace, WorkspaceKind,
+    InvalidationResult, LocalFS, NormalizedPath, PathWithScheme, Vfs, VfsHandler, Workspace,
+    WorkspaceKind,
 };
 
 use crate::{
-    ProjectOptions, debug,
+    ProjectOptions,
+    body_edit::BodyEdit,
+    cancellation::CancellationToken,
+    debug,
     file::{ClassNodeRef, File, PythonFile, SuperFile},
     lines::split_lines,
     node_ref::NodeRef,
+    plugins::Plugins,
     python_state::PythonState,
-    recoverable_error, sys_path,
+    recoverable_error, sys_path, timing,
     type_::{
         CallableContent, DataclassTransformObj, FunctionKind, FunctionOverload, GenericItem,
         GenericsList, ParamSpecUsage, RecursiveType, ReplaceTypeVarLikes, StringSlice, Type,
@@ -544,6 +549,7 @@ pub(crate) enum Specific {
     TypingTypeIs,
     TypingTypeForm,
     RevealTypeFunction,
+    RevealLocalsFunction,
     AssertTypeFunction,
     TypingNamedTuple,      // typing.NamedTuple
     CollectionsNamedTuple, // collections.namedtuple
@@ -1016,27 +1022,42 @@ pub enum RunCause {
     LanguageServer,
 }
 
+/// Reported for every workspace (e.g. a directory in the sys path) while loading a project,
+/// because scanning large directories can take a while. The last report has `done == total` and no
+/// current path.
+pub struct LoadingProgress<'a> {
+    pub done: usize,
+    pub total: usize,
+    pub current_path: Option<&'a str>,
+}
+
 pub(crate) struct Database {
     pub vfs: Vfs<PythonFile>,
     pub python_state: PythonState,
     pub project: PythonProject,
     pub run_cause: RunCause,
     pub pytest_folder: RwLock<Option<Weak<Directory>>>,
+    pub cancellation: CancellationToken,
+    // Increased after every request of a long-running server to find the least recently used
+    // files, see `Project::enforce_memory_limit`.
+    pub access_generation: u32,
 }
 
 impl Database {
     pub fn new(vfs_handler: Box<dyn VfsHandler>, options: ProjectOptions, cause: RunCause) -> Self {
-        Self::new_internal(vfs_handler, options, cause, None)
+        Self::new_internal(vfs_handler.into(), options, cause, None, &mut |_| ())
     }
 
     pub fn new_internal(
-        vfs_handler: Box<dyn VfsHandler>,
+        vfs_handler: Arc<dyn VfsHandler>,
         options: ProjectOptions,
         run_cause: RunCause,
         recovery: Option<vfs::VfsPanicRecovery<Tree>>,
+        on_progress: &mut dyn FnMut(LoadingProgress),
     ) -> Self {
         let project = PythonProject {
             sys_path: sys_path::create_sys_path(&*vfs_handler, &options.settings),
+            plugins: Plugins::new(&options.settings.plugins),
             settings: options.settings,
             flags: options.flags.finalize(),
             overrides: options.overrides,
@@ -1044,7 +1065,20 @@ impl Database {
 
         let mut vfs = Vfs::new(vfs_handler);
 
+        // The typeshed workspaces are the two additional ones
+        let total = project.settings.mypy_path.len() + 2 + project.sys_path.len();
+        let mut done = 0;
+        let mut report = |path: &str| {
+            on_progress(LoadingProgress {
+                done,
+                total,
+                current_path: Some(path),
+            });
+            done += 1;
+        };
+
         for p in project.settings.mypy_path.iter() {
+            report(p);
             if vfs.add_workspace(p.clone(), WorkspaceKind::TypeChecking)
                 && !project.settings.explicit_package_bases
             {
@@ -1072,6 +1106,7 @@ impl Database {
             format!("{typeshed_path}{sep}stdlib"),
             format!("{typeshed_path}{sep}stubs{sep}mypy-extensions"),
         ] {
+            report(&p);
             vfs.add_workspace(
                 vfs.handler
                     .unchecked_normalized_path(vfs.handler.unchecked_abs_path(&p)),
@@ -1080,8 +1115,14 @@ impl Database {
         }
 
         for (kind, p) in &project.sys_path {
+            report(p);
             add_workspace_and_check_for_pth_files(&mut vfs, p.clone(), recovery.is_some(), *kind);
         }
+        on_progress(LoadingProgress {
+            done: total,
+            total,
+            current_path: None,
+        });
         // This AbsPath is not really an absolute path, it's just a fallback so anything can be
         // part of it.
         vfs.add_workspace(
@@ -1091,11 +1132,9 @@ impl Database {
         );
 
         if let Some(recovery) = recovery {
-            vfs.load_panic_recovery(
-                project.flags.case_sensitive,
-                recovery,
-                |index, file_entry, tree| PythonFile::new(&project, index, file_entry, tree),
-            );
+            vfs.load_panic_recovery(recovery, |index, file_entry, tree| {
+                PythonFile::new(&project, index, file_entry, tree)
+            });
         }
 
         let mut this = Self {
@@ -1104,6 +1143,8 @@ impl Database {
             project,
             run_cause,
             pytest_folder: Default::default(),
+            cancellation: Default::default(),
+            access_generation: 0,
         };
 
         this.generate_python_state();
@@ -1124,13 +1165,21 @@ impl Database {
         options: ProjectOptions,
         cause: RunCause,
         recovery: vfs::VfsPanicRecovery<Tree>,
+        on_progress: &mut dyn FnMut(LoadingProgress),
     ) -> Self {
-        Database::new_internal(vfs_handler, options, cause, Some(recovery))
+        Database::new_internal(
+            vfs_handler.into(),
+            options,
+            cause,
+            Some(recovery),
+            on_progress,
+        )
     }
 
     pub fn try_to_reuse_project_resources_for_tests(&mut self, options: ProjectOptions) -> Self {
         let project = PythonProject {
             sys_path: sys_path::create_sys_path(&*self.vfs.handler, &options.settings),
+            plugins: Plugins::new(&options.settings.plugins),
             settings: options.settings,
             flags: options.flags.finalize(),
             overrides: options.overrides,
@@ -1151,6 +1200,8 @@ impl Database {
             run_cause: self.run_cause,
             project,
             pytest_folder: Default::default(),
+            cancellation: Default::default(),
+            access_generation: 0,
         };
 
         for (kind, p1) in &new_db.project.sys_path {
@@ -1242,10 +1293,7 @@ impl Database {
     }
 
     pub fn file_by_file_path(&self, path: &PathWithScheme) -> Option<FileIndex> {
-        let DirOrFile::File(file_entry) = self
-            .vfs
-            .search_path(self.project.flags.case_sensitive, path)?
-        else {
+        let DirOrFile::File(file_entry) = self.vfs.search_path(path)? else {
             return None;
         };
         self.load_file_index_from_workspace(&file_entry, false)
@@ -1299,34 +1347,46 @@ impl Database {
         code: Box<str>,
         parent: Option<FileIndex>,
     ) {
-        if let Some(parent) = parent
-            && let Some(in_mem_file) = self.vfs.in_memory_file(&path)
-            && let Some(file) = self.vfs.file_mut(in_mem_file)
+        if let Some(in_mem_file) = self.vfs.in_memory_file(&path)
+            && let Some(file) = self.vfs.file(in_mem_file)
+            && file.super_file.map(|sup| sup.file) != parent
         {
-            let super_file = file.super_file.map(|sup| sup.file);
-            if super_file != Some(parent) {
-                file.super_file = Some(SuperFile {
-                    file: parent,
-                    offset: None,
+            // A notebook cell was moved, the names it sees might therefore be different.
+            let invalidation = self
+                .vfs
+                .reload_file(in_mem_file, |file_state, file_index, code| {
+                    let mut file = PythonFile::from_file_entry_and_code(
+                        &self.project,
+                        file_index,
+                        file_state.file_entry(),
+                        code,
+                    );
+                    file.super_file = parent.map(|file| SuperFile { file, offset: None });
+                    file
                 });
-                file.invalidate_references_to(super_file)
-            }
+            self.handle_invalidation(invalidation);
         }
-        let (file_index, invalidation) = self.vfs.store_in_memory_file(
-            self.project.flags.case_sensitive,
-            path,
-            code,
-            |file_index, file_entry, new_code| {
-                let mut file = PythonFile::from_file_entry_and_code(
-                    &self.project,
-                    file_index,
-                    file_entry,
-                    new_code,
-                );
-                file.super_file = parent.map(|file| SuperFile { file, offset: None });
-                file
-            },
-        );
+        // An edit of a single function body only needs to check that body again.
+        let body_edit = if parent.is_none()
+            && let Some(in_mem_file) = self.vfs.in_memory_file(&path)
+            && let Some(file) = self.vfs.file(in_mem_file)
+        {
+            BodyEdit::new(self, file, &code)
+        } else {
+            None
+        };
+        let (file_index, invalidation) =
+            self.vfs
+                .store_in_memory_file(path, code, |file_index, file_entry, new_code| {
+                    let mut file = PythonFile::from_file_entry_and_code(
+                        &self.project,
+                        file_index,
+                        file_entry,
+                        new_code,
+                    );
+                    file.super_file = parent.map(|file| SuperFile { file, offset: None });
+                    file
+                });
         if let Some(parent) = parent {
             debug_assert!(
                 file_index.is_some(),
@@ -1341,7 +1401,14 @@ impl Database {
                     .add_separate_file(file_index)
             }
         }
+        let invalidated_db = invalidation == InvalidationResult::InvalidatedDb;
         self.handle_invalidation(invalidation);
+        if !invalidated_db
+            && let Some(body_edit) = body_edit
+            && let Some(file_index) = file_index
+        {
+            body_edit.apply(self, file_index)
+        }
     }
 
     fn handle_invalidation(&mut self, invalidation_result: InvalidationResult) {
@@ -1350,6 +1417,73 @@ impl Database {
         }
     }
 
+    /// Infers everything again for a different Python version, but keeps the parsed files. If
+    /// the version changes the sys path (e.g. the site-packages of an environment), the
+    /// workspaces are loaded again.
+    pub fn set_python_version(&mut self, version: PythonVersion) {
+        let mut settings = self.project.settings.clone();
+        settings.python_version = Some(version);
+        if sys_path::create_sys_path(&*self.vfs.handler, &settings) == self.project.sys_path {
+            self.project.settings = settings;
+            self.invalidate_db();
+            return;
+        }
+        let options = ProjectOptions {
+            settings,
+            flags: self.project.flags.clone().into_unfinalized(),
+            overrides: self.project.overrides.clone(),
+        };
+        let handler = self.vfs.handler.clone();
+        let recovery =
+            std::mem::replace(&mut self.vfs, Vfs::new(handler.clone())).into_panic_recovery();
+        let cancellation = self.cancellation.clone();
+        *self = Self::new_internal(
+            handler,
+            options,
+            self.run_cause,
+            Some(recovery),
+            &mut |_| (),
+        );
+        self.cancellation = cancellation;
+    }
+
+    /// Applies options that do not change how modules are found. The workspaces and the parsed
+    /// files are kept, but the flags of every file are calculated again and everything is
+    /// inferred again. Returns false if the options change the module resolution, which needs a
+    /// new database.
+    pub fn apply_options_without_reindex(&mut self, options: ProjectOptions) -> bool {
+        let settings = &options.settings;
+        let current = &self.project.settings;
+        let sys_path = sys_path::create_sys_path(&*self.vfs.handler, settings);
+        if sys_path != self.project.sys_path
+            || settings.mypy_path != current.mypy_path
+            || settings.typeshed_path != current.typeshed_path
+            || settings.explicit_package_bases != current.explicit_package_bases
+        {
+            return false;
+        }
+        self.project = PythonProject {
+            sys_path,
+            plugins: Plugins::new(&options.settings.plugins),
+            settings: options.settings,
+            flags: options.flags.finalize(),
+            overrides: options.overrides,
+        };
+        for file_state in self.vfs.files.iter_mut() {
+            let file_entry = file_state.file_entry().clone();
+            if let Some(file) = file_state.file_mut() {
+                if file.has_super_file() {
+                    file_state.unload();
+                } else {
+                    file.invalidate_for_changed_options(&self.project, &file_entry);
+                }
+            }
+        }
+        self.python_state = PythonState::reserve();
+        self.generate_python_state();
+        true
+    }
+
     fn invalidate_db(&mut self) {
         for file_state in self.vfs.files.iter_mut() {
             if let Some(file) = file_state.file_mut() {
@@ -1369,7 +1503,6 @@ impl Database {
         dir_path: &PathWithScheme,
     ) -> Result<(), String> {
         let invalidation = self.vfs.delete_in_memory_files_directory(
-            self.project.flags.case_sensitive,
             dir_path,
             |file_state, file_index, new_code| {
                 PythonFile::from_file_entry_and_code(
@@ -1393,35 +1526,108 @@ impl Database {
                 .sub_files
                 .take_separate_files()
             {
-                // Avoid an invalid pointer to a non-existing super file
-                if let Some(f) = self.vfs.file_mut(separate_file) {
+                // Avoid an invalid pointer to a non-existing super file. Files that were moved
+                // to a different super file in the meantime are not affected.
+                if let Some(f) = self.vfs.file_mut(separate_file)
+                    && f.super_file.is_some_and(|sup| sup.file == in_mem)
+                {
                     f.super_file = None;
                 }
             }
         }
-        let result = self.vfs.close_in_memory_file(
-            self.project.flags.case_sensitive,
-            path,
-            |file_state, file_index, new_code| {
+        let result = self
+            .vfs
+            .close_in_memory_file(path, |file_state, file_index, new_code| {
                 PythonFile::from_file_entry_and_code(
                     &self.project,
                     file_index,
                     file_state.file_entry(),
                     new_code,
                 )
-            },
-        )?;
+            })?;
         self.handle_invalidation(result);
         Ok(())
     }
 
     pub fn invalidate_path(&mut self, path: &AbsPath) {
-        let invalidation = self
-            .vfs
-            .invalidate_path(self.project.flags.case_sensitive, path);
+        let invalidation = self.vfs.invalidate_path(path);
         self.handle_invalidation(invalidation);
     }
 
+    pub fn rescan_path(&mut self, path: &AbsPath) {
+        let invalidation = self.vfs.rescan_path(path);
+        self.handle_invalidation(invalidation);
+    }
+
+    /// Evicts the least recently used files until the loaded files use less than the configured
+    /// memory limit. Returns the number of evicted files.
+    ///
+    /// Only files of the project and of site-packages are evicted, typeshed is needed by all
+    /// files. Files that are open in the editor and files that were used since the last call are
+    /// kept, as well as files whose invalidation would invalidate the whole database. The files
+    /// that depend on evicted files keep their trees and name binding results, but their
+    /// inference results are calculated again when needed.
+    pub fn evict_least_recently_used_files(&mut self) -> usize {
+        let generation = self.access_generation;
+        self.access_generation = generation.wrapping_add(1);
+        let Some(limit_mb) = self.project.settings.memory_limit_mb else {
+            return 0;
+        };
+        let limit = limit_mb.saturating_mul(1024 * 1024);
+        let in_memory: FastHashSet<_> = self.vfs.in_memory_files().map(|(_, i)| i).collect();
+        let mut total = 0;
+        let mut candidates = vec![];
+        for index in 0..self.vfs.files.len() {
+            let file_index = FileIndex(index as u32);
+            let Some(file) = self.vfs.file(file_index) else {
+                continue;
+            };
+            let memory = timing::file_memory(file);
+            total += memory;
+            let is_evictable = matches!(
+                self.vfs.file_entry(file_index).parent.workspace().kind,
+                WorkspaceKind::TypeChecking | WorkspaceKind::SitePackages | WorkspaceKind::Fallback
+            );
+            if file.super_file.is_none()
+                && is_evictable
+                && file.last_used() != generation
+                && !in_memory.contains(&file_index)
+            {
+                candidates.push((file.last_used(), memory, file_index))
+            }
+        }
+        if total <= limit {
+            return 0;
+        }
+        // Evicting a bit more than necessary avoids evicting files after every request.
+        let target = limit / 10 * 8;
+        // The generation wraps around, so the age is relative to the current generation.
+        candidates.sort_by_key(|(last_used, _, _)| {
+            std::cmp::Reverse(generation.wrapping_sub(*last_used))
+        });
+        let mut evicted = 0;
+        for (_, memory, file_index) in candidates {
+            if total <= target {
+                break;
+            }
+            if self.vfs.dependent_files(file_index).is_none() {
+                // Evicting these would throw away the inference results of all files.
+                continue;
+            }
+            let invalidation = self.vfs.evict_file(file_index);
+            debug_assert!(invalidation == InvalidationResult::InvalidatedFiles);
+            total -= memory;
+            evicted += 1;
+        }
+        if evicted > 0 {
+            tracing::info!(
+                "Evicted {evicted} files to stay below the memory limit of {limit_mb} MiB, {} MiB remain",
+                total / (1024 * 1024)
+            );
+        }
+        evicted
+    }
+
     fn preload_typeshed_stub(&self, workspace: &Workspace, file_name: &'static str) -> &PythonFile {
         self.preload_typeshed_stub_in_entries(&workspace.entries, file_name, || {
             workspace.root_path().to_string()
@@ -1452,12 +1658,14 @@ impl Database {
     }
 
     pub fn loaded_python_file(&self, index: FileIndex) -> &PythonFile {
-        self.vfs.file(index).unwrap_or_else(|| {
+        let file = self.vfs.file(index).unwrap_or_else(|| {
             panic!(
                 "Expected loaded file #{index}: {}",
                 self.vfs.file_path(index).path()
             )
-        })
+        });
+        file.mark_used(self.access_generation);
+        file
     }
 
     fn generate_python_state(&mut self) {
@@ -1600,11 +1808,24 @@ fn add_workspace_and_check_for_pth_files(
                 if let Some(content) = vfs.handler.read_and_watch_file(&pth_path) {
                     for line in split_lines(&content) {
                         let line = line.trim_end();
-                        if line.is_empty()
-                            || line.starts_with("#")
-                            || line.starts_with("import ")
-                            || line.starts_with("import\t")
-                        {
+                        if line.is_empty() || line.starts_with("#") {
+                            continue;
+                        }
+                        if line.starts_with("import ") || line.starts_with("import\t") {
+                            let roots = sys_path::editable_finder_roots(
+                                &*vfs.handler,
+                                &workspace_path,
+                                line,
+                            );
+                            // The sources of editable installs are no site-packages, so their
+                            // .pth files are not used.
+                            for (path, packages) in roots {
+                                tracing::info!(
+                                    "Add editable install of {packages:?} in {path} of .pth file: {}",
+                                    pth_path.as_uri()
+                                );
+                                vfs.add_editable_install(path, packages.into());
+                            }
                             continue;
                         }
                         let path = vfs
@@ -1630,6 +1851,7 @@ pub(crate) struct PythonProject {
     pub settings: Settings,
     pub flags: FinalizedTypeCheckerFlags,
     pub(crate) overrides: Vec<OverrideConfig>,
+    pub plugins: Plugins,
     // is_django: bool,  // TODO maybe add?
 }
 
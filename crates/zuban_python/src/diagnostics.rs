@@ -1,15 +1,23 @@
-use std::{collections::HashMap, io::Write, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
 
 use colored::{ColoredString, Colorize as _};
-use config::DiagnosticConfig;
+use config::{DiagnosticConfig, ErrorCodeSeverity, Settings};
 use parsa_python_cst::{CodeIndex, NodeIndex, Tree, TypeIgnoreComment};
 use utils::InsertOnlyVec;
+use vfs::AbsPath;
 
 use crate::{
     PythonVersion, TypeCheckerFlags,
     database::{Database, PointLink},
     file::{File, GenericCounts, OVERLAPPING_REVERSE_TO_NORMAL_METHODS, PythonFile},
     lines::PositionInfos,
+    name::{Name, TreeName},
     node_ref::NodeRef,
     type_::{TypeVarLike, Variance},
     utils::join_with_commas,
@@ -41,7 +49,8 @@ pub(crate) enum IssueKind {
     NameError { name: Box<str>, note: Option<Box<str>> },
     ReadingDeletedVariable,
     ArgumentIssue(Box<str>),
-    ArgumentTypeIssue(Box<str>),
+    // The classes of the got and the expected type, used to implement missing protocol members.
+    ArgumentTypeIssue { message: Box<str>, classes: Option<(PointLink, PointLink)> },
     TooFewArguments(Box<str>),
     TooManyArguments(Box<str>),
     IncompatibleDefaultArgument{ argument_name: Box<str>, got: Box<str>, expected: Box<str> },
@@ -81,13 +90,16 @@ pub(crate) enum IssueKind {
     CannotDetermineType { for_: Box<str> },
     Deprecated { identifier: Box<str>, reason: Arc<Box<str>>},
 
-    Redefinition { name: Box<str>, suffix: Box<str>, is_self_attribute: bool },
+    Redefinition { name: Box<str>, suffix: Box<str>, is_self_attribute: bool, original: PointLink },
     CannotRedefineAs { name: Box<str>, as_: &'static str },
     CannotRedefineAsFinal,
     IncompatibleConditionalFunctionSignature { original: Box<str>, redefinition: Box<str> },
     IncompatibleConditionalFunctionSignaturePretty { original: Box<str>, redefinition: Box<str> },
     NameUsedBeforeDefinition { name: Box<str> },
-    ModuleNotFound { module_name: Box<str> },
+    // `with_other_case` is the name of a module that only differs in case, which a
+    // case-insensitive file system would find, but Python does not import.
+    ModuleNotFound { module_name: Box<str>, with_other_case: Option<Box<str>> },
+    ImportIgnored { module_name: Box<str> },
     NoParentModule,
     TypeNotFound,
     UnexpectedTypeDeclaration,
@@ -298,10 +310,10 @@ pub(crate) enum IssueKind {
     CallToAbstractMethodViaSuper { method_name: Box<str>, class_name: Box<str> },
 
     IncompatibleAssignmentInSubclass { base_class: Box<str>, got: Box<str>, expected: Box<str> },
-    SignatureIncompatibleWithSupertype { base_class: Box<str>, name: Box<str>, notes: Box<[Box<str>]> },
+    SignatureIncompatibleWithSupertype { base_class: Box<str>, name: Box<str>, details: Box<IncompatibleSignatureDetails> },
     OverloadOrderMustMatchSupertype { name: Box<str>, base_class: Box<str> },
-    ReturnTypeIncompatibleWithSupertype { message: String, async_note: Option<Box<str>> },
-    ArgumentIncompatibleWithSupertype { message: Box<str>, eq_class: Option<Box<str>>, add_liskov_note: bool },
+    ReturnTypeIncompatibleWithSupertype { message: Box<str>, async_note: Option<Box<str>>, original: Option<PointLink> },
+    ArgumentIncompatibleWithSupertype { message: Box<str>, eq_class: Option<Box<str>>, add_liskov_note: bool, original: Option<PointLink> },
     MultipleInheritanceIncompatibility { name: Box<str>, class1: Box<str>, class2: Box<str> },
     IncompatiblePropertySetterOverride { notes: Vec<String> },
     MissingBaseForOverride { name: Box<str> },
@@ -339,7 +351,7 @@ pub(crate) enum IssueKind {
     InvalidDunderMatchArgs,
     DuplicateKeywordPattern { name: Box<str> },
     DuplicateImplicitKeywordPattern { name: Box<str> },
-    NonExhaustiveMatch { unmatched_type: Box<str> },
+    NonExhaustiveMatch { unmatched_type: Box<str>, missing_cases: Box<[Box<str>]> },
 
     IntersectionCannotExistDueToIncompatibleMethodSignatures { intersection: Box<str> },
     IntersectionCannotExistDueToFinalClass { intersection: Box<str>, final_class: Box<str> },
@@ -516,6 +528,17 @@ pub(crate) enum IssueKind {
     InvariantNote { actual: &'static str, maybe: &'static str },
     AnnotationInUntypedFunction,
     Note(Box<str>),
+
+    // A diagnostic of an unchanged file that was restored from the incremental cache.
+    // The code is an index into `DOCUMENTED_ERROR_CODES`, see `error_code_index`.
+    Cached { message: Box<str>, notes: Box<[Box<str>]>, code: Option<u8>, is_note: bool, tag: Option<DiagnosticTag> },
+}
+
+/// Boxed in [`IssueKind::SignatureIncompatibleWithSupertype`] to keep [`IssueKind`] small.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct IncompatibleSignatureDetails {
+    pub notes: Box<[Box<str>]>,
+    pub original: Option<PointLink>,
 }
 
 impl IssueKind {
@@ -523,6 +546,7 @@ impl IssueKind {
         use IssueKind::*;
         Some(match &self {
             Note(_) | InvariantNote { .. } => return None,
+            Cached { code, .. } => return code.map(|index| DOCUMENTED_ERROR_CODES[index as usize]),
             InvalidSyntax
             | InvalidSyntaxInTypeComment { .. }
             | InvalidSyntaxInTypeAnnotation
@@ -540,7 +564,7 @@ impl IssueKind {
             UnionAttributeError { .. }
             | UnionAttributeErrorOfUpperBound(..)
             | NotIterableMissingIterInUnion { .. } => "union-attr",
-            ArgumentTypeIssue(_) | SuperArgument1MustBeTypeObject { .. } => "arg-type",
+            ArgumentTypeIssue { .. } | SuperArgument1MustBeTypeObject { .. } => "arg-type",
             ArgumentIssue { .. } | TooManyArguments { .. } | TooFewArguments { .. } => "call-arg",
             InvalidType { .. } => "valid-type",
             IncompatibleReturn { .. }
@@ -570,7 +594,7 @@ impl IssueKind {
             | NotCallable { .. }
             | UnknownFunctionNotCallable => "operator",
             TypeArgumentIssue { .. } | MissingTypeParameters { .. } => "type-arg",
-            ModuleNotFound { module_name } => {
+            ModuleNotFound { module_name, .. } => {
                 if has_known_types_package(module_name).is_some() {
                     "import-untyped"
                 } else {
@@ -660,6 +684,12 @@ impl IssueKind {
     }
 
     pub(crate) fn is_disabled(&self, flags: &TypeCheckerFlags) -> bool {
+        if self
+            .mypy_error_code()
+            .is_some_and(|code| flags.error_code_severity(code) == Some(ErrorCodeSeverity::Off))
+        {
+            return true;
+        }
         if !flags.disabled_error_codes.is_empty() {
             let should_not_report = |code: Option<&str>| {
                 code.is_some_and(|code| {
@@ -726,11 +756,15 @@ impl Issue {
             from_name_binder: false,
         }
     }
+
+    pub(crate) fn is_from_name_binder(&self) -> bool {
+        self.from_name_binder
+    }
 }
 
 // These roughly correspond to LSP DiagnosticSeverity:
 // https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#diagnosticSeverity
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Severity {
     Error,
     Warning,
@@ -738,8 +772,25 @@ pub enum Severity {
     Hint,
 }
 
+// These correspond to LSP DiagnosticTag:
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#diagnosticTag
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum DiagnosticTag {
+    Unnecessary,
+    Deprecated,
+}
+
+/// A secondary location that helps explaining a diagnostic, e.g. the original definition of a
+/// redefined name.
+pub struct RelatedInformation<'db> {
+    pub name: Name<'db, 'db>,
+    pub message: String,
+}
+
 pub struct Diagnostic<'db> {
-    db: &'db Database,
+    pub(crate) db: &'db Database,
     file: &'db PythonFile,
     pub(crate) issue: &'db Issue,
 }
@@ -760,20 +811,89 @@ impl<'db> Diagnostic<'db> {
     }
 
     pub fn severity(&self) -> Severity {
-        match &self.is_note() {
-            false => Severity::Error,
-            true => Severity::Information,
+        let configured = self
+            .issue
+            .kind
+            .mypy_error_code()
+            .and_then(|code| self.file.flags(self.db).error_code_severity(code));
+        match configured {
+            Some(ErrorCodeSeverity::Error | ErrorCodeSeverity::Off) => Severity::Error,
+            Some(ErrorCodeSeverity::Warning) => Severity::Warning,
+            Some(ErrorCodeSeverity::Note) => Severity::Information,
+            Some(ErrorCodeSeverity::Hint) => Severity::Hint,
+            None if self.is_note() => Severity::Information,
+            None => Severity::Error,
+        }
+    }
+
+    pub fn tag(&self) -> Option<DiagnosticTag> {
+        match &self.issue.kind {
+            IssueKind::UnreachableStatement => Some(DiagnosticTag::Unnecessary),
+            IssueKind::Deprecated { .. } => Some(DiagnosticTag::Deprecated),
+            IssueKind::Cached { tag, .. } => *tag,
+            _ => None,
         }
     }
 
+    pub fn related_information(&self) -> Vec<RelatedInformation<'db>> {
+        let (link, message) = match &self.issue.kind {
+            IssueKind::Redefinition { name, original, .. } => {
+                (*original, format!(r#""{name}" first defined here"#))
+            }
+            IssueKind::SignatureIncompatibleWithSupertype {
+                base_class,
+                details,
+                ..
+            } => {
+                let Some(original) = details.original else {
+                    return vec![];
+                };
+                (
+                    original,
+                    format!(r#"Overridden definition in supertype "{base_class}""#),
+                )
+            }
+            IssueKind::ReturnTypeIncompatibleWithSupertype {
+                original: Some(original),
+                ..
+            }
+            | IssueKind::ArgumentIncompatibleWithSupertype {
+                original: Some(original),
+                ..
+            } => (*original, "Overridden definition in supertype".to_string()),
+            _ => return vec![],
+        };
+        let node_ref = NodeRef::from_link(self.db, link);
+        let Some(name) = node_ref.maybe_name() else {
+            return vec![];
+        };
+        vec![RelatedInformation {
+            name: Name::TreeName(TreeName::with_unknown_parent_scope(
+                self.db,
+                node_ref.file,
+                name,
+            )),
+            message,
+        }]
+    }
+
     pub(crate) fn is_note(&self) -> bool {
-        matches!(
-            &self.issue.kind,
+        match &self.issue.kind {
             IssueKind::Note(_)
-                | IssueKind::InvariantNote { .. }
-                | IssueKind::AnnotationInUntypedFunction
-                | IssueKind::InvalidDunderMatchArgs
-        )
+            | IssueKind::InvariantNote { .. }
+            | IssueKind::AnnotationInUntypedFunction
+            | IssueKind::InvalidDunderMatchArgs => true,
+            IssueKind::Deprecated { .. } => self.file.flags(self.db).report_deprecated_as_note,
+            IssueKind::Cached { is_note, .. } => *is_note,
+            _ => false,
+        }
+    }
+
+    /// The path of the file the diagnostic belongs to, which is not a sub file for e.g. type
+    /// comments.
+    pub fn original_file_path(&self) -> &'db AbsPath {
+        let original_file = self.file.original_file(self.db);
+        self.db.file_path(original_file.file_index)
     }
 
     fn code_under_issue(&self) -> &'db str {
@@ -784,6 +904,10 @@ impl<'db> Diagnostic<'db> {
         self.issue.kind.mypy_error_code().unwrap_or("note")
     }
 
+    pub fn documentation_url(&self) -> Option<String> {
+        error_code_documentation_url(self.issue.kind.mypy_error_code()?)
+    }
+
     pub fn is_mypy_semanal_error(&self) -> bool {
         // Mypy has semanal-*.test tests that only use Mypy's semantic analysis part instead of
         // full type checking, which leads to not all errors being relevant. Here we filter only
@@ -973,7 +1097,7 @@ impl<'db> Diagnostic<'db> {
                 "{identifier} is deprecated: {message}"
             ),
 
-            Redefinition{name, suffix, is_self_attribute } => match *is_self_attribute {
+            Redefinition{name, suffix, is_self_attribute, .. } => match *is_self_attribute {
                 false => format!(r#"Name "{name}" already defined {suffix}"#),
                 true => format!(r#"Attribute "{name}" already defined {suffix}"#),
             },
@@ -992,7 +1116,7 @@ impl<'db> Diagnostic<'db> {
             NameUsedBeforeDefinition { name } => format!(
                 r#"Name "{name}" is used before definition"#
             ),
-            ArgumentIssue(s) | ArgumentTypeIssue(s) => s.clone().into(),
+            ArgumentIssue(s) | ArgumentTypeIssue { message: s, .. } => s.clone().into(),
             InvalidType { message, additional_note } => {
                 if let Some(additional_note) = additional_note {
                     additional_notes.push(additional_note.to_string());
@@ -1072,7 +1196,12 @@ impl<'db> Diagnostic<'db> {
                     )
                 }
             }
-            ModuleNotFound{module_name} => {
+            ModuleNotFound{module_name, with_other_case} => {
+                if let Some(other) = with_other_case {
+                    additional_notes.push(format!(
+                        "Module names are case-sensitive, but the module is named \"{other}\""
+                    ));
+                }
                 if let Some(types_package) = has_known_types_package(module_name) {
                     additional_notes.push(format!("Hint: \"python3 -m pip install {types_package}\""));
                     if self.db.mypy_compatible() {
@@ -1086,6 +1215,12 @@ impl<'db> Diagnostic<'db> {
 
                 }
             }
+            ImportIgnored { module_name } => {
+                additional_notes.push(
+                    "(Using --follow-imports=error, module not passed on command line)".to_string()
+                );
+                format!("Import of \"{module_name}\" ignored")
+            }
             NoParentModule => "No parent module -- cannot perform relative import".to_string(),
             NotCallable{type_} => format!("{type_} not callable"),
             UnknownFunctionNotCallable => "Cannot call function of unknown type".to_string(),
@@ -1715,8 +1850,8 @@ impl<'db> Diagnostic<'db> {
                 "Incompatible types in assignment (expression has type \"{got}\", \
                  base class \"{base_class}\" defined the type as \"{expected}\")"
             ),
-            SignatureIncompatibleWithSupertype {base_class, name, notes} => {
-                for note in notes.iter() {
+            SignatureIncompatibleWithSupertype {base_class, name, details } => {
+                for note in details.notes.iter() {
                     additional_notes.push(note.to_string());
                 }
                 format!(r#"Signature of "{name}" incompatible with supertype "{base_class}""#)
@@ -1727,16 +1862,16 @@ impl<'db> Diagnostic<'db> {
                 ));
                 format!(r#"Signature of "{name}" incompatible with supertype "{base_class}""#)
             }
-            ReturnTypeIncompatibleWithSupertype { message, async_note } => {
+            ReturnTypeIncompatibleWithSupertype { message, async_note, .. } => {
                 if let Some(async_note) = async_note {
                     additional_notes.push(async_note.clone().into());
                     additional_notes.push(
                         "See https://mypy.readthedocs.io/en/stable/more_types.html#asynchronous-iterators".into()
                     )
                 }
-                message.clone()
+                message.to_string()
             }
-            ArgumentIncompatibleWithSupertype { message, eq_class, add_liskov_note } => {
+            ArgumentIncompatibleWithSupertype { message, eq_class, add_liskov_note, .. } => {
                 if *add_liskov_note {
                     additional_notes.push("This violates the Liskov substitution principle".into());
                     additional_notes.push("See https://mypy.readthedocs.io/en/stable/common_issues.html#incompatible-overrides".into());
@@ -1857,7 +1992,7 @@ impl<'db> Diagnostic<'db> {
             DuplicateImplicitKeywordPattern { name } => format!(
                 r#"Keyword "{name}" already matches a positional pattern"#
             ),
-            NonExhaustiveMatch { unmatched_type } => {
+            NonExhaustiveMatch { unmatched_type, .. } => {
                 additional_notes.push(
                     "If match statement is intended to be non-exhaustive, add `case _: pass`".into()
                 );
@@ -2128,6 +2263,10 @@ impl<'db> Diagnostic<'db> {
             Note(s) => {
                 s.clone().into()
             }
+            Cached { message, notes, .. } => {
+                additional_notes.extend(notes.iter().map(|note| note.to_string()));
+                message.to_string()
+            }
         }
     }
 
@@ -2163,6 +2302,14 @@ impl<'db> Diagnostic<'db> {
         .unwrap_or_else(|| abs.to_string());
         let mut additional_notes = vec![];
         let error = self.message_with_notes(&mut additional_notes);
+        if config.show_error_code_links
+            && config.show_error_codes
+            && let Some(code) = self.issue.kind.mypy_error_code()
+            && !HIDE_LINK_ERROR_CODES.contains(&code)
+            && let Some(url) = error_code_documentation_url(code)
+        {
+            additional_notes.push(format!("See {url} for more info"));
+        }
 
         let mut line_number_infos = String::with_capacity(32);
         let mut add_part = |n| line_number_infos.push_str(&format!(":{n}"));
@@ -2179,16 +2326,32 @@ impl<'db> Diagnostic<'db> {
         MessageFormattingInfos {
             error,
             additional_notes,
-            kind: match self.is_note() {
-                true => "note",
-                false => "error",
+            kind: match self.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Information | Severity::Hint => "note",
             },
             path,
             line_number_infos,
         }
     }
 
+    /// Inline configuration comments like `# zuban: show-error-codes` change how the diagnostics
+    /// of a file are formatted.
+    fn config_with_file_overrides<'c>(
+        &self,
+        config: &'c DiagnosticConfig,
+    ) -> Cow<'c, DiagnosticConfig> {
+        let overrides = &self.file.original_file(self.db).diagnostic_config_overrides;
+        if overrides.is_empty() {
+            Cow::Borrowed(config)
+        } else {
+            Cow::Owned(config.with_overrides(overrides))
+        }
+    }
+
     pub fn as_string(&self, config: &DiagnosticConfig, current_dir: Option<&str>) -> String {
+        let config = &self.config_with_file_overrides(config);
         let opts = self.message_formatting_options(config, current_dir);
         let fmt_line =
             |kind, error| format!("{}{}: {kind}: {error}", opts.path, opts.line_number_infos);
@@ -2212,29 +2375,68 @@ impl<'db> Diagnostic<'db> {
         result
     }
 
+    /// Like [`Self::as_string`], but highlights parts of the message if `add_colors` is true and
+    /// colors are enabled for the terminal.
     pub fn write_colored(
         &self,
         writer: &mut dyn Write,
         config: &DiagnosticConfig,
         current_dir: &str,
+        add_colors: bool,
     ) -> std::io::Result<()> {
+        let config = &self.config_with_file_overrides(config);
         let opts = self.message_formatting_options(config, Some(current_dir));
+        let paint = |colored: ColoredString| if add_colors { colored } else { colored.clear() };
         let fmt_line = |writer: &mut dyn Write, kind: &str, error| {
             write!(writer, "{}{}: ", opts.path, opts.line_number_infos)?;
             if kind == "error" {
-                write!(writer, "{}", "error: ".red().bold())?;
+                write!(writer, "{}", paint("error: ".red().bold()))?;
+            } else if kind == "warning" {
+                write!(writer, "{}", paint("warning: ".yellow().bold()))?;
             } else {
-                write!(writer, "{}", kind.blue())?;
-                write!(writer, "{}", ": ".blue())?;
+                write!(writer, "{}", paint(kind.blue()))?;
+                write!(writer, "{}", paint(": ".blue()))?;
             }
-            highlight_quote_groups(writer, error)
+            highlight_quote_groups(writer, error, add_colors)
         };
-        fmt_line(writer, opts.kind, &opts.error)?;
-        if config.show_error_codes
-            && let Some(mypy_error_code) = self.issue.kind.mypy_error_code()
-        {
-            let with_code = format!("  [{mypy_error_code}]").yellow();
-            write!(writer, "{with_code}")?;
+        let error_code = config
+            .show_error_codes
+            .then(|| self.issue.kind.mypy_error_code())
+            .flatten()
+            .map(|code| format!("[{code}]"));
+        if config.pretty && opts.kind == "error" {
+            // Like Mypy, only errors are wrapped to fit the terminal.
+            let prefix_len =
+                opts.path.chars().count() + opts.line_number_infos.len() + ": error: ".len();
+            let mut words = words_and_quoted_groups(&opts.error);
+            words.extend(error_code.as_deref());
+            let lines = soft_wrap(&words, config.terminal_width, prefix_len);
+            write!(writer, "{}{}: ", opts.path, opts.line_number_infos)?;
+            write!(writer, "{}", paint("error: ".red().bold()))?;
+            for (i, line) in lines.iter().enumerate() {
+                if i > 0 {
+                    writeln!(writer)?;
+                }
+                for (j, word) in line.iter().enumerate() {
+                    let is_error_code = i == lines.len() - 1
+                        && j == line.len() - 1
+                        && error_code.as_deref() == Some(*word);
+                    if is_error_code {
+                        let separator = if j == 0 { "" } else { "  " };
+                        write!(writer, "{separator}{}", paint(word.yellow()))?;
+                    } else {
+                        if j > 0 {
+                            write!(writer, " ")?;
+                        }
+                        highlight_quote_groups(writer, word, add_colors)?;
+                    }
+                }
+            }
+        } else {
+            fmt_line(writer, opts.kind, &opts.error)?;
+            if let Some(error_code) = &error_code {
+                write!(writer, "{}", paint(format!("  {error_code}").yellow()))?;
+            }
         }
         for note in &opts.additional_notes {
             writeln!(writer)?;
@@ -2242,7 +2444,7 @@ impl<'db> Diagnostic<'db> {
         }
         writeln!(writer)?;
         if config.pretty {
-            self.pretty_print_code_surrounding_issue(writer, true)?;
+            self.pretty_print_code_surrounding_issue(writer, add_colors)?;
             writeln!(writer)?;
         }
         Ok(())
@@ -2313,8 +2515,27 @@ impl<'db> Diagnostic<'db> {
                     write!(writer, "{}", &line[end_column..])?;
                 }
                 writeln!(writer)?;
+                if line_nr != start_line {
+                    continue;
+                }
 
-                //writeln!(writer, "{}", format!("{:^>chars$}", "|", chars = x).bright_red());
+                // A caret span like `^~~~` under the range of the error on its first line
+                write_colored(
+                    writer,
+                    format!("{:width$} | ", "", width = until_line_space_needed).blue(),
+                )?;
+                // Tabs are kept to align the span with the code.
+                let indent: String = line[..start_column]
+                    .chars()
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let span_len = line[start_column..end_column].chars().count().max(1);
+                write!(writer, "{indent}")?;
+                write_colored(
+                    writer,
+                    format!("^{}", "~".repeat(span_len - 1)).bright_red(),
+                )?;
+                writeln!(writer)?;
             } else {
                 writeln!(writer, "{line}")?;
             }
@@ -2323,12 +2544,58 @@ impl<'db> Diagnostic<'db> {
     }
 }
 
-fn highlight_quote_groups(out: &mut dyn Write, msg: &str) -> std::io::Result<()> {
+/// Splits a message at spaces, but keeps quoted groups like `"Union[int, str]"` together.
+fn words_and_quoted_groups(msg: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut in_quotes = false;
+    let mut word_start = 0;
+    for (i, c) in msg.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ' ' if !in_quotes => {
+                if word_start < i {
+                    words.push(&msg[word_start..i])
+                }
+                word_start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if word_start < msg.len() {
+        words.push(&msg[word_start..])
+    }
+    words
+}
+
+/// Distributes the words over lines of at most `max_len` characters, where the first line
+/// already has `first_offset` characters. Words that are longer than a line get their own line.
+fn soft_wrap<'a>(words: &[&'a str], max_len: usize, first_offset: usize) -> Vec<Vec<&'a str>> {
+    let mut lines: Vec<Vec<&str>> = vec![vec![]];
+    let mut line_len = first_offset;
+    for word in words {
+        let word_len = word.chars().count();
+        let current = lines.last_mut().unwrap();
+        if !current.is_empty() && line_len + 1 + word_len > max_len {
+            lines.push(vec![word]);
+            line_len = word_len;
+        } else {
+            if !current.is_empty() {
+                line_len += 1;
+            }
+            current.push(word);
+            line_len += word_len;
+        }
+    }
+    lines
+}
+
+fn highlight_quote_groups(out: &mut dyn Write, msg: &str, add_colors: bool) -> std::io::Result<()> {
     let mut in_quotes = false;
 
     for part in msg.split('"') {
         if in_quotes {
-            write!(out, "{}", format!("\"{}\"", part).bold())?;
+            let quoted = format!("\"{}\"", part).bold();
+            write!(out, "{}", if add_colors { quoted } else { quoted.clear() })?;
         } else {
             write!(out, "{}", part)?;
         }
@@ -2352,14 +2619,45 @@ impl std::fmt::Debug for Diagnostic<'_> {
 }
 
 #[derive(Default, Clone)]
-pub(crate) struct Diagnostics(InsertOnlyVec<Issue>);
+pub(crate) struct Diagnostics {
+    issues: InsertOnlyVec<Issue>,
+    // Issues that were suppressed by `# type: ignore` comments, these are only used for reports
+    // and only collected if `collect_suppressed` is set.
+    suppressed: InsertOnlyVec<Issue>,
+    collect_suppressed: bool,
+    // Set if the issues of the file were restored from the incremental cache or from a parallel
+    // check, see `parallel.rs`. Issues that are found afterwards (e.g. while inferring the file
+    // from other files) are already part of them.
+    restored_from_cache: OnceLock<()>,
+    // Set once all diagnostics of the file were calculated.
+    complete: OnceLock<()>,
+    // Ranges of unchanged functions, whose issues were kept from the previous version of the file
+    // after an edit of another function body, see `body_edit.rs`.
+    reused_ranges: Vec<(CodeIndex, CodeIndex)>,
+}
 
 impl Diagnostics {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            collect_suppressed: settings.collect_suppressed_diagnostics,
+            ..Default::default()
+        }
+    }
+
+    fn add_suppressed(&self, issue: &Issue) {
+        if self.collect_suppressed {
+            self.suppressed.push(Box::pin(issue.clone()));
+        }
+    }
+
     pub fn add_if_not_ignored(
         &self,
         issue: Issue,
         maybe_ignored: Option<TypeIgnoreComment>,
     ) -> Result<&Issue, Issue> {
+        if self.is_restored_from_cache() || self.is_reused(issue.start_position) {
+            return Err(issue);
+        }
         let mut add_not_covered_note = None;
         if let Some(specific) = maybe_ignored {
             if let TypeIgnoreComment::WithCodes { codes, .. } = specific {
@@ -2370,18 +2668,20 @@ impl Diagnostics {
                     let code = code.trim_matches(' ');
                     e == Some(code) || super_ == Some(code) || e.is_none()
                 }) {
+                    self.add_suppressed(&issue);
                     return Err(issue);
                 } else if e.is_some() {
                     add_not_covered_note = e;
                 }
             } else {
+                self.add_suppressed(&issue);
                 return Err(issue);
             }
         }
-        self.0.push(Box::pin(issue));
-        let last_issue = self.0.last().unwrap();
+        self.issues.push(Box::pin(issue));
+        let last_issue = self.issues.last().unwrap();
         if let Some(s) = add_not_covered_note {
-            self.0.push(Box::pin(Issue::from_start_stop(
+            self.issues.push(Box::pin(Issue::from_start_stop(
                 last_issue.start_position,
                 last_issue.end_position,
                 IssueKind::Note(
@@ -2393,14 +2693,184 @@ impl Diagnostics {
     }
 
     pub unsafe fn iter(&self) -> impl Iterator<Item = &Issue> {
-        unsafe { self.0.iter() }
+        unsafe { self.issues.iter() }
+    }
+
+    pub unsafe fn iter_suppressed(&self) -> impl Iterator<Item = &Issue> {
+        unsafe { self.suppressed.iter() }
+    }
+
+    /// Adds the issues of a file that were found in a previous run (see `cache.rs`) or in another
+    /// database (see `parallel.rs`).
+    pub fn restore_from_cache(&self, issues: Vec<Issue>, suppressed: Vec<Issue>) {
+        for issue in issues {
+            self.issues.push(Box::pin(issue));
+        }
+        for issue in suppressed {
+            self.suppressed.push(Box::pin(issue));
+        }
+        self.restored_from_cache.get_or_init(|| ());
+    }
+
+    pub fn is_restored_from_cache(&self) -> bool {
+        self.restored_from_cache.get().is_some()
+    }
+
+    /// Adds the issues of unchanged functions that were found before an edit. Issues that are
+    /// found in these ranges afterwards are ignored.
+    pub fn reuse_issues_in_ranges(
+        &mut self,
+        ranges: Vec<(CodeIndex, CodeIndex)>,
+        issues: Vec<Issue>,
+        suppressed: Vec<Issue>,
+    ) {
+        for issue in issues {
+            self.issues.push(Box::pin(issue));
+        }
+        for issue in suppressed {
+            self.suppressed.push(Box::pin(issue));
+        }
+        self.reused_ranges = ranges;
+    }
+
+    pub fn is_reused(&self, position: CodeIndex) -> bool {
+        self.reused_ranges
+            .iter()
+            .any(|&(start, end)| start <= position && position < end)
+    }
+
+    pub fn mark_complete(&self) {
+        self.complete.get_or_init(|| ());
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete.get().is_some() || self.is_restored_from_cache()
     }
 
     pub fn invalidate_non_name_binder_issues(&mut self) {
-        self.0.as_vec_mut().retain(|issue| issue.from_name_binder)
+        self.restored_from_cache = OnceLock::new();
+        self.complete = OnceLock::new();
+        self.reused_ranges = vec![];
+        self.issues
+            .as_vec_mut()
+            .retain(|issue| issue.from_name_binder);
+        self.suppressed
+            .as_vec_mut()
+            .retain(|issue| issue.from_name_binder)
     }
 }
 
+/// Cached issues store the error code as an index into [`DOCUMENTED_ERROR_CODES`] to keep
+/// [`IssueKind`] small.
+pub(crate) fn error_code_index(code: &str) -> Option<u8> {
+    DOCUMENTED_ERROR_CODES
+        .iter()
+        .position(|documented| *documented == code)
+        .map(|index| index as u8)
+}
+
+/// The error codes that Mypy documents. The names of error codes are stable, because they are used
+/// in `# type: ignore[code]` comments and configs.
+const DOCUMENTED_ERROR_CODES: [&str; 76] = [
+    // Enabled by default
+    "attr-defined",
+    "union-attr",
+    "name-defined",
+    "used-before-def",
+    "call-arg",
+    "arg-type",
+    "call-overload",
+    "valid-type",
+    "nonetype-type",
+    "var-annotated",
+    "override",
+    "return",
+    "empty-body",
+    "return-value",
+    "assignment",
+    "method-assign",
+    "type-var",
+    "operator",
+    "index",
+    "list-item",
+    "dict-item",
+    "typeddict-item",
+    "typeddict-unknown-key",
+    "typeddict-readonly-mutated",
+    "has-type",
+    "import",
+    "import-not-found",
+    "import-untyped",
+    "no-redef",
+    "func-returns-value",
+    "abstract",
+    "type-abstract",
+    "safe-super",
+    "valid-newtype",
+    "exit-return",
+    "name-match",
+    "literal-required",
+    "no-overload-impl",
+    "unused-coroutine",
+    "top-level-await",
+    "await-not-async",
+    "assert-type",
+    "truthy-function",
+    "str-format",
+    "str-bytes-safe",
+    "annotation-unchecked",
+    "prop-decorator",
+    "syntax",
+    "overload-overlap",
+    "overload-cannot-match",
+    "narrowed-type-not-subtype",
+    "metaclass",
+    "misc",
+    // Optional
+    "type-arg",
+    "no-untyped-def",
+    "redundant-cast",
+    "redundant-self",
+    "comparison-overlap",
+    "no-untyped-call",
+    "no-any-return",
+    "no-any-unimported",
+    "unreachable",
+    "deprecated",
+    "redundant-expr",
+    "possibly-undefined",
+    "truthy-bool",
+    "truthy-iterable",
+    "ignore-without-code",
+    "unused-awaitable",
+    "unused-ignore",
+    "explicit-override",
+    "mutable-override",
+    "unimported-reveal",
+    "explicit-any",
+    "exhaustive-match",
+    "untyped-decorator",
+];
+
+/// Like in Mypy, the terminal output does not link these error codes, because the messages are
+/// self-explanatory or the documentation is not useful (e.g. for `misc`).
+const HIDE_LINK_ERROR_CODES: [&str; 7] = [
+    "misc",
+    "assignment",
+    "arg-type",
+    "return-value",
+    "attr-defined",
+    "name-defined",
+    "override",
+];
+
+/// The documentation of an error code or `None` if the error code is not documented.
+pub fn error_code_documentation_url(code: &str) -> Option<String> {
+    DOCUMENTED_ERROR_CODES
+        .contains(&code)
+        .then(|| format!("https://mypy.readthedocs.io/en/stable/_refs.html#code-{code}"))
+}
+
 pub fn has_known_types_package(name: &str) -> Option<&str> {
     lazy_static::lazy_static! {
         // This list is simply copied from Mypy
@@ -2676,4 +3146,35 @@ mod tests {
         use super::*;
         assert_eq!(size_of::<IssueKind>(), 56);
     }
+
+    #[test]
+    fn test_soft_wrap() {
+        use super::*;
+        let words =
+            words_and_quoted_groups(r#"Argument 1 has type "Union[int, str]"; expected "int""#);
+        assert_eq!(
+            words,
+            [
+                "Argument",
+                "1",
+                "has",
+                "type",
+                r#""Union[int, str]";"#,
+                "expected",
+                r#""int""#
+            ]
+        );
+        assert_eq!(
+            soft_wrap(&words, 30, 10),
+            vec![
+                vec!["Argument", "1", "has", "type"],
+                vec![r#""Union[int, str]";"#, "expected"],
+                vec![r#""int""#],
+            ]
+        );
+        assert_eq!(
+            soft_wrap(&["a_very_long_word"], 10, 5),
+            vec![vec!["a_very_long_word"]]
+        );
+    }
 }
@@ -14,6 +14,80 @@ impl<'project> Document<'project> {
         position: InputPosition,
         only_docstrings: bool,
     ) -> anyhow::Result<Option<DocumentationResult<'_>>> {
+        let Some(parts) = self.documentation_parts(position, only_docstrings)? else {
+            return Ok(None);
+        };
+        let docs = parts.docs.join("\n\n");
+        let documentation = if only_docstrings {
+            docs
+        } else {
+            let mut out = code_block(&parts.declaration_kinds, &parts.signature);
+            if !docs.is_empty() {
+                out += "\n---\n";
+                out += &docs;
+            }
+            out
+        };
+        Ok(Some(DocumentationResult {
+            documentation,
+            on_symbol_range: parts.on_symbol_range,
+        }))
+    }
+
+    /// Like `documentation`, but keeps the different parts of a hover separate and converts
+    /// docstrings to markdown, so that clients can render them nicely.
+    pub fn hover(&self, position: InputPosition) -> anyhow::Result<Option<HoverContents<'_>>> {
+        let Some(parts) = self.documentation_parts(position, false)? else {
+            return Ok(None);
+        };
+        Ok(Some(HoverContents {
+            declaration_kinds: parts.declaration_kinds,
+            signature: parts.signature,
+            class_bases: parts.class_bases,
+            docstring: docstring_fields_to_markdown(&parts.docs.join("\n\n")),
+            on_symbol_range: parts.on_symbol_range,
+        }))
+    }
+
+    /// The type at the position formatted like `reveal_type` does, e.g. `builtins.list[builtins.int]`.
+    pub fn inferred_type(&self, position: InputPosition) -> anyhow::Result<Option<String>> {
+        let infos = self.positional_document(position)?;
+        let Some(inf) = infos.infer_position() else {
+            return Ok(None);
+        };
+        Ok(Some(infos.with_i_s(|i_s| {
+            inf.as_cow_type(i_s)
+                .format(&FormatData::new_reveal_type(i_s.db))
+                .into_string()
+        })))
+    }
+
+    /// The type at the position with all recursive type aliases and `NewType`s replaced by the
+    /// types they stand for, e.g. `list[UserId]` becomes `list[int]`.
+    pub fn expanded_type(&self, position: InputPosition) -> anyhow::Result<Option<String>> {
+        let infos = self.positional_document(position)?;
+        let Some(inf) = infos.infer_position() else {
+            return Ok(None);
+        };
+        Ok(Some(infos.with_i_s(|i_s| {
+            let format_data = &FormatData::new_expanded(i_s.db);
+            match inf.as_cow_type(i_s).as_ref() {
+                Type::Callable(c) => c.format_pretty(format_data).into_string(),
+                Type::FunctionOverload(o) => o
+                    .iter_functions()
+                    .map(|callable| callable.format_pretty(format_data))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                t => t.format(format_data).into_string(),
+            }
+        })))
+    }
+
+    fn documentation_parts(
+        &self,
+        position: InputPosition,
+        only_docstrings: bool,
+    ) -> anyhow::Result<Option<DocumentationParts<'_>>> {
         let mut resolver = GotoResolver::new(
             self.positional_document(position)?,
             GotoGoal::Indifferent,
@@ -28,12 +102,15 @@ impl<'project> Document<'project> {
         let db = &self.project.db;
         let mut overwritten_results = vec![];
 
-        let mut type_formatted = resolver.infos.with_i_s(|i_s| {
+        let (mut type_formatted, class_bases) = resolver.infos.with_i_s(|i_s| {
             if only_docstrings {
-                "".into()
-            } else {
-                pretty_type_formatting(i_s, &inf.as_cow_type(i_s)).into_string()
+                return (String::new(), vec![]);
             }
+            let t = inf.as_cow_type(i_s);
+            (
+                pretty_type_formatting(i_s, &t).into_string(),
+                class_bases(i_s, &t),
+            )
         });
 
         let resolver = GotoResolver::new(resolver.infos, GotoGoal::Indifferent, |n: Name| {
@@ -60,53 +137,169 @@ impl<'project> Document<'project> {
         }
         results.retain(|doc| !doc.is_empty());
 
-        let docs = results.join("\n\n");
-        let documentation = if only_docstrings {
-            docs
-        } else {
-            let mut out = String::default();
-            out += "```python\n";
-            if !declaration_kinds.is_empty() {
-                out.push('(');
-                out += &declaration_kinds.join(", ");
-                out += ") ";
-            }
-            if let Some(name) = on_name {
-                match declaration_kinds.as_slice() {
-                    ["class"] => {
-                        // Return the inner part in type[A], because that makes more sense and
-                        // looks nicer
-                        if type_formatted.starts_with("type[") && type_formatted.ends_with("]") {
-                            type_formatted.drain(..5);
-                            type_formatted.drain(type_formatted.len() - 1..);
-                        }
-                    }
-                    ["function" | "property"] => (),
-                    ["type"] => {
-                        out += name.as_code();
-                        out += " = ";
-                    }
-                    _ => {
-                        out += name.as_code();
-                        out += ": ";
+        let mut signature = String::default();
+        if let Some(name) = on_name {
+            match declaration_kinds.as_slice() {
+                ["class"] => {
+                    // Return the inner part in type[A], because that makes more sense and
+                    // looks nicer
+                    if type_formatted.starts_with("type[") && type_formatted.ends_with("]") {
+                        type_formatted.drain(..5);
+                        type_formatted.drain(type_formatted.len() - 1..);
                     }
                 }
+                ["function" | "property"] => (),
+                ["type"] => {
+                    signature += name.as_code();
+                    signature += " = ";
+                }
+                _ => {
+                    signature += name.as_code();
+                    signature += ": ";
+                }
             }
-            out += &type_formatted;
-            out += "\n```";
-            if !results.is_empty() {
-                out += "\n---\n";
-                out += &docs;
-            }
-            out
+        }
+        signature += &type_formatted;
+        let class_bases = match declaration_kinds.as_slice() {
+            ["class"] => class_bases,
+            _ => vec![],
         };
-        Ok(Some(DocumentationResult {
-            documentation,
+        Ok(Some(DocumentationParts {
+            declaration_kinds,
+            signature,
+            class_bases,
+            docs: results,
             on_symbol_range,
         }))
     }
 }
 
+struct DocumentationParts<'a> {
+    declaration_kinds: Vec<&'static str>,
+    signature: String,
+    class_bases: Vec<String>,
+    docs: Vec<String>,
+    on_symbol_range: Range<'a>,
+}
+
+fn code_block(declaration_kinds: &[&str], signature: &str) -> String {
+    let mut out = String::default();
+    out += "```python\n";
+    if !declaration_kinds.is_empty() {
+        out.push('(');
+        out += &declaration_kinds.join(", ");
+        out += ") ";
+    }
+    out += signature;
+    out += "\n```";
+    out
+}
+
+fn class_bases(i_s: &InferenceState, t: &Type) -> Vec<String> {
+    let db = i_s.db;
+    let Type::Type(inner) = t else {
+        return vec![];
+    };
+    let Some(class) = inner.maybe_class(db) else {
+        return vec![];
+    };
+    class
+        .bases(db)
+        .filter(|base| !base.is_object(db))
+        .map(|base| base.as_type(db).format_short(db).into_string())
+        .collect()
+}
+
+/// Converts reST field lists (`:param x: ...`) and Google style sections (`Args:`) to markdown.
+/// Doctests are already converted when the docstrings are fetched.
+fn docstring_fields_to_markdown(docstring: &str) -> String {
+    const SECTIONS: [&str; 10] = [
+        "Args",
+        "Arguments",
+        "Parameters",
+        "Returns",
+        "Return",
+        "Yields",
+        "Raises",
+        "Attributes",
+        "Note",
+        "Notes",
+    ];
+    let mut out = String::with_capacity(docstring.len());
+    let mut in_section = false;
+    let mut in_code_block = false;
+    for line in docstring.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        if content.starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || content.starts_with("```") {
+            in_section = false;
+            out += line;
+            continue;
+        }
+        let newline = &line[content.len()..];
+        let trimmed = content.trim_start();
+        if let Some(section) = trimmed.strip_suffix(':')
+            && trimmed.len() == content.len()
+            && SECTIONS.contains(&section)
+        {
+            in_section = true;
+            out += "**";
+            out += section;
+            out += "**";
+            out += newline;
+            continue;
+        }
+        if in_section && trimmed.len() != content.len() && !trimmed.is_empty() {
+            // An indented line in a section like `x (int): The x`
+            match trimmed.split_once(':') {
+                Some((name, rest)) if !name.contains(' ') || name.ends_with(')') => {
+                    let (name, annotation) = name.split_once(' ').unwrap_or((name, ""));
+                    out += &format!("- `{name}`");
+                    if !annotation.is_empty() {
+                        out += " ";
+                        out += annotation;
+                    }
+                    out += ":";
+                    out += rest;
+                }
+                _ => {
+                    out += "  ";
+                    out += trimmed;
+                }
+            }
+            out += newline;
+            continue;
+        }
+        in_section = false;
+        if let Some(field) = trimmed.strip_prefix(':')
+            && let Some((field, rest)) = field.split_once(':')
+        {
+            let mut parts = field.split_whitespace();
+            let kind = parts.next().unwrap_or_default();
+            let arg = parts.next_back();
+            let rest = rest.trim_start();
+            match (kind, arg) {
+                ("param" | "parameter" | "arg" | "argument" | "key" | "keyword", Some(arg)) => {
+                    out += &format!("- `{arg}`: {rest}")
+                }
+                ("type", Some(arg)) => out += &format!("- `{arg}` type: {rest}"),
+                ("raises" | "raise" | "except" | "exception", Some(arg)) => {
+                    out += &format!("- **Raises** `{arg}`: {rest}")
+                }
+                ("returns" | "return", None) => out += &format!("- **Returns**: {rest}"),
+                ("rtype", None) => out += &format!("- **Return type**: {rest}"),
+                _ => out += content,
+            }
+            out += newline;
+            continue;
+        }
+        out += line;
+    }
+    out
+}
+
 fn pretty_type_formatting(i_s: &InferenceState, t: &Type) -> Box<str> {
     let db = i_s.db;
     match t {
@@ -138,3 +331,38 @@ pub struct DocumentationResult<'a> {
     pub documentation: String,
     pub on_symbol_range: Range<'a>,
 }
+
+pub struct HoverContents<'a> {
+    pub declaration_kinds: Vec<&'static str>,
+    /// The fully resolved type or signature, e.g. `x: list[int]` or `def f(x: int) -> str`
+    pub signature: String,
+    /// The bases of a class (without `object`), only set when hovering a class.
+    pub class_bases: Vec<String>,
+    /// The docstring(s) converted to markdown
+    pub docstring: String,
+    pub on_symbol_range: Range<'a>,
+}
+
+impl HoverContents<'_> {
+    pub fn to_markdown(&self) -> String {
+        let mut out = code_block(&self.declaration_kinds, &self.signature);
+        if !self.class_bases.is_empty() {
+            out += "\n\nBases: ";
+            out += &self
+                .class_bases
+                .iter()
+                .map(|base| format!("`{base}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !self.docstring.is_empty() {
+                // Without an empty line, the bases would become a heading.
+                out += "\n";
+            }
+        }
+        if !self.docstring.is_empty() {
+            out += "\n---\n";
+            out += &self.docstring;
+        }
+        out
+    }
+}
@@ -20,7 +20,7 @@ use crate::{
         ParentScope, Point, PointLink, Specific,
     },
     debug,
-    diagnostics::{Issue, IssueKind},
+    diagnostics::{IncompatibleSignatureDetails, Issue, IssueKind},
     file::{File, Inference, inference::AssignKind},
     format_data::FormatData,
     imports::ImportResult,
@@ -375,6 +375,7 @@ impl Inference<'_, '_, '_> {
                         .file
                         .tree
                         .node_end_position_without_whitespace(stmt_like.parent_index);
+                    self.file.add_unreachable_range(start, end);
                     self.add_unreachable_error(start, end);
                     /*
                     if self.flags().mypy_compatible {
@@ -2714,6 +2715,7 @@ pub(super) fn check_override(
     original_class_name: impl Fn(&TypeOrClass) -> Box<str>,
     original_formatter: Option<&dyn Fn() -> String>,
 ) -> bool {
+    let original_link = original_lookup_details.lookup.maybe_name();
     let original_inf = original_lookup_details.lookup.into_inferred();
     let original_t = original_inf.as_cow_type(i_s);
     let original_class = original_lookup_details.class;
@@ -2890,6 +2892,7 @@ pub(super) fn check_override(
                             ).into(),
                             eq_class: (name == "__eq__").then(|| override_class.name().into()),
                             add_liskov_note: name != "__post_init__" && !added_liskov_note,
+                            original: original_link,
                         };
                         added_liskov_note = true;
                         match &param1.name {
@@ -2925,8 +2928,10 @@ pub(super) fn check_override(
                             r#"Return type "{}" of "{name}" incompatible with return type "{}" in supertype "{supertype}""#,
                             got_ret.format_short(db),
                             expected_ret.format_short(db),
-                        ),
+                        )
+                        .into(),
                         async_note,
+                        original: original_link,
                     };
                     if let Some(func) = maybe_func() {
                         func.add_issue_for_declaration(i_s, issue);
@@ -3028,7 +3033,10 @@ pub(super) fn check_override(
             let issue = IssueKind::SignatureIncompatibleWithSupertype {
                 name: name.into(),
                 base_class: original_class_name(&original_class),
-                notes: notes.into(),
+                details: Box::new(IncompatibleSignatureDetails {
+                    notes: notes.into(),
+                    original: original_link,
+                }),
             };
             if let Some(func) = maybe_func() {
                 func.add_issue_for_declaration(i_s, issue);
@@ -35,6 +35,7 @@ use crate::{
     matching::{LookupKind, Matcher, OnTypeError},
     new_class,
     node_ref::NodeRef,
+    quick_fixes::case_patterns,
     recoverable_error,
     result_context::{CouldBeALiteral, ResultContext},
     type_::{
@@ -1044,6 +1045,16 @@ impl FlowAnalysis {
                         } else {
                             InferenceState::new(db, func.node_ref.file)
                         };
+                        if func
+                            .node_ref
+                            .file
+                            .issues
+                            .is_reused(func.node_ref.node_start_position())
+                        {
+                            // The function is unchanged since its diagnostics were calculated.
+                            func.cache_func_from_diagnostics(&i_s);
+                            return;
+                        }
                         self.with_frame(
                             Frame::new(FrameKind::BaseScope, delayed_func.reused_narrowings),
                             || {
@@ -1726,8 +1737,7 @@ impl<'file> Inference<'_, 'file, '_> {
         original_name_index: NodeIndex,
         name_link: PointLink,
     ) -> Option<Inferred> {
-        let (result, deleted) = FLOW_ANALYSIS
-            .with(|fa| fa.lookup_narrowed_key_and_deleted(self.i_s.db, FlowKey::Name(name_link)));
+        let (result, deleted) = self.lookup_narrowed_name_and_deleted(name_link);
         if deleted {
             self.add_issue(original_name_index, IssueKind::ReadingDeletedVariable);
             return Some(Inferred::new_any_from_error());
@@ -1741,6 +1751,15 @@ impl<'file> Inference<'_, 'file, '_> {
         Some(result)
     }
 
+    /// Like `maybe_lookup_narrowed_name`, but a deleted name is returned instead of reported.
+    pub fn lookup_narrowed_name_and_deleted(
+        &self,
+        name_link: PointLink,
+    ) -> (Option<Inferred>, bool) {
+        FLOW_ANALYSIS
+            .with(|fa| fa.lookup_narrowed_key_and_deleted(self.i_s.db, FlowKey::Name(name_link)))
+    }
+
     pub fn maybe_lookup_narrowed_primary(&self, primary: Primary) -> Option<Inferred> {
         self.maybe_has_primary_entry(primary).map(|x| x.1)
     }
@@ -2251,6 +2270,10 @@ impl<'file> Inference<'_, 'file, '_> {
             .point(if_block.first_leaf_index())
             .maybe_calculated_and_specific();
         if name_binder_check == Some(Specific::IfBranchAfterAlwaysReachableInNameBinder) {
+            self.add_unreachable_block(match if_block {
+                IfBlockType::If(_, block) => block,
+                IfBlockType::Else(else_block) => else_block.block(),
+            });
             return self.process_ifs(if_blocks, class, func);
         }
 
@@ -2271,6 +2294,7 @@ impl<'file> Inference<'_, 'file, '_> {
                         self.process_ifs(if_blocks, class, func)
                     }
                     Some(Specific::IfBranchAlwaysUnreachableInNameBinder) => {
+                        self.add_unreachable_block(block);
                         self.process_ifs(if_blocks, class, func)
                     }
                     _ => {
@@ -2293,6 +2317,19 @@ impl<'file> Inference<'_, 'file, '_> {
         }
     }
 
+    fn add_unreachable_block(&self, block: Block) {
+        // Blocks that the name binder considers unreachable (e.g. sys.version_info checks) are
+        // never analyzed, so they are only recorded for graying them out.
+        if let Some(first) = block.iter_stmt_likes().next() {
+            self.file.add_unreachable_range(
+                self.file.tree.node_start_position(first.parent_index),
+                self.file
+                    .tree
+                    .node_end_position_without_whitespace(block.index()),
+            );
+        }
+    }
+
     pub fn flow_analysis_for_comprehension_with_comp_ifs<T>(
         &self,
         for_if_clauses: ForIfClauseIterator,
@@ -2748,6 +2785,7 @@ impl<'file> Inference<'_, 'file, '_> {
                     subject_expr.index(),
                     IssueKind::NonExhaustiveMatch {
                         unmatched_type: rest.format_short(self.i_s.db),
+                        missing_cases: case_patterns(self.i_s.db, &rest),
                     },
                 );
             }
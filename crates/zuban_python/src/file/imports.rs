@@ -4,21 +4,28 @@ use parsa_python_cst::{
 };
 use vfs::{Directory, DirectoryEntry, FileEntry, Parent};
 
+use config::FollowImports;
+
 use crate::{
     database::{Database, Locality, Point, PointKind, Specific},
     debug,
     diagnostics::IssueKind,
     imports::{
         ImportAncestor, ImportResult, LoadedImportResult, STUBS_SUFFIX, find_import_ancestor,
-        global_import, namespace_import_with_unloaded_file, python_import_with_needs_exact_case,
+        global_import, module_with_other_case, namespace_import_with_unloaded_file,
+        python_import_with_needs_exact_case,
     },
     inference_state::InferenceState,
     inferred::Inferred,
     node_ref::NodeRef,
+    select_files::skipped_by_follow_imports,
     type_::{LookupResult, Type},
 };
 
-use super::{PythonFile, python_file::StarImport};
+use super::{
+    PythonFile,
+    python_file::{StarImport, qualified_name_of_file_entry},
+};
 
 impl PythonFile {
     pub(super) fn global_import(&self, db: &Database, name: Name) -> Option<ImportResult> {
@@ -30,7 +37,40 @@ impl PythonFile {
                 result.debug_info(db),
             );
         }
-        result
+        self.apply_follow_imports(db, result, name)
+    }
+
+    /// Imports of modules that are not checked are treated as Any for follow_imports = skip and
+    /// error. The skipped modules are not loaded.
+    fn apply_follow_imports(
+        &self,
+        db: &Database,
+        result: Option<ImportResult>,
+        name: Name,
+    ) -> Option<ImportResult> {
+        let Some(ImportResult::File(file_index)) = result else {
+            return result;
+        };
+        if file_index == self.file_index {
+            return result;
+        }
+        let file_entry = db.vfs.file_entry(file_index);
+        let Some(follow_imports) = skipped_by_follow_imports(db, file_entry) else {
+            return result;
+        };
+        debug!(
+            "Ignored import of {} because of follow_imports",
+            name.as_str()
+        );
+        if follow_imports == FollowImports::Error {
+            NodeRef::new(self, name.index()).add_type_issue(
+                db,
+                IssueKind::ImportIgnored {
+                    module_name: qualified_name_of_file_entry(file_entry).into(),
+                },
+            );
+        }
+        Some(ImportResult::PyTypedMissing)
     }
 
     pub fn cache_import_dotted_name(
@@ -69,6 +109,7 @@ impl PythonFile {
                 }
                 ImportResult::PyTypedMissing => Some(ImportResult::PyTypedMissing),
             };
+            let result = self.apply_follow_imports(db, result, name);
             if let Some(imported) = &result {
                 debug!(
                     "Imported {:?} for {:?}",
@@ -81,6 +122,7 @@ impl PythonFile {
                     name.as_str()
                 );
             } else if !self.flags(db).ignore_missing_imports {
+                let with_other_case = sub_module_with_other_case(db, &base, name.as_str());
                 let module_name = if let Some(base_loaded) = base.ensured_loaded_file(db) {
                     format!("{}.{}", base_loaded.qualified_name(db), name.as_str()).into()
                 } else {
@@ -88,8 +130,13 @@ impl PythonFile {
                     // file is deleted but still in the virtual filesystem.
                     dotted.as_code().into()
                 };
-                NodeRef::new(self, name.index())
-                    .add_type_issue(db, IssueKind::ModuleNotFound { module_name });
+                NodeRef::new(self, name.index()).add_type_issue(
+                    db,
+                    IssueKind::ModuleNotFound {
+                        module_name,
+                        with_other_case,
+                    },
+                );
             }
             result
         };
@@ -260,6 +307,10 @@ impl PythonFile {
                 db,
                 IssueKind::ModuleNotFound {
                     module_name: Box::from(name.as_str()),
+                    with_other_case: module_with_other_case(
+                        db.vfs.workspaces.iter().map(|w| &w.entries),
+                        name.as_str(),
+                    ),
                 },
             );
         }
@@ -422,6 +473,32 @@ fn sub_module_import(
     }
 }
 
+fn sub_module_with_other_case(db: &Database, base: &ImportResult, name: &str) -> Option<Box<str>> {
+    match base {
+        ImportResult::File(file_index) => {
+            let file_entry = db.vfs.file_entry(*file_index);
+            if !is_package_name(file_entry) {
+                return None;
+            }
+            let Parent::Directory(dir) = &file_entry.parent else {
+                return None;
+            };
+            module_with_other_case(
+                std::iter::once(Directory::entries(&db.vfs, &dir.upgrade().unwrap())),
+                name,
+            )
+        }
+        ImportResult::Namespace(namespace) => module_with_other_case(
+            namespace
+                .directories
+                .iter()
+                .map(|d| Directory::entries(&db.vfs, d)),
+            name,
+        ),
+        ImportResult::PyTypedMissing => None,
+    }
+}
+
 fn in_partial_stubs(db: &Database, file_entry: &FileEntry) -> bool {
     let Some(dir) = file_entry.parent.most_outer_dir() else {
         return false;
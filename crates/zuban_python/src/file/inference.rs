@@ -40,7 +40,7 @@ use crate::{
         AnyCause, CallableContent, CallableParam, CallableParams, DbString, IterCause, IterInfos,
         Literal, LiteralKind, LookupResult, ParamType, StarParamType, StarStarParamType,
         StringSlice, Tuple, TupleArgs, TupleUnpack, Type, UnionEntry, UnionType, Variance,
-        dataclass_converter_fields_lookup,
+        dataclass_converter_fields_lookup, narrow_partial_typed_dict_literal,
     },
     type_helpers::{
         Class, ClassLookupOptions, FirstParamKind, Function, GeneratorType, Instance,
@@ -506,7 +506,27 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
         right_side: AssignmentRightSide,
     ) {
         let (inplace_method, op_infos) = aug_assign.magic_methods();
-        let right = self.infer_assignment_right_side(right_side, &mut ResultContext::ValueExpected);
+        // The left side is needed early for `td |= {...}`, see `typed_dict_or_context`.
+        let right_atom = right_side
+            .maybe_simple_expression()
+            .and_then(|expr| expr.maybe_unpacked_atom());
+        let early_left = (op_infos.operand == "|"
+            && matches!(right_atom, Some(AtomContent::Dict(_))))
+        .then(|| self.infer_target(target.clone(), true));
+        let right = match early_left
+            .as_ref()
+            .and_then(|left| self.typed_dict_or_context(left.as_ref()?, right_atom))
+        {
+            Some((context, dict)) => narrow_partial_typed_dict_literal(
+                self.i_s,
+                self.infer_assignment_right_side(
+                    right_side,
+                    &mut ResultContext::new_known(&context),
+                ),
+                dict,
+            ),
+            None => self.infer_assignment_right_side(right_side, &mut ResultContext::ValueExpected),
+        };
         let lookup_and_execute = |left: Inferred| {
             let had_lookup_error = Cell::new(false);
             let mut result = left.type_lookup_and_execute(
@@ -528,7 +548,7 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
             }
             result
         };
-        if let Some(left) = self.infer_target(target.clone(), true) {
+        if let Some(left) = early_left.unwrap_or_else(|| self.infer_target(target.clone(), true)) {
             let result = lookup_and_execute(left);
 
             let n = NodeRef::new(self.file, right_side.index());
@@ -2443,6 +2463,7 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
             name: name.into(),
             suffix,
             is_self_attribute,
+            original: PointLink::new(self.file.file_index, first_index_of_definition),
         });
     }
 
@@ -3057,10 +3078,40 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
             self.infer_expression_part_with_context(part, context)
         };
         let left = check(op.left);
-        let right = check(op.right);
+        let right_atom = op.right.maybe_unpacked_atom();
+        let right = match (op.infos.operand == "|")
+            .then(|| self.typed_dict_or_context(&left, right_atom))
+            .flatten()
+        {
+            Some((context, dict)) => narrow_partial_typed_dict_literal(
+                self.i_s,
+                self.infer_expression_part_with_context(
+                    op.right,
+                    &mut ResultContext::new_known(&context),
+                ),
+                dict,
+            ),
+            None => check(op.right),
+        };
         self.infer_detailed_operation(op.index, op.infos, left, &right, result_context)
     }
 
+    /// A dict literal on the right side of `|` and `|=` with a TypedDict on the left side is
+    /// inferred like the argument of `update`, so it only needs to contain some of the keys.
+    fn typed_dict_or_context<'x>(
+        &self,
+        left: &Inferred,
+        right: Option<AtomContent<'x>>,
+    ) -> Option<(Type, Dict<'x>)> {
+        let Some(AtomContent::Dict(dict)) = right else {
+            return None;
+        };
+        match left.as_cow_type(self.i_s).as_ref() {
+            Type::TypedDict(td) => Some((Type::TypedDict(td.as_partial(self.i_s.db)), dict)),
+            _ => None,
+        }
+    }
+
     fn infer_detailed_operation(
         &self,
         error_index: NodeIndex,
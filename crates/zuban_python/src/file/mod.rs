@@ -22,7 +22,8 @@ pub(crate) use name_binder::{
 };
 pub(crate) use name_resolution::{is_private_import_and_not_in_dunder_all, is_reexport_issue};
 pub(crate) use python_file::{
-    ComplexValues, FileImport, OtherDefinitionIterator, PythonFile, SuperFile, dotted_path_from_dir,
+    ComplexValues, FileImport, OtherDefinitionIterator, PythonFile, SuperFile,
+    dotted_path_from_dir, flags_from_overrides,
 };
 pub(crate) use type_computation::{
     ANNOTATION_TO_EXPR_DIFFERENCE, CLASS_TO_CLASS_INFO_DIFFERENCE, ClassInitializer, ClassNodeRef,
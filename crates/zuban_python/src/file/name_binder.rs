@@ -2145,6 +2145,18 @@ fn maybe_sys_platform_startswith(
     Truthiness::Unknown
 }
 
+fn always_true_or_false_symbol(flags: &FinalizedTypeCheckerFlags, name: &str) -> Truthiness {
+    if flags.always_true_symbols.iter().any(|s| s == name) {
+        Truthiness::True {
+            in_type_checking_block: false,
+        }
+    } else if flags.always_false_symbols.iter().any(|s| s == name) {
+        Truthiness::False
+    } else {
+        Truthiness::Unknown
+    }
+}
+
 fn is_expr_reachable_for_name_binder(
     settings: &Settings,
     flags: &FinalizedTypeCheckerFlags,
@@ -2171,13 +2183,14 @@ pub fn is_expr_part_reachable_for_name_binder(
                     return Truthiness::True {
                         in_type_checking_block: true,
                     };
-                } else if "PY3" == n || flags.always_true_symbols.iter().any(|s| s == n) {
+                } else if "PY3" == n {
                     return Truthiness::True {
                         in_type_checking_block: false,
                     };
-                } else if n == "PY2" || flags.always_false_symbols.iter().any(|s| s == n) {
+                } else if n == "PY2" {
                     return Truthiness::False;
                 }
+                return always_true_or_false_symbol(flags, n);
             }
             AtomContent::NamedExpression(named_expr) => {
                 return is_expr_reachable_for_name_binder(settings, flags, named_expr.expression());
@@ -2195,6 +2208,8 @@ pub fn is_expr_part_reachable_for_name_binder(
                         in_type_checking_block: true,
                     };
                 }
+                // Like Mypy we also allow attributes like `config.FEATURE_X`
+                return always_true_or_false_symbol(flags, second);
             }
             PrimaryContent::Execution(execution) => {
                 if let PrimaryOrAtom::Primary(prim) = primary.first() {
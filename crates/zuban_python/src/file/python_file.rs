@@ -4,11 +4,16 @@ use std::{
     collections::{HashMap, VecDeque},
     fmt,
     ops::Range,
-    sync::{Arc, OnceLock, RwLock},
+    sync::{
+        Arc, OnceLock, RwLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Instant,
 };
 
 use config::{
-    DiagnosticConfig, FinalizedTypeCheckerFlags, IniOrTomlValue, TypeCheckerFlags, set_flag,
+    DiagnosticConfig, DiagnosticConfigOverrides, FinalizedTypeCheckerFlags, FollowImports,
+    IniOrTomlValue, TypeCheckerFlags, set_flag,
 };
 use parsa_python_cst::*;
 use utils::InsertOnlyVec;
@@ -25,6 +30,7 @@ use super::{
 };
 use crate::{
     InputPosition,
+    cancellation::Cancelled,
     database::{
         ComplexPoint, Database, Locality, Point, PointLink, Points, PythonProject, Specific,
     },
@@ -34,6 +40,8 @@ use crate::{
     inference_state::InferenceState,
     lines::{BytePositionInfos, NewlineIndices, PositionInfos},
     node_ref::NodeRef,
+    select_files::is_part_of_checked_files,
+    timing::FileTimings,
     type_::{DbString, LookupResult},
     utils::SymbolTable,
 };
@@ -46,6 +54,10 @@ impl ComplexValues {
         &self.0[index]
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn insert(
         &self,
         points: &Points,
@@ -110,7 +122,16 @@ pub(crate) struct PythonFile {
     stub_cache: Option<StubCache>,
     pub ignore_type_errors: bool,
     flags: Option<FinalizedTypeCheckerFlags>,
+    // Set by inline configuration comments like `# zuban: show-error-codes`.
+    pub diagnostic_config_overrides: DiagnosticConfigOverrides,
     pub(super) delayed_diagnostics: RwLock<VecDeque<DelayedDiagnostic>>,
+    // Statements that are unreachable, even if --warn-unreachable is not enabled. This is used to
+    // gray out code in editors.
+    unreachable_ranges: RwLock<Vec<(CodeIndex, CodeIndex)>>,
+    pub timings: FileTimings,
+    // The access generation of the database when the file was last used, which is used to evict
+    // the least recently used files if the memory limit is reached.
+    last_used: AtomicU32,
 
     pub newline_indices: NewlineIndices,
 }
@@ -126,12 +147,23 @@ impl SubFiles {
         self.in_same_file.read().unwrap().get(&start).copied()
     }
 
+    pub fn in_same_file(&self) -> Vec<FileIndex> {
+        self.in_same_file
+            .read()
+            .unwrap()
+            .values()
+            .copied()
+            .collect()
+    }
+
     fn save_sub_file_at_position(&self, start: CodeIndex, file_index: FileIndex) {
         self.in_same_file.write().unwrap().insert(start, file_index);
     }
 
     pub fn add_separate_file(&mut self, sub_file: FileIndex) {
-        self.separate_files.push(sub_file);
+        if !self.separate_files.contains(&sub_file) {
+            self.separate_files.push(sub_file);
+        }
     }
 
     pub fn take_separate_files(&mut self) -> Vec<FileIndex> {
@@ -165,7 +197,11 @@ impl Clone for PythonFile {
             stub_cache: self.stub_cache.clone(),
             ignore_type_errors: self.ignore_type_errors,
             flags: self.flags.clone(),
+            diagnostic_config_overrides: self.diagnostic_config_overrides,
             delayed_diagnostics: RwLock::new(self.delayed_diagnostics.read().unwrap().clone()),
+            unreachable_ranges: RwLock::new(self.unreachable_ranges.read().unwrap().clone()),
+            timings: self.timings.clone(),
+            last_used: AtomicU32::new(self.last_used()),
             newline_indices: self.newline_indices.clone(),
         }
     }
@@ -202,9 +238,17 @@ impl File for PythonFile {
     }
 
     fn diagnostics<'db>(&'db self, db: &'db Database) -> Box<[Diagnostic<'db>]> {
+        if self.super_file.is_none()
+            && self.flags(db).follow_imports == FollowImports::Silent
+            && !is_part_of_checked_files(db, self.file_entry(db))
+        {
+            // Modules that are only followed are analyzed, but their errors are not reported.
+            return Box::new([]);
+        }
         if self
             .super_file
             .is_none_or(|super_file| !super_file.is_part_of_parent())
+            && !self.issues.is_restored_from_cache()
         {
             // The main file is responsible for calculating diagnostics of type comments,
             // annotation strings, etc.
@@ -217,9 +261,16 @@ impl File for PythonFile {
                 .map(|i| Diagnostic::new(db, self, i))
                 .collect()
         };
-        for (_, file_index) in self.sub_files.in_same_file.read().unwrap().iter() {
-            let file = db.loaded_python_file(*file_index);
-            vec.extend(file.diagnostics(db).into_vec().into_iter());
+        // The issues of sub files are part of the issues that were restored from the cache.
+        if !self.issues.is_restored_from_cache() {
+            for (offset, file_index) in self.sub_files.in_same_file.read().unwrap().iter() {
+                // The issues of reused ranges already contain the issues of their sub files.
+                if self.issues.is_reused(*offset) {
+                    continue;
+                }
+                let file = db.loaded_python_file(*file_index);
+                vec.extend(file.diagnostics(db).into_vec().into_iter());
+            }
         }
         vec.sort_by_key(|diag| diag.issue.start_position);
         vec.into_boxed_slice()
@@ -231,16 +282,18 @@ impl File for PythonFile {
         points.invalidate_full_db();
         let is_stub = self.is_stub();
         let tree = std::mem::replace(&mut self.tree, Tree::invalid_empty());
+        let diagnostic_config_overrides = self.diagnostic_config_overrides;
         *self = Self::new_internal(
             self.file_index,
             tree,
             points,
-            Diagnostics::default(),
+            Diagnostics::new(&project.settings),
             is_stub,
             self.flags.take().map(|flags| flags.into_unfinalized()),
             project,
             self.ignore_type_errors,
         );
+        self.diagnostic_config_overrides = diagnostic_config_overrides;
     }
 
     fn has_super_file(&self) -> bool {
@@ -262,6 +315,7 @@ impl vfs::VfsFile for PythonFile {
     fn invalidate_references_to(&mut self, file_index: Option<FileIndex>) {
         self.points.invalidate_references_to(file_index);
         self.issues.invalidate_non_name_binder_issues();
+        self.unreachable_ranges.get_mut().unwrap().clear();
         if let Some(cache) = self.stub_cache.as_mut() {
             *cache = StubCache::default();
         }
@@ -297,8 +351,12 @@ impl<'db> PythonFile {
         code: Box<str>,
     ) -> Self {
         debug!("Initialize {} ({file_index})", file_entry.name);
+        let start = Instant::now();
         let tree = Tree::parse(code);
-        PythonFile::new(project, file_index, file_entry, tree)
+        let parse = start.elapsed();
+        let mut file = PythonFile::new(project, file_index, file_entry, tree);
+        file.timings.parse = parse;
+        file
     }
 
     pub fn new(
@@ -308,7 +366,7 @@ impl<'db> PythonFile {
         tree: Tree,
     ) -> Self {
         let is_stub = file_entry.name.ends_with(".pyi");
-        let issues = Diagnostics::default();
+        let issues = Diagnostics::new(&project_options.settings);
         let mut ignore_type_errors =
             tree.has_type_ignore_at_start()
                 .unwrap_or_else(|ignore_code| {
@@ -330,7 +388,7 @@ impl<'db> PythonFile {
             project_options,
             file_entry,
             &issues,
-            tree.mypy_inline_config_directives(),
+            tree.inline_config_directives(),
         );
         ignore_type_errors |= match &directives_info.flags {
             Some(flags) => flags.ignore_errors,
@@ -344,7 +402,7 @@ impl<'db> PythonFile {
             debug_assert!(result.is_ok());
         }
         let points = Points::new(tree.length());
-        Self::new_internal(
+        let mut file = Self::new_internal(
             file_index,
             tree,
             points,
@@ -353,7 +411,21 @@ impl<'db> PythonFile {
             directives_info.flags,
             project_options,
             ignore_type_errors,
-        )
+        );
+        file.diagnostic_config_overrides = directives_info.diagnostic_config_overrides;
+        file
+    }
+
+    /// Like `invalidate_full_db`, but the flags of the overrides and of the inline configuration
+    /// are calculated again, because the options of the project changed.
+    pub(crate) fn invalidate_for_changed_options(
+        &mut self,
+        project: &PythonProject,
+        file_entry: &FileEntry,
+    ) {
+        debug_assert!(self.super_file.is_none());
+        let tree = std::mem::replace(&mut self.tree, Tree::invalid_empty());
+        *self = Self::new(project, self.file_index, file_entry, tree);
     }
 
     fn new_internal(
@@ -370,6 +442,7 @@ impl<'db> PythonFile {
         let complex_points = Default::default();
         let star_imports: RefCell<Vec<StarImport>> = Default::default();
         let all_imports: RefCell<Vec<FileImport>> = Default::default();
+        let start = Instant::now();
         let symbol_table = NameBinder::with_global_binder(
             DbInfos {
                 // TODO this does not use flags of the super file. Is this an issue?
@@ -386,6 +459,10 @@ impl<'db> PythonFile {
             },
             |binder| binder.index_file(tree.root()),
         );
+        let timings = FileTimings {
+            name_binding: start.elapsed(),
+            ..Default::default()
+        };
         Self {
             tree,
             file_index,
@@ -402,7 +479,22 @@ impl<'db> PythonFile {
             stub_cache: is_stub.then(StubCache::default),
             ignore_type_errors,
             flags,
+            diagnostic_config_overrides: Default::default(),
             delayed_diagnostics: Default::default(),
+            unreachable_ranges: Default::default(),
+            timings,
+            last_used: Default::default(),
+        }
+    }
+
+    pub fn last_used(&self) -> u32 {
+        self.last_used.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_used(&self, generation: u32) {
+        // Avoids writing to the shared cache line if nothing changed.
+        if self.last_used() != generation {
+            self.last_used.store(generation, Ordering::Relaxed)
         }
     }
 
@@ -468,9 +560,84 @@ impl<'db> PythonFile {
         (entry, is_package_name(entry))
     }
 
+    pub(crate) fn add_unreachable_range(&self, start: CodeIndex, end: CodeIndex) {
+        self.unreachable_ranges.write().unwrap().push((start, end))
+    }
+
+    /// Expects the diagnostics of this file to be calculated.
+    pub(crate) fn is_unreachable(&self, index: CodeIndex) -> bool {
+        self.unreachable_ranges
+            .read()
+            .unwrap()
+            .iter()
+            .any(|&(start, end)| start <= index && index < end)
+    }
+
+    /// The unreachable ranges in the order they were found, see `unreachable_ranges`.
+    pub(crate) fn unmerged_unreachable_ranges(&self) -> Vec<(CodeIndex, CodeIndex)> {
+        self.unreachable_ranges.read().unwrap().clone()
+    }
+
+    pub fn unreachable_ranges(
+        &'db self,
+        db: &'db Database,
+    ) -> Vec<(PositionInfos<'db>, PositionInfos<'db>)> {
+        let result = self.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        let mut ranges = self.unreachable_ranges.read().unwrap().clone();
+        ranges.sort();
+        let mut result: Vec<(CodeIndex, CodeIndex)> = vec![];
+        for (start, end) in ranges {
+            if let Some(last) = result.last_mut() {
+                // Unreachable blocks are also analyzed in the language server, which means that
+                // nested statements might be reported again. Statements that directly follow
+                // each other are merged.
+                if start <= last.1
+                    || self.tree.code()[last.1 as usize..start as usize]
+                        .trim()
+                        .is_empty()
+                {
+                    last.1 = last.1.max(end);
+                    continue;
+                }
+            }
+            result.push((start, end));
+        }
+        result
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    self.byte_to_position_infos(db, start),
+                    self.byte_to_position_infos(db, end),
+                )
+            })
+            .collect()
+    }
+
     pub fn ensure_calculated_diagnostics(&self, db: &Database) -> Result<(), ()> {
-        self.inference(&InferenceState::new(db, self))
-            .calculate_module_diagnostics()
+        let start = Instant::now();
+        let result = self
+            .inference(&InferenceState::new(db, self))
+            .calculate_module_diagnostics();
+        if result.is_ok() {
+            self.timings.check.get_or_init(|| start.elapsed());
+            self.issues.mark_complete();
+        }
+        result
+    }
+
+    /// Calculating diagnostics cannot be interrupted without leaving partial state behind,
+    /// therefore cancellation is only checked before the calculation starts.
+    pub fn ensure_calculated_diagnostics_unless_cancelled(
+        &self,
+        db: &Database,
+    ) -> Result<(), Cancelled> {
+        if !self.points.get(0).calculated() {
+            db.cancellation.check()?
+        }
+        let result = self.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        Ok(())
     }
 
     pub fn ensure_module_symbols_flow_analysis(&self, db: &Database) -> Result<(), ()> {
@@ -499,7 +666,7 @@ impl<'db> PythonFile {
                 file_index,
                 tree,
                 points,
-                Diagnostics::default(),
+                Diagnostics::new(&db.project.settings),
                 self.is_stub(),
                 None,
                 &db.project,
@@ -804,12 +971,7 @@ impl<'db> PythonFile {
     }
 
     pub fn qualified_name(&self, db: &Database) -> String {
-        let (name, parent_dir) = self.name_and_parent_dir(db);
-        if let Some(parent_dir) = parent_dir {
-            dotted_path_from_dir(&parent_dir) + "." + name
-        } else {
-            name.strip_suffix(STUBS_SUFFIX).unwrap_or(name).to_string()
-        }
+        qualified_name_of_file_entry(self.file_entry(db))
     }
 
     pub fn name(&self, db: &'db Database) -> &'db str {
@@ -817,6 +979,27 @@ impl<'db> PythonFile {
         name.strip_suffix(STUBS_SUFFIX).unwrap_or(name)
     }
 
+    /// The diagnostics that were suppressed by `# type: ignore` comments. Only available after
+    /// the diagnostics were calculated.
+    pub fn suppressed_diagnostics(&'db self, db: &'db Database) -> Vec<Diagnostic<'db>> {
+        let mut vec: Vec<_> = unsafe {
+            self.issues
+                .iter_suppressed()
+                .map(|i| Diagnostic::new(db, self, i))
+                .collect()
+        };
+        if !self.issues.is_restored_from_cache() {
+            for (_, file_index) in self.sub_files.in_same_file.read().unwrap().iter() {
+                vec.extend(
+                    db.loaded_python_file(*file_index)
+                        .suppressed_diagnostics(db),
+                );
+            }
+        }
+        vec.sort_by_key(|diag| diag.issue.start_position);
+        vec
+    }
+
     pub fn flags<'x>(&'x self, db: &'x Database) -> &'x FinalizedTypeCheckerFlags {
         self.maybe_more_specific_flags(db)
             .unwrap_or(&db.project.flags)
@@ -913,6 +1096,42 @@ fn name_and_parent_dir(entry: &FileEntry, skip_stubs: bool) -> (&str, Option<Arc
     (name, entry.parent.maybe_dir().ok())
 }
 
+/// The flags of a module if they are changed by the overrides of the config, which does not need
+/// the module to be loaded.
+pub(crate) fn flags_from_overrides(
+    project: &PythonProject,
+    file_entry: &FileEntry,
+) -> Option<TypeCheckerFlags> {
+    if project.overrides.is_empty() {
+        return None;
+    }
+    let mut flags = None;
+    let (name, parent_dir) = name_and_parent_dir(file_entry, true);
+    for override_ in &project.overrides {
+        if override_
+            .module
+            .matches_file_path(name, parent_dir.as_deref())
+        {
+            override_
+                .apply_to_flags(
+                    flags.get_or_insert_with(|| project.flags.clone().into_unfinalized()),
+                )
+                .expect("Overrides are validated when loading the config");
+        }
+    }
+    flags
+}
+
+/// The dotted name of the module of a file, e.g. `foo.bar` for `foo/bar/__init__.py`.
+pub(crate) fn qualified_name_of_file_entry(file_entry: &FileEntry) -> String {
+    let (name, parent_dir) = name_and_parent_dir(file_entry, true);
+    if let Some(parent_dir) = parent_dir {
+        dotted_path_from_dir(&parent_dir) + "." + name
+    } else {
+        name.strip_suffix(STUBS_SUFFIX).unwrap_or(name).to_string()
+    }
+}
+
 fn info_from_directives<'x>(
     project: &PythonProject,
     file_entry: &FileEntry,
@@ -920,24 +1139,8 @@ fn info_from_directives<'x>(
     directives: impl Iterator<Item = (CodeIndex, &'x str)>,
 ) -> DirectivesInfos {
     // Directives like `# mypy: disallow-any-generics`
-    let mut flags = None;
-
-    if !project.overrides.is_empty() {
-        let (name, parent_dir) = name_and_parent_dir(file_entry, true);
-        for override_ in &project.overrides {
-            if override_
-                .module
-                .matches_file_path(name, parent_dir.as_deref())
-            {
-                if flags.is_none() {
-                    flags = Some(project.flags.clone().into_unfinalized());
-                }
-                override_
-                    .apply_to_flags(flags.as_mut().unwrap())
-                    .expect("Issues with loading config overrides, TODO need better error");
-            }
-        }
-    }
+    let mut flags = flags_from_overrides(project, file_entry);
+    let mut diagnostic_config_overrides = DiagnosticConfigOverrides::default();
 
     for (start_position, rest) in directives {
         let splitter = DirectiveSplitter {
@@ -947,16 +1150,16 @@ fn info_from_directives<'x>(
         };
         for (name, value) in splitter {
             let name = name.replace('-', "_");
-            if flags.is_none() {
-                flags = Some(project.flags.clone().into_unfinalized());
-            }
+            let value = match value {
+                Some(value) => IniOrTomlValue::Ini(value),
+                None => IniOrTomlValue::InlineConfigNoValue,
+            };
             let mut check = || -> anyhow::Result<_> {
-                let value = match value {
-                    Some(value) => IniOrTomlValue::Ini(value),
-                    None => IniOrTomlValue::InlineConfigNoValue,
-                };
-                set_flag(flags.as_mut().unwrap(), &name, value, true)?;
-                Ok(())
+                if let Some(result) = diagnostic_config_overrides.set(&name, value) {
+                    return result;
+                }
+                let flags = flags.get_or_insert_with(|| project.flags.clone().into_unfinalized());
+                set_flag(flags, &name, value, true)
             };
             if let Err(err) = check() {
                 issues
@@ -972,11 +1175,15 @@ fn info_from_directives<'x>(
             }
         }
     }
-    DirectivesInfos { flags }
+    DirectivesInfos {
+        flags,
+        diagnostic_config_overrides,
+    }
 }
 
 struct DirectivesInfos {
     flags: Option<TypeCheckerFlags>,
+    diagnostic_config_overrides: DiagnosticConfigOverrides,
 }
 
 struct DirectiveSplitter<'db, 'code> {
@@ -1706,7 +1706,7 @@ fn initialize_typed_dict_members(db: &Database, cls: &Class, td_infos: DeferredT
     debug!("Start TypedDict members calculation for {:?}", cls.name());
     let file = cls.node_ref.file;
     let i_s = &InferenceState::new(db, file).with_class_context(cls);
-    find_stmt_typed_dict_types(
+    let legacy_extra_items = find_stmt_typed_dict_types(
         i_s,
         file,
         &mut typed_dict_members,
@@ -1714,7 +1714,10 @@ fn initialize_typed_dict_members(db: &Database, cls: &Class, td_infos: DeferredT
         &typed_dict_definition.initialization_args,
         extra_items.as_ref(),
     );
-    let initialization_args = &td_infos.1;
+    let mut initialization_args = Cow::Borrowed(&td_infos.1);
+    if legacy_extra_items.is_some() {
+        initialization_args.to_mut().extra_items = legacy_extra_items;
+    }
     let add = |issue| NodeRef::new(file, args.unwrap().index()).add_type_issue(db, issue);
     if let Some(old) = &extra_items {
         match initialization_args.closed {
@@ -1730,7 +1733,7 @@ fn initialize_typed_dict_members(db: &Database, cls: &Class, td_infos: DeferredT
     }
     if let Some(new) = file
         .name_resolution_for_types(i_s)
-        .compute_class_typed_dict_extra_items(initialization_args)
+        .compute_class_typed_dict_extra_items(&initialization_args)
     {
         if let Some(old) = &extra_items {
             // Closed was already handled above
@@ -1778,8 +1781,9 @@ fn find_stmt_typed_dict_types(
     stmt_likes: StmtLikeIterator,
     initialization_args: &TypedDictArgs,
     extra_items: Option<&ExtraItemsType>,
-) {
+) -> Option<NodeIndex> {
     let db = i_s.db;
+    let mut legacy_extra_items = None;
     for stmt_like in stmt_likes {
         match stmt_like.node {
             StmtLikeContent::Assignment(assignment) => match assignment.unpack() {
@@ -1788,6 +1792,15 @@ fn find_stmt_typed_dict_types(
                         NodeRef::new(file, assignment.index())
                             .add_type_issue(db, IssueKind::TypedDictInvalidMemberRightSide);
                     }
+                    if name_def.as_code() == "__extra_items__"
+                        && initialization_args.closed == Some(true)
+                        && initialization_args.extra_items.is_none()
+                    {
+                        // `closed=True` with an `__extra_items__` key is the syntax of an earlier
+                        // draft of PEP 728 that is still supported by typing_extensions.
+                        legacy_extra_items = Some(annot.expression().index());
+                        continue;
+                    }
                     if let Err(issue) = vec.add(
                         i_s,
                         file.name_resolution_for_types(i_s)
@@ -1835,6 +1848,7 @@ fn find_stmt_typed_dict_types(
             }
         }
     }
+    legacy_extra_items
 }
 
 fn find_stmt_named_tuple_types(
@@ -2261,7 +2275,9 @@ impl<'db, 'file> NameResolution<'db, 'file, '_> {
                     "order_default" => assign_option(&mut d.order_default),
                     "kw_only_default" => assign_option(&mut d.kw_only_default),
                     "frozen_default" => assign_option(&mut d.frozen_default),
-                    "field_specifiers" => self
+                    // `field_descriptors` is the name of an earlier draft of PEP 681 that is
+                    // still used by e.g. pydantic 1.10.
+                    "field_specifiers" | "field_descriptors" => self
                         .fill_dataclass_transform_field_specifiers(value, &mut d.field_specifiers),
                     _ => {
                         self.add_issue(
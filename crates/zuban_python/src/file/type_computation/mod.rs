@@ -3592,6 +3592,24 @@ impl<'db, 'file> NameResolution<'db, 'file, '_> {
     }
 
     #[inline]
+    fn type_from_plugins(
+        i_s: &InferenceState,
+        node_ref: NodeRef,
+        name_def: NameDef,
+    ) -> Option<Type> {
+        let name = name_def.name();
+        // Only module level names have a fully qualified name.
+        if node_ref.file.lookup_symbol(name.as_code())?.node_index != name.index() {
+            return None;
+        }
+        let fullname = format!(
+            "{}.{}",
+            node_ref.file.qualified_name(i_s.db),
+            name.as_code()
+        );
+        i_s.db.project.plugins.analyze_type(i_s, &fullname)
+    }
+
     fn handle_name_def(i_s: &InferenceState<'db, '_>, node_ref: NodeRef) -> Lookup<'db, 'db> {
         let node_ref = node_ref.to_db_lifetime(i_s.db);
         let name_def = node_ref.expect_name_def();
@@ -3600,10 +3618,17 @@ impl<'db, 'file> NameResolution<'db, 'file, '_> {
                 cache_class_name(node_ref, c);
                 Self::ensure_cached_class(i_s, ClassNodeRef::new(node_ref.file, c.index()))
             }
-            TypeLike::Assignment(assignment) => node_ref
-                .file
-                .name_resolution_for_types(i_s)
-                .compute_type_assignment(assignment),
+            TypeLike::Assignment(assignment) => {
+                if !i_s.db.project.plugins.is_empty()
+                    && let Some(t) = Self::type_from_plugins(i_s, node_ref, name_def)
+                {
+                    return Lookup::T(TypeContent::Type(t));
+                }
+                node_ref
+                    .file
+                    .name_resolution_for_types(i_s)
+                    .compute_type_assignment(assignment)
+            }
             TypeLike::ImportFromAsName(from_as_name) => {
                 let name_resolution = node_ref.file.name_resolution_for_types(i_s);
                 let lookup = name_resolution.point_resolution_to_type_name_lookup(
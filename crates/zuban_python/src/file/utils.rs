@@ -638,7 +638,11 @@ pub fn on_argument_type_error(
         "ModuleType" => "Module".to_string(),
         got => format!("\"{got}\""),
     };
-    if arg.add_argument_issue(i_s, &got, &strings.expected, error_text) {
+    let classes = match (&types.got, types.expected) {
+        (GotType::Type(Type::Class(got)), Type::Class(expected)) => Some((got.link, expected.link)),
+        _ => None,
+    };
+    if arg.add_argument_issue(i_s, &got, &strings.expected, classes, error_text) {
         types.add_mismatch_notes(|issue| {
             arg.add_issue(i_s, issue);
         })
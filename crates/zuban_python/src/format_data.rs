@@ -35,6 +35,8 @@ pub(crate) struct FormatData<'db, 'a, 'b, 'c> {
     pub style: FormatStyle,
     pub verbose: bool,
     pub hide_implicit_literals: bool,
+    /// Recursive type aliases and `NewType`s are shown as the types they stand for.
+    pub expand_aliases: bool,
     types_that_need_qualified_names: &'a [PointLink],
     displayed_recursive: Option<DisplayedRecursive<'c>>,
 }
@@ -47,6 +49,7 @@ impl<'db, 'a, 'b, 'c> FormatData<'db, 'a, 'b, 'c> {
             style: FormatStyle::Short,
             verbose: false,
             hide_implicit_literals: true,
+            expand_aliases: false,
             displayed_recursive: None,
             types_that_need_qualified_names: &[],
         }
@@ -62,6 +65,13 @@ impl<'db, 'a, 'b, 'c> FormatData<'db, 'a, 'b, 'c> {
         }
     }
 
+    pub fn new_expanded(db: &'db Database) -> Self {
+        Self {
+            expand_aliases: true,
+            ..Self::new_short(db)
+        }
+    }
+
     pub fn new_reveal_type(db: &'db Database) -> Self {
         Self {
             style: FormatStyle::MypyRevealType,
@@ -98,6 +108,7 @@ impl<'db, 'a, 'b, 'c> FormatData<'db, 'a, 'b, 'c> {
                 style: self.style,
                 verbose: self.verbose,
                 hide_implicit_literals: self.hide_implicit_literals,
+                expand_aliases: self.expand_aliases,
                 displayed_recursive: Some(displayed_recursive),
                 types_that_need_qualified_names: self.types_that_need_qualified_names,
             })
@@ -111,6 +122,7 @@ impl<'db, 'a, 'b, 'c> FormatData<'db, 'a, 'b, 'c> {
             style: self.style,
             verbose: self.verbose,
             hide_implicit_literals: self.hide_implicit_literals,
+            expand_aliases: self.expand_aliases,
             displayed_recursive: self.displayed_recursive,
             types_that_need_qualified_names: self.types_that_need_qualified_names,
         }
@@ -101,7 +101,7 @@ impl<'db> PositionalDocument<'db, GotoNode<'db>> {
         )
     }
 
-    fn infer_position(&self) -> Option<Inferred> {
+    pub fn infer_position(&self) -> Option<Inferred> {
         let result = match &self.node {
             GotoNode::Name(name) => self.infer_name(*name),
             GotoNode::ImportFromAsName { import_as_name, .. } => {
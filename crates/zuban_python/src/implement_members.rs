@@ -0,0 +1,370 @@
+//! Generates stubs for the members of abstract base classes and protocols that a class does not
+//! implement yet. The signatures are taken from the base class with its type arguments applied.
+
+use parsa_python_cst::{ClassDef, Scope};
+
+use crate::{
+    CodeAction,
+    database::Database,
+    diagnostics::IssueKind,
+    file::{ClassNodeRef, File as _, PythonFile},
+    goto::with_i_s_non_self,
+    inference_state::InferenceState,
+    node_ref::NodeRef,
+    quick_fixes::writable_annotation,
+    type_::{
+        CallableContent, CallableParams, FunctionKind, LookupResult, ParamType, StarParamType,
+        StarStarParamType, Type,
+    },
+    type_helpers::{Class, Function, Instance, InstanceLookupOptions, TypeOrClass},
+};
+
+/// Offered for classes that inherit abstract members without implementing them.
+pub(crate) fn implement_abstract_members<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    class: ClassDef,
+) -> Option<CodeAction<'db>> {
+    let class_ref = ClassNodeRef::new(file, class.index());
+    let class_infos = class_ref.maybe_cached_class_infos(db)?;
+    let symbol_table = &class_ref.class_storage().class_symbol_table;
+    let members: Vec<_> = class_infos
+        .abstract_attributes
+        .iter()
+        .map(|&link| NodeRef::from_link(db, link))
+        .filter(|definition| symbol_table.lookup_symbol(definition.as_code()).is_none())
+        .collect();
+    if members.is_empty() {
+        return None;
+    }
+    let self_class = Class::with_self_generics(db, class_ref);
+    let code = with_i_s_non_self(db, file, Scope::Module, |i_s| {
+        let instance = Instance::new(self_class, None);
+        members_code(i_s, file, &members, false, |name| {
+            instance
+                .lookup(i_s, name, InstanceLookupOptions::new(&|_| false))
+                .lookup
+        })
+    });
+    insert_members(
+        db,
+        file,
+        class,
+        format!(r#"Implement abstract members of "{}""#, class_ref.name()),
+        code,
+    )
+}
+
+/// Offered for errors about instantiating abstract classes and about classes that are not
+/// compatible with a protocol, because members are missing.
+pub(crate) fn implement_members_for_issue<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    kind: &IssueKind,
+) -> Option<CodeAction<'db>> {
+    match kind {
+        IssueKind::CannotInstantiateAbstractClass { name, .. } => {
+            let class_ref = class_in_file(db, file, name)?;
+            implement_abstract_members(db, file, class_ref.node())
+        }
+        IssueKind::IncompatibleAssignment { got, expected }
+        | IssueKind::IncompatibleReturn { got, expected } => implement_protocol_members(
+            db,
+            file,
+            class_in_file(db, file, got)?,
+            resolve_class(db, file, expected)?,
+        ),
+        IssueKind::ArgumentTypeIssue {
+            classes: Some((got, expected)),
+            ..
+        } => {
+            let class_ref = ClassNodeRef::from_link(db, *got);
+            if class_ref.file_index() != file.file_index {
+                return None;
+            }
+            implement_protocol_members(db, file, class_ref, ClassNodeRef::from_link(db, *expected))
+        }
+        _ => None,
+    }
+}
+
+fn implement_protocol_members<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    class_ref: ClassNodeRef<'db>,
+    protocol_ref: ClassNodeRef<'db>,
+) -> Option<CodeAction<'db>> {
+    let protocol = Class::with_self_generics(db, protocol_ref);
+    if !protocol.is_protocol(db) {
+        return None;
+    }
+    let self_class = Class::with_self_generics(db, class_ref);
+    let code = with_i_s_non_self(db, file, Scope::Module, |i_s| {
+        let instance = Instance::new(self_class, None);
+        let mut members: Vec<NodeRef> = vec![];
+        for (_, base) in protocol.mro(db) {
+            let TypeOrClass::Class(base) = base else {
+                continue;
+            };
+            let Some(class_infos) = base.maybe_cached_class_infos(db) else {
+                continue;
+            };
+            for member in class_infos.protocol_members.iter() {
+                let definition = NodeRef::new(base.node_ref.file, member.name_index);
+                let name = definition.as_code();
+                let is_missing = matches!(
+                    instance
+                        .lookup(i_s, name, InstanceLookupOptions::new(&|_| false))
+                        .lookup,
+                    LookupResult::None
+                );
+                if is_missing && !members.iter().any(|m| m.as_code() == name) {
+                    members.push(definition)
+                }
+            }
+        }
+        if members.is_empty() {
+            return None;
+        }
+        let self_type = self_class.as_type(db);
+        let as_self_instance = || self_type.clone();
+        let protocol_instance = Instance::new(protocol, None);
+        Some(members_code(i_s, file, &members, true, |name| {
+            protocol_instance
+                .lookup(
+                    i_s,
+                    name,
+                    InstanceLookupOptions::new(&|_| false).with_as_self_instance(&as_self_instance),
+                )
+                .lookup
+        }))
+    })?;
+    insert_members(
+        db,
+        file,
+        class_ref.node(),
+        format!(r#"Implement members of protocol "{}""#, protocol_ref.name()),
+        code,
+    )
+}
+
+/// The code of all members, every member is separated by an empty line from the previous
+/// statement. The lines are not indented yet.
+fn members_code(
+    i_s: &InferenceState,
+    file: &PythonFile,
+    members: &[NodeRef],
+    avoid_type_vars: bool,
+    lookup: impl Fn(&str) -> LookupResult,
+) -> Vec<Vec<String>> {
+    let db = i_s.db;
+    let annotation = |t: &Type| {
+        if t.is_any() || avoid_type_vars && t.has_type_vars() {
+            return None;
+        }
+        writable_annotation(db, file, t)
+    };
+    members
+        .iter()
+        .filter_map(|definition| {
+            let name = definition.as_code();
+            let t = lookup(name).into_inferred().as_type(i_s);
+            let Some(func) = definition.maybe_name_of_function() else {
+                // Annotations that cannot be written are copied from the definition.
+                let annotation = annotation(&t).or_else(|| {
+                    let tree = &definition.file.tree;
+                    tree.annotation_of_default_or_assignment(definition.node_start_position())
+                        .map(|annotation| annotation.as_code().to_string())
+                });
+                return Some(vec![format!("{name}: {}", annotation?)]);
+            };
+            let Scope::Class(defining_class) = func.parent_scope() else {
+                return None;
+            };
+            let defining_class = Class::with_self_generics(
+                db,
+                ClassNodeRef::new(definition.file, defining_class.index()),
+            );
+            let function = Function::new(
+                NodeRef::new(definition.file, func.index()),
+                Some(defining_class),
+            );
+            let kind = function.kind(i_s);
+            let mut lines = vec![];
+            let mut params = vec![];
+            match &kind {
+                FunctionKind::Function { .. } => params.push("self".to_string()),
+                FunctionKind::Property { .. } => lines.push("@property".to_string()),
+                FunctionKind::Classmethod { .. } => {
+                    lines.push("@classmethod".to_string());
+                    params.push("cls".to_string())
+                }
+                FunctionKind::Staticmethod => lines.push("@staticmethod".to_string()),
+            }
+            let signature = match (&kind, &t) {
+                (FunctionKind::Property { .. }, _) => Some(match annotation(&t) {
+                    Some(annotation) => format!("def {name}(self) -> {annotation}:"),
+                    None => format!("def {name}(self):"),
+                }),
+                (_, Type::Callable(callable)) => format_signature(
+                    db,
+                    name,
+                    params,
+                    callable,
+                    func.parent().is_async(),
+                    &annotation,
+                ),
+                _ => None,
+            };
+            // Overloads and unusual parameters are copied from the definition.
+            lines.push(signature.unwrap_or_else(|| {
+                let code = definition.file.tree.code();
+                let (params, return_annotation) = (func.params(), func.return_annotation());
+                let end = return_annotation.map_or(params.end(), |r| r.end());
+                let async_ = if func.parent().is_async() {
+                    "async "
+                } else {
+                    ""
+                };
+                format!(
+                    "{async_}def {name}{}:",
+                    &code[params.start() as usize..end as usize]
+                )
+            }));
+            lines.push("    raise NotImplementedError".to_string());
+            Some(lines)
+        })
+        .collect()
+}
+
+fn format_signature(
+    db: &Database,
+    name: &str,
+    mut params: Vec<String>,
+    callable: &CallableContent,
+    is_async: bool,
+    annotation: &impl Fn(&Type) -> Option<String>,
+) -> Option<String> {
+    let return_type = if is_async {
+        // The return type of an async function is wrapped in a Coroutine.
+        match &callable.return_type {
+            Type::Class(c) if c.link == db.python_state.coroutine_link() => {
+                c.class(db).nth_type_argument(db, 2)
+            }
+            _ => return None,
+        }
+    } else {
+        callable.return_type.clone()
+    };
+    let def = if is_async { "async def" } else { "def" };
+    let CallableParams::Simple(callable_params) = &callable.params else {
+        params.push("*args".to_string());
+        params.push("**kwargs".to_string());
+        return Some(format!("{def} {name}({}):", params.join(", ")));
+    };
+    let mut had_positional_only = false;
+    let mut had_star = false;
+    for (i, param) in callable_params.iter().enumerate() {
+        let param_name = match &param.name {
+            Some(param_name) => param_name.as_str(db).to_string(),
+            None => format!("arg{i}"),
+        };
+        let (prefix, t) = match &param.type_ {
+            ParamType::PositionalOnly(t) => {
+                had_positional_only = true;
+                ("", t)
+            }
+            ParamType::PositionalOrKeyword(t) | ParamType::KeywordOnly(t) => ("", t),
+            ParamType::Star(StarParamType::ArbitraryLen(t)) => ("*", t),
+            ParamType::StarStar(StarStarParamType::ValueType(t)) => ("**", t),
+            // ParamSpecs, unpacked tuples and TypedDicts are rare enough to just copy the
+            // definition.
+            _ => return None,
+        };
+        if had_positional_only && !matches!(param.type_, ParamType::PositionalOnly(_)) {
+            had_positional_only = false;
+            params.push("/".to_string())
+        }
+        if matches!(param.type_, ParamType::KeywordOnly(_)) && !had_star {
+            params.push("*".to_string())
+        }
+        had_star |= matches!(param.type_, ParamType::Star(_) | ParamType::KeywordOnly(_));
+        let mut formatted = match annotation(t) {
+            Some(annotation) => format!("{prefix}{param_name}: {annotation}"),
+            None => format!("{prefix}{param_name}"),
+        };
+        if param.has_default {
+            formatted += " = ...";
+        }
+        params.push(formatted)
+    }
+    if had_positional_only {
+        params.push("/".to_string())
+    }
+    let params = params.join(", ");
+    Some(match annotation(&return_type) {
+        Some(return_annotation) => format!("{def} {name}({params}) -> {return_annotation}:"),
+        None if return_type.is_any() => format!("{def} {name}({params}):"),
+        None => return None,
+    })
+}
+
+fn insert_members<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    class: ClassDef,
+    title: String,
+    members: Vec<Vec<String>>,
+) -> Option<CodeAction<'db>> {
+    let (insertion_index, indentation) = file.tree.class_member_insertion_point(class)?;
+    let code = file.code();
+    let newline = if code.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut replacement = String::new();
+    if !code[..insertion_index as usize].ends_with('\n') {
+        replacement += newline;
+    }
+    for lines in members {
+        replacement += newline;
+        for line in lines {
+            replacement += indentation;
+            replacement += &line;
+            replacement += newline;
+        }
+    }
+    let position = file.byte_to_position_infos(db, insertion_index);
+    Some(CodeAction {
+        title,
+        start_of_change: position,
+        end_of_change: position,
+        replacement,
+        additional_changes: vec![],
+    })
+}
+
+fn class_in_file<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    name: &str,
+) -> Option<ClassNodeRef<'db>> {
+    let class_ref = resolve_class(db, file, name)?;
+    (class_ref.file_index() == file.file_index).then_some(class_ref)
+}
+
+/// Resolves a class from the name a diagnostic prints for it, e.g. `Foo` or `Foo[int]`.
+fn resolve_class<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    name: &str,
+) -> Option<ClassNodeRef<'db>> {
+    let name = name.split('[').next().unwrap();
+    with_i_s_non_self(db, file, Scope::Module, |i_s| {
+        let inferred = file.lookup(db, |_| false, name).into_maybe_inferred()?;
+        match inferred.as_type(i_s) {
+            Type::Type(t) => match t.as_ref() {
+                Type::Class(c) => Some(ClassNodeRef::from_link(db, c.link)),
+                _ => None,
+            },
+            _ => None,
+        }
+    })
+}
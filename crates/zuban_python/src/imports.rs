@@ -18,7 +18,9 @@ const INIT_PYI: &str = "__init__.pyi";
 pub(crate) enum ImportResult {
     File(FileIndex),
     Namespace(Arc<Namespace>), // A Python Namespace package, i.e. a directory
-    PyTypedMissing,            // Files exist, but the py.typed marker is missing.
+    // Files exist, but the py.typed marker is missing or the import is skipped because of
+    // follow_imports.
+    PyTypedMissing,
 }
 
 impl ImportResult {
@@ -414,6 +416,40 @@ fn match_c(db: &Database, x: &str, y: &str, needs_exact_case: bool) -> bool {
     }
 }
 
+/// Returns the name of a module in the directories that only differs in case from `name`.
+/// Python compares module names exactly, so these modules are not imported, even if they are on
+/// a case-insensitive file system that would find them.
+pub(crate) fn module_with_other_case<'x>(
+    dirs: impl Iterator<Item = &'x Entries>,
+    name: &str,
+) -> Option<Box<str>> {
+    for dir in dirs {
+        for entry in &dir.iter() {
+            let (module_name, parent) = match entry {
+                DirectoryEntry::Directory(dir) => (dir.name.as_ref(), &dir.parent),
+                DirectoryEntry::File(file) => {
+                    let Some(module_name) = file
+                        .name
+                        .strip_suffix(".pyi")
+                        .or_else(|| file.name.strip_suffix(".py"))
+                    else {
+                        continue;
+                    };
+                    (module_name, &file.parent)
+                }
+                _ => continue,
+            };
+            if module_name != name
+                && module_name.eq_ignore_ascii_case(name)
+                && !parent.is_case_sensitive()
+            {
+                return Some(module_name.into());
+            }
+        }
+    }
+    None
+}
+
 fn ensure_django_stubs_workspace_and_return_newly_created(
     db: &Database,
 ) -> Option<impl Deref<Target = &Workspace>> {
@@ -37,7 +37,8 @@ use crate::{
     type_helpers::{
         BoundMethod, BoundMethodFunction, Callable, Class, FirstParamProperties, FuncLike as _,
         Function, Instance, LookupDetails, OverloadedFunction, TypeOrClass, execute_assert_type,
-        execute_cast, execute_isinstance, execute_issubclass, execute_reveal_type, execute_super,
+        execute_cast, execute_isinstance, execute_issubclass, execute_reveal_locals,
+        execute_reveal_type, execute_super,
     },
 };
 
@@ -2190,6 +2191,9 @@ impl<'db: 'slf, 'slf> Inferred {
                             Specific::RevealTypeFunction => {
                                 return execute_reveal_type(i_s, args, result_context);
                             }
+                            Specific::RevealLocalsFunction => {
+                                return execute_reveal_locals(i_s, args);
+                            }
                             Specific::AssertTypeFunction => {
                                 return execute_assert_type(i_s, args, result_context);
                             }
@@ -3048,7 +3052,7 @@ pub fn specific_to_type<'db>(
         | Specific::TypingTypeAlias
         | Specific::TypingCallable => Cow::Owned(i_s.db.python_state.typing_special_form_type()),
         // TODO (low prio) this should return the cast overload/assert_type within typeshed
-        Specific::TypingCast | Specific::AssertTypeFunction => {
+        Specific::TypingCast | Specific::AssertTypeFunction | Specific::RevealLocalsFunction => {
             Cow::Owned(i_s.db.python_state.object_type())
         }
         Specific::RevealTypeFunction => Cow::Owned(i_s.db.python_state.reveal_type(i_s.db)),
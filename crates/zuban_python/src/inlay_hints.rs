@@ -17,11 +17,28 @@ use crate::{
     type_helpers::{FuncLike as _, Function},
 };
 
+/// Which kind of inlay hints should be generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InlayHintOptions {
+    pub variable_types: bool,
+    pub function_return_types: bool,
+}
+
+impl Default for InlayHintOptions {
+    fn default() -> Self {
+        Self {
+            variable_types: true,
+            function_return_types: true,
+        }
+    }
+}
+
 impl<'project> Document<'project> {
     pub fn inlay_hints(
         &self,
         start: InputPosition,
         end: InputPosition,
+        options: InlayHintOptions,
     ) -> anyhow::Result<impl Iterator<Item = InlayHint<'project>>> {
         let db = &self.project.db;
         let file = db.loaded_python_file(self.file_index);
@@ -31,90 +48,103 @@ impl<'project> Document<'project> {
         );
         let start = file.line_column_to_byte(start)?;
         let end = file.line_column_to_byte(end)?;
-        let result = file.ensure_calculated_diagnostics(db);
-        debug_assert!(result.is_ok());
+        file.ensure_calculated_diagnostics_unless_cancelled(db)?;
         Ok(file
             .tree
             .potential_inlay_hints(start.byte, end.byte)
-            .filter_map(|potential| match potential {
-                PotentialInlayHint::FunctionDef(f) => {
-                    if f.return_annotation().is_some()
-                        || matches!(f.name().as_code(), "__init__" | "__init_subclass__")
-                    {
-                        return None;
-                    }
-                    let func = Function::new_with_unknown_parent(db, NodeRef::new(file, f.index()));
-                    let mut t = func.inferred_return_type(&InferenceState::new(db, file));
-                    if let Some(new_t) = t.replace_type_var_likes(db, &mut |usage| {
-                        if usage.as_type_var_like().is_untyped() {
-                            Some(usage.as_any_generic_item())
-                        } else {
-                            None
-                        }
-                    }) {
-                        t = Cow::Owned(new_t);
-                    }
-                    if t.is_any() {
-                        return None;
-                    }
-                    let type_ = t.into_owned();
-                    Some(InlayHint {
-                        db,
-                        type_,
-                        kind: InlayHintKind::TYPE,
-                        position: file.byte_to_position_infos(db, f.params().end()),
-                        label_kind: LabelKind::FunctionReturnAnnotation,
-                    })
+            // Callers are expected to check the cancellation token again, since the result is
+            // incomplete in that case.
+            .take_while(|_| !db.cancellation.is_cancelled())
+            .filter_map(move |potential| inlay_hint(db, file, potential, options)))
+    }
+}
+
+/// The hint for a potential inlay hint, if there is a type that is worth showing.
+pub(crate) fn inlay_hint<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    potential: PotentialInlayHint,
+    options: InlayHintOptions,
+) -> Option<InlayHint<'db>> {
+    match potential {
+        PotentialInlayHint::FunctionDef(f) => {
+            if !options.function_return_types
+                || f.return_annotation().is_some()
+                || matches!(f.name().as_code(), "__init__" | "__init_subclass__")
+            {
+                return None;
+            }
+            let func = Function::new_with_unknown_parent(db, NodeRef::new(file, f.index()));
+            let mut t = func.inferred_return_type(&InferenceState::new(db, file));
+            if let Some(new_t) = t.replace_type_var_likes(db, &mut |usage| {
+                if usage.as_type_var_like().is_untyped() {
+                    Some(usage.as_any_generic_item())
+                } else {
+                    None
                 }
-                PotentialInlayHint::Assignment(assignment) => match assignment.unpack() {
-                    AssignmentContent::Normal(mut targets, right_side) => {
-                        let target = targets.next().unwrap();
-                        if targets.next().is_some() {
-                            return None;
-                        }
-                        let (Target::Name(name_def) | Target::NameExpression(_, name_def)) = target
-                        else {
-                            return None;
-                        };
-                        let name_def_ref = NodeRef::new(file, name_def.index());
-                        let i_s = &InferenceState::new_in_unknown_file(db);
-                        if assignment_type_node_ref(file, assignment)
-                            .point()
-                            .calculated()
-                        {
-                            // Type assignments like NamedTuple/Enum/TypedDict definitions should
-                            // never have an inlay hint, because they can never make sense.
-                            return None;
-                        }
-                        if name_def_ref
-                            .name_ref_of_name_def()
-                            .point()
-                            .maybe_calculated_and_specific()
-                            == Some(Specific::NameOfNameDef)
-                        {
-                            return None;
-                        }
-                        let inf = name_def_ref.maybe_inferred(i_s)?;
-                        let type_ = inf.as_type(i_s);
-                        if type_.is_any() {
-                            return None;
-                        }
-                        // Only allow relevant assignments. Literal/Enum/Class instantiation
-                        // assignments are not relevant and we therefore ignore them.
-                        if avoid_inline_hint(i_s, file, right_side) {
-                            return None;
-                        }
-                        Some(InlayHint {
-                            db,
-                            kind: InlayHintKind::TYPE,
-                            position: file.byte_to_position_infos(db, name_def.end()),
-                            type_,
-                            label_kind: LabelKind::NormalAnnotation,
-                        })
-                    }
-                    _ => None,
-                },
-            }))
+            }) {
+                t = Cow::Owned(new_t);
+            }
+            if t.is_any() {
+                return None;
+            }
+            let type_ = t.into_owned();
+            Some(InlayHint {
+                db,
+                type_,
+                kind: InlayHintKind::TYPE,
+                position: file.byte_to_position_infos(db, f.params().end()),
+                label_kind: LabelKind::FunctionReturnAnnotation,
+            })
+        }
+        PotentialInlayHint::Assignment(_) if !options.variable_types => None,
+        PotentialInlayHint::Assignment(assignment) => match assignment.unpack() {
+            AssignmentContent::Normal(mut targets, right_side) => {
+                let target = targets.next().unwrap();
+                if targets.next().is_some() {
+                    return None;
+                }
+                let (Target::Name(name_def) | Target::NameExpression(_, name_def)) = target else {
+                    return None;
+                };
+                let name_def_ref = NodeRef::new(file, name_def.index());
+                let i_s = &InferenceState::new_in_unknown_file(db);
+                if assignment_type_node_ref(file, assignment)
+                    .point()
+                    .calculated()
+                {
+                    // Type assignments like NamedTuple/Enum/TypedDict definitions should
+                    // never have an inlay hint, because they can never make sense.
+                    return None;
+                }
+                if name_def_ref
+                    .name_ref_of_name_def()
+                    .point()
+                    .maybe_calculated_and_specific()
+                    == Some(Specific::NameOfNameDef)
+                {
+                    return None;
+                }
+                let inf = name_def_ref.maybe_inferred(i_s)?;
+                let type_ = inf.as_type(i_s);
+                if type_.is_any() {
+                    return None;
+                }
+                // Only allow relevant assignments. Literal/Enum/Class instantiation
+                // assignments are not relevant and we therefore ignore them.
+                if avoid_inline_hint(i_s, file, right_side) {
+                    return None;
+                }
+                Some(InlayHint {
+                    db,
+                    kind: InlayHintKind::TYPE,
+                    position: file.byte_to_position_infos(db, name_def.end()),
+                    type_,
+                    label_kind: LabelKind::NormalAnnotation,
+                })
+            }
+            _ => None,
+        },
     }
 }
 
@@ -181,7 +211,7 @@ enum LabelKind {
 
 pub struct InlayHint<'project> {
     db: &'project Database,
-    type_: Type,
+    pub(crate) type_: Type,
     pub kind: InlayHintKind,
     pub position: PositionInfos<'project>,
     label_kind: LabelKind,
@@ -189,10 +219,18 @@ pub struct InlayHint<'project> {
 
 impl InlayHint<'_> {
     pub fn label(&self) -> String {
-        let formatted = self.type_.format_short(self.db);
+        format!(
+            "{}{}",
+            self.label_prefix(),
+            self.type_.format_short(self.db)
+        )
+    }
+
+    /// What is written before the type to make it an annotation.
+    pub(crate) fn label_prefix(&self) -> &'static str {
         match self.label_kind {
-            LabelKind::NormalAnnotation => format!(": {formatted}"),
-            LabelKind::FunctionReturnAnnotation => format!(" -> {formatted}"),
+            LabelKind::NormalAnnotation => ": ",
+            LabelKind::FunctionReturnAnnotation => " -> ",
         }
     }
 }
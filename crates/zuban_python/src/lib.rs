@@ -1,8 +1,13 @@
 #![allow(clippy::nonminimal_bool)] // I don't like this rule
 #![allow(clippy::too_many_arguments)] // TODO For now this is easier, but probably enable again
 
+mod annotate;
 mod arguments;
 mod auto_imports;
+mod baseline;
+mod body_edit;
+mod cache;
+mod cancellation;
 mod code_actions;
 mod completion;
 mod database;
@@ -12,6 +17,7 @@ mod file;
 mod format_data;
 mod getitem;
 mod goto;
+mod implement_members;
 mod imports;
 mod inference_state;
 mod inferred;
@@ -22,23 +28,36 @@ mod match_;
 mod matching;
 mod name;
 mod node_ref;
+mod organize_imports;
+mod parallel;
 mod params;
+mod plugins;
 mod pytest;
 mod python_state;
+mod quick_fixes;
+mod refactoring;
 mod result_context;
 mod select_files;
 mod selection_ranges;
 mod semantic_tokens;
 mod signatures;
+mod stubgen;
 mod sys_path;
+mod timing;
 mod type_;
+mod type_coverage;
 mod type_helpers;
 mod utils;
 
-use std::{cell::OnceCell, path::Path};
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+};
 
-use ::utils::FastHashMap;
+use ::utils::{FastHashMap, FastHashSet};
+pub use annotate::AnnotateOptions;
 use anyhow::bail;
+pub use baseline::{Baseline, BaselineEntry};
 use completion::CompletionResolver;
 pub use completion::{Completion, CompletionItemKind};
 pub use goto::{GotoGoal, ReferencesGoal};
@@ -50,24 +69,39 @@ use rayon::prelude::*;
 pub use signatures::{CallSignature, CallSignatures, SignatureParam};
 use vfs::{AbsPath, FileIndex, LocalFS, PathWithScheme, VfsHandler};
 
+pub use cancellation::{CancellationToken, Cancelled};
 pub use code_actions::CodeAction;
 use config::{ProjectOptions, PythonVersion, Settings, TypeCheckerFlags};
-pub use database::RunCause;
 use database::{Database, PythonProject};
-pub use diagnostics::Severity;
-pub use documentation::DocumentationResult;
+pub use database::{LoadingProgress, RunCause};
+pub use diagnostics::{
+    Diagnostic, DiagnosticTag, RelatedInformation, Severity, error_code_documentation_url,
+};
+pub use documentation::{DocumentationResult, HoverContents};
 use file::File;
 use inference_state::InferenceState;
 use inferred::Inferred;
+pub use inlay_hints::InlayHintOptions;
 pub use lines::PositionInfos;
 use matching::invalidate_protocol_cache;
 pub use name::{Name, NameSymbol, ValueName};
+pub use plugins::{
+    PluginParam, PluginParamKind, PluginSignature, PluginType, TypeCheckerPlugin, register_plugin,
+};
+pub use refactoring::{Refactoring, TextChange};
 pub use semantic_tokens::{SemanticToken, SemanticTokenProperties};
+pub use stubgen::{GeneratedStub, StubgenOptions};
+pub use timing::{FileTimingStats, MemoryUsage, TimingStats};
+pub use type_coverage::{LinePrecision, ModulePrecision, TypeCoverage};
 
 use crate::{node_ref::NodeRef, select_files::all_typechecked_files};
 
 pub struct Project {
     db: Database,
+    // The error is only reported when calculating the diagnostics of the whole project.
+    baseline: Option<Result<Baseline, String>>,
+    // Reported like the baseline error.
+    unsupported_plugins_error: Option<String>,
 }
 
 impl Project {
@@ -76,18 +110,63 @@ impl Project {
         Self::new_internal(db)
     }
 
+    pub fn new_with_progress(
+        vfs: Box<dyn VfsHandler>,
+        options: ProjectOptions,
+        cause: RunCause,
+        mut on_progress: impl FnMut(LoadingProgress),
+    ) -> Self {
+        let db = Database::new_internal(vfs.into(), options, cause, None, &mut on_progress);
+        Self::new_internal(db)
+    }
+
     fn new_internal(db: Database) -> Self {
         tracing::debug!("Project settings: {:#?}", &db.project);
-        Self { db }
+        Self::with_baseline(db)
+    }
+
+    fn with_baseline(db: Database) -> Self {
+        let unsupported_plugins_error = plugins::unsupported_plugins_error(&db.project.settings);
+        if let Some(err) = &unsupported_plugins_error {
+            tracing::error!("{err}");
+        }
+        let mut project = Self {
+            db,
+            baseline: None,
+            unsupported_plugins_error,
+        };
+        project.load_baseline();
+        project
+    }
+
+    fn load_baseline(&mut self) {
+        self.baseline = self.db.project.settings.baseline.as_ref().map(|path| {
+            Baseline::load(self.db.vfs.handler.as_ref(), path).map_err(|err| {
+                tracing::error!("{err:#}");
+                format!("{err:#}")
+            })
+        });
+    }
+
+    fn baseline_dir(&self) -> Option<&Path> {
+        let path = self.db.project.settings.baseline.as_ref()?;
+        Path::new(path.as_ref().as_ref()).parent()
     }
 
     pub fn from_recovery(
         vfs: Box<dyn VfsHandler>,
         options: ProjectOptions,
         recovery: PanicRecovery,
+        mut on_progress: impl FnMut(LoadingProgress),
     ) -> Self {
-        let db = Database::from_recovery(vfs, options, recovery.run_cause, recovery.vfs);
-        Self { db }
+        let db = Database::from_recovery(
+            vfs,
+            options,
+            recovery.run_cause,
+            recovery.vfs,
+            &mut on_progress,
+        );
+        Self::with_baseline(db)
     }
 
     pub fn without_watcher(options: ProjectOptions, cause: RunCause) -> Self {
@@ -95,10 +174,90 @@ impl Project {
         Self::new_internal(db)
     }
 
+    /// Long running requests check this token and return a `Cancelled` error once it was
+    /// cancelled.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.db.cancellation = token
+    }
+
+    /// Evicts the analysis results of the least recently used files if the loaded files use more
+    /// memory than `memory_limit_mb`. Long-running servers should call this after every request.
+    /// Returns the number of evicted files.
+    pub fn enforce_memory_limit(&mut self) -> usize {
+        self.db.evict_least_recently_used_files()
+    }
+
     pub fn invalidate_path(&mut self, path: &AbsPath) {
+        if self
+            .db
+            .project
+            .settings
+            .baseline
+            .as_ref()
+            .is_some_and(|baseline| **baseline.as_ref() == *path)
+        {
+            self.load_baseline()
+        }
         self.db.invalidate_path(path)
     }
 
+    /// Invalidates a directory and everything within it, which is read again when needed.
+    pub fn rescan_path(&mut self, path: &AbsPath) {
+        self.db.rescan_path(path)
+    }
+
+    /// Checks the project against a different Python version from now on, e.g. to check it
+    /// against all of `python_versions` one after another.
+    pub fn set_python_version(&mut self, version: PythonVersion) {
+        self.db.set_python_version(version)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.db.project.settings
+    }
+
+    /// Applies the options of a config that was loaded again, because a config file changed.
+    /// Options that only change how the results are reported are applied directly. Other options
+    /// invalidate the analysis of all files, but only options that change how modules are found
+    /// need a new project.
+    pub fn apply_changed_options(&mut self, options: ProjectOptions) -> OptionsChange {
+        let project = &self.db.project;
+        let flags = options.flags.clone().finalize();
+        if options.settings == project.settings
+            && flags == project.flags
+            && options.overrides == project.overrides
+        {
+            return OptionsChange::Unchanged;
+        }
+        let without_output_options = |settings: &Settings| Settings {
+            baseline: None,
+            junit_xml: None,
+            report_dirs: vec![],
+            jobs: None,
+            incremental: false,
+            cache_dir: None,
+            memory_limit_mb: None,
+            unknown_options: vec![],
+            ..settings.clone()
+        };
+        let analysis_changed = flags != project.flags
+            || options.overrides != project.overrides
+            || without_output_options(&options.settings)
+                != without_output_options(&project.settings);
+        let baseline_changed = options.settings.baseline != project.settings.baseline;
+        if analysis_changed {
+            if !self.db.apply_options_without_reindex(options) {
+                return OptionsChange::NeedsReindex;
+            }
+        } else {
+            self.db.project.settings = options.settings;
+        }
+        if baseline_changed {
+            self.load_baseline()
+        }
+        OptionsChange::Applied
+    }
+
     pub fn into_panic_recovery(self) -> PanicRecovery {
         PanicRecovery {
             vfs: self.db.vfs.into_panic_recovery(),
@@ -168,6 +327,21 @@ impl Project {
         Some(self.db.loaded_python_file(file_index).code())
     }
 
+    /// Returns all in memory files with their code and the in memory file that was used as a
+    /// parent when storing them.
+    pub fn in_memory_files(
+        &self,
+    ) -> impl Iterator<Item = (&PathWithScheme, &str, Option<&PathWithScheme>)> {
+        self.db.vfs.in_memory_files().map(|(path, file_index)| {
+            let file = self.db.loaded_python_file(file_index);
+            let parent = file
+                .super_file
+                .filter(|super_file| super_file.offset.is_none())
+                .map(|super_file| self.db.vfs.file_path(super_file.file));
+            (path, file.code(), parent)
+        })
+    }
+
     pub fn delete_directory_of_in_memory_files(
         &mut self,
         path: &PathWithScheme,
@@ -196,35 +370,191 @@ impl Project {
                 .map(|g| g.as_str())
                 .collect::<Vec<_>>()
         );
+        if let Some(err) = &self.unsupported_plugins_error {
+            bail!("{err}");
+        }
         let mut checked_files = 0;
         let mut files_with_errors = 0;
 
-        let issues = select_files::diagnostics_for_relevant_files(&self.db, |file| {
+        let files = select_files::relevant_files(&self.db)?;
+        let cache = cache::IncrementalCache::new(&self.db, &files);
+        let keys = cache.as_ref().map(|cache| cache.keys(&self.db, &files));
+        // The keys of the files that were not restored from the cache and need to be checked.
+        let mut to_check = vec![];
+        let mut positions_to_check = vec![None; files.len()];
+        for (nth, file) in files.iter().enumerate() {
+            let key = keys.as_ref().map(|keys| keys[nth]);
+            if let (Some(cache), Some(key)) = (&cache, key)
+                && cache.restore(&self.db, file, key)
+            {
+                continue;
+            }
+            positions_to_check[nth] = Some(to_check.len());
+            to_check.push((*file, key));
+        }
+        if cache.is_some() {
+            tracing::info!(
+                "Restored {} of {} files from the cache",
+                files.len() - to_check.len(),
+                files.len()
+            );
+        }
+        let files_to_check: Vec<_> = to_check.iter().map(|(file, _)| *file).collect();
+        match parallel::worker_count(&self.db, files_to_check.len()) {
+            1 => (),
+            worker_count => parallel::check_in_parallel(&self.db, &files_to_check, worker_count),
+        }
+        let baseline = match &self.baseline {
+            Some(Ok(baseline)) => self.baseline_dir().map(|dir| (baseline, dir)),
+            Some(Err(err)) => bail!("{err}"),
+            None => None,
+        };
+        let mut stale_baseline_entries = vec![];
+        let mut checked_baseline_paths = FastHashSet::default();
+        let mut suppressed = vec![];
+        let mut issues = vec![];
+        let db = &self.db;
+        for (nth, file) in files.into_iter().enumerate() {
             checked_files += 1;
-            let mut issues = file.diagnostics(&self.db).into_vec();
-            issues.sort_by_key(|issue| issue.start_position().byte_position);
-            if !issues.is_empty() {
+            let mut file_issues = file.diagnostics(db).into_vec();
+            if let (Some(cache), Some(position)) = (&cache, positions_to_check[nth])
+                && let (_, Some(key)) = to_check[position]
+            {
+                cache.store(db, file, key)
+            }
+            suppressed.extend(file.suppressed_diagnostics(db));
+            if let Some((baseline, baseline_dir)) = baseline {
+                let path = baseline::relative_path(
+                    db.vfs.handler.as_ref(),
+                    baseline_dir,
+                    db.file_path(file.file_index),
+                );
+                stale_baseline_entries.extend(baseline.filter(
+                    baseline_dir,
+                    &mut file_issues,
+                    Some(&path),
+                ));
+                checked_baseline_paths.insert(path);
+            }
+            file_issues.sort_by_key(|issue| issue.start_position().byte_position);
+            if file_issues
+                .iter()
+                .any(|issue| issue.severity() == Severity::Error)
+            {
                 files_with_errors += 1;
             }
-            issues
-        })?;
+            issues.extend(file_issues);
+        }
+        // Files that are not part of a partial check (e.g. `zuban check foo.py`) might still have
+        // diagnostics, so their entries are only stale if the whole project was checked.
+        if let Some((baseline, _)) = baseline
+            && !self.db.project.settings.files_passed_on_command_line
+        {
+            stale_baseline_entries
+                .extend(baseline.entries_of_unchecked_files(&checked_baseline_paths));
+        }
         tracing::info!("Checked {checked_files} files ({files_with_errors} files had errors)");
         invalidate_protocol_cache();
         Ok(Diagnostics {
             checked_files,
             files_with_errors,
             issues,
+            suppressed,
+            stale_baseline_entries,
+            baseline_dir: baseline.map(|(_, dir)| dir.to_owned()),
             error_count: Default::default(),
         })
     }
 
+    /// The type coverage of all files that would be checked by [`Project::diagnostics`].
+    pub fn type_coverage(&mut self) -> anyhow::Result<Vec<(PathWithScheme, TypeCoverage)>> {
+        let db = &self.db;
+        let coverage = select_files::relevant_files(db)?
+            .into_iter()
+            .map(|file| {
+                let result = file.ensure_calculated_diagnostics(db);
+                debug_assert!(result.is_ok());
+                (
+                    file.file_path_with_scheme(db).clone(),
+                    type_coverage::file_type_coverage(db, file),
+                )
+            })
+            .collect();
+        invalidate_protocol_cache();
+        Ok(coverage)
+    }
+
+    /// How long parsing, name binding and checking took for every loaded file.
+    pub fn timing_stats(&self) -> TimingStats {
+        timing::timing_stats(&self.db)
+    }
+
+    /// The precision of every line in the files that would be checked by
+    /// [`Project::diagnostics`], sorted by module name.
+    pub fn line_precision(&mut self) -> anyhow::Result<Vec<ModulePrecision>> {
+        let db = &self.db;
+        let mut modules: Vec<_> = select_files::relevant_files(db)?
+            .into_iter()
+            .map(|file| {
+                let result = file.ensure_calculated_diagnostics(db);
+                debug_assert!(result.is_ok());
+                type_coverage::module_precision(db, file)
+            })
+            .collect();
+        invalidate_protocol_cache();
+        modules.sort_by(|a, b| a.module.cmp(&b.module));
+        Ok(modules)
+    }
+
+    /// The new code of all files that would be checked by [`Project::diagnostics`] and that get
+    /// annotations for the types of inlay hints.
+    pub fn annotate(
+        &mut self,
+        options: AnnotateOptions,
+    ) -> anyhow::Result<Vec<(PathWithScheme, String)>> {
+        let db = &self.db;
+        let changed = select_files::relevant_files(db)?
+            .into_iter()
+            .filter(|file| !file.is_stub())
+            .filter_map(|file| {
+                let result = file.ensure_calculated_diagnostics(db);
+                debug_assert!(result.is_ok());
+                let new_code = annotate::annotate_file(db, file, options)?;
+                Some((file.file_path_with_scheme(db).clone(), new_code))
+            })
+            .collect();
+        invalidate_protocol_cache();
+        Ok(changed)
+    }
+
+    /// Stubs for all files that would be checked by [`Project::diagnostics`], except for files
+    /// that are already stubs.
+    pub fn generate_stubs(
+        &mut self,
+        options: StubgenOptions,
+    ) -> anyhow::Result<Vec<GeneratedStub>> {
+        let db = &self.db;
+        let stubs = select_files::relevant_files(db)?
+            .into_iter()
+            .filter(|file| !file.is_stub())
+            .map(|file| {
+                // Type definitions like type aliases are only known after type checking.
+                let result = file.ensure_calculated_diagnostics(db);
+                debug_assert!(result.is_ok());
+                stubgen::generate_stub(db, file, options)
+            })
+            .collect();
+        invalidate_protocol_cache();
+        Ok(stubs)
+    }
+
     /// This function is mostly for tests and should therefore not be used for something
     /// stable. We would have to ensure first it works everywhere.
     /// It currently is for example a big issue that HashableRawStr used in the name binder is very
     /// unsafe and will lead to SEGFAULTS if the original project is not kept.
     pub fn try_to_reuse_project_resources_for_tests(&mut self, options: ProjectOptions) -> Self {
         let db = self.db.try_to_reuse_project_resources_for_tests(options);
-        Project { db }
+        Project::with_baseline(db)
     }
 
     pub fn document(&mut self, path: &PathWithScheme) -> Option<Document<'_>> {
@@ -249,6 +579,18 @@ impl std::fmt::Debug for Project {
     }
 }
 
+/// The result of `Project::apply_changed_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionsChange {
+    /// E.g. if a part of a pyproject.toml changed that is not about type checking.
+    Unchanged,
+    /// The options were applied to the project, e.g. a baseline or the flags of the type checker.
+    /// The diagnostics might need to be reported again.
+    Applied,
+    /// The options change how modules are found and the project needs to be created again.
+    NeedsReindex,
+}
+
 pub struct Document<'project> {
     project: &'project Project,
     file_index: FileIndex,
@@ -262,7 +604,43 @@ impl<'project> Document<'project> {
 
     pub fn diagnostics(&mut self) -> Box<[diagnostics::Diagnostic<'_>]> {
         let python_file = self.project.db.loaded_python_file(self.file_index);
-        python_file.diagnostics(&self.project.db)
+        let diagnostics = python_file.diagnostics(&self.project.db);
+        match self.baseline_and_path() {
+            Some((baseline, dir, path)) => {
+                let mut diagnostics = diagnostics.into_vec();
+                baseline.filter(dir, &mut diagnostics, Some(&path));
+                diagnostics.into_boxed_slice()
+            }
+            None => diagnostics,
+        }
+    }
+
+    /// The entries of the baseline for this file that do not match a diagnostic anymore.
+    pub fn stale_baseline_entries(&mut self) -> Vec<BaselineEntry> {
+        let Some((baseline, dir, path)) = self.baseline_and_path() else {
+            return vec![];
+        };
+        let python_file = self.project.db.loaded_python_file(self.file_index);
+        let mut diagnostics = python_file.diagnostics(&self.project.db).into_vec();
+        baseline.filter(dir, &mut diagnostics, Some(&path))
+    }
+
+    fn baseline_and_path(&self) -> Option<(&'project Baseline, &'project Path, String)> {
+        let Some(Ok(baseline)) = &self.project.baseline else {
+            return None;
+        };
+        let dir = self.project.baseline_dir()?;
+        let db = &self.project.db;
+        let path =
+            baseline::relative_path(db.vfs.handler.as_ref(), dir, db.file_path(self.file_index));
+        Some((baseline, dir, path))
+    }
+
+    /// Ranges of statements that are never executed. This is independent of --warn-unreachable
+    /// and mostly useful for graying out code in editors.
+    pub fn unreachable_ranges(&mut self) -> Vec<(PositionInfos<'_>, PositionInfos<'_>)> {
+        let python_file = self.project.db.loaded_python_file(self.file_index);
+        python_file.unreachable_ranges(&self.project.db)
     }
 
     fn positional_document(
@@ -546,6 +924,12 @@ pub struct Diagnostics<'a> {
     pub checked_files: usize,
     pub files_with_errors: usize,
     pub issues: Vec<diagnostics::Diagnostic<'a>>,
+    /// Diagnostics that were suppressed by `# type: ignore` comments.
+    pub suppressed: Vec<diagnostics::Diagnostic<'a>>,
+    /// Entries of the baseline that did not match a diagnostic anymore.
+    pub stale_baseline_entries: Vec<BaselineEntry>,
+    /// The paths of baseline entries are relative to this directory.
+    pub baseline_dir: Option<PathBuf>,
     error_count: OnceCell<usize>,
 }
 
@@ -565,7 +949,7 @@ impl Diagnostics<'_> {
         } else {
             format!(
                 "Found {error_count} error{e_s} in {fwe} file{fwe_s} (checked {checked} source file{checked_s})",
-                e_s = s_if_plural(self.issues.len()),
+                e_s = s_if_plural(error_count),
                 fwe = self.files_with_errors,
                 fwe_s = s_if_plural(self.files_with_errors),
                 checked = self.checked_files,
@@ -583,6 +967,15 @@ impl Diagnostics<'_> {
         })
     }
 
+    /// A baseline that contains all diagnostics. Paths are relative to the directory of the
+    /// baseline file.
+    pub fn to_baseline(&self, baseline_path: &AbsPath) -> Baseline {
+        let dir = Path::new(baseline_path.as_ref())
+            .parent()
+            .unwrap_or(Path::new(""));
+        Baseline::from_diagnostics(dir, &self.issues)
+    }
+
     pub fn sort_issues_by_kind(&mut self) {
         self.issues.sort_by_key(|issue| &issue.issue.kind)
     }
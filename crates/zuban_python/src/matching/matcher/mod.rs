@@ -754,6 +754,7 @@ impl<'a> Matcher<'a> {
                             i_s,
                             &format!("\"*{got_name}.args\""),
                             &format!("{expected_name}.args"),
+                            None,
                             of_function,
                         );
                         let mut kwarg = arg.clone();
@@ -765,6 +766,7 @@ impl<'a> Matcher<'a> {
                             i_s,
                             &format!("\"**{got_name}.kwargs\""),
                             &format!("{expected_name}.kwargs"),
+                            None,
                             of_function,
                         );
                     }
@@ -793,7 +795,7 @@ impl<'a> Matcher<'a> {
                             _ => format!("{param_spec_name}.args"),
                         };
                         let got = &format!("\"{}\"", got.format(&FormatData::new_short(i_s.db)));
-                        arg.add_argument_issue(i_s, got, &expected, of_function);
+                        arg.add_argument_issue(i_s, got, &expected, None, of_function);
                     }
                     SignatureMatch::False { similar: false }
                 }
@@ -688,9 +688,10 @@ fn calc_type_vars_with_callback<'db: 'a, 'a>(
     );
     if matches!(result.matches, SignatureMatch::False { .. }) {
         if on_type_error.is_some() {
-            add_issue(IssueKind::ArgumentTypeIssue(
-                "Incompatible callable argument with type vars".into(),
-            ));
+            add_issue(IssueKind::ArgumentTypeIssue {
+                message: "Incompatible callable argument with type vars".into(),
+                classes: None,
+            });
         }
         result.matches = SignatureMatch::False { similar: false };
     } else {
@@ -849,6 +850,7 @@ pub(crate) fn match_arguments_against_params<
                                 _ => format!("\"*{n}.args\""),
                             },
                             &expected.format_short(i_s.db),
+                            None,
                             &diagnostic_string,
                         );
                         matches &= Match::new_false();
@@ -3,7 +3,12 @@ mod generics;
 mod matcher;
 mod utils;
 
-use std::{borrow::Cow, cell::RefCell, collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::Arc,
+};
 
 pub(crate) use generic::Generic;
 pub(crate) use generics::Generics;
@@ -41,6 +46,9 @@ thread_local! {
 struct ProtocolCache {
     avoid_recursions: RefCell<Vec<(Type, Type)>>,
     cached: RefCell<HashMap<(Type, Type), Match>>,
+    // Hits and misses are counted for the timing statistics and not reset on invalidation.
+    hits: Cell<usize>,
+    misses: Cell<usize>,
 }
 
 pub fn invalidate_protocol_cache() {
@@ -50,6 +58,11 @@ pub fn invalidate_protocol_cache() {
     })
 }
 
+/// The hits and misses of the protocol cache of the current thread.
+pub(crate) fn protocol_cache_statistics() -> (usize, usize) {
+    PROTOCOL_CACHE.with(|cache| (cache.hits.get(), cache.misses.get()))
+}
+
 pub fn avoid_protocol_mismatch(
     db: &Database,
     t1: &Type,
@@ -100,8 +113,12 @@ pub fn avoid_protocol_mismatch(
                     t2.format_short(db),
                     already_known,
                 );
+                cache.hits.set(cache.hits.get() + 1);
                 return already_known.clone();
             }
+            if !had_type_var_matcher {
+                cache.misses.set(cache.misses.get() + 1);
+            }
             current.push(new_t);
             drop(current);
             debug!(
@@ -0,0 +1,399 @@
+//! The `source.organizeImports` code action for the imports at the start of a module.
+//!
+//! Unused imports are removed, `from` imports of the same module are merged and all imports are
+//! sorted into the sections `__future__`, standard library, third party and local imports like
+//! isort does it.
+
+use parsa_python_cst::{
+    CodeIndex, DottedAsNameContent, DottedImportName, DottedImportNameContent, ImportFrom,
+    ImportFromTargets, ImportName, Name, NameDef, SimpleNameParent, StmtLikeContent,
+};
+use utils::FastHashMap;
+
+use crate::{
+    CodeAction, Document,
+    auto_imports::{ImportKind, dotted_as_name_kind, import_from_kind},
+    database::{Database, PointKind},
+    file::{File as _, PythonFile, first_defined_name},
+};
+
+// The same line length as black and ruff use by default.
+const MAX_LINE_LENGTH: usize = 88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Future,
+    Kind(ImportKind),
+}
+
+struct PlainImport {
+    section: Section,
+    module: String,
+    as_name: Option<String>,
+}
+
+struct FromImport {
+    section: Section,
+    module: String,
+    has_star: bool,
+    names: Vec<(String, Option<String>)>,
+}
+
+#[derive(Default)]
+struct Imports {
+    plain: Vec<PlainImport>,
+    from: Vec<FromImport>,
+}
+
+impl<'project> Document<'project> {
+    /// Returns `None` if the imports are already organized or if they cannot be organized
+    /// without losing comments.
+    pub fn organize_imports(&self) -> anyhow::Result<Option<CodeAction<'_>>> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        let result = file.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        let Some((start, end, initial_imports)) = initial_imports(file) else {
+            return Ok(None);
+        };
+        let code = file.code();
+        let old = &code[start as usize..end as usize];
+        if old.contains(['#', '\\']) {
+            // Comments would get lost and line continuations are rare enough to not care.
+            return Ok(None);
+        }
+        // Stubs and __init__ files usually import names to make them available to others.
+        let remove_unused = !file.is_stub() && !file.file_entry(db).name.starts_with("__init__.");
+        let names: FastHashMap<CodeIndex, Name> = file
+            .tree
+            .filter_all_names(None)
+            .map(|name| (name.start(), name))
+            .collect();
+        let is_used = |name_def: NameDef| {
+            !remove_unused || is_used_outside_of(file, &names, (start, end), name_def)
+        };
+        let mut imports = Imports::default();
+        for import in initial_imports {
+            match import {
+                InitialImport::Name(import_name) => {
+                    imports.add_import_name(db, file, import_name, &is_used)
+                }
+                InitialImport::From(import_from) => {
+                    imports.add_import_from(db, file, import_from, &is_used)
+                }
+            }
+        }
+        let newline = if old.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut replacement = imports.format(newline);
+        if !old.ends_with('\n') {
+            replacement.truncate(replacement.trim_end().len());
+        }
+        if replacement == old {
+            return Ok(None);
+        }
+        Ok(Some(CodeAction {
+            title: "Organize imports".to_string(),
+            start_of_change: file.byte_to_position_infos(db, start),
+            end_of_change: file.byte_to_position_infos(db, end),
+            replacement,
+            additional_changes: vec![],
+        }))
+    }
+}
+
+enum InitialImport<'db> {
+    Name(ImportName<'db>),
+    From(ImportFrom<'db>),
+}
+
+/// The imports at the start of a module (after the docstring) and the range of their full lines,
+/// if there are no other statements on these lines.
+fn initial_imports(file: &PythonFile) -> Option<(CodeIndex, CodeIndex, Vec<InitialImport<'_>>)> {
+    let code = file.code();
+    let mut range: Option<(CodeIndex, CodeIndex)> = None;
+    let mut imports = vec![];
+    for (i, stmt) in file.tree.root().iter_stmt_likes().enumerate() {
+        let (start, end) = match stmt.node {
+            StmtLikeContent::ImportName(import_name) => {
+                imports.push(InitialImport::Name(import_name));
+                (import_name.start(), import_name.end())
+            }
+            StmtLikeContent::ImportFrom(import_from) => {
+                imports.push(InitialImport::From(import_from));
+                (import_from.start(), import_from.end())
+            }
+            StmtLikeContent::Newline => continue,
+            _ if i == 0 && stmt.node.maybe_string().is_some() => continue,
+            _ => break,
+        };
+        let in_between = match range {
+            Some((_, previous_end)) => &code[previous_end as usize..start as usize],
+            None => code[..start as usize].rsplit('\n').next().unwrap(),
+        };
+        if !in_between.chars().all(|c| c.is_whitespace() || c == ';') {
+            return None;
+        }
+        range = Some((range.map_or(start, |(start, _)| start), end));
+    }
+    let (start, end) = range?;
+    let rest_of_line = &code[end as usize..];
+    let line_end = rest_of_line
+        .find('\n')
+        .map_or(code.len(), |i| end as usize + i + 1);
+    if !code[end as usize..line_end].trim().is_empty() {
+        // Something like `import foo; x = 1`
+        return None;
+    }
+    Some((start, line_end as CodeIndex, imports))
+}
+
+impl Imports {
+    fn add_import_name(
+        &mut self,
+        db: &Database,
+        file: &PythonFile,
+        import_name: ImportName,
+        is_used: &impl Fn(NameDef) -> bool,
+    ) {
+        for dotted_as_name in import_name.iter_dotted_as_names() {
+            let (module, as_name, keep) = match dotted_as_name.unpack() {
+                DottedAsNameContent::Simple(name_def, rest) => {
+                    let module = match rest {
+                        Some(rest) => format!("{}.{}", name_def.as_code(), dotted_to_string(rest)),
+                        None => name_def.as_code().to_string(),
+                    };
+                    (module, None, is_used(name_def))
+                }
+                DottedAsNameContent::WithAs(dotted, name_def) => {
+                    let module = dotted_to_string(dotted);
+                    let as_name = name_def.as_code();
+                    // `import foo as foo` is an explicit re-export.
+                    let keep = is_used(name_def) || module == as_name;
+                    (module, Some(as_name.to_string()), keep)
+                }
+            };
+            if !keep
+                || self
+                    .plain
+                    .iter()
+                    .any(|p| p.module == module && p.as_name == as_name)
+            {
+                continue;
+            }
+            let section = Section::Kind(
+                dotted_as_name_kind(db, file, dotted_as_name).unwrap_or(ImportKind::ThirdParty),
+            );
+            self.plain.push(PlainImport {
+                section,
+                module,
+                as_name,
+            })
+        }
+    }
+
+    fn add_import_from(
+        &mut self,
+        db: &Database,
+        file: &PythonFile,
+        import_from: ImportFrom,
+        is_used: &impl Fn(NameDef) -> bool,
+    ) {
+        let (level, dotted) = import_from.level_with_dotted_name();
+        let mut module = ".".repeat(level);
+        if let Some(dotted) = dotted {
+            module += &dotted_to_string(dotted);
+        }
+        let section = if module == "__future__" {
+            Section::Future
+        } else if level > 0 {
+            Section::Kind(ImportKind::Project)
+        } else {
+            Section::Kind(import_from_kind(db, file, import_from).unwrap_or(ImportKind::ThirdParty))
+        };
+        let mut has_star = false;
+        let mut names = vec![];
+        match import_from.unpack_targets() {
+            ImportFromTargets::Star(_) => has_star = true,
+            ImportFromTargets::Iterator(targets) => {
+                for target in targets {
+                    let (name, name_def) = target.unpack();
+                    let has_as = name.index() != name_def.name_index();
+                    let as_name = name_def.as_code();
+                    // `from foo import bar as bar` is an explicit re-export.
+                    let is_reexport = has_as && name.as_code() == as_name;
+                    if section == Section::Future || is_used(name_def) || is_reexport {
+                        names.push((
+                            name.as_code().to_string(),
+                            has_as.then(|| as_name.to_string()),
+                        ))
+                    }
+                }
+            }
+        }
+        if !has_star && names.is_empty() {
+            return;
+        }
+        if let Some(existing) = self
+            .from
+            .iter_mut()
+            .find(|f| f.module == module && f.section == section)
+        {
+            existing.has_star |= has_star;
+            existing.names.extend(names);
+        } else {
+            self.from.push(FromImport {
+                section,
+                module,
+                has_star,
+                names,
+            })
+        }
+    }
+
+    fn format(mut self, newline: &str) -> String {
+        self.plain.sort_by(|a, b| {
+            (a.section, module_sort_key(&a.module), &a.as_name).cmp(&(
+                b.section,
+                module_sort_key(&b.module),
+                &b.as_name,
+            ))
+        });
+        self.from.sort_by(|a, b| {
+            (a.section, module_sort_key(&a.module)).cmp(&(b.section, module_sort_key(&b.module)))
+        });
+        let mut sections: Vec<(Section, Vec<String>)> = vec![];
+        let mut add_line = |section, line| match sections.last_mut() {
+            Some((last, lines)) if *last == section => lines.push(line),
+            _ => sections.push((section, vec![line])),
+        };
+        let mut plain = self.plain.into_iter().peekable();
+        let mut from = self.from.into_iter().peekable();
+        loop {
+            // Within a section all `import foo` lines come before the `from foo import bar` lines.
+            let take_plain = match (plain.peek(), from.peek()) {
+                (Some(p), Some(f)) => p.section <= f.section,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_plain {
+                let p = plain.next().unwrap();
+                let line = match p.as_name {
+                    Some(as_name) => format!("import {} as {as_name}", p.module),
+                    None => format!("import {}", p.module),
+                };
+                add_line(p.section, line)
+            } else {
+                let f = from.next().unwrap();
+                if f.has_star {
+                    add_line(f.section, format!("from {} import *", f.module))
+                }
+                if !f.names.is_empty() {
+                    add_line(f.section, format_from_import(&f.module, f.names, newline))
+                }
+            }
+        }
+        let mut result = String::new();
+        for (i, (_, lines)) in sections.into_iter().enumerate() {
+            if i > 0 {
+                result += newline;
+            }
+            for line in lines {
+                result += &line;
+                result += newline;
+            }
+        }
+        result
+    }
+}
+
+fn format_from_import(
+    module: &str,
+    mut names: Vec<(String, Option<String>)>,
+    newline: &str,
+) -> String {
+    names.sort_by(|(a, a_as), (b, b_as)| (name_sort_key(a), a_as).cmp(&(name_sort_key(b), b_as)));
+    names.dedup();
+    let names: Vec<_> = names
+        .into_iter()
+        .map(|(name, as_name)| match as_name {
+            Some(as_name) => format!("{name} as {as_name}"),
+            None => name,
+        })
+        .collect();
+    let line = format!("from {module} import {}", names.join(", "));
+    if line.len() <= MAX_LINE_LENGTH {
+        return line;
+    }
+    let mut line = format!("from {module} import ({newline}");
+    for name in names {
+        line += &format!("    {name},{newline}");
+    }
+    line.push(')');
+    line
+}
+
+fn module_sort_key(module: &str) -> (String, &str) {
+    (module.to_lowercase(), module)
+}
+
+/// Like isort: constants first, then classes and then everything else.
+fn name_sort_key(name: &str) -> (u8, String, &str) {
+    let kind = if name.len() > 1 && name.chars().all(|c| !c.is_lowercase()) {
+        0
+    } else if name.starts_with(|c: char| c.is_uppercase()) {
+        1
+    } else {
+        2
+    };
+    (kind, name.to_lowercase(), name)
+}
+
+fn dotted_to_string(dotted: DottedImportName) -> String {
+    match dotted.unpack() {
+        DottedImportNameContent::Name(name) => name.as_code().to_string(),
+        DottedImportNameContent::DottedName(dotted, name) => {
+            format!("{}.{}", dotted_to_string(dotted), name.as_code())
+        }
+    }
+}
+
+/// An import counts as used if a name outside of the imports refers to it according to the name
+/// binder. Attributes, keyword arguments and local names that shadow the import are therefore no
+/// usages. Names in strings and comments can only be found by their text, which covers string
+/// annotations, type comments and `__all__`.
+fn is_used_outside_of(
+    file: &PythonFile,
+    names: &FastHashMap<CodeIndex, Name>,
+    (start, end): (CodeIndex, CodeIndex),
+    name_def: NameDef,
+) -> bool {
+    let definition = first_defined_name(file, name_def.name_index());
+    let code = file.code();
+    let word = name_def.as_code();
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+    code.match_indices(word).any(|(i, _)| {
+        let position = i as CodeIndex;
+        if (start..end).contains(&position)
+            || code[..i].ends_with(is_identifier_char)
+            || code[i + word.len()..].starts_with(is_identifier_char)
+        {
+            return false;
+        }
+        let Some(name) = names.get(&position) else {
+            // A string or a comment
+            return true;
+        };
+        if !matches!(name.simple_parent(), SimpleNameParent::Atom) {
+            return false;
+        }
+        let p = file.points.get(name.index());
+        if !p.calculated() {
+            // The name binder does not resolve all names, e.g. in the presence of star imports.
+            return true;
+        }
+        p.kind() == PointKind::Redirect
+            && p.file_index() == file.file_index
+            && first_defined_name(file, p.node_index()) == definition
+    })
+}
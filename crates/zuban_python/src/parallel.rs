@@ -0,0 +1,119 @@
+//! Checks the files of a project on multiple threads, see `zuban check --jobs`.
+//!
+//! Inference writes lazily to the points of all files, including the ones of typeshed and of
+//! other modules. Different threads would therefore race for the same points and could see each
+//! other's unfinished calculations as cycles. Every thread thus checks its share of the files in
+//! a temporary database of its own, which reads files with the handler of the main database and
+//! uses its options. The diagnostics of the checked files are then moved to the main database,
+//! like the ones of the incremental cache, and the temporary databases are dropped.
+
+use std::{num::NonZeroUsize, thread, time::Duration};
+
+use config::ProjectOptions;
+use vfs::PathWithScheme;
+
+use crate::{cache::IssueSnapshot, database::Database, file::PythonFile};
+
+/// Every thread needs to infer parts of typeshed again, which is only worth it for enough files.
+const MIN_FILES_PER_WORKER: usize = 50;
+
+/// Inference recurses deeply for complex code, so the threads get the stack size of a typical
+/// main thread.
+const WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+pub(crate) fn worker_count(db: &Database, files: usize) -> usize {
+    // Projects that watch files are checked again incrementally (e.g. `zuban check --watch`),
+    // which needs all results of inference in the main database.
+    if db.vfs.handler.notify_receiver().is_some() {
+        return 1;
+    }
+    let jobs = db.project.settings.jobs.map_or(1, NonZeroUsize::get);
+    jobs.min(files / MIN_FILES_PER_WORKER).max(1)
+}
+
+/// Checks the files in `worker_count` temporary databases and adds their diagnostics to the files
+/// of the main database. Files whose diagnostics cannot be moved (see [`IssueSnapshot`]) or that
+/// could not be found by a worker, e.g. because they were deleted in the meantime, are left
+/// unchecked and are checked in the main database once their diagnostics are requested.
+pub(crate) fn check_in_parallel(db: &Database, files: &[&PythonFile], worker_count: usize) {
+    // Distributes the files by size, the biggest first, to the worker with the least code.
+    let mut by_size: Vec<_> = (0..files.len()).collect();
+    by_size.sort_by_key(|&i| std::cmp::Reverse(files[i].tree.code().len()));
+    let mut partitions = vec![(0, vec![]); worker_count];
+    for nth in by_size {
+        let (size, partition) = partitions.iter_mut().min_by_key(|(size, _)| *size).unwrap();
+        *size += files[nth].tree.code().len();
+        partition.push((nth, files[nth].file_path_with_scheme(db).clone()));
+    }
+    tracing::info!("Checking {} files on {worker_count} threads", files.len());
+
+    let results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = partitions
+            .into_iter()
+            .map(|(_, partition)| {
+                thread::Builder::new()
+                    .name("zuban:check".into())
+                    .stack_size(WORKER_STACK_SIZE)
+                    .spawn_scoped(scope, move || check_partition(db, partition))
+                    .expect("Failed to spawn a checking thread")
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| match handle.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    });
+
+    let mut moved = 0;
+    for (nth, snapshot, check_duration) in results {
+        let (issues, suppressed) = snapshot.into_issues();
+        files[nth].issues.restore_from_cache(issues, suppressed);
+        if let Some(check_duration) = check_duration {
+            files[nth].timings.check.get_or_init(|| check_duration);
+        }
+        moved += 1;
+    }
+    if moved < files.len() {
+        tracing::info!(
+            "{} files checked in parallel need to be checked again",
+            files.len() - moved
+        );
+    }
+}
+
+fn check_partition(
+    db: &Database,
+    partition: Vec<(usize, PathWithScheme)>,
+) -> Vec<(usize, IssueSnapshot, Option<Duration>)> {
+    let options = ProjectOptions {
+        settings: db.project.settings.clone(),
+        flags: db.project.flags.clone().into_unfinalized(),
+        overrides: db.project.overrides.clone(),
+    };
+    let mut worker_db = Database::new_internal(
+        db.vfs.handler.clone(),
+        options,
+        db.run_cause,
+        None,
+        &mut |_| (),
+    );
+    worker_db.cancellation = db.cancellation.clone();
+    for (path, file_index) in db.vfs.in_memory_files() {
+        let code = db.loaded_python_file(file_index).tree.code();
+        worker_db.store_in_memory_file(path.clone(), code.into(), None);
+    }
+    partition
+        .into_iter()
+        .filter_map(|(nth, path)| {
+            let file_index = worker_db.file_by_file_path(&path)?;
+            let file = worker_db.loaded_python_file(file_index);
+            let result = file.ensure_calculated_diagnostics(&worker_db);
+            debug_assert!(result.is_ok());
+            let snapshot = IssueSnapshot::new(&worker_db, file, |_| true)?;
+            Some((nth, snapshot, file.timings.check.get().copied()))
+        })
+        .collect()
+}
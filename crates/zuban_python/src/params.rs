@@ -1178,7 +1178,7 @@ where
                 if !matches!(self.unused_unpack_typed_dict, UnpackTypedDictState::Used) {
                     for (i, unused) in self.unused_keyword_arguments.iter().enumerate() {
                         if let Some(key) = unused.keyword_name(self.db)
-                            && let Some(entry) = td.find_entry(self.db, key)
+                            && let Some(entry) = td.find_kwarg_entry(self.db, key)
                         {
                             self.unused_unpack_typed_dict =
                                 UnpackTypedDictState::CheckingUnusedKwArgs;
@@ -1195,7 +1195,7 @@ where
                 }
                 while let Some(argument) = self.next_arg() {
                     if let Some(key) = argument.keyword_name(self.db) {
-                        if let Some(entry) = td.find_entry(self.db, key) {
+                        if let Some(entry) = td.find_kwarg_entry(self.db, key) {
                             self.unused_unpack_typed_dict = UnpackTypedDictState::Used;
                             return Some(InferrableParam {
                                 param,
@@ -0,0 +1,157 @@
+//! Mirrors the parts of the django-stubs Mypy plugin (`mypy_django_plugin.main`) that synthesize
+//! attributes. The generics of fields and managers are filled in without the plugin, see
+//! `Class::fill_django_default_generics`.
+
+use std::sync::Arc;
+
+use parsa_python_cst::{
+    Argument, ArgumentsDetails, AssignmentContent, AssignmentRightSide, ExpressionContent,
+    ExpressionPart, NodeIndex, PrimaryContent, StarExpressionContent,
+};
+
+use super::{Plugin, lookup_class, lookup_module_attribute};
+use crate::{
+    file::PythonFile,
+    inference_state::InferenceState,
+    node_ref::NodeRef,
+    type_::{ClassGenerics, GenericItem, GenericsList, Type},
+    type_helpers::Class,
+};
+
+pub(super) struct DjangoPlugin;
+
+impl Plugin for DjangoPlugin {
+    fn names(&self) -> &'static [&'static str] {
+        &["mypy_django_plugin.main"]
+    }
+
+    fn class_attribute(&self, i_s: &InferenceState, class: Class, name: &str) -> Option<Type> {
+        let db = i_s.db;
+        if !class.has_django_stubs_base_class(db) {
+            return None;
+        }
+        if class.qualified_name(db) == "django.conf.LazySettings" {
+            return settings_attribute(i_s, name);
+        }
+        if !class.is_django_model(db) {
+            return None;
+        }
+        if name == "objects" {
+            // Older versions of django-stubs do not define the default manager.
+            let manager = lookup_class(db, "django.db.models.manager.Manager")?;
+            return Some(Type::new_class(
+                manager.node_ref.as_link(),
+                ClassGenerics::List(GenericsList::new_generics(Arc::new([
+                    GenericItem::TypeArg(class.as_type(db)),
+                ]))),
+            ));
+        }
+        foreign_key_id(i_s, class, name.strip_suffix("_id")?)
+    }
+}
+
+/// Settings are looked up in the configured `django_settings_module` and then in Django's
+/// defaults.
+fn settings_attribute(i_s: &InferenceState, name: &str) -> Option<Type> {
+    let db = i_s.db;
+    db.project
+        .settings
+        .django_settings_module
+        .as_deref()
+        .and_then(|module| lookup_module_attribute(db, module, name))
+        .or_else(|| lookup_module_attribute(db, "django.conf.global_settings", name))
+}
+
+/// Django adds an attribute with the primary key of the referenced model for every foreign key,
+/// e.g. `author_id` for `author = ForeignKey(Author, ...)`.
+fn foreign_key_id(i_s: &InferenceState, class: Class, field_name: &str) -> Option<Type> {
+    let db = i_s.db;
+    for (_, base) in class.mro(db) {
+        let Some(base) = base.maybe_class() else {
+            continue;
+        };
+        let Some(inferred) = base.lookup_symbol(i_s, field_name).into_maybe_inferred() else {
+            continue;
+        };
+        let field_type = inferred.as_cow_type(i_s);
+        let field = field_type.maybe_class(db)?;
+        if !field.has_django_stubs_base_class(db)
+            || !["ForeignKey", "OneToOneField"].contains(&field.name())
+        {
+            return None;
+        }
+        // The type argument is the referenced model, which is optional for nullable fields.
+        let referenced = field.nth_type_argument(db, 1);
+        let (model, is_nullable) = match &referenced {
+            Type::Union(u) => (
+                u.iter().find_map(|t| t.maybe_class(db)),
+                u.iter().any(|t| matches!(t, Type::None)),
+            ),
+            t => (t.maybe_class(db), false),
+        };
+        let mut t = match model {
+            Some(model) => primary_key_type(i_s, model),
+            None => db.python_state.int_type(),
+        };
+        if is_nullable {
+            t.union_in_place(Type::None)
+        }
+        return Some(t);
+    }
+    None
+}
+
+/// The primary key of a model is the field defined with `primary_key=True`. Otherwise Django
+/// adds an `id` field, which is an integer by default.
+fn primary_key_type(i_s: &InferenceState, model: Class) -> Type {
+    let db = i_s.db;
+    for (_, base) in model.mro(db) {
+        let Some(base) = base.maybe_class() else {
+            continue;
+        };
+        if base.node_ref.file.is_from_django(db) {
+            break;
+        }
+        for (name, &name_index) in base.class_storage.class_symbol_table.iter() {
+            if !is_primary_key_definition(base.node_ref.file, name_index) {
+                continue;
+            }
+            let Some(inferred) = base.lookup_symbol(i_s, name).into_maybe_inferred() else {
+                continue;
+            };
+            if let Some(field) = inferred.as_cow_type(i_s).maybe_class(db) {
+                return field.nth_type_argument(db, 1);
+            }
+        }
+    }
+    db.python_state.int_type()
+}
+
+/// Whether a name is defined like `id = models.UUIDField(primary_key=True)`.
+fn is_primary_key_definition(file: &PythonFile, name_index: NodeIndex) -> bool {
+    let name = NodeRef::new(file, name_index).expect_name();
+    let right_side = match name.maybe_assignment_definition_name().map(|a| a.unpack()) {
+        Some(AssignmentContent::Normal(_, right_side))
+        | Some(AssignmentContent::WithAnnotation(_, _, Some(right_side))) => right_side,
+        _ => return false,
+    };
+    let AssignmentRightSide::StarExpressions(star_exprs) = right_side else {
+        return false;
+    };
+    let StarExpressionContent::Expression(expr) = star_exprs.unpack() else {
+        return false;
+    };
+    let ExpressionContent::ExpressionPart(ExpressionPart::Primary(primary)) = expr.unpack() else {
+        return false;
+    };
+    let PrimaryContent::Execution(ArgumentsDetails::Node(args)) = primary.second() else {
+        return false;
+    };
+    args.iter().any(|arg| match arg {
+        Argument::Keyword(kwarg) => {
+            let (key, value) = kwarg.unpack();
+            key.as_str() == "primary_key" && value.maybe_simple_bool() == Some(true)
+        }
+        _ => false,
+    })
+}
@@ -0,0 +1,226 @@
+//! The public interface for plugins that are not part of Zuban. Programs that embed the type
+//! checker implement [`TypeCheckerPlugin`] and call [`register_plugin`] before creating a
+//! project. Like the builtin plugins, a registered plugin is only used if one of its names is
+//! listed in the `plugins` setting of the project options.
+//!
+//! The hooks only see fully qualified names and describe types with [`PluginType`], so they don't
+//! depend on the internal types of the type checker. Types that cannot be resolved, e.g. because
+//! a class does not exist, are ignored like a hook that returns `None`.
+
+use std::sync::{Arc, RwLock};
+
+use parsa_python_cst::ArgumentsDetails;
+
+use super::{Plugin, lookup_class};
+use crate::{
+    database::Database,
+    inference_state::InferenceState,
+    type_::{
+        AnyCause, CallableContent, CallableParam, CallableParams, ClassGenerics, DbString,
+        GenericItem, GenericsList, ParamType, StarParamType, StarStarParamType, Type,
+    },
+    type_helpers::Class,
+};
+
+static REGISTERED_PLUGINS: RwLock<Vec<&'static dyn Plugin>> = RwLock::new(Vec::new());
+
+/// Hooks that customize the type checker for a framework, similar to Mypy's plugins. All hooks
+/// are optional, the first enabled plugin that returns something wins.
+pub trait TypeCheckerPlugin: Send + Sync {
+    /// The names that enable this plugin in the `plugins` setting. There must be at least one.
+    fn names(&self) -> &'static [&'static str];
+
+    /// Replaces the signature of a function or method like `module.Class.method`, like Mypy's
+    /// `get_function_signature_hook`.
+    fn function_signature(&self, _fullname: &str) -> Option<PluginSignature> {
+        None
+    }
+
+    /// Synthesizes attributes that are not defined in a class or its bases, like Mypy's
+    /// `get_base_class_hook`. The type is used like the annotation of a class variable.
+    fn class_attribute(&self, _class_fullname: &str, _name: &str) -> Option<PluginType> {
+        None
+    }
+
+    /// Whether a decorator returns the decorated function unchanged, e.g. for untyped
+    /// decorators of frameworks that register the function somewhere.
+    fn keeps_decorated_signature(&self, _decorator_fullname: &str) -> bool {
+        false
+    }
+
+    /// Replaces the type of a module level alias or class variable when it is used in an
+    /// annotation, like Mypy's `get_type_analyze_hook`.
+    fn analyze_type(&self, _fullname: &str) -> Option<PluginType> {
+        None
+    }
+
+    /// Whether a method decorated with the decorator is implicitly a classmethod.
+    fn is_implicit_classmethod(&self, _decorator_fullname: &str) -> bool {
+        false
+    }
+}
+
+/// A type that is resolved by the type checker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginType {
+    Any,
+    None,
+    /// An instance of a class like `builtins.int` with its type arguments, e.g. `builtins.str`
+    /// for `builtins.list`.
+    Instance {
+        fullname: String,
+        type_args: Vec<PluginType>,
+    },
+    Union(Vec<PluginType>),
+}
+
+impl PluginType {
+    /// An instance of a class without type arguments.
+    pub fn instance(fullname: impl Into<String>) -> Self {
+        Self::Instance {
+            fullname: fullname.into(),
+            type_args: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginSignature {
+    pub params: Vec<PluginParam>,
+    pub return_type: PluginType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginParam {
+    pub name: String,
+    pub kind: PluginParamKind,
+    pub type_: PluginType,
+    pub has_default: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginParamKind {
+    PositionalOnly,
+    PositionalOrKeyword,
+    KeywordOnly,
+    /// `*args`, the type is the type of every argument.
+    Star,
+    /// `**kwargs`, the type is the type of every value.
+    StarStar,
+}
+
+/// Makes a plugin available to all projects that are created afterwards and enable it. Plugins
+/// live until the process ends.
+pub fn register_plugin(plugin: impl TypeCheckerPlugin + 'static) {
+    assert!(!plugin.names().is_empty(), "A plugin needs a name");
+    let plugin: &'static dyn Plugin = Box::leak(Box::new(External(plugin)));
+    REGISTERED_PLUGINS.write().unwrap().push(plugin)
+}
+
+pub(super) fn registered_plugins() -> Vec<&'static dyn Plugin> {
+    REGISTERED_PLUGINS.read().unwrap().clone()
+}
+
+struct External<P>(P);
+
+impl<P: TypeCheckerPlugin> Plugin for External<P> {
+    fn names(&self) -> &'static [&'static str] {
+        self.0.names()
+    }
+
+    fn function_signature(
+        &self,
+        i_s: &InferenceState,
+        fullname: &str,
+        original: &dyn Fn() -> CallableContent,
+    ) -> Option<CallableContent> {
+        let signature = self.0.function_signature(fullname)?;
+        let params = signature
+            .params
+            .iter()
+            .map(|param| to_callable_param(i_s.db, param))
+            .collect::<Option<Arc<[_]>>>()?;
+        let return_type = to_type(i_s.db, &signature.return_type)?;
+        let mut callable = original();
+        callable.params = CallableParams::new_simple(params);
+        callable.return_type = return_type;
+        Some(callable)
+    }
+
+    fn class_attribute(&self, i_s: &InferenceState, class: Class, name: &str) -> Option<Type> {
+        let t = self
+            .0
+            .class_attribute(&class.qualified_name(i_s.db), name)?;
+        to_type(i_s.db, &t)
+    }
+
+    fn decorated(
+        &self,
+        _i_s: &InferenceState,
+        decorator_fullname: &str,
+        decorated: &Type,
+    ) -> Option<Type> {
+        self.0
+            .keeps_decorated_signature(decorator_fullname)
+            .then(|| decorated.clone())
+    }
+
+    fn analyze_type(&self, i_s: &InferenceState, fullname: &str) -> Option<Type> {
+        to_type(i_s.db, &self.0.analyze_type(fullname)?)
+    }
+
+    fn is_implicit_classmethod(&self, decorator_fullname: &str, _args: ArgumentsDetails) -> bool {
+        self.0.is_implicit_classmethod(decorator_fullname)
+    }
+}
+
+fn to_type(db: &Database, t: &PluginType) -> Option<Type> {
+    Some(match t {
+        PluginType::Any => Type::Any(AnyCause::Explicit),
+        PluginType::None => Type::None,
+        PluginType::Instance {
+            fullname,
+            type_args,
+        } => {
+            let Some(class) = lookup_class(db, fullname) else {
+                tracing::warn!("A plugin used the class {fullname}, which does not exist");
+                return None;
+            };
+            if type_args.is_empty() {
+                class.as_type(db)
+            } else {
+                let type_args = type_args
+                    .iter()
+                    .map(|t| Some(GenericItem::TypeArg(to_type(db, t)?)))
+                    .collect::<Option<Arc<[_]>>>()?;
+                Type::new_class(
+                    class.node_ref.as_link(),
+                    ClassGenerics::List(GenericsList::new_generics(type_args)),
+                )
+            }
+        }
+        PluginType::Union(entries) => {
+            let mut entries = entries.iter();
+            let mut result = to_type(db, entries.next()?)?;
+            for t in entries {
+                result.union_in_place(to_type(db, t)?)
+            }
+            result
+        }
+    })
+}
+
+fn to_callable_param(db: &Database, param: &PluginParam) -> Option<CallableParam> {
+    let t = to_type(db, &param.type_)?;
+    let type_ = match param.kind {
+        PluginParamKind::PositionalOnly => ParamType::PositionalOnly(t),
+        PluginParamKind::PositionalOrKeyword => ParamType::PositionalOrKeyword(t),
+        PluginParamKind::KeywordOnly => ParamType::KeywordOnly(t),
+        PluginParamKind::Star => ParamType::Star(StarParamType::ArbitraryLen(t)),
+        PluginParamKind::StarStar => ParamType::StarStar(StarStarParamType::ValueType(t)),
+    };
+    let mut callable_param =
+        CallableParam::new(DbString::ArcStr(param.name.as_str().into()), type_);
+    callable_param.has_default = param.has_default;
+    Some(callable_param)
+}
@@ -0,0 +1,315 @@
+//! Plugins customize the type checker for frameworks whose behavior cannot be expressed with
+//! type annotations, similar to Mypy's plugins. They are enabled by name with the `plugins`
+//! setting, e.g. `plugins = numpy.typing.mypy_plugin`. Unknown plugin names are reported, see
+//! [`unsupported_plugins_error`].
+//!
+//! The builtin plugins in `BUILTIN_PLUGINS` work with the internal types of the type checker.
+//! Programs that embed Zuban can register their own plugins, see [`TypeCheckerPlugin`].
+//!
+//! All hooks are optional and receive fully qualified names like `module.Class.method`. The first
+//! enabled plugin that returns something wins.
+
+mod django;
+mod external;
+mod numpy;
+mod pydantic;
+
+use std::fmt;
+
+use config::{Settings, split_plugin_entry_point};
+
+use parsa_python_cst::{
+    ArgumentsDetails, Decorator, ExpressionContent, ExpressionPart, PrimaryContent,
+};
+
+use crate::{
+    database::Database,
+    file::PythonFile,
+    imports::{ImportResult, global_import},
+    inference_state::InferenceState,
+    type_::{CallableContent, CallableParam, Type},
+    type_helpers::Class,
+};
+
+pub use external::{
+    PluginParam, PluginParamKind, PluginSignature, PluginType, TypeCheckerPlugin, register_plugin,
+};
+
+const BUILTIN_PLUGINS: &[&dyn Plugin] = &[
+    &django::DjangoPlugin,
+    &numpy::NumpyPlugin,
+    &pydantic::PydanticPlugin,
+];
+
+pub(crate) trait Plugin: Sync {
+    /// The names that enable this plugin in the `plugins` setting.
+    fn names(&self) -> &'static [&'static str];
+
+    /// Changes the signature of a function or method, like Mypy's
+    /// `get_function_signature_hook`. The original signature is only calculated if needed.
+    fn function_signature(
+        &self,
+        _i_s: &InferenceState,
+        _fullname: &str,
+        _original: &dyn Fn() -> CallableContent,
+    ) -> Option<CallableContent> {
+        None
+    }
+
+    /// Synthesizes attributes that are not defined in a class or its bases, like Mypy's
+    /// `get_base_class_hook`. The type is used like the annotation of a class variable.
+    fn class_attribute(&self, _i_s: &InferenceState, _class: Class, _name: &str) -> Option<Type> {
+        None
+    }
+
+    /// Returns the type of a function after applying the decorator, instead of calling the
+    /// decorator.
+    fn decorated(
+        &self,
+        _i_s: &InferenceState,
+        _decorator_fullname: &str,
+        _decorated: &Type,
+    ) -> Option<Type> {
+        None
+    }
+
+    /// Replaces the type of a module level alias or class variable when it is used in an
+    /// annotation, like Mypy's `get_type_analyze_hook`.
+    fn analyze_type(&self, _i_s: &InferenceState, _fullname: &str) -> Option<Type> {
+        None
+    }
+
+    /// Changes the synthesized `__init__` parameters of a dataclass or a class that uses
+    /// `dataclass_transform`. The parameters of base classes were already changed.
+    fn dataclass_init_params(
+        &self,
+        _i_s: &InferenceState,
+        _class: Class,
+        _params: &mut Vec<CallableParam>,
+    ) {
+    }
+
+    /// Whether a method decorated with a call like `@validator("x")` is implicitly a
+    /// classmethod.
+    fn is_implicit_classmethod(&self, _decorator_fullname: &str, _args: ArgumentsDetails) -> bool {
+        false
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Plugins(Vec<&'static dyn Plugin>);
+
+fn available_plugins() -> impl Iterator<Item = &'static dyn Plugin> {
+    BUILTIN_PLUGINS
+        .iter()
+        .copied()
+        .chain(external::registered_plugins())
+}
+
+fn find_plugin(name: &str) -> Option<&'static dyn Plugin> {
+    let (location, _) = split_plugin_entry_point(name);
+    available_plugins().find(|plugin| plugin.names().contains(&location))
+}
+
+/// Plugins that are neither part of Zuban nor registered cannot be loaded. This is an error for
+/// plugins enabled in a Zuban config, but Mypy configs are allowed to use them, so they keep
+/// working with Mypy.
+pub(crate) fn unsupported_plugins_error(settings: &Settings) -> Option<String> {
+    if !settings.plugins_from_zuban {
+        return None;
+    }
+    let unsupported: Vec<_> = settings
+        .plugins
+        .iter()
+        .filter(|name| find_plugin(name).is_none())
+        .map(|name| format!("{name:?}"))
+        .collect();
+    if unsupported.is_empty() {
+        return None;
+    }
+    let mut supported: Vec<_> = available_plugins()
+        .flat_map(|plugin| plugin.names())
+        .copied()
+        .collect();
+    supported.sort();
+    Some(format!(
+        "Cannot load the plugin{} {}, the supported plugins are {}",
+        if unsupported.len() == 1 { "" } else { "s" },
+        unsupported.join(", "),
+        supported.join(", ")
+    ))
+}
+
+impl Plugins {
+    pub fn new(names: &[String]) -> Self {
+        let mut plugins: Vec<&'static dyn Plugin> = vec![];
+        for name in names {
+            match find_plugin(name) {
+                Some(plugin) => {
+                    if !plugins.iter().any(|p| std::ptr::eq(*p, plugin)) {
+                        plugins.push(plugin)
+                    }
+                }
+                None => tracing::warn!("Ignored unknown plugin {name}"),
+            }
+        }
+        Self(plugins)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn function_signature(
+        &self,
+        i_s: &InferenceState,
+        fullname: &str,
+        original: &dyn Fn() -> CallableContent,
+    ) -> Option<CallableContent> {
+        self.0
+            .iter()
+            .find_map(|plugin| plugin.function_signature(i_s, fullname, original))
+    }
+
+    pub fn class_attribute(&self, i_s: &InferenceState, class: Class, name: &str) -> Option<Type> {
+        self.0
+            .iter()
+            .find_map(|plugin| plugin.class_attribute(i_s, class, name))
+    }
+
+    pub fn decorated(
+        &self,
+        i_s: &InferenceState,
+        decorator: &Type,
+        decorated: &Type,
+    ) -> Option<Type> {
+        let fullname = fullname(i_s.db, decorator)?;
+        self.0
+            .iter()
+            .find_map(|plugin| plugin.decorated(i_s, &fullname, decorated))
+    }
+
+    pub fn analyze_type(&self, i_s: &InferenceState, fullname: &str) -> Option<Type> {
+        self.0
+            .iter()
+            .find_map(|plugin| plugin.analyze_type(i_s, fullname))
+    }
+
+    pub fn dataclass_init_params(
+        &self,
+        i_s: &InferenceState,
+        class: Class,
+        params: &mut Vec<CallableParam>,
+    ) {
+        for plugin in &self.0 {
+            plugin.dataclass_init_params(i_s, class, params)
+        }
+    }
+
+    pub fn is_implicit_classmethod(
+        &self,
+        i_s: &InferenceState,
+        file: &PythonFile,
+        decorator: Decorator,
+    ) -> bool {
+        let ExpressionContent::ExpressionPart(ExpressionPart::Primary(primary)) =
+            decorator.named_expression().expression().unpack()
+        else {
+            return false;
+        };
+        let PrimaryContent::Execution(args) = primary.second() else {
+            return false;
+        };
+        let callee = file.inference(i_s).infer_primary_or_atom(primary.first());
+        // Within a class the callable would otherwise be named like a method of that class.
+        let module_i_s = &InferenceState::new(i_s.db, file);
+        let Some(fullname) = fullname(i_s.db, &callee.as_cow_type(module_i_s)) else {
+            return false;
+        };
+        self.0
+            .iter()
+            .any(|plugin| plugin.is_implicit_classmethod(&fullname, args))
+    }
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|plugin| plugin.names()[0]))
+            .finish()
+    }
+}
+
+/// The fully qualified name of a function or class.
+fn fullname(db: &Database, t: &Type) -> Option<String> {
+    Some(match t {
+        Type::Callable(c) => c.qualified_name(db),
+        Type::FunctionOverload(o) => o.iter_functions().next()?.qualified_name(db),
+        Type::Type(t) => match t.as_ref() {
+            Type::Class(c) => c.class(db).qualified_name(db),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// Resolves a fully qualified name like `numpy._typing._nbit_base._8Bit` to a class, importing
+/// the modules on the way.
+pub(crate) fn lookup_class<'db>(db: &'db Database, fullname: &str) -> Option<Class<'db>> {
+    let (file, names) = import_longest_module(db, fullname)?;
+    let i_s = &InferenceState::new(db, file);
+    let (first, nested) = names.split_first()?;
+    let inferred = file.lookup(db, |_| false, first).into_maybe_inferred()?;
+    let as_class = |t: &Type| match t {
+        Type::Type(t) => match t.as_ref() {
+            Type::Class(c) => Some(Class::from_non_generic_link(db, c.link)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let mut class = as_class(&inferred.as_cow_type(i_s))?;
+    for name in nested {
+        let inferred = class.lookup_symbol(i_s, name).into_maybe_inferred()?;
+        class = as_class(&inferred.as_cow_type(i_s))?;
+    }
+    Some(class)
+}
+
+/// The type of a name in a module like `DEBUG` in `myproject.settings`. Literals of variables
+/// are widened like for all variables that are not final.
+pub(crate) fn lookup_module_attribute(db: &Database, module: &str, name: &str) -> Option<Type> {
+    let (file, names) = import_longest_module(db, module)?;
+    // Attributes of all modules like `__getattr__` are not relevant.
+    if !names.is_empty() || file.lookup_symbol(name).is_none() {
+        return None;
+    }
+    let i_s = &InferenceState::new(db, file);
+    let inferred = file.lookup(db, |_| false, name).into_maybe_inferred()?;
+    Some(inferred.avoid_implicit_literal(i_s).as_type(i_s))
+}
+
+/// Imports the longest module of a dotted name and returns the names after the module.
+fn import_longest_module<'db, 'x>(
+    db: &'db Database,
+    fullname: &'x str,
+) -> Option<(&'db PythonFile, Vec<&'x str>)> {
+    let builtins = db.python_state.builtins();
+    let mut parts = fullname.split('.');
+    let mut import = global_import(db, builtins, parts.next()?)?;
+    let mut names = vec![];
+    for part in parts {
+        if names.is_empty()
+            && let Some(sub_module) = import.clone().import(db, builtins, part)
+        {
+            import = sub_module;
+        } else {
+            names.push(part)
+        }
+    }
+    let ImportResult::File(file_index) = import.ensured_loaded_file(db)?.into_import_result()
+    else {
+        return None;
+    };
+    Some((db.loaded_python_file(file_index), names))
+}
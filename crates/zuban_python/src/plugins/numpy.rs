@@ -0,0 +1,38 @@
+//! Mirrors `numpy.typing.mypy_plugin`, which replaces the placeholders for the precision of
+//! platform dependent number types like `np.long` with the precision of the target platform.
+
+use super::{Plugin, lookup_class};
+use crate::{inference_state::InferenceState, type_::Type};
+
+pub(super) struct NumpyPlugin;
+
+impl Plugin for NumpyPlugin {
+    fn names(&self) -> &'static [&'static str] {
+        &["numpy.typing.mypy_plugin"]
+    }
+
+    fn analyze_type(&self, i_s: &InferenceState, fullname: &str) -> Option<Type> {
+        let name = fullname.strip_prefix("numpy._typing._nbit.")?;
+        let bits = precision(name, i_s.db.project.settings.computed_platform())?;
+        // Older NumPy versions define the precisions directly in `numpy`.
+        ["numpy._typing._nbit_base", "numpy"]
+            .into_iter()
+            .find_map(|module| lookup_class(i_s.db, &format!("{module}._{bits}Bit")))
+            .map(|class| class.as_type(i_s.db))
+    }
+}
+
+/// The number of bits of the C types on a 64 bit platform.
+fn precision(name: &str, platform: &str) -> Option<usize> {
+    Some(match name {
+        "_NBitByte" => 8,
+        "_NBitShort" | "_NBitHalf" => 16,
+        "_NBitIntC" | "_NBitSingle" => 32,
+        "_NBitIntP" | "_NBitInt" | "_NBitLongLong" | "_NBitDouble" => 64,
+        "_NBitLong" if platform == "win32" => 32,
+        "_NBitLong" => 64,
+        "_NBitLongDouble" if platform == "linux" => 128,
+        "_NBitLongDouble" => 64,
+        _ => return None,
+    })
+}
@@ -0,0 +1,316 @@
+//! Mirrors pydantic's Mypy plugin (`pydantic.mypy`) with its default options. Models are
+//! dataclass-like thanks to `dataclass_transform`, but pydantic validates and converts values, so
+//! the plugin makes all `__init__` parameters `Any` and accepts extra keyword arguments unless the
+//! model config forbids them. Validators are implicitly classmethods.
+//!
+//! The model config is read from the syntax tree, because it is usually a call to `ConfigDict`,
+//! a dict literal, the keyword arguments of the class or a nested `class Config` in pydantic v1.
+
+use parsa_python_cst::{
+    Argument, ArgumentsDetails, AssignmentContent, AtomContent, ClassDef, DictElement, Expression,
+    ExpressionContent, ExpressionPart, PrimaryContent, PrimaryOrAtom, StmtLikeContent, Target,
+};
+
+use super::Plugin;
+use crate::{
+    file::ClassNodeRef,
+    inference_state::InferenceState,
+    type_::{AnyCause, CallableParam, DbString, ParamType, StarStarParamType, StringSlice, Type},
+    type_helpers::{Class, TypeOrClass},
+};
+
+const BASE_MODELS: &[&str] = &["pydantic.main.BaseModel", "pydantic.v1.main.BaseModel"];
+const BASE_SETTINGS: &[&str] = &[
+    "pydantic_settings.main.BaseSettings",
+    "pydantic.env_settings.BaseSettings",
+    "pydantic.v1.env_settings.BaseSettings",
+];
+const VALIDATORS: &[&str] = &[
+    "pydantic.class_validators.validator",
+    "pydantic.class_validators.root_validator",
+    "pydantic.deprecated.class_validators.validator",
+    "pydantic.deprecated.class_validators.root_validator",
+    "pydantic.v1.class_validators.validator",
+    "pydantic.v1.class_validators.root_validator",
+    "pydantic.functional_validators.field_validator",
+    "pydantic.functional_validators.model_validator",
+];
+
+pub(super) struct PydanticPlugin;
+
+impl Plugin for PydanticPlugin {
+    fn names(&self) -> &'static [&'static str] {
+        &["pydantic.mypy", "pydantic.v1.mypy"]
+    }
+
+    fn dataclass_init_params(
+        &self,
+        i_s: &InferenceState,
+        class: Class,
+        params: &mut Vec<CallableParam>,
+    ) {
+        let db = i_s.db;
+        let mut is_model = false;
+        let mut is_settings = false;
+        let mut model_classes = vec![];
+        for (_, base) in class.mro(db) {
+            let name = base.qualified_name(db);
+            is_model |= BASE_MODELS.contains(&name.as_str());
+            is_settings |= BASE_SETTINGS.contains(&name.as_str());
+            model_classes.push(match base {
+                TypeOrClass::Class(c) => c.node_ref,
+                TypeOrClass::Type(t) => match t.as_ref() {
+                    Type::Dataclass(d) => ClassNodeRef::from_link(db, d.class.link),
+                    _ => continue,
+                },
+            });
+        }
+        if !is_model {
+            return;
+        }
+        // Subclasses override the config and the fields of their bases.
+        let mut config = ModelConfig::default();
+        let mut fields = vec![];
+        for class_ref in model_classes.into_iter().rev() {
+            config.apply_class(class_ref.node());
+            collect_fields(class_ref, &mut fields);
+        }
+
+        params.retain(|param| !matches!(param.type_, ParamType::StarStar(_)));
+        for param in params.iter_mut() {
+            let (ParamType::PositionalOrKeyword(t) | ParamType::KeywordOnly(t)) = &mut param.type_
+            else {
+                continue;
+            };
+            *t = Type::Any(AnyCause::Explicit);
+            let param_name = param.name.as_ref().unwrap().as_str(db);
+            if let Some(field) = fields
+                .iter()
+                .rev()
+                .find(|f| f.alias.unwrap_or(f.name.as_str(db)) == param_name)
+            {
+                param.has_default = field.has_default;
+                if config.populate_by_name && field.alias.is_some() {
+                    param.name = Some(field.name.clone());
+                }
+            }
+            param.has_default |= is_settings;
+        }
+        params.retain(|param| {
+            param
+                .name
+                .as_ref()
+                .is_none_or(|name| !name.as_str(db).starts_with('_'))
+        });
+        // Aliases that are generated dynamically can only be passed as keyword arguments.
+        let has_dynamic_aliases = config.has_alias_generator && !config.populate_by_name;
+        if !config.forbid_extra || has_dynamic_aliases {
+            params.push(CallableParam::new(
+                DbString::Static("kwargs"),
+                ParamType::StarStar(StarStarParamType::ValueType(Type::Any(AnyCause::Explicit))),
+            ));
+        }
+    }
+
+    fn is_implicit_classmethod(&self, decorator_fullname: &str, args: ArgumentsDetails) -> bool {
+        if !VALIDATORS.contains(&decorator_fullname) {
+            return false;
+        }
+        // `@model_validator(mode="after")` is the exception, it expects a normal method.
+        let ArgumentsDetails::Node(args) = args else {
+            return true;
+        };
+        !args.iter().any(|arg| {
+            matches!(arg, Argument::Keyword(kwarg) if {
+                let (name, value) = kwarg.unpack();
+                name.as_code() == "mode"
+                    && value
+                        .maybe_single_string_literal()
+                        .is_some_and(|s| s.content() == "after")
+            })
+        })
+    }
+}
+
+#[derive(Default)]
+struct ModelConfig {
+    populate_by_name: bool,
+    forbid_extra: bool,
+    has_alias_generator: bool,
+}
+
+impl ModelConfig {
+    fn apply_class(&mut self, class: ClassDef) {
+        if let Some(args) = class.arguments() {
+            for arg in args.iter() {
+                if let Argument::Keyword(kwarg) = arg {
+                    let (name, value) = kwarg.unpack();
+                    self.set(name.as_code(), value)
+                }
+            }
+        }
+        for stmt in class.block().iter_stmt_likes() {
+            match stmt.node {
+                StmtLikeContent::Assignment(assignment) => {
+                    if let Some((name_def, _, value)) =
+                        assignment.maybe_simple_type_expression_assignment()
+                        && name_def.as_code() == "model_config"
+                    {
+                        self.apply_model_config(value)
+                    }
+                }
+                // The config of pydantic v1
+                StmtLikeContent::ClassDef(config) if config.name().as_code() == "Config" => {
+                    for stmt in config.block().iter_stmt_likes() {
+                        if let StmtLikeContent::Assignment(assignment) = stmt.node
+                            && let Some((name_def, _, value)) =
+                                assignment.maybe_simple_type_expression_assignment()
+                        {
+                            self.set(name_def.as_code(), value)
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn apply_model_config(&mut self, value: Expression) {
+        let ExpressionContent::ExpressionPart(part) = value.unpack() else {
+            return;
+        };
+        match part {
+            // `ConfigDict(populate_by_name=True)`
+            ExpressionPart::Primary(primary) => {
+                if let PrimaryContent::Execution(ArgumentsDetails::Node(args)) = primary.second() {
+                    for arg in args.iter() {
+                        if let Argument::Keyword(kwarg) = arg {
+                            let (name, value) = kwarg.unpack();
+                            self.set(name.as_code(), value)
+                        }
+                    }
+                }
+            }
+            // `{"populate_by_name": True}`
+            ExpressionPart::Atom(atom) => {
+                if let AtomContent::Dict(dict) = atom.unpack() {
+                    for element in dict.iter_elements() {
+                        if let DictElement::KeyValue(key_value) = element
+                            && let Some(key) = key_value.key().maybe_single_string_literal()
+                        {
+                            self.set(key.content(), key_value.value())
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Expression) {
+        match key {
+            "populate_by_name" | "validate_by_name" | "allow_population_by_field_name" => {
+                if let Some(b) = value.maybe_simple_bool() {
+                    self.populate_by_name = b
+                }
+            }
+            "extra" => self.forbid_extra = is_forbid(value),
+            "alias_generator" => self.has_alias_generator = !value.is_none_literal(),
+            _ => (),
+        }
+    }
+}
+
+/// Either `"forbid"` or `Extra.forbid`.
+fn is_forbid(value: Expression) -> bool {
+    if let Some(literal) = value.maybe_single_string_literal() {
+        return literal.content() == "forbid";
+    }
+    match value.unpack() {
+        ExpressionContent::ExpressionPart(ExpressionPart::Primary(primary)) => {
+            matches!(primary.second(), PrimaryContent::Attribute(name) if name.as_code() == "forbid")
+        }
+        _ => false,
+    }
+}
+
+struct Field<'db> {
+    name: DbString,
+    alias: Option<&'db str>,
+    has_default: bool,
+}
+
+fn collect_fields<'db>(class_ref: ClassNodeRef<'db>, fields: &mut Vec<Field<'db>>) {
+    for stmt in class_ref.node().block().iter_stmt_likes() {
+        let StmtLikeContent::Assignment(assignment) = stmt.node else {
+            continue;
+        };
+        let AssignmentContent::WithAnnotation(Target::Name(name_def), _, right_side) =
+            assignment.unpack()
+        else {
+            continue;
+        };
+        let value = right_side.and_then(|r| r.maybe_simple_expression());
+        let field_call = value.and_then(|value| match value.unpack() {
+            ExpressionContent::ExpressionPart(ExpressionPart::Primary(primary))
+                if is_field_function(primary.first()) =>
+            {
+                match primary.second() {
+                    PrimaryContent::Execution(args) => Some(args),
+                    _ => None,
+                }
+            }
+            _ => None,
+        });
+        let mut alias = None;
+        let has_default = match field_call {
+            Some(args) => {
+                let mut has_default = false;
+                if let ArgumentsDetails::Node(args) = args {
+                    for arg in args.iter() {
+                        match arg {
+                            // The default is the only positional argument.
+                            Argument::Positional(default) => {
+                                has_default = !default.expression().is_ellipsis_literal()
+                            }
+                            Argument::Keyword(kwarg) => {
+                                let (name, value) = kwarg.unpack();
+                                match name.as_code() {
+                                    "default" => has_default = !value.is_ellipsis_literal(),
+                                    "default_factory" => has_default = !value.is_none_literal(),
+                                    "alias" => {
+                                        alias =
+                                            value.maybe_single_string_literal().map(|s| s.content())
+                                    }
+                                    _ => (),
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                has_default
+            }
+            // Only `x: int = ...` is required.
+            None => value.is_some_and(|value| !value.is_ellipsis_literal()),
+        };
+        fields.push(Field {
+            name: DbString::StringSlice(StringSlice::from_name(
+                class_ref.file_index(),
+                name_def.name(),
+            )),
+            alias,
+            has_default,
+        });
+    }
+}
+
+/// Whether a call is a call to `Field` or `pydantic.Field`.
+fn is_field_function(callee: PrimaryOrAtom) -> bool {
+    match callee {
+        PrimaryOrAtom::Atom(atom) => atom.as_code() == "Field",
+        PrimaryOrAtom::Primary(primary) => {
+            matches!(primary.second(), PrimaryContent::Attribute(name) if name.as_code() == "Field")
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Quick fixes for common diagnostics. Appending a `# type: ignore[code]` comment is handled
+//! separately, because it's possible for almost all diagnostics.
+
+use parsa_python_cst::{CodeIndex, Expression, Scope, is_identifier};
+
+use crate::{
+    CodeAction, InputPosition, TextChange,
+    annotate::{annotation_with_imports, import_insertion},
+    completion::{Completion, CompletionResolver},
+    database::Database,
+    diagnostics::{Issue, IssueKind},
+    file::{File as _, PythonFile},
+    format_data::FormatData,
+    goto::with_i_s_non_self,
+    implement_members::implement_members_for_issue,
+    result_context::ResultContext,
+    type_::{Enum, Type},
+};
+
+// Like difflib.get_close_matches, which Mypy uses for its "maybe" suggestions.
+const MAX_SPELLING_SUGGESTIONS: usize = 3;
+
+pub(crate) fn quick_fixes<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    issue: &Issue,
+) -> Vec<CodeAction<'db>> {
+    let mut fixes = vec![];
+    let replace = |start, end, title: String, replacement: String| CodeAction {
+        title,
+        start_of_change: file.byte_to_position_infos(db, start),
+        end_of_change: file.byte_to_position_infos(db, end),
+        replacement,
+        additional_changes: vec![],
+    };
+    let change_annotation = |annotation: Expression, title: &str, new: String| {
+        replace(
+            annotation.start(),
+            annotation.end(),
+            format!(r#"{title} "{new}""#),
+            new,
+        )
+    };
+    match &issue.kind {
+        IssueKind::IncompatibleDefaultArgument { got, .. }
+        | IssueKind::IncompatibleAssignment { got, .. }
+            if got.as_ref() == "None" =>
+        {
+            if let Some(annotation) = file
+                .tree
+                .annotation_of_default_or_assignment(issue.start_position)
+                && let Some(new) = with_none(db, file, annotation)
+            {
+                fixes.push(change_annotation(annotation, "Change type to", new))
+            }
+        }
+        IssueKind::IncompatibleReturn { got, .. } => {
+            // In async functions, the await is missing instead.
+            if let Some(annotation) = file
+                .tree
+                .return_annotation_of_function_at(issue.start_position)
+                && !(is_missing_await(&issue.kind)
+                    && file.tree.is_in_async_function(issue.start_position))
+            {
+                let title = "Change return type to";
+                if got.as_ref() == "None" {
+                    if let Some(new) = with_none(db, file, annotation) {
+                        fixes.push(change_annotation(annotation, title, new))
+                    }
+                } else if let Some((new, imports)) =
+                    return_value_annotation(db, file, issue.start_position)
+                {
+                    let mut fix = change_annotation(annotation, title, new);
+                    if !imports.is_empty() {
+                        let (position, code) = import_insertion(file, &imports);
+                        let position = file.byte_to_position_infos(db, position);
+                        fix.additional_changes.push(TextChange {
+                            start_of_change: position,
+                            end_of_change: position,
+                            replacement: code,
+                        });
+                    }
+                    fixes.push(fix)
+                }
+            }
+        }
+        IssueKind::NonExhaustiveMatch { missing_cases, .. } => {
+            if !missing_cases.is_empty()
+                && missing_cases
+                    .iter()
+                    .all(|pattern| is_accessible_pattern(db, file, pattern))
+                && let Some(insertion) = file
+                    .tree
+                    .insertion_point_for_match_cases(issue.start_position)
+            {
+                let code = file.tree.code();
+                let newline = if code.contains("\r\n") { "\r\n" } else { "\n" };
+                let format_case = |pattern: &str, body: &str| {
+                    format!(
+                        "{}case {pattern}:{newline}{}{body}{newline}",
+                        insertion.case_indentation, insertion.body_indentation
+                    )
+                };
+                let mut cases = String::new();
+                if insertion.needs_newline {
+                    cases += newline
+                }
+                for pattern in missing_cases.iter() {
+                    cases += &format_case(pattern, "pass")
+                }
+                let title = match missing_cases.len() {
+                    1 => "Add missing case",
+                    _ => "Add missing cases",
+                };
+                let at = insertion.insertion_index;
+                let with_assert_never = file.lookup_symbol("assert_never").is_some().then(|| {
+                    let assert_never = format!("assert_never({})", insertion.subject);
+                    cases.clone() + &format_case("_", &assert_never)
+                });
+                fixes.push(replace(at, at, title.to_string(), cases));
+                if let Some(cases) = with_assert_never {
+                    fixes.push(replace(at, at, format!("{title} with assert_never"), cases));
+                }
+            }
+        }
+        _ => (),
+    }
+    if let Some(misspelled) = misspelled_name(&issue.kind) {
+        for (start, end, suggestion) in spelling_suggestions(db, file, issue, misspelled) {
+            fixes.push(replace(
+                start,
+                end,
+                format!(r#"Change to "{suggestion}""#),
+                suggestion,
+            ))
+        }
+    }
+    if is_missing_await(&issue.kind) && file.tree.is_in_async_function(issue.start_position) {
+        let (start, end) = (issue.start_position, issue.end_position);
+        let title = r#"Add "await""#.to_string();
+        fixes.push(match file.tree.await_insertion_point(start, end) {
+            Some(position) => replace(position, position, title, "await ".to_string()),
+            None => {
+                let code = &file.tree.code()[start as usize..end as usize];
+                replace(start, end, title, format!("await ({code})"))
+            }
+        })
+    }
+    fixes.extend(implement_members_for_issue(db, file, &issue.kind));
+    fixes
+}
+
+/// The annotation for the type of the value that is returned at `position`, together with the
+/// imports it needs.
+fn return_value_annotation(
+    db: &Database,
+    file: &PythonFile,
+    position: CodeIndex,
+) -> Option<(String, Vec<String>)> {
+    let (func, value) = file.tree.return_value_at(position)?;
+    let t = with_i_s_non_self(db, file, Scope::Function(func), |i_s| {
+        let inferred = file
+            .inference(i_s)
+            .infer_star_expressions(value, &mut ResultContext::Unknown);
+        inferred.avoid_implicit_literal(i_s).as_type(i_s)
+    });
+    if t.is_any() {
+        return None;
+    }
+    match writable_annotation(db, file, &t) {
+        Some(annotation) => Some((annotation, vec![])),
+        None => annotation_with_imports(db, file, &t),
+    }
+}
+
+/// `X | None` where that syntax is available and `Optional[X]` if `Optional` is imported.
+fn with_none(db: &Database, file: &PythonFile, annotation: Expression) -> Option<String> {
+    if annotation.is_string() {
+        return None;
+    }
+    let code = annotation.as_code();
+    if file.is_stub()
+        || db
+            .project
+            .settings
+            .python_version_or_default()
+            .at_least_3_dot(10)
+    {
+        Some(format!("{code} | None"))
+    } else if file.lookup_symbol("Optional").is_some() {
+        Some(format!("Optional[{code}]"))
+    } else {
+        None
+    }
+}
+
+/// Types like `def (x: int) -> str` or `Overload(...)` are printed in a form that is not valid
+/// Python.
+fn is_writable_type(t: &str) -> bool {
+    !t.contains(['?', '(', '<', '*', '"'])
+}
+
+/// Returns an annotation for the type, if it can be written in this file without adding imports.
+pub(crate) fn writable_annotation(db: &Database, file: &PythonFile, t: &Type) -> Option<String> {
+    writable_annotation_with_additional_names(db, file, t, &[])
+}
+
+/// Like [`writable_annotation`], but the additional names are considered accessible as well, e.g.
+/// because they are about to be imported.
+pub(crate) fn writable_annotation_with_additional_names(
+    db: &Database,
+    file: &PythonFile,
+    t: &Type,
+    additional_names: &[&str],
+) -> Option<String> {
+    let formatted = t.format_short(db);
+    if !is_writable_type(&formatted) || formatted.contains('\'') {
+        return None;
+    }
+    let builtins = db.python_state.builtins();
+    formatted
+        .split(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+        .filter(|part| !part.is_empty() && *part != "None")
+        .all(|part| {
+            let first = part.split('.').next().unwrap();
+            is_identifier(first)
+                && (file.lookup_symbol(first).is_some()
+                    || builtins.lookup_symbol(first).is_some()
+                    || additional_names.contains(&first))
+        })
+        .then(|| formatted.into_string())
+}
+
+/// The patterns of `case` blocks that match all values of a type, e.g. `Color.RED` for enum
+/// members, `None` or `int()` for instances. Returns nothing if a part of the type cannot be
+/// matched with a simple pattern.
+pub(crate) fn case_patterns(db: &Database, t: &Type) -> Box<[Box<str>]> {
+    let format_data = &FormatData::new_short(db);
+    let mut patterns: Vec<Box<str>> = vec![];
+    for t in t.iter_with_unpacked_unions(db) {
+        match t {
+            Type::None => patterns.push("None".into()),
+            Type::Literal(literal) => patterns.push(literal.format_inner(db).into()),
+            Type::EnumMember(member) => patterns.push(member.format_inner(format_data).into()),
+            Type::Enum(enum_) => patterns.extend(
+                Enum::implicit_members(enum_).map(|member| member.format_inner(format_data).into()),
+            ),
+            Type::Class(c) if c.link == db.python_state.bool_link() => {
+                patterns.extend(["True".into(), "False".into()])
+            }
+            Type::Class(c) => patterns.push(format!("{}()", c.class(db).name()).into()),
+            Type::Dataclass(d) => patterns.push(format!("{}()", d.class(db).name()).into()),
+            _ => return Box::default(),
+        }
+    }
+    patterns.into()
+}
+
+/// Literal patterns are always valid, but names like `Color` in `Color.RED` or `int` in `int()`
+/// need to be available in the file.
+fn is_accessible_pattern(db: &Database, file: &PythonFile, pattern: &str) -> bool {
+    let name = pattern.split(['.', '(']).next().unwrap_or_default();
+    if !is_identifier(name) || ["None", "True", "False"].contains(&name) {
+        // Literals like `1`, `"foo"` or `b"bar"`
+        return true;
+    }
+    file.lookup_symbol(name).is_some() || db.python_state.builtins().lookup_symbol(name).is_some()
+}
+
+fn misspelled_name(kind: &IssueKind) -> Option<&str> {
+    match kind {
+        IssueKind::AttributeError { name, .. } | IssueKind::ModuleAttributeError { name } => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+fn is_missing_await(kind: &IssueKind) -> bool {
+    match kind {
+        IssueKind::CoroutineValueMustBeUsed { .. } | IssueKind::AwaitableValueMustBeUsed { .. } => {
+            true
+        }
+        IssueKind::IncompatibleAssignment { got, .. }
+        | IssueKind::IncompatibleReturn { got, .. } => got.starts_with("Coroutine["),
+        _ => false,
+    }
+}
+
+/// Uses the attribute completions at the misspelled name to find names that are close to it.
+fn spelling_suggestions(
+    db: &Database,
+    file: &PythonFile,
+    issue: &Issue,
+    misspelled: &str,
+) -> Vec<(CodeIndex, CodeIndex, String)> {
+    let Some(name) = file
+        .tree
+        .filter_all_names(Some(issue.start_position))
+        .take_while(|name| name.start() <= issue.end_position)
+        .filter(|name| name.as_code() == misspelled)
+        .last()
+    else {
+        return vec![];
+    };
+    let Ok(candidates) = CompletionResolver::complete(
+        db,
+        file,
+        InputPosition::NthUTF8Byte(name.end() as usize),
+        false,
+        |_, completion: &dyn Completion| Some(completion.label().to_string()),
+    ) else {
+        return vec![];
+    };
+    let mut matches: Vec<_> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let distance = edit_distance(misspelled, &candidate);
+            let max_distance =
+                (misspelled.chars().count().max(candidate.chars().count()) / 3).max(1);
+            (candidate != misspelled && distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    matches.sort();
+    matches.dedup();
+    matches
+        .into_iter()
+        .take(MAX_SPELLING_SUGGESTIONS)
+        .map(|(_, candidate)| (name.start(), name.end(), candidate))
+        .collect()
+}
+
+/// The Damerau-Levenshtein distance with adjacent transpositions, because swapped characters are
+/// a very common typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
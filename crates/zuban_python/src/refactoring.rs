@@ -0,0 +1,236 @@
+//! Refactorings that move code around, currently extracting an expression into a variable and
+//! extracting statements into a function. The annotations of the new code are inferred.
+
+use parsa_python_cst::{
+    CodeIndex, ExpressionExtraction, ExtractableExpression, Name as CSTName, StatementsExtraction,
+};
+
+use crate::{
+    Document, InputPosition, PositionInfos,
+    database::Database,
+    file::{File as _, PythonFile},
+    goto::{PositionalDocument, with_i_s_non_self},
+    inference_state::InferenceState,
+    inferred::Inferred,
+    quick_fixes::writable_annotation,
+};
+
+pub struct Refactoring<'db> {
+    pub title: String,
+    /// The changes are sorted and do not overlap.
+    pub changes: Vec<TextChange<'db>>,
+}
+
+pub struct TextChange<'db> {
+    pub start_of_change: PositionInfos<'db>,
+    pub end_of_change: PositionInfos<'db>,
+    pub replacement: String,
+}
+
+impl<'project> Document<'project> {
+    pub fn refactorings(
+        &self,
+        position: InputPosition,
+        until: InputPosition,
+    ) -> anyhow::Result<Vec<Refactoring<'_>>> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        let result = file.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        let start = file.line_column_to_byte(position)?.byte;
+        let end = file.line_column_to_byte(until)?.byte;
+        let mut refactorings = vec![];
+        if let Some(extraction) = file.tree.expression_for_extraction(start, end) {
+            refactorings.push(extract_variable(db, file, extraction))
+        }
+        if let Some(extraction) = file.tree.statements_for_extraction(start, end) {
+            refactorings.push(extract_function(db, file, extraction))
+        }
+        Ok(refactorings)
+    }
+}
+
+fn extract_variable<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    extraction: ExpressionExtraction,
+) -> Refactoring<'db> {
+    let expression = extraction.expression;
+    let annotation = with_i_s_non_self(db, file, extraction.scope, |i_s| {
+        let inference = file.inference(i_s);
+        let inferred = match expression {
+            ExtractableExpression::Expression(expr) => inference.infer_expression(expr),
+            ExtractableExpression::Part(part) => inference.infer_expression_part(part),
+        };
+        annotation_for(i_s, file, inferred)
+    });
+    let name = unused_name(file, "new_var");
+    let mut code = expression.as_code().to_string();
+    if code.contains('\n') {
+        // The expression was probably within brackets that allowed line breaks.
+        code = format!("({code})");
+    }
+    let newline = newline(file);
+    let indentation = extraction.indentation;
+    let assignment = match annotation {
+        Some(annotation) => format!("{name}: {annotation} = {code}{newline}{indentation}"),
+        None => format!("{name} = {code}{newline}{indentation}"),
+    };
+    Refactoring {
+        title: "Extract variable".to_string(),
+        changes: vec![
+            insertion(db, file, extraction.insertion_index, assignment),
+            TextChange {
+                start_of_change: file.byte_to_position_infos(db, expression.start()),
+                end_of_change: file.byte_to_position_infos(db, expression.end()),
+                replacement: name,
+            },
+        ],
+    }
+}
+
+fn extract_function<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    extraction: StatementsExtraction,
+) -> Refactoring<'db> {
+    let infer_name = |name: CSTName| {
+        let document = PositionalDocument {
+            db,
+            file,
+            scope: name.parent_scope(),
+            node: (),
+        };
+        document.with_i_s(|i_s| {
+            document
+                .infer_name(name)
+                .and_then(|inferred| annotation_for(i_s, file, inferred))
+        })
+    };
+    let name = unused_name(file, "new_function");
+    let newline = newline(file);
+    let params: Vec<_> = extraction
+        .parameters
+        .iter()
+        .map(|&param| match infer_name(param) {
+            Some(annotation) => format!("{}: {annotation}", param.as_code()),
+            None => param.as_code().to_string(),
+        })
+        .collect();
+    let return_annotations: Option<Vec<_>> = extraction
+        .return_values
+        .iter()
+        .map(|&name| infer_name(name))
+        .collect();
+    let return_annotation = match (extraction.return_values.len(), return_annotations) {
+        (0, _) => Some("None".to_string()),
+        (1, Some(mut annotations)) => annotations.pop(),
+        (_, Some(annotations)) => Some(format!("tuple[{}]", annotations.join(", "))),
+        (_, None) => None,
+    };
+    let return_values = extraction
+        .return_values
+        .iter()
+        .map(|name| name.as_code())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut function = String::new();
+    if extraction.contains_await {
+        function += "async ";
+    }
+    function += &format!("def {name}({})", params.join(", "));
+    if let Some(return_annotation) = return_annotation {
+        function += &format!(" -> {return_annotation}");
+    }
+    function += ":";
+    function += newline;
+    let code = &file.code()[extraction.start as usize..extraction.end as usize];
+    for line in code.trim_end().lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = line.strip_prefix(extraction.indentation).unwrap_or(line);
+        if !line.trim().is_empty() {
+            function += "    ";
+            function += line;
+        }
+        function += newline;
+    }
+    if !return_values.is_empty() {
+        function += &format!("    return {return_values}{newline}");
+    }
+    function += newline;
+    function += newline;
+
+    let mut call = extraction.indentation.to_string();
+    if !return_values.is_empty() {
+        call += &format!("{return_values} = ");
+    }
+    if extraction.contains_await {
+        call += "await ";
+    }
+    let args: Vec<_> = extraction.parameters.iter().map(|p| p.as_code()).collect();
+    call += &format!("{name}({}){newline}", args.join(", "));
+
+    let replace_statements = |replacement| TextChange {
+        start_of_change: file.byte_to_position_infos(db, extraction.start),
+        end_of_change: file.byte_to_position_infos(db, extraction.end),
+        replacement,
+    };
+    let changes = if extraction.top_level_start == extraction.start {
+        vec![replace_statements(function + &call)]
+    } else {
+        vec![
+            insertion(db, file, extraction.top_level_start, function),
+            replace_statements(call),
+        ]
+    };
+    Refactoring {
+        title: "Extract function".to_string(),
+        changes,
+    }
+}
+
+fn annotation_for(i_s: &InferenceState, file: &PythonFile, inferred: Inferred) -> Option<String> {
+    let t = inferred.avoid_implicit_literal(i_s).as_type(i_s);
+    if t.is_any() {
+        return None;
+    }
+    writable_annotation(i_s.db, file, &t)
+}
+
+fn unused_name(file: &PythonFile, base: &str) -> String {
+    let is_used = |candidate: &str| {
+        file.tree
+            .filter_all_names(None)
+            .any(|name| name.as_code() == candidate)
+    };
+    let mut name = base.to_string();
+    let mut i = 1;
+    while is_used(&name) {
+        name = format!("{base}{i}");
+        i += 1;
+    }
+    name
+}
+
+fn insertion<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    index: CodeIndex,
+    replacement: String,
+) -> TextChange<'db> {
+    let position = file.byte_to_position_infos(db, index);
+    TextChange {
+        start_of_change: position,
+        end_of_change: position,
+        replacement,
+    }
+}
+
+fn newline(file: &PythonFile) -> &'static str {
+    if file.code().contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
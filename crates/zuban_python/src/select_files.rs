@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex, RwLock};
 
-use config::TypeCheckerFlags;
+use config::{FollowImports, TypeCheckerFlags};
 use rayon::prelude::*;
 use utils::FastHashSet;
 use vfs::{
@@ -10,25 +10,14 @@ use vfs::{
 
 use crate::{
     database::Database,
-    diagnostics::Diagnostic,
-    file::PythonFile,
+    file::{PythonFile, flags_from_overrides},
     imports::ImportResult,
     utils::{is_file_with_python_ending, join_with_commas},
 };
 
-pub(crate) fn diagnostics_for_relevant_files<'db>(
-    db: &'db Database,
-    on_file: impl FnMut(&'db PythonFile) -> Vec<Diagnostic<'db>>,
-) -> anyhow::Result<Vec<Diagnostic<'db>>> {
-    let files = FileSelector::find_files(db)?;
-    Ok(files
-        .into_iter()
-        .map(on_file)
-        .reduce(|mut vec1, vec2| {
-            vec1.extend(vec2);
-            vec1
-        })
-        .unwrap_or_default())
+/// The files that are type checked when checking the whole project (e.g. on the command line).
+pub(crate) fn relevant_files(db: &Database) -> anyhow::Result<Vec<&PythonFile>> {
+    FileSelector::find_files(db)
 }
 
 pub(crate) fn all_typechecked_files(
@@ -45,15 +34,47 @@ pub(crate) fn all_typechecked_files(
     )
 }
 
+/// Whether a file is part of the checked files, i.e. the files passed on the command line or all
+/// files of the type checked workspaces if no files are passed.
+pub(crate) fn is_part_of_checked_files(db: &Database, file_entry: &FileEntry) -> bool {
+    if !file_entry.parent.workspace().is_type_checked() {
+        return false;
+    }
+    let check_files = &db.project.settings.files_or_directories_to_check;
+    if check_files.is_empty() {
+        return true;
+    }
+    let handler = &*db.vfs.handler;
+    let path = file_entry.absolute_path(handler);
+    // Directories that are checked include all files in them.
+    GlobAbsPath::matches_path_or_parent(check_files, handler, path.path())
+}
+
+/// Returns the follow_imports mode if imports of the file are ignored because of it. This only
+/// depends on the config, so skipped modules are never loaded.
+pub(crate) fn skipped_by_follow_imports(
+    db: &Database,
+    file_entry: &FileEntry,
+) -> Option<FollowImports> {
+    let overridden = flags_from_overrides(&db.project, file_entry);
+    let flags = overridden.as_ref().unwrap_or(&db.project.flags);
+    let follow_imports = flags.follow_imports;
+    (matches!(follow_imports, FollowImports::Skip | FollowImports::Error)
+        && (flags.follow_imports_for_stubs || !file_entry.name.ends_with(".pyi"))
+        && !is_part_of_checked_files(db, file_entry))
+    .then_some(follow_imports)
+}
+
 fn should_skip_file(flags: &TypeCheckerFlags, rel_path: &str) -> bool {
     if !is_file_with_python_ending(rel_path) {
         return true;
     }
-    should_skip_dir_or_file(flags, rel_path)
+    should_skip_dir_or_file(flags, rel_path, false)
 }
 
-fn should_skip_dir_or_file(flags: &TypeCheckerFlags, rel_path: &str) -> bool {
+fn should_skip_dir_or_file(flags: &TypeCheckerFlags, rel_path: &str, is_dir: bool) -> bool {
     flags.excludes.iter().any(|e| e.regex.is_match(rel_path))
+        || flags.exclude_patterns.is_path_ignored(rel_path, is_dir)
 }
 
 struct FileSelector<'db> {
@@ -144,15 +165,18 @@ impl<'db> FileSelector<'db> {
             // file or directory and will land here.
             let not_yet_checked_globs: Vec<&GlobAbsPath> = check_files
                 .iter()
+                // Negated patterns only remove files that were added by other patterns.
+                .filter(|pattern| !pattern.is_negated())
                 .filter_map(|pattern| {
                     if let Some(path) = pattern.maybe_simple_path() {
                         self.added_file = false;
                         let normalized =
                             LocalFS::without_watcher().normalized_path_from_current_dir(path);
-                        match self.db.vfs.search_path(
-                            self.db.project.flags.case_sensitive,
-                            &PathWithScheme::with_file_scheme(normalized.clone()),
-                        ) {
+                        match self
+                            .db
+                            .vfs
+                            .search_path(&PathWithScheme::with_file_scheme(normalized.clone()))
+                        {
                             Some(DirOrFile::Dir(dir)) => self.handle_dir(&dir),
                             Some(DirOrFile::File(file)) => self.add_file(file),
                             None => {
@@ -189,7 +213,7 @@ impl<'db> FileSelector<'db> {
                         };
                         if not_yet_checked_globs
                             .iter()
-                            .any(|glob| glob.matches(vfs_handler, path.path()))
+                            .any(|glob| glob.matches(path.path()))
                         {
                             self.handle_entry(in_dir, entry);
                             false
@@ -208,6 +232,14 @@ impl<'db> FileSelector<'db> {
     }
 
     fn add_file(&mut self, file: Arc<FileEntry>) {
+        let check_files = &self.db.project.settings.files_or_directories_to_check;
+        if check_files.iter().any(|glob| glob.is_negated()) {
+            let handler = &*self.db.vfs.handler;
+            let path = file.absolute_path(handler);
+            if !GlobAbsPath::matches_path_or_parent(check_files, handler, path.path()) {
+                return;
+            }
+        }
         self.added_file = true;
         if let Some(file_index) = file.get_file_index() {
             self.file_indexes.write().unwrap().insert(file_index);
@@ -242,10 +274,12 @@ impl<'db> FileSelector<'db> {
             }
             DirectoryEntry::Directory(dir) => {
                 let path = dir.relative_path(handler);
-                if !should_skip_dir_or_file(&self.db.project.flags, &path)
+                if !should_skip_dir_or_file(&self.db.project.flags, &path, true)
                     && !self.ignored_by_gitignore(|| dir.absolute_path(handler), true)
                     // Nested workspaces are handled by checking the other workspaces
                     && !dir.is_nested_workspace()
+                    // The targets of symlinks are checked with their own paths
+                    && !dir.is_symlink_into_workspace()
                 {
                     self.handle_dir(dir)
                 }
@@ -307,7 +341,11 @@ fn find_imports_and_preload_files(
             imp.node_index,
             |on_file| match on_file {
                 ImportResult::File(file_index) => {
-                    let ptr = ArcPtrWrapper(Arc::as_ptr(db.vfs.file_entry(file_index)));
+                    let file_entry = db.vfs.file_entry(file_index);
+                    if skipped_by_follow_imports(db, file_entry).is_some() {
+                        return;
+                    }
+                    let ptr = ArcPtrWrapper(Arc::as_ptr(file_entry));
                     if loaded_file_entries.lock().unwrap().insert(ptr) {
                         need_to_load_files.insert(file_index);
                     }
@@ -0,0 +1,568 @@
+//! Generates stubs (`.pyi` files) from source modules, similar to Mypy's `stubgen`. Imports,
+//! type aliases and other type definitions are copied, functions and classes are reduced to their
+//! signatures. Annotations are copied from the source and unannotated code is either left without
+//! annotations or, with [`StubgenOptions::infer_types`], annotated with the inferred types.
+//!
+//! Only definitions in module and class scopes are part of a stub. `if` blocks are kept with
+//! their conditions (e.g. `if TYPE_CHECKING:` or `if sys.version_info >= ...:`), while the bodies
+//! of `try` statements are inlined without their `except` handlers.
+
+use std::borrow::Cow;
+
+use parsa_python_cst::{
+    AssignmentContent, AsyncStmtContent, ClassDef, Decoratee, Decorators, FunctionDef, IfBlockType,
+    IfStmt, NameDef, ParamAnnotation, ParamKind, StmtLikeContent, StmtLikeIterator, Target,
+    TryBlockType,
+};
+
+use crate::{
+    database::{ComplexPoint, Database},
+    file::{ClassNodeRef, PythonFile, assignment_type_node_ref},
+    inference_state::InferenceState,
+    node_ref::NodeRef,
+    quick_fixes::writable_annotation,
+    type_::{ReplaceTypeVarLikes as _, Type},
+    type_helpers::{Class, FuncLike as _, Function, Instance, InstanceLookupOptions},
+};
+
+/// Options for [`crate::Project::generate_stubs`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StubgenOptions {
+    /// Also generate stubs for names that start with an underscore.
+    pub include_private: bool,
+    /// Annotate unannotated variables, parameters with defaults and return types with their
+    /// inferred types.
+    pub infer_types: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedStub {
+    /// The path of the stub relative to the output directory with `/` as a separator, e.g.
+    /// `foo/bar.pyi` for the module `foo.bar`.
+    pub path: String,
+    pub code: String,
+}
+
+pub(crate) fn generate_stub<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    options: StubgenOptions,
+) -> GeneratedStub {
+    let mut generator = StubGenerator {
+        db,
+        file,
+        i_s: InferenceState::new(db, file),
+        options,
+        uses_incomplete: false,
+        lines: vec![],
+    };
+    generator.add_stmts(file.tree.root().iter_stmt_likes(), 0, None, None);
+    let mut code = String::new();
+    if generator.uses_incomplete {
+        code += "from _typeshed import Incomplete\n\n";
+    }
+    for line in generator.lines {
+        code += &line;
+        code.push('\n');
+    }
+    let (_, is_package) = file.file_entry_and_is_package(db);
+    let mut path = file.qualified_name(db).replace('.', "/");
+    if is_package {
+        path += "/__init__";
+    }
+    GeneratedStub {
+        path: path + ".pyi",
+        code,
+    }
+}
+
+struct StubGenerator<'db> {
+    db: &'db Database,
+    file: &'db PythonFile,
+    i_s: InferenceState<'db, 'db>,
+    options: StubgenOptions,
+    uses_incomplete: bool,
+    lines: Vec<String>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Kind {
+    Import,
+    Variable,
+    Function,
+    Class,
+    If,
+}
+
+impl<'db> StubGenerator<'db> {
+    fn add_stmts(
+        &mut self,
+        stmts: StmtLikeIterator<'db>,
+        indent: usize,
+        class: Option<ClassNodeRef<'db>>,
+        mut previous_kind: Option<Kind>,
+    ) -> Option<Kind> {
+        let prefix = " ".repeat(indent);
+        let mut overloaded_name = None;
+        for stmt in stmts {
+            let start = self.lines.len();
+            let kind = match stmt.node {
+                StmtLikeContent::ImportFrom(import) => {
+                    self.lines.push(format!("{prefix}{}", import.as_code()));
+                    Kind::Import
+                }
+                StmtLikeContent::ImportName(import) => {
+                    self.lines.push(format!("{prefix}{}", import.as_code()));
+                    Kind::Import
+                }
+                StmtLikeContent::TypeAlias(alias) => {
+                    if !self.is_public(alias.name_def().as_code()) {
+                        continue;
+                    }
+                    self.lines.push(format!("{prefix}{}", alias.as_code()));
+                    Kind::Variable
+                }
+                StmtLikeContent::Assignment(assignment) => {
+                    if !self.add_assignment(assignment, &prefix) {
+                        continue;
+                    }
+                    Kind::Variable
+                }
+                StmtLikeContent::FunctionDef(func) => {
+                    if !self.add_function(func, None, false, &prefix, class, &mut overloaded_name) {
+                        continue;
+                    }
+                    Kind::Function
+                }
+                StmtLikeContent::AsyncStmt(async_stmt) => {
+                    let AsyncStmtContent::FunctionDef(func) = async_stmt.unpack() else {
+                        continue;
+                    };
+                    if !self.add_function(func, None, true, &prefix, class, &mut overloaded_name) {
+                        continue;
+                    }
+                    Kind::Function
+                }
+                StmtLikeContent::ClassDef(class_def) => {
+                    if !self.add_class(class_def, None, indent) {
+                        continue;
+                    }
+                    Kind::Class
+                }
+                StmtLikeContent::Decorated(decorated) => {
+                    let decorators = Some(decorated.decorators());
+                    let added = match decorated.decoratee() {
+                        Decoratee::FunctionDef(func) => self.add_function(
+                            func,
+                            decorators,
+                            false,
+                            &prefix,
+                            class,
+                            &mut overloaded_name,
+                        ),
+                        Decoratee::AsyncFunctionDef(func) => self.add_function(
+                            func,
+                            decorators,
+                            true,
+                            &prefix,
+                            class,
+                            &mut overloaded_name,
+                        ),
+                        Decoratee::ClassDef(class_def) => {
+                            self.add_class(class_def, decorators, indent)
+                        }
+                    };
+                    if !added {
+                        continue;
+                    }
+                    match decorated.decoratee() {
+                        Decoratee::ClassDef(_) => Kind::Class,
+                        _ => Kind::Function,
+                    }
+                }
+                StmtLikeContent::IfStmt(if_stmt) => {
+                    if !self.add_if_stmt(if_stmt, indent, class) {
+                        continue;
+                    }
+                    Kind::If
+                }
+                StmtLikeContent::TryStmt(try_stmt) => {
+                    // Stubs cannot contain `try` statements, the definitions that are available
+                    // without an exception are used instead.
+                    for block in try_stmt.iter_blocks() {
+                        let block = match block {
+                            TryBlockType::Try(block) => block,
+                            TryBlockType::Else(else_block) => else_block.block(),
+                            TryBlockType::Finally(finally_block) => finally_block.block(),
+                            TryBlockType::Except(_) | TryBlockType::ExceptStar(_) => continue,
+                        };
+                        previous_kind =
+                            self.add_stmts(block.iter_stmt_likes(), indent, class, previous_kind);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            // Classes, if blocks and groups of the same kind of statements are separated by empty
+            // lines.
+            if previous_kind.as_ref().is_some_and(|previous| {
+                *previous != kind
+                    || matches!(kind, Kind::Class | Kind::If)
+                    || matches!(previous, Kind::Class | Kind::If)
+            }) {
+                self.lines.insert(start, String::new())
+            }
+            previous_kind = Some(kind);
+        }
+        previous_kind
+    }
+
+    /// Returns whether something was added. Branches without definitions are kept with a `pass`
+    /// body so that the conditions of the following branches stay intact.
+    fn add_if_stmt(
+        &mut self,
+        if_stmt: IfStmt<'db>,
+        indent: usize,
+        class: Option<ClassNodeRef<'db>>,
+    ) -> bool {
+        let prefix = " ".repeat(indent);
+        let start = self.lines.len();
+        let mut added = false;
+        for (i, block) in if_stmt.iter_blocks().enumerate() {
+            let (header, block) = match block {
+                IfBlockType::If(condition, block) => {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    (format!("{prefix}{keyword} {}:", condition.as_code()), block)
+                }
+                IfBlockType::Else(else_block) => (format!("{prefix}else:"), else_block.block()),
+            };
+            self.lines.push(header);
+            let body_start = self.lines.len();
+            self.add_stmts(block.iter_stmt_likes(), indent + 4, class, None);
+            if self.lines.len() == body_start {
+                self.lines.push(format!("{prefix}    pass"));
+            } else {
+                added = true;
+            }
+        }
+        if !added {
+            self.lines.truncate(start);
+        }
+        added
+    }
+
+    /// Returns whether something was added.
+    fn add_assignment(&mut self, assignment: parsa_python_cst::Assignment, prefix: &str) -> bool {
+        match assignment.unpack() {
+            AssignmentContent::WithAnnotation(Target::Name(name_def), annotation, right_side) => {
+                let name = name_def.as_code();
+                if !self.is_public(name) {
+                    return false;
+                }
+                let annotation = annotation.expression().as_code();
+                // Final and type alias definitions need their values.
+                let is_special = ["Final", "TypeAlias"].iter().any(|special| {
+                    annotation == *special || annotation.ends_with(&format!(".{special}"))
+                });
+                if is_special && right_side.is_some() {
+                    self.lines.push(format!("{prefix}{}", assignment.as_code()));
+                } else {
+                    self.lines.push(format!("{prefix}{name}: {annotation}"));
+                }
+                true
+            }
+            AssignmentContent::Normal(mut targets, _) => {
+                let Some(Target::Name(name_def)) = targets.next() else {
+                    return false;
+                };
+                if targets.next().is_some() {
+                    return false;
+                }
+                let name = name_def.as_code();
+                if name == "__all__" || self.is_type_definition(assignment) {
+                    self.lines.push(format!("{prefix}{}", assignment.as_code()));
+                    return true;
+                }
+                if !self.is_public(name) {
+                    return false;
+                }
+                let annotation = self.variable_annotation(name_def);
+                self.lines.push(format!("{prefix}{name}: {annotation}"));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Type aliases like `X = int | str` and definitions like `T = TypeVar("T")` or
+    /// `P = NamedTuple("P", [("x", int)])` are copied.
+    fn is_type_definition(&self, assignment: parsa_python_cst::Assignment) -> bool {
+        let type_ref = assignment_type_node_ref(self.file, assignment);
+        if type_ref.point().calculated()
+            && matches!(type_ref.maybe_complex(), Some(ComplexPoint::TypeAlias(a)) if a.is_valid())
+        {
+            return true;
+        }
+        let Some((name_def, _, _)) = assignment.maybe_simple_type_expression_assignment() else {
+            return false;
+        };
+        let Some(inferred) = NodeRef::new(self.file, name_def.index()).maybe_inferred(&self.i_s)
+        else {
+            return false;
+        };
+        matches!(
+            inferred.maybe_complex_point(self.db),
+            Some(
+                ComplexPoint::TypeVarLike(_)
+                    | ComplexPoint::NamedTupleDefinition(_)
+                    | ComplexPoint::TypedDictDefinition(_)
+            )
+        ) || matches!(inferred.as_cow_type(&self.i_s).as_ref(), Type::Type(_))
+    }
+
+    fn variable_annotation(&mut self, name_def: NameDef) -> String {
+        if self.options.infer_types
+            && let Some(inferred) =
+                NodeRef::new(self.file, name_def.index()).maybe_inferred(&self.i_s)
+            && let Some(annotation) = self.annotation(&inferred.as_cow_type(&self.i_s))
+        {
+            return annotation;
+        }
+        self.incomplete()
+    }
+
+    /// Returns whether something was added.
+    fn add_function(
+        &mut self,
+        func: FunctionDef<'db>,
+        decorators: Option<Decorators>,
+        is_async: bool,
+        prefix: &str,
+        class: Option<ClassNodeRef<'db>>,
+        overloaded_name: &mut Option<&'db str>,
+    ) -> bool {
+        let name = func.name().as_code();
+        if !self.is_public(name) {
+            return false;
+        }
+        let is_overload = decorators.is_some_and(|decorators| {
+            decorators.iter().any(|decorator| {
+                let code = decorator.named_expression().as_code();
+                code == "overload" || code.ends_with(".overload")
+            })
+        });
+        // The implementation of an overloaded function is not part of a stub.
+        if is_overload {
+            *overloaded_name = Some(name);
+        } else if overloaded_name.take() == Some(name) {
+            return false;
+        }
+        if let Some(decorators) = decorators {
+            for decorator in decorators.iter() {
+                self.lines.push(format!(
+                    "{prefix}@{}",
+                    decorator.named_expression().as_code()
+                ));
+            }
+        }
+        let mut params = vec![];
+        let mut had_positional_only = false;
+        let mut had_star = false;
+        for (i, param) in func.params().iter().enumerate() {
+            if had_positional_only && param.kind() != ParamKind::PositionalOnly {
+                had_positional_only = false;
+                params.push("/".to_string())
+            }
+            let param_prefix = match param.kind() {
+                ParamKind::PositionalOnly => {
+                    had_positional_only = true;
+                    ""
+                }
+                ParamKind::KeywordOnly if !had_star => {
+                    had_star = true;
+                    params.push("*".to_string());
+                    ""
+                }
+                ParamKind::Star => {
+                    had_star = true;
+                    "*"
+                }
+                ParamKind::StarStar => "**",
+                _ => "",
+            };
+            let param_name = param.name_def().as_code();
+            let mut formatted = format!("{param_prefix}{param_name}");
+            let annotation = match param.annotation() {
+                Some(ParamAnnotation::Annotation(annotation)) => {
+                    Some(annotation.expression().as_code().to_string())
+                }
+                Some(ParamAnnotation::StarAnnotation(annotation)) => {
+                    Some(match annotation.unpack() {
+                        parsa_python_cst::StarAnnotationContent::Expression(e) => {
+                            e.as_code().to_string()
+                        }
+                        parsa_python_cst::StarAnnotationContent::StarExpression(e) => {
+                            e.as_code().to_string()
+                        }
+                    })
+                }
+                // `self` and `cls` are never annotated.
+                None if i == 0 && class.is_some() => None,
+                None => param
+                    .default()
+                    .filter(|default| self.options.infer_types && !default.is_none_literal())
+                    .and_then(|default| {
+                        let inferred = self.file.inference(&self.i_s).infer_expression(default);
+                        let t = inferred
+                            .avoid_implicit_literal(&self.i_s)
+                            .as_type(&self.i_s);
+                        self.annotation(&t)
+                    }),
+            };
+            let has_annotation = annotation.is_some();
+            if let Some(annotation) = annotation {
+                formatted += ": ";
+                formatted += &annotation;
+            }
+            if param.default().is_some() {
+                formatted += if has_annotation { " = ..." } else { "=..." };
+            }
+            params.push(formatted)
+        }
+        if had_positional_only {
+            params.push("/".to_string())
+        }
+        let return_annotation = match func.return_annotation() {
+            Some(annotation) => Some(annotation.expression().as_code().to_string()),
+            None if name == "__init__" => Some("None".to_string()),
+            None if self.options.infer_types => self.inferred_return_annotation(func),
+            None => None,
+        };
+        let async_ = if is_async { "async " } else { "" };
+        let type_params = func
+            .type_params()
+            .map(|type_params| type_params.as_code())
+            .unwrap_or_default();
+        let params = params.join(", ");
+        self.lines.push(match return_annotation {
+            Some(r) => format!("{prefix}{async_}def {name}{type_params}({params}) -> {r}: ..."),
+            None => format!("{prefix}{async_}def {name}{type_params}({params}): ..."),
+        });
+        true
+    }
+
+    fn inferred_return_annotation(&self, func: FunctionDef) -> Option<String> {
+        let function =
+            Function::new_with_unknown_parent(self.db, NodeRef::new(self.file, func.index()));
+        let mut t = function.inferred_return_type(&self.i_s);
+        if let Some(new_t) = t.replace_type_var_likes(self.db, &mut |usage| {
+            usage
+                .as_type_var_like()
+                .is_untyped()
+                .then(|| usage.as_any_generic_item())
+        }) {
+            t = Cow::Owned(new_t);
+        }
+        self.annotation(&t)
+    }
+
+    /// Returns whether something was added.
+    fn add_class(
+        &mut self,
+        class_def: ClassDef<'db>,
+        decorators: Option<Decorators>,
+        indent: usize,
+    ) -> bool {
+        let name = class_def.name().as_code();
+        if !self.is_public(name) {
+            return false;
+        }
+        let prefix = " ".repeat(indent);
+        if let Some(decorators) = decorators {
+            for decorator in decorators.iter() {
+                self.lines.push(format!(
+                    "{prefix}@{}",
+                    decorator.named_expression().as_code()
+                ));
+            }
+        }
+        let (type_params, arguments, block) = class_def.unpack();
+        let type_params = type_params.map(|t| t.as_code()).unwrap_or_default();
+        let arguments = arguments
+            .map(|arguments| format!("({})", arguments.as_code()))
+            .unwrap_or_default();
+        let header = format!("{prefix}class {name}{type_params}{arguments}:");
+        let header_index = self.lines.len();
+        self.lines.push(header);
+        let body_start = self.lines.len();
+        let class_ref = ClassNodeRef::new(self.file, class_def.index());
+        self.add_self_attributes(class_ref, indent + 4);
+        let previous_kind = (self.lines.len() > body_start).then_some(Kind::Variable);
+        self.add_stmts(
+            block.iter_stmt_likes(),
+            indent + 4,
+            Some(class_ref),
+            previous_kind,
+        );
+        if self.lines.len() == body_start {
+            self.lines[header_index] += " ...";
+        }
+        true
+    }
+
+    /// Attributes that are only assigned to `self` in methods, e.g. `self.x = 1`.
+    fn add_self_attributes(&mut self, class_ref: ClassNodeRef<'db>, indent: usize) {
+        let storage = class_ref.class_storage();
+        let mut names: Vec<_> = storage
+            .self_symbol_table
+            .iter()
+            .filter(|(name, _)| storage.class_symbol_table.lookup_symbol(name).is_none())
+            .map(|(name, index)| (*index, name))
+            .collect();
+        names.sort();
+        for (index, name) in names {
+            if !self.is_public(name) {
+                continue;
+            }
+            let definition = NodeRef::new(self.file, index);
+            let written = self
+                .file
+                .tree
+                .annotation_of_default_or_assignment(definition.node_start_position())
+                .map(|annotation| annotation.as_code().to_string());
+            let annotation = written.or_else(|| {
+                if !self.options.infer_types {
+                    return None;
+                }
+                let instance = Instance::new(Class::with_self_generics(self.db, class_ref), None);
+                let t = instance
+                    .lookup(&self.i_s, name, InstanceLookupOptions::new(&|_| false))
+                    .lookup
+                    .into_inferred()
+                    .as_type(&self.i_s);
+                self.annotation(&t)
+            });
+            let annotation = annotation.unwrap_or_else(|| self.incomplete());
+            self.lines
+                .push(format!("{}{name}: {annotation}", " ".repeat(indent)));
+        }
+    }
+
+    fn annotation(&self, t: &Type) -> Option<String> {
+        if t.has_any(&self.i_s) {
+            return None;
+        }
+        writable_annotation(self.db, self.file, t)
+    }
+
+    fn incomplete(&mut self) -> String {
+        self.uses_incomplete = true;
+        "Incomplete".to_string()
+    }
+
+    fn is_public(&self, name: &str) -> bool {
+        self.options.include_private
+            || !name.starts_with('_')
+            || name.starts_with("__") && name.ends_with("__")
+    }
+}
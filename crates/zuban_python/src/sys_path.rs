@@ -3,7 +3,7 @@ use std::{
     sync::Arc,
 };
 
-use vfs::{AbsPath, LocalFS, NormalizedPath, VfsHandler, WorkspaceKind};
+use vfs::{AbsPath, LocalFS, NormalizedPath, PathWithScheme, VfsHandler, WorkspaceKind};
 
 use crate::{PythonVersion, Settings};
 
@@ -144,6 +144,112 @@ fn add_editable_src_packages(
     }
 }
 
+/// Editable installs of setuptools and hatchling (`pip install -e .`) use a .pth file with a line
+/// like `import __editable___foo_1_0_finder; ...` or `import _editable_impl_foo`. Such a finder
+/// module maps the names of packages to their sources. Returns the directories that contain these
+/// packages together with the names of the top-level packages, because the other entries of the
+/// directories are not importable.
+pub(crate) fn editable_finder_roots(
+    handler: &dyn VfsHandler,
+    site_packages: &AbsPath,
+    import_line: &str,
+) -> Vec<(Arc<NormalizedPath>, Vec<Box<str>>)> {
+    let statement = import_line.split(';').next().unwrap_or_default();
+    let Some(modules) = statement.trim().strip_prefix("import") else {
+        return vec![];
+    };
+    let mut roots: Vec<(Arc<NormalizedPath>, Vec<Box<str>>)> = vec![];
+    for module in modules.split(',').map(str::trim) {
+        let is_finder = module.starts_with("__editable__") && module.ends_with("_finder")
+            || module.starts_with("_editable_impl_");
+        if !is_finder {
+            continue;
+        }
+        let finder_path = handler
+            .normalize_rc_path(handler.absolute_path(site_packages, &format!("{module}.py")));
+        let Some(code) =
+            handler.read_and_watch_file(&PathWithScheme::with_file_scheme(finder_path.clone()))
+        else {
+            tracing::info!("Did not find the editable finder {finder_path}");
+            continue;
+        };
+        for (name, source) in editable_mappings(&code) {
+            match package_parent_dir(&name, &source) {
+                Some(dir) => {
+                    let dir = handler.normalize_rc_path(handler.absolute_path(site_packages, dir));
+                    let top_level = name.split('.').next().unwrap_or_default().into();
+                    match roots.iter_mut().find(|(root, _)| *root == dir) {
+                        Some((_, packages)) => packages.push(top_level),
+                        None => roots.push((dir, vec![top_level])),
+                    }
+                }
+                None => tracing::warn!(
+                    "Ignored the editable package {name:?} in {finder_path}, because it is not \
+                     in a directory with the same name: {source:?}"
+                ),
+            }
+        }
+    }
+    roots
+}
+
+/// Finds the package mappings of setuptools (`MAPPING = {'foo': '/src/foo'}`) and of the
+/// editables library (`F.map_module('foo', '/src/foo/__init__.py')`).
+fn editable_mappings(code: &str) -> Vec<(String, String)> {
+    let mut mappings = vec![];
+    if let Some(start) = code.find("MAPPING")
+        && let Some(brace) = code[start..].find('{')
+    {
+        let mut rest = &code[start + brace + 1..];
+        while let Some((name, after_name)) = next_string_literal(rest)
+            && let Some((source, after_source)) = next_string_literal(after_name)
+        {
+            mappings.push((name, source));
+            rest = after_source;
+        }
+    }
+    for (index, _) in code.match_indices("map_module(") {
+        let rest = &code[index + "map_module(".len()..];
+        if let Some((name, rest)) = next_string_literal(rest)
+            && let Some((source, _)) = next_string_literal(rest)
+        {
+            mappings.push((name, source));
+        }
+    }
+    mappings
+}
+
+/// Skips separators like `,` and `:` and parses a simple string literal like `'foo'`.
+fn next_string_literal(code: &str) -> Option<(String, &str)> {
+    let code = code.trim_start_matches(|c: char| c.is_whitespace() || c == ',' || c == ':');
+    let quote = code.chars().next().filter(|&c| c == '\'' || c == '"')?;
+    let mut result = String::new();
+    let mut chars = code.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => result.push(chars.next()?.1),
+            c if c == quote => return Some((result, &code[i + 1..])),
+            c => result.push(c),
+        }
+    }
+    None
+}
+
+/// The directory that needs to be on the sys path to import the package `name` from `source`.
+fn package_parent_dir<'x>(name: &str, source: &'x str) -> Option<&'x str> {
+    let mut path = Path::new(source);
+    if path.file_name()? == "__init__.py" {
+        path = path.parent()?;
+    }
+    for part in name.rsplit('.') {
+        if path.file_stem()?.to_str()? != part {
+            return None;
+        }
+        path = path.parent()?;
+    }
+    path.to_str()
+}
+
 fn lib_path(settings: &Settings) -> Option<String> {
     let check = |path: String| {
         let os_path = Path::new(&path).join("os.py");
@@ -227,6 +333,19 @@ fn lib_path(settings: &Settings) -> Option<String> {
                 if let result @ Some(_) = check_path_buf(parent.join("Lib")) {
                     return result;
                 }
+                // Embedded distributions contain the standard library as e.g. python312.zip.
+                if let Some(zipped) = parent.read_dir().ok().and_then(|entries| {
+                    entries.flatten().map(|entry| entry.path()).find(|path| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .and_then(|name| name.strip_prefix("python3"))
+                            .and_then(|name| name.strip_suffix(".zip"))
+                            .is_some_and(|minor| minor.parse::<usize>().is_ok())
+                    })
+                }) && let result @ Some(_) = check_path_buf(zipped)
+                {
+                    return result;
+                }
             } else {
                 let lib_path = parent.join("lib");
                 let mut found = vec![];
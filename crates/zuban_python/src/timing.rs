@@ -0,0 +1,115 @@
+//! Durations of the phases of checking a file and estimates of the memory they use, which show
+//! the modules that dominate the check time of a project.
+
+use std::{sync::OnceLock, time::Duration};
+
+use parsa_python_cst::Tree;
+use vfs::{FileIndex, PathWithScheme};
+
+use crate::{
+    database::{ComplexPoint, Database, Point},
+    file::PythonFile,
+    matching::protocol_cache_statistics,
+};
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct FileTimings {
+    pub parse: Duration,
+    pub name_binding: Duration,
+    // Set when the diagnostics of the module are calculated for the first time. Since inference
+    // is lazy, this includes inferring the parts of other modules that were needed.
+    pub check: OnceLock<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileTimingStats {
+    pub path: PathWithScheme,
+    pub module: String,
+    pub parse: Duration,
+    pub name_binding: Duration,
+    /// `None` for files that were loaded, but not checked, like most stubs in typeshed.
+    pub check: Option<Duration>,
+}
+
+impl FileTimingStats {
+    pub fn total(&self) -> Duration {
+        self.parse + self.name_binding + self.check.unwrap_or_default()
+    }
+}
+
+/// The approximate memory of the loaded files in bytes. Memory of data structures that are
+/// referenced by types is not included.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryUsage {
+    pub code: usize,
+    pub syntax_trees: usize,
+    pub inference_points: usize,
+    pub complex_points: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.code + self.syntax_trees + self.inference_points + self.complex_points
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimingStats {
+    /// All loaded files sorted by their total duration, the slowest first.
+    pub files: Vec<FileTimingStats>,
+    pub protocol_cache_hits: usize,
+    pub protocol_cache_misses: usize,
+    pub memory: MemoryUsage,
+}
+
+impl TimingStats {
+    /// The share of protocol matches that were cached in percent, `None` if there were none.
+    pub fn protocol_cache_hit_rate(&self) -> Option<f64> {
+        let total = self.protocol_cache_hits + self.protocol_cache_misses;
+        (total > 0).then(|| self.protocol_cache_hits as f64 * 100.0 / total as f64)
+    }
+}
+
+pub(crate) fn timing_stats(db: &Database) -> TimingStats {
+    let mut files = vec![];
+    let mut memory = MemoryUsage::default();
+    for index in 0..db.vfs.files.len() {
+        let Some(file) = db.vfs.file(FileIndex(index as u32)) else {
+            continue;
+        };
+        add_memory_usage(&mut memory, file);
+        // Sub files like type comments are part of their parent file.
+        if file.super_file.is_some() {
+            continue;
+        }
+        files.push(FileTimingStats {
+            path: file.file_path_with_scheme(db).clone(),
+            module: file.qualified_name(db),
+            parse: file.timings.parse,
+            name_binding: file.timings.name_binding,
+            check: file.timings.check.get().copied(),
+        })
+    }
+    files.sort_by_key(|f| std::cmp::Reverse(f.total()));
+    let (protocol_cache_hits, protocol_cache_misses) = protocol_cache_statistics();
+    TimingStats {
+        files,
+        protocol_cache_hits,
+        protocol_cache_misses,
+        memory,
+    }
+}
+
+fn add_memory_usage(memory: &mut MemoryUsage, file: &PythonFile) {
+    let tree: &Tree = &file.tree;
+    memory.code += tree.code().len();
+    memory.syntax_trees += tree.nodes_memory_size();
+    memory.inference_points += tree.length() * size_of::<Point>();
+    memory.complex_points += file.complex_points.len() * size_of::<ComplexPoint>();
+}
+
+pub(crate) fn file_memory(file: &PythonFile) -> usize {
+    let mut memory = MemoryUsage::default();
+    add_memory_usage(&mut memory, file);
+    memory.total()
+}
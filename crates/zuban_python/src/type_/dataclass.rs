@@ -523,6 +523,10 @@ fn calculate_init_of_dataclass(db: &Database, dataclass: &Arc<Dataclass>) -> Ini
             }
         }
     }
+    let plugins = &db.project.plugins;
+    if !plugins.is_empty() {
+        plugins.dataclass_init_params(i_s, cls, &mut params);
+    }
     if cls.incomplete_mro(i_s.db) {
         add_any_params_to_params(&mut params);
     }
@@ -761,12 +765,15 @@ fn apply_default_options_from_dataclass_transform_field<'db>(
     let mut apply_from_callable = |c: &CallableContent| {
         if let Some(func) = NodeRef::from_link(i_s.db, c.defined_at).maybe_function() {
             for p in func.params().iter() {
-                // Currently this is only applied for init in Mypy.
-                if p.name_def().as_code() == "init"
-                    && let Some(default) = p.default()
-                    && let Some(b) = default.maybe_simple_bool()
-                {
-                    options.init = b;
+                // Mypy only applies this for init, but like Pyright we also use the default of
+                // kw_only, which is how e.g. attrs' `kw_only_field` is typically defined.
+                let Some(b) = p.default().and_then(|default| default.maybe_simple_bool()) else {
+                    continue;
+                };
+                match p.name_def().as_code() {
+                    "init" => options.init = b,
+                    "kw_only" => options.kw_only = Some(b),
+                    _ => (),
                 }
             }
         }
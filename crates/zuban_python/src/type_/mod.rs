@@ -65,7 +65,7 @@ pub(crate) use self::{
     typed_dict::{
         ExtraItemsType, TypedDict, TypedDictGenerics, TypedDictMember, TypedDictMembers,
         check_typed_dict_call, infer_typed_dict_arg, initialize_typed_dict, lookup_on_typed_dict,
-        maybe_add_extra_keys_issue,
+        maybe_add_extra_keys_issue, narrow_partial_typed_dict_literal,
     },
     union::{UnionEntry, UnionType, simplified_union_from_iterators_with_format_index},
 };
@@ -1119,15 +1119,19 @@ impl Type {
             Self::Literal(literal) => literal.format(format_data),
             Self::NewType(n) => n.format(format_data),
             Self::RecursiveType(rec) => {
-                if let Some(generics) = &rec.generics
-                    && format_data.style != FormatStyle::MypyRevealType
-                {
-                    return format!(
+                let with_generics = |generics: &GenericsList| {
+                    format!(
                         "{}[{}]",
                         rec.name(format_data.db),
                         generics.format(format_data)
                     )
-                    .into();
+                    .into()
+                };
+                if let Some(generics) = &rec.generics
+                    && format_data.style != FormatStyle::MypyRevealType
+                    && !format_data.expand_aliases
+                {
+                    return with_generics(generics);
                 }
 
                 let avoid = AvoidRecursionFor::RecursiveType(rec);
@@ -1144,6 +1148,8 @@ impl Type {
                     Err(()) => {
                         if format_data.style == FormatStyle::MypyRevealType {
                             "...".into()
+                        } else if let Some(generics) = &rec.generics {
+                            with_generics(generics)
                         } else {
                             rec.name(format_data.db).into()
                         }
@@ -2025,6 +2031,9 @@ impl NewType {
     }
 
     pub fn format(&self, format_data: &FormatData) -> Box<str> {
+        if format_data.expand_aliases {
+            return self.type_.format(format_data);
+        }
         match format_data.style {
             FormatStyle::Short if !format_data.should_format_qualified(self.name_string) => {
                 self.name(format_data.db).into()
@@ -2135,7 +2144,7 @@ impl Literal {
         }
     }
 
-    fn format_inner(&self, db: &Database) -> Cow<'_, str> {
+    pub fn format_inner(&self, db: &Database) -> Cow<'_, str> {
         match self.value(db) {
             LiteralValue::String(s) => Cow::Owned(str_repr(s)),
             LiteralValue::Int(i) => Cow::Owned(format!("{i}")),
@@ -8,6 +8,8 @@ use super::{
     GenericsList, LookupResult, NeverCause, ParamType, RecursiveType, ReplaceTypeVarLikes,
     StringSlice, Type, TypeVarLikeUsage, TypeVarLikes, utils::method_with_fallback,
 };
+use parsa_python_cst::{AtomContent, Dict, DictElement};
+
 use crate::{
     arguments::{ArgKind, Args, InferredArg},
     database::{Database, PointLink},
@@ -283,6 +285,31 @@ impl TypedDict {
         }
     }
 
+    /// The same TypedDict with all keys being not required, which is what `update` accepts.
+    pub fn as_partial(&self, db: &Database) -> Arc<TypedDict> {
+        let ms = self.members(db);
+        let mut members: Vec<_> = ms.named.as_ref().into();
+        for member in members.iter_mut() {
+            member.required = false;
+        }
+        TypedDict::new(
+            self.name,
+            TypedDictMembers {
+                named: members.into_boxed_slice(),
+                extra_items: ms.extra_items.clone(),
+            },
+            self.defined_at,
+            self.generics.clone(),
+        )
+    }
+
+    /// Like [`Self::find_entry`], but the extra items of a closed TypedDict are not an entry,
+    /// so arguments for unknown keys are reported as unexpected.
+    pub fn find_kwarg_entry(&self, db: &Database, name: &str) -> Option<TypedDictEntry<'_>> {
+        self.find_entry(db, name)
+            .filter(|entry| entry.name.is_some() || !entry.type_.is_never())
+    }
+
     fn qualified_name(&self, db: &Database) -> Option<String> {
         let name = self.name?;
         let module = db.loaded_python_file(name.file_index).qualified_name(db);
@@ -741,6 +768,12 @@ fn typed_dict_setdefault_internal<'db>(
     let maybe_had_literals = inferred_name.run_on_str_literals(i_s, |key| {
         Some(Inferred::from_type({
             if let Some(member) = td.find_entry(i_s.db, key) {
+                if member.read_only {
+                    args.add_issue(
+                        i_s,
+                        IssueKind::TypedDictReadOnlyKeyMutated { key: key.into() },
+                    );
+                }
                 if !member
                     .type_
                     .is_simple_super_type_of(i_s, &default.as_cow_type(i_s))
@@ -1094,56 +1127,236 @@ fn typed_dict_update_internal<'db>(
     td: &TypedDict,
     args: &dyn Args<'db>,
 ) -> Option<Inferred> {
-    let ms = td.members(i_s.db);
-    let mut members: Vec<_> = ms.named.as_ref().into();
-    for member in members.iter_mut() {
-        member.required = false;
+    let mut inf = args.maybe_single_positional_arg(
+        i_s,
+        &mut ResultContext::new_known(&Type::TypedDict(td.as_partial(i_s.db))),
+    )?;
+    if let Some(arg) = args.iter(i_s.mode).next()
+        && let ArgKind::Positional(positional) = &arg.kind
+        && let Some(AtomContent::Dict(dict)) =
+            positional.named_expr.expression().maybe_unpacked_atom()
+    {
+        inf = narrow_partial_typed_dict_literal(i_s, inf, dict);
+    }
+    if let Type::TypedDict(from_arg) = inf.as_cow_type(i_s).as_ref() {
+        check_read_only_update(i_s, td, from_arg, args);
+    }
+    Some(Inferred::new_none())
+}
+
+/// A dict literal that is inferred with a partial TypedDict as context has the type of the
+/// context, but only the keys of the literal are actually updated. This matters for read-only
+/// keys.
+pub(crate) fn narrow_partial_typed_dict_literal(
+    i_s: &InferenceState,
+    inferred: Inferred,
+    dict: Dict,
+) -> Inferred {
+    let db = i_s.db;
+    let Type::TypedDict(td) = inferred.as_cow_type(i_s).into_owned() else {
+        return inferred;
+    };
+    let mut keys = vec![];
+    for element in dict.iter_elements() {
+        let DictElement::KeyValue(key_value) = element else {
+            return inferred;
+        };
+        let Some(key) = key_value.key().maybe_single_string_literal() else {
+            return inferred;
+        };
+        keys.push(key.content());
     }
-    let expected = TypedDict::new(
+    let ms = td.members(db);
+    let named: Box<[_]> = ms
+        .named
+        .iter()
+        .filter(|m| keys.contains(&m.name.as_str(db)))
+        .cloned()
+        .collect();
+    let extra_items = if keys.len() > named.len() {
+        ms.extra_items.clone()
+    } else {
+        Some(ExtraItemsType {
+            t: Type::Never(NeverCause::Other),
+            read_only: false,
+        })
+    };
+    Inferred::from_type(Type::TypedDict(TypedDict::new(
         td.name,
-        TypedDictMembers {
-            named: members.into_boxed_slice(),
-            extra_items: ms.extra_items.clone(),
-        },
+        TypedDictMembers { named, extra_items },
         td.defined_at,
         td.generics.clone(),
-    );
-    let inf = args.maybe_single_positional_arg(
-        i_s,
-        &mut ResultContext::new_known(&Type::TypedDict(expected)),
-    )?;
-    if let Type::TypedDict(from_arg) = inf.as_cow_type(i_s).as_ref() {
-        let arg_members = from_arg.members(i_s.db);
-        for member in arg_members.named.iter() {
-            let name = member.name.as_str(i_s.db);
-            if let Some(e) = td.find_entry(i_s.db, name)
-                && e.read_only
-                && !member.type_.is_never()
-            {
-                args.add_issue(
-                    i_s,
-                    IssueKind::TypedDictUpdateOfReadOnlyMember { name: name.into() },
-                );
-            }
-        }
-        let members = td.members(i_s.db);
-        if let Some(arg_extra) = arg_members.extra_items.as_ref()
-            && !arg_extra.t.is_never()
-            && (members.named.iter().any(|m| m.read_only)
-                || members
-                    .extra_items
-                    .as_ref()
-                    .is_some_and(|extra| extra.read_only))
+    )))
+}
+
+fn check_read_only_update(
+    i_s: &InferenceState,
+    td: &TypedDict,
+    from_arg: &TypedDict,
+    args: &dyn Args,
+) {
+    let arg_members = from_arg.members(i_s.db);
+    for member in arg_members.named.iter() {
+        let name = member.name.as_str(i_s.db);
+        if let Some(e) = td.find_entry(i_s.db, name)
+            && e.read_only
+            && !member.type_.is_never()
         {
             args.add_issue(
                 i_s,
-                IssueKind::TypedDictUpdateOfReadOnlyMember {
-                    name: "extra_items".into(),
-                },
+                IssueKind::TypedDictUpdateOfReadOnlyMember { name: name.into() },
             );
         }
     }
-    Some(Inferred::new_none())
+    let members = td.members(i_s.db);
+    if let Some(arg_extra) = arg_members.extra_items.as_ref()
+        && !arg_extra.t.is_never()
+        && (members.named.iter().any(|m| m.read_only)
+            || members
+                .extra_items
+                .as_ref()
+                .is_some_and(|extra| extra.read_only))
+    {
+        args.add_issue(
+            i_s,
+            IssueKind::TypedDictUpdateOfReadOnlyMember {
+                name: "extra_items".into(),
+            },
+        );
+    }
+}
+
+fn typed_dict_or<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+    result_context: &mut ResultContext,
+    on_type_error: OnTypeError,
+    bound: Option<&Type>,
+) -> Inferred {
+    let Type::TypedDict(td) = bound.unwrap() else {
+        unreachable!();
+    };
+    typed_dict_operator_with_fallback(
+        i_s,
+        args,
+        result_context,
+        on_type_error,
+        td,
+        "__or__",
+        typed_dict_or_internal,
+    )
+}
+
+fn typed_dict_ror<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+    result_context: &mut ResultContext,
+    on_type_error: OnTypeError,
+    bound: Option<&Type>,
+) -> Inferred {
+    let Type::TypedDict(td) = bound.unwrap() else {
+        unreachable!();
+    };
+    typed_dict_operator_with_fallback(
+        i_s,
+        args,
+        result_context,
+        on_type_error,
+        td,
+        "__ror__",
+        typed_dict_or_internal,
+    )
+}
+
+fn typed_dict_ior<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+    result_context: &mut ResultContext,
+    on_type_error: OnTypeError,
+    bound: Option<&Type>,
+) -> Inferred {
+    let Type::TypedDict(td) = bound.unwrap() else {
+        unreachable!();
+    };
+    typed_dict_operator_with_fallback(
+        i_s,
+        args,
+        result_context,
+        on_type_error,
+        td,
+        "__ior__",
+        typed_dict_ior_internal,
+    )
+}
+
+/// The operators in typeshed use `Self`, which needs to be the TypedDict in the fallback.
+fn typed_dict_operator_with_fallback<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+    result_context: &mut ResultContext,
+    on_type_error: OnTypeError,
+    td: &Arc<TypedDict>,
+    name: &str,
+    handler: fn(&InferenceState<'db, '_>, &TypedDict, &dyn Args<'db>) -> Option<Inferred>,
+) -> Inferred {
+    handler(i_s, td, args).unwrap_or_else(|| {
+        Instance::new(i_s.db.python_state.typed_dict_class(), None)
+            .lookup(
+                i_s,
+                name,
+                InstanceLookupOptions::new(&|issue| args.add_issue(i_s, issue))
+                    .with_kind(LookupKind::OnlyType)
+                    .with_as_self_instance(&|| Type::TypedDict(td.clone())),
+            )
+            .lookup
+            .into_inferred()
+            .execute_with_details(i_s, args, result_context, on_type_error)
+    })
+}
+
+/// Like for `update`, the other operand of `|` only needs to contain some of the keys. Other
+/// operands are handled by the overloads in typeshed.
+fn typed_dict_or_internal<'db>(
+    i_s: &InferenceState<'db, '_>,
+    td: &TypedDict,
+    args: &dyn Args<'db>,
+) -> Option<Inferred> {
+    infer_partial_typed_dict_arg(i_s, td, args)?;
+    Some(Inferred::from_type(Type::TypedDict(Arc::new(td.clone()))))
+}
+
+fn typed_dict_ior_internal<'db>(
+    i_s: &InferenceState<'db, '_>,
+    td: &TypedDict,
+    args: &dyn Args<'db>,
+) -> Option<Inferred> {
+    let from_arg = infer_partial_typed_dict_arg(i_s, td, args)?;
+    check_read_only_update(i_s, td, &from_arg, args);
+    Some(Inferred::from_type(Type::TypedDict(Arc::new(td.clone()))))
+}
+
+fn infer_partial_typed_dict_arg<'db>(
+    i_s: &InferenceState<'db, '_>,
+    td: &TypedDict,
+    args: &dyn Args<'db>,
+) -> Option<Arc<TypedDict>> {
+    let expected = td.as_partial(i_s.db);
+    let inf = args.maybe_single_positional_arg(
+        i_s,
+        &mut ResultContext::new_known(&Type::TypedDict(expected.clone())),
+    )?;
+    match inf.as_cow_type(i_s).as_ref() {
+        // Dict literals were already checked against the context.
+        Type::TypedDict(from_arg)
+            if from_arg.defined_at == td.defined_at
+                || expected
+                    .matches(i_s, &mut Matcher::default(), from_arg, true)
+                    .bool() =>
+        {
+            Some(from_arg.clone())
+        }
+        _ => None,
+    }
 }
 
 pub(crate) fn initialize_typed_dict<'db>(
@@ -1226,6 +1439,9 @@ pub(crate) fn lookup_on_typed_dict<'a>(
         "__setitem__" => CustomBehavior::new_method(typed_dict_setitem, Some(bound())),
         "__delitem__" => CustomBehavior::new_method(typed_dict_delitem, Some(bound())),
         "update" => CustomBehavior::new_method(typed_dict_update, Some(bound())),
+        "__or__" => CustomBehavior::new_method(typed_dict_or, Some(bound())),
+        "__ror__" => CustomBehavior::new_method(typed_dict_ror, Some(bound())),
+        "__ior__" => CustomBehavior::new_method(typed_dict_ior, Some(bound())),
         "clear" if td.can_be_emptied(i_s.db) => {
             // Return an empty Callable
             return as_callable_without_params(i_s.db, td, "clear", Type::None);
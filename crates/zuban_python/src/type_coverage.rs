@@ -0,0 +1,151 @@
+//! Type coverage is the share of expressions with a precise type, i.e. a type that does not
+//! contain `Any`. Expressions in annotations are not counted and neither is unreachable code.
+//!
+//! The same data is used for Mypy's line precision reports, which classify every line of a file
+//! by its least precise expression.
+
+use std::ops::AddAssign;
+
+use parsa_python_cst::Expression;
+
+use crate::{
+    Document, PathWithScheme,
+    database::Database,
+    file::{File as _, PythonFile},
+    goto::with_i_s_non_self,
+    lines::split_lines,
+    type_::Type,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TypeCoverage {
+    pub expressions: usize,
+    /// Expressions whose types do not contain `Any`.
+    pub precise: usize,
+    pub any: usize,
+}
+
+impl TypeCoverage {
+    /// The percentage of precise expressions, 100 for files without expressions.
+    pub fn percentage(&self) -> f64 {
+        if self.expressions == 0 {
+            return 100.0;
+        }
+        self.precise as f64 * 100.0 / self.expressions as f64
+    }
+}
+
+impl AddAssign for TypeCoverage {
+    fn add_assign(&mut self, other: Self) {
+        self.expressions += other.expressions;
+        self.precise += other.precise;
+        self.any += other.any;
+    }
+}
+
+/// The precision of a line. The variants are ordered so that the maximum of the expressions in a
+/// line is the precision of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinePrecision {
+    /// Lines without expressions, e.g. comments or `pass`.
+    Empty,
+    /// Lines that are unreachable and therefore not checked.
+    Unanalyzed,
+    Precise,
+    /// Lines with types that contain `Any` like `list[Any]`.
+    Imprecise,
+    /// Lines with an expression of type `Any`.
+    Any,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModulePrecision {
+    pub path: PathWithScheme,
+    pub module: String,
+    pub code: String,
+    pub coverage: TypeCoverage,
+    /// One entry per line of the code.
+    pub lines: Vec<LinePrecision>,
+}
+
+impl<'project> Document<'project> {
+    pub fn type_coverage(&self) -> anyhow::Result<TypeCoverage> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        file.ensure_calculated_diagnostics_unless_cancelled(db)?;
+        Ok(file_type_coverage(db, file))
+    }
+}
+
+/// Expects the diagnostics of the file to be calculated, because the unreachable code is only
+/// known afterwards.
+pub(crate) fn file_type_coverage(db: &Database, file: &PythonFile) -> TypeCoverage {
+    let mut coverage = TypeCoverage::default();
+    for_each_expression(db, file, |_, precision| coverage.add(precision));
+    coverage
+}
+
+/// Like [`file_type_coverage`] with the precision of every line.
+pub(crate) fn module_precision(db: &Database, file: &PythonFile) -> ModulePrecision {
+    let code = file.tree.code();
+    let mut line_count = split_lines(code).count();
+    if code.ends_with(['\n', '\r']) {
+        line_count -= 1
+    }
+    let mut lines = vec![LinePrecision::Empty; line_count];
+    let mut coverage = TypeCoverage::default();
+    for_each_expression(db, file, |expr, precision| {
+        coverage.add(precision);
+        let line = file
+            .byte_to_position_infos(db, expr.start())
+            .line_zero_based();
+        if let Some(line) = lines.get_mut(line) {
+            *line = (*line).max(precision)
+        }
+    });
+    ModulePrecision {
+        path: file.file_path_with_scheme(db).clone(),
+        module: file.qualified_name(db),
+        code: code.to_string(),
+        coverage,
+        lines,
+    }
+}
+
+impl TypeCoverage {
+    fn add(&mut self, precision: LinePrecision) {
+        match precision {
+            LinePrecision::Precise => self.precise += 1,
+            LinePrecision::Imprecise | LinePrecision::Any => self.any += 1,
+            LinePrecision::Empty | LinePrecision::Unanalyzed => return,
+        }
+        self.expressions += 1;
+    }
+}
+
+fn for_each_expression<'file>(
+    db: &Database,
+    file: &'file PythonFile,
+    mut callback: impl FnMut(Expression<'file>, LinePrecision),
+) {
+    for expr in file.tree.runtime_expressions() {
+        if file.is_unreachable(expr.start()) {
+            callback(expr, LinePrecision::Unanalyzed);
+            continue;
+        }
+        // Most expressions were already inferred while type checking, but some (e.g. in unchecked
+        // functions or in conditions that are narrowed) still need to be inferred.
+        let precision = with_i_s_non_self(db, file, expr.parent_scope(), |i_s| {
+            let inferred = file.inference(i_s).infer_expression(expr);
+            let t = inferred.as_cow_type(i_s);
+            if matches!(t.as_ref(), Type::Any(_)) {
+                LinePrecision::Any
+            } else if t.has_any(i_s) {
+                LinePrecision::Imprecise
+            } else {
+                LinePrecision::Precise
+            }
+        });
+        callback(expr, precision)
+    }
+}
@@ -5,7 +5,9 @@ use std::{
     sync::Arc,
 };
 
-use parsa_python_cst::{Assignment, AssignmentContent, AtomContent, ClassDef, Name, TypeLike};
+use parsa_python_cst::{
+    Assignment, AssignmentContent, AtomContent, ClassDef, Name, Scope, TypeLike,
+};
 
 use super::{Callable, Instance, InstanceLookupOptions, LookupDetails, overload::OverloadResult};
 use crate::{
@@ -815,6 +817,16 @@ impl<'db: 'a, 'a> Class<'a> {
                 };
             }
         }
+        if super_count == 0
+            && !i_s.db.project.plugins.is_empty()
+            && let Some(t) = i_s.db.project.plugins.class_attribute(i_s, *self, name)
+        {
+            return bind(
+                LookupResult::UnknownName(Inferred::from_type(t)),
+                TypeOrClass::Class(*self),
+                0.into(),
+            );
+        }
         bind(
             LookupResult::None,
             TypeOrClass::Type(Cow::Borrowed(&Type::Any(AnyCause::Internal))),
@@ -1406,6 +1418,37 @@ impl<'db: 'a, 'a> Class<'a> {
             recoverable_error!("Expected a list when trying to fill Django generics");
             return;
         };
+        if self.mro(i_s.db).any(|(_, base)| {
+            base.maybe_class().is_some_and(|cls| {
+                cls.has_django_stubs_base_class(i_s.db)
+                    && matches!(cls.name(), "BaseManager" | "QuerySet")
+            })
+        }) {
+            // A manager like `objects = Manager()` in the body of a model manages that model.
+            let model = args.as_node_ref_internal().and_then(|node_ref| {
+                let Scope::Class(class_def) = node_ref.node_parent_scope() else {
+                    return None;
+                };
+                let model = Class::with_self_generics(
+                    i_s.db,
+                    ClassNodeRef::new(node_ref.file, class_def.index()),
+                );
+                model.is_django_model(i_s.db).then(|| model.as_type(i_s.db))
+            });
+            if let Some(model) = model {
+                let entries = list
+                    .iter()
+                    .map(|entry| match entry {
+                        GenericItem::TypeArg(Type::Any(AnyCause::UnknownTypeParam)) => {
+                            GenericItem::TypeArg(model.clone())
+                        }
+                        _ => entry.clone(),
+                    })
+                    .collect();
+                *generics = ClassGenerics::List(GenericsList::new_generics(entries))
+            }
+            return;
+        }
         let mut known_type = None;
         if matches!(
             self.name(),
@@ -2140,6 +2183,18 @@ impl<'db: 'a, 'a> Class<'a> {
             })
     }
 
+    /// Models are classes with the `ModelBase` metaclass of Django.
+    pub fn is_django_model(&self, db: &Database) -> bool {
+        let Some(class_infos) = self.maybe_cached_class_infos(db) else {
+            return false;
+        };
+        if !matches!(class_infos.metaclass, MetaclassState::Some(_)) {
+            return false;
+        }
+        let meta = class_infos.metaclass(db);
+        meta.has_django_stubs_base_class(db) && meta.name() == "ModelBase"
+    }
+
     fn is_django_field(&self, db: &Database) -> bool {
         self.has_django_stubs_base_class(db)
             && self.mro_maybe_without_object(db, true).any(|(_, base)| {
@@ -2505,20 +2560,16 @@ fn init_as_callable(
     init_class: TypeOrClass,
 ) -> Option<CallableLike> {
     let mut init_class = init_class;
-    let class_infos = cls.use_cached_class_infos(i_s.db);
-    if let MetaclassState::Some(_) = class_infos.metaclass {
-        let meta = class_infos.metaclass(i_s.db);
-        if meta.has_django_stubs_base_class(i_s.db) && meta.name() == "ModelBase" {
-            let c = CallableContent::new_non_generic(
-                i_s.db,
-                Some(DbString::StringSlice(cls.name_string_slice())),
-                None,
-                cls.node_ref.as_link(),
-                django_model_params(i_s, cls),
-                cls.as_type(i_s.db),
-            );
-            return Some(CallableLike::Callable(Arc::new(c)));
-        }
+    if cls.is_django_model(i_s.db) {
+        let c = CallableContent::new_non_generic(
+            i_s.db,
+            Some(DbString::StringSlice(cls.name_string_slice())),
+            None,
+            cls.node_ref.as_link(),
+            django_model_params(i_s, cls),
+            cls.as_type(i_s.db),
+        );
+        return Some(CallableLike::Callable(Arc::new(c)));
     }
     let cls = if matches!(cls.generics(), Generics::NotDefinedYet { .. }) {
         if let TypeOrClass::Class(init_class) = &mut init_class {
@@ -421,11 +421,33 @@ impl<'db: 'a + 'class, 'a, 'class> Function<'a, 'class> {
         if self.node_ref.point().calculated() {
             return;
         }
-        let maybe_computed = if no_type_check {
+        let mut maybe_computed = if no_type_check {
             None
         } else {
             self.ensure_cached_type_vars(i_s)
         };
+        if !no_type_check && !i_s.db.project.plugins.is_empty() {
+            let original = || {
+                maybe_computed.clone().unwrap_or_else(|| {
+                    self.as_callable_with_options(
+                        i_s,
+                        AsCallableOptions {
+                            first_param: FirstParamProperties::None,
+                            return_type: self.node_ref.return_type(i_s),
+                        },
+                    )
+                })
+            };
+            let fullname = self.qualified_name(i_s.db);
+            if let Some(callable) = i_s
+                .db
+                .project
+                .plugins
+                .function_signature(i_s, &fullname, &original)
+            {
+                maybe_computed = Some(callable);
+            }
+        }
         if let Some(decorated) = maybe_decorated {
             if let Some(class) = self.class {
                 let class = Class::with_self_generics(i_s.db, class.node_ref);
@@ -672,7 +694,13 @@ impl<'db: 'a + 'class, 'a, 'class> Function<'a, 'class> {
             if self.class.is_some()
                 && let Some(decorated) = self.node().maybe_decorated()
             {
+                let plugins = &i_s.db.project.plugins;
                 for decorator in decorated.decorators().iter() {
+                    if !plugins.is_empty()
+                        && plugins.is_implicit_classmethod(i_s, self.file, decorator)
+                    {
+                        return FirstParamKind::ClassOfSelf;
+                    }
                     let inf = self.file.inference(i_s).infer_decorator(decorator);
                     if let Some(saved_link) = inf.maybe_saved_link() {
                         if saved_link == i_s.db.python_state.classmethod_node_ref().as_link() {
@@ -1007,6 +1035,17 @@ impl<'db: 'a + 'class, 'a, 'class> Function<'a, 'class> {
         let mut inferred = Inferred::from_type(Type::Callable(Arc::new(c)));
         if !return_any_because_untyped {
             for (decorator_index, inferred_dec) in inferred_decs {
+                let plugins = &i_s.db.project.plugins;
+                if !plugins.is_empty()
+                    && let Some(t) = plugins.decorated(
+                        i_s,
+                        &inferred_dec.as_cow_type(i_s),
+                        &inferred.as_cow_type(i_s),
+                    )
+                {
+                    inferred = Inferred::from_type(t);
+                    continue;
+                }
                 let nr = NodeRef::new(self.node_ref.file, decorator_index);
                 inferred = inferred_dec.execute_with_details(
                     i_s,
@@ -2403,6 +2442,15 @@ fn infer_decorator_details(
     decorator: Decorator,
     had_first_annotation: bool,
 ) -> InferredDecorator {
+    let plugins = &i_s.db.project.plugins;
+    if !plugins.is_empty() && plugins.is_implicit_classmethod(i_s, file, decorator) {
+        return InferredDecorator::FunctionKind {
+            kind: FunctionKind::Classmethod {
+                had_first_self_or_class_annotation: had_first_annotation,
+            },
+            is_abstract: false,
+        };
+    }
     let inference = file.inference(i_s);
     let inf = inference.infer_decorator(decorator);
     if let Some(saved_link) = inf.maybe_saved_link() {
@@ -459,6 +459,21 @@ impl<'a> Instance<'a> {
         {
             return self.fill_total_ordering_method(i_s, name, options);
         }
+        if options.super_count == 0
+            && !i_s.db.project.plugins.is_empty()
+            && let Some(t) = i_s
+                .db
+                .project
+                .plugins
+                .class_attribute(i_s, self.class, name)
+        {
+            return LookupDetails {
+                class: TypeOrClass::Class(self.class),
+                lookup: LookupResult::UnknownName(Inferred::from_type(t)),
+                attr_kind,
+                mro_index: None,
+            };
+        }
         if options.kind == LookupKind::Normal && options.check_dunder_getattr {
             for method_name in ["__getattr__", "__getattribute__"] {
                 let l = self.lookup(
@@ -20,4 +20,6 @@ pub(crate) use instance::{
     execute_super,
 };
 pub(crate) use overload::{OverloadResult, OverloadedFunction};
-pub(crate) use typing::{execute_assert_type, execute_cast, execute_reveal_type};
+pub(crate) use typing::{
+    execute_assert_type, execute_cast, execute_reveal_locals, execute_reveal_type,
+};
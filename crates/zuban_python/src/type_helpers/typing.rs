@@ -1,9 +1,12 @@
 use std::borrow::Cow;
 
+use parsa_python_cst::{NAME_DEF_TO_NAME_DIFFERENCE, NameDef, NameDefParent, Scope};
+
 use crate::{
     arguments::{ArgKind, Args, InferredArg},
-    database::Database,
+    database::{Database, PointLink},
     diagnostics::IssueKind,
+    file::{ClassNodeRef, first_defined_name},
     format_data::FormatData,
     inference_state::InferenceState,
     inferred::Inferred,
@@ -147,6 +150,82 @@ pub(crate) fn execute_reveal_type<'db>(
     inferred
 }
 
+pub(crate) fn execute_reveal_locals<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+) -> Inferred {
+    if args.iter(i_s.mode).next().is_some() {
+        args.add_issue(
+            i_s,
+            IssueKind::TooManyArguments(r#" for "reveal_locals""#.into()),
+        );
+        return Inferred::new_any_from_error();
+    }
+    let Some(node_ref) = args.as_node_ref_internal() else {
+        return Inferred::new_none();
+    };
+    let file = node_ref.file;
+    let name_def_of_name =
+        |index| NameDef::by_index(&file.tree, index - NAME_DEF_TO_NAME_DIFFERENCE);
+    let mut name_defs = vec![];
+    match file.tree.node_parent_scope(node_ref.node_index) {
+        Scope::Module => name_defs.extend(
+            file.symbol_table
+                .iter()
+                .map(|(_, &index)| name_def_of_name(index)),
+        ),
+        Scope::Class(cls) => name_defs.extend(
+            ClassNodeRef::new(file, cls.index())
+                .class_storage()
+                .class_symbol_table
+                .iter()
+                .map(|(_, &index)| name_def_of_name(index)),
+        ),
+        Scope::Function(func) => {
+            func.on_name_def_in_scope(&mut |name_def| name_defs.push(name_def))
+        }
+        Scope::Lambda(lambda) => name_defs.extend(lambda.params().map(|p| p.name_def())),
+    }
+
+    // Like Mypy we only reveal variables, not functions, classes or imports.
+    let inference = file.inference(i_s);
+    let format_data = FormatData::new_reveal_type(i_s.db);
+    let mut locals: Vec<(&str, Box<str>)> = vec![];
+    for name_def in name_defs {
+        let name = name_def.as_code();
+        if name_def.maybe_import().is_some()
+            || name_def.maybe_name_of_func().is_some()
+            || name_def.maybe_name_of_class().is_some()
+            || !matches!(name_def.parent(), NameDefParent::Other)
+            || locals.iter().any(|(n, _)| *n == name)
+        {
+            continue;
+        }
+        let first = first_defined_name(file, name_def.name_index());
+        if first > node_ref.node_index {
+            // The name is not yet defined at this point.
+            continue;
+        }
+        let (narrowed, deleted) =
+            inference.lookup_narrowed_name_and_deleted(PointLink::new(file.file_index, first));
+        if deleted {
+            continue;
+        }
+        let inf = narrowed.unwrap_or_else(|| inference.infer_name_of_definition_by_index(first));
+        locals.push((name, inf.as_cow_type(i_s).format(&format_data)));
+    }
+    if locals.is_empty() {
+        args.add_issue(i_s, IssueKind::Note("There are no locals to reveal".into()));
+    } else {
+        locals.sort_by_key(|(name, _)| *name);
+        args.add_issue(i_s, IssueKind::Note("Revealed local types are:".into()));
+        for (name, type_) in locals {
+            args.add_issue(i_s, IssueKind::Note(format!("    {name}: {type_}").into()));
+        }
+    }
+    Inferred::new_none()
+}
+
 fn reveal_type_info(i_s: &InferenceState, t: &Type) -> Box<str> {
     let format_data = FormatData::new_reveal_type(i_s.db);
     if let Type::Type(type_) = t {
@@ -1 +1,3 @@
+mod memory_fs_tests;
+mod plugin_tests;
 mod signature_tests;
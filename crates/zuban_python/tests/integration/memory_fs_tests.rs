@@ -0,0 +1,95 @@
+use config::ProjectOptions;
+use vfs::{MemoryFS, VfsHandler};
+use zuban_python::{Project, RunCause};
+
+fn issues(project: &mut Project) -> Vec<String> {
+    let diagnostics = project.diagnostics().unwrap();
+    diagnostics
+        .issues
+        .iter()
+        .map(|issue| issue.message())
+        .collect()
+}
+
+fn apply_events(project: &mut Project) {
+    let Some(receiver) = project.vfs_handler().notify_receiver().cloned() else {
+        return;
+    };
+    for event in receiver.try_iter() {
+        for path in event.unwrap().paths {
+            let path = project
+                .vfs_handler()
+                .unchecked_abs_path(path.to_str().unwrap());
+            project.invalidate_path(&path)
+        }
+    }
+}
+
+#[test]
+fn test_check_memory_fs() {
+    let typeshed_path = test_utils::typeshed_path();
+    let fs = MemoryFS::new()
+        .with_watcher()
+        .with_disk_dir(&***typeshed_path);
+    let root = fs.normalize_unchecked_abs_path("/memory-project");
+    fs.write_file("/memory-project/foo.py", "x: int = ''");
+    fs.write_file(
+        "/memory-project/pkg/bar.py",
+        "from foo import x\ny: str = x",
+    );
+
+    let mut po = ProjectOptions::default();
+    po.settings.typeshed_path = Some(typeshed_path);
+    po.settings.mypy_path = vec![root];
+    let mut project = Project::new(Box::new(fs.clone()), po, RunCause::LanguageServer);
+    apply_events(&mut project);
+    assert_eq!(
+        issues(&mut project),
+        [
+            r#"Incompatible types in assignment (expression has type "str", variable has type "int")"#,
+            r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#,
+        ]
+    );
+
+    fs.write_file("/memory-project/foo.py", "x: str = ''");
+    apply_events(&mut project);
+    assert_eq!(issues(&mut project), Vec::<String>::new());
+
+    fs.write_file("/memory-project/pkg/baz.py", "import foo\nfoo.undefined");
+    assert!(fs.remove("/memory-project/pkg/bar.py"));
+    apply_events(&mut project);
+    assert_eq!(
+        issues(&mut project),
+        [r#"Module has no attribute "undefined""#]
+    );
+}
+
+#[test]
+fn test_case_insensitive_memory_fs() {
+    let check = |fs: MemoryFS| {
+        let typeshed_path = test_utils::typeshed_path();
+        let fs = fs.with_disk_dir(&***typeshed_path);
+        let root = fs.normalize_unchecked_abs_path("/memory-project");
+        fs.write_file("/memory-project/foo.py", "");
+        fs.write_file("/memory-project/main.py", "import Foo");
+
+        let mut po = ProjectOptions::default();
+        po.settings.typeshed_path = Some(typeshed_path);
+        po.settings.mypy_path = vec![root];
+        let mut project = Project::new(Box::new(fs), po, RunCause::LanguageServer);
+        apply_events(&mut project);
+        issues(&mut project)
+    };
+    assert_eq!(
+        check(MemoryFS::new()),
+        [r#"Cannot find implementation or library stub for module named "Foo""#]
+    );
+    // Python compares module names exactly, even on case-insensitive file systems.
+    assert_eq!(
+        check(MemoryFS::new().with_case_insensitive()),
+        [
+            "Cannot find implementation or library stub for module named \"Foo\"\n\
+             Module names are case-sensitive, but the module is named \"foo\""
+        ]
+    );
+}
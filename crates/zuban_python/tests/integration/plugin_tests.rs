@@ -0,0 +1,101 @@
+use config::ProjectOptions;
+use vfs::{MemoryFS, VfsHandler};
+use zuban_python::{
+    PluginParam, PluginParamKind, PluginSignature, PluginType, Project, RunCause,
+    TypeCheckerPlugin, register_plugin,
+};
+
+struct TestPlugin;
+
+impl TypeCheckerPlugin for TestPlugin {
+    fn names(&self) -> &'static [&'static str] {
+        &["test_plugin"]
+    }
+
+    fn function_signature(&self, fullname: &str) -> Option<PluginSignature> {
+        (fullname == "framework.untyped").then(|| PluginSignature {
+            params: vec![PluginParam {
+                name: "x".to_string(),
+                kind: PluginParamKind::PositionalOrKeyword,
+                type_: PluginType::instance("builtins.int"),
+                has_default: false,
+            }],
+            return_type: PluginType::instance("builtins.str"),
+        })
+    }
+
+    fn class_attribute(&self, class_fullname: &str, name: &str) -> Option<PluginType> {
+        (class_fullname == "framework.Model" && name == "generated").then(|| {
+            PluginType::Union(vec![PluginType::instance("builtins.int"), PluginType::None])
+        })
+    }
+
+    fn keeps_decorated_signature(&self, decorator_fullname: &str) -> bool {
+        decorator_fullname == "framework.register"
+    }
+
+    fn analyze_type(&self, fullname: &str) -> Option<PluginType> {
+        (fullname == "framework.Alias").then(|| PluginType::Instance {
+            fullname: "builtins.list".to_string(),
+            type_args: vec![PluginType::instance("builtins.str")],
+        })
+    }
+}
+
+fn issues(plugins: &[&str]) -> Vec<String> {
+    let typeshed_path = test_utils::typeshed_path();
+    let fs = MemoryFS::new().with_disk_dir(&***typeshed_path);
+    let root = fs.normalize_unchecked_abs_path("/plugin-project");
+    fs.write_file(
+        "/plugin-project/framework.py",
+        "from typing import Any\n\
+         def register(f: Any) -> int: ...\n\
+         def untyped(x): ...\n\
+         class Model: ...\n\
+         Alias = Any\n",
+    );
+    fs.write_file(
+        "/plugin-project/main.py",
+        "from framework import register, untyped, Model, Alias\n\
+         @register\n\
+         def f(x: int) -> None: ...\n\
+         f('')\n\
+         a: int = untyped(1)\n\
+         b: str = Model().generated\n\
+         c: Alias = [1]\n",
+    );
+
+    let mut po = ProjectOptions::default();
+    po.settings.typeshed_path = Some(typeshed_path);
+    po.settings.mypy_path = vec![root];
+    po.settings.plugins = plugins.iter().map(|name| name.to_string()).collect();
+    let mut project = Project::new(Box::new(fs), po, RunCause::LanguageServer);
+    let diagnostics = project.diagnostics().unwrap();
+    diagnostics
+        .issues
+        .iter()
+        .map(|issue| issue.message())
+        .collect()
+}
+
+#[test]
+fn test_registered_plugin() {
+    register_plugin(TestPlugin);
+    assert_eq!(
+        issues(&["test_plugin"]),
+        [
+            r#"Argument 1 to "f" has incompatible type "str"; expected "int""#,
+            r#"Incompatible types in assignment (expression has type "str", variable has type "int")"#,
+            r#"Incompatible types in assignment (expression has type "int | None", variable has type "str")"#,
+            r#"List item 0 has incompatible type "int"; expected "str""#,
+        ]
+    );
+    // Registered plugins are only used if they are enabled.
+    assert_eq!(
+        issues(&[]),
+        [
+            r#""int" not callable"#,
+            r#""Model" has no attribute "generated""#,
+        ]
+    );
+}
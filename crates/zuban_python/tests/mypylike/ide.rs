@@ -29,11 +29,15 @@ pub enum Commands {
     Infer(InferArgs),
     Signatures(SignaturesArgs),
     Documentation(DocumentationArgs),
+    Hover,
+    ExpandedType,
     References(ReferencesArgs),
     Rename(RenameArgs),
     SemanticTokens(SemanticTokensArgs),
     SelectionRanges(SelectionRangeArgs),
     CodeActions(CodeActionArgs),
+    OrganizeImports,
+    Refactorings(RefactoringArgs),
     FoldingRanges(FoldingBlocksArgs),
     InlayHints(InlayHintArgs),
 }
@@ -122,9 +126,19 @@ pub struct CodeActionArgs {
     #[arg(long)]
     pub only_auto_imports: bool,
     #[arg(long)]
+    pub only_quick_fixes: bool,
+    #[arg(long)]
     pub strict_range: bool,
 }
 
+#[derive(Parser, Debug)]
+pub struct RefactoringArgs {
+    #[arg(long)]
+    pub until_line: usize,
+    #[arg(long, default_value_t = 0)]
+    pub until_column: usize,
+}
+
 #[derive(Parser, Debug)]
 pub struct FoldingBlocksArgs {}
 
@@ -291,6 +305,24 @@ pub(crate) fn find_and_check_ide_tests(
                             }]
                         }),
                 ),
+                Commands::Hover => (
+                    "hover",
+                    document.hover(position).map(|result| {
+                        vec![match result {
+                            Some(result) => format!("{:?}", result.to_markdown()),
+                            None => "No docs found".to_string(),
+                        }]
+                    }),
+                ),
+                Commands::ExpandedType => (
+                    "expanded-type",
+                    document.expanded_type(position).map(|result| {
+                        vec![match result {
+                            Some(result) => result,
+                            None => "No type found".to_string(),
+                        }]
+                    }),
+                ),
                 Commands::References(references) => {
                     let goal = match references.only_check_file {
                         true => ReferencesGoal::OnlyCurrentFile,
@@ -468,6 +500,12 @@ pub(crate) fn find_and_check_ide_tests(
                             if args.only_auto_imports {
                                 actions.retain(|action| action.title.starts_with("Import"))
                             }
+                            if args.only_quick_fixes {
+                                actions.retain(|action| {
+                                    !action.title.starts_with("Import")
+                                        && !action.title.starts_with("Add \"#")
+                                })
+                            }
                             let end = if actions.is_empty() { " []" } else { "" };
                             output.push(format!("{path}:{test_on_line_nr}: Code Actions:{end}"));
                             for action in actions {
@@ -480,12 +518,73 @@ pub(crate) fn find_and_check_ide_tests(
                                     action.end_of_change.code_points_column(),
                                     action.replacement,
                                 ));
+                                for change in action.additional_changes {
+                                    output.push(format!(
+                                        "  and {}:{}-{}:{} replaced with: {:?}",
+                                        change.start_of_change.line_one_based(),
+                                        change.start_of_change.code_points_column(),
+                                        change.end_of_change.line_one_based(),
+                                        change.end_of_change.code_points_column(),
+                                        change.replacement,
+                                    ));
+                                }
                             }
                             continue;
                         }
                         Err(err) => ("code-actions", Err(err)),
                     }
                 }
+                Commands::OrganizeImports => match document.organize_imports() {
+                    Ok(Some(action)) => {
+                        output.push(format!(
+                            "{path}:{test_on_line_nr}: {}: {}:{}-{}:{} replaced with:",
+                            action.title,
+                            action.start_of_change.line_one_based(),
+                            action.start_of_change.code_points_column(),
+                            action.end_of_change.line_one_based(),
+                            action.end_of_change.code_points_column(),
+                        ));
+                        output.extend(
+                            action
+                                .replacement
+                                .lines()
+                                .map(|l| format!("  {l}").trim_end().to_owned()),
+                        );
+                        continue;
+                    }
+                    Ok(None) => {
+                        output.push(format!("{path}:{test_on_line_nr}: Imports are organized"));
+                        continue;
+                    }
+                    Err(err) => ("organize-imports", Err(err)),
+                },
+                Commands::Refactorings(args) => {
+                    let until = InputPosition::CodePoints {
+                        line: args.until_line - 1,
+                        column: args.until_column,
+                    };
+                    match document.refactorings(position, until) {
+                        Ok(refactorings) => {
+                            let end = if refactorings.is_empty() { " []" } else { "" };
+                            output.push(format!("{path}:{test_on_line_nr}: Refactorings:{end}"));
+                            for refactoring in refactorings {
+                                output.push(format!("- {}:", refactoring.title));
+                                for change in refactoring.changes {
+                                    output.push(format!(
+                                        "  - {}:{}-{}:{} replaced with: {:?}",
+                                        change.start_of_change.line_one_based(),
+                                        change.start_of_change.code_points_column(),
+                                        change.end_of_change.line_one_based(),
+                                        change.end_of_change.code_points_column(),
+                                        change.replacement,
+                                    ));
+                                }
+                            }
+                            continue;
+                        }
+                        Err(err) => ("refactorings", Err(err)),
+                    }
+                }
                 Commands::FoldingRanges(_) => {
                     output.push(format!("{path}:{test_on_line_nr}: Folding Ranges:"));
                     for range in document.folding_ranges() {
@@ -505,7 +604,7 @@ pub(crate) fn find_and_check_ide_tests(
                         line: args.until_line.unwrap_or_else(|| code.split('\n').count()) - 1,
                         column: 0,
                     };
-                    match document.inlay_hints(position, until) {
+                    match document.inlay_hints(position, until, Default::default()) {
                         Ok(hints) => {
                             output.push(format!("{path}:{test_on_line_nr}: Inlay Hints:"));
                             for hint in hints {
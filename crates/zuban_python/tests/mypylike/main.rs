@@ -10,9 +10,12 @@ use std::{
     time::Instant,
 };
 
-use clap::{Command, CommandFactory as _, FromArgMatches as _, Parser};
+use clap::{Command, CommandFactory as _, FromArgMatches as _, Parser, ValueEnum as _};
 
-use config::{DiagnosticConfig, Mode, ProjectOptions, PythonVersion, Settings, TypeCheckerFlags};
+use config::{
+    DiagnosticConfig, FollowImports, Mode, ProjectOptions, PythonVersion, Settings,
+    TypeCheckerFlags,
+};
 use ide::find_and_check_ide_tests;
 use regex::{Captures, Regex, Replacer};
 use test_utils::{Step, calculate_steps};
@@ -135,7 +138,10 @@ struct PerTestFlags {
     no_namespace_packages: bool,
     #[arg(long)]
     no_strict_bytes: bool,
-    #[arg(long)]
+    // Mypy's tests only pass the main module on the command line, while all files of a test are
+    // checked here. The flag is therefore only applied to our own tests. Its id differs from the
+    // one of the Zuban flag, which is renamed in ProjectsCache::new.
+    #[arg(long, id = "test_follow_imports")]
     follow_imports: Option<String>,
 
     // Won't implement, Mypy internals
@@ -327,6 +333,11 @@ impl TestCase<'_, '_> {
         if flags.disallow_empty_bodies {
             config.allow_empty_bodies = false;
         }
+        if let Some(follow_imports) = &flags.follow_imports
+            && !self.from_mypy_test_suite
+        {
+            config.follow_imports = FollowImports::from_str(follow_imports, false).unwrap();
+        }
 
         let project = if let Some(mut project_options) = project_options {
             project_options.settings = settings;
@@ -906,7 +917,8 @@ impl ProjectsCache {
         po.settings.typeshed_path = Some(test_utils::typeshed_path());
         set_mypy_path(&mut po);
         Self {
-            command: PerTestFlags::command(),
+            command: PerTestFlags::command()
+                .mut_arg("follow_imports", |arg| arg.long("zuban-follow-imports")),
             base_project: reuse_db.then(|| Project::without_watcher(po, run_cause)),
             base_version,
             run_cause,
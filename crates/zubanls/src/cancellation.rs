@@ -0,0 +1,91 @@
+//! Handling of `$/cancelRequest`.
+//!
+//! Requests are processed one after another in the event loop, which means that a cancel
+//! notification would only be seen after the request it cancels has been answered. Therefore the
+//! messages are forwarded by a separate thread that marks requests as cancelled as soon as the
+//! notification arrives.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::Receiver;
+use lsp_server::{Message, RequestId};
+use lsp_types::{CancelParams, NumberOrString, notification::Notification as _};
+
+use crate::progress::ProgressCreations;
+use zuban_python::CancellationToken;
+
+#[derive(Default)]
+pub(crate) struct Cancellations {
+    // The request the event loop is currently working on. Also guards `pending`.
+    running: Mutex<Option<(RequestId, CancellationToken)>>,
+    // Requests that were received, but the event loop did not start working on yet, and whether
+    // they were cancelled. Cancellations of other requests (e.g. of answered ones) are ignored.
+    pending: Mutex<HashMap<RequestId, bool>>,
+}
+
+impl Cancellations {
+    /// Returns None if the request was already cancelled.
+    pub fn start_request(&self, id: &RequestId) -> Option<CancellationToken> {
+        let mut running = self.running.lock().unwrap();
+        if self.pending.lock().unwrap().remove(id) == Some(true) {
+            *running = None;
+            return None;
+        }
+        let token = CancellationToken::default();
+        *running = Some((id.clone(), token.clone()));
+        Some(token)
+    }
+
+    pub fn finish_request(&self) {
+        *self.running.lock().unwrap() = None;
+    }
+
+    fn receive_request(&self, id: RequestId) {
+        self.pending.lock().unwrap().insert(id, false);
+    }
+
+    fn cancel(&self, id: RequestId) {
+        let running = self.running.lock().unwrap();
+        match running.as_ref() {
+            Some((running_id, token)) if *running_id == id => token.cancel(),
+            _ => {
+                if let Some(cancelled) = self.pending.lock().unwrap().get_mut(&id) {
+                    *cancelled = true;
+                }
+            }
+        }
+    }
+}
+
+/// Forwards all messages to the returned receiver while reacting to cancel notifications and the
+/// creation of progress tokens immediately.
+pub(crate) fn forward_messages(
+    receiver: Receiver<Message>,
+    cancellations: Arc<Cancellations>,
+    progress_creations: Arc<ProgressCreations>,
+) -> Receiver<Message> {
+    let (sender, forwarded) = crossbeam_channel::unbounded();
+    std::thread::spawn(move || {
+        for msg in receiver {
+            if let Message::Request(request) = &msg {
+                cancellations.receive_request(request.id.clone());
+            } else if let Message::Response(response) = &msg {
+                progress_creations.receive_response(response);
+            } else if let Message::Notification(not) = &msg
+                && not.method == lsp_types::notification::Cancel::METHOD
+                && let Ok(params) = serde_json::from_value::<CancelParams>(not.params.clone())
+            {
+                cancellations.cancel(match params.id {
+                    NumberOrString::Number(n) => n.into(),
+                    NumberOrString::String(s) => s.into(),
+                });
+            }
+            if sender.send(msg).is_err() {
+                // The event loop has ended
+                break;
+            }
+        }
+    });
+    forwarded
+}
@@ -1,14 +1,15 @@
 //! Advertises the capabilities of the LSP Server.
 use lsp_types::{
-    CodeActionProviderCapability, CompletionOptions, DeclarationCapability,
-    FoldingRangeProviderCapability, HoverProviderCapability, ImplementationProviderCapability,
-    NotebookCellSelector, NotebookDocumentSyncOptions, NotebookSelector, OneOf, Position,
-    PositionEncodingKind, RenameOptions, SelectionRangeProviderCapability,
-    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
-    SemanticTokensServerCapabilities, ServerCapabilities, SignatureHelpOptions,
-    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
-    TypeDefinitionProviderCapability, WorkspaceFileOperationsServerCapabilities,
-    WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities,
+    CodeActionKind, CodeActionOptions, CodeActionProviderCapability, CompletionOptions,
+    DeclarationCapability, FoldingRangeProviderCapability, HoverProviderCapability,
+    ImplementationProviderCapability, NotebookCellSelector, NotebookDocumentSyncOptions,
+    NotebookSelector, OneOf, Position, PositionEncodingKind, RenameOptions,
+    SelectionRangeProviderCapability, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensServerCapabilities, ServerCapabilities,
+    SignatureHelpOptions, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncOptions, TypeDefinitionProviderCapability,
+    WorkspaceFileOperationsServerCapabilities, WorkspaceFoldersServerCapabilities,
+    WorkspaceServerCapabilities,
 };
 use zuban_python::InputPosition;
 
@@ -59,7 +60,19 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
         document_highlight_provider: Some(OneOf::Left(true)),
         document_symbol_provider: Some(OneOf::Left(true)),
         workspace_symbol_provider: Some(OneOf::Left(true)),
-        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        code_action_provider: Some(if client_capabilities.code_action_literals() {
+            CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                ]),
+                work_done_progress_options: Default::default(),
+                resolve_provider: None,
+            })
+        } else {
+            CodeActionProviderCapability::Simple(true)
+        }),
         code_lens_provider: None,
         document_formatting_provider: None,       // TODO
         document_range_formatting_provider: None, // TODO
@@ -200,7 +213,6 @@ impl ClientCapabilities {
         .unwrap_or_default()
     }
 
-    #[expect(dead_code)]
     pub(crate) fn code_action_literals(&self) -> bool {
         (|| {
             self.caps
@@ -250,6 +262,10 @@ impl ClientCapabilities {
         .unwrap_or_default()
     }
 
+    pub(crate) fn work_done_progress(&self) -> bool {
+        (|| self.caps.window.as_ref()?.work_done_progress)().unwrap_or_default()
+    }
+
     fn text_document_diagnostic(caps: &lsp_types::ClientCapabilities) -> bool {
         (|| caps.text_document.as_ref()?.diagnostic.as_ref())().is_some()
     }
@@ -258,7 +274,6 @@ impl ClientCapabilities {
         self.should_push_diagnostics
     }
 
-    #[expect(dead_code)]
     pub(crate) fn diagnostics_refresh(&self) -> bool {
         (|| {
             self.caps
@@ -271,6 +286,22 @@ impl ClientCapabilities {
         .unwrap_or_default()
     }
 
+    pub(crate) fn inlay_hint_refresh(&self) -> bool {
+        (|| {
+            self.caps
+                .workspace
+                .as_ref()?
+                .inlay_hint
+                .as_ref()?
+                .refresh_support
+        })()
+        .unwrap_or_default()
+    }
+
+    pub(crate) fn workspace_configuration(&self) -> bool {
+        (|| self.caps.workspace.as_ref()?.configuration)().unwrap_or_default()
+    }
+
     #[expect(dead_code)]
     pub(crate) fn insert_replace_support(&self) -> bool {
         (|| {
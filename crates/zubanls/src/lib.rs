@@ -1,11 +1,19 @@
+mod cancellation;
 mod capabilities;
 mod notebooks;
 mod notification_handlers;
 mod panic_hooks;
+mod progress;
 mod request_handlers;
 mod semantic_tokens;
 mod server;
+mod settings;
+mod transport;
 
 pub use crate::server::{
-    GLOBAL_NOTIFY_EVENT_COUNTER, run_server, run_server_with_custom_connection,
+    GLOBAL_EVICTED_FILES_COUNTER, GLOBAL_NOTIFY_EVENT_COUNTER, run_server,
+    run_server_with_custom_connection,
+};
+pub use crate::transport::{
+    ListenAddress, Transport, run_server_listening, run_server_with_listener,
 };
@@ -7,9 +7,16 @@ use vfs::PathWithScheme;
 #[derive(Default)]
 pub(crate) struct Notebooks(HashMap<Uri, Notebook>);
 
+/// Every code cell is an in memory file whose parent is the previous code cell, so a cell sees
+/// the names of all cells before it.
 #[derive(Default)]
 pub(crate) struct Notebook {
-    cells: Vec<PathWithScheme>,
+    cells: Vec<Cell>,
+}
+
+struct Cell {
+    path: PathWithScheme,
+    is_code: bool,
 }
 
 impl Notebooks {
@@ -21,46 +28,70 @@ impl Notebooks {
         self.0.remove(&notebook_uri);
     }
 
-    pub fn remove_cells(&mut self, notebook_uri: &Uri, range: Range<usize>) -> anyhow::Result<()> {
-        let Some(notebook) = self.0.get_mut(notebook_uri) else {
+    fn notebook(&self, notebook_uri: &Uri) -> anyhow::Result<&Notebook> {
+        let Some(notebook) = self.0.get(notebook_uri) else {
             bail!("Expected a notebook for {notebook_uri:?}");
         };
-        notebook.cells.drain(range.clone());
-        Ok(())
+        Ok(notebook)
     }
 
-    pub fn add_cell_and_return_parent(
+    /// Replaces the cells in `range` with new cells (code cells are marked with true), which
+    /// also covers cells that were moved.
+    pub fn splice_cells(
         &mut self,
         notebook_uri: &Uri,
-        cell_path: PathWithScheme,
-        at_nth_cell: usize,
-    ) -> anyhow::Result<Option<PathWithScheme>> {
+        range: Range<usize>,
+        new_cells: Vec<(PathWithScheme, bool)>,
+    ) -> anyhow::Result<()> {
         let Some(notebook) = self.0.get_mut(notebook_uri) else {
             bail!("Expected a notebook for {notebook_uri:?}");
         };
-        if at_nth_cell > notebook.cells.len() {
+        if range.end > notebook.cells.len() {
             bail!(
-                "Expected to be able to insert a cell at index {at_nth_cell}, \
-                 but had only {} entries",
+                "Expected to be able to replace the cells {range:?}, but had only {} entries",
                 notebook.cells.len()
             );
         }
-        notebook.cells.insert(at_nth_cell, cell_path.clone());
-        Ok(if at_nth_cell > 0 {
-            notebook.cells.get(at_nth_cell - 1).cloned()
-        } else {
-            None
-        })
+        notebook.cells.splice(
+            range,
+            new_cells
+                .into_iter()
+                .map(|(path, is_code)| Cell { path, is_code }),
+        );
+        Ok(())
     }
 
-    pub fn nth_cell(
-        &mut self,
+    /// Returns the code cells in `range` and the first code cell after it together with their
+    /// parents. These are the cells that need to be linked again after the cells in `range`
+    /// changed, the parents of all other cells stay the same.
+    pub fn code_cells_with_parents(
+        &self,
         notebook_uri: &Uri,
-        index: usize,
-    ) -> anyhow::Result<Option<PathWithScheme>> {
-        let Some(notebook) = self.0.get_mut(notebook_uri) else {
-            bail!("Expected a notebook for {notebook_uri:?}");
-        };
-        Ok(notebook.cells.get(index).cloned())
+        range: Range<usize>,
+    ) -> anyhow::Result<Vec<(PathWithScheme, Option<PathWithScheme>)>> {
+        let mut result = vec![];
+        let mut parent = None;
+        for (i, cell) in self.notebook(notebook_uri)?.cells.iter().enumerate() {
+            if !cell.is_code {
+                continue;
+            }
+            if i >= range.start {
+                result.push((cell.path.clone(), parent.clone()));
+                if i >= range.end {
+                    break;
+                }
+            }
+            parent = Some(cell.path.clone());
+        }
+        Ok(result)
+    }
+
+    pub fn is_code_cell(&self, notebook_uri: &Uri, path: &PathWithScheme) -> bool {
+        self.notebook(notebook_uri).is_ok_and(|notebook| {
+            notebook
+                .cells
+                .iter()
+                .any(|cell| cell.is_code && cell.path == *path)
+        })
     }
 }
@@ -1,15 +1,60 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
 use anyhow::bail;
 use lsp_types::{
-    DidChangeNotebookDocumentParams, DidChangeTextDocumentParams, DidCloseNotebookDocumentParams,
+    CancelParams, DidChangeConfigurationParams, DidChangeNotebookDocumentParams,
+    DidChangeTextDocumentParams, DidChangeWorkspaceFoldersParams, DidCloseNotebookDocumentParams,
     DidCloseTextDocumentParams, DidOpenNotebookDocumentParams, DidOpenTextDocumentParams,
     NotebookCell, NotebookCellKind, TextDocumentContentChangeEvent, TextDocumentIdentifier,
-    TextDocumentItem, Uri, VersionedTextDocumentIdentifier,
+    TextDocumentItem, Uri, VersionedTextDocumentIdentifier, WorkspaceFolder,
 };
 use vfs::PathWithScheme;
 
-use crate::server::GlobalState;
+use crate::server::{GlobalState, patch_path_prefix};
+use crate::settings::{ClientSettings, SECTION as SETTINGS_SECTION};
 
 impl GlobalState<'_> {
+    pub(crate) fn handle_cancel(&mut self, _: CancelParams) -> anyhow::Result<()> {
+        // Cancellations are already handled while forwarding the messages to the event loop, see
+        // the cancellation module.
+        Ok(())
+    }
+
+    pub(crate) fn handle_did_change_configuration(
+        &mut self,
+        params: DidChangeConfigurationParams,
+    ) -> anyhow::Result<()> {
+        if self.client_capabilities.workspace_configuration() {
+            // Settings in the notification are deprecated in favor of pulling them.
+            self.request_configuration();
+            return Ok(());
+        }
+        let settings = &params.settings;
+        let settings =
+            ClientSettings::from_json(settings.get(SETTINGS_SECTION).unwrap_or(settings))
+                .inspect_err(|err| self.show_warning(err.to_string()))?;
+        self.set_client_settings(settings);
+        Ok(())
+    }
+
+    pub(crate) fn handle_did_change_workspace_folders(
+        &mut self,
+        params: DidChangeWorkspaceFoldersParams,
+    ) -> anyhow::Result<()> {
+        let to_roots = |folders: Vec<WorkspaceFolder>| {
+            folders
+                .iter()
+                .map(|folder| patch_path_prefix(&folder.uri))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let added = to_roots(params.event.added)?;
+        let removed = to_roots(params.event.removed)?;
+        self.change_workspace_folders(added, removed);
+        self.request_configuration();
+        Ok(())
+    }
+
     pub(crate) fn handle_did_open_text_document(
         &mut self,
         params: DidOpenTextDocumentParams,
@@ -32,16 +77,14 @@ impl GlobalState<'_> {
         content_changes: Vec<TextDocumentContentChangeEvent>,
     ) -> anyhow::Result<()> {
         let encoding = self.client_capabilities.negotiated_encoding();
-        let project = self.project();
-        let path = Self::uri_to_path(project, &text_document.uri)?;
+        let (project, path) = self.project_for_uri(&text_document.uri)?;
         tracing::info!("Changing document {}", path.as_uri());
         project
             .store_file_with_lsp_changes(path, content_changes, |pos| encoding.input_position(pos))
     }
 
     fn store_in_memory_file(&mut self, uri: lsp_types::Uri, code: Box<str>) -> anyhow::Result<()> {
-        let project = self.project();
-        let path = Self::uri_to_path(project, &uri)?;
+        let (project, path) = self.project_for_uri(&uri)?;
         tracing::info!("Loading {}", path.as_uri());
         project.store_in_memory_file(path, code);
         Ok(())
@@ -53,7 +96,7 @@ impl GlobalState<'_> {
         code: Box<str>,
         parent: Option<PathWithScheme>,
     ) -> anyhow::Result<()> {
-        let project = self.project();
+        let project = self.project_for_path(&path);
         tracing::info!("Loading {}", path.as_uri());
         if let Some(parent) = parent {
             project.store_in_memory_file_with_parent(path, code, &parent)
@@ -68,8 +111,7 @@ impl GlobalState<'_> {
         params: DidCloseTextDocumentParams,
     ) -> anyhow::Result<()> {
         let _p = tracing::info_span!("handle_did_change_text_document").entered();
-        let project = self.project();
-        let path = Self::uri_to_path(project, &params.text_document.uri)?;
+        let (project, path) = self.project_for_uri(&params.text_document.uri)?;
         tracing::info!("Closing {}", path.as_uri());
 
         project
@@ -84,50 +126,73 @@ impl GlobalState<'_> {
         let _p = tracing::info_span!("handle_did_open_notebook").entered();
         self.notebooks
             .add_notebook(params.notebook_document.uri.clone());
-        self.new_cells(
+        self.change_cells(
             &params.notebook_document.uri,
+            0..0,
             params.notebook_document.cells,
             params.cell_text_documents,
-            0,
-        )?;
-        Ok(())
+            vec![],
+        )
     }
 
-    fn new_cells(
+    /// Replaces the cells in `range`. Cells that were moved are part of `cells`, but not of
+    /// `did_open` and keep their code.
+    fn change_cells(
         &mut self,
         notebook: &Uri,
+        range: Range<usize>,
         cells: Vec<NotebookCell>,
-        mut text_documents: Vec<TextDocumentItem>,
-        start_at_nth_cell: usize,
+        did_open: Vec<TextDocumentItem>,
+        did_close: Vec<TextDocumentIdentifier>,
     ) -> anyhow::Result<()> {
-        for (i, cell) in cells.into_iter().enumerate() {
-            if cell.kind != NotebookCellKind::Code {
-                continue;
-            }
-            let Some(pos) = text_documents.iter().position(|x| x.uri == cell.document) else {
-                bail!(
-                    "Did not find URI {:?} in cell_text_documents",
-                    cell.document
-                );
+        let result = self.close_cells(notebook, did_close);
+        let mut opened = HashMap::new();
+        for doc_item in did_open {
+            let (_, path) = self.project_for_uri(&doc_item.uri)?;
+            opened.insert(path, doc_item.text);
+        }
+        let mut new_cells = vec![];
+        for cell in cells {
+            let (_, path) = self.project_for_uri(&cell.document)?;
+            new_cells.push((path, cell.kind == NotebookCellKind::Code));
+        }
+        let new_end = range.start + new_cells.len();
+        let start = range.start;
+        self.notebooks.splice_cells(notebook, range, new_cells)?;
+        // Only the new cells and the cell after them get a different parent.
+        for (path, parent) in self
+            .notebooks
+            .code_cells_with_parents(notebook, start..new_end)?
+        {
+            let code = match opened.remove(&path) {
+                Some(text) => text.into(),
+                None => {
+                    let Some(code) = self.project_for_path(&path).code_of_in_memory_file(&path)
+                    else {
+                        bail!("Expected to find code for the cell {}", path.as_uri());
+                    };
+                    // Storing the same code again is cheap, only the parent might change.
+                    code.into()
+                }
             };
-            let doc_item = text_documents.swap_remove(pos);
-            let project = self.project();
-            let path = Self::uri_to_path(project, &cell.document)?;
-            let maybe_parent = self.notebooks.add_cell_and_return_parent(
-                notebook,
-                path.clone(),
-                start_at_nth_cell + i,
-            )?;
-            self.store_in_memory_file_with_parent(path, doc_item.text.into(), maybe_parent)?;
+            self.store_in_memory_file_with_parent(path, code, parent)?;
         }
-        Ok(())
+        result
     }
 
-    fn close_cells(&mut self, text_documents: Vec<TextDocumentIdentifier>) -> anyhow::Result<()> {
-        let project = self.project();
+    /// Markdown cells are not stored and therefore not closed.
+    fn close_cells(
+        &mut self,
+        notebook: &Uri,
+        text_documents: Vec<TextDocumentIdentifier>,
+    ) -> anyhow::Result<()> {
         let mut result = Ok(());
         for text_document in text_documents {
-            let path = Self::uri_to_path(project, &text_document.uri)?;
+            let (_, path) = self.project_for_uri(&text_document.uri)?;
+            if !self.notebooks.is_code_cell(notebook, &path) {
+                continue;
+            }
+            let project = self.project_for_path(&path);
             tracing::info!("Closing {}", path.as_uri());
             if let err @ Err(_) = project
                 .close_in_memory_file(&path)
@@ -148,47 +213,17 @@ impl GlobalState<'_> {
             // Only the meta data changed
             return Ok(());
         };
+        let notebook = &params.notebook_document.uri;
         let mut result = Ok(());
         if let Some(structure) = cells.structure {
             let start = structure.array.start as usize;
-            let new_end = start
-                + structure
-                    .array
-                    .cells
-                    .as_ref()
-                    .map(|v| v.len())
-                    .unwrap_or_default();
-            if let Some(closed) = structure.did_close {
-                result = self.close_cells(closed);
-                self.notebooks.remove_cells(
-                    &params.notebook_document.uri,
-                    start..start + structure.array.delete_count as usize,
-                )?;
-            }
-            if let Some(cells) = structure.array.cells {
-                self.new_cells(
-                    &params.notebook_document.uri,
-                    cells,
-                    structure.did_open.unwrap_or_default(),
-                    start,
-                )?
-            }
-            if let Some(child) = self
-                .notebooks
-                .nth_cell(&params.notebook_document.uri, new_end)?
-            {
-                let Some(code) = self.project().code_of_in_memory_file(&child) else {
-                    bail!("Expected to find code for the latest child {child:?}");
-                };
-                let code = code.into();
-                let parent = self
-                    .notebooks
-                    .nth_cell(&params.notebook_document.uri, new_end)?
-                    .unwrap();
-                // TODO this is not optimal, we should probably not clone the code again for an
-                // entry that is already there.
-                self.store_in_memory_file_with_parent(child, code, Some(parent))?;
-            }
+            result = self.change_cells(
+                notebook,
+                start..start + structure.array.delete_count as usize,
+                structure.array.cells.unwrap_or_default(),
+                structure.did_open.unwrap_or_default(),
+                structure.did_close.unwrap_or_default(),
+            );
         }
         if let Some(metadata_change) = cells.data {
             for _cell in metadata_change {
@@ -199,7 +234,10 @@ impl GlobalState<'_> {
         }
         if let Some(text_content) = cells.text_content {
             for change in text_content {
-                self.change_document(change.document, change.changes)?
+                let (_, path) = self.project_for_uri(&change.document.uri)?;
+                if self.notebooks.is_code_cell(notebook, &path) {
+                    self.change_document(change.document, change.changes)?
+                }
             }
         }
         result
@@ -210,7 +248,7 @@ impl GlobalState<'_> {
         params: DidCloseNotebookDocumentParams,
     ) -> anyhow::Result<()> {
         let _p = tracing::info_span!("handle_did_close_notebook").entered();
-        let result = self.close_cells(params.cell_text_documents);
+        let result = self.close_cells(&params.notebook_document.uri, params.cell_text_documents);
         self.notebooks.close_notebook(params.notebook_document.uri);
         result
     }
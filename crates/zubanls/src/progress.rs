@@ -0,0 +1,240 @@
+//! Work done progress, see
+//! https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workDoneProgress
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::Sender;
+use lsp_server::{Message, RequestId};
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport, notification::Notification as _, request::Request as _,
+};
+
+use crate::capabilities::ClientCapabilities;
+
+pub(crate) const CREATE_REQUEST_ID_PREFIX: &str = "zuban/workDoneProgress/create/";
+
+static PROGRESS_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The responses to `window/workDoneProgress/create`. They are received while the event loop is
+/// busy with the work the progress is about, so they are recorded by the thread that forwards
+/// the messages (see `forward_messages`).
+#[derive(Default)]
+pub(crate) struct ProgressCreations(Mutex<Creations>);
+
+#[derive(Default)]
+struct Creations {
+    // Whether the client accepted the token of a request
+    responses: HashMap<RequestId, bool>,
+    // Progresses that ended before the client answered, they are begun and ended once the
+    // event loop sees the response.
+    pending_ends: HashMap<RequestId, PendingEnd>,
+}
+
+struct PendingEnd {
+    token: ProgressToken,
+    title: String,
+    last_report: Option<WorkDoneProgressReport>,
+    message: Option<String>,
+}
+
+impl ProgressCreations {
+    pub fn receive_response(&self, response: &lsp_server::Response) {
+        if response.id.to_string().contains(CREATE_REQUEST_ID_PREFIX) {
+            let mut creations = self.0.lock().unwrap();
+            creations
+                .responses
+                .insert(response.id.clone(), response.error.is_none());
+        }
+    }
+
+    /// Called by the event loop once it sees the response. Progresses that already ended are
+    /// only sent now, so that the client still shows work that was faster than its answer.
+    pub fn complete(&self, sender: &Sender<Message>, response: &lsp_server::Response) {
+        let mut creations = self.0.lock().unwrap();
+        creations.responses.remove(&response.id);
+        if let Some(pending) = creations.pending_ends.remove(&response.id)
+            && response.error.is_none()
+        {
+            send(sender, &pending.token, begin(&pending.title));
+            if let Some(report) = pending.last_report
+                && report.percentage != Some(0)
+            {
+                send(sender, &pending.token, WorkDoneProgress::Report(report));
+            }
+            send(
+                sender,
+                &pending.token,
+                WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: pending.message,
+                }),
+            );
+        }
+    }
+
+    fn take_response(&self, id: &RequestId) -> Option<bool> {
+        self.0.lock().unwrap().responses.remove(id)
+    }
+}
+
+enum State {
+    /// Nothing may be sent before the client accepted a token that the server created.
+    Creating(RequestId, Arc<ProgressCreations>),
+    Started,
+    Rejected,
+}
+
+pub(crate) struct Progress<'sender> {
+    sender: &'sender Sender<Message>,
+    token: ProgressToken,
+    title: String,
+    state: State,
+    last_percentage: Option<u32>,
+    // The latest report while the token is being created, which is sent once the progress begins
+    unsent_report: Option<WorkDoneProgressReport>,
+}
+
+impl<'sender> Progress<'sender> {
+    /// Starts a progress with a token the server generates. This is only possible if the client
+    /// supports `window/workDoneProgress/create`. The progress begins once the client accepted
+    /// the token.
+    pub fn server_initiated(
+        sender: &'sender Sender<Message>,
+        client_capabilities: &ClientCapabilities,
+        creations: &Arc<ProgressCreations>,
+        title: &str,
+    ) -> Option<Self> {
+        if !client_capabilities.work_done_progress() {
+            return None;
+        }
+        let n = PROGRESS_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("zuban/{title}/{n}"));
+        let id: RequestId = format!("{CREATE_REQUEST_ID_PREFIX}{n}").into();
+        let request = lsp_server::Request::new(
+            id.clone(),
+            lsp_types::request::WorkDoneProgressCreate::METHOD.to_owned(),
+            WorkDoneProgressCreateParams {
+                token: token.clone(),
+            },
+        );
+        _ = sender.send(request.into());
+        Some(Self {
+            sender,
+            token,
+            title: title.to_owned(),
+            state: State::Creating(id, creations.clone()),
+            last_percentage: None,
+            unsent_report: None,
+        })
+    }
+
+    /// Starts a progress with a token that was given by the client as part of a request.
+    pub fn client_initiated(
+        sender: &'sender Sender<Message>,
+        token: Option<ProgressToken>,
+        title: &str,
+    ) -> Option<Self> {
+        let mut slf = Self {
+            sender,
+            token: token?,
+            title: title.to_owned(),
+            state: State::Started,
+            last_percentage: None,
+            unsent_report: None,
+        };
+        slf.begin();
+        Some(slf)
+    }
+
+    fn begin(&mut self) {
+        self.state = State::Started;
+        self.last_percentage = Some(0);
+        self.send(begin(&self.title));
+        if let Some(report) = self.unsent_report.take()
+            && report.percentage != Some(0)
+        {
+            self.last_percentage = report.percentage;
+            self.send(WorkDoneProgress::Report(report));
+        }
+    }
+
+    /// Returns whether the progress may be sent, which is not the case as long as the client did
+    /// not accept the token.
+    fn is_started(&mut self) -> bool {
+        if let State::Creating(id, creations) = &self.state {
+            match creations.take_response(id) {
+                Some(true) => self.begin(),
+                Some(false) => self.state = State::Rejected,
+                None => return false,
+            }
+        }
+        matches!(self.state, State::Started)
+    }
+
+    /// Reports the progress, but only if the percentage changed, to avoid flooding the client
+    /// with notifications.
+    pub fn report(&mut self, done: usize, total: usize, message: impl FnOnce() -> String) {
+        let percentage = (done * 100).checked_div(total).unwrap_or(100).min(100) as u32;
+        let is_started = self.is_started();
+        if self.last_percentage == Some(percentage) {
+            return;
+        }
+        self.last_percentage = Some(percentage);
+        let report = WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(message()),
+            percentage: Some(percentage),
+        };
+        if is_started {
+            self.send(WorkDoneProgress::Report(report));
+        } else if matches!(self.state, State::Creating(..)) {
+            self.unsent_report = Some(report);
+        }
+    }
+
+    /// Ends the progress. If the client did not accept the token yet, the progress is sent once
+    /// the event loop receives the answer, see [`ProgressCreations::complete`].
+    pub fn end(mut self, message: Option<String>) {
+        if self.is_started() {
+            self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+        } else if let State::Creating(id, creations) = self.state {
+            // The answer has not been received yet, the event loop only sees it after this.
+            creations.0.lock().unwrap().pending_ends.insert(
+                id,
+                PendingEnd {
+                    token: self.token,
+                    title: self.title,
+                    last_report: self.unsent_report,
+                    message,
+                },
+            );
+        }
+    }
+
+    fn send(&self, value: WorkDoneProgress) {
+        send(self.sender, &self.token, value)
+    }
+}
+
+fn begin(title: &str) -> WorkDoneProgress {
+    WorkDoneProgress::Begin(WorkDoneProgressBegin {
+        title: title.to_owned(),
+        cancellable: Some(false),
+        message: None,
+        percentage: Some(0),
+    })
+}
+
+fn send(sender: &Sender<Message>, token: &ProgressToken, value: WorkDoneProgress) {
+    let not = lsp_server::Notification::new(
+        lsp_types::notification::Progress::METHOD.to_owned(),
+        ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        },
+    );
+    _ = sender.send(not.into());
+}
@@ -1,38 +1,46 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+};
 
 use anyhow::bail;
 use lsp_server::ErrorCode;
 use lsp_types::{
     CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
     CompletionItem, CompletionParams, CompletionResponse, CompletionTextEdit, Diagnostic,
-    DiagnosticSeverity, DocumentChangeOperation, DocumentChanges, DocumentDiagnosticParams,
-    DocumentDiagnosticReport, DocumentDiagnosticReportResult, DocumentHighlight,
-    DocumentHighlightKind, DocumentHighlightParams, DocumentSymbol, DocumentSymbolParams,
-    DocumentSymbolResponse, Documentation, FoldingRange, FoldingRangeParams,
+    DiagnosticRelatedInformation, DiagnosticSeverity, DocumentChangeOperation, DocumentChanges,
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, Documentation, FoldingRange, FoldingRangeParams,
     FullDocumentDiagnosticReport, GotoDefinitionParams, GotoDefinitionResponse, Hover,
     HoverContents, HoverParams, InlayHint, InlayHintLabel, InlayHintParams, Location, LocationLink,
     MarkupContent, MarkupKind, OneOf, OptionalVersionedTextDocumentIdentifier,
     ParameterInformation, ParameterLabel, Position, PrepareRenameResponse, Range, ReferenceParams,
-    RelatedFullDocumentDiagnosticReport, RenameFile, RenameParams, ResourceOp,
-    ResourceOperationKind, SelectionRange, SelectionRangeParams, SemanticTokens,
-    SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
+    RelatedFullDocumentDiagnosticReport, RelatedUnchangedDocumentDiagnosticReport, RenameFile,
+    RenameParams, ResourceOp, ResourceOperationKind, SelectionRange, SelectionRangeParams,
+    SemanticTokens, SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
     SemanticTokensResult, SignatureHelp, SignatureHelpParams, SignatureInformation, SymbolKind,
-    TextDocumentEdit, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, Uri,
-    WorkspaceDiagnosticParams, WorkspaceDiagnosticReport, WorkspaceDiagnosticReportResult,
-    WorkspaceDocumentDiagnosticReport, WorkspaceEdit, WorkspaceFullDocumentDiagnosticReport,
-    WorkspaceSymbol, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+    TextDocumentEdit, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit,
+    UnchangedDocumentDiagnosticReport, Uri, WorkspaceDiagnosticParams, WorkspaceDiagnosticReport,
+    WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport, WorkspaceEdit,
+    WorkspaceFullDocumentDiagnosticReport, WorkspaceSymbol, WorkspaceSymbolParams,
+    WorkspaceSymbolResponse, WorkspaceUnchangedDocumentDiagnosticReport,
     request::{
         GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
         GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
     },
 };
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use zuban_python::{
-    Document, GotoGoal, InputPosition, Name, NameSymbol, PositionInfos, ReferencesGoal, Severity,
+    DiagnosticTag, Document, GotoGoal, InputPosition, Name, NameSymbol, PositionInfos,
+    ReferencesGoal, Severity, TypeCoverage,
 };
 
 use crate::{
     capabilities::{ClientCapabilities, NegotiatedEncoding},
+    progress::Progress,
     semantic_tokens::SemanticTokensBuilder,
     server::{GlobalState, LspError},
 };
@@ -40,30 +48,81 @@ use crate::{
 impl GlobalState<'_> {
     pub(crate) fn handle_workspace_diagnostics(
         &mut self,
-        _params: WorkspaceDiagnosticParams,
+        params: WorkspaceDiagnosticParams,
     ) -> anyhow::Result<WorkspaceDiagnosticReportResult> {
         let encoding = self.client_capabilities.negotiated_encoding();
+        let previous_result_ids: HashMap<_, _> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect();
+        let title = "Checking workspace";
+        let mut progress = Progress::client_initiated(
+            self.sender,
+            params.work_done_progress_params.work_done_token,
+            title,
+        )
+        .or_else(|| {
+            Progress::server_initiated(
+                self.sender,
+                &self.client_capabilities,
+                &self.progress_creations,
+                title,
+            )
+        });
         // TODO Currently we don't want to fetch parallel diagnostics, but it would be nice to do
         // that in the future.
-        let documents: Vec<_> = self.project().workspace_documents().collect();
+        let cancellation_token = self.cancellation_token.clone();
+        let documents: Vec<_> = self
+            .projects()
+            .flat_map(|project| project.workspace_documents().collect::<Vec<_>>())
+            .collect();
+        let total = documents.len();
+        let items = documents
+            .into_iter()
+            .enumerate()
+            .map_while(|(i, document)| {
+                // The diagnostics of a single file are always calculated completely.
+                if cancellation_token.is_cancelled() {
+                    return None;
+                }
+                let uri = to_uri(document.path().as_uri());
+                if let Some(progress) = &mut progress {
+                    progress.report(i, total, || uri.path().to_string())
+                }
+                let items = Self::diagnostics_for_file(document, encoding);
+                let result_id = diagnostics_result_id(&items);
+                if previous_result_ids.get(&uri) == Some(&result_id) {
+                    return Some(WorkspaceDocumentDiagnosticReport::Unchanged(
+                        WorkspaceUnchangedDocumentDiagnosticReport {
+                            uri,
+                            version: None,
+                            unchanged_document_diagnostic_report:
+                                UnchangedDocumentDiagnosticReport { result_id },
+                        },
+                    ));
+                }
+                Some(WorkspaceDocumentDiagnosticReport::Full(
+                    WorkspaceFullDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                            result_id: Some(result_id),
+                            items,
+                        },
+                    },
+                ))
+            })
+            .collect();
+        if let Some(progress) = progress {
+            progress.end(Some(match self.cancellation_token.is_cancelled() {
+                false => format!("Checked {total} files"),
+                true => "Cancelled".to_owned(),
+            }))
+        }
+        self.check_cancelled()?;
         Ok(WorkspaceDiagnosticReportResult::Report(
-            WorkspaceDiagnosticReport {
-                items: documents
-                    .into_iter()
-                    .map(|document| {
-                        WorkspaceDocumentDiagnosticReport::Full(
-                            WorkspaceFullDocumentDiagnosticReport {
-                                uri: to_uri(document.path().as_uri()),
-                                version: None,
-                                full_document_diagnostic_report: FullDocumentDiagnosticReport {
-                                    result_id: None,
-                                    items: Self::diagnostics_for_file(document, encoding),
-                                },
-                            },
-                        )
-                    })
-                    .collect(),
-            },
+            WorkspaceDiagnosticReport { items },
         ))
     }
 
@@ -78,13 +137,27 @@ impl GlobalState<'_> {
             self.sent_diagnostic_count
         );
         let encoding = self.client_capabilities.negotiated_encoding();
+        self.check_cancelled()?;
         let document = self.document(&params.text_document)?;
+        let items = Self::diagnostics_for_file(document, encoding);
+        let result_id = diagnostics_result_id(&items);
+        if params.previous_result_id.as_ref() == Some(&result_id) {
+            tracing::info!("Diagnostics unchanged (result id {result_id})");
+            return Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id,
+                    },
+                }),
+            ));
+        }
         Ok(DocumentDiagnosticReportResult::Report(
             DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
                 related_documents: None,
                 full_document_diagnostic_report: FullDocumentDiagnosticReport {
-                    result_id: None,
-                    items: Self::diagnostics_for_file(document, encoding),
+                    result_id: Some(result_id),
+                    items,
                 },
             }),
         ))
@@ -113,7 +186,7 @@ impl GlobalState<'_> {
         mut document: Document,
         encoding: NegotiatedEncoding,
     ) -> Vec<Diagnostic> {
-        document
+        let mut diagnostics: Vec<_> = document
             .diagnostics()
             .iter()
             .map(|issue| Diagnostic {
@@ -127,19 +200,78 @@ impl GlobalState<'_> {
                 code: Some(lsp_types::NumberOrString::String(
                     issue.mypy_error_code().to_string(),
                 )),
-                code_description: None,
+                code_description: issue
+                    .documentation_url()
+                    .map(|url| lsp_types::CodeDescription { href: to_uri(url) }),
                 source: Some("zuban".to_owned()),
                 message: issue.message(),
+                related_information: {
+                    let related = issue.related_information();
+                    (!related.is_empty()).then(|| {
+                        related
+                            .into_iter()
+                            .map(|info| DiagnosticRelatedInformation {
+                                location: lsp_location(encoding, info.name),
+                                message: info.message,
+                            })
+                            .collect()
+                    })
+                },
+                tags: issue.tag().map(|tag| {
+                    vec![match tag {
+                        DiagnosticTag::Unnecessary => lsp_types::DiagnosticTag::UNNECESSARY,
+                        DiagnosticTag::Deprecated => lsp_types::DiagnosticTag::DEPRECATED,
+                    }]
+                }),
+                data: None,
+            })
+            .collect();
+        for range in document.unreachable_ranges() {
+            let range = Self::to_range(encoding, range);
+            // With --warn-unreachable there is already an error for this statement.
+            if diagnostics.iter().any(|d| {
+                d.range.start == range.start
+                    && d.tags
+                        .as_ref()
+                        .is_some_and(|tags| tags.contains(&lsp_types::DiagnosticTag::UNNECESSARY))
+            }) {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(lsp_types::NumberOrString::String("unreachable".to_owned())),
+                code_description: None,
+                source: Some("zuban".to_owned()),
+                message: "Code is unreachable".to_owned(),
+                related_information: None,
+                tags: Some(vec![lsp_types::DiagnosticTag::UNNECESSARY]),
+                data: None,
+            })
+        }
+        // There is no position for errors that were fixed, so they are shown at the start of the
+        // file.
+        for entry in document.stale_baseline_entries() {
+            diagnostics.push(Diagnostic {
+                range: lsp_types::Range::default(),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(lsp_types::NumberOrString::String("baseline".to_owned())),
+                code_description: None,
+                source: Some("zuban".to_owned()),
+                message: format!(
+                    "Baseline entry does not match an error anymore: {}  [{}]",
+                    entry.message, entry.code
+                ),
                 related_information: None,
                 tags: None,
                 data: None,
             })
-            .collect()
+        }
+        diagnostics
     }
 
     fn document(&mut self, text_document: &TextDocumentIdentifier) -> anyhow::Result<Document<'_>> {
-        let project = self.project();
-        let path = Self::uri_to_path(project, &text_document.uri)?;
+        let (project, path) = self.project_for_uri(&text_document.uri)?;
         let Some(document) = project.document(&path) else {
             tracing::error!("File {} does not exist", path.as_uri());
             bail!(LspError {
@@ -155,8 +287,14 @@ impl GlobalState<'_> {
         params: CompletionParams,
     ) -> anyhow::Result<Option<CompletionResponse>> {
         let encoding = self.client_capabilities.negotiated_encoding();
+        let cancellation_token = self.cancellation_token.clone();
         let (document, pos) = self.document_with_pos(&params.text_document_position)?;
         let mut completions = document.complete(pos, false, |replace_range, completion| {
+            // Calculating the kind might need inference, which is avoided for cancelled requests.
+            // The completion itself will return an error in that case.
+            if cancellation_token.is_cancelled() {
+                return None;
+            }
             Some(CompletionItem {
                 label: completion.label().to_string(),
                 kind: Some(completion.kind()),
@@ -257,21 +395,69 @@ impl GlobalState<'_> {
         let encoding = self.client_capabilities.negotiated_encoding();
         let (document, pos) = self.document_with_pos(&params.text_document_position_params)?;
 
-        let Some(documentation_result) = document.documentation(pos, false)? else {
+        let Some(hover) = document.hover(pos)? else {
             return Ok(None);
         };
         Ok(Some(Hover {
             contents: HoverContents::Markup(MarkupContent {
                 kind: MarkupKind::Markdown,
-                value: documentation_result.documentation,
+                value: hover.to_markdown(),
             }),
-            range: Some(Self::to_range(
-                encoding,
-                documentation_result.on_symbol_range,
-            )),
+            range: Some(Self::to_range(encoding, hover.on_symbol_range)),
         }))
     }
 
+    pub fn handle_expanded_type(
+        &mut self,
+        params: TextDocumentPositionParams,
+    ) -> anyhow::Result<Option<String>> {
+        let (document, pos) = self.document_with_pos(&params)?;
+        document.expanded_type(pos)
+    }
+
+    pub fn handle_type_coverage(
+        &mut self,
+        params: TypeCoverageParams,
+    ) -> anyhow::Result<TypeCoverageResult> {
+        let coverage_of = |document: Document| {
+            let uri = to_uri(document.path().as_uri());
+            anyhow::Ok((uri, document.type_coverage()?))
+        };
+        let mut files = match params.text_document {
+            Some(text_document) => vec![coverage_of(self.document(&text_document)?)?],
+            None => {
+                let cancellation_token = self.cancellation_token.clone();
+                let documents: Vec<_> = self
+                    .projects()
+                    .flat_map(|project| project.workspace_documents().collect::<Vec<_>>())
+                    .collect();
+                let files = documents
+                    .into_iter()
+                    .map_while(|document| {
+                        (!cancellation_token.is_cancelled()).then(|| coverage_of(document))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                self.check_cancelled()?;
+                files
+            }
+        };
+        files.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        let mut total = TypeCoverage::default();
+        for (_, coverage) in &files {
+            total += *coverage;
+        }
+        Ok(TypeCoverageResult {
+            files: files
+                .into_iter()
+                .map(|(uri, coverage)| FileTypeCoverage {
+                    uri,
+                    coverage: coverage.into(),
+                })
+                .collect(),
+            total: total.into(),
+        })
+    }
+
     pub fn handle_goto_declaration(
         &mut self,
         params: GotoDeclarationParams,
@@ -425,31 +611,93 @@ impl GlobalState<'_> {
         let encoding = self.client_capabilities.negotiated_encoding();
         let uri = params.text_document.uri.clone();
         let document = self.document(&params.text_document)?;
-        let actions = document.code_actions(
-            encoding.input_position(params.range.start),
-            Some(encoding.input_position(params.range.end)),
-            false,
-        )?;
+        let wants_kind = |kind: &CodeActionKind| {
+            params.context.only.as_ref().is_none_or(|only| {
+                only.iter().any(|wanted| {
+                    // A kind like `source` also includes `source.organizeImports`.
+                    kind.as_str() == wanted.as_str()
+                        || kind.as_str().starts_with(&format!("{}.", wanted.as_str()))
+                })
+            })
+        };
+        let mut actions = vec![];
+        if wants_kind(&CodeActionKind::QUICKFIX) {
+            actions.extend(
+                document
+                    .code_actions(
+                        encoding.input_position(params.range.start),
+                        Some(encoding.input_position(params.range.end)),
+                        false,
+                    )?
+                    .into_iter()
+                    .map(|action| (CodeActionKind::QUICKFIX, action)),
+            );
+        }
+        if wants_kind(&CodeActionKind::SOURCE_ORGANIZE_IMPORTS)
+            && let Some(action) = document.organize_imports()?
+        {
+            actions.push((CodeActionKind::SOURCE_ORGANIZE_IMPORTS, action));
+        }
+        let mut actions: Vec<_> = actions
+            .into_iter()
+            .map(|(kind, action)| {
+                let to_edit = |start, end, new_text| TextEdit {
+                    range: Self::to_range(encoding, (start, end)),
+                    new_text,
+                };
+                let mut edits: Vec<_> = action
+                    .additional_changes
+                    .into_iter()
+                    .map(|change| {
+                        to_edit(
+                            change.start_of_change,
+                            change.end_of_change,
+                            change.replacement,
+                        )
+                    })
+                    .collect();
+                edits.push(to_edit(
+                    action.start_of_change,
+                    action.end_of_change,
+                    action.replacement,
+                ));
+                (action.title, kind, edits)
+            })
+            .collect();
+        // Refactorings only make sense for a selection and not for the cursor position.
+        if wants_kind(&CodeActionKind::REFACTOR_EXTRACT) && params.range.start != params.range.end {
+            for refactoring in document.refactorings(
+                encoding.input_position(params.range.start),
+                encoding.input_position(params.range.end),
+            )? {
+                let edits = refactoring
+                    .changes
+                    .into_iter()
+                    .map(|change| TextEdit {
+                        range: Self::to_range(
+                            encoding,
+                            (change.start_of_change, change.end_of_change),
+                        ),
+                        new_text: change.replacement,
+                    })
+                    .collect();
+                actions.push((refactoring.title, CodeActionKind::REFACTOR_EXTRACT, edits));
+            }
+        }
         if actions.is_empty() {
             return Ok(None);
         }
         Ok(Some(
             actions
                 .into_iter()
-                .map(|action| {
+                .map(|(title, kind, edits)| {
                     CodeActionOrCommand::CodeAction(CodeAction {
-                        title: action.title,
-                        kind: Some(CodeActionKind::QUICKFIX),
+                        title,
+                        kind: Some(kind),
                         diagnostics: None,
                         edit: Some(WorkspaceEdit::new(HashMap::from_iter([(
                             uri.clone(),
-                            vec![TextEdit {
-                                range: Self::to_range(
-                                    encoding,
-                                    (action.start_of_change, action.end_of_change),
-                                ),
-                                new_text: action.replacement,
-                            }],
+                            edits,
                         )]))),
                         command: None,
                         is_preferred: None,
@@ -609,17 +857,24 @@ impl GlobalState<'_> {
     ) -> anyhow::Result<Option<WorkspaceSymbolResponse>> {
         let encoding = self.client_capabilities.negotiated_encoding();
         let symbols = self
-            .project()
-            .workspace_documents()
-            .map(|doc| {
-                let symbols = doc.symbols();
-                let mut out = Vec::with_capacity(symbols.len());
-                Self::nested_workspace_doc_symbols(encoding, symbols, &params.query, &mut |s| {
-                    out.push(s)
-                });
-                out
+            .projects()
+            .flat_map(|project| {
+                project
+                    .workspace_documents()
+                    .map(|doc| {
+                        let symbols = doc.symbols();
+                        let mut out = Vec::with_capacity(symbols.len());
+                        Self::nested_workspace_doc_symbols(
+                            encoding,
+                            symbols,
+                            &params.query,
+                            &mut |s| out.push(s),
+                        );
+                        out
+                    })
+                    .flatten_iter()
+                    .collect::<Vec<_>>()
             })
-            .flatten_iter()
             .collect();
         Ok(Some(WorkspaceSymbolResponse::Nested(symbols)))
     }
@@ -745,12 +1000,15 @@ impl GlobalState<'_> {
         params: InlayHintParams,
     ) -> anyhow::Result<Option<Vec<InlayHint>>> {
         let encoding = self.client_capabilities.negotiated_encoding();
+        let options = self
+            .client_settings_for_uri(&params.text_document.uri)?
+            .inlay_hints;
         let document = self.document(&params.text_document)?;
         let start = encoding.input_position(params.range.start);
         let end = encoding.input_position(params.range.end);
-        Ok(Some(
+        let hints = Some(
             document
-                .inlay_hints(start, end)?
+                .inlay_hints(start, end, options)?
                 .map(|hint| {
                     let pos = Self::to_position(encoding, hint.position);
                     InlayHint {
@@ -768,7 +1026,10 @@ impl GlobalState<'_> {
                     }
                 })
                 .collect(),
-        ))
+        );
+        // The hints are incomplete if the request was cancelled in the meantime.
+        self.check_cancelled()?;
+        Ok(hints)
     }
 
     pub(crate) fn handle_shutdown(&mut self, _: ()) -> anyhow::Result<()> {
@@ -832,3 +1093,73 @@ fn lsp_location(encoding: NegotiatedEncoding, name: Name) -> Location {
         GlobalState::to_range(encoding, name.name_range()),
     )
 }
+
+/// The result id is a hash of the diagnostics, which makes it possible for clients to avoid
+/// receiving the same diagnostics over and over again. Since `DefaultHasher::new` is not randomly
+/// seeded, the ids are also stable across server restarts.
+fn diagnostics_result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .expect("Diagnostics should always be serializable")
+        .hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+/// A custom request that returns the type at a position with all type aliases and `NewType`s
+/// expanded, because hovers only show their names.
+pub(crate) enum ExpandedTypeRequest {}
+
+impl lsp_types::request::Request for ExpandedTypeRequest {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<String>;
+    const METHOD: &'static str = "zuban/expandedType";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TypeCoverageParams {
+    /// The coverage of all files in the workspaces is reported if no document is given.
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct TypeCoverageCounts {
+    expressions: usize,
+    precise: usize,
+    any: usize,
+    percentage: f64,
+}
+
+impl From<TypeCoverage> for TypeCoverageCounts {
+    fn from(coverage: TypeCoverage) -> Self {
+        Self {
+            expressions: coverage.expressions,
+            precise: coverage.precise,
+            any: coverage.any,
+            percentage: coverage.percentage(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FileTypeCoverage {
+    uri: Uri,
+    #[serde(flatten)]
+    coverage: TypeCoverageCounts,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TypeCoverageResult {
+    files: Vec<FileTypeCoverage>,
+    total: TypeCoverageCounts,
+}
+
+/// A custom request that reports the share of expressions with precise (non-`Any`) types, either
+/// for a single document or for all documents in the workspaces.
+pub(crate) enum TypeCoverageRequest {}
+
+impl lsp_types::request::Request for TypeCoverageRequest {
+    type Params = TypeCoverageParams;
+    type Result = TypeCoverageResult;
+    const METHOD: &'static str = "zuban/typeCoverage";
+}
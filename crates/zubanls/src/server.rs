@@ -2,15 +2,15 @@
 
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::atomic::AtomicI64;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 
 use anyhow::bail;
-use config::ProjectOptions;
-use crossbeam_channel::{Receiver, Sender, never, select};
+use config::{ProjectOptions, UnknownOption};
+use crossbeam_channel::{Receiver, Select, Sender};
 use fluent_uri::Scheme;
 use lsp_server::{Connection, ExtractError, Message, Request};
 use lsp_types::notification::Notification as _;
@@ -18,19 +18,28 @@ use lsp_types::{TextDocumentPositionParams, Uri};
 use notify::EventKind;
 use serde::{Serialize, de::DeserializeOwned};
 use vfs::{LocalFS, NormalizedPath, NotifyEvent, PathWithScheme, VfsHandler as _};
-use zuban_python::{PanicRecovery, Project, RunCause};
+use zuban_python::{
+    CancellationToken, Cancelled, LoadingProgress, OptionsChange, PanicRecovery, Project, RunCause,
+};
 
+use crate::cancellation::{Cancellations, forward_messages};
 use crate::capabilities::{ClientCapabilities, server_capabilities};
 use crate::notebooks::Notebooks;
 use crate::notification_handlers::TestPanic;
 use crate::panic_hooks;
-use crate::request_handlers::to_uri;
+use crate::progress::{CREATE_REQUEST_ID_PREFIX, Progress, ProgressCreations};
+use crate::request_handlers::{ExpandedTypeRequest, TypeCoverageRequest, to_uri};
+use crate::settings::{ClientSettings, SECTION as SETTINGS_SECTION};
 
 // Since we currently don't do garbage collection, we simply delete the project and reindex,
 // because it's not that expensive after a specific amount of diagnostics.
 const REINDEX_AFTER_N_DIAGNOSTICS: usize = 1000;
 
+const SERVER_REQUEST_ID_PREFIX: &str = "zuban/request/";
+
 pub static GLOBAL_NOTIFY_EVENT_COUNTER: AtomicI64 = AtomicI64::new(0);
+/// The number of files evicted because of `memory_limit_mb`, simply for tests.
+pub static GLOBAL_EVICTED_FILES_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -40,6 +49,22 @@ pub fn run_server_with_custom_connection(
     connection: Connection,
     typeshed_path: Option<Arc<NormalizedPath>>,
     cleanup: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    run_session(
+        connection,
+        typeshed_path,
+        &mut WarmState::default(),
+        cleanup,
+    )
+}
+
+/// Serves a single client. The projects of the client are left in `warm_state` when the session
+/// ends, so that the next client of the same workspace does not need to index it again.
+pub(crate) fn run_session(
+    connection: Connection,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    warm_state: &mut WarmState,
+    cleanup: impl FnOnce() -> anyhow::Result<()>,
 ) -> anyhow::Result<()> {
     tracing::info!("Server version {} will start", version());
 
@@ -60,6 +85,7 @@ pub fn run_server_with_custom_connection(
         capabilities,
         workspace_folders,
         client_info,
+        initialization_options,
         ..
     } = from_json::<lsp_types::InitializeParams>("InitializeParams", &initialize_params)?;
 
@@ -97,6 +123,12 @@ pub fn run_server_with_custom_connection(
         }
     };
 
+    // Some clients only pass settings as initialization options.
+    let initial_settings = initialization_options
+        .as_ref()
+        .map(|options| ClientSettings::from_json(options.get(SETTINGS_SECTION).unwrap_or(options)))
+        .transpose();
+
     let client_capabilities = ClientCapabilities::new(capabilities);
     let server_capabilities = server_capabilities(&client_capabilities);
 
@@ -178,13 +210,33 @@ pub fn run_server_with_custom_connection(
         tracing::error!("Panic hook: {panic_info}\n{backtrace}");
     }));
 
+    let cancellations = Arc::new(Cancellations::default());
+    let progress_creations = Arc::new(ProgressCreations::default());
+    let receiver = forward_messages(
+        connection.receiver.clone(),
+        cancellations.clone(),
+        progress_creations.clone(),
+    );
     let mut global_state = GlobalState::new(
         &connection.sender,
         client_capabilities,
         workspace_roots.clone(),
         typeshed_path,
+        cancellations,
+        progress_creations,
+        std::mem::take(warm_state),
     );
-    global_state.event_loop(&connection.receiver)?;
+    match initial_settings {
+        Ok(settings) => global_state.set_client_settings(settings.unwrap_or_default()),
+        Err(err) => global_state.show_warning(err.to_string()),
+    }
+    global_state.request_configuration();
+    let result = global_state.event_loop(&receiver);
+    *warm_state = global_state.into_warm_state();
+    // The sender would otherwise outlive the connection and the writer of the transport would
+    // never finish.
+    LOCAL_SENDER.with(|s| s.borrow_mut().take());
+    result?;
     tracing::info!("Server loop ended");
     cleanup()?;
     tracing::info!("Server did successfully shut down");
@@ -209,56 +261,279 @@ struct NotificationDispatcher<'a, 'sender> {
 }
 
 pub(crate) struct GlobalState<'sender> {
-    paths_that_invalidate_whole_project: HashSet<PathBuf>,
-    sender: &'sender Sender<lsp_server::Message>,
-    roots: Rc<[String]>,
+    pub sender: &'sender Sender<lsp_server::Message>,
+    workspaces: Vec<Workspace>,
     typeshed_path: Option<Arc<NormalizedPath>>,
     pub client_capabilities: ClientCapabilities,
-    project: Option<Project>,
-    panic_recovery: Option<PanicRecovery>,
     pub sent_diagnostic_count: usize,
     changed_in_memory_files: Arc<RwLock<Vec<PathWithScheme>>>,
     pub notebooks: Notebooks,
     pub last_completion_position: Option<TextDocumentPositionParams>,
     pub shutdown_requested: bool,
+    cancellations: Arc<Cancellations>,
+    pub progress_creations: Arc<ProgressCreations>,
+    pub cancellation_token: CancellationToken,
+    /// The settings pushed by the client, used for workspaces that don't have specific settings.
+    client_settings: ClientSettings,
+    // The workspace roots the items of pending `workspace/configuration` requests are for
+    configuration_requests: HashMap<lsp_server::RequestId, Vec<String>>,
+}
+
+/// The state that outlives the connection of a client. A server that listens on a socket serves
+/// clients one after another and reuses the indexed projects for workspace roots that are opened
+/// again.
+#[derive(Default)]
+pub(crate) struct WarmState {
+    workspaces: Vec<Workspace>,
+    changed_in_memory_files: Arc<RwLock<Vec<PathWithScheme>>>,
 }
 
+/// A workspace folder of the client. Every workspace has its own project with its own config,
+/// search paths and Python version.
+struct Workspace {
+    root: String,
+    settings: ClientSettings,
+    project: Option<Project>,
+    panic_recovery: Option<PanicRecovery>,
+    paths_that_invalidate_whole_project: HashSet<PathBuf>,
+    // The config files with diagnostics about unknown options, which are cleared once the
+    // options are fixed.
+    config_files_with_diagnostics: Vec<PathWithScheme>,
+}
+
+impl Workspace {
+    fn new(root: String, settings: ClientSettings) -> Self {
+        Self {
+            root,
+            settings,
+            project: None,
+            panic_recovery: None,
+            paths_that_invalidate_whole_project: Default::default(),
+            config_files_with_diagnostics: vec![],
+        }
+    }
+
+    fn notify_receiver(&self) -> Option<&Receiver<NotifyEvent>> {
+        self.project.as_ref()?.vfs_handler().notify_receiver()
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        let is_separator = |c| c == '/' || c == std::path::MAIN_SEPARATOR;
+        path.strip_prefix(&self.root).is_some_and(|rest| {
+            rest.is_empty() || rest.starts_with(is_separator) || self.root.ends_with(is_separator)
+        })
+    }
+
+    /// Finds the config of the workspace and applies the editor settings. `watch` is called for
+    /// every path that might contain a config file.
+    fn load_options(
+        &mut self,
+        sender: &Sender<lsp_server::Message>,
+        typeshed_path: &Option<Arc<NormalizedPath>>,
+        vfs_handler: &dyn vfs::VfsHandler,
+        watch: impl Fn(&Path),
+    ) -> ProjectOptions {
+        let root = vfs_handler.unchecked_abs_path(&self.root);
+        let paths_that_invalidate_whole_project = &mut self.paths_that_invalidate_whole_project;
+        let mut config = config::find_workspace_config(vfs_handler, root.clone(), |path| {
+            // Watch the file itself to make sure that we can invalidate when it changes.
+            let path = Path::new(&**path);
+            watch(path);
+            // Since these are config files there should always be a parent
+            let parent_dir = path.parent().unwrap();
+            // This function is executed even when a file is not found. Therefore we watch the
+            // directory as well, if the file suddenly gets inserted.
+            // Don't delete this line of code, it might not be necessary in most cases, because
+            // the base directory is typically already watched, but I'm not sure this will
+            // always be the case.
+            match std::fs::canonicalize(parent_dir) {
+                Ok(parent_dir) => {
+                    watch(&parent_dir);
+                    let path = parent_dir.join(path.file_name().expect(
+                        "config files where hand generated and should therefore always exist",
+                    ));
+                    watch(&path);
+                    paths_that_invalidate_whole_project.insert(path);
+                }
+                Err(err) => tracing::info!(
+                    "Canonicalizing of path that invalidates the whole project failed: {err}"
+                ),
+            }
+        })
+        .map(|found| {
+            for note in found.notes {
+                log_message(sender, note)
+            }
+            found.project_options
+        })
+        .unwrap_or_else(|err| {
+            tracing::warn!("Error while loading config: {}", err.to_string());
+            show_warning(sender, err.to_string());
+            ProjectOptions::default()
+        });
+        if let Err(err) =
+            config.apply_editor_settings(vfs_handler, &root, self.settings.type_checker_options())
+        {
+            tracing::warn!("Error while applying editor settings: {err}");
+            show_warning(sender, err.to_string());
+        }
+
+        tracing::info!("Using workspace root {:?}", &self.root);
+        // I'm not sure if this is correct. The problem is that the mypy_path currently does
+        // two things:
+        //
+        // 1. Adds it as a workspace to be type-checked
+        // 2. Adds it to the "sys path"
+        //
+        // It's questionable that we want those two things. And maybe there will also be a need
+        // for the type checker to understand what the mypy_path originally was.
+        config
+            .settings
+            .mypy_path
+            .push(vfs_handler.normalize_unchecked_abs_path(&self.root));
+        if typeshed_path.is_some() {
+            config.settings.typeshed_path = typeshed_path.clone();
+        }
+        config
+            .settings
+            .try_to_apply_environment_variables(vfs_handler, &root, |n| std::env::var(n));
+        config
+    }
+
+    fn publish_config_diagnostics(
+        &mut self,
+        sender: &Sender<lsp_server::Message>,
+        vfs_handler: &dyn vfs::VfsHandler,
+        unknown_options: &[UnknownOption],
+    ) {
+        self.config_files_with_diagnostics = publish_config_diagnostics(
+            sender,
+            vfs_handler,
+            unknown_options,
+            std::mem::take(&mut self.config_files_with_diagnostics),
+        );
+    }
+
+    fn recover_from_panic(&mut self) {
+        if let Some(project) = self.project.take() {
+            self.panic_recovery = Some(project.into_panic_recovery());
+        }
+    }
+}
+
+/// Files are part of the workspace with the most specific root. Files outside of all workspaces
+/// are part of the first workspace.
+fn workspace_index(workspaces: &[Workspace], path: &str) -> usize {
+    workspaces
+        .iter()
+        .enumerate()
+        .filter(|(_, workspace)| workspace.contains(path))
+        .max_by_key(|(_, workspace)| workspace.root.len())
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+type InMemoryFile = (PathWithScheme, Box<str>, Option<PathWithScheme>);
+
 impl<'sender> GlobalState<'sender> {
     fn new(
         sender: &'sender Sender<lsp_server::Message>,
         client_capabilities: ClientCapabilities,
         roots: Rc<[String]>,
         typeshed_path: Option<Arc<NormalizedPath>>,
+        cancellations: Arc<Cancellations>,
+        progress_creations: Arc<ProgressCreations>,
+        warm_state: WarmState,
     ) -> Self {
+        let mut warm_workspaces = warm_state.workspaces;
         GlobalState {
-            paths_that_invalidate_whole_project: Default::default(),
             sender,
-            roots,
+            workspaces: roots
+                .iter()
+                .map(
+                    |root| match warm_workspaces.iter().position(|w| w.root == *root) {
+                        Some(index) => {
+                            tracing::info!("Reuse the project of workspace root {root:?}");
+                            warm_workspaces.swap_remove(index)
+                        }
+                        None => Workspace::new(root.clone(), Default::default()),
+                    },
+                )
+                .collect(),
             typeshed_path,
             client_capabilities,
-            project: None,
-            panic_recovery: None,
-            changed_in_memory_files: Default::default(),
+            changed_in_memory_files: warm_state.changed_in_memory_files,
             notebooks: Default::default(),
             sent_diagnostic_count: 0,
             last_completion_position: None,
             shutdown_requested: false,
+            cancellations,
+            progress_creations,
+            cancellation_token: Default::default(),
+            client_settings: Default::default(),
+            configuration_requests: Default::default(),
+        }
+    }
+
+    /// The in memory files belong to the client and are therefore closed, everything else is
+    /// kept for the next client.
+    fn into_warm_state(mut self) -> WarmState {
+        for workspace in &mut self.workspaces {
+            let Some(project) = &mut workspace.project else {
+                continue;
+            };
+            let paths: Vec<_> = project
+                .in_memory_files()
+                .map(|(path, ..)| path.clone())
+                .collect();
+            for path in &paths {
+                if let Err(err) = project.close_in_memory_file(path) {
+                    tracing::error!("Failed to close {} after the session: {err}", path.as_uri());
+                }
+            }
+        }
+        self.changed_in_memory_files.write().unwrap().clear();
+        WarmState {
+            workspaces: self.workspaces,
+            changed_in_memory_files: self.changed_in_memory_files,
         }
     }
 
     fn event_loop(&mut self, receiver: &Receiver<Message>) -> anyhow::Result<()> {
         loop {
-            // Make sure the project is basically loaded
-            self.project();
+            // Make sure the projects are basically loaded. Workspaces wait for the settings of
+            // the client, so they are not indexed once without and once with them.
+            for index in 0..self.workspaces.len() {
+                if !self.waits_for_settings(index) {
+                    self.project_at(index);
+                }
+            }
 
-            select! {
-                recv(receiver) -> msg => {
-                    if self.on_lsp_message_and_return_on_shutdown(msg?) {
+            let notify_receivers: Vec<_> = self
+                .workspaces
+                .iter()
+                .enumerate()
+                .filter_map(|(index, workspace)| {
+                    Some((index, workspace.notify_receiver()?.clone()))
+                })
+                .collect();
+            let mut select = Select::new();
+            select.recv(receiver);
+            for (_, notify_receiver) in &notify_receivers {
+                select.recv(notify_receiver);
+            }
+            let operation = select.select();
+            match operation.index() {
+                0 => {
+                    if self.on_lsp_message_and_return_on_shutdown(operation.recv(receiver)?) {
                         return Ok(());
                     }
-                },
-                recv(self.notify_receiver().unwrap_or(&never())) -> msg =>
-                    self.on_notify_events(msg?)
+                }
+                i => {
+                    let (workspace_index, notify_receiver) = &notify_receivers[i - 1];
+                    let event = operation.recv(notify_receiver)?;
+                    self.on_notify_events(*workspace_index, event)
+                }
             }
             // See comment on REINDEX_AFTER_N_DIAGNOSTICS
             if self.sent_diagnostic_count > REINDEX_AFTER_N_DIAGNOSTICS {
@@ -271,105 +546,192 @@ impl<'sender> GlobalState<'sender> {
         }
     }
 
-    fn notify_receiver(&self) -> Option<&Receiver<NotifyEvent>> {
-        self.project.as_ref()?.vfs_handler().notify_receiver()
+    fn ensure_projects_loaded(&mut self) {
+        for index in 0..self.workspaces.len() {
+            self.project_at(index);
+        }
     }
 
-    pub(crate) fn project(&mut self) -> &mut Project {
-        let project = &mut self.project;
-        if let Some(p) = project {
-            p
+    fn waits_for_settings(&self, index: usize) -> bool {
+        let root = &self.workspaces[index].root;
+        self.configuration_requests
+            .values()
+            .any(|roots| roots.contains(root))
+    }
+
+    /// All projects of all workspaces, loaded if necessary.
+    pub(crate) fn projects(&mut self) -> impl Iterator<Item = &Project> {
+        self.ensure_projects_loaded();
+        self.workspaces
+            .iter()
+            .map(|workspace| workspace.project.as_ref().unwrap())
+    }
+
+    pub(crate) fn project_for_uri(
+        &mut self,
+        uri: &lsp_types::Uri,
+    ) -> anyhow::Result<(&mut Project, PathWithScheme)> {
+        let index = workspace_index(&self.workspaces, &patch_path_prefix(uri)?);
+        let project = self.project_at(index);
+        let path = Self::uri_to_path(project, uri)?;
+        Ok((project, path))
+    }
+
+    pub(crate) fn client_settings_for_uri(
+        &self,
+        uri: &lsp_types::Uri,
+    ) -> anyhow::Result<&ClientSettings> {
+        let index = workspace_index(&self.workspaces, &patch_path_prefix(uri)?);
+        Ok(&self.workspaces[index].settings)
+    }
+
+    pub(crate) fn project_for_path(&mut self, path: &PathWithScheme) -> &mut Project {
+        self.project_at(workspace_index(&self.workspaces, path_str(path)))
+    }
+
+    fn project_at(&mut self, index: usize) -> &mut Project {
+        let workspace = &mut self.workspaces[index];
+        if workspace.project.is_some() {
+            workspace.project.as_mut().unwrap()
         } else {
+            // The config decides how files are watched, so the config files are only watched
+            // once the file watcher is created.
+            let config_paths = RefCell::new(vec![]);
+            let config = workspace.load_options(
+                self.sender,
+                &self.typeshed_path,
+                &LocalFS::without_watcher(),
+                |path| config_paths.borrow_mut().push(path.to_owned()),
+            );
             let new_changed_files = self.changed_in_memory_files.clone();
-            let should_push = self.client_capabilities.should_push_diagnostics();
-            let vfs_handler = LocalFS::with_watcher(move |path| {
-                if should_push {
+            // Projects might be reused by a later client with different capabilities, therefore
+            // changes are always recorded and only published if the client wants that.
+            let vfs_handler = LocalFS::with_watcher_kind(
+                config.settings.file_watcher,
+                Path::new(&workspace.root),
+                move |path| {
                     let mut changed_files = new_changed_files.as_ref().write().unwrap();
                     // This is currently a not a set, because the order matters
                     if !changed_files.contains(&path) {
                         changed_files.push(path)
                     }
-                }
-            });
-            let first_root = self
-                .roots
-                .first()
-                .expect("There should always be at least one root at this point");
-            let first_root = vfs_handler.unchecked_abs_path(first_root);
-            let mut config = config::find_workspace_config(&vfs_handler, first_root.clone(), |path| {
-                // Watch the file itself to make sure that we can invalidate when it changes.
-                let path = Path::new(&**path);
-                vfs_handler.watch(path);
-                // Since these are config files there should always be a parent
-                let parent_dir = path.parent().unwrap();
-                // This function is executed even when a file is not found. Therefore we watch the
-                // directory as well, if the file suddenly gets inserted.
-                // Don't delete this line of code, it might not be necessary in most cases, because
-                // the base directory is typically already watched, but I'm not sure this will
-                // always be the case.
-                match std::fs::canonicalize(parent_dir) {
-                    Ok(parent_dir) => {
-                        vfs_handler.watch(&parent_dir);
-                        let path = parent_dir.join(path.file_name().expect(
-                            "config files where hand generated and should therefore always exist",
-                        ));
-                        vfs_handler.watch(&path);
-                        self.paths_that_invalidate_whole_project.insert(path);
-                    }
-                    Err(err) => tracing::info!(
-                        "Canonicalizing of path that invalidates the whole project failed: {err}"
-                    ),
-                }
-            })
-            .unwrap_or_else(|err| {
-                use lsp_types::{
-                    MessageType, ShowMessageParams,
-                    notification::{Notification, ShowMessage},
-                };
-                tracing::warn!("Error while loading config: {}", err.to_string());
-                let not = lsp_server::Notification::new(
-                    ShowMessage::METHOD.to_owned(),
-                    ShowMessageParams {
-                        typ: MessageType::WARNING,
-                        message: err.to_string(),
-                    },
-                );
-                self.sender
-                    .send(lsp_server::Message::Notification(not))
-                    .unwrap();
-                ProjectOptions::default()
-            });
-
-            tracing::info!("Using workspace roots {:?}", &self.roots);
-            // I'm not sure if this is correct. The problem is that the mypy_path currently does
-            // two things:
-            //
-            // 1. Adds it as a workspace to be type-checked
-            // 2. Adds it to the "sys path"
-            //
-            // It's questionable that we want those two things. And maybe there will also be a need
-            // for the type checker to understand what the mypy_path originally was.
-            config.settings.mypy_path.extend(
-                self.roots
-                    .iter()
-                    .map(|p| vfs_handler.normalize_unchecked_abs_path(p)),
+                },
             );
-            if self.typeshed_path.is_some() {
-                config.settings.typeshed_path = self.typeshed_path.clone();
+            for path in config_paths.into_inner() {
+                vfs_handler.watch(path)
             }
-            config.settings.try_to_apply_environment_variables(
+            workspace.publish_config_diagnostics(
+                self.sender,
                 &vfs_handler,
-                &first_root.clone(),
-                |n| std::env::var(n),
+                &config.settings.unknown_options,
             );
 
             let vfs = Box::new(vfs_handler);
-            *project = Some(if let Some(recovery) = self.panic_recovery.take() {
-                Project::from_recovery(vfs, config, recovery)
+            let mut progress = Progress::server_initiated(
+                self.sender,
+                &self.client_capabilities,
+                &self.progress_creations,
+                "Indexing",
+            );
+            let on_progress = |p: LoadingProgress| {
+                if let Some(progress) = &mut progress {
+                    progress.report(p.done, p.total, || {
+                        p.current_path.unwrap_or("Loaded all workspaces").to_owned()
+                    })
+                }
+            };
+            let mut new_project = if let Some(recovery) = workspace.panic_recovery.take() {
+                Project::from_recovery(vfs, config, recovery, on_progress)
             } else {
-                Project::new(vfs, config, RunCause::LanguageServer)
-            });
-            project.as_mut().unwrap()
+                Project::new_with_progress(vfs, config, RunCause::LanguageServer, on_progress)
+            };
+            if let Some(progress) = progress {
+                progress.end(None)
+            }
+            new_project.set_cancellation_token(self.cancellation_token.clone());
+            workspace.project.insert(new_project)
+        }
+    }
+
+    pub(crate) fn change_workspace_folders(&mut self, added: Vec<String>, removed: Vec<String>) {
+        for root in added {
+            if self
+                .workspaces
+                .iter()
+                .any(|workspace| workspace.root == root)
+            {
+                continue;
+            }
+            tracing::info!("Add workspace root {root:?}");
+            self.workspaces
+                .push(Workspace::new(root, self.client_settings.clone()));
+        }
+        let mut files_to_move: Vec<InMemoryFile> = vec![];
+        let to_owned = |(path, code, parent): (&PathWithScheme, &str, Option<&PathWithScheme>)| {
+            (path.clone(), Box::from(code), parent.cloned())
+        };
+        for root in removed {
+            let Some(index) = self.workspaces.iter().position(|w| w.root == root) else {
+                tracing::warn!("Tried to remove the unknown workspace root {root:?}");
+                continue;
+            };
+            if self.workspaces.len() == 1 {
+                tracing::warn!("Did not remove the workspace root {root:?}, it is the last one");
+                continue;
+            }
+            tracing::info!("Remove workspace root {root:?}");
+            if let Some(project) = self.workspaces.remove(index).project {
+                files_to_move.extend(project.in_memory_files().map(to_owned));
+            }
+        }
+        // In memory files that now belong to a different workspace are moved to the project of
+        // that workspace.
+        for index in 0..self.workspaces.len() {
+            let Some(project) = &self.workspaces[index].project else {
+                continue;
+            };
+            let moved: Vec<_> = project
+                .in_memory_files()
+                .filter(|(path, ..)| workspace_index(&self.workspaces, path_str(path)) != index)
+                .map(to_owned)
+                .collect();
+            let project = self.workspaces[index].project.as_mut().unwrap();
+            for (path, ..) in &moved {
+                if let Err(err) = project.close_in_memory_file(path) {
+                    tracing::error!("Failed to close {} while moving it: {err}", path.as_uri());
+                }
+            }
+            files_to_move.extend(moved);
+        }
+        // Parents (e.g. previous notebook cells) need to be stored before their children.
+        while !files_to_move.is_empty() {
+            let pending: HashSet<_> = files_to_move
+                .iter()
+                .map(|(path, ..)| path.clone())
+                .collect();
+            let (ready, rest): (Vec<_>, Vec<_>) =
+                files_to_move.into_iter().partition(|(_, _, parent)| {
+                    parent
+                        .as_ref()
+                        .is_none_or(|parent| !pending.contains(parent))
+                });
+            if ready.is_empty() {
+                tracing::error!("In memory files have cyclic parents, cannot move them");
+                break;
+            }
+            for (path, code, parent) in ready {
+                tracing::info!("Moving {} to a different workspace", path.as_uri());
+                let project = self.project_for_path(&path);
+                if let Some(parent) = parent {
+                    if let Err(err) = project.store_in_memory_file_with_parent(path, code, &parent)
+                    {
+                        tracing::error!("{err}");
+                    }
+                } else {
+                    project.store_in_memory_file(path, code)
+                }
+            }
+            files_to_move = rest;
         }
     }
 
@@ -381,7 +743,8 @@ impl<'sender> GlobalState<'sender> {
             not: Some(not),
             global_state: self,
         }
-        //.on_sync_mut::<Cancel>(GlobalState::handle_cancel)
+        .on_sync_mut::<Cancel>(GlobalState::handle_cancel)
+        .on_sync_mut::<DidChangeConfiguration>(GlobalState::handle_did_change_configuration)
         //.on_sync_mut::<WorkDoneProgressCancel>(GlobalState::handle_work_done_progress_cancel)
         .on_sync_mut::<DidOpenTextDocument>(GlobalState::handle_did_open_text_document)
         .on_sync_mut::<DidChangeTextDocument>(GlobalState::handle_did_change_text_document)
@@ -389,10 +752,11 @@ impl<'sender> GlobalState<'sender> {
         .on_sync_mut::<DidOpenNotebookDocument>(GlobalState::handle_did_open_notebook)
         .on_sync_mut::<DidChangeNotebookDocument>(GlobalState::handle_did_change_notebook)
         .on_sync_mut::<DidCloseNotebookDocument>(GlobalState::handle_did_close_notebook)
-        //.on_sync_mut::<DidChangeWorkspaceFolders>(GlobalState::handle_did_change_workspace_folders)
+        .on_sync_mut::<DidChangeWorkspaceFolders>(GlobalState::handle_did_change_workspace_folders)
         //.on_sync_mut::<notifs::DidChangeWatchedFiles>(GlobalState::handle_did_change_watched_files)
         .on_sync_mut::<TestPanic>(GlobalState::test_panic)
         .finish();
+        self.enforce_memory_limits();
     }
 
     fn on_request(&mut self, request: Request) {
@@ -404,6 +768,13 @@ impl<'sender> GlobalState<'sender> {
             ));
             return;
         }
+        let Some(token) = self.cancellations.start_request(&request.id) else {
+            tracing::info!("Request {} was cancelled before it started", request.id);
+            self.respond(cancelled_response(request.id));
+            return;
+        };
+        self.cancellation_token = token.clone();
+        self.set_cancellation_token(token);
 
         use lsp_types::request::*;
 
@@ -417,6 +788,8 @@ impl<'sender> GlobalState<'sender> {
         .on_sync_mut::<ResolveCompletionItem>(GlobalState::resolve_completion_item)
         .on_sync_mut::<SignatureHelpRequest>(GlobalState::handle_signature_help)
         .on_sync_mut::<HoverRequest>(GlobalState::handle_hover)
+        .on_sync_mut::<ExpandedTypeRequest>(GlobalState::handle_expanded_type)
+        .on_sync_mut::<TypeCoverageRequest>(GlobalState::handle_type_coverage)
         .on_sync_mut::<GotoDeclaration>(GlobalState::handle_goto_declaration)
         .on_sync_mut::<GotoDefinition>(GlobalState::handle_goto_definition)
         .on_sync_mut::<GotoTypeDefinition>(GlobalState::handle_goto_type_definition)
@@ -435,11 +808,39 @@ impl<'sender> GlobalState<'sender> {
         .on_sync_mut::<InlayHintRequest>(GlobalState::inlay_hints)
         .on_sync_mut::<Shutdown>(GlobalState::handle_shutdown)
         .finish();
+        self.cancellations.finish_request();
+        self.cancellation_token = Default::default();
+        self.set_cancellation_token(Default::default());
+        self.enforce_memory_limits();
+    }
+
+    fn enforce_memory_limits(&mut self) {
+        for workspace in &mut self.workspaces {
+            if let Some(project) = &mut workspace.project {
+                let evicted = project.enforce_memory_limit();
+                GLOBAL_EVICTED_FILES_COUNTER.fetch_add(evicted, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn set_cancellation_token(&mut self, token: CancellationToken) {
+        for workspace in &mut self.workspaces {
+            if let Some(project) = &mut workspace.project {
+                project.set_cancellation_token(token.clone())
+            }
+        }
+    }
+
+    pub(crate) fn check_cancelled(&self) -> anyhow::Result<()> {
+        if self.cancellation_token.is_cancelled() {
+            bail!(Cancelled)
+        }
+        Ok(())
     }
 
     fn on_lsp_message_and_return_on_shutdown(&mut self, msg: Message) -> bool {
         // It is a bit questionable that we use AssertUnwindSafe here. But the data is mostly in
-        // self.project and will be cleaned up if it panics.
+        // the projects and will be cleaned up if it panics.
         tracing::trace!("New LSP message: {msg:?}");
         let mut was_message = None;
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
@@ -484,31 +885,58 @@ impl<'sender> GlobalState<'sender> {
             .write()
             .unwrap()
             .clear();
-        if let Some(project) = self.project.take() {
-            self.panic_recovery = Some(project.into_panic_recovery());
+        for workspace in &mut self.workspaces {
+            workspace.recover_from_panic()
         }
     }
 
-    fn on_notify_events(&mut self, event: NotifyEvent) {
-        self.on_notify_event(event);
-        // Check all events in the Notify queue
-        while let Some(next) = self.notify_receiver().and_then(|n| {
-            if cfg!(target_os = "windows") {
-                // On Windows some events simply cause multiple events (e.g. rename), but also writes
-                // to files may be a Create + Modify, so we simply wait. This is useful for tests, but
-                // might also be useful in other cases, so we don't have to compute states in between
-                // changes.
-                n.recv_timeout(std::time::Duration::from_millis(3)).ok()
-            } else {
-                n.try_recv().ok()
+    fn recover_workspace_from_panic(&mut self, index: usize) {
+        let workspaces = &self.workspaces;
+        self.changed_in_memory_files
+            .as_ref()
+            .write()
+            .unwrap()
+            .retain(|path| workspace_index(workspaces, path_str(path)) != index);
+        self.workspaces[index].recover_from_panic()
+    }
+
+    fn on_notify_events(&mut self, workspace_index: usize, event: NotifyEvent) {
+        if self.shutdown_requested {
+            // Only the exit notification is relevant at this point, file changes would only
+            // lead to diagnostics being published.
+            return;
+        }
+        let mut next = Some(event);
+        while let Some(event) = next {
+            // The project might have been recreated by a config change.
+            let workspace = &self.workspaces[workspace_index];
+            let (Some(project), Some(receiver)) =
+                (&workspace.project, workspace.notify_receiver().cloned())
+            else {
+                return;
+            };
+            // Some changes simply cause multiple events (e.g. rename on Windows), but also writes
+            // to files may be a Create + Modify, so we simply wait. This is useful for tests, but
+            // might also be useful in other cases, so we don't have to compute states in between
+            // changes.
+            let debounce_ms = project.settings().file_watcher_debounce_ms.unwrap_or(3);
+            // Check all events in the Notify queue
+            let events = vfs::receive_coalesced_events(
+                &receiver,
+                event,
+                std::time::Duration::from_millis(debounce_ms),
+            );
+            for event in events {
+                self.on_notify_event(workspace_index, event);
             }
-        }) {
-            self.on_notify_event(next);
+            // Events might have arrived while the others were processed.
+            next = receiver.try_recv().ok();
         }
     }
 
-    fn on_notify_event(&mut self, event: NotifyEvent) {
-        if let Some(project) = &mut self.project {
+    fn on_notify_event(&mut self, workspace_index: usize, event: NotifyEvent) {
+        let workspace = &mut self.workspaces[workspace_index];
+        if let Some(project) = &mut workspace.project {
             match event {
                 Ok(event) => {
                     match event.kind {
@@ -518,23 +946,28 @@ impl<'sender> GlobalState<'sender> {
                                 .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
                             tracing::info!("Notify Event: {event:?}");
+                            let mut config_changed = false;
+                            // Directories with lots of changes are read again as a whole.
+                            let rescan = event.need_rescan();
                             for path in event.paths.into_iter() {
-                                if self.paths_that_invalidate_whole_project.contains(&path) {
-                                    // Since invalidating the whole project is as bad as a panic we
-                                    // just use that mechanism to recover from such a worst case
-                                    // change. This might be something like changing the used
-                                    // python version.
-
-                                    // TODO this ignores a few events in the vec that might
-                                    // invalidate additional files that get caught in the panic
-                                    // recovery and reused. Currently this is not handled
-                                    // correctly. If the VFS rechecks files, then it could be fine,
-                                    // BUT we should document that here.
-                                    tracing::info!(
-                                        "Reindex because a file was changed that invalidates the whole project: {path:?}"
-                                    );
-                                    self.recover_from_panic();
-                                    return;
+                                if rescan
+                                    && workspace
+                                        .paths_that_invalidate_whole_project
+                                        .iter()
+                                        .any(|p| p.starts_with(&path))
+                                {
+                                    // A config file might be one of the changed entries.
+                                    config_changed = true;
+                                }
+                                if workspace
+                                    .paths_that_invalidate_whole_project
+                                    .contains(&path)
+                                {
+                                    // The config is loaded again once the other paths are
+                                    // invalidated, since it might need a new project.
+                                    tracing::info!("A config file changed: {path:?}");
+                                    config_changed = true;
+                                    continue;
                                 }
                                 if let Some(p) = path.to_str() {
                                     debug_assert!(path.is_absolute());
@@ -546,9 +979,16 @@ impl<'sender> GlobalState<'sender> {
                                         p
                                     };
                                     let p = project.vfs_handler().unchecked_abs_path(s);
-                                    project.invalidate_path(&p)
+                                    if rescan {
+                                        project.rescan_path(&p)
+                                    } else {
+                                        project.invalidate_path(&p)
+                                    }
                                 }
                             }
+                            if config_changed {
+                                self.reload_config(workspace_index)
+                            }
                         }
                         EventKind::Access(_) => (), // Ignore access, they are probably never relevant
                         _ => tracing::debug!("Ignored Notify Event: {event:?}"),
@@ -558,7 +998,7 @@ impl<'sender> GlobalState<'sender> {
                     tracing::error!(
                         "Invalidating project, because of a notify event error: {err:?}"
                     );
-                    self.project = None;
+                    workspace.project = None;
                 }
             }
         }
@@ -574,12 +1014,194 @@ impl<'sender> GlobalState<'sender> {
     }
 
     fn complete_request(&mut self, response: lsp_server::Response) {
+        if let Some(roots) = self.configuration_requests.remove(&response.id) {
+            self.on_configuration_response(roots, response);
+            return;
+        }
+        if response.id.to_string().contains(SERVER_REQUEST_ID_PREFIX) {
+            if let Some(err) = response.error {
+                tracing::warn!("A request to the client failed: {}", err.message);
+            }
+            return;
+        }
+        if response.id.to_string().contains(CREATE_REQUEST_ID_PREFIX) {
+            self.progress_creations.complete(self.sender, &response);
+            if let Some(err) = response.error {
+                tracing::warn!("Creating a work done progress failed: {}", err.message);
+            }
+            return;
+        }
         tracing::error!("unhandled request: {:?}", response);
     }
 
+    pub(crate) fn send_request<R: lsp_types::request::Request>(
+        &self,
+        params: R::Params,
+    ) -> lsp_server::RequestId {
+        static SERVER_REQUEST_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = SERVER_REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let id = lsp_server::RequestId::from(format!("{SERVER_REQUEST_ID_PREFIX}{n}"));
+        let request = lsp_server::Request::new(id.clone(), R::METHOD.to_owned(), params);
+        _ = self.sender.send(request.into());
+        id
+    }
+
+    pub(crate) fn show_warning(&self, message: String) {
+        show_warning(self.sender, message)
+    }
+
+    /// Pulls the settings of all workspaces, if the client supports it.
+    pub(crate) fn request_configuration(&mut self) {
+        if !self.client_capabilities.workspace_configuration() {
+            return;
+        }
+        let roots: Vec<_> = self.workspaces.iter().map(|w| w.root.clone()).collect();
+        let local_fs = LocalFS::without_watcher();
+        let items = roots
+            .iter()
+            .map(|root| {
+                let path = local_fs.normalize_unchecked_abs_path(root);
+                lsp_types::ConfigurationItem {
+                    scope_uri: Some(to_uri(PathWithScheme::with_file_scheme(path).as_uri())),
+                    section: Some(SETTINGS_SECTION.to_owned()),
+                }
+            })
+            .collect();
+        let id = self.send_request::<lsp_types::request::WorkspaceConfiguration>(
+            lsp_types::ConfigurationParams { items },
+        );
+        self.configuration_requests.insert(id, roots);
+    }
+
+    fn on_configuration_response(&mut self, roots: Vec<String>, response: lsp_server::Response) {
+        if let Some(err) = response.error {
+            tracing::warn!("Fetching the configuration failed: {}", err.message);
+            return;
+        }
+        let values: Vec<serde_json::Value> = match from_json(
+            "ConfigurationResponse",
+            &response.result.unwrap_or_default(),
+        ) {
+            Ok(values) => values,
+            Err(err) => {
+                tracing::error!("{err}");
+                return;
+            }
+        };
+        for (root, value) in roots.into_iter().zip(values) {
+            let settings = match ClientSettings::from_json(&value) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    self.show_warning(err.to_string());
+                    continue;
+                }
+            };
+            if let Some(index) = self.workspaces.iter().position(|w| w.root == root) {
+                self.set_workspace_settings(index, settings)
+            }
+        }
+    }
+
+    /// Sets the settings for all workspaces.
+    pub(crate) fn set_client_settings(&mut self, settings: ClientSettings) {
+        for index in 0..self.workspaces.len() {
+            self.set_workspace_settings(index, settings.clone());
+        }
+        self.client_settings = settings;
+    }
+
+    /// Only invalidates the state that depends on the settings that changed.
+    fn set_workspace_settings(&mut self, index: usize, settings: ClientSettings) {
+        let workspace = &mut self.workspaces[index];
+        let old = std::mem::replace(&mut workspace.settings, settings);
+        let new = &workspace.settings;
+        tracing::info!("Settings for {:?}: {new:?}", &workspace.root);
+        let type_checker_changed = old.type_checker != new.type_checker;
+        let inlay_hints_changed = old.inlay_hints != new.inlay_hints;
+        // Projects that are not loaded yet will use the new settings anyway.
+        if type_checker_changed {
+            self.reload_config(index)
+        }
+        if inlay_hints_changed && self.client_capabilities.inlay_hint_refresh() {
+            self.send_request::<lsp_types::request::InlayHintRefreshRequest>(());
+        }
+    }
+
+    /// Loads the config of a workspace again after a config file or the settings changed. The
+    /// project is only created again if module resolution depends on the options that changed.
+    fn reload_config(&mut self, index: usize) {
+        let workspace = &mut self.workspaces[index];
+        let Some(mut project) = workspace.project.take() else {
+            return;
+        };
+        // The paths that might contain a config were already watched when the project was
+        // created.
+        let options = workspace.load_options(
+            self.sender,
+            &self.typeshed_path,
+            project.vfs_handler(),
+            |_| (),
+        );
+        let unknown_options = options.settings.unknown_options.clone();
+        let change = project.apply_changed_options(options);
+        // A new project publishes them when it is created.
+        if change == OptionsChange::Applied {
+            workspace.publish_config_diagnostics(
+                self.sender,
+                project.vfs_handler(),
+                &unknown_options,
+            );
+        }
+        workspace.project = Some(project);
+        tracing::info!(
+            "Options of {:?} after the config or settings changed: {change:?}",
+            &workspace.root
+        );
+        match change {
+            OptionsChange::Unchanged => (),
+            OptionsChange::Applied => {
+                let in_memory_files = self.in_memory_files_of_workspace(index);
+                self.refresh_diagnostics(in_memory_files)
+            }
+            OptionsChange::NeedsReindex => self.reload_workspace(index),
+        }
+    }
+
+    fn in_memory_files_of_workspace(&self, index: usize) -> Vec<PathWithScheme> {
+        self.workspaces[index]
+            .project
+            .iter()
+            .flat_map(|project| project.in_memory_files().map(|(path, ..)| path.clone()))
+            .collect()
+    }
+
+    /// Reloads the project of a workspace and lets the client know that the diagnostics of its
+    /// files might have changed.
+    fn reload_workspace(&mut self, index: usize) {
+        let in_memory_files = self.in_memory_files_of_workspace(index);
+        self.recover_workspace_from_panic(index);
+        self.refresh_diagnostics(in_memory_files)
+    }
+
+    fn refresh_diagnostics(&mut self, in_memory_files: Vec<PathWithScheme>) {
+        if self.client_capabilities.should_push_diagnostics() {
+            let mut changed_files = self.changed_in_memory_files.as_ref().write().unwrap();
+            for path in in_memory_files {
+                if !changed_files.contains(&path) {
+                    changed_files.push(path)
+                }
+            }
+        } else if self.client_capabilities.diagnostics_refresh() {
+            self.send_request::<lsp_types::request::WorkspaceDiagnosticRefresh>(());
+        }
+    }
+
     fn publish_diagnostics_if_necessary(&mut self) {
         let encoding = self.client_capabilities.negotiated_encoding();
         let files = std::mem::take(&mut *self.changed_in_memory_files.as_ref().write().unwrap());
+        if !self.client_capabilities.should_push_diagnostics() {
+            return;
+        }
         if !files.is_empty() {
             tracing::info!(
                 "Needs to publish diagnostics for {} files start at #{}",
@@ -588,7 +1210,7 @@ impl<'sender> GlobalState<'sender> {
             );
             for path in files {
                 self.sent_diagnostic_count += 1;
-                let project = self.project();
+                let project = self.project_for_path(&path);
                 let Some(document) = project.document(&path) else {
                     tracing::info!(
                         "Wanted to publish diagnostics for {}, but it does not exist anymore",
@@ -639,11 +1261,15 @@ impl<'sender> GlobalState<'sender> {
     ) -> anyhow::Result<PathWithScheme> {
         let (scheme, path) = unpack_uri(uri)?;
         let handler = project.vfs_handler();
-        let path = handler.unchecked_abs_path_from_uri(Arc::from(path));
         Ok(if scheme.eq_lowercase("file") {
-            let path = handler.normalize_rc_path(path);
-            PathWithScheme::with_file_scheme(path)
+            let path = handler.unchecked_abs_path_from_uri(Arc::from(path));
+            PathWithScheme::with_file_scheme(handler.normalize_rc_path(path))
         } else {
+            // Documents of other schemes (e.g. `untitled:Untitled-1`) only exist in the client.
+            // Their path is everything after the colon and stays percent-encoded, so the URI can
+            // be recreated exactly.
+            let rest = &uri.as_str()[scheme.as_str().len() + 1..];
+            let path = handler.unchecked_abs_path_from_uri(Arc::from(rest));
             let path = handler.unchecked_normalized_path(path);
             PathWithScheme::new(Arc::new(scheme.to_lowercase().into_boxed_str()), path)
         })
@@ -728,9 +1354,8 @@ impl<'sender> RequestDispatcher<'_, 'sender> {
             tracing::info_span!("request", method = ?req.method, "request_id" = ?req.id).entered();
         tracing::debug!(?params);
         let result = f(self.global_state, params);
-        if let Ok(response) = result_to_response::<R>(req.id, result) {
-            self.global_state.respond(response);
-        }
+        let response = result_to_response::<R>(req.id, result);
+        self.global_state.respond(response);
 
         self
     }
@@ -777,23 +1402,21 @@ pub fn from_json<T: DeserializeOwned>(
         .map_err(|e| anyhow::format_err!("Failed to deserialize {what}: {e}; {json}"))
 }
 
-struct Cancelled(); // TODO currently unused
-
 fn result_to_response<R>(
     id: lsp_server::RequestId,
     result: anyhow::Result<R::Result>,
-) -> Result<lsp_server::Response, Cancelled>
+) -> lsp_server::Response
 where
     R: lsp_types::request::Request,
     R::Params: DeserializeOwned,
     R::Result: Serialize,
 {
-    let res = match result {
+    match result {
         Ok(resp) => lsp_server::Response::new_ok(id, &resp),
         Err(e) => match e.downcast::<LspError>() {
             Ok(lsp_error) => lsp_server::Response::new_err(id, lsp_error.code, lsp_error.message),
-            Err(e) => match e.downcast::<u8/*TODO Cancelled*/>() {
-                Ok(_cancelled) => todo!(), // return Err(cancelled),
+            Err(e) => match e.downcast::<Cancelled>() {
+                Ok(_) => cancelled_response(id),
                 Err(e) => lsp_server::Response::new_err(
                     id,
                     lsp_server::ErrorCode::InternalError as i32,
@@ -801,8 +1424,15 @@ where
                 ),
             },
         },
-    };
-    Ok(res)
+    }
+}
+
+fn cancelled_response(id: lsp_server::RequestId) -> lsp_server::Response {
+    lsp_server::Response::new_err(
+        id,
+        lsp_server::ErrorCode::RequestCanceled as i32,
+        Cancelled.to_string(),
+    )
 }
 
 #[derive(Debug)]
@@ -823,7 +1453,7 @@ impl std::fmt::Display for LspError {
 
 impl std::error::Error for LspError {}
 
-fn patch_path_prefix(path: &Uri) -> anyhow::Result<String> {
+pub(crate) fn patch_path_prefix(path: &Uri) -> anyhow::Result<String> {
     let (_, path) = unpack_uri(path)?;
     use std::path::{Component, Prefix};
     if cfg!(windows) {
@@ -861,6 +1491,127 @@ fn patch_path_prefix(path: &Uri) -> anyhow::Result<String> {
     }
 }
 
+/// Unknown options of config files are usually typos and therefore reported as warnings on the
+/// config file. Returns the config files with diagnostics, the diagnostics of the previous config
+/// files are cleared.
+fn publish_config_diagnostics(
+    sender: &Sender<lsp_server::Message>,
+    vfs_handler: &dyn vfs::VfsHandler,
+    unknown_options: &[UnknownOption],
+    previous: Vec<PathWithScheme>,
+) -> Vec<PathWithScheme> {
+    let mut by_file: Vec<(PathWithScheme, Vec<lsp_types::Diagnostic>)> = vec![];
+    for unknown in unknown_options {
+        let Some(config_path) = &unknown.config_path else {
+            continue;
+        };
+        let path =
+            PathWithScheme::with_file_scheme(vfs_handler.normalize_rc_path(config_path.clone()));
+        let code = std::fs::read_to_string(config_path.as_ref()).unwrap_or_default();
+        let diagnostic = lsp_types::Diagnostic {
+            range: config_option_range(&code, unknown.section.as_deref(), &unknown.name),
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            source: Some("zuban".to_owned()),
+            message: unknown.message(),
+            ..Default::default()
+        };
+        match by_file.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, diagnostics)) => diagnostics.push(diagnostic),
+            None => by_file.push((path, vec![diagnostic])),
+        }
+    }
+    let cleared = previous
+        .into_iter()
+        .filter(|path| by_file.iter().all(|(p, _)| p != path))
+        .map(|path| (path, vec![]))
+        .collect::<Vec<_>>();
+    let with_diagnostics = by_file.iter().map(|(path, _)| path.clone()).collect();
+    for (path, diagnostics) in by_file.into_iter().chain(cleared) {
+        let not = lsp_server::Notification::new(
+            lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
+            lsp_types::PublishDiagnosticsParams {
+                uri: to_uri(path.as_uri()),
+                diagnostics,
+                version: None,
+            },
+        );
+        _ = sender.send(not.into());
+    }
+    with_diagnostics
+}
+
+/// The range of the name of an option like `name = value` in the given section, the start of the
+/// file if there is no such line.
+fn config_option_range(code: &str, section: Option<&str>, name: &str) -> lsp_types::Range {
+    let mut current_section = None;
+    for (line_nr, line) in code.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            current_section = Some(trimmed.trim_end().trim_matches(['[', ']']).trim());
+            continue;
+        }
+        if let Some(section) = section
+            && !current_section.is_some_and(|current| is_same_config_section(current, section))
+        {
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix(name)
+            && rest.trim_start().starts_with('=')
+        {
+            let start = (line.len() - trimmed.len()) as u32;
+            return lsp_types::Range::new(
+                lsp_types::Position::new(line_nr as u32, start),
+                lsp_types::Position::new(line_nr as u32, start + name.len() as u32),
+            );
+        }
+    }
+    lsp_types::Range::default()
+}
+
+/// A section of a mypy.ini like `[mypy-foo.*,bar]` applies to all of its modules.
+fn is_same_config_section(header: &str, section: &str) -> bool {
+    if let Some(modules) = header.strip_prefix("mypy-")
+        && let Some(module) = section.strip_prefix("mypy-")
+    {
+        return modules.split(',').any(|m| m.trim() == module);
+    }
+    header == section
+}
+
+fn show_warning(sender: &Sender<lsp_server::Message>, message: String) {
+    use lsp_types::{
+        MessageType, ShowMessageParams,
+        notification::{Notification, ShowMessage},
+    };
+    let not = lsp_server::Notification::new(
+        ShowMessage::METHOD.to_owned(),
+        ShowMessageParams {
+            typ: MessageType::WARNING,
+            message,
+        },
+    );
+    sender.send(lsp_server::Message::Notification(not)).unwrap();
+}
+
+fn log_message(sender: &Sender<lsp_server::Message>, message: String) {
+    use lsp_types::{
+        LogMessageParams, MessageType,
+        notification::{LogMessage, Notification},
+    };
+    let not = lsp_server::Notification::new(
+        LogMessage::METHOD.to_owned(),
+        LogMessageParams {
+            typ: MessageType::INFO,
+            message,
+        },
+    );
+    sender.send(lsp_server::Message::Notification(not)).unwrap();
+}
+
+fn path_str(path: &PathWithScheme) -> &str {
+    path.path()
+}
+
 fn unpack_uri(uri: &lsp_types::Uri) -> anyhow::Result<(&Scheme, Cow<'_, str>)> {
     let Some(scheme) = uri.scheme() else {
         bail!("No scheme found in uri {}", uri.as_str())
@@ -0,0 +1,90 @@
+//! Settings that are configured in the editor, either pushed by
+//! `workspace/didChangeConfiguration` or pulled with `workspace/configuration`.
+//!
+//! The settings of the `zuban` section look like this:
+//!
+//! ```json
+//! {
+//!     "strict": true,
+//!     "python_version": "3.12",
+//!     "inlayHints": {"variableTypes": false, "functionReturnTypes": true}
+//! }
+//! ```
+//!
+//! Everything except `inlayHints` is a type checker option with the same name as in a Mypy
+//! config file.
+
+use anyhow::bail;
+use serde_json::Value;
+use zuban_python::InlayHintOptions;
+
+pub(crate) const SECTION: &str = "zuban";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ClientSettings {
+    /// These overwrite the options of config files.
+    pub type_checker: Vec<(String, String)>,
+    pub inlay_hints: InlayHintOptions,
+}
+
+impl ClientSettings {
+    pub fn from_json(value: &Value) -> anyhow::Result<Self> {
+        let mut settings = Self::default();
+        let map = match value {
+            Value::Null => return Ok(settings),
+            Value::Object(map) => map,
+            _ => bail!("Expected the {SECTION} settings to be an object, got {value}"),
+        };
+        for (key, value) in map {
+            if key == "inlayHints" {
+                settings.inlay_hints = inlay_hint_options(value)?;
+            } else if let Some(value) = to_config_value(key, value)? {
+                settings.type_checker.push((key.clone(), value))
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn type_checker_options(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.type_checker
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+fn inlay_hint_options(value: &Value) -> anyhow::Result<InlayHintOptions> {
+    let mut options = InlayHintOptions::default();
+    let Value::Object(map) = value else {
+        bail!("Expected inlayHints to be an object, got {value}")
+    };
+    for (key, value) in map {
+        let Value::Bool(enabled) = value else {
+            bail!("Expected inlayHints.{key} to be a bool, got {value}")
+        };
+        match key.as_str() {
+            "variableTypes" => options.variable_types = *enabled,
+            "functionReturnTypes" => options.function_return_types = *enabled,
+            _ => bail!("Unrecognized option: inlayHints.{key}"),
+        }
+    }
+    Ok(options)
+}
+
+/// Type checker options are passed to the config parser in the same form as in a Mypy ini file.
+fn to_config_value(key: &str, value: &Value) -> anyhow::Result<Option<String>> {
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(s.as_str()),
+                _ => bail!("Expected {key} to be a list of strings, got {value}"),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .join(","),
+        Value::Object(_) => bail!("Expected {key} to be a simple value, got {value}"),
+    }))
+}
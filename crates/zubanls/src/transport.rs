@@ -0,0 +1,335 @@
+//! Transports other than stdio, which make it possible to run the server in a container or on a
+//! remote machine and to serve editors that run in a browser.
+//!
+//! A listening server serves one client after another. Since the indexed projects are kept
+//! between connections, reconnecting to an already opened workspace is fast.
+//!
+//! There is no authentication, every client that can connect may read the files of the
+//! workspaces it opens. Without a host, the server therefore only listens on `127.0.0.1`, and
+//! other addresses should only be used within a trusted network.
+
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use anyhow::bail;
+use crossbeam_channel::{Receiver, Sender, bounded, select, unbounded};
+use lsp_server::{Connection, Message};
+use tungstenite::{WebSocket, error::ProtocolError, protocol::Role};
+use vfs::NormalizedPath;
+
+use crate::server::{WarmState, run_session};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// LSP messages with `Content-Length` headers, like on stdio.
+    Tcp,
+    /// Every LSP message is a text message without headers.
+    WebSocket,
+}
+
+/// An address like `tcp://127.0.0.1:8080` or `ws://0.0.0.0:8080`. Without a host (e.g.
+/// `tcp://8080`), the server listens on `127.0.0.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenAddress {
+    pub transport: Transport,
+    pub address: String,
+}
+
+impl FromStr for ListenAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (transport, address) = if let Some(address) = s.strip_prefix("tcp://") {
+            (Transport::Tcp, address)
+        } else if let Some(address) = s.strip_prefix("ws://") {
+            (Transport::WebSocket, address)
+        } else {
+            bail!("Expected an address like tcp://HOST:PORT or ws://HOST:PORT, got {s:?}")
+        };
+        if address.is_empty() {
+            bail!("Expected a HOST:PORT after the scheme in {s:?}")
+        }
+        let address = if address.bytes().all(|b| b.is_ascii_digit()) {
+            format!("127.0.0.1:{address}")
+        } else {
+            address.to_owned()
+        };
+        Ok(Self { transport, address })
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.transport {
+            Transport::Tcp => "tcp",
+            Transport::WebSocket => "ws",
+        };
+        write!(f, "{scheme}://{}", self.address)
+    }
+}
+
+pub fn run_server_listening(address: &ListenAddress) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&address.address)?;
+    tracing::info!("Listening on {address}");
+    if !listener.local_addr()?.ip().is_loopback() {
+        tracing::warn!(
+            "{address} is not a loopback address. There is no authentication, every client that \
+             can connect may read the files of the workspaces it opens."
+        );
+    }
+    run_server_with_listener(listener, address.transport, None)
+}
+
+/// Accepts clients until the listener fails. Only one client is served at a time.
+pub fn run_server_with_listener(
+    listener: TcpListener,
+    transport: Transport,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+) -> anyhow::Result<()> {
+    let mut warm_state = WarmState::default();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!("Failed to accept a client: {err}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "<unknown>".to_owned());
+        tracing::info!("Client {peer} connected");
+        let result = match transport {
+            Transport::Tcp => serve_tcp_client(stream, typeshed_path.clone(), &mut warm_state),
+            Transport::WebSocket => {
+                serve_websocket_client(stream, typeshed_path.clone(), &mut warm_state)
+            }
+        };
+        match result {
+            Ok(()) => tracing::info!("Client {peer} disconnected"),
+            Err(err) => tracing::warn!("Connection to client {peer} ended with an error: {err}"),
+        }
+    }
+    Ok(())
+}
+
+fn serve_tcp_client(
+    stream: TcpStream,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    warm_state: &mut WarmState,
+) -> anyhow::Result<()> {
+    let (reader_sender, reader_receiver) = bounded::<Message>(0);
+    let mut read_stream = BufReader::new(stream.try_clone()?);
+    let reader = thread::Builder::new()
+        .name("zubanls:tcp-reader".into())
+        .spawn(move || -> io::Result<()> {
+            while let Some(msg) = Message::read(&mut read_stream)? {
+                let is_exit = is_exit(&msg);
+                if reader_sender.send(msg).is_err() || is_exit {
+                    break;
+                }
+            }
+            Ok(())
+        })?;
+
+    let (writer_sender, writer_receiver) = bounded::<Message>(0);
+    let mut write_stream = stream.try_clone()?;
+    let writer = thread::Builder::new()
+        .name("zubanls:tcp-writer".into())
+        .spawn(move || {
+            let result = writer_receiver
+                .iter()
+                .try_for_each(|msg| msg.write(&mut write_stream));
+            // Once the client is gone, the messages of the server are dropped until the session
+            // ends.
+            for _ in writer_receiver {}
+            result
+        })?;
+
+    let connection = Connection {
+        sender: writer_sender,
+        receiver: reader_receiver,
+    };
+    let result = run_session(connection, typeshed_path, warm_state, || Ok(()));
+    // The connection was dropped, which lets the writer finish. The reader might still wait for
+    // a client that did not send an exit notification.
+    let written = join(writer);
+    stream.shutdown(Shutdown::Both).ok();
+    let read = join(reader);
+    result?;
+    written?;
+    read?;
+    Ok(())
+}
+
+fn serve_websocket_client(
+    stream: TcpStream,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+    warm_state: &mut WarmState,
+) -> anyhow::Result<()> {
+    // A WebSocket cannot be split into a reading and a writing half. Reading also writes (e.g.
+    // the handshake or answers to pings), which is therefore passed on to the writer, so that
+    // frames are never interleaved.
+    let (written_sender, written_receiver) = unbounded::<Vec<u8>>();
+    let (writer_sender, writer_receiver) = bounded::<Message>(0);
+    let client_closed = Arc::new(AtomicBool::new(false));
+    let write_stream = stream.try_clone()?;
+    let closed = client_closed.clone();
+    let writer = thread::Builder::new()
+        .name("zubanls:websocket-writer".into())
+        .spawn(move || {
+            let mut websocket = WebSocket::from_raw_socket(write_stream, Role::Server, None);
+            let result =
+                write_websocket_messages(&mut websocket, &writer_receiver, written_receiver);
+            // Once the client is gone, the messages of the server are dropped until the session
+            // ends.
+            for _ in writer_receiver {}
+            if result.is_ok() && !closed.load(Ordering::Acquire) {
+                websocket.close(None).ok();
+                websocket.flush().ok();
+            }
+            result
+        })?;
+
+    let read_half = ReadHalf {
+        stream: stream.try_clone()?,
+        written: written_sender,
+    };
+    let websocket = match tungstenite::accept(read_half) {
+        Ok(websocket) => websocket,
+        Err(err) => {
+            drop(writer_sender);
+            join(writer).ok();
+            return Err(err.into());
+        }
+    };
+    let (reader_sender, reader_receiver) = bounded::<Message>(0);
+    let reader = thread::Builder::new()
+        .name("zubanls:websocket-reader".into())
+        .spawn(move || read_websocket_messages(websocket, reader_sender, &client_closed))?;
+
+    let connection = Connection {
+        sender: writer_sender,
+        receiver: reader_receiver,
+    };
+    let result = run_session(connection, typeshed_path, warm_state, || Ok(()));
+    // Like for TCP, the reader might still wait for a client that did not close the connection.
+    let written = join(writer);
+    stream.shutdown(Shutdown::Both).ok();
+    let read = join(reader);
+    result?;
+    written?;
+    read?;
+    Ok(())
+}
+
+/// The stream of the reading thread, which passes everything that is written on to the writing
+/// thread.
+#[derive(Debug)]
+struct ReadHalf {
+    stream: TcpStream,
+    written: Sender<Vec<u8>>,
+}
+
+impl Read for ReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for ReadHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // tungstenite only writes complete frames. If the writer is gone, nothing can be sent
+        // anymore anyway.
+        _ = self.written.send(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns when either the client or the connection is done.
+fn read_websocket_messages(
+    mut websocket: WebSocket<ReadHalf>,
+    reader_sender: Sender<Message>,
+    client_closed: &AtomicBool,
+) -> anyhow::Result<()> {
+    loop {
+        match websocket.read() {
+            Ok(tungstenite::Message::Text(text)) => {
+                let msg: Message = serde_json::from_str(&text)?;
+                let is_exit = is_exit(&msg);
+                if reader_sender.send(msg).is_err() || is_exit {
+                    return Ok(());
+                }
+            }
+            // Pings and closing the connection are answered by tungstenite with the next read,
+            // which then ends with `ConnectionClosed`.
+            Ok(_) => (),
+            Err(
+                tungstenite::Error::ConnectionClosed
+                | tungstenite::Error::AlreadyClosed
+                | tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake),
+            ) => {
+                client_closed.store(true, Ordering::Release);
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Returns when either the session is done or the client is gone.
+fn write_websocket_messages(
+    websocket: &mut WebSocket<TcpStream>,
+    writer_receiver: &Receiver<Message>,
+    mut written_receiver: Receiver<Vec<u8>>,
+) -> anyhow::Result<()> {
+    'session: loop {
+        select! {
+            recv(writer_receiver) -> msg => {
+                let Ok(msg) = msg else {
+                    break 'session;
+                };
+                let text = serde_json::to_string(&msg)?;
+                websocket.send(tungstenite::Message::text(text))?;
+            },
+            recv(written_receiver) -> bytes => {
+                match bytes {
+                    Ok(bytes) => {
+                        let stream = websocket.get_mut();
+                        stream.write_all(&bytes)?;
+                        stream.flush()?;
+                    }
+                    // The reader is done
+                    Err(_) => written_receiver = crossbeam_channel::never(),
+                }
+            },
+        }
+    }
+    // What the reader wrote last (e.g. the answer to closing the connection) is still sent.
+    for bytes in written_receiver.try_iter() {
+        websocket.get_mut().write_all(&bytes)?
+    }
+    Ok(())
+}
+
+fn is_exit(msg: &Message) -> bool {
+    use lsp_types::notification::{Exit, Notification as _};
+    matches!(msg, Message::Notification(n) if n.method == Exit::METHOD)
+}
+
+fn join<T, E: Into<anyhow::Error>>(handle: thread::JoinHandle<Result<T, E>>) -> anyhow::Result<T> {
+    handle
+        .join()
+        .map_err(|e| anyhow::anyhow!("Error while joining the thread: {e:?}"))?
+        .map_err(Into::into)
+}
@@ -1,4 +1,12 @@
-use std::{cell::Cell, path::Path, str::FromStr, time::Duration};
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
 
 use crossbeam_channel::RecvTimeoutError;
 use lsp_server::Message;
@@ -49,13 +57,36 @@ impl Connection {
         }
     }
 
+    /// Connects to a server that was started with `zubanls::run_server_with_listener`.
+    pub(crate) fn connect(address: SocketAddr, transport: zubanls::Transport) -> Self {
+        logging_config::setup_logging_for_tests();
+        let client = match transport {
+            zubanls::Transport::Tcp => lsp_server::Connection::connect(address).unwrap().0,
+            zubanls::Transport::WebSocket => websocket_client(address),
+        };
+        Self {
+            client,
+            server_thread: None,
+            request_id_counter: Cell::new(0),
+            server_capabilities: None,
+        }
+    }
+
     pub(crate) fn initialized(
         roots: &[&str],
         position_encodings: Option<Vec<lsp_types::PositionEncodingKind>>,
         pull_diagnostics: bool,
+        work_done_progress: bool,
+        workspace_configuration: bool,
     ) -> Self {
         let mut slf = Self::new();
-        let response = slf.initialize(roots, position_encodings, pull_diagnostics);
+        let response = slf.initialize(
+            roots,
+            position_encodings,
+            pull_diagnostics,
+            work_done_progress,
+            workspace_configuration,
+        );
         slf.server_capabilities = Some(response.capabilities);
         slf
     }
@@ -65,6 +96,8 @@ impl Connection {
         roots: &[&str],
         position_encodings: Option<Vec<lsp_types::PositionEncodingKind>>,
         pull_diagnostics: bool,
+        work_done_progress: bool,
+        workspace_configuration: bool,
     ) -> InitializeResult {
         let capabilities = lsp_types::ClientCapabilities {
             workspace: Some(lsp_types::WorkspaceClientCapabilities {
@@ -82,6 +115,7 @@ impl Connection {
                     ]),
                     ..Default::default()
                 }),
+                configuration: Some(workspace_configuration),
                 ..Default::default()
             }),
             general: Some(lsp_types::GeneralClientCapabilities {
@@ -96,6 +130,10 @@ impl Connection {
                 }),
                 ..Default::default()
             }),
+            window: Some(lsp_types::WindowClientCapabilities {
+                work_done_progress: Some(work_done_progress),
+                ..Default::default()
+            }),
             ..Default::default()
         };
         let initialize_params = lsp_types::InitializeParams {
@@ -188,7 +226,7 @@ impl Connection {
             .expect("Expected to be able to send a message");
     }
 
-    fn expect_response(&self) -> lsp_server::Response {
+    pub(crate) fn expect_response(&self) -> lsp_server::Response {
         match self.recv_timeout() {
             Ok(Message::Response(response)) => response,
             Ok(msg) => panic!("Unexpected message, expected response: {msg:?}"),
@@ -212,6 +250,66 @@ impl Connection {
         }
     }
 
+    pub(crate) fn expect_request<R: lsp_types::request::Request>(
+        &self,
+    ) -> (lsp_server::RequestId, R::Params) {
+        match self.recv_timeout() {
+            Ok(Message::Request(request)) => request
+                .extract::<R::Params>(R::METHOD)
+                .unwrap_or_else(|err| panic!("Wanted {}, got {err:?}", R::METHOD)),
+            Ok(msg) => panic!("Unexpected message, expected request: {msg:?}"),
+            Err(err) => panic!("Expected the request {}, but got: {err:?}", R::METHOD),
+        }
+    }
+
+    /// Receives all `$/progress` notifications for a token until the progress ends. Requests to
+    /// create the token are acknowledged after a while, no progress may be received in the
+    /// meantime. Responses that arrive before the progress ended are returned as well.
+    pub(crate) fn expect_work_done_progress(
+        &self,
+    ) -> (Vec<lsp_types::WorkDoneProgress>, Vec<lsp_server::Response>) {
+        let mut result = vec![];
+        let mut responses = vec![];
+        let mut received = VecDeque::new();
+        loop {
+            let msg = match received.pop_front() {
+                Some(msg) => Ok(msg),
+                None => self.recv_timeout(),
+            };
+            match msg {
+                Ok(Message::Request(request)) => {
+                    assert_eq!(
+                        request.method,
+                        <lsp_types::request::WorkDoneProgressCreate as lsp_types::request::Request>::METHOD
+                    );
+                    std::thread::sleep(Duration::from_millis(100));
+                    while let Ok(msg) = self.client.receiver.try_recv() {
+                        if matches!(&msg, Message::Notification(not) if not.method == "$/progress")
+                        {
+                            panic!("Received {msg:?} before the progress token was created")
+                        }
+                        received.push_back(msg);
+                    }
+                    self.send(lsp_server::Response::new_ok(request.id, ()));
+                }
+                Ok(Message::Notification(not)) => {
+                    let params = not
+                        .extract::<lsp_types::ProgressParams>("$/progress")
+                        .unwrap_or_else(|err| panic!("Wanted $/progress, got {err:?}"));
+                    let lsp_types::ProgressParamsValue::WorkDone(value) = params.value;
+                    let is_end = matches!(value, lsp_types::WorkDoneProgress::End(_));
+                    result.push(value);
+                    if is_end {
+                        assert!(received.is_empty(), "Unexpected messages: {received:?}");
+                        return (result, responses);
+                    }
+                }
+                Ok(Message::Response(response)) => responses.push(response),
+                Err(err) => panic!("Expected progress, but got: {err:?}"),
+            }
+        }
+    }
+
     pub(crate) fn expect_notification_message(&self) -> lsp_types::ShowMessageParams {
         self.expect_notification::<lsp_types::notification::ShowMessage>()
     }
@@ -228,6 +326,48 @@ impl Connection {
     }
 }
 
+fn websocket_client(address: SocketAddr) -> lsp_server::Connection {
+    let stream = TcpStream::connect(address).unwrap();
+    let (mut websocket, _) = tungstenite::client(format!("ws://{address}"), stream).unwrap();
+    websocket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    // Zero capacity, so that the messages are all gone once the connection is dropped.
+    let (sender, to_server) = crossbeam_channel::bounded::<Message>(0);
+    let (from_server, receiver) = crossbeam_channel::unbounded();
+    std::thread::spawn(move || {
+        loop {
+            loop {
+                match to_server.try_recv() {
+                    Ok(msg) => {
+                        let text = serde_json::to_string(&msg).unwrap();
+                        websocket.send(tungstenite::Message::text(text)).unwrap();
+                    }
+                    Err(crossbeam_channel::TryRecvError::Empty) => break,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                        websocket.close(None).ok();
+                        return;
+                    }
+                }
+            }
+            match websocket.read() {
+                Ok(tungstenite::Message::Text(text)) => {
+                    _ = from_server.send(serde_json::from_str(&text).unwrap());
+                }
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(err))
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(_) => return,
+            }
+        }
+    });
+    lsp_server::Connection { sender, receiver }
+}
+
 impl Drop for Connection {
     fn drop(&mut self) {
         if let Ok(msg) = self.client.receiver.try_recv() {
@@ -11,11 +11,12 @@ use std::str::FromStr;
 
 use lsp_server::Response;
 use lsp_types::{
-    CodeActionParams, CompletionItem, CompletionItemKind, CompletionParams,
-    DiagnosticServerCapabilities, DocumentDiagnosticParams, DocumentDiagnosticReport,
-    DocumentDiagnosticReportResult, DocumentHighlightKind, DocumentHighlightParams,
-    DocumentSymbolParams, FoldingRangeParams, GotoDefinitionParams, HoverParams, InlayHintParams,
-    NumberOrString, PartialResultParams, Position, PositionEncodingKind, Range, ReferenceContext,
+    CodeActionContext, CodeActionKind, CodeActionParams, CompletionItem, CompletionItemKind,
+    CompletionParams, CompletionResponse, DiagnosticServerCapabilities, DiagnosticTag,
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    DocumentHighlightKind, DocumentHighlightParams, DocumentSymbolParams, FoldingRangeParams,
+    GotoDefinitionParams, HoverParams, InlayHintParams, NotebookCellKind, NumberOrString,
+    PartialResultParams, Position, PositionEncodingKind, PreviousResultId, Range, ReferenceContext,
     ReferenceParams, RenameParams, SelectionRangeParams, SemanticToken, SemanticTokenType,
     SemanticTokens, SemanticTokensParams, SemanticTokensRangeParams,
     SemanticTokensServerCapabilities, SignatureHelpParams, SymbolKind,
@@ -44,12 +45,13 @@ use serde_json::{Value, json};
 // https://users.rust-lang.org/t/is-there-any-way-to-set-panic-hook-reliably-in-the-test/18202
 use serial_test::{parallel, serial};
 use support::Project;
+use zubanls::GLOBAL_EVICTED_FILES_COUNTER;
 
 #[test]
 #[parallel]
 fn basic_server_setup() {
     let con = Connection::new();
-    let response = con.initialize(&["/foo/bar"], None, true);
+    let response = con.initialize(&["/foo/bar"], None, true, false, false);
 
     // Check diagnostic capabilities
     {
@@ -76,7 +78,7 @@ fn basic_server_setup() {
 #[test]
 #[parallel]
 fn request_after_shutdown_is_invalid() {
-    let con = Connection::initialized(&["/foo/bar"], None, true);
+    let con = Connection::initialized(&["/foo/bar"], None, true, false, false);
     con.request::<lsp_types::request::Shutdown>(());
 
     let expect_shutdown_already_requested = |response: Response| {
@@ -108,7 +110,7 @@ fn request_after_shutdown_is_invalid() {
 #[test]
 #[parallel]
 fn exit_without_shutdown() {
-    let con = Connection::initialized(&["/foo/bar"], None, true);
+    let con = Connection::initialized(&["/foo/bar"], None, true, false, false);
     con.notify::<lsp_types::notification::Exit>(());
 }
 
@@ -145,6 +147,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
             "items": [
                 {
                   "code": "attr-defined",
+                  "codeDescription": {"href": "https://mypy.readthedocs.io/en/stable/_refs.html#code-attr-defined"},
                   "message": "Module \"pkg.foo\" has no attribute \"Bar\"",
                   "range": {
                     "start": {
@@ -161,6 +164,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
                 },
                 {
                   "code": "operator",
+                  "codeDescription": {"href": "https://mypy.readthedocs.io/en/stable/_refs.html#code-operator"},
                   "message": "\"int\" not callable",
                   "range": {
                     "start": {
@@ -176,7 +180,8 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
                   "source": "zuban"
                 },
             ],
-            "kind": "full"
+            "kind": "full",
+            "resultId": "[..]"
         }),
     );
 
@@ -233,6 +238,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
               "items": [
                 {
                   "code": "name-defined",
+                  "codeDescription": {"href": "https://mypy.readthedocs.io/en/stable/_refs.html#code-name-defined"},
                   "message": "Name \"lala\" is not defined",
                   "range": {
                     "end": {
@@ -249,6 +255,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
                 }
               ],
               "kind": "full",
+              "resultId": "[..]",
               "uri": server.doc_id("pkg/foo.py").uri,
               "version": null
             },
@@ -256,6 +263,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
               "items": [
                 {
                   "code": "attr-defined",
+                  "codeDescription": {"href": "https://mypy.readthedocs.io/en/stable/_refs.html#code-attr-defined"},
                   "message": "Module \"pkg.foo\" has no attribute \"Bar\"",
                   "range": {
                     "end": {
@@ -272,6 +280,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
                 },
                 {
                   "code": "operator",
+                  "codeDescription": {"href": "https://mypy.readthedocs.io/en/stable/_refs.html#code-operator"},
                   "message": "\"int\" not callable",
                   "range": {
                     "end": {
@@ -288,6 +297,7 @@ fn diagnostics_for_saved_files_and_workspace_diagnostics() {
                 }
               ],
               "kind": "full",
+              "resultId": "[..]",
               "uri": server.doc_id("pkg/__init__.py").uri,
               "version": null
             }
@@ -369,6 +379,87 @@ fn in_memory_file_changes() {
     assert_eq!(error.code, lsp_server::ErrorCode::InvalidParams as i32);
 }
 
+#[test]
+#[parallel]
+fn pull_diagnostics_with_result_ids() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+
+        [file foo.py]
+        1()
+        "#,
+    )
+    .into_server();
+
+    let request = |previous_result_id: Option<String>| {
+        let DocumentDiagnosticReportResult::Report(report) = server
+            .request::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+                text_document: server.doc_id("foo.py"),
+                identifier: None,
+                previous_result_id,
+                partial_result_params: PartialResultParams::default(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+        else {
+            unreachable!()
+        };
+        report
+    };
+
+    let DocumentDiagnosticReport::Full(report) = request(None) else {
+        panic!("Expected a full report without a previous result id");
+    };
+    let result_id = report
+        .full_document_diagnostic_report
+        .result_id
+        .expect("Expected a result id");
+
+    let DocumentDiagnosticReport::Unchanged(report) = request(Some(result_id.clone())) else {
+        panic!("Expected an unchanged report for the same result id");
+    };
+    assert_eq!(
+        report.unchanged_document_diagnostic_report.result_id,
+        result_id
+    );
+
+    server.open_in_memory_file("foo.py", "1.0()\n");
+    let DocumentDiagnosticReport::Full(report) = request(Some(result_id.clone())) else {
+        panic!("Expected a full report after a change");
+    };
+    let new_result_id = report.full_document_diagnostic_report.result_id.unwrap();
+    assert_ne!(new_result_id, result_id);
+    let messages: Vec<_> = report
+        .full_document_diagnostic_report
+        .items
+        .into_iter()
+        .map(|d| d.message)
+        .collect();
+    assert_eq!(messages, vec!["\"float\" not callable"]);
+
+    server.request_and_expect_json::<WorkspaceDiagnosticRequest>(
+        WorkspaceDiagnosticParams {
+            identifier: None,
+            previous_result_ids: vec![PreviousResultId {
+                uri: server.doc_id("foo.py").uri,
+                value: new_result_id.clone(),
+            }],
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        },
+        json!({
+          "items": [
+            {
+              "kind": "unchanged",
+              "resultId": new_result_id,
+              "uri": server.doc_id("foo.py").uri,
+              "version": null
+            }
+          ],
+        }),
+    );
+}
+
 #[test]
 #[serial]
 fn change_config_file() {
@@ -419,6 +510,92 @@ fn change_config_file() {
     assert_eq!(req("in_mem.py"), vec![NOT_CALLABLE]);
 }
 
+#[test]
+#[serial]
+fn change_config_file_without_reindex() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [project]
+        name = "foo"
+
+        [file baseline.json]
+        {"version": 1, "entries": [
+            {"path": "m.py", "code": "operator", "message": "\"int\" not callable", "count": 1}
+        ]}
+
+        [file m.py]
+        1()
+        "#,
+    )
+    .into_server();
+
+    const NOT_CALLABLE: &str = r#""int" not callable"#;
+    assert_eq!(server.diagnostics_for_file("m.py"), vec![NOT_CALLABLE]);
+
+    server.write_file_and_wait("pyproject.toml", "[project]\nname = \"bar\"\n");
+    assert_eq!(server.diagnostics_for_file("m.py"), vec![NOT_CALLABLE]);
+
+    server.write_file_and_wait(
+        "pyproject.toml",
+        "[project]\nname = \"bar\"\n[tool.zuban]\nbaseline = \"baseline.json\"\n",
+    );
+    assert_eq!(server.diagnostics_for_file("m.py"), Vec::<String>::new());
+
+    server.write_file_and_wait(
+        "pyproject.toml",
+        "[tool.zuban]\nbaseline = \"baseline.json\"\nstrict = true\n",
+    );
+    assert_eq!(
+        server.diagnostics_for_file("m.py"),
+        Vec::<String>::new(),
+        "The baseline is still used after the flags changed"
+    );
+    server.write_file_and_wait("m.py", "def f(x): ...\n1()");
+    assert_eq!(
+        server.diagnostics_for_file("m.py"),
+        vec!["Function is missing a type annotation"]
+    );
+
+    // The flags of the overrides are calculated again for the files that were already loaded.
+    server.write_file_and_wait(
+        "pyproject.toml",
+        "[tool.zuban]\nbaseline = \"baseline.json\"\nstrict = true\n\
+         [[tool.zuban.overrides]]\nmodule = \"m\"\ndisallow_untyped_defs = false\n",
+    );
+    assert_eq!(server.diagnostics_for_file("m.py"), Vec::<String>::new());
+}
+
+#[test]
+#[serial]
+fn unknown_config_options() {
+    let server = Project::with_fixture(
+        r#"
+        [file mypy.ini]
+        [mypy]
+        disallow_untyed_defs = True
+
+        [file foo.py]
+        "#,
+    )
+    .with_push_diagnostics()
+    .into_server();
+
+    const UNKNOWN: &str = "Unrecognized option: disallow_untyed_defs = True \
+                           (did you mean \"disallow_untyped_defs\"?)";
+    server.open_in_memory_file("in_mem.py", "1()");
+    server.expect_multiple_diagnostics_pushes([
+        ("mypy.ini", vec![UNKNOWN]),
+        ("in_mem.py", vec![r#""int" not callable"#]),
+    ]);
+
+    server.write_file_and_wait("mypy.ini", "[mypy]\ndisallow_untyped_defs = True");
+    server.expect_multiple_diagnostics_pushes([
+        ("mypy.ini", vec![]),
+        ("in_mem.py", vec![r#""int" not callable"#]),
+    ]);
+}
+
 #[test]
 #[serial]
 fn check_rename_without_symlinks() {
@@ -507,6 +684,10 @@ fn multi_roots() {
 
         [file p1/foo.py]
 
+        [file p2/check.py]
+        import foo
+        import bar
+
         [file p2/bar.py]
         "#,
     )
@@ -514,19 +695,233 @@ fn multi_roots() {
     .root("p2")
     .into_server();
 
-    let d = || server.diagnostics_for_file("p1/check.py");
+    let d1 = || server.diagnostics_for_file("p1/check.py");
+    let d2 = || server.diagnostics_for_file("p2/check.py");
     const UNDEF: &str = "Cannot find implementation or library stub for module named \"undefined\"";
+    const NO_FOO: &str = "Cannot find implementation or library stub for module named \"foo\"";
     const NO_BAR: &str = "Cannot find implementation or library stub for module named \"bar\"";
 
-    assert_eq!(d(), vec![UNDEF.to_string()]);
+    // Every workspace folder has its own search path
+    assert_eq!(d1(), vec![NO_BAR.to_string(), UNDEF.to_string()]);
+    assert_eq!(d2(), vec![NO_FOO.to_string()]);
 
     server.tmp_dir.remove_file("p2/bar.py");
     server.rename_file_and_wait("p1/foo.py", "p2/foo.py");
 
-    assert_eq!(d(), vec![NO_BAR.to_string(), UNDEF.to_string()]);
+    assert_eq!(
+        d1(),
+        vec![NO_FOO.to_string(), NO_BAR.to_string(), UNDEF.to_string()]
+    );
+    assert_eq!(d2(), vec![NO_BAR.to_string()]);
     server.write_file_and_wait("p2/bar.py", "1()");
 
-    assert_eq!(d(), vec![UNDEF.to_string()]);
+    assert_eq!(d2(), Vec::<String>::new());
+}
+
+#[test]
+#[parallel]
+fn multi_roots_with_separate_configs() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/m.py]
+        def f(x): ...
+        1()
+
+        [file p2/m.py]
+        def f(x): ...
+        1()
+
+        [file p2/mypy.ini]
+        [mypy]
+        disallow_untyped_defs = True
+        "#,
+    )
+    .root("p1")
+    .root("p2")
+    .into_server();
+
+    const NOT_CALLABLE: &str = "\"int\" not callable";
+    const UNTYPED: &str = "Function is missing a type annotation";
+
+    assert_eq!(
+        server.diagnostics_for_file("p1/m.py"),
+        vec![NOT_CALLABLE.to_string()]
+    );
+    assert_eq!(
+        server.diagnostics_for_file("p2/m.py"),
+        vec![UNTYPED.to_string(), NOT_CALLABLE.to_string()]
+    );
+}
+
+#[test]
+#[parallel]
+fn change_workspace_folders() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/m.py]
+        import bar
+
+        [file p2/bar.py]
+
+        [file p2/mypy.ini]
+        [mypy]
+        disallow_untyped_defs = True
+        "#,
+    )
+    .root("p1")
+    .into_server();
+
+    const NO_BAR: &str = "Cannot find implementation or library stub for module named \"bar\"";
+    const NOT_CALLABLE: &str = "\"int\" not callable";
+    const UNTYPED: &str = "Function is missing a type annotation";
+
+    // Files outside of all workspace folders are part of the first one.
+    server.open_in_memory_file("p2/in_mem.py", "def f(x): ...\n1()");
+    assert_eq!(
+        server.diagnostics_for_file("p2/in_mem.py"),
+        vec![NOT_CALLABLE.to_string()]
+    );
+    assert_eq!(
+        server.diagnostics_for_file("p1/m.py"),
+        vec![NO_BAR.to_string()]
+    );
+
+    // The in memory file is moved to the new workspace and uses its config.
+    server.change_workspace_folders(&["p2"], &[]);
+    assert_eq!(
+        server.diagnostics_for_file("p2/in_mem.py"),
+        vec![UNTYPED.to_string(), NOT_CALLABLE.to_string()]
+    );
+    assert_eq!(
+        server.diagnostics_for_file("p1/m.py"),
+        vec![NO_BAR.to_string()]
+    );
+
+    server.change_in_memory_file("p2/in_mem.py", "def f(x): ...");
+    assert_eq!(
+        server.diagnostics_for_file("p2/in_mem.py"),
+        vec![UNTYPED.to_string()]
+    );
+
+    server.change_workspace_folders(&[], &["p2"]);
+    assert_eq!(
+        server.diagnostics_for_file("p2/in_mem.py"),
+        Vec::<String>::new()
+    );
+
+    // The last workspace folder is never removed
+    server.change_workspace_folders(&[], &["p1"]);
+    assert_eq!(
+        server.diagnostics_for_file("p1/m.py"),
+        vec![NO_BAR.to_string()]
+    );
+}
+
+#[test]
+#[parallel]
+fn change_configuration() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        def f(x): ...
+        a = 1
+        b = a
+        "#,
+    )
+    .into_server();
+
+    const UNTYPED: &str = "Function is missing a type annotation";
+    let change_settings = |settings| {
+        server.notify::<lsp_types::notification::DidChangeConfiguration>(
+            lsp_types::DidChangeConfigurationParams { settings },
+        )
+    };
+    let inlay_hints = || {
+        server
+            .request::<InlayHintRequest>(InlayHintParams {
+                work_done_progress_params: Default::default(),
+                text_document: server.doc_id("m.py"),
+                range: Range::new(Position::new(0, 0), Position::new(3, 0)),
+            })
+            .unwrap()
+            .into_iter()
+            .map(|hint| match hint.label {
+                lsp_types::InlayHintLabel::String(label) => label,
+                lsp_types::InlayHintLabel::LabelParts(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert!(server.diagnostics_for_file("m.py").is_empty());
+    assert_eq!(inlay_hints(), vec![" -> None", ": int"]);
+
+    change_settings(json!({"zuban": {"disallow_untyped_defs": true}}));
+    assert_eq!(server.diagnostics_for_file("m.py"), vec![UNTYPED]);
+
+    change_settings(json!({"zuban": {"inlayHints": {"variableTypes": false}}}));
+    assert!(server.diagnostics_for_file("m.py").is_empty());
+    assert_eq!(inlay_hints(), vec![" -> None"]);
+
+    change_settings(json!({"zuban": {"strict": "maybe"}}));
+    assert_eq!(
+        server.expect_notification_message().message,
+        r#"Invalid editor setting strict: Expected bool, got "maybe""#
+    );
+    assert!(server.diagnostics_for_file("m.py").is_empty());
+    assert_eq!(inlay_hints(), vec![" -> None", ": int"]);
+}
+
+#[test]
+#[parallel]
+fn pull_configuration_for_workspace_folders() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/m.py]
+        def f(x): ...
+
+        [file p2/m.py]
+        def f(x): ...
+        "#,
+    )
+    .root("p1")
+    .root("p2")
+    .with_workspace_configuration()
+    .into_server();
+
+    const UNTYPED: &str = "Function is missing a type annotation";
+    let respond_to_configuration_request = |settings: Value| {
+        let (id, params) = server.expect_request::<lsp_types::request::WorkspaceConfiguration>();
+        let scopes: Vec<_> = params
+            .items
+            .iter()
+            .map(|item| {
+                assert_eq!(item.section.as_deref(), Some("zuban"));
+                item.scope_uri.clone().unwrap()
+            })
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                server.uri_from_rel_path("p1"),
+                server.uri_from_rel_path("p2")
+            ]
+        );
+        server.send(Response::new_ok(id, settings));
+    };
+
+    respond_to_configuration_request(json!([{"disallow_untyped_defs": true}, null]));
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), vec![UNTYPED]);
+    assert!(server.diagnostics_for_file("p2/m.py").is_empty());
+
+    // The settings are pulled again after a change notification.
+    server.notify::<lsp_types::notification::DidChangeConfiguration>(
+        lsp_types::DidChangeConfigurationParams {
+            settings: Value::Null,
+        },
+    );
+    respond_to_configuration_request(json!([null, {"disallow_untyped_defs": true}]));
+    assert!(server.diagnostics_for_file("p1/m.py").is_empty());
+    assert_eq!(server.diagnostics_for_file("p2/m.py"), vec![UNTYPED]);
 }
 
 #[test]
@@ -762,6 +1157,89 @@ fn symlinks_with_dir_loop() {
     assert_eq!(d(), vec![cannot_find("nested"), cannot_find("nested"),]);
 }
 
+#[test]
+#[parallel]
+#[cfg(unix)]
+fn sockets_in_workspace_are_ignored() {
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        import pkg.special
+        from pkg.mod import x
+        reveal_type(x)
+
+        [file pkg/__init__.py]
+        [file pkg/mod.py]
+        x = 1
+        "#,
+    )
+    .into_server();
+
+    let socket_path = std::path::Path::new(server.tmp_dir.path()).join("pkg/special.py");
+    let _listener = std::os::unix::net::UnixListener::bind(socket_path).unwrap();
+
+    assert_eq!(
+        server.diagnostics_for_file("foo.py"),
+        vec![
+            r#"Cannot find implementation or library stub for module named "pkg.special""#
+                .to_string(),
+            r#"Revealed type is "builtins.int""#.to_string()
+        ]
+    );
+}
+
+#[test]
+#[parallel]
+fn symlinked_dir_within_workspace() {
+    if !symlink_creation_allowed() {
+        return;
+    }
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        from typing import assert_type
+        from pkg.mod import C
+        from alias.mod import C as D
+        import loop1
+
+        assert_type(C(), D)
+        reveal_type(D().x)
+
+        [file pkg/__init__.py]
+        [file pkg/mod.py]
+        class C:
+            x = 1
+        "#,
+    )
+    .into_server();
+
+    server.tmp_dir.create_symlink_dir("pkg", "alias");
+    // Links that point to each other should not lead to endless recursion.
+    server.tmp_dir.create_symlink_dir("loop2", "loop1");
+    server.tmp_dir.create_symlink_dir("loop1", "loop2");
+
+    let d = || server.diagnostics_for_file("foo.py");
+    let cannot_find_loop = r#"Cannot find implementation or library stub for module named "loop1""#;
+
+    assert_eq!(
+        d(),
+        vec![
+            cannot_find_loop.to_string(),
+            r#"Revealed type is "builtins.int""#.to_string()
+        ]
+    );
+
+    // The file behind the link is the same, so changes are visible through both paths.
+    server.write_file_and_wait("pkg/mod.py", "class C:\n    x = ''\n");
+    assert_eq!(
+        d(),
+        vec![
+            cannot_find_loop.to_string(),
+            r#"Revealed type is "builtins.str""#.to_string()
+        ]
+    );
+}
+
 #[test]
 #[parallel]
 fn diagnostics_positions() {
@@ -798,6 +1276,443 @@ fn diagnostics_positions() {
     }
 }
 
+#[test]
+#[parallel]
+fn diagnostic_tags() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [tool.mypy]
+        enable_error_code = ["deprecated"]
+
+        [file m.py]
+        import sys
+        from typing_extensions import deprecated
+
+        @deprecated("Use g")
+        def f() -> None: ...
+
+        def g(x: int) -> None:
+            return
+            print(x)
+
+        def h(x: int) -> None:
+            if isinstance(x, str):
+                print(x)
+                print(x)
+            if sys.version_info < (3, 0):
+                print(x)
+
+        f()
+        "#,
+    )
+    .into_server();
+    let diagnostics: Vec<_> = server
+        .full_diagnostics_for_file("m.py")
+        .into_iter()
+        .map(|d| (d.range.start.line, d.range.end.line, d.message, d.tags))
+        .collect();
+    let unreachable = |start, end| {
+        (
+            start,
+            end,
+            "Code is unreachable".to_string(),
+            Some(vec![DiagnosticTag::UNNECESSARY]),
+        )
+    };
+    assert_eq!(
+        diagnostics,
+        vec![
+            (
+                17,
+                17,
+                "function m.f is deprecated: Use g".to_string(),
+                Some(vec![DiagnosticTag::DEPRECATED])
+            ),
+            // After return
+            unreachable(8, 8),
+            // Impossible isinstance
+            unreachable(12, 13),
+            // Python version mismatch
+            unreachable(15, 15),
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn memory_limit_evicts_files() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [tool.zuban]
+        memory_limit_mb = 0
+
+        [file a.py]
+        def f() -> int: ...
+
+        [file b.py]
+        from a import f
+        x: str = f()
+
+        [file c.py]
+        y: str = 1
+        "#,
+    )
+    .into_server();
+    let messages = |path| -> Vec<_> {
+        server
+            .full_diagnostics_for_file(path)
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    };
+    let evicted = || GLOBAL_EVICTED_FILES_COUNTER.load(std::sync::atomic::Ordering::SeqCst);
+    let b_messages = [
+        r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#,
+    ];
+    // Open files are never evicted, so b.py depends on an evicted a.py below.
+    server.open_in_memory_file("b.py", "from a import f\nx: str = f()");
+    assert_eq!(messages("b.py"), b_messages);
+    let before = evicted();
+    // Evicts a.py, which was not used by this request.
+    assert_eq!(
+        messages("c.py"),
+        [
+            r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#
+        ]
+    );
+    assert!(evicted() > before, "Nothing was evicted");
+
+    // The evicted file is loaded and checked again.
+    assert_eq!(messages("b.py"), b_messages);
+    let before = evicted();
+    messages("c.py");
+    assert!(evicted() > before, "Nothing was evicted");
+
+    // A change of the evicted file invalidates the files that depend on it.
+    server.write_file_and_wait("a.py", "def f() -> str: ...");
+    assert_eq!(messages("b.py"), Vec::<String>::new());
+}
+
+#[test]
+#[serial]
+fn replace_file_with_same_code() {
+    let server = Project::with_fixture(
+        r#"
+        [file a.py]
+        def f() -> int: ...
+        "#,
+    )
+    .with_push_diagnostics()
+    .into_server();
+
+    server.open_in_memory_file("b.py", "from a import f\nx: str = f()");
+    server.expect_multiple_diagnostics_pushes([(
+        "b.py",
+        vec![
+            r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#,
+        ],
+    )]);
+
+    // Replacing the file with the same code does not invalidate b.py, otherwise its diagnostics
+    // would be pushed again before the ones of the actual change. The files are replaced, because
+    // a write might be observed while the file is still empty.
+    server.write_file_and_wait("a.txt", "def f() -> int: ...\n");
+    server.rename_file_and_wait("a.txt", "a.py");
+    server.write_file_and_wait("a.txt", "def f() -> str: ...\n");
+    server.rename_file_and_wait("a.txt", "a.py");
+    server.expect_multiple_diagnostics_pushes([("b.py", vec![])]);
+}
+
+#[test]
+#[parallel]
+fn edit_function_body() {
+    let server = Project::with_fixture(
+        r#"
+        [file a.py]
+
+        [file b.py]
+        from a import g
+        y: str = g(1)
+        "#,
+    )
+    .into_server();
+    let a_code = |f_body: &str, g_body: &str, g_return: &str| {
+        format!(
+            "def f() -> int:\n{f_body}    return ''\n\n\
+             class C:\n    def m(self) -> int:\n        x: str = 1\n        return 1\n\n\
+             def g(x: int) -> {g_return}:\n    return {g_body}\n"
+        )
+    };
+    let diagnostics = |path| -> Vec<_> {
+        server
+            .full_diagnostics_for_file(path)
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    };
+    let return_error = r#"Incompatible return value type (got "str", expected "int")"#;
+    let assignment_error =
+        r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#;
+    let operand_error = r#"Unsupported operand types for + ("int" and "str")"#;
+
+    server.open_in_memory_file("a.py", &a_code("", "x", "int"));
+    let mut a_diagnostics = vec![(1, return_error.into()), (5, assignment_error.into())];
+    assert_eq!(diagnostics("a.py"), a_diagnostics);
+    assert_eq!(diagnostics("b.py"), [(1, assignment_error.into())]);
+
+    // Only the body of g changes
+    server.change_in_memory_file("a.py", &a_code("", "x + ''", "int"));
+    a_diagnostics.push((9, operand_error.into()));
+    assert_eq!(diagnostics("a.py"), a_diagnostics);
+    assert_eq!(diagnostics("b.py"), [(1, assignment_error.into())]);
+
+    // The kept diagnostics move with the code after the edited body
+    server.change_in_memory_file("a.py", &a_code("    z = 1\n", "x + ''", "int"));
+    let a_diagnostics = vec![
+        (2, return_error.into()),
+        (6, assignment_error.into()),
+        (10, operand_error.into()),
+    ];
+    assert_eq!(diagnostics("a.py"), a_diagnostics);
+    assert_eq!(diagnostics("b.py"), [(1, assignment_error.into())]);
+
+    // The signature of g changes, which is visible in b.py
+    server.change_in_memory_file("a.py", &a_code("    z = 1\n", "x + ''", "str"));
+    let mut a_diagnostics = a_diagnostics;
+    a_diagnostics.push((
+        10,
+        r#"Incompatible return value type (got "int", expected "str")"#.into(),
+    ));
+    assert_eq!(diagnostics("a.py"), a_diagnostics);
+    assert_eq!(diagnostics("b.py"), []);
+}
+
+#[test]
+#[parallel]
+fn diagnostics_with_baseline() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [tool.zuban]
+        baseline = "baseline.json"
+
+        [file baseline.json]
+        {"version": 1, "entries": [
+            {"path": "m.py", "code": "operator", "message": "\"int\" not callable", "count": 1},
+            {"path": "m.py", "code": "name-defined", "message": "Name \"x\" is not defined", "count": 1}
+        ]}
+
+        [file m.py]
+        1()
+        1()
+        "#,
+    )
+    .into_server();
+    let diagnostics: Vec<_> = server
+        .full_diagnostics_for_file("m.py")
+        .into_iter()
+        .map(|d| (d.range.start.line, d.message))
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec![
+            (1, r#""int" not callable"#.to_string()),
+            (
+                0,
+                r#"Baseline entry does not match an error anymore: Name "x" is not defined  [name-defined]"#
+                    .to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+#[parallel]
+fn diagnostic_related_information() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        def f() -> None: ...
+        def f() -> None: ...
+
+        class Base:
+            def method(self, x: int) -> int: ...
+
+        class Sub(Base):
+            def method(self, x: str) -> int: ...
+        "#,
+    )
+    .into_server();
+    let uri = server.doc_id("m.py").uri;
+    let related: Vec<_> = server
+        .full_diagnostics_for_file("m.py")
+        .into_iter()
+        .map(|d| {
+            let info = d.related_information.unwrap_or_default();
+            (
+                d.range.start.line,
+                info.into_iter()
+                    .map(|info| {
+                        assert_eq!(info.location.uri, uri);
+                        (info.location.range.start, info.message)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        related,
+        vec![
+            (
+                1,
+                vec![(Position::new(0, 4), r#""f" first defined here"#.to_string())]
+            ),
+            // Notes about the Liskov substitution principle
+            (4, vec![]),
+            (7, vec![]),
+            (
+                7,
+                vec![(
+                    Position::new(4, 8),
+                    "Overridden definition in supertype".to_string()
+                )]
+            ),
+        ]
+    );
+}
+
+#[test]
+#[parallel]
+fn work_done_progress() {
+    use lsp_types::WorkDoneProgress;
+
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        lala
+        [file bar.py]
+        import foo
+        "#,
+    )
+    .with_work_done_progress()
+    .into_server();
+
+    let check_progress = |progress: Vec<WorkDoneProgress>, title: &str| {
+        let Some(WorkDoneProgress::Begin(begin)) = progress.first() else {
+            panic!("Expected a begin, got {progress:?}")
+        };
+        assert_eq!(begin.title, title);
+        assert!(matches!(progress.last(), Some(WorkDoneProgress::End(_))));
+        let mut last_percentage = 0;
+        for p in &progress[1..progress.len() - 1] {
+            let WorkDoneProgress::Report(report) = p else {
+                panic!("Expected a report, got {p:?}")
+            };
+            let percentage = report.percentage.unwrap();
+            assert!(percentage > last_percentage && percentage <= 100);
+            last_percentage = percentage;
+        }
+        progress
+    };
+
+    // Loading the project, which reports all workspaces as loaded at the end
+    let (progress, responses) = server.expect_work_done_progress();
+    assert!(responses.is_empty());
+    let progress = check_progress(progress, "Indexing");
+    let [
+        ..,
+        WorkDoneProgress::Report(last_report),
+        WorkDoneProgress::End(_),
+    ] = &progress[..]
+    else {
+        panic!("Expected a report before the end, got {progress:?}")
+    };
+    assert_eq!(last_report.percentage, Some(100));
+
+    let check_workspace = |token: Option<NumberOrString>| {
+        server.send(lsp_server::Request::new(
+            1000.into(),
+            "workspace/diagnostic".to_owned(),
+            WorkspaceDiagnosticParams {
+                identifier: None,
+                previous_result_ids: vec![],
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: token,
+                },
+                partial_result_params: Default::default(),
+            },
+        ));
+        // A progress that ends before the client accepted the token is only sent afterwards, so
+        // the response might arrive first.
+        let (progress, mut responses) = server.expect_work_done_progress();
+        let progress = check_progress(progress, "Checking workspace");
+        let Some(WorkDoneProgress::End(end)) = progress.last() else {
+            unreachable!()
+        };
+        assert_eq!(end.message.as_deref(), Some("Checked 2 files"));
+        let response = responses.pop().unwrap_or_else(|| server.expect_response());
+        assert!(responses.is_empty());
+        assert!(response.error.is_none());
+    };
+    // The server creates a token
+    check_workspace(None);
+    // The client provides a token
+    check_workspace(Some(NumberOrString::String("client-token".into())));
+}
+
+#[test]
+#[parallel]
+fn cancel_request() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        import os
+        os.pat
+        "#,
+    )
+    .into_server();
+    let completion_params = CompletionParams {
+        text_document_position: TextDocumentPositionParams::new(
+            server.doc_id("m.py"),
+            Position::new(1, 6),
+        ),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    };
+
+    // The cancel notification arrives while the server is still loading the project for the
+    // first request, before it starts working on the cancelled one.
+    for id in [999, 1000] {
+        server.send(lsp_server::Request::new(
+            id.into(),
+            "textDocument/completion".to_owned(),
+            &completion_params,
+        ));
+    }
+    server.notify::<lsp_types::notification::Cancel>(lsp_types::CancelParams {
+        id: NumberOrString::Number(1000),
+    });
+    let response = server.expect_response();
+    assert_eq!(response.id, 999.into());
+    assert!(response.error.is_none());
+    let response = server.expect_response();
+    assert_eq!(response.id, 1000.into());
+    assert_eq!(
+        response.error.expect("Expected an error").code,
+        lsp_server::ErrorCode::RequestCanceled as i32
+    );
+
+    // Other requests are not affected
+    let Some(CompletionResponse::Array(items)) = server.request::<Completion>(completion_params)
+    else {
+        unreachable!()
+    };
+    assert!(items.iter().any(|item| item.label == "path"));
+}
+
 #[test]
 #[serial]
 fn check_panic_recovery() {
@@ -2319,6 +3234,36 @@ fn check_notebook_cell_change() {
     expect_result();
 }
 
+#[test]
+#[serial]
+fn notebook_cell_structure_changes() {
+    let server = Project::with_fixture(r#""#).into_server();
+    server.open_notebook(&[
+        (NotebookCellKind::Code, "x = 1"),
+        (NotebookCellKind::Markup, "# Notes"),
+        (NotebookCellKind::Code, "x"),
+    ]);
+    const UNDEFINED: &str = r#"Name "x" is not defined"#;
+    assert!(server.notebook_cell_diagnostics(2).is_empty());
+
+    // The cell after a removed cell is linked to the cell before the removed cell.
+    server.change_notebook_structure(0..1, &[], &[], &[0]);
+    assert_eq!(server.notebook_cell_diagnostics(2), [UNDEFINED]);
+
+    // Cells: markdown, 3, 2
+    server.change_notebook_structure(1..1, &[3], &[(3, "x = ''")], &[]);
+    assert!(server.notebook_cell_diagnostics(2).is_empty());
+
+    // Moved cells are neither closed nor opened, cells: markdown, 2, 3
+    server.change_notebook_structure(1..3, &[2, 3], &[], &[]);
+    assert_eq!(server.notebook_cell_diagnostics(2), [UNDEFINED]);
+    assert!(server.notebook_cell_diagnostics(3).is_empty());
+
+    // And back again
+    server.change_notebook_structure(1..3, &[3, 2], &[], &[]);
+    assert!(server.notebook_cell_diagnostics(2).is_empty());
+}
+
 #[test]
 #[serial]
 fn test_symbols() {
@@ -3146,8 +4091,244 @@ fn test_auto_imports_code_actions() {
             "kind": "quickfix",
             "title": "Add \"# zuban: ignore[name-defined]\""
           },
+          {
+            "edit": {
+              "changes": {
+                foo.uri.as_str(): [
+                  {
+                    "newText": "",
+                    "range": {
+                      "end": {
+                        "character": 0,
+                        "line": 1
+                      },
+                      "start": {
+                        "character": 0,
+                        "line": 0
+                      }
+                    }
+                  }
+                ]
+              }
+            },
+            "kind": "source.organizeImports",
+            "title": "Organize imports"
+          },
+        ]),
+    );
+}
+
+#[test]
+#[serial]
+fn test_organize_imports_code_action() {
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        import sys
+        from typing import Any, cast
+        from os import path
+        import foo
+        from typing import List
+
+        x: List[Any] = cast(list, sys.argv + [path, foo])
+        "#,
+    )
+    .into_server();
+
+    let foo = server.doc_id("foo.py");
+    let params = |only| CodeActionParams {
+        text_document: foo.clone(),
+        range: Range::new(Position::new(6, 0), Position::new(6, 0)),
+        context: CodeActionContext {
+            diagnostics: vec![],
+            only: Some(only),
+            trigger_kind: None,
+        },
+        partial_result_params: Default::default(),
+        work_done_progress_params: Default::default(),
+    };
+    server.request_and_expect_json::<CodeActionRequest>(
+        params(vec![CodeActionKind::SOURCE]),
+        json!([
+          {
+            "edit": {
+              "changes": {
+                foo.uri.as_str(): [
+                  {
+                    "newText": "import sys\nfrom os import path\nfrom typing import Any, List, cast\n\nimport foo\n",
+                    "range": {
+                      "end": {
+                        "character": 0,
+                        "line": 5
+                      },
+                      "start": {
+                        "character": 0,
+                        "line": 0
+                      }
+                    }
+                  }
+                ]
+              }
+            },
+            "kind": "source.organizeImports",
+            "title": "Organize imports"
+          },
+        ]),
+    );
+    server.request_and_expect_json::<CodeActionRequest>(
+        params(vec![CodeActionKind::QUICKFIX]),
+        json!(null),
+    );
+}
+
+#[test]
+#[serial]
+fn test_extract_refactoring_code_actions() {
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        def f(x: int) -> int:
+            y = x * 2
+            return y
+        "#,
+    )
+    .into_server();
+
+    let foo = server.doc_id("foo.py");
+    let params = |range| CodeActionParams {
+        text_document: foo.clone(),
+        range,
+        context: CodeActionContext {
+            diagnostics: vec![],
+            only: Some(vec![CodeActionKind::REFACTOR]),
+            trigger_kind: None,
+        },
+        partial_result_params: Default::default(),
+        work_done_progress_params: Default::default(),
+    };
+    server.request_and_expect_json::<CodeActionRequest>(
+        params(Range::new(Position::new(1, 8), Position::new(1, 13))),
+        json!([
+          {
+            "edit": {
+              "changes": {
+                foo.uri.as_str(): [
+                  {
+                    "newText": "new_var: int = x * 2\n    ",
+                    "range": {
+                      "end": {
+                        "character": 4,
+                        "line": 1
+                      },
+                      "start": {
+                        "character": 4,
+                        "line": 1
+                      }
+                    }
+                  },
+                  {
+                    "newText": "new_var",
+                    "range": {
+                      "end": {
+                        "character": 13,
+                        "line": 1
+                      },
+                      "start": {
+                        "character": 8,
+                        "line": 1
+                      }
+                    }
+                  }
+                ]
+              }
+            },
+            "kind": "refactor.extract",
+            "title": "Extract variable"
+          },
         ]),
     );
+    // Without a selection there is nothing to extract.
+    server.request_and_expect_json::<CodeActionRequest>(
+        params(Range::new(Position::new(1, 8), Position::new(1, 8))),
+        json!(null),
+    );
+}
+
+enum ExpandedTypeRequest {}
+
+impl lsp_types::request::Request for ExpandedTypeRequest {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<String>;
+    const METHOD: &'static str = "zuban/expandedType";
+}
+
+#[test]
+#[serial]
+fn test_expanded_type() {
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        from typing import NewType
+        UserId = NewType("UserId", int)
+        def f(x: dict[str, list[UserId]]) -> None:
+            x
+        "#,
+    )
+    .into_server();
+
+    let pos =
+        |line| TextDocumentPositionParams::new(server.doc_id("foo.py"), Position::new(line, 4));
+    server.request_and_expect_json::<ExpandedTypeRequest>(pos(3), json!("dict[str, list[int]]"));
+    server.request_and_expect_json::<ExpandedTypeRequest>(
+        pos(2),
+        json!("def f(x: dict[str, list[int]]) -> None"),
+    );
+}
+
+enum TypeCoverageRequest {}
+
+impl lsp_types::request::Request for TypeCoverageRequest {
+    type Params = Value;
+    type Result = Value;
+    const METHOD: &'static str = "zuban/typeCoverage";
+}
+
+#[test]
+#[serial]
+fn test_type_coverage() {
+    let server = Project::with_fixture(
+        r#"
+        [file foo.py]
+        from typing import Any
+        def f(x: int, y: Any) -> None:
+            x + 1
+            y.foo
+
+        [file bar.py]
+        x = [1]
+        "#,
+    )
+    .into_server();
+
+    let result = server.request::<TypeCoverageRequest>(json!({
+        "textDocument": server.doc_id("foo.py"),
+    }));
+    assert_eq!(
+        result["total"],
+        json!({"expressions": 2, "precise": 1, "any": 1, "percentage": 50.0})
+    );
+    assert_eq!(result["files"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        result["files"][0]["uri"],
+        json!(server.doc_id("foo.py").uri)
+    );
+
+    let result = server.request::<TypeCoverageRequest>(json!({}));
+    assert_eq!(
+        result["total"],
+        json!({"expressions": 4, "precise": 3, "any": 1, "percentage": 75.0})
+    );
+    assert_eq!(result["files"].as_array().unwrap().len(), 2);
 }
 
 #[test]
@@ -3247,3 +4428,168 @@ fn test_inlay_hints() {
         ]),
     );
 }
+
+#[test]
+#[parallel]
+fn sequential_clients_over_tcp() {
+    sequential_clients(zubanls::Transport::Tcp)
+}
+
+#[test]
+#[parallel]
+fn sequential_clients_over_websocket() {
+    sequential_clients(zubanls::Transport::WebSocket)
+}
+
+#[test]
+#[parallel]
+fn listen_addresses() {
+    let parse = |s: &str| s.parse::<zubanls::ListenAddress>().map(|a| a.to_string());
+    // Without a host, only local clients can connect.
+    assert_eq!(parse("tcp://8080").unwrap(), "tcp://127.0.0.1:8080");
+    assert_eq!(parse("ws://0.0.0.0:8080").unwrap(), "ws://0.0.0.0:8080");
+    assert!(parse("ws://").is_err());
+    assert!(parse("http://127.0.0.1:8080").is_err());
+}
+
+fn sequential_clients(transport: zubanls::Transport) {
+    let tmp_dir = test_utils::write_files_from_fixture(
+        r#"
+        [file m.py]
+        x: int = ""
+        "#,
+        false,
+    );
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let typeshed_path = Some(test_utils::typeshed_path());
+        zubanls::run_server_with_listener(listener, transport, typeshed_path)
+    });
+
+    let root = tmp_dir.path_for_uri();
+    let uri = connection::path_to_uri(&format!("{root}/m.py"));
+    let diagnostics = |con: &Connection| {
+        let result = con.request::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            identifier: None,
+            previous_result_id: None,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = result
+        else {
+            unreachable!()
+        };
+        report
+            .full_document_diagnostic_report
+            .items
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>()
+    };
+    let shutdown_and_exit = |con: Connection| {
+        // The result of a shutdown is null, which is lost when the response is serialized.
+        let response = con.request_with_response::<lsp_types::request::Shutdown>(());
+        assert!(response.error.is_none());
+        con.notify::<lsp_types::notification::Exit>(());
+    };
+    const ON_DISK: &str =
+        r#"Incompatible types in assignment (expression has type "str", variable has type "int")"#;
+    const IN_MEMORY: &str =
+        r#"Incompatible types in assignment (expression has type "int", variable has type "str")"#;
+
+    let first = Connection::connect(address, transport);
+    first.initialize(&[&root], None, true, false, false);
+    assert_eq!(diagnostics(&first), [ON_DISK]);
+    first.notify::<lsp_types::notification::DidOpenTextDocument>(
+        lsp_types::DidOpenTextDocumentParams {
+            text_document: lsp_types::TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "python".to_owned(),
+                version: 0,
+                text: "x: str = 1".to_owned(),
+            },
+        },
+    );
+    assert_eq!(diagnostics(&first), [IN_MEMORY]);
+    shutdown_and_exit(first);
+
+    // The project is reused, but the files that were opened by the previous client are not.
+    let second = Connection::connect(address, transport);
+    second.initialize(&[&root], None, true, false, false);
+    assert_eq!(diagnostics(&second), [ON_DISK]);
+    shutdown_and_exit(second);
+}
+
+#[test]
+#[parallel]
+fn non_file_uri_schemes() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        class C:
+            x = 1
+        "#,
+    )
+    .into_server();
+
+    let m_uri = server.doc_id("m.py").uri;
+    // Has the same path as m.py, but is a different document.
+    let memfs_m = format!("memfs:{}", m_uri.as_str().strip_prefix("file://").unwrap());
+    for uri in [
+        "untitled:Untitled-1",
+        // Percent-encoded characters are kept in the URIs of the responses.
+        "untitled:Untitled%201",
+        "vscode-vfs://github/owner/repo/foo.py",
+        "memfs:/proj/foo.py",
+        &memfs_m,
+    ] {
+        let uri = Uri::from_str(uri).unwrap();
+        let doc = TextDocumentIdentifier { uri: uri.clone() };
+        let diags = || {
+            server
+                .full_diagnostics_for_abs_path(doc.clone())
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>()
+        };
+        server.open_in_memory_file_for_uri(uri.clone(), "import m\nm.C.x\n1()\ny = 1\ny");
+        assert_eq!(diags(), [r#""int" not callable"#]);
+
+        let goto = |line, column| {
+            let response = server.request_with_response::<GotoDefinition>(GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    doc.clone(),
+                    Position::new(line, column),
+                ),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            });
+            response.result.unwrap()[0]["targetUri"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+        // Imports of workspace modules resolve to the files on disk.
+        assert_eq!(goto(1, 4), m_uri.as_str());
+        // The URIs of the document are the same as the ones of the client.
+        assert_eq!(goto(4, 0), uri.as_str());
+
+        server.change_in_memory_file_for_uri(uri.clone(), "import m\nm.C.y");
+        assert_eq!(diags(), [r#""type[C]" has no attribute "y""#]);
+
+        server.close_in_memory_file_for_uri(uri.clone());
+        let response =
+            server.request_with_response::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+                text_document: doc,
+                identifier: None,
+                previous_result_id: None,
+                partial_result_params: PartialResultParams::default(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            });
+        let err = response.error.unwrap();
+        assert_eq!(err.message, format!("File {} does not exist", uri.as_str()));
+    }
+    assert!(server.diagnostics_for_file("m.py").is_empty());
+}
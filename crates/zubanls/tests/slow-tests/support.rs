@@ -8,16 +8,18 @@ use std::{
 };
 
 use lsp_types::{
-    DidChangeNotebookDocumentParams, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
-    DidOpenNotebookDocumentParams, DidOpenTextDocumentParams, DocumentDiagnosticParams,
-    DocumentDiagnosticReport, DocumentDiagnosticReportResult, NotebookCell, NotebookCellKind,
-    NotebookDocument, NotebookDocumentCellChange, NotebookDocumentChangeEvent,
+    DidChangeNotebookDocumentParams, DidChangeTextDocumentParams, DidChangeWorkspaceFoldersParams,
+    DidCloseTextDocumentParams, DidOpenNotebookDocumentParams, DidOpenTextDocumentParams,
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    NotebookCell, NotebookCellArrayChange, NotebookCellKind, NotebookDocument,
+    NotebookDocumentCellChange, NotebookDocumentCellChangeStructure, NotebookDocumentChangeEvent,
     NotebookDocumentChangeTextContent, PartialResultParams, TextDocumentContentChangeEvent,
     TextDocumentIdentifier, TextDocumentItem, Uri, VersionedNotebookDocumentIdentifier,
-    VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    VersionedTextDocumentIdentifier, WorkDoneProgressParams, WorkspaceFolder,
+    WorkspaceFoldersChangeEvent,
     notification::{
-        DidChangeNotebookDocument, DidChangeTextDocument, DidCloseTextDocument,
-        DidOpenNotebookDocument, DidOpenTextDocument,
+        DidChangeNotebookDocument, DidChangeTextDocument, DidChangeWorkspaceFolders,
+        DidCloseTextDocument, DidOpenNotebookDocument, DidOpenTextDocument,
     },
     request::DocumentDiagnosticRequest,
 };
@@ -39,6 +41,8 @@ pub(crate) struct Project<'a> {
     roots: Vec<String>,
     root_dir_contains_symlink: bool,
     push_diagnostics: bool,
+    work_done_progress: bool,
+    workspace_configuration: bool,
 }
 
 impl<'a> Project<'a> {
@@ -49,6 +53,8 @@ impl<'a> Project<'a> {
             roots: vec![],
             root_dir_contains_symlink: false,
             push_diagnostics: false,
+            work_done_progress: false,
+            workspace_configuration: false,
         }
     }
 
@@ -67,6 +73,16 @@ impl<'a> Project<'a> {
         self
     }
 
+    pub(crate) fn with_work_done_progress(mut self) -> Self {
+        self.work_done_progress = true;
+        self
+    }
+
+    pub(crate) fn with_workspace_configuration(mut self) -> Self {
+        self.workspace_configuration = true;
+        self
+    }
+
     pub(crate) fn into_server(self) -> Server {
         self.into_server_detailed(None)
     }
@@ -99,6 +115,8 @@ impl<'a> Project<'a> {
                 &roots.iter().map(|root| root.as_str()).collect::<Vec<_>>(),
                 client_encodings,
                 !self.push_diagnostics,
+                self.work_done_progress,
+                self.workspace_configuration,
             ),
             version_incrementor: Default::default(),
         }
@@ -300,11 +318,15 @@ impl Server {
     }
 
     pub fn change_in_memory_file(&self, path: &str, code: &str) {
+        self.change_in_memory_file_for_uri(self.doc_id(path).uri, code)
+    }
+
+    pub fn change_in_memory_file_for_uri(&self, uri: lsp_types::Uri, code: &str) {
         self.version_incrementor
             .set(self.version_incrementor.get() + 1);
         self.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
             text_document: VersionedTextDocumentIdentifier {
-                uri: self.doc_id(path).uri,
+                uri,
                 version: self.version_incrementor.get(),
             },
             content_changes: vec![TextDocumentContentChangeEvent {
@@ -315,6 +337,24 @@ impl Server {
         });
     }
 
+    pub fn change_workspace_folders(&self, added: &[&str], removed: &[&str]) {
+        let to_folders = |rel_paths: &[&str]| {
+            rel_paths
+                .iter()
+                .map(|rel_path| WorkspaceFolder {
+                    uri: self.uri_from_rel_path(rel_path),
+                    name: rel_path.to_string(),
+                })
+                .collect()
+        };
+        self.notify::<DidChangeWorkspaceFolders>(DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: to_folders(added),
+                removed: to_folders(removed),
+            },
+        });
+    }
+
     pub fn open_in_memory_file(&self, path: &str, code: &str) {
         self.open_in_memory_file_for_uri(self.doc_id(path).uri, code)
     }
@@ -331,8 +371,12 @@ impl Server {
     }
 
     pub fn close_in_memory_file(&self, path: &str) {
+        self.close_in_memory_file_for_uri(self.doc_id(path).uri)
+    }
+
+    pub fn close_in_memory_file_for_uri(&self, uri: lsp_types::Uri) {
         self.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
-            text_document: self.doc_id(path),
+            text_document: TextDocumentIdentifier { uri },
         });
     }
 
@@ -345,6 +389,14 @@ impl Server {
     }
 
     pub fn open_notebook_with_cells(&self, cells: &[&str]) {
+        let cells: Vec<_> = cells
+            .iter()
+            .map(|code| (NotebookCellKind::Code, *code))
+            .collect();
+        self.open_notebook(&cells)
+    }
+
+    pub fn open_notebook(&self, cells: &[(NotebookCellKind, &str)]) {
         self.notify::<DidOpenNotebookDocument>(DidOpenNotebookDocumentParams {
             notebook_document: NotebookDocument {
                 uri: self.notebook_uri(),
@@ -354,27 +406,94 @@ impl Server {
                 cells: cells
                     .iter()
                     .enumerate()
-                    .map(|(i, _)| NotebookCell {
-                        kind: NotebookCellKind::Code,
-                        document: self.notebook_cell_uri(i),
-                        metadata: None,
-                        execution_summary: None,
-                    })
+                    .map(|(i, (kind, _))| self.notebook_cell(i, kind.clone()))
                     .collect(),
             },
             cell_text_documents: cells
                 .iter()
                 .enumerate()
-                .map(|(i, code)| TextDocumentItem {
-                    uri: self.notebook_cell_uri(i),
-                    language_id: "python".into(),
-                    version: 1,
-                    text: (*code).into(),
-                })
+                .map(|(i, (_, code))| self.notebook_cell_text_document(i, code))
                 .collect(),
         });
     }
 
+    fn notebook_cell(&self, nth: usize, kind: NotebookCellKind) -> NotebookCell {
+        NotebookCell {
+            kind,
+            document: self.notebook_cell_uri(nth),
+            metadata: None,
+            execution_summary: None,
+        }
+    }
+
+    fn notebook_cell_text_document(&self, nth: usize, code: &str) -> TextDocumentItem {
+        TextDocumentItem {
+            uri: self.notebook_cell_uri(nth),
+            language_id: "python".into(),
+            version: 1,
+            text: code.into(),
+        }
+    }
+
+    /// Replaces the cells in `range` with the code cells `cells`, which are identified by the
+    /// number they were created with. Cells that are moved are neither opened nor closed.
+    pub fn change_notebook_structure(
+        &self,
+        range: std::ops::Range<u32>,
+        cells: &[usize],
+        did_open: &[(usize, &str)],
+        did_close: &[usize],
+    ) {
+        self.notify::<DidChangeNotebookDocument>(DidChangeNotebookDocumentParams {
+            notebook_document: VersionedNotebookDocumentIdentifier {
+                version: 0,
+                uri: self.notebook_uri(),
+            },
+            change: NotebookDocumentChangeEvent {
+                metadata: None,
+                cells: Some(NotebookDocumentCellChange {
+                    structure: Some(NotebookDocumentCellChangeStructure {
+                        array: NotebookCellArrayChange {
+                            start: range.start,
+                            delete_count: range.end - range.start,
+                            cells: Some(
+                                cells
+                                    .iter()
+                                    .map(|nth| self.notebook_cell(*nth, NotebookCellKind::Code))
+                                    .collect(),
+                            ),
+                        },
+                        did_open: Some(
+                            did_open
+                                .iter()
+                                .map(|(nth, code)| self.notebook_cell_text_document(*nth, code))
+                                .collect(),
+                        ),
+                        did_close: Some(
+                            did_close
+                                .iter()
+                                .map(|nth| TextDocumentIdentifier {
+                                    uri: self.notebook_cell_uri(*nth),
+                                })
+                                .collect(),
+                        ),
+                    }),
+                    data: None,
+                    text_content: None,
+                }),
+            },
+        });
+    }
+
+    pub fn notebook_cell_diagnostics(&self, nth: usize) -> Vec<String> {
+        self.full_diagnostics_for_abs_path(TextDocumentIdentifier {
+            uri: self.notebook_cell_uri(nth),
+        })
+        .into_iter()
+        .map(|d| d.message)
+        .collect()
+    }
+
     pub fn change_notebook_cell(
         &self,
         nth_cell: usize,
@@ -0,0 +1,11 @@
+#!/usr/bin/env bash
+set -eu -o pipefail
+
+cd "$(dirname "$0")"
+
+BASE_DIR="../../.."
+WORKSPACE_TOML="$BASE_DIR/Cargo.toml"
+
+# Use --debug to speed up compilation
+cargo install --locked --debug cargo-about
+cargo about generate -c ../zuban/about.toml -o licenses.html ../zuban/about.hbs --fail --manifest-path "$WORKSPACE_TOML"
@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+set -eu -o pipefail -x
+
+pip install zuban-api --no-index --find-links dist/ --force-reinstall
+python -c 'import zuban_api; print(zuban_api.Project.load(".").check())'
@@ -0,0 +1,11 @@
+import os as _os
+
+# Python is the executable, so the bundled typeshed cannot be found relative to it.
+_os.environ.setdefault(
+    "ZUBAN_TYPESHED",
+    _os.path.join(_os.path.dirname(__file__), "third_party", "typeshed"),
+)
+
+from ._native import Diagnostic, Project, Signature  # noqa: E402
+
+__all__ = ["Diagnostic", "Project", "Signature"]